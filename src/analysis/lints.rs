@@ -0,0 +1,405 @@
+//! The checks behind the built-in lints. Each reports warnings, which the
+//! analyzer raises to errors for denied lints.
+
+use super::LintContext;
+use crate::{
+    ast::{ASTNode, BinaryOperator, NodeId, Pattern, PatternFields, Type, VariantFields},
+    diagnostics::Diagnostic,
+    semantic::{
+        deprecated,
+        resolve::{SymbolId, SymbolKind},
+    },
+    span::Span,
+    visitor::{walk_node, walk_nodes, Visitor},
+    Result,
+};
+use std::collections::HashSet;
+
+pub(super) fn complexity(context: &LintContext, diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+    let mut complexity = Complexity::default();
+    walk_nodes(&mut complexity, context.items)?;
+    for (name, span, paths) in complexity.functions {
+        if paths > context.config.max_complexity {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "Function `{}` has a cyclomatic complexity of {} (maximum {})",
+                    name, paths, context.config.max_complexity
+                ))
+                .with_span(span),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn deprecated(context: &LintContext, diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+    diagnostics.extend(deprecated::uses(context.items, context.resolution)?);
+    Ok(())
+}
+
+pub(super) fn needless_clone(
+    context: &LintContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let mut clones = Clones {
+        context,
+        repeated: Vec::new(),
+        closures: Vec::new(),
+        found: Vec::new(),
+    };
+    walk_nodes(&mut clones, context.items)?;
+
+    for (symbol, receiver, span) in clones.found {
+        let uses = context.resolution.uses_of(symbol);
+        let last = uses.last().map(|(id, _)| *id);
+        let captured = uses.iter().any(|(_, span)| {
+            clones
+                .closures
+                .iter()
+                .any(|closure| closure.encloses(*span))
+        });
+        if last == Some(receiver) && !captured {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "Needless clone of `{}`, which is not used afterwards",
+                    context.resolution.symbol(symbol).name
+                ))
+                .with_span(span),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn shadowing(context: &LintContext, diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+    for (id, symbol) in context.symbols() {
+        if let Some(hidden) = context.resolution.shadowed(id) {
+            diagnostics.push(
+                Diagnostic::warning(format!("`{}` shadows an earlier variable", symbol.name))
+                    .with_span(symbol.span)
+                    .with_note(
+                        context.resolution.symbol(hidden).span,
+                        "the earlier variable is declared here",
+                    ),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn unused_functions(
+    context: &LintContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    for item in context.items {
+        let ASTNode::Function {
+            name,
+            is_pub: false,
+            id,
+            span,
+            ..
+        } = item
+        else {
+            continue;
+        };
+        let Some(symbol) = context.resolution.declared(*id) else {
+            continue;
+        };
+        // Calls from its own body don't count.
+        let called = context
+            .resolution
+            .uses_of(symbol)
+            .iter()
+            .any(|(_, call)| !span.encloses(*call));
+        let is_test = item.attribute("test").is_some();
+        if !called && !is_test && name != "main" && !name.starts_with('_') {
+            diagnostics.push(
+                Diagnostic::warning(format!("Function `{}` is never used", name)).with_span(*span),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn unused_imports(
+    context: &LintContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    // Types aren't resolved, so an import used only as one is found by name.
+    let mut types = TypeNames::default();
+    walk_nodes(&mut types, context.items)?;
+
+    for (id, symbol) in context.symbols() {
+        if symbol.kind == SymbolKind::Import
+            && !types.0.contains(&symbol.name)
+            && context.resolution.uses_of(id).is_empty()
+        {
+            diagnostics.push(
+                Diagnostic::warning(format!("Unused import `{}`", symbol.name))
+                    .with_span(symbol.span),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn unused_variables(
+    context: &LintContext,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    for (id, symbol) in context.symbols() {
+        if symbol.is_local()
+            && symbol.name != "self"
+            && !symbol.name.starts_with('_')
+            && context.resolution.uses_of(id).is_empty()
+        {
+            diagnostics.push(
+                Diagnostic::warning(format!("Unused variable `{}`", symbol.name))
+                    .with_span(symbol.span),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Counts the paths through each function: one, plus one per branch of an
+/// `if`, loop, `&&` or `||`, and per `match` arm after the first.
+#[derive(Default)]
+struct Complexity {
+    /// The counts of the functions being walked, innermost last.
+    open: Vec<u32>,
+    /// Each function's name, span and count.
+    functions: Vec<(String, Span, u32)>,
+}
+
+impl Visitor for Complexity {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        let branches = match node {
+            ASTNode::Function { name, span, .. } => {
+                self.open.push(1);
+                walk_node(self, node)?;
+                let paths = self.open.pop().unwrap_or(1);
+                self.functions.push((name.clone(), *span, paths));
+                return Ok(());
+            }
+            ASTNode::If { .. } | ASTNode::While { .. } | ASTNode::For { .. } => 1,
+            ASTNode::BinaryOp {
+                op: BinaryOperator::And | BinaryOperator::Or,
+                ..
+            } => 1,
+            ASTNode::Match { arms, .. } => arms.len().saturating_sub(1) as u32,
+            _ => 0,
+        };
+        if let Some(paths) = self.open.last_mut() {
+            *paths += branches;
+        }
+        walk_node(self, node)
+    }
+}
+
+/// Finds `x.clone()` calls on local variables.
+struct Clones<'a> {
+    context: &'a LintContext<'a>,
+    /// The loops and closures being walked, whose bodies may run many times.
+    repeated: Vec<Span>,
+    /// Every closure walked.
+    closures: Vec<Span>,
+    /// The variable, the identifier and the call of each clone that runs at
+    /// most once per value of the variable.
+    found: Vec<(SymbolId, NodeId, Span)>,
+}
+
+impl Visitor for Clones<'_> {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::While { span, .. } | ASTNode::For { span, .. } => {
+                self.repeated.push(*span);
+                let result = walk_node(self, node);
+                self.repeated.pop();
+                return result;
+            }
+            ASTNode::Closure { span, .. } => {
+                self.closures.push(*span);
+                self.repeated.push(*span);
+                let result = walk_node(self, node);
+                self.repeated.pop();
+                return result;
+            }
+            ASTNode::Call {
+                callee, args, span, ..
+            } if args.is_empty() => {
+                if let ASTNode::MemberAccess { object, member, .. } = callee.as_ref() {
+                    if let ASTNode::Identifier { id, .. } = object.as_ref() {
+                        let symbol = self.context.resolution.resolved(*id).filter(|symbol| {
+                            let declared = self.context.resolution.symbol(*symbol);
+                            declared.is_local()
+                                && self
+                                    .repeated
+                                    .iter()
+                                    .all(|repeated| repeated.encloses(declared.span))
+                        });
+                        if let (Some(symbol), "clone") = (symbol, member.as_str()) {
+                            self.found.push((symbol, *id, *span));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        walk_node(self, node)
+    }
+}
+
+/// The names of the types, traits and variants a program mentions.
+#[derive(Default)]
+struct TypeNames(HashSet<String>);
+
+impl TypeNames {
+    /// Adds the first segment of `path`, the name an import would bring in.
+    fn name(&mut self, path: &str) {
+        let head = path.split("::").next().unwrap_or(path);
+        self.0.insert(head.to_string());
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match ty {
+            Type::Named(name) | Type::Dyn(name) => self.name(name),
+            Type::Generic { name, args } => {
+                self.name(name);
+                args.iter().for_each(|arg| self.ty(arg));
+            }
+            Type::Struct { name, fields } => {
+                self.name(name);
+                fields.iter().for_each(|(_, ty)| self.ty(ty));
+            }
+            Type::ConstArray { elem_type, length } => {
+                self.name(length);
+                self.ty(elem_type);
+            }
+            Type::Array { elem_type, .. } => self.ty(elem_type),
+            Type::Pointer(inner) | Type::Ref { inner, .. } => self.ty(inner),
+            Type::Tuple(elements) => elements.iter().for_each(|element| self.ty(element)),
+            Type::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                params.iter().for_each(|param| self.ty(param));
+                self.ty(return_type);
+            }
+            _ => {}
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Struct { path, fields, .. } => {
+                self.name(path);
+                match fields {
+                    PatternFields::Unit => {}
+                    PatternFields::Tuple(patterns) => {
+                        patterns.iter().for_each(|pattern| self.pattern(pattern))
+                    }
+                    PatternFields::Named { fields, .. } => {
+                        fields.iter().for_each(|(_, pattern)| self.pattern(pattern))
+                    }
+                }
+            }
+            Pattern::Array { elements, .. } | Pattern::Tuple { elements, .. } => {
+                elements.iter().for_each(|element| self.pattern(element))
+            }
+            Pattern::Or { alternatives, .. } => alternatives
+                .iter()
+                .for_each(|pattern| self.pattern(pattern)),
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } | Pattern::Literal { .. } => {}
+        }
+    }
+}
+
+impl Visitor for TypeNames {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Function {
+                bounds,
+                params,
+                return_type,
+                ..
+            } => {
+                bounds.iter().for_each(|(_, bound)| self.name(bound));
+                params
+                    .iter()
+                    .for_each(|param| self.ty(&param.type_annotation));
+                return_type.iter().for_each(|ty| self.ty(ty));
+            }
+            ASTNode::Closure {
+                params,
+                return_type,
+                ..
+            } => {
+                params
+                    .iter()
+                    .for_each(|param| self.ty(&param.type_annotation));
+                return_type.iter().for_each(|ty| self.ty(ty));
+            }
+            ASTNode::Global {
+                type_annotation, ..
+            }
+            | ASTNode::Let {
+                type_annotation, ..
+            } => type_annotation.iter().for_each(|ty| self.ty(ty)),
+            ASTNode::LetPattern {
+                pattern,
+                type_annotation,
+                ..
+            } => {
+                self.pattern(pattern);
+                type_annotation.iter().for_each(|ty| self.ty(ty));
+            }
+            ASTNode::For { pattern, .. } => self.pattern(pattern),
+            ASTNode::Match { arms, .. } => arms.iter().for_each(|arm| self.pattern(&arm.pattern)),
+            ASTNode::Cast { target, .. } => self.ty(target),
+            ASTNode::StructLiteral { name, .. } => self.name(name),
+            ASTNode::StructDef { fields, .. } => fields
+                .iter()
+                .for_each(|field| self.ty(&field.type_annotation)),
+            ASTNode::EnumDef { variants, .. } => {
+                for variant in variants {
+                    match &variant.fields {
+                        VariantFields::Unit => {}
+                        VariantFields::Tuple(types) => types.iter().for_each(|ty| self.ty(ty)),
+                        VariantFields::Named(fields) => fields
+                            .iter()
+                            .for_each(|field| self.ty(&field.type_annotation)),
+                    }
+                }
+            }
+            ASTNode::TraitDef { methods, .. } => {
+                for method in methods {
+                    method
+                        .params
+                        .iter()
+                        .for_each(|param| self.ty(&param.type_annotation));
+                    method.return_type.iter().for_each(|ty| self.ty(ty));
+                }
+            }
+            ASTNode::Impl {
+                trait_name,
+                trait_args,
+                self_type,
+                ..
+            } => {
+                trait_name.iter().for_each(|name| self.name(name));
+                trait_args.iter().for_each(|arg| self.ty(arg));
+                self.ty(self_type);
+            }
+            _ => {}
+        }
+        walk_node(self, node)
+    }
+}
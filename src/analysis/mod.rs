@@ -1,150 +1,507 @@
-use std::collections::{HashMap, HashSet};
-use crate::{ast::ASTNode, Result};
+//! Lints: checks for code that compiles but is likely a mistake or hard to
+//! maintain, run by `io lint`.
+//!
+//! Each lint has a level. `allow` turns it off, `warn` reports what it finds
+//! as warnings and `deny` as errors. The `[lints]` table of `io.toml`
+//! overrides the defaults for a project, and `#[allow(lint, ...)]` turns lints
+//! off for one function, struct or import.
 
-pub struct CodeAnalyzer {
-    complexity_threshold: u32,
-    dependencies: HashMap<String, HashSet<String>>,
-    metrics: AnalysisMetrics,
+mod lints;
+
+use crate::{
+    ast::ASTNode,
+    diagnostics::{Diagnostic, DiagnosticLevel, SourceMap},
+    error::IoError,
+    formatter::{find_manifest, MANIFEST_NAME},
+    semantic::{
+        flow,
+        resolve::{Resolution, Symbol, SymbolId},
+    },
+    span::Span,
+    stdlib::prelude,
+    visitor::{walk_node, Visitor},
+    Result,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+/// How a lint reports what it finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
 }
 
-impl CodeAnalyzer {
-    pub fn new() -> Self {
+/// A check `io lint` runs.
+pub struct Lint {
+    /// The name `io.toml` and `#[allow]` refer to it by.
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default_level: Level,
+    /// How to fix what it finds, shown with each finding.
+    pub fix: &'static str,
+    check: fn(&LintContext, &mut Vec<Diagnostic>) -> Result<()>,
+}
+
+/// Every built-in lint, by name.
+pub static LINTS: &[Lint] = &[
+    Lint {
+        name: "complexity",
+        description: "functions with more paths through them than `max_complexity`",
+        default_level: Level::Warn,
+        fix: "split it into smaller functions",
+        check: lints::complexity,
+    },
+    Lint {
+        name: "deprecated",
+        description: "uses of functions and structs marked `#[deprecated]`",
+        default_level: Level::Warn,
+        fix: "move to what replaces it",
+        check: lints::deprecated,
+    },
+    Lint {
+        name: "needless_clone",
+        description: "clones of variables that are not used afterwards",
+        default_level: Level::Warn,
+        fix: "use the variable itself instead of a clone",
+        check: lints::needless_clone,
+    },
+    Lint {
+        name: "shadowing",
+        description: "variables declared while another of the same name is in scope",
+        default_level: Level::Allow,
+        fix: "give one of the variables another name",
+        check: lints::shadowing,
+    },
+    Lint {
+        name: "unused_functions",
+        description: "private functions nothing calls",
+        default_level: Level::Warn,
+        fix: "remove the function, or make it `pub` if other modules need it",
+        check: lints::unused_functions,
+    },
+    Lint {
+        name: "unused_imports",
+        description: "imported items nothing uses",
+        default_level: Level::Warn,
+        fix: "remove the item from the import",
+        check: lints::unused_imports,
+    },
+    Lint {
+        name: "unused_variables",
+        description: "parameters and variables nothing reads",
+        default_level: Level::Warn,
+        fix: "remove it, or start its name with `_` if it is unused on purpose",
+        check: lints::unused_variables,
+    },
+];
+
+/// The built-in lint called `name`.
+pub fn lint(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().find(|lint| lint.name == name)
+}
+
+/// Lint levels and options, read from the `[lints]` table of `io.toml`:
+///
+/// ```toml
+/// [lints]
+/// shadowing = "warn"
+/// unused_imports = "deny"
+/// max_complexity = 15
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    /// Levels that differ from the lints' defaults, by lint name.
+    levels: HashMap<&'static str, Level>,
+    /// The cyclomatic complexity above which `complexity` reports a function.
+    pub max_complexity: u32,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
         Self {
-            complexity_threshold: 10,
-            dependencies: HashMap::new(),
-            metrics: AnalysisMetrics::default(),
+            levels: HashMap::new(),
+            max_complexity: 10,
         }
     }
+}
 
-    pub fn analyze(&mut self, ast: &ASTNode) -> Result<AnalysisReport> {
-        let mut report = AnalysisReport::new();
-        
-        self.analyze_cyclomatic_complexity(ast, &mut report)?;
-        self.analyze_dependencies(ast, &mut report)?;
-        self.analyze_code_smells(ast, &mut report)?;
-        
-        Ok(report)
-    }
-
-    fn analyze_cyclomatic_complexity(&self, node: &ASTNode, report: &mut AnalysisReport) -> Result<u32> {
-        let complexity = match node {
-            ASTNode::Function { body, .. } => {
-                let mut count = 1; // Base complexity
-                for stmt in body {
-                    count += match stmt {
-                        ASTNode::If { .. } => 1,
-                        ASTNode::While { .. } => 1,
-                        ASTNode::Match { arms, .. } => arms.len() as u32 - 1,
-                        _ => 0,
-                    };
-                }
-                if count > self.complexity_threshold {
-                    report.add_warning(format!(
-                        "High cyclomatic complexity: {} (threshold: {})",
-                        count,
-                        self.complexity_threshold
-                    ));
-                }
-                count
-            }
-            _ => 0,
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    lints: LintTable,
+}
+
+#[derive(Default, Deserialize)]
+struct LintTable {
+    max_complexity: Option<u32>,
+    #[serde(flatten)]
+    levels: HashMap<String, Level>,
+}
+
+impl LintConfig {
+    /// Configuration from the nearest `io.toml` at or above `path`, or the
+    /// defaults if there is none.
+    pub fn load(path: &Path) -> Result<Self> {
+        let Some(manifest) = find_manifest(path) else {
+            return Ok(Self::default());
         };
-        Ok(complexity)
-    }
-
-    fn analyze_dependencies(&self, node: &ASTNode, report: &mut AnalysisReport) -> Result<()> {
-        match node {
-            ASTNode::Function { name, body, .. } => {
-                let mut deps = HashSet::new();
-                self.collect_dependencies(body, &mut deps);
-                if deps.len() > 5 {
-                    report.add_warning(format!(
-                        "Function '{}' has too many dependencies: {}",
-                        name,
-                        deps.len()
-                    ));
-                }
-            }
-            _ => {}
-        }
-        Ok(())
+        let contents = std::fs::read_to_string(&manifest)?;
+        Self::from_manifest(&contents).map_err(|err| {
+            IoError::validation_error(format!("{}: {}", manifest.display(), err.message()))
+        })
     }
 
-    fn analyze_code_smells(&self, node: &ASTNode, report: &mut AnalysisReport) -> Result<()> {
-        // Detect potential code smells
-        match node {
-            ASTNode::Function { name, body, .. } => {
-                if body.len() > 50 {
-                    report.add_warning(format!("Function '{}' is too long", name));
-                }
-                self.check_nested_blocks(body, 0, report);
-            }
-            _ => {}
+    /// Parses the contents of an `io.toml`; only its `[lints]` table is read.
+    pub fn from_manifest(contents: &str) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(contents).map_err(|err| {
+            IoError::validation_error(format!("Invalid {}: {}", MANIFEST_NAME, err))
+        })?;
+        let mut config = Self::default();
+        if let Some(max_complexity) = manifest.lints.max_complexity {
+            config.max_complexity = max_complexity;
         }
+        for (name, level) in manifest.lints.levels {
+            config.set_level(&name, level)?;
+        }
+        Ok(config)
+    }
+
+    /// Sets the level of the lint called `name`.
+    pub fn set_level(&mut self, name: &str, level: Level) -> Result<()> {
+        let lint = lint(name)
+            .ok_or_else(|| IoError::validation_error(format!("Unknown lint `{}`", name)))?;
+        self.levels.insert(lint.name, level);
         Ok(())
     }
 
-    fn check_nested_blocks(&self, nodes: &[ASTNode], depth: usize, report: &mut AnalysisReport) {
-        if depth > 3 {
-            report.add_warning("Excessive nesting detected".to_string());
-        }
-        for node in nodes {
-            match node {
-                ASTNode::Block(inner) => self.check_nested_blocks(inner, depth + 1, report),
-                ASTNode::If { then_branch, else_branch, .. } => {
-                    self.check_nested_blocks(then_branch, depth + 1, report);
-                    if let Some(else_nodes) = else_branch {
-                        self.check_nested_blocks(else_nodes, depth + 1, report);
-                    }
-                }
-                _ => {}
-            }
-        }
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.levels
+            .get(lint.name)
+            .copied()
+            .unwrap_or(lint.default_level)
     }
 }
 
-#[derive(Default)]
-pub struct AnalysisMetrics {
-    function_count: usize,
-    line_count: usize,
-    complexity_scores: Vec<u32>,
+/// What the lints see of a program.
+struct LintContext<'a> {
+    items: &'a [ASTNode],
+    resolution: &'a Resolution,
+    config: &'a LintConfig,
 }
 
+impl LintContext<'_> {
+    /// The symbols the program declares.
+    fn symbols(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.resolution
+            .symbols()
+            .filter(|(_, symbol)| !prelude::contains(symbol.node))
+    }
+}
+
+/// What `CodeAnalyzer::analyze` found, in source order.
+#[derive(Debug, Default)]
 pub struct AnalysisReport {
-    warnings: Vec<String>,
-    metrics: AnalysisMetrics,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl AnalysisReport {
-    pub fn new() -> Self {
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    /// Whether a denied lint or the compiler found an error.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    /// Every diagnostic, rendered against `source_map`.
+    pub fn report(&self, source_map: &SourceMap) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.report(source_map) + "\n")
+            .collect()
+    }
+}
+
+/// Runs the lints on one parsed file.
+pub struct CodeAnalyzer {
+    config: LintConfig,
+}
+
+impl CodeAnalyzer {
+    pub fn new(config: &LintConfig) -> Self {
         Self {
-            warnings: Vec::new(),
-            metrics: AnalysisMetrics::default(),
+            config: config.clone(),
         }
     }
 
-    pub fn add_warning(&mut self, warning: String) {
-        self.warnings.push(warning);
-    }
+    /// Lints `program`, one file as parsed and resolved by `resolution`, along
+    /// with the control-flow diagnostics of [`flow::check`], which no
+    /// configuration turns off.
+    pub fn analyze(&self, program: &ASTNode, resolution: &Resolution) -> Result<AnalysisReport> {
+        let items = match program {
+            ASTNode::Program(items) => items.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+        let context = LintContext {
+            items,
+            resolution,
+            config: &self.config,
+        };
+
+        let mut allowed = Allowed::default();
+        allowed.visit_node(program)?;
 
-    pub fn print_report(&self) {
-        println!("\nCode Analysis Report");
-        println!("===================");
-        
-        if !self.warnings.is_empty() {
-            println!("\nWarnings:");
-            for warning in &self.warnings {
-                println!("- {}", warning);
+        let mut diagnostics = std::mem::take(&mut allowed.unknown);
+        for lint in LINTS {
+            let level = self.config.level(lint);
+            if level == Level::Allow {
+                continue;
+            }
+            let mut found = Vec::new();
+            (lint.check)(&context, &mut found)?;
+            for mut diagnostic in found {
+                if diagnostic
+                    .span
+                    .is_some_and(|span| allowed.allows(lint.name, span))
+                {
+                    continue;
+                }
+                if level == Level::Deny {
+                    diagnostic.level = DiagnosticLevel::Error;
+                }
+                diagnostics.push(
+                    diagnostic
+                        .with_hint(lint.fix)
+                        .with_hint(format!("`#[allow({})]` turns this lint off", lint.name)),
+                );
             }
         }
+        diagnostics.extend(flow::check(program)?);
+        diagnostics
+            .sort_by_key(|diagnostic| diagnostic.span.map(|span| (span.file_id.0, span.start)));
+        Ok(AnalysisReport { diagnostics })
+    }
+}
+
+/// The `#[allow(...)]` attributes of a program.
+#[derive(Default)]
+struct Allowed {
+    /// The item each attribute is on, with the lints it allows.
+    items: Vec<(Span, Vec<String>)>,
+    /// Warnings for attributes naming lints that don't exist.
+    unknown: Vec<Diagnostic>,
+}
 
-        println!("\nMetrics:");
-        println!("- Functions: {}", self.metrics.function_count);
-        println!("- Lines of Code: {}", self.metrics.line_count);
-        if !self.metrics.complexity_scores.is_empty() {
-            let avg_complexity: f64 = self.metrics.complexity_scores.iter().sum::<u32>() as f64 
-                / self.metrics.complexity_scores.len() as f64;
-            println!("- Average Complexity: {:.2}", avg_complexity);
+impl Allowed {
+    fn allows(&self, lint: &str, span: Span) -> bool {
+        self.items
+            .iter()
+            .any(|(item, lints)| item.encloses(span) && lints.iter().any(|name| name == lint))
+    }
+}
+
+impl Visitor for Allowed {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        for attribute in node.attributes() {
+            if attribute.name != "allow" {
+                continue;
+            }
+            for name in attribute.words() {
+                if lint(name).is_none() {
+                    self.unknown.push(
+                        Diagnostic::warning(format!("Unknown lint `{}`", name))
+                            .with_span(attribute.span),
+                    );
+                }
+            }
+            let lints = attribute.words().map(str::to_string).collect();
+            let span = match node {
+                // A module's attributes cover its whole file.
+                ASTNode::ModuleAttributes { span, .. } => Span::new(span.file_id, 0, usize::MAX),
+                node => node.span(),
+            };
+            self.items.push((span, lints));
         }
+        walk_node(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, semantic::resolve, span::FileId};
+
+    /// Whether each diagnostic for `source` is an error, its message and the
+    /// first line of the source text it points at.
+    fn lint_source<'a>(source: &'a str, config: &LintConfig) -> Vec<(bool, String, &'a str)> {
+        let program = parse_source(source, FileId(0)).unwrap();
+        let resolution = resolve::resolve(&program).unwrap();
+        CodeAnalyzer::new(config)
+            .analyze(&program, &resolution)
+            .unwrap()
+            .into_diagnostics()
+            .into_iter()
+            .map(|diagnostic| {
+                let span = diagnostic.span.unwrap();
+                let text = source[span.start..span.end].lines().next().unwrap_or("");
+                (diagnostic.is_error(), diagnostic.message, text)
+            })
+            .collect()
+    }
+
+    fn finding(message: &str, text: &'static str) -> (bool, String, &'static str) {
+        (false, message.to_string(), text)
+    }
+
+    #[test]
+    fn test_default_lints() {
+        let source = "\
+import shapes::{Shape, area, unused};
+fn dead() -> int { return dead(); }
+fn size(s: Shape, scale: int) -> float {
+    let copy = s.clone();
+    return area(copy);
+}
+fn main() {
+    let items = [1, 2];
+    let x = items.clone();
+    for i in 0..3 {
+        let y = x.clone();
+        println(y);
+    }
+    println(size(Shape::Empty, 2));
+}";
+        assert_eq!(
+            lint_source(source, &LintConfig::default()),
+            vec![
+                finding(
+                    "Unused import `unused`",
+                    "import shapes::{Shape, area, unused};"
+                ),
+                finding(
+                    "Function `dead` is never used",
+                    "fn dead() -> int { return dead(); }"
+                ),
+                finding("Unused variable `scale`", "scale: int"),
+                finding(
+                    "Needless clone of `s`, which is not used afterwards",
+                    "s.clone()"
+                ),
+                finding(
+                    "Needless clone of `items`, which is not used afterwards",
+                    "items.clone()"
+                ),
+                finding("Unused variable `i`", "i"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allow_attributes() {
+        let source = "\
+#[allow(unused_imports)]
+import util::{log};
+#[allow(unused_variables, unused_functions)]
+fn quiet(x: int) {}
+#[allow(unused_varibles)]
+fn loud(y: int) {}
+fn main() { loud(1); }";
+        assert_eq!(
+            lint_source(source, &LintConfig::default()),
+            vec![
+                finding(
+                    "Unknown lint `unused_varibles`",
+                    "#[allow(unused_varibles)]"
+                ),
+                finding("Unused variable `y`", "y: int"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deprecated_uses() {
+        let source = "\
+#[deprecated(note = \"use `distance` instead\")]
+fn dist(a: int, b: int) -> int { return dist(b, a); }
+#[deprecated]
+struct Point { x: int }
+fn distance(a: int, b: int) -> int { return b - a; }
+#[allow(deprecated)]
+fn legacy() -> int { return dist(1, 2); }
+#[test]
+fn measures() {
+    let p = Point { x: dist(0, 1) };
+    println(p.x + distance(0, 1) + legacy());
+}";
+        let program = parse_source(source, FileId(0)).unwrap();
+        let resolution = resolve::resolve(&program).unwrap();
+        let report = CodeAnalyzer::new(&LintConfig::default())
+            .analyze(&program, &resolution)
+            .unwrap();
+        let found: Vec<(&str, &[String])> = report
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| (diagnostic.message.as_str(), diagnostic.hints.as_slice()))
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0, "Use of deprecated struct `Point`");
+        assert_eq!(found[1].0, "Use of deprecated function `dist`");
+        assert_eq!(found[1].1[0], "use `distance` instead");
+    }
+
+    #[test]
+    fn test_configured_levels() {
+        let config = LintConfig::from_manifest(
+            "[package]\nname = \"demo\"\n\n[lints]\n\
+             shadowing = \"deny\"\nunused_variables = \"allow\"\nmax_complexity = 2\n",
+        )
+        .unwrap();
+        assert_eq!(config.level(lint("shadowing").unwrap()), Level::Deny);
+        assert_eq!(config.level(lint("complexity").unwrap()), Level::Warn);
+        assert_eq!(config.max_complexity, 2);
+
+        let source = "\
+pub fn f(n: int, unused: int) -> int {
+    let n = n + 1;
+    if n > 1 && n < 10 {
+        return 1;
+    }
+    return 0;
+}";
+        assert_eq!(
+            lint_source(source, &config),
+            vec![
+                finding(
+                    "Function `f` has a cyclomatic complexity of 3 (maximum 2)",
+                    "pub fn f(n: int, unused: int) -> int {"
+                ),
+                (
+                    true,
+                    "`n` shadows an earlier variable".to_string(),
+                    "let n = n + 1;"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        let err = LintConfig::from_manifest("[lints]\nunused_everything = \"warn\"").unwrap_err();
+        assert_eq!(err.message(), "Unknown lint `unused_everything`");
+        assert!(LintConfig::from_manifest("[lints]\nshadowing = \"loud\"").is_err());
+        assert_eq!(
+            LintConfig::from_manifest("[package]\nname = \"demo\"").unwrap(),
+            LintConfig::default()
+        );
     }
 }
@@ -0,0 +1,223 @@
+use super::{ASTNode, BinaryOperator, MatchArm, Parameter, Pattern, Type};
+use crate::{
+    visitor::{walk_nodes, walk_place, Visitor},
+    Result,
+};
+use std::collections::HashSet;
+
+/// A variable a closure uses from the scope it is created in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    pub name: String,
+    /// Assigned to in the closure's body.
+    pub assigned: bool,
+}
+
+/// The variables the closure with `params` and `body` uses without declaring
+/// them, in order of first use. Besides the enclosing function's locals these
+/// include globals and the names of functions; each backend picks the ones it
+/// has to store in the closure's environment.
+pub fn captures(params: &[Parameter], body: &ASTNode) -> Vec<Capture> {
+    let mut finder = FreeVariables {
+        scopes: vec![params.iter().map(|param| param.name.clone()).collect()],
+        captures: Vec::new(),
+    };
+    finder
+        .visit_node(body)
+        .expect("finding free variables cannot fail");
+    finder.captures
+}
+
+struct FreeVariables {
+    scopes: Vec<HashSet<String>>,
+    captures: Vec<Capture>,
+}
+
+impl FreeVariables {
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    fn refer(&mut self, name: &str, assigned: bool) {
+        if self.scopes.iter().any(|scope| scope.contains(name)) {
+            return;
+        }
+        match self
+            .captures
+            .iter_mut()
+            .find(|capture| capture.name == name)
+        {
+            Some(capture) => capture.assigned |= assigned,
+            None => self.captures.push(Capture {
+                name: name.to_string(),
+                assigned,
+            }),
+        }
+    }
+
+    /// Assigning to a field or element of a variable assigns the variable.
+    fn assign(&mut self, target: &ASTNode) -> Result<()> {
+        if let Some(name) = target.place_root() {
+            self.refer(name, true);
+        }
+        walk_place(self, target)
+    }
+
+    fn scoped(
+        &mut self,
+        declared: impl IntoIterator<Item = String>,
+        nodes: &[ASTNode],
+    ) -> Result<()> {
+        self.scopes.push(declared.into_iter().collect());
+        let result = walk_nodes(self, nodes);
+        self.scopes.pop();
+        result
+    }
+}
+
+impl Visitor for FreeVariables {
+    type Output = ();
+
+    fn visit_block(&mut self, statements: &[ASTNode]) -> Result<()> {
+        self.scoped([], statements)
+    }
+
+    fn visit_let(&mut self, name: &str, _: Option<&Type>, value: Option<&ASTNode>) -> Result<()> {
+        if let Some(value) = value {
+            self.visit_node(value)?;
+        }
+        self.declare(name);
+        Ok(())
+    }
+
+    fn visit_let_pattern(
+        &mut self,
+        pattern: &Pattern,
+        _: Option<&Type>,
+        value: &ASTNode,
+    ) -> Result<()> {
+        self.visit_node(value)?;
+        for (name, _) in pattern.bindings() {
+            self.declare(name);
+        }
+        Ok(())
+    }
+
+    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<()> {
+        self.visit_node(value)?;
+        self.assign(target)
+    }
+
+    fn visit_compound_assignment(
+        &mut self,
+        target: &ASTNode,
+        _: &BinaryOperator,
+        value: &ASTNode,
+    ) -> Result<()> {
+        self.visit_node(value)?;
+        self.assign(target)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &ASTNode,
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+    ) -> Result<()> {
+        self.visit_node(condition)?;
+        self.scoped([], then_branch)?;
+        if let Some(else_branch) = else_branch {
+            self.scoped([], else_branch)?;
+        }
+        Ok(())
+    }
+
+    fn visit_while(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<()> {
+        self.visit_node(condition)?;
+        self.scoped([], body)
+    }
+
+    fn visit_for(&mut self, pattern: &Pattern, iterable: &ASTNode, body: &[ASTNode]) -> Result<()> {
+        self.visit_node(iterable)?;
+        let bindings = pattern.bindings();
+        self.scoped(bindings.iter().map(|(name, _)| name.to_string()), body)
+    }
+
+    fn visit_match(&mut self, scrutinee: &ASTNode, arms: &[MatchArm]) -> Result<()> {
+        self.visit_node(scrutinee)?;
+        for arm in arms {
+            let bindings = arm.pattern.bindings();
+            self.scopes
+                .push(bindings.iter().map(|(name, _)| name.to_string()).collect());
+            if let Some(guard) = &arm.guard {
+                self.visit_node(guard)?;
+            }
+            self.visit_node(&arm.body)?;
+            self.scopes.pop();
+        }
+        Ok(())
+    }
+
+    fn visit_closure(
+        &mut self,
+        params: &[Parameter],
+        _: Option<&Type>,
+        body: &ASTNode,
+        _: bool,
+    ) -> Result<()> {
+        self.scopes
+            .push(params.iter().map(|param| param.name.clone()).collect());
+        self.visit_node(body)?;
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn visit_identifier(&mut self, name: &str) -> Result<()> {
+        self.refer(name, false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+
+    fn closure_captures(source: &str) -> Vec<Capture> {
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        match &items[0] {
+            ASTNode::Let {
+                value: Some(value), ..
+            } => match value.as_ref() {
+                ASTNode::Closure { params, body, .. } => captures(params, body),
+                other => panic!("expected closure, got {:?}", other),
+            },
+            other => panic!("expected let, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_captures_are_the_free_variables() {
+        let found = closure_captures(
+            "let f = |x| { let y = x + a; total += y; for i in items { b = i; } |z| z + c };",
+        );
+        let names: Vec<(&str, bool)> = found
+            .iter()
+            .map(|capture| (capture.name.as_str(), capture.assigned))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("a", false),
+                ("total", true),
+                ("items", false),
+                ("b", true),
+                ("c", false)
+            ]
+        );
+    }
+}
@@ -1,11 +1,13 @@
-mod expression;
-mod module;
+mod captures;
 mod node;
-mod statement;
+mod operator;
+mod pattern;
 mod types;
 
-pub use expression::Expression;
-pub use module::{Declaration, Function, Global, Import, Module, StructDef};
-pub use node::ASTNode;
-pub use statement::Statement;
-pub use types::{BinaryOperator, Literal, Parameter, Type};
+pub use captures::{captures, Capture};
+pub use node::{ASTNode, MatchArm, NodeId};
+pub use operator::{BinaryOperator, UnaryOperator};
+pub use pattern::{Pattern, PatternFields};
+pub use types::{
+    Attribute, AttributeArg, Field, Literal, Parameter, TraitMethod, Type, Variant, VariantFields,
+};
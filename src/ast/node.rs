@@ -1,63 +1,420 @@
-use super::{Expression, Statement};
+use super::{
+    Attribute, BinaryOperator, Field, Literal, Parameter, Pattern, TraitMethod, Type,
+    UnaryOperator, Variant,
+};
+use crate::span::Span;
+
+/// Identifies a node within one parsed program. Passes use it to attach
+/// side tables (types, resolved symbols) without mutating the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct NodeId(pub u32);
+
+impl NodeId {
+    /// Id for nodes synthesized after parsing, e.g. by a folder.
+    pub const DUMMY: NodeId = NodeId(u32::MAX);
+}
 
 #[derive(Debug, Clone)]
 pub enum ASTNode {
     Program(Vec<ASTNode>),
     Function {
         name: String,
-        params: Vec<super::Parameter>,
-        return_type: Option<String>,
+        /// Names of the generic type parameters, e.g. `T` and `U` in `fn map<T, U>`.
+        type_params: Vec<String>,
+        /// Traits the type parameters must implement, e.g. `(T, Show)` for
+        /// `fn show<T: Show>`.
+        bounds: Vec<(String, String)>,
+        params: Vec<Parameter>,
+        return_type: Option<Type>,
         body: Vec<ASTNode>,
         is_async: bool,
+        /// Declared `const fn`: callable in constant expressions.
+        is_const: bool,
+        /// Declared `pub`: visible to the modules importing this one.
+        is_pub: bool,
+        attributes: Vec<Attribute>,
+        id: NodeId,
+        span: Span,
     },
-    Statement(Statement),
-    Expression(Expression),
-    Block(Vec<ASTNode>),
-    Call {
+    /// `const NAME: Type = value;`, a global whose value is computed at compile
+    /// time.
+    Global {
+        name: String,
+        type_annotation: Option<Type>,
+        value: Box<ASTNode>,
+        is_pub: bool,
+        id: NodeId,
+        span: Span,
+    },
+    /// `struct Name<A, B> { field: Type, ... }`
+    StructDef {
         name: String,
+        type_params: Vec<String>,
+        fields: Vec<Field>,
+        is_pub: bool,
+        attributes: Vec<Attribute>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `enum Name<T> { Unit, Tuple(T), Named { field: T } }`
+    EnumDef {
+        name: String,
+        type_params: Vec<String>,
+        variants: Vec<Variant>,
+        is_pub: bool,
+        id: NodeId,
+        span: Span,
+    },
+    /// `trait Name<T> { fn method(self) -> T; ... }`
+    TraitDef {
+        name: String,
+        type_params: Vec<String>,
+        methods: Vec<TraitMethod>,
+        is_pub: bool,
+        id: NodeId,
+        span: Span,
+    },
+    /// `import net::http::{get, post};`, bringing public items of the module
+    /// `net::http` into scope.
+    Import {
+        /// The module's path, `["net", "http"]`.
+        path: Vec<String>,
+        items: Vec<String>,
+        attributes: Vec<Attribute>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `impl Trait<Arg> for Type { fn method(self) -> T { ... } ... }`, or
+    /// `impl Type { ... }` for methods of the type's own. The methods are
+    /// `Function` nodes whose first parameter is `self`.
+    /// `#![cfg(test)]`, `#![deprecated]` and the like at the top of a file,
+    /// which apply to the whole module.
+    ModuleAttributes {
+        attributes: Vec<Attribute>,
+        id: NodeId,
+        span: Span,
+    },
+    Impl {
+        /// `None` for an inherent impl, which has no trait.
+        trait_name: Option<String>,
+        trait_args: Vec<Type>,
+        self_type: Type,
+        methods: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    Block {
+        statements: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    Call {
+        callee: Box<ASTNode>,
         args: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
     },
     If {
         condition: Box<ASTNode>,
         then_branch: Vec<ASTNode>,
         else_branch: Option<Vec<ASTNode>>,
+        id: NodeId,
+        span: Span,
     },
     While {
         condition: Box<ASTNode>,
         body: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `match scrutinee { pattern => value, ... }`, an expression.
+    Match {
+        scrutinee: Box<ASTNode>,
+        arms: Vec<MatchArm>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `for pattern in iterable { body }`; the pattern must match every
+    /// element.
+    For {
+        pattern: Pattern,
+        iterable: Box<ASTNode>,
+        body: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
     },
-    Return(Option<Box<ASTNode>>),
+    Return {
+        value: Option<Box<ASTNode>>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `let x = value;`, or `let x: T;` to declare `x` without a value. Such a
+    /// variable must be assigned on every path before it is read.
     Let {
         name: String,
+        type_annotation: Option<Type>,
+        value: Option<Box<ASTNode>>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `let (a, b) = pair;` or `let Point { x, y } = p;`. The pattern must match
+    /// every value of its type; a plain `let x` is a `Let`.
+    LetPattern {
+        pattern: Pattern,
+        type_annotation: Option<Type>,
         value: Box<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    Identifier {
+        name: String,
+        id: NodeId,
+        span: Span,
+    },
+    Literal {
+        value: Literal,
+        id: NodeId,
+        span: Span,
     },
-    Identifier(String),
-    IntegerLiteral(i64),
     BinaryOp {
-        op: String,
+        op: BinaryOperator,
         left: Box<ASTNode>,
         right: Box<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    UnaryOp {
+        op: UnaryOperator,
+        operand: Box<ASTNode>,
+        id: NodeId,
+        span: Span,
     },
-    Break,
-    Continue,
-    IntLiteral {
-        value: i32,
-    }, // Added IntLiteral variant
-    FloatLiteral {
-        value: f32,
-    }, // Added FloatLiteral variant
-    StringLiteral {
-        value: String,
-    }, // Added StringLiteral variant
-    BoolLiteral {
-        value: bool,
-    }, // Added BoolLiteral variant
+    /// `value as target`, a conversion between numeric types.
+    Cast {
+        value: Box<ASTNode>,
+        target: Type,
+        id: NodeId,
+        span: Span,
+    },
+    Break {
+        id: NodeId,
+        span: Span,
+    },
+    Continue {
+        id: NodeId,
+        span: Span,
+    },
+    /// `|x, y: i32| x + y` or `move || -> i32 { n }`, a function value
+    /// capturing the variables its body uses from the enclosing scope.
+    Closure {
+        /// Unannotated parameters have type `Unknown` until inferred.
+        params: Vec<Parameter>,
+        return_type: Option<Type>,
+        /// An expression, or a `Block` when a return type is given.
+        body: Box<ASTNode>,
+        /// Declared `move`: captures copies of the variables instead of
+        /// referring to them.
+        is_move: bool,
+        id: NodeId,
+        span: Span,
+    },
+    ArrayLiteral {
+        elements: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `(a, b)`; a tuple of one element is written `(a,)`.
+    TupleLiteral {
+        elements: Vec<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `Point { x: 1, y: 2 }` or `Shape::Rect { w: 1.0, h: 2.0 }`, building a
+    /// struct or a variant with named fields.
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, ASTNode)>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `target = value`. The target is a place: a variable, a field of one
+    /// (`p.pos.x`) or an element (`grid[i][j]`).
     Assignment {
-        target: String,
+        target: Box<ASTNode>,
+        value: Box<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    /// `target op= value`, e.g. `total += x` or `counts[i] += 1`
+    CompoundAssignment {
+        target: Box<ASTNode>,
+        op: BinaryOperator,
         value: Box<ASTNode>,
-    }, // Added Assignment variant
+        id: NodeId,
+        span: Span,
+    },
     MemberAccess {
         object: Box<ASTNode>,
         member: String,
-    }, // Added MemberAccess variant
+        id: NodeId,
+        span: Span,
+    },
+    Index {
+        array: Box<ASTNode>,
+        index: Box<ASTNode>,
+        id: NodeId,
+        span: Span,
+    },
+    /// Placeholder for source that failed to parse; the error was reported separately.
+    Error {
+        id: NodeId,
+        span: Span,
+    },
+}
+
+/// One arm of a `match`: `pattern if guard => body`. A block body is an
+/// `ASTNode::Block`.
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<ASTNode>,
+    pub body: ASTNode,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+impl ASTNode {
+    /// Source range covered by this node. `Program` spans all of its items.
+    pub fn span(&self) -> Span {
+        match self {
+            ASTNode::Program(items) => match (items.first(), items.last()) {
+                (Some(first), Some(last)) => first.span().to(last.span()),
+                _ => Span::dummy(),
+            },
+            ASTNode::Function { span, .. }
+            | ASTNode::Global { span, .. }
+            | ASTNode::StructDef { span, .. }
+            | ASTNode::EnumDef { span, .. }
+            | ASTNode::TraitDef { span, .. }
+            | ASTNode::Impl { span, .. }
+            | ASTNode::Import { span, .. }
+            | ASTNode::ModuleAttributes { span, .. }
+            | ASTNode::Block { span, .. }
+            | ASTNode::Call { span, .. }
+            | ASTNode::If { span, .. }
+            | ASTNode::Match { span, .. }
+            | ASTNode::While { span, .. }
+            | ASTNode::For { span, .. }
+            | ASTNode::Return { span, .. }
+            | ASTNode::Let { span, .. }
+            | ASTNode::LetPattern { span, .. }
+            | ASTNode::Identifier { span, .. }
+            | ASTNode::Literal { span, .. }
+            | ASTNode::BinaryOp { span, .. }
+            | ASTNode::UnaryOp { span, .. }
+            | ASTNode::Cast { span, .. }
+            | ASTNode::Break { span, .. }
+            | ASTNode::Continue { span, .. }
+            | ASTNode::Closure { span, .. }
+            | ASTNode::ArrayLiteral { span, .. }
+            | ASTNode::TupleLiteral { span, .. }
+            | ASTNode::StructLiteral { span, .. }
+            | ASTNode::Assignment { span, .. }
+            | ASTNode::CompoundAssignment { span, .. }
+            | ASTNode::MemberAccess { span, .. }
+            | ASTNode::Index { span, .. }
+            | ASTNode::Error { span, .. } => *span,
+        }
+    }
+
+    /// Id assigned by the parser. `Program` is the root and has no id of its own.
+    pub fn id(&self) -> NodeId {
+        match self {
+            ASTNode::Program(_) => NodeId::DUMMY,
+            ASTNode::Function { id, .. }
+            | ASTNode::Global { id, .. }
+            | ASTNode::StructDef { id, .. }
+            | ASTNode::EnumDef { id, .. }
+            | ASTNode::TraitDef { id, .. }
+            | ASTNode::Impl { id, .. }
+            | ASTNode::Import { id, .. }
+            | ASTNode::ModuleAttributes { id, .. }
+            | ASTNode::Block { id, .. }
+            | ASTNode::Call { id, .. }
+            | ASTNode::If { id, .. }
+            | ASTNode::Match { id, .. }
+            | ASTNode::While { id, .. }
+            | ASTNode::For { id, .. }
+            | ASTNode::Return { id, .. }
+            | ASTNode::Let { id, .. }
+            | ASTNode::LetPattern { id, .. }
+            | ASTNode::Identifier { id, .. }
+            | ASTNode::Literal { id, .. }
+            | ASTNode::BinaryOp { id, .. }
+            | ASTNode::UnaryOp { id, .. }
+            | ASTNode::Cast { id, .. }
+            | ASTNode::Break { id, .. }
+            | ASTNode::Continue { id, .. }
+            | ASTNode::Closure { id, .. }
+            | ASTNode::ArrayLiteral { id, .. }
+            | ASTNode::TupleLiteral { id, .. }
+            | ASTNode::StructLiteral { id, .. }
+            | ASTNode::Assignment { id, .. }
+            | ASTNode::CompoundAssignment { id, .. }
+            | ASTNode::MemberAccess { id, .. }
+            | ASTNode::Index { id, .. }
+            | ASTNode::Error { id, .. } => *id,
+        }
+    }
+
+    /// The variable a place expression writes into: `p` for `p.pos.x` and
+    /// `grid` for `grid[i][j]`. `None` if this is not a place.
+    pub fn place_root(&self) -> Option<&str> {
+        match self {
+            ASTNode::Identifier { name, .. } => Some(name),
+            ASTNode::MemberAccess { object, .. } => object.place_root(),
+            ASTNode::Index { array, .. } => array.place_root(),
+            _ => None,
+        }
+    }
+
+    /// The id of the identifier `place_root` gives the name of.
+    pub fn place_root_id(&self) -> Option<NodeId> {
+        match self {
+            ASTNode::Identifier { id, .. } => Some(*id),
+            ASTNode::MemberAccess { object, .. } => object.place_root_id(),
+            ASTNode::Index { array, .. } => array.place_root_id(),
+            _ => None,
+        }
+    }
+
+    /// The attributes written before a function, struct or import, or at the
+    /// top of a module.
+    pub fn attributes(&self) -> &[Attribute] {
+        match self {
+            ASTNode::Function { attributes, .. }
+            | ASTNode::StructDef { attributes, .. }
+            | ASTNode::Import { attributes, .. }
+            | ASTNode::ModuleAttributes { attributes, .. } => attributes,
+            _ => &[],
+        }
+    }
+
+    /// The attribute called `name` written before this item, if any.
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes()
+            .iter()
+            .find(|attribute| attribute.name == name)
+    }
+
+    /// The callee's name when this is a call to a plain identifier.
+    pub fn callee_name(&self) -> Option<&str> {
+        match self {
+            ASTNode::Call { callee, .. } => match callee.as_ref() {
+                ASTNode::Identifier { name, .. } => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
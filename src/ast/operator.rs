@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOperator {
     Add,
@@ -6,10 +8,120 @@ pub enum BinaryOperator {
     Divide,
     Equal,
     NotEqual,
-    Less,
-    LessEqual,
-    Greater,
-    GreaterEqual,
+    LessThan,
+    LessThanEqual,
+    GreaterThan,
+    GreaterThanEqual,
     And,
     Or,
+    Modulo,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    LeftShift,
+    RightShift,
+    Range,
+    RangeInclusive,
+}
+
+impl BinaryOperator {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::LessThan
+                | BinaryOperator::LessThanEqual
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::GreaterThanEqual
+        )
+    }
+
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOperator::And | BinaryOperator::Or)
+    }
+
+    pub fn is_range(&self) -> bool {
+        matches!(self, BinaryOperator::Range | BinaryOperator::RangeInclusive)
+    }
+
+    /// Binding strength, loosest first. Every level is left-associative except
+    /// ranges, which cannot be chained.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Range | BinaryOperator::RangeInclusive => 1,
+            BinaryOperator::Or => 2,
+            BinaryOperator::And => 3,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 4,
+            BinaryOperator::LessThan
+            | BinaryOperator::LessThanEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanEqual => 5,
+            BinaryOperator::BitwiseOr => 6,
+            BinaryOperator::BitwiseXor => 7,
+            BinaryOperator::BitwiseAnd => 8,
+            BinaryOperator::LeftShift | BinaryOperator::RightShift => 9,
+            BinaryOperator::Add | BinaryOperator::Subtract => 10,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 11,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::LessThanEqual => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::GreaterThanEqual => ">=",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+            BinaryOperator::BitwiseAnd => "&",
+            BinaryOperator::BitwiseOr => "|",
+            BinaryOperator::BitwiseXor => "^",
+            BinaryOperator::LeftShift => "<<",
+            BinaryOperator::RightShift => ">>",
+            BinaryOperator::Range => "..",
+            BinaryOperator::RangeInclusive => "..=",
+        };
+        f.write_str(symbol)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    /// Postfix `?`
+    Try,
+    /// Prefix `await`
+    Await,
+    /// `&value`, a shared borrow
+    Ref,
+    /// `&mut value`, an exclusive borrow
+    RefMut,
+    /// `*reference`
+    Deref,
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+            UnaryOperator::Try => "?",
+            UnaryOperator::Await => "await",
+            UnaryOperator::Ref => "&",
+            UnaryOperator::RefMut => "&mut ",
+            UnaryOperator::Deref => "*",
+        };
+        f.write_str(symbol)
+    }
 }
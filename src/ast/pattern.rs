@@ -0,0 +1,112 @@
+use super::{Literal, NodeId};
+use crate::span::Span;
+
+/// The left-hand side of a `match` arm, or what a `let` or `for` binds.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`
+    Wildcard { span: Span },
+    /// A plain name, bound to the matched value.
+    Binding {
+        name: String,
+        id: NodeId,
+        span: Span,
+    },
+    /// Matches values equal to the literal, e.g. `0`, `-1` or `"quit"`.
+    Literal { value: Literal, span: Span },
+    /// An enum variant or struct: `Shape::Circle(r)`, `Rect { w, .. }`, `Shape::Empty`.
+    /// A bare name without fields is a `Binding`, so unit variants are written
+    /// with their enum.
+    Struct {
+        path: String,
+        fields: PatternFields,
+        span: Span,
+    },
+    /// `[first, .., last]`; `rest` is the position of the `..`, if any.
+    Array {
+        elements: Vec<Pattern>,
+        rest: Option<usize>,
+        span: Span,
+    },
+    /// `(first, _)`
+    Tuple { elements: Vec<Pattern>, span: Span },
+    /// `A | B`
+    Or {
+        alternatives: Vec<Pattern>,
+        span: Span,
+    },
+}
+
+/// The fields a struct pattern matches.
+#[derive(Debug, Clone)]
+pub enum PatternFields {
+    Unit,
+    Tuple(Vec<Pattern>),
+    /// `{ w, h: 0.0, .. }`; `rest` is set when `..` stands for the fields not listed.
+    Named {
+        fields: Vec<(String, Pattern)>,
+        rest: bool,
+    },
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard { span }
+            | Pattern::Binding { span, .. }
+            | Pattern::Literal { span, .. }
+            | Pattern::Struct { span, .. }
+            | Pattern::Array { span, .. }
+            | Pattern::Tuple { span, .. }
+            | Pattern::Or { span, .. } => *span,
+        }
+    }
+
+    /// The names the pattern binds, in order. Every alternative of an or-pattern
+    /// binds the same names, so only the first is looked at.
+    pub fn bindings(&self) -> Vec<(&str, Span)> {
+        let mut names = Vec::new();
+        self.collect_bindings(&mut names);
+        names
+            .into_iter()
+            .map(|(name, _, span)| (name, span))
+            .collect()
+    }
+
+    /// The names the pattern binds with the ids of their `Binding`s, in order.
+    pub fn binding_ids(&self) -> Vec<(&str, NodeId)> {
+        let mut names = Vec::new();
+        self.collect_bindings(&mut names);
+        names.into_iter().map(|(name, id, _)| (name, id)).collect()
+    }
+
+    fn collect_bindings<'a>(&'a self, names: &mut Vec<(&'a str, NodeId, Span)>) {
+        match self {
+            Pattern::Binding { name, id, span } => names.push((name, *id, *span)),
+            Pattern::Struct { fields, .. } => match fields {
+                PatternFields::Unit => {}
+                PatternFields::Tuple(patterns) => {
+                    for pattern in patterns {
+                        pattern.collect_bindings(names);
+                    }
+                }
+                PatternFields::Named { fields, .. } => {
+                    for (_, pattern) in fields {
+                        pattern.collect_bindings(names);
+                    }
+                }
+            },
+            Pattern::Array { elements, .. } | Pattern::Tuple { elements, .. } => {
+                for element in elements {
+                    element.collect_bindings(names);
+                }
+            }
+            Pattern::Or { alternatives, .. } => {
+                if let Some(first) = alternatives.first() {
+                    first.collect_bindings(names);
+                }
+            }
+            Pattern::Wildcard { .. } | Pattern::Literal { .. } => {}
+        }
+    }
+}
@@ -1,53 +1,631 @@
-#[derive(Debug, Clone, PartialEq)]
+use super::{ASTNode, NodeId};
+use crate::{error::IoError, span::Span, Result};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// The type representation shared by the parser, the checkers and code generation.
+#[derive(Debug, Clone, PartialEq, Default)]
 pub enum Type {
+    I8,
+    I16,
     I32,
     I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
     F32,
     F64,
     Bool,
+    Char,
     String,
     Void,
-    Array(Box<Type>),
+    Array {
+        elem_type: Box<Type>,
+        size: usize,
+    },
+    /// `[T; N]` sized by the constant `N`, until constant evaluation replaces
+    /// it with the `Array` of that size.
+    ConstArray {
+        elem_type: Box<Type>,
+        length: String,
+    },
     Function {
         params: Vec<Type>,
-        ret: Box<Type>,
+        return_type: Box<Type>,
+        is_async: bool,
     },
     Struct {
         name: String,
         fields: Vec<(String, Type)>,
     },
+    /// `(A, B)`, fields reached by position: `pair.0`.
+    Tuple(Vec<Type>),
     Pointer(Box<Type>),
+    /// A borrowed reference, `&T` or `&mut T`.
+    Ref {
+        mutable: bool,
+        inner: Box<Type>,
+    },
+    /// A user-defined type referenced by name, resolved by the type checker.
+    Named(String),
+    /// A type parameter of the enclosing generic function or struct.
+    Param(String),
+    /// A value of any type implementing the trait, called through a vtable.
+    Dyn(String),
+    /// A generic struct applied to type arguments, e.g. `Pair<i32, bool>`.
+    Generic {
+        name: String,
+        args: Vec<Type>,
+    },
+    /// An inference variable, standing for a type the checker has yet to work out.
+    Var(u32),
+    /// Not known yet, e.g. a `let` without annotation before checking.
+    #[default]
     Unknown,
 }
 
+impl Type {
+    pub fn is_integer(&self) -> bool {
+        self.int_bits().is_some()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::I128 | Type::Isize
+        )
+    }
+
+    /// The width of an integer type in bits. `isize` and `usize` are 64 bits
+    /// wide, the pointer width of every supported target.
+    pub fn int_bits(&self) -> Option<u32> {
+        match self {
+            Type::I8 | Type::U8 => Some(8),
+            Type::I16 | Type::U16 => Some(16),
+            Type::I32 | Type::U32 => Some(32),
+            Type::I64 | Type::U64 | Type::Isize | Type::Usize => Some(64),
+            Type::I128 | Type::U128 => Some(128),
+            _ => None,
+        }
+    }
+
+    /// The smallest and largest value of an integer type.
+    pub fn int_range(&self) -> Option<(i128, u128)> {
+        let bits = self.int_bits()?;
+        Some(if self.is_signed() {
+            (
+                i128::MIN >> (128 - bits),
+                (i128::MAX >> (128 - bits)) as u128,
+            )
+        } else {
+            (0, u128::MAX >> (128 - bits))
+        })
+    }
+
+    /// Whether `value` is in the range of this integer type.
+    pub fn fits_integer(&self, value: i128) -> bool {
+        self.int_range().is_some_and(|(min, max)| {
+            if value < 0 {
+                value >= min
+            } else {
+                value as u128 <= max
+            }
+        })
+    }
+
+    /// Whether `as` converts values of this type to `target`: any numeric
+    /// type to any other, `bool` and `char` to integers, and `u8` to `char`.
+    pub fn can_cast_to(&self, target: &Type) -> bool {
+        match (self, target) {
+            (from, to) if from == to => true,
+            (from, to) if from.is_numeric() && to.is_numeric() => true,
+            (Type::Bool | Type::Char, to) => to.is_integer(),
+            (Type::U8, Type::Char) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Whether assigning or passing a value of this type copies it rather than
+    /// moving it. References count as copies, since the borrows they carry are
+    /// tracked separately; types not worked out yet count too, so they never
+    /// cause a move error.
+    pub fn is_copy(&self) -> bool {
+        match self {
+            Type::String
+            | Type::Struct { .. }
+            | Type::Named(_)
+            | Type::Param(_)
+            | Type::Dyn(_)
+            | Type::Generic { .. } => false,
+            Type::Array { elem_type, .. } => elem_type.is_copy(),
+            Type::Tuple(elements) => elements.iter().all(Type::is_copy),
+            _ => true,
+        }
+    }
+
+    /// The type behind any number of references. Fields and elements are
+    /// reached through references implicitly.
+    pub fn referent(self) -> Type {
+        match self {
+            Type::Ref { inner, .. } => inner.referent(),
+            other => other,
+        }
+    }
+
+    /// Whether a value of this type may hold a reference.
+    pub fn has_refs(&self) -> bool {
+        match self {
+            Type::Ref { .. } | Type::Param(_) | Type::Var(_) | Type::Unknown => true,
+            // A closure may capture variables by reference.
+            Type::Function { .. } => true,
+            Type::Array { elem_type, .. } => elem_type.has_refs(),
+            Type::Struct { fields, .. } => fields.iter().any(|(_, ty)| ty.has_refs()),
+            Type::Tuple(elements) => elements.iter().any(Type::has_refs),
+            Type::Generic { args, .. } => args.iter().any(Type::has_refs),
+            _ => false,
+        }
+    }
+
+    /// Whether the type mentions a type parameter anywhere.
+    pub fn has_params(&self) -> bool {
+        match self {
+            Type::Param(_) => true,
+            Type::Array { elem_type, .. } => elem_type.has_params(),
+            Type::Function {
+                params,
+                return_type,
+                ..
+            } => params.iter().any(Type::has_params) || return_type.has_params(),
+            Type::Struct { fields, .. } => fields.iter().any(|(_, ty)| ty.has_params()),
+            Type::Tuple(elements) => elements.iter().any(Type::has_params),
+            Type::Pointer(inner) | Type::Ref { inner, .. } => inner.has_params(),
+            Type::Generic { args, .. } => args.iter().any(Type::has_params),
+            _ => false,
+        }
+    }
+
+    /// Matches this type, which may mention type parameters, against the concrete
+    /// type `actual`, adding the parameters it pins down to `bindings`. Returns
+    /// false if the types don't fit or a parameter would be bound twice differently.
+    /// An unsized array (`size` 0) matches arrays of any size.
+    pub fn bind(&self, actual: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+        match (self, actual) {
+            (Type::Param(name), actual) => match bindings.get(name) {
+                Some(bound) => bound == actual,
+                None => {
+                    bindings.insert(name.clone(), actual.clone());
+                    true
+                }
+            },
+            (
+                Type::Array { elem_type, size },
+                Type::Array {
+                    elem_type: actual_elem,
+                    size: actual_size,
+                },
+            ) => (*size == 0 || size == actual_size) && elem_type.bind(actual_elem, bindings),
+            (
+                Type::Function {
+                    params,
+                    return_type,
+                    ..
+                },
+                Type::Function {
+                    params: actual_params,
+                    return_type: actual_return,
+                    ..
+                },
+            ) => {
+                params.len() == actual_params.len()
+                    && params
+                        .iter()
+                        .zip(actual_params)
+                        .all(|(param, actual)| param.bind(actual, bindings))
+                    && return_type.bind(actual_return, bindings)
+            }
+            (Type::Tuple(elements), Type::Tuple(actual)) => {
+                elements.len() == actual.len()
+                    && elements
+                        .iter()
+                        .zip(actual)
+                        .all(|(element, actual)| element.bind(actual, bindings))
+            }
+            (Type::Pointer(inner), Type::Pointer(actual)) => inner.bind(actual, bindings),
+            (
+                Type::Ref { mutable, inner },
+                Type::Ref {
+                    mutable: actual_mutable,
+                    inner: actual,
+                },
+            ) => mutable == actual_mutable && inner.bind(actual, bindings),
+            (
+                Type::Generic { name, args },
+                Type::Generic {
+                    name: actual_name,
+                    args: actual_args,
+                },
+            ) => {
+                name == actual_name
+                    && args.len() == actual_args.len()
+                    && args
+                        .iter()
+                        .zip(actual_args)
+                        .all(|(arg, actual)| arg.bind(actual, bindings))
+            }
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    /// Replaces the type parameters bound in `bindings`; unbound ones are kept.
+    pub fn substitute(&self, bindings: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Param(name) => bindings.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::Array { elem_type, size } => Type::Array {
+                elem_type: Box::new(elem_type.substitute(bindings)),
+                size: *size,
+            },
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => Type::Function {
+                params: params.iter().map(|p| p.substitute(bindings)).collect(),
+                return_type: Box::new(return_type.substitute(bindings)),
+                is_async: *is_async,
+            },
+            Type::Struct { name, fields } => Type::Struct {
+                name: name.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(field, ty)| (field.clone(), ty.substitute(bindings)))
+                    .collect(),
+            },
+            Type::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| element.substitute(bindings))
+                    .collect(),
+            ),
+            Type::Pointer(inner) => Type::Pointer(Box::new(inner.substitute(bindings))),
+            Type::Ref { mutable, inner } => Type::Ref {
+                mutable: *mutable,
+                inner: Box::new(inner.substitute(bindings)),
+            },
+            Type::Generic { name, args } => Type::Generic {
+                name: name.clone(),
+                args: args.iter().map(|arg| arg.substitute(bindings)).collect(),
+            },
+            other => other.clone(),
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = IoError;
+
+    /// Parses a builtin type name; `int`, `float` and `unit` are aliases for the
+    /// default integer, float and empty types.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "i8" => Ok(Type::I8),
+            "i16" => Ok(Type::I16),
+            "int" | "i32" => Ok(Type::I32),
+            "i64" => Ok(Type::I64),
+            "i128" => Ok(Type::I128),
+            "isize" => Ok(Type::Isize),
+            "u8" => Ok(Type::U8),
+            "u16" => Ok(Type::U16),
+            "u32" => Ok(Type::U32),
+            "u64" => Ok(Type::U64),
+            "u128" => Ok(Type::U128),
+            "usize" => Ok(Type::Usize),
+            "f32" => Ok(Type::F32),
+            "float" | "f64" => Ok(Type::F64),
+            "bool" => Ok(Type::Bool),
+            "char" => Ok(Type::Char),
+            "string" | "str" => Ok(Type::String),
+            "void" | "unit" => Ok(Type::Void),
+            s if s.starts_with("array<") && s.ends_with('>') => {
+                let inner = s[6..s.len() - 1].trim();
+                Ok(Type::Array {
+                    elem_type: Box::new(inner.parse()?),
+                    size: 0,
+                })
+            }
+            _ => Err(IoError::type_error(format!("Unknown type: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::I128 => write!(f, "i128"),
+            Type::Isize => write!(f, "isize"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::U128 => write!(f, "u128"),
+            Type::Usize => write!(f, "usize"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Array { elem_type, size: 0 } => write!(f, "[{}]", elem_type),
+            Type::Array { elem_type, size } => write!(f, "[{}; {}]", elem_type, size),
+            Type::ConstArray { elem_type, length } => write!(f, "[{}; {}]", elem_type, length),
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => {
+                if *is_async {
+                    write!(f, "async ")?;
+                }
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", return_type)
+            }
+            Type::Struct { name, .. } | Type::Named(name) | Type::Param(name) => {
+                write!(f, "{}", name)
+            }
+            Type::Generic { name, args } => {
+                write!(f, "{}<", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ">")
+            }
+            // A tuple of one element keeps its comma: `(i32,)`.
+            Type::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Type::Pointer(inner) => write!(f, "*{}", inner),
+            Type::Ref {
+                mutable: false,
+                inner,
+            } => write!(f, "&{}", inner),
+            Type::Ref {
+                mutable: true,
+                inner,
+            } => write!(f, "&mut {}", inner),
+            Type::Dyn(name) => write!(f, "dyn {}", name),
+            Type::Var(_) | Type::Unknown => write!(f, "_"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Parameter {
     pub name: String,
-    pub r#type: Type,
+    pub type_annotation: Type,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// A method declared by a trait. One with a body is a default, used by
+/// implementations that leave the method out.
+#[derive(Debug, Clone)]
+pub struct TraitMethod {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub return_type: Option<Type>,
+    pub body: Option<Vec<ASTNode>>,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// A field of a struct definition.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub type_annotation: Type,
+    pub attributes: Vec<Attribute>,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// `#[name]` or `#[name(arg, ...)]` written before an item or field, e.g.
+/// `#[allow(unused_variables)]` or `#[cfg(not(test))]`.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<AttributeArg>,
+    pub span: Span,
+}
+
+impl Attribute {
+    /// The arguments that are plain names, like the lints of `#[allow(a, b)]`.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.args.iter().filter_map(|arg| match arg {
+            AttributeArg::Word(word) => Some(word.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The string given for `key`, as in `#[deprecated(note = "...")]`.
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.args.iter().find_map(|arg| match arg {
+            AttributeArg::Value(name, value) if name == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
 }
 
+/// An argument of an attribute.
 #[derive(Debug, Clone, PartialEq)]
-pub enum BinaryOperator {
-    Add,
-    Subtract,
-    Multiply,
-    Divide,
-    Equal,
-    NotEqual,
-    LessThan,
-    LessThanEqual,
-    GreaterThan,
-    GreaterThanEqual,
-    And,
-    Or,
+pub enum AttributeArg {
+    /// `unused_variables`
+    Word(String),
+    /// `target_os = "linux"`
+    Value(String, String),
+    /// `not(test)`
+    List(String, Vec<AttributeArg>),
 }
 
+/// A variant of an enum definition.
 #[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub fields: VariantFields,
+    pub id: NodeId,
+    pub span: Span,
+}
+
+/// The data a variant carries.
+#[derive(Debug, Clone)]
+pub enum VariantFields {
+    /// `Empty`
+    Unit,
+    /// `Circle(f64)`
+    Tuple(Vec<Type>),
+    /// `Rect { w: f64, h: f64 }`
+    Named(Vec<Field>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Integer(i64),
     Float(f64),
+    /// An integer with a type suffix, `200u8`.
+    TypedInteger(i128, Type),
+    /// A float with a type suffix, `2.5f32`.
+    TypedFloat(f64, Type),
     String(String),
     Boolean(bool),
-    Array(Vec<Literal>),
-    Unit,
+    Char(char),
+}
+
+impl Literal {
+    /// Type of the literal before any context is applied.
+    pub fn default_type(&self) -> Type {
+        match self {
+            Literal::Integer(_) => Type::I32,
+            Literal::Float(_) => Type::F64,
+            Literal::TypedInteger(_, ty) | Literal::TypedFloat(_, ty) => ty.clone(),
+            Literal::String(_) => Type::String,
+            Literal::Boolean(_) => Type::Bool,
+            Literal::Char(_) => Type::Char,
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::Float(value) if value.fract() == 0.0 && value.is_finite() => {
+                write!(f, "{:.1}", value)
+            }
+            Literal::Float(value) => write!(f, "{}", value),
+            Literal::TypedInteger(value, ty) => write!(f, "{}{}", value, ty),
+            Literal::TypedFloat(value, ty) => write!(f, "{}{}", Literal::Float(*value), ty),
+            Literal::String(value) => write!(f, "{:?}", value),
+            Literal::Boolean(value) => write!(f, "{}", value),
+            Literal::Char(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.args.is_empty() {
+            write!(f, "#[{}]", self.name)
+        } else {
+            write!(f, "#[{}({})]", self.name, join_args(&self.args))
+        }
+    }
+}
+
+impl fmt::Display for AttributeArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeArg::Word(word) => write!(f, "{}", word),
+            AttributeArg::Value(key, value) => write!(f, "{} = {:?}", key, value),
+            AttributeArg::List(name, args) => write!(f, "{}({})", name, join_args(args)),
+        }
+    }
+}
+
+fn join_args(args: &[AttributeArg]) -> String {
+    args.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_names_round_trip() {
+        for name in [
+            "i32", "u8", "i128", "usize", "f64", "bool", "char", "string", "[i64]",
+        ] {
+            let ty: Type = name
+                .replace('[', "array<")
+                .replace(']', ">")
+                .parse()
+                .unwrap();
+            assert_eq!(ty.to_string(), name);
+        }
+        assert_eq!("int".parse::<Type>().unwrap(), Type::I32);
+        assert!("widget".parse::<Type>().is_err());
+    }
+
+    #[test]
+    fn test_integer_ranges_and_casts() {
+        assert_eq!(Type::I8.int_range(), Some((-128, 127)));
+        assert_eq!(Type::U16.int_range(), Some((0, 65535)));
+        assert_eq!(Type::U128.int_range(), Some((0, u128::MAX)));
+        assert_eq!(Type::F32.int_range(), None);
+        assert!(Type::I8.fits_integer(-128));
+        assert!(!Type::U8.fits_integer(-1));
+        assert!(!Type::U8.fits_integer(256));
+
+        assert!(Type::F64.can_cast_to(&Type::U8));
+        assert!(Type::Bool.can_cast_to(&Type::I64));
+        assert!(Type::U8.can_cast_to(&Type::Char));
+        assert!(!Type::U32.can_cast_to(&Type::Char));
+        assert!(!Type::String.can_cast_to(&Type::I32));
+        assert!(!Type::I32.can_cast_to(&Type::Bool));
+    }
 }
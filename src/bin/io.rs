@@ -1,7 +1,22 @@
 use clap::{Parser, Subcommand};
 use inkwell::context::Context;
-use io_lang::{compiler::Compiler, Result};
-use std::path::PathBuf;
+use io_lang::{
+    analysis::{CodeAnalyzer, Level, LintConfig, LINTS},
+    build::{cfg::Cfg, Target},
+    compiler::Compiler,
+    diagnostics::Diagnostic,
+    diagnostics::SourceMap,
+    formatter::{unified, CodeFormatter, FormattingConfig},
+    macro_system,
+    module::ModuleManager,
+    parser::parse_source,
+    repl::Repl,
+    runtime::Interpreter,
+    semantic::{deprecated, resolve},
+    ASTNode, IoError, Result,
+};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 #[derive(Parser)]
 #[command(name = "io")]
@@ -30,6 +45,14 @@ enum Commands {
         #[arg(short, long)]
         args: Vec<String>,
     },
+    /// Print a file with its macros expanded.
+    Expand {
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// Start an interactive session.
+    Repl,
+    /// Run the `#[test]` functions of source files.
     Test {
         #[arg(short, long)]
         path: PathBuf,
@@ -40,13 +63,26 @@ enum Commands {
         #[arg(short, long)]
         parallel: bool,
     },
-    Format {
-        #[arg(short, long)]
+    /// Format source files in place, using the `[format]` table of `io.toml`.
+    #[command(visible_alias = "format")]
+    Fmt {
+        #[arg(short, long, default_value = ".")]
         path: PathBuf,
 
+        /// Print a diff and exit with an error instead of rewriting files.
         #[arg(short, long)]
         check: bool,
     },
+    /// Check source files for likely mistakes, using the `[lints]` table of
+    /// `io.toml`. Exits with an error if a denied lint fires.
+    Lint {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// List the lints and their levels instead.
+        #[arg(short, long)]
+        list: bool,
+    },
 }
 
 fn main() -> Result<()> {
@@ -54,7 +90,6 @@ fn main() -> Result<()> {
     io_lang::init_logging();
 
     let cli = Cli::parse();
-    let context = Context::create();
 
     match cli.command {
         Commands::Build {
@@ -62,6 +97,7 @@ fn main() -> Result<()> {
             output,
             release,
         } => {
+            let context = Context::create();
             let mut compiler = Compiler::new(&context);
             if release {
                 compiler.set_optimization_level(OptimizationLevel::Aggressive);
@@ -76,8 +112,21 @@ fn main() -> Result<()> {
             println!("Build completed successfully!");
         }
         Commands::Run { file, args } => {
-            let executor = Executor::new();
-            executor.run_file(file, args)?;
+            if !run_file(file, args)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Expand { file } => {
+            if !expand_file(file)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Repl => {
+            let mut repl = Repl::new(Interpreter::new());
+            if let Some(path) = Repl::default_history_path() {
+                repl = repl.with_history_file(path);
+            }
+            repl.run(&mut std::io::stdin().lock(), &mut std::io::stdout().lock())?;
         }
         Commands::Test {
             path,
@@ -93,12 +142,22 @@ fn main() -> Result<()> {
             }
             test_runner.run_tests(path)?;
         }
-        Commands::Format { path, check } => {
-            let formatter = Formatter::new();
+        Commands::Fmt { path, check } => {
+            let formatter = Formatter::new(&path)?;
             if check {
-                formatter.check(path)?;
+                if !formatter.check(&path)? {
+                    std::process::exit(1);
+                }
             } else {
-                formatter.format(path)?;
+                formatter.format(&path)?;
+            }
+        }
+        Commands::Lint { path, list } => {
+            let config = LintConfig::load(&path)?;
+            if list {
+                list_lints(&config);
+            } else if !lint(&path, &config)? {
+                std::process::exit(1);
             }
         }
     }
@@ -106,24 +165,94 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-struct Executor {
-    runtime: Runtime,
-    context: ExecutionContext,
+/// Interprets `path` directly, with the modules it imports, printing a
+/// diagnostic if it fails to parse or run; returns whether it succeeded.
+fn run_file(path: PathBuf, args: Vec<String>) -> Result<bool> {
+    let source = std::fs::read_to_string(&path)?;
+    let mut source_map = SourceMap::new();
+    let file_id = source_map.add_file(path.clone(), source);
+
+    let mut modules = ModuleManager::for_file(&path);
+    let result = modules
+        .load_program(file_id, &mut source_map)
+        .and_then(|program| {
+            let resolution = resolve::resolve(&program)?;
+            for warning in deprecated::check(&program, &resolution)? {
+                eprintln!("{}", warning.report(&source_map));
+            }
+            let mut interpreter = Interpreter::new();
+            interpreter.context_mut().set_args(args);
+            interpreter.run(&program)
+        });
+
+    if let Err(err) = result {
+        eprintln!("{}", Diagnostic::from_error(&err).report(&source_map));
+        return Ok(false);
+    }
+    Ok(true)
 }
 
-impl Executor {
-    fn new() -> Self {
-        Self {
-            runtime: Runtime::new(),
-            context: ExecutionContext::new(),
+/// Prints `path` with its macros expanded and the result formatted, or a
+/// diagnostic if the expansion fails; returns whether it succeeded.
+fn expand_file(path: PathBuf) -> Result<bool> {
+    let source = std::fs::read_to_string(&path)?;
+    let mut source_map = SourceMap::new();
+    let file_id = source_map.add_file(path.clone(), source.clone());
+
+    let config = FormattingConfig::load(&path)?;
+    let result = macro_system::expanded_source(&source, file_id, &mut source_map)
+        .and_then(|expanded| CodeFormatter::new(&config).format(&expanded));
+    match result {
+        Ok(expanded) => {
+            print!("{}", expanded);
+            Ok(true)
+        }
+        Err(err) => {
+            eprintln!("{}", Diagnostic::from_error(&err).report(&source_map));
+            Ok(false)
         }
     }
+}
 
-    fn run_file(&self, path: PathBuf, args: Vec<String>) -> Result<()> {
-        let code = std::fs::read_to_string(path)?;
+/// Prints the lints of every file at `path`; returns whether none was an
+/// error.
+fn lint(path: &Path, config: &LintConfig) -> Result<bool> {
+    let analyzer = CodeAnalyzer::new(config);
+    // Tests are linted too.
+    let cfg = Cfg::new(Target::Native).with_test(true);
+    let mut source_map = SourceMap::new();
+    let mut clean = true;
+    for file in source_files(path) {
+        let source = std::fs::read_to_string(&file)?;
+        let file_id = source_map.add_file(file.clone(), source.clone());
+        let report = parse_source(&source, file_id)
+            .and_then(|program| cfg.strip(program))
+            .and_then(|program| {
+                let resolution = resolve::resolve(&program)?;
+                analyzer.analyze(&program, &resolution)
+            });
+        match report {
+            Ok(report) => {
+                eprint!("{}", report.report(&source_map));
+                clean &= !report.has_errors();
+            }
+            Err(err) => {
+                eprintln!("{}", Diagnostic::from_error(&err).report(&source_map));
+                clean = false;
+            }
+        }
+    }
+    Ok(clean)
+}
 
-        self.context.set_args(args);
-        self.runtime.execute(&code, &self.context)
+fn list_lints(config: &LintConfig) {
+    for lint in LINTS {
+        let level = match config.level(lint) {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        };
+        println!("{:<18} {:<5} {}", lint.name, level, lint.description);
     }
 }
 
@@ -199,6 +328,57 @@ impl TestRunner {
         }
         Ok(())
     }
+
+    /// Runs each `#[test]` function of `file` in an interpreter of its own,
+    /// with the modules the file imports; fails if any of them fails.
+    fn run_single_test(&self, file: &PathBuf) -> Result<()> {
+        let source = std::fs::read_to_string(file)?;
+        let mut source_map = SourceMap::new();
+        let file_id = source_map.add_file(file.clone(), source);
+        let program = ModuleManager::for_file(file)
+            .with_cfg(Cfg::new(Target::Native).with_test(true))
+            .load_program(file_id, &mut source_map)
+            .inspect_err(|err| {
+                eprintln!("{}", Diagnostic::from_error(err).report(&source_map));
+            })?;
+
+        // The tests of imported modules are run with those modules' files.
+        let ASTNode::Program(items) = &program else {
+            return Ok(());
+        };
+        let tests = items.iter().filter_map(|item| match item {
+            ASTNode::Function { name, span, .. }
+                if span.file_id == file_id && item.attribute("test").is_some() =>
+            {
+                Some(name)
+            }
+            _ => None,
+        });
+        let mut failed = 0;
+        for name in tests {
+            let mut interpreter = Interpreter::new();
+            let result = interpreter.run_entry(&program).and_then(|_| {
+                let test = interpreter.context().get(name).unwrap_or_default();
+                interpreter.call(test, Vec::new())
+            });
+            match result {
+                Ok(_) => println!("test {} ... ok", name),
+                Err(err) => {
+                    failed += 1;
+                    println!("test {} ... FAILED", name);
+                    eprintln!("{}", Diagnostic::from_error(&err).report(&source_map));
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(IoError::runtime_error(format!(
+                "{} test(s) failed in {}",
+                failed,
+                file.display()
+            )));
+        }
+        Ok(())
+    }
 }
 
 struct Formatter {
@@ -206,79 +386,60 @@ struct Formatter {
 }
 
 impl Formatter {
-    fn new() -> Self {
-        Self {
-            config: FormattingConfig::default(),
-        }
-    }
-
-    fn format(&self, path: PathBuf) -> Result<()> {
-        if path.is_dir() {
-            self.format_directory(path)
-        } else {
-            self.format_file(path)
-        }
+    fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            config: FormattingConfig::load(path)?,
+        })
     }
 
-    fn check(&self, path: PathBuf) -> Result<()> {
-        if path.is_dir() {
-            self.check_directory(path)
-        } else {
-            self.check_file(path)
-        }
-    }
-
-    fn format_directory(&self, dir: PathBuf) -> Result<()> {
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "io") {
-                self.format_file(path)?;
+    fn format(&self, path: &Path) -> Result<()> {
+        for file in source_files(path) {
+            let code = std::fs::read_to_string(&file)?;
+            let formatted = self.format_code(&file, &code)?;
+            if formatted != code {
+                std::fs::write(&file, formatted)?;
             }
         }
         Ok(())
     }
 
-    fn check_directory(&self, dir: PathBuf) -> Result<()> {
-        let mut has_errors = false;
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "io") {
-                if let Err(e) = self.check_file(path.clone()) {
-                    has_errors = true;
-                    eprintln!("Formatting error in {}: {}", path.display(), e);
-                }
+    /// Prints a diff for every file that isn't formatted; returns whether all were.
+    fn check(&self, path: &Path) -> Result<bool> {
+        let mut formatted_already = true;
+        for file in source_files(path) {
+            let code = std::fs::read_to_string(&file)?;
+            let formatted = self.format_code(&file, &code)?;
+            if formatted != code {
+                formatted_already = false;
+                print!(
+                    "{}",
+                    unified(&file.display().to_string(), &code, &formatted)
+                );
             }
         }
-        if has_errors {
-            Err(io_lang::Error::FormattingError)
-        } else {
-            Ok(())
-        }
+        Ok(formatted_already)
     }
 
-    fn format_file(&self, path: PathBuf) -> Result<()> {
-        let code = std::fs::read_to_string(&path)?;
-        let formatted = self.format_code(&code)?;
-        std::fs::write(&path, formatted)?;
-        Ok(())
-    }
-
-    fn check_file(&self, path: PathBuf) -> Result<()> {
-        let code = std::fs::read_to_string(&path)?;
-        let formatted = self.format_code(&code)?;
-        if code != formatted {
-            Err(io_lang::Error::FormattingError)
-        } else {
-            Ok(())
-        }
+    fn format_code(&self, path: &Path, code: &str) -> Result<String> {
+        CodeFormatter::new(&self.config)
+            .format(code)
+            .inspect_err(|err| {
+                let mut source_map = SourceMap::new();
+                source_map.add_file(path.to_path_buf(), code.to_string());
+                eprintln!("{}", Diagnostic::from_error(err).report(&source_map));
+            })
     }
+}
 
-    fn format_code(&self, code: &str) -> Result<String> {
-        let parser = Parser::new(code);
-        let ast = parser.parse()?;
-        let formatter = CodeFormatter::new(&self.config);
-        Ok(formatter.format(&ast))
+/// `path` itself, or every `.io` file below it.
+fn source_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
     }
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "io"))
+        .collect()
 }
@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand};
-use io_lang::{build::BuildConfig, Result};
+use io_lang::{
+    build::BuildConfig, diagnostics::SourceMap, parser::parse_source_with_recovery, IoError, Result,
+};
 use std::{fs, path::PathBuf, process::Command};
 
 #[derive(Parser)]
@@ -71,6 +73,7 @@ fn main() -> Result<()> {
             release,
         } => {
             println!("Building project...");
+            check_syntax(&input)?;
             let config = BuildConfig {
                 input_path: input.clone(),
                 output_path: output.clone(),
@@ -86,6 +89,7 @@ fn main() -> Result<()> {
         }
         Commands::Run { file } => {
             println!("Running project...");
+            check_syntax(&file)?;
 
             // First build the project to a temporary location
             let temp_output = std::env::temp_dir().join("io_temp_executable");
@@ -160,6 +164,26 @@ fn main() -> Result<()> {
     }
 }
 
+/// Parses `path` with error recovery and prints every syntax error it contains.
+fn check_syntax(path: &PathBuf) -> Result<()> {
+    let source = fs::read_to_string(path)?;
+    let mut source_map = SourceMap::new();
+    let file_id = source_map.add_file(path.clone(), source.clone());
+
+    let (_, diagnostics) = parse_source_with_recovery(&source, file_id);
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+
+    for diagnostic in &diagnostics {
+        eprintln!("{}\n", diagnostic.report(&source_map));
+    }
+    Err(IoError::parser_error(format!(
+        "aborting due to {} syntax error(s)",
+        diagnostics.len()
+    )))
+}
+
 fn run_test(test_path: &PathBuf) -> Result<()> {
     // Build and run test file
     let temp_output = std::env::temp_dir().join("io_test_executable");
@@ -0,0 +1,240 @@
+//! Conditional compilation. `#[cfg(condition)]` keeps the item or field it is
+//! written on only when the condition holds for the target being built for:
+//!
+//! - `target_arch = "x86_64"` and `target_os = "linux"` compare against the
+//!   [`Target`],
+//! - `test` holds when building for `io test`,
+//! - `not(c)`, `all(c, ...)` and `any(c, ...)` combine conditions.
+//!
+//! `#[test]` functions are kept only when building for `io test`. An
+//! `#[cfg]` on an `import` leaves its module unloaded when it doesn't hold,
+//! and a `#![cfg]` at the top of a file leaves the whole module empty.
+
+use super::Target;
+use crate::{
+    ast::{ASTNode, Attribute, AttributeArg, Field, VariantFields},
+    error::IoError,
+    Result,
+};
+
+/// What `#[cfg]` conditions are evaluated against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cfg {
+    pub target: Target,
+    /// Whether the program is built for `io test`.
+    pub test: bool,
+}
+
+impl Cfg {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            test: false,
+        }
+    }
+
+    pub fn with_test(mut self, test: bool) -> Self {
+        self.test = test;
+        self
+    }
+
+    /// Whether `condition` holds.
+    pub fn holds(&self, condition: &AttributeArg) -> Result<bool> {
+        match condition {
+            AttributeArg::Word(word) if word == "test" => Ok(self.test),
+            AttributeArg::Value(key, value) if key == "target_arch" => {
+                Ok(self.target.arch() == value)
+            }
+            AttributeArg::Value(key, value) if key == "target_os" => Ok(self.target.os() == value),
+            AttributeArg::List(name, args) if name == "not" && args.len() == 1 => {
+                Ok(!self.holds(&args[0])?)
+            }
+            AttributeArg::List(name, args) if name == "all" => {
+                for arg in args {
+                    if !self.holds(arg)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            AttributeArg::List(name, args) if name == "any" => {
+                for arg in args {
+                    if self.holds(arg)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            other => Err(IoError::validation_error(format!(
+                "Unknown cfg condition `{}`",
+                other
+            ))),
+        }
+    }
+
+    /// Whether an item or field with `attributes` is compiled.
+    pub fn enabled(&self, attributes: &[Attribute]) -> Result<bool> {
+        for attribute in attributes {
+            let enabled = match (attribute.name.as_str(), attribute.args.as_slice()) {
+                ("cfg", [condition]) => self
+                    .holds(condition)
+                    .map_err(|err| err.with_span(attribute.span))?,
+                ("test", _) => self.test,
+                _ => true,
+            };
+            if !enabled {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// `program` without the items, methods and fields configured out, or
+    /// with none at all if its `#![cfg]` doesn't hold.
+    pub fn strip(&self, program: ASTNode) -> Result<ASTNode> {
+        match program {
+            ASTNode::Program(items) => {
+                if let Some(module @ ASTNode::ModuleAttributes { .. }) = items.first() {
+                    if !self.enabled(module.attributes())? {
+                        return Ok(ASTNode::Program(Vec::new()));
+                    }
+                }
+                Ok(ASTNode::Program(self.items(items)?))
+            }
+            other => Ok(other),
+        }
+    }
+
+    fn items(&self, items: Vec<ASTNode>) -> Result<Vec<ASTNode>> {
+        let mut kept = Vec::new();
+        for mut item in items {
+            if !self.enabled(item.attributes())? {
+                continue;
+            }
+            match &mut item {
+                ASTNode::StructDef { fields, .. } => {
+                    *fields = self.fields(std::mem::take(fields))?;
+                }
+                ASTNode::EnumDef { variants, .. } => {
+                    for variant in variants {
+                        if let VariantFields::Named(fields) = &mut variant.fields {
+                            *fields = self.fields(std::mem::take(fields))?;
+                        }
+                    }
+                }
+                ASTNode::Impl { methods, .. } => {
+                    *methods = self.items(std::mem::take(methods))?;
+                }
+                _ => {}
+            }
+            kept.push(item);
+        }
+        Ok(kept)
+    }
+
+    fn fields(&self, fields: Vec<Field>) -> Result<Vec<Field>> {
+        let mut kept = Vec::new();
+        for field in fields {
+            if self.enabled(&field.attributes)? {
+                kept.push(field);
+            }
+        }
+        Ok(kept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+
+    /// The names of the items `cfg` keeps of `source`, with the fields of
+    /// structs after a `.`.
+    fn kept(source: &str, cfg: Cfg) -> Result<Vec<String>> {
+        let program = cfg.strip(parse_source(source, FileId(0)).unwrap())?;
+        let ASTNode::Program(items) = program else {
+            panic!("expected program");
+        };
+        let mut names = Vec::new();
+        for item in &items {
+            match item {
+                ASTNode::Function { name, .. } => names.push(name.clone()),
+                ASTNode::StructDef { name, fields, .. } => {
+                    names.push(name.clone());
+                    names.extend(
+                        fields
+                            .iter()
+                            .map(|field| format!("{}.{}", name, field.name)),
+                    );
+                }
+                ASTNode::Import { path, .. } => names.push(path.join("::")),
+                _ => {}
+            }
+        }
+        Ok(names)
+    }
+
+    #[test]
+    fn test_conditions_follow_the_target() {
+        let source = "\
+#[cfg(target_os = \"windows\")]
+import win::{console};
+#[cfg(target_os = \"linux\")]
+fn separator() -> char { return '/'; }
+#[cfg(not(target_os = \"linux\"))]
+fn separator() -> char { return '\\\\'; }
+#[cfg(any(target_arch = \"wasm32\", all(target_arch = \"aarch64\", test)))]
+fn small() {}
+struct Handle { id: int, #[cfg(target_os = \"windows\")] raw: int }";
+        let linux = Cfg::new(Target::X86_64Linux);
+        assert_eq!(
+            kept(source, linux).unwrap(),
+            ["separator", "Handle", "Handle.id"]
+        );
+        assert_eq!(
+            kept(source, Cfg::new(Target::X86_64Windows)).unwrap(),
+            ["win", "separator", "Handle", "Handle.id", "Handle.raw"]
+        );
+        assert_eq!(
+            kept(source, Cfg::new(Target::Wasm32)).unwrap(),
+            ["separator", "small", "Handle", "Handle.id"]
+        );
+        let aarch64 = Cfg::new(Target::Aarch64);
+        assert!(!kept(source, aarch64)
+            .unwrap()
+            .contains(&"small".to_string()));
+        assert!(kept(source, aarch64.with_test(true))
+            .unwrap()
+            .contains(&"small".to_string()));
+    }
+
+    #[test]
+    fn test_functions_only_exist_under_test() {
+        let source = "fn main() {}\n#[test]\nfn adds() {}\n#[cfg(test)]\nfn helper() {}";
+        let cfg = Cfg::new(Target::Native);
+        assert_eq!(kept(source, cfg).unwrap(), ["main"]);
+        assert_eq!(
+            kept(source, cfg.with_test(true)).unwrap(),
+            ["main", "adds", "helper"]
+        );
+    }
+
+    #[test]
+    fn test_whole_modules_exist_under_test() {
+        let source = "#![cfg(test)]\n#![allow(unused_variables)]\nfn check() {}\nstruct Fixture {}";
+        let cfg = Cfg::new(Target::Native);
+        assert!(kept(source, cfg).unwrap().is_empty());
+        assert_eq!(
+            kept(source, cfg.with_test(true)).unwrap(),
+            ["check", "Fixture"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_conditions() {
+        let source = "#[cfg(target = \"wasm\")]\nfn f() {}";
+        let err = kept(source, Cfg::new(Target::Native)).unwrap_err();
+        assert_eq!(err.message(), "Unknown cfg condition `target = \"wasm\"`");
+        assert_eq!(err.span().unwrap().start, 0);
+    }
+}
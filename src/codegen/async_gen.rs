@@ -3,7 +3,7 @@ use inkwell::{
     values::{FunctionValue, BasicValueEnum},
 };
 use crate::{
-    ast::{ASTNode, ASTVisitor},
+    ast::ASTNode,
     error::IoError,
     Result,
 };
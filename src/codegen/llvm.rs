@@ -1,10 +1,19 @@
 use crate::codegen::debug::{DebugInfo, SourceLocation};
+use crate::codegen::monomorphize::{self, DynCall, VariantPath};
 use crate::{
-    ast::{ASTNode, BinaryOperator, Function, Module as AstModule},
+    ast::{
+        ASTNode, Attribute, BinaryOperator, Literal, MatchArm, NodeId, Parameter, Pattern,
+        PatternFields, Type, UnaryOperator,
+    },
     error::IoError,
+    semantic::resolve::{Resolution, SymbolId},
+    stdlib::prelude,
+    types::checker::Implementation,
+    visitor::{walk_node, walk_nodes, Visitor},
     Result,
 };
 use inkwell::{
+    attributes::{Attribute as LLVMAttribute, AttributeLoc},
     builder::Builder,
     context::Context,
     debug_info::{
@@ -14,21 +23,85 @@ use inkwell::{
     module::Module,
     passes::PassManager,
     targets::{CodeModel, FileType, RelocMode, Target, TargetMachine},
-    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
-    values::{BasicValue, BasicValueEnum, CallSiteValue, FunctionValue},
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType},
+    values::{
+        BasicMetadataValueEnum, BasicValue, BasicValueEnum, CallSiteValue, CallableValue,
+        FunctionValue, IntValue, PointerValue, StructValue,
+    },
     AddressSpace, OptimizationLevel,
 };
-use std::collections::HashMap; // Add if not imported
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
 
 pub struct LLVMCodeGen<'ctx> {
     pub(crate) context: &'ctx Context,
     pub(crate) module: Module<'ctx>,
     pub(crate) builder: Builder<'ctx>,
-    named_values: HashMap<String, BasicValueEnum<'ctx>>,
+    /// The slot of each local variable, by its symbol.
+    named_values: HashMap<SymbolId, BasicValueEnum<'ctx>>,
+    /// The function type of each variable holding a closure, by its symbol.
+    callables: HashMap<SymbolId, Type>,
     current_function: Option<FunctionValue<'ctx>>,
     optimization_level: OptimizationLevel,
     function_pass_manager: inkwell::passes::PassManager<FunctionValue<'ctx>>,
     types: HashMap<String, BasicTypeEnum<'ctx>>,
+    /// The methods of each trait in vtable slot order.
+    vtables: HashMap<String, Vec<String>>,
+    /// Values converted to `dyn Trait`, by node id.
+    coercions: HashMap<NodeId, Implementation>,
+    /// Method calls through a vtable, by call node id.
+    dyn_calls: HashMap<NodeId, DynCall>,
+    /// The type of each variable, by the node id declaring it.
+    binding_types: HashMap<NodeId, Type>,
+    /// Identifiers whose use moves the value out of their variable.
+    moves: HashSet<NodeId>,
+    /// Variables owning heap memory, innermost scope last.
+    drop_scopes: Vec<Vec<DropSlot<'ctx>>>,
+    /// The variants of each enum in declaration order, with their field counts.
+    enums: HashMap<String, Vec<(String, usize)>>,
+    /// What each `Enum::Variant` path builds, by qualified name.
+    variant_paths: HashMap<String, VariantPath>,
+    /// The enum each `?` takes apart, by node id.
+    tries: HashMap<NodeId, String>,
+    /// The type of each number literal, the operand type of each operator and
+    /// integer intrinsic, and the source type of each `as`, by node id.
+    numeric_types: HashMap<NodeId, Type>,
+    /// The position of the field each member access names, by node id.
+    field_indices: HashMap<NodeId, usize>,
+    /// The laid-out struct each struct literal builds, by node id.
+    struct_types: HashMap<NodeId, Type>,
+    /// What `+`, `-` and `*` do when the result does not fit the integer type.
+    overflow: OverflowMode,
+    /// The symbol each binding declares and each identifier refers to, by
+    /// node id.
+    resolution: Resolution,
+}
+
+/// What integer `+`, `-` and `*` do on overflow. Debug builds trap, release
+/// builds wrap around; `wrapping_*` and `checked_*` choose explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowMode {
+    Trap,
+    Wrap,
+}
+
+impl From<OptimizationLevel> for OverflowMode {
+    fn from(level: OptimizationLevel) -> Self {
+        match level {
+            OptimizationLevel::None => OverflowMode::Trap,
+            _ => OverflowMode::Wrap,
+        }
+    }
+}
+
+/// A variable whose value is freed when it goes out of scope, unless `flag`
+/// was cleared by moving the value elsewhere.
+struct DropSlot<'ctx> {
+    symbol: SymbolId,
+    value: PointerValue<'ctx>,
+    flag: PointerValue<'ctx>,
 }
 
 impl<'ctx> LLVMCodeGen<'ctx> {
@@ -60,21 +133,68 @@ impl<'ctx> LLVMCodeGen<'ctx> {
             module,
             builder,
             named_values: HashMap::new(),
+            callables: HashMap::new(),
             current_function: None,
             optimization_level: OptimizationLevel::Default,
             function_pass_manager,
             types: HashMap::new(),
+            vtables: HashMap::new(),
+            coercions: HashMap::new(),
+            dyn_calls: HashMap::new(),
+            binding_types: HashMap::new(),
+            moves: HashSet::new(),
+            drop_scopes: Vec::new(),
+            enums: HashMap::new(),
+            variant_paths: HashMap::new(),
+            tries: HashMap::new(),
+            numeric_types: HashMap::new(),
+            field_indices: HashMap::new(),
+            struct_types: HashMap::new(),
+            overflow: OverflowMode::Trap,
+            resolution: Resolution::default(),
         }
     }
 
-    pub fn generate(&mut self, node: &ASTNode) -> Result<()> {
-        self.visit_node(node)?;
+    /// Sets the optimization level, and with it the overflow mode: trapping
+    /// without optimizations, wrapping with them.
+    pub fn set_optimization_level(&mut self, level: OptimizationLevel) {
+        self.optimization_level = level;
+        self.overflow = OverflowMode::from(level);
+    }
+
+    pub fn set_overflow_mode(&mut self, mode: OverflowMode) {
+        self.overflow = mode;
+    }
+
+    /// Generates code for `node`, whose names `resolution` resolved, after
+    /// type- and borrow-checking it and monomorphizing its generic functions,
+    /// structs and traits.
+    pub fn generate(&mut self, node: &ASTNode, resolution: &Resolution) -> Result<()> {
+        let lowered = monomorphize::monomorphize(node, resolution)?;
+        self.vtables = lowered.vtables;
+        self.coercions = lowered.coercions;
+        self.dyn_calls = lowered.dyn_calls;
+        self.binding_types = lowered.binding_types;
+        self.moves = lowered.moves;
+        self.enums = lowered.enums;
+        self.variant_paths = lowered.variant_paths;
+        self.tries = lowered.tries;
+        self.numeric_types = lowered.numeric_types;
+        self.field_indices = lowered.field_indices;
+        self.struct_types = lowered.struct_types;
+        self.resolution = resolution.clone();
+        self.visit_node(&lowered.program)?;
         if self.module.verify().is_err() {
             return Err(IoError::runtime_error("LLVM module verification failed"));
         }
         Ok(())
     }
 
+    /// Textual LLVM IR for everything generated so far.
+    pub fn emit_ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
+
     fn get_function(&self, name: &str) -> Option<FunctionValue<'ctx>> {
         self.module.get_function(name)
     }
@@ -94,23 +214,33 @@ impl<'ctx> LLVMCodeGen<'ctx> {
         inkwell::values::BasicValueEnum::PointerValue(builder.build_alloca(ty, name))
     }
 
+    /// `left op right`, where `operand_type` is the type of both operands as
+    /// far as the type checker knows it; integers without one count as signed.
     fn generate_binary_op(
         &self,
         op: &BinaryOperator,
         left: BasicValueEnum<'ctx>,
         right: BasicValueEnum<'ctx>,
+        operand_type: Option<&Type>,
     ) -> Result<BasicValueEnum<'ctx>> {
+        let unsigned = operand_type.is_some_and(|ty| ty.is_integer() && !ty.is_signed());
         match (left.get_type(), right.get_type()) {
             (l, r) if l.is_int_type() && r.is_int_type() => {
                 let l = left.into_int_value();
                 let r = right.into_int_value();
                 Ok(match op {
-                    BinaryOperator::Add => self.builder.build_int_add(l, r, "addtmp").into(),
-                    BinaryOperator::Subtract => self.builder.build_int_sub(l, r, "subtmp").into(),
-                    BinaryOperator::Multiply => self.builder.build_int_mul(l, r, "multmp").into(),
+                    BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply => {
+                        self.overflowing(op, l, r, unsigned)?.into()
+                    }
+                    BinaryOperator::Divide if unsigned => {
+                        self.builder.build_int_unsigned_div(l, r, "divtmp").into()
+                    }
                     BinaryOperator::Divide => {
                         self.builder.build_int_signed_div(l, r, "divtmp").into()
                     }
+                    BinaryOperator::Modulo if unsigned => {
+                        self.builder.build_int_unsigned_rem(l, r, "modtmp").into()
+                    }
                     BinaryOperator::Modulo => {
                         self.builder.build_int_signed_rem(l, r, "modtmp").into()
                     }
@@ -122,7 +252,7 @@ impl<'ctx> LLVMCodeGen<'ctx> {
                     }
                     BinaryOperator::RightShift => self
                         .builder
-                        .build_right_shift(l, r, false, "rshifttmp")
+                        .build_right_shift(l, r, !unsigned, "rshifttmp")
                         .into(),
                     _ => return Err(IoError::runtime_error("Unsupported binary operator")),
                 })
@@ -856,365 +986,1700 @@ impl<'ctx> LLVMCodeGen<'ctx> {
 }
 
 impl<'ctx> LLVMCodeGen<'ctx> {
-    fn visit_function(
-        &mut self,
-        name: &str,
-        params: &[Parameter],
-        return_type: &Option<String>,
-        body: &[ASTNode],
-        is_async: bool,
-    ) -> Result<BasicValueEnum<'ctx>> {
-        let ret_type = self.get_llvm_type(return_type.as_deref().unwrap_or("unit"))?;
-        let param_types: Vec<_> = params
-            .iter()
-            .map(|p| self.get_llvm_type(&p.type_annotation))
-            .collect::<Result<_>>()?;
-
-        let fn_type = ret_type.fn_type(&param_types, false);
-        let function = self.module.add_function(name, fn_type, None);
-
-        // Create basic block
-        let basic_block = self.context.append_basic_block(function, "entry");
-        self.builder.position_at_end(basic_block);
-
-        // Save current function
-        let previous_function = self.current_function;
-        self.current_function = Some(function);
-
-        // Add parameters to scope
-        self.named_values.clear();
-        for (param, value) in params.iter().zip(function.get_param_iter()) {
-            let alloca = self.create_entry_block_alloca(function, &param.name, value.get_type());
-            self.builder.build_store(alloca, value);
-            self.named_values.insert(param.name.clone(), alloca);
-        }
-
-        // Generate function body
-        for node in body {
-            self.visit_node(node)?;
-        }
-
-        // Verify function
-        if function.verify(true) {
-            // Restore previous function
-            self.current_function = previous_function;
-            Ok(function.as_basic_value_enum())
-        } else {
-            Err(IoError::runtime_error("Invalid generated function"))
+    /// Makes the variable `symbol` free its value when its scope ends. Only
+    /// trait objects own heap memory.
+    fn own(&mut self, symbol: SymbolId) -> Result<()> {
+        let function = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("Variable declaration outside function"))?;
+        let value = self
+            .named_values
+            .get(&symbol)
+            .ok_or_else(|| {
+                IoError::codegen_error(format!(
+                    "Unknown variable name: {}",
+                    self.resolution.symbol(symbol).name
+                ))
+            })?
+            .into_pointer_value();
+        let flag = self
+            .create_entry_block_alloca(function, "drop.flag", self.bool_type().into())
+            .into_pointer_value();
+        self.builder
+            .build_store(flag, self.bool_type().const_int(1, false));
+        if let Some(scope) = self.drop_scopes.last_mut() {
+            scope.push(DropSlot {
+                symbol,
+                value,
+                flag,
+            });
         }
+        Ok(())
     }
 
-    fn visit_node(&mut self, node: &ASTNode) -> Result<BasicValueEnum<'ctx>> {
-        match node {
-            ASTNode::Function {
-                name,
-                params,
-                return_type,
-                body,
-                is_async,
-                location,
-            } => self.visit_function(name, params, return_type, body, *is_async),
-            ASTNode::BinaryOp { op, left, right } => {
-                let lhs = self.visit_node(left)?;
-                let rhs = self.visit_node(right)?;
-                self.generate_binary_op(op, lhs, rhs)
-            }
-            ASTNode::IntegerLiteral(value) => Ok(self
-                .context
-                .i32_type()
-                .const_int(*value as u64, false)
-                .into()),
-            ASTNode::Variable { name } => {
-                if let Some(value) = self.named_values.get(name) {
-                    Ok(self.builder.build_load(*value, name).into())
-                } else {
-                    Err(IoError::runtime_error("Unknown variable name"))
-                }
-            }
-            ASTNode::VariableDeclaration {
-                name,
-                initializer,
-                type_annotation,
-            } => self.visit_variable_declaration(name, initializer, type_annotation),
-            ASTNode::Return { value } => self.visit_return(value),
-            ASTNode::If {
-                condition,
-                then_branch,
-                else_branch,
-            } => self.visit_if(condition, then_branch, else_branch),
-            ASTNode::While { condition, body } => self.visit_while(condition, body),
-            ASTNode::Call { name, args } => self.visit_call(name, args),
-            _ => Err(IoError::runtime_error("Unimplemented node type")),
+    /// Marks the value of `symbol` as moved, so its scope no longer frees it.
+    fn disarm(&mut self, symbol: SymbolId) {
+        let slot = self
+            .drop_scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|slot| slot.symbol == symbol);
+        if let Some(slot) = slot {
+            self.builder
+                .build_store(slot.flag, self.bool_type().const_zero());
         }
     }
 
-    fn visit_binary_op(
-        &mut self,
-        op: &BinaryOperator,
-        left: &ASTNode,
-        right: &ASTNode,
-    ) -> Result<BasicValueEnum<'ctx>> {
-        let lhs = self.visit_node(left)?;
-        let rhs = self.visit_node(right)?;
+    /// Frees the values still owned by the variables of the scopes from
+    /// `depth` inward, innermost first.
+    fn emit_drops(&mut self, depth: usize) -> Result<()> {
+        let function = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("Drop outside function"))?;
+        let slots: Vec<(PointerValue<'ctx>, PointerValue<'ctx>)> = self.drop_scopes[depth..]
+            .iter()
+            .flatten()
+            .map(|slot| (slot.value, slot.flag))
+            .collect();
+        for (value, flag) in slots.into_iter().rev() {
+            let drop_bb = self.context.append_basic_block(function, "drop");
+            let next_bb = self.context.append_basic_block(function, "drop.next");
+            let armed = self.builder.build_load(flag, "drop.armed").into_int_value();
+            self.builder
+                .build_conditional_branch(armed, drop_bb, next_bb);
 
-        match op {
-            BinaryOperator::Add => {
-                let result = self.builder.build_int_add(
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "addtmp",
-                );
-                Ok(result.into())
-            }
-            BinaryOperator::Subtract => {
-                let result = self.builder.build_int_sub(
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "subtmp",
-                );
-                Ok(result.into())
-            }
-            BinaryOperator::Multiply => {
-                let result = self.builder.build_int_mul(
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "multmp",
-                );
-                Ok(result.into())
-            }
-            BinaryOperator::Divide => {
-                let result = self.builder.build_int_signed_div(
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "divtmp",
-                );
-                Ok(result.into())
-            }
-            BinaryOperator::Equal => {
-                let cmp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::EQ,
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "cmptmp",
-                );
-                let result =
-                    self.builder
-                        .build_int_z_extend(cmp, self.context.i32_type(), "booltmp");
-                Ok(result.into())
-            }
-            BinaryOperator::NotEqual => {
-                let cmp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::NE,
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "cmptmp",
-                );
-                let result =
-                    self.builder
-                        .build_int_z_extend(cmp, self.context.i32_type(), "booltmp");
-                Ok(result.into())
-            }
-            BinaryOperator::LessThan => {
-                let cmp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::SLT,
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "cmptmp",
-                );
-                let result =
-                    self.builder
-                        .build_int_z_extend(cmp, self.context.i32_type(), "booltmp");
-                Ok(result.into())
-            }
-            BinaryOperator::LessThanOrEqual => {
-                let cmp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::SLE,
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "cmptmp",
-                );
-                let result =
-                    self.builder
-                        .build_int_z_extend(cmp, self.context.i32_type(), "booltmp");
-                Ok(result.into())
-            }
-            BinaryOperator::GreaterThan => {
-                let cmp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::SGT,
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "cmptmp",
-                );
-                let result =
-                    self.builder
-                        .build_int_z_extend(cmp, self.context.i32_type(), "booltmp");
-                Ok(result.into())
-            }
-            BinaryOperator::GreaterThanOrEqual => {
-                let cmp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::SGE,
-                    lhs.into_int_value(),
-                    rhs.into_int_value(),
-                    "cmptmp",
-                );
-                let result =
-                    self.builder
-                        .build_int_z_extend(cmp, self.context.i32_type(), "booltmp");
-                Ok(result.into())
-            }
-            BinaryOperator::BitAnd => {
-                self.builder
-                    .build_and(lhs.into_int_value(), rhs.into_int_value(), "bitandtmp")?
-            }
-            BinaryOperator::BitOr => {
-                self.builder
-                    .build_or(lhs.into_int_value(), rhs.into_int_value(), "bitortmp")?
-            }
-            BinaryOperator::BitXor => {
-                self.builder
-                    .build_xor(lhs.into_int_value(), rhs.into_int_value(), "bitxortmp")?
-            }
-            BinaryOperator::LeftShift => self.builder.build_left_shift(
-                lhs.into_int_value(),
-                rhs.into_int_value(),
-                "shltmp",
-            )?,
-            BinaryOperator::RightShift => self.builder.build_right_shift(
-                lhs.into_int_value(),
-                rhs.into_int_value(),
-                false,
-                "shrtmp",
-            )?,
-            BinaryOperator::Power => {
-                let pow_intrinsic = self.get_or_insert_intrinsic("llvm.pow")?;
-                let call = self.builder.build_call(
-                    pow_intrinsic,
-                    &[lhs.into_float_value().into(), rhs.into_float_value().into()],
-                    "powtmp",
-                )?;
-                call.try_as_basic_value().left().unwrap()
-            }
-            _ => Err(IoError::runtime_error("Unsupported binary operator")),
+            self.builder.position_at_end(drop_bb);
+            let object = self.builder.build_load(value, "drop.object");
+            let data = self
+                .builder
+                .build_extract_value(object.into_struct_value(), 0, "drop.data")
+                .ok_or_else(|| IoError::codegen_error("Cannot read trait object data"))?;
+            self.builder.build_free(data.into_pointer_value());
+            self.builder.build_unconditional_branch(next_bb);
+            self.builder.position_at_end(next_bb);
         }
+        Ok(())
     }
 
-    fn visit_if(
-        &mut self,
-        condition: &ASTNode,
-        then_branch: &[ASTNode],
-        else_branch: &Option<Vec<ASTNode>>,
-    ) -> Result<BasicValueEnum<'ctx>> {
-        let cond_val = self.visit_node(condition)?;
-        let current_fn = self
-            .current_function
-            .ok_or_else(|| IoError::runtime_error("If statement outside function"))?;
-
-        let then_bb = self.context.append_basic_block(current_fn, "then");
-        let else_bb = self.context.append_basic_block(current_fn, "else");
-        let merge_bb = self.context.append_basic_block(current_fn, "merge");
-
+    /// Whether the block being generated still needs a terminator.
+    fn is_open(&self) -> bool {
         self.builder
-            .build_conditional_branch(cond_val.into_int_value(), then_bb, else_bb);
-
-        // Generate then block
-        self.builder.position_at_end(then_bb);
-        for node in then_branch {
-            self.visit_node(node)?;
-        }
-        self.builder.build_unconditional_branch(merge_bb);
+            .get_insert_block()
+            .is_some_and(|block| block.get_terminator().is_none())
+    }
 
-        // Generate else block
-        self.builder.position_at_end(else_bb);
-        if let Some(else_nodes) = else_branch {
-            for node in else_nodes {
-                self.visit_node(node)?;
-            }
+    /// Generates `statements` in a scope of their own, dropping what they
+    /// declare at its end.
+    fn scoped(&mut self, statements: &[ASTNode]) -> Result<Option<BasicValueEnum<'ctx>>> {
+        self.drop_scopes.push(Vec::new());
+        let value = walk_nodes(self, statements)?;
+        if self.is_open() {
+            self.emit_drops(self.drop_scopes.len() - 1)?;
         }
-        self.builder.build_unconditional_branch(merge_bb);
-
-        // Continue in merge block
-        self.builder.position_at_end(merge_bb);
-        Ok(self.context.i32_type().const_zero().into())
+        self.drop_scopes.pop();
+        Ok(value)
     }
 
-    fn visit_while(
+    /// `for variable in start..end { body }` as a counting loop, without
+    /// building the range. The counter is kept apart from the loop variable, so
+    /// the body assigning to it doesn't change the iterations.
+    fn range_loop(
         &mut self,
-        condition: &ASTNode,
+        variable: SymbolId,
+        id: NodeId,
+        op: &BinaryOperator,
+        start: &ASTNode,
+        end: &ASTNode,
         body: &[ASTNode],
-    ) -> Result<BasicValueEnum<'ctx>> {
-        let current_fn = self
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let function = self
             .current_function
-            .ok_or_else(|| IoError::runtime_error("While loop outside function"))?;
-
-        let cond_bb = self.context.append_basic_block(current_fn, "while.cond");
-        let body_bb = self.context.append_basic_block(current_fn, "while.body");
-        let end_bb = self.context.append_basic_block(current_fn, "while.end");
-
-        // Jump to condition block
+            .ok_or_else(|| IoError::codegen_error("For loop outside function"))?;
+        let elem_type = self
+            .binding_types
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| IoError::codegen_error("An unchecked for loop"))?;
+        let llvm_type = elem_type.to_llvm_type(self.context);
+        let name = self.resolution.symbol(variable).name.clone();
+        let start = self.value_of(start)?;
+        let end = self.value_of(end)?.into_int_value();
+        let counter = self
+            .create_entry_block_alloca(function, "for.counter", llvm_type)
+            .into_pointer_value();
+        self.builder.build_store(counter, start);
+
+        let cond_bb = self.context.append_basic_block(function, "for.cond");
+        let body_bb = self.context.append_basic_block(function, "for.body");
+        let step_bb = self.context.append_basic_block(function, "for.step");
+        let end_bb = self.context.append_basic_block(function, "for.end");
         self.builder.build_unconditional_branch(cond_bb);
 
-        // Generate condition
         self.builder.position_at_end(cond_bb);
-        let cond_val = self.visit_node(condition)?;
+        let current = self.builder.build_load(counter, &name).into_int_value();
+        let predicate = match (elem_type.is_signed(), op) {
+            (true, BinaryOperator::Range) => inkwell::IntPredicate::SLT,
+            (true, _) => inkwell::IntPredicate::SLE,
+            (false, BinaryOperator::Range) => inkwell::IntPredicate::ULT,
+            (false, _) => inkwell::IntPredicate::ULE,
+        };
+        let in_range = self
+            .builder
+            .build_int_compare(predicate, current, end, "for.test");
         self.builder
-            .build_conditional_branch(cond_val.into_int_value(), body_bb, end_bb);
+            .build_conditional_branch(in_range, body_bb, end_bb);
 
-        // Generate body
         self.builder.position_at_end(body_bb);
-        for node in body {
-            self.visit_node(node)?;
+        let slot = self.create_entry_block_alloca(function, &name, llvm_type);
+        self.builder.build_store(slot.into_pointer_value(), current);
+        self.named_values.insert(variable, slot);
+        self.scoped(body)?;
+        self.named_values.remove(&variable);
+        if self.is_open() {
+            self.builder.build_unconditional_branch(step_bb);
+        }
+
+        // An inclusive range stops at its end rather than stepping past it,
+        // which could overflow.
+        self.builder.position_at_end(step_bb);
+        let current = self.builder.build_load(counter, &name).into_int_value();
+        if *op == BinaryOperator::RangeInclusive {
+            let next_bb = self.context.append_basic_block(function, "for.next");
+            let last =
+                self.builder
+                    .build_int_compare(inkwell::IntPredicate::EQ, current, end, "for.last");
+            self.builder.build_conditional_branch(last, end_bb, next_bb);
+            self.builder.position_at_end(next_bb);
         }
+        let one = current.get_type().const_int(1, false);
+        let next = self.builder.build_int_add(current, one, "for.next");
+        self.builder.build_store(counter, next);
         self.builder.build_unconditional_branch(cond_bb);
 
-        // Continue after loop
         self.builder.position_at_end(end_bb);
-        Ok(self.context.i32_type().const_zero().into())
+        Ok(None)
+    }
+
+    /// Generates `node` and returns the value it produces, boxed as a trait
+    /// object where the type checker converted it to `dyn Trait`.
+    fn value_of(&mut self, node: &ASTNode) -> Result<BasicValueEnum<'ctx>> {
+        let value = self.visit_node(node)?.ok_or_else(|| {
+            IoError::codegen_error("Expression produced no value").with_span(node.span())
+        })?;
+        match self.coercions.get(&node.id()).cloned() {
+            Some(implementation) => self
+                .trait_object(value, &implementation)
+                .map_err(|err| err.or_span(node.span())),
+            None => Ok(value),
+        }
     }
 
-    fn visit_variable_declaration(
+    /// A trait object for `value`: a pointer to a heap copy of it next to the
+    /// vtable of its type's impl of the trait.
+    fn trait_object(
         &mut self,
-        name: &str,
-        initializer: &Option<ASTNode>,
-        type_annotation: &Option<String>,
+        value: BasicValueEnum<'ctx>,
+        implementation: &Implementation,
     ) -> Result<BasicValueEnum<'ctx>> {
-        let var_type = if let Some(type_name) = type_annotation {
-            self.get_llvm_type(type_name)?
-        } else if let Some(init) = initializer {
-            // Infer type from initializer
-            self.visit_node(init)?.get_type()
-        } else {
-            return Err(IoError::type_error("Cannot infer variable type"));
-        };
+        let vtable = self.vtable(implementation)?;
+        let data = self
+            .builder
+            .build_malloc(value.get_type(), "dyn.data")
+            .map_err(|err| IoError::codegen_error(err.to_string()))?;
+        self.builder.build_store(data, value);
+        let data = self
+            .builder
+            .build_pointer_cast(data, self.string_type(), "dyn.erased");
+
+        // Laid out like `Type::Dyn`: the data pointer, then the vtable.
+        let vtable_type = self.string_type().ptr_type(AddressSpace::default());
+        let object_type = self
+            .struct_type(&[self.string_type().into(), vtable_type.into()], None)
+            .into_struct_type();
+        let object = self
+            .builder
+            .build_insert_value(object_type.get_undef(), data, 0, "dyn")
+            .and_then(|object| self.builder.build_insert_value(object, vtable, 1, "dyn"))
+            .ok_or_else(|| IoError::codegen_error("Cannot build trait object"))?;
+        Ok(object.into_struct_value().into())
+    }
 
-        let alloca = self.create_entry_block_alloca(self.current_function.unwrap(), name, var_type);
+    /// The vtable of `implementation`, emitted on first use: a constant array
+    /// holding a function pointer for each of the trait's methods, in
+    /// declaration order.
+    fn vtable(&mut self, implementation: &Implementation) -> Result<PointerValue<'ctx>> {
+        let name = format!(
+            "vtable.<{} as {}>",
+            implementation.self_type, implementation.trait_name
+        );
+        let vtable_type = self.string_type().ptr_type(AddressSpace::default());
+        if let Some(global) = self.module.get_global(&name) {
+            return Ok(global.as_pointer_value().const_cast(vtable_type));
+        }
 
-        if let Some(init) = initializer {
-            let init_val = self.visit_node(init)?;
-            self.builder
-                .build_store(alloca.into_pointer_value(), init_val);
+        let methods = self
+            .vtables
+            .get(&implementation.trait_name)
+            .cloned()
+            .ok_or_else(|| {
+                IoError::codegen_error(format!("Unknown trait {}", implementation.trait_name))
+            })?;
+        let mut slots = Vec::with_capacity(methods.len());
+        for method in &methods {
+            let symbol = monomorphize::method_symbol(
+                &implementation.self_type,
+                &implementation.trait_name,
+                method,
+            );
+            let shim = self.erased_receiver(&symbol)?;
+            slots.push(
+                shim.as_global_value()
+                    .as_pointer_value()
+                    .const_cast(self.string_type()),
+            );
         }
 
-        self.named_values.insert(name.to_string(), alloca);
-        Ok(alloca)
+        let table = self.string_type().const_array(&slots);
+        let global = self.module.add_global(table.get_type(), None, &name);
+        global.set_initializer(&table);
+        global.set_constant(true);
+        Ok(global.as_pointer_value().const_cast(vtable_type))
     }
 
-    fn visit_return(&mut self, value: &Option<ASTNode>) -> Result<BasicValueEnum<'ctx>> {
-        let return_value = if let Some(expr) = value {
-            Some(self.visit_node(expr)?)
-        } else {
-            None
+    /// A wrapper around the method `symbol` taking its receiver as an untyped
+    /// pointer, as vtable entries do, and passing it on by value.
+    fn erased_receiver(&mut self, symbol: &str) -> Result<FunctionValue<'ctx>> {
+        let name = format!("{}.dyn", symbol);
+        if let Some(shim) = self.get_function(&name) {
+            return Ok(shim);
+        }
+        let method = self
+            .get_function(symbol)
+            .ok_or_else(|| IoError::codegen_error(format!("Unknown function: {}", symbol)))?;
+        let mut param_types = method.get_type().get_param_types();
+        let receiver_type = param_types[0];
+        param_types[0] = self.string_type().into();
+        let param_types: Vec<BasicMetadataTypeEnum> =
+            param_types.into_iter().map(Into::into).collect();
+        let shim_type = match method.get_type().get_return_type() {
+            Some(ty) => ty.fn_type(&param_types, false),
+            None => self.void_type().fn_type(&param_types, false),
         };
 
-        match return_value {
-            Some(val) => {
-                self.builder.build_return(Some(&val));
-                Ok(val)
-            }
-            None => {
+        let shim = self.module.add_function(&name, shim_type, None);
+        let previous_block = self.builder.get_insert_block();
+        self.builder
+            .position_at_end(self.context.append_basic_block(shim, "entry"));
+        let data = shim
+            .get_nth_param(0)
+            .ok_or_else(|| IoError::codegen_error("Method without a receiver"))?
+            .into_pointer_value();
+        let data = self.builder.build_pointer_cast(
+            data,
+            receiver_type.ptr_type(AddressSpace::default()),
+            "self.ptr",
+        );
+        let mut args: Vec<BasicMetadataValueEnum> =
+            vec![self.builder.build_load(data, "self").into()];
+        args.extend(shim.get_param_iter().skip(1).map(Into::into));
+        let call = self.builder.build_call(method, &args, "calltmp");
+        match call.try_as_basic_value().left() {
+            Some(value) => self.builder.build_return(Some(&value)),
+            None => self.builder.build_return(None),
+        };
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        Ok(shim)
+    }
+
+    /// The tag of `variant` and the index of its first field in the layout of
+    /// `enum_name`: the tag, then the fields of each variant in turn.
+    fn variant_slot(&self, enum_name: &str, variant: &str) -> Result<(u64, u32)> {
+        let variants = self
+            .enums
+            .get(enum_name)
+            .ok_or_else(|| IoError::codegen_error(format!("Unknown enum: {}", enum_name)))?;
+        let mut field = 1;
+        for (tag, (name, count)) in variants.iter().enumerate() {
+            if name == variant {
+                return Ok((tag as u64, field));
+            }
+            field += *count as u32;
+        }
+        Err(IoError::codegen_error(format!(
+            "Enum {} has no variant {}",
+            enum_name, variant
+        )))
+    }
+
+    /// A value of the variant `path` names, holding `fields`.
+    fn build_variant(
+        &self,
+        path: &VariantPath,
+        fields: &[BasicValueEnum<'ctx>],
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let (tag, first) = self.variant_slot(&path.enum_name, &path.variant)?;
+        let ty = path.ty.to_llvm_type(self.context).into_struct_type();
+        let mut value = self.insert_field(
+            ty.get_undef(),
+            self.i32_type().const_int(tag, false).into(),
+            0,
+        )?;
+        for (offset, field) in fields.iter().enumerate() {
+            value = self.insert_field(value, *field, first + offset as u32)?;
+        }
+        Ok(value.into())
+    }
+
+    fn insert_field(
+        &self,
+        value: StructValue<'ctx>,
+        field: BasicValueEnum<'ctx>,
+        index: u32,
+    ) -> Result<StructValue<'ctx>> {
+        self.builder
+            .build_insert_value(value, field, index, "field")
+            .map(|value| value.into_struct_value())
+            .ok_or_else(|| IoError::codegen_error("Field index out of range"))
+    }
+
+    fn extract_field(&self, value: StructValue<'ctx>, index: u32) -> Result<BasicValueEnum<'ctx>> {
+        self.builder
+            .build_extract_value(value, index, "field")
+            .ok_or_else(|| IoError::codegen_error("Field index out of range"))
+    }
+
+    fn field_index(&self, id: NodeId) -> Result<u32> {
+        self.field_indices
+            .get(&id)
+            .map(|&index| index as u32)
+            .ok_or_else(|| IoError::codegen_error("An unchecked field access"))
+    }
+
+    /// The field `object.member` names; a reference is loaded through first.
+    fn member_value(&mut self, object: &ASTNode, id: NodeId) -> Result<BasicValueEnum<'ctx>> {
+        let index = self.field_index(id)?;
+        let object = match self.value_of(object)? {
+            BasicValueEnum::PointerValue(referent) => self.builder.build_load(referent, "referent"),
+            value => value,
+        };
+        self.extract_field(object.into_struct_value(), index)
+    }
+
+    /// The address an assignment writes to: the variable's slot, then a struct
+    /// GEP for each field and an in-bounds GEP for each element on the way.
+    fn place(&mut self, target: &ASTNode) -> Result<PointerValue<'ctx>> {
+        match target {
+            ASTNode::Identifier { name, id, .. } => self
+                .local(*id)
+                .map(|(_, slot)| slot.into_pointer_value())
+                .ok_or_else(|| IoError::codegen_error(format!("Unknown variable name: {}", name))),
+            ASTNode::MemberAccess {
+                object, member, id, ..
+            } => {
+                let object = self.place(object)?;
+                let index = self.field_index(*id)?;
+                self.build_struct_gep(object, index, member)
+            }
+            ASTNode::Index { array, index, .. } => {
+                let array = self.place(array)?;
+                let index = self.value_of(index)?.into_int_value();
+                let zero = self.i32_type().const_zero();
+                // Each element is wrapped in a struct of one field.
+                Ok(unsafe {
+                    self.builder
+                        .build_in_bounds_gep(array, &[zero, index, zero], "element")
+                })
+            }
+            other => {
+                Err(IoError::codegen_error("Invalid assignment target").with_span(other.span()))
+            }
+        }
+    }
+
+    /// The variable `name` the binding `node` declares, holding `value` or
+    /// left for a later assignment; `ty` is its declared type, if there is one.
+    fn declare_variable(
+        &mut self,
+        node: NodeId,
+        name: &str,
+        value: Option<BasicValueEnum<'ctx>>,
+        ty: Option<&Type>,
+    ) -> Result<SymbolId> {
+        let symbol = self.declared(node, name)?;
+        let function = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("Variable declaration outside function"))?;
+        let var_type = match (ty, value) {
+            (Some(ty), _) => ty.to_llvm_type(self.context),
+            (None, Some(value)) => value.get_type(),
+            (None, None) => {
+                return Err(IoError::codegen_error(format!(
+                    "Cannot infer type of variable {}",
+                    name
+                )))
+            }
+        };
+
+        let alloca = self.create_entry_block_alloca(function, name, var_type);
+        if let Some(value) = value {
+            self.builder.build_store(alloca.into_pointer_value(), value);
+        }
+        self.named_values.insert(symbol, alloca);
+        if let Some(ty @ Type::Function { .. }) = ty {
+            self.callables.insert(symbol, ty.clone());
+        }
+        Ok(symbol)
+    }
+
+    /// The symbol of the variable the binding `node` declares.
+    fn declared(&self, node: NodeId, name: &str) -> Result<SymbolId> {
+        self.resolution
+            .declared(node)
+            .ok_or_else(|| IoError::codegen_error(format!("Unresolved variable {}", name)))
+    }
+
+    /// The symbol and slot of the local variable the identifier `node` refers
+    /// to, if it refers to one.
+    fn local(&self, node: NodeId) -> Option<(SymbolId, BasicValueEnum<'ctx>)> {
+        let symbol = self.resolution.resolved(node)?;
+        Some((symbol, *self.named_values.get(&symbol)?))
+    }
+
+    /// The value of the variable, function or unit variant the identifier
+    /// `node` names.
+    fn identifier(&mut self, name: &str, node: NodeId) -> Result<Option<BasicValueEnum<'ctx>>> {
+        if let Some(path) = self.variant_paths.get(name) {
+            return self.build_variant(path, &[]).map(Some);
+        }
+        if let Some((_, slot)) = self.local(node) {
+            return Ok(Some(
+                self.builder.build_load(slot.into_pointer_value(), name),
+            ));
+        }
+        match self.get_function(name) {
+            Some(function) => self.function_value(function).map(Some),
+            None => Err(IoError::codegen_error(format!(
+                "Unknown variable name: {}",
+                name
+            ))),
+        }
+    }
+
+    /// Declares the variables `pattern` binds in `value`, a value of the
+    /// laid-out type `ty`.
+    fn bind_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: BasicValueEnum<'ctx>,
+        ty: &Type,
+    ) -> Result<()> {
+        match (pattern, ty) {
+            (Pattern::Wildcard { .. }, _) => Ok(()),
+            (Pattern::Binding { name, id, .. }, ty) => self
+                .declare_variable(*id, name, Some(value), Some(ty))
+                .map(|_| ()),
+            (Pattern::Tuple { elements, .. }, Type::Tuple(types)) => {
+                for (i, (element, ty)) in elements.iter().zip(types).enumerate() {
+                    let field = self.extract_field(value.into_struct_value(), i as u32)?;
+                    self.bind_pattern(element, field, ty)?;
+                }
+                Ok(())
+            }
+            (
+                Pattern::Struct {
+                    path,
+                    fields: PatternFields::Named { fields, .. },
+                    ..
+                },
+                Type::Struct { fields: layout, .. },
+            ) if !path.contains("::") => {
+                for (name, pattern) in fields {
+                    let index = layout
+                        .iter()
+                        .position(|(field, _)| field == name)
+                        .ok_or_else(|| {
+                            IoError::codegen_error(format!("Struct {} has no field {}", path, name))
+                        })?;
+                    let field = self.extract_field(value.into_struct_value(), index as u32)?;
+                    self.bind_pattern(pattern, field, &layout[index].1)?;
+                }
+                Ok(())
+            }
+            _ => Err(IoError::codegen_error(
+                "Only tuple and struct patterns can be compiled in a let; run it with `io run`",
+            )
+            .with_span(pattern.span())),
+        }
+    }
+
+    fn tuple_value(&mut self, elements: &[ASTNode]) -> Result<BasicValueEnum<'ctx>> {
+        let values = elements
+            .iter()
+            .map(|element| self.value_of(element))
+            .collect::<Result<Vec<_>>>()?;
+        let types: Vec<BasicTypeEnum<'ctx>> = values.iter().map(|value| value.get_type()).collect();
+        let mut tuple = self.context.struct_type(&types, false).get_undef();
+        for (i, value) in values.into_iter().enumerate() {
+            tuple = self.insert_field(tuple, value, i as u32)?;
+        }
+        Ok(tuple.into())
+    }
+
+    /// A value of the laid-out struct `ty`, with its fields evaluated in the
+    /// order the literal gives them.
+    fn struct_value(
+        &mut self,
+        ty: &Type,
+        fields: &[(String, ASTNode)],
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let Type::Struct { fields: layout, .. } = ty else {
+            return Err(IoError::codegen_error(format!("Cannot lay out {}", ty)));
+        };
+        let mut value = ty.to_llvm_type(self.context).into_struct_type().get_undef();
+        for (name, field) in fields {
+            let index = layout
+                .iter()
+                .position(|(declared, _)| declared == name)
+                .ok_or_else(|| IoError::codegen_error(format!("{} has no field {}", ty, name)))?;
+            let field = self.value_of(field)?;
+            value = self.insert_field(value, field, index as u32)?;
+        }
+        Ok(value.into())
+    }
+
+    /// `operand?`: the field of the operand's first variant (`Some`, `Ok`), or
+    /// else an early return of its second (`None`, `Err`), rebuilt as the
+    /// function's return type, the same enum with other type arguments.
+    fn try_operator(
+        &mut self,
+        operand: &ASTNode,
+        enum_name: &str,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let function = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("The ? operator outside function"))?;
+        let variants = self.enums.get(enum_name).cloned().unwrap_or_default();
+        let [(success, _), (failure, failure_fields)] = variants.as_slice() else {
+            return Err(IoError::codegen_error(format!(
+                "The ? operator needs an enum of two variants, not {}",
+                enum_name
+            )));
+        };
+        let (success_tag, payload) = self.variant_slot(enum_name, success)?;
+        let (failure_tag, first) = self.variant_slot(enum_name, failure)?;
+
+        let value = self.value_of(operand)?.into_struct_value();
+        let tag = self.extract_field(value, 0)?.into_int_value();
+        let is_success = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            tag,
+            self.i32_type().const_int(success_tag, false),
+            "try.cond",
+        );
+        let success_bb = self.context.append_basic_block(function, "try.ok");
+        let failure_bb = self.context.append_basic_block(function, "try.fail");
+        self.builder
+            .build_conditional_branch(is_success, success_bb, failure_bb);
+
+        self.builder.position_at_end(failure_bb);
+        let return_type = function
+            .get_type()
+            .get_return_type()
+            .ok_or_else(|| IoError::codegen_error("The ? operator in a function returning void"))?
+            .into_struct_type();
+        let failure_tag = self.i32_type().const_int(failure_tag, false).into();
+        let mut early = self.insert_field(return_type.get_undef(), failure_tag, 0)?;
+        for field in first..first + *failure_fields as u32 {
+            early = self.insert_field(early, self.extract_field(value, field)?, field)?;
+        }
+        self.emit_drops(0)?;
+        self.builder.build_return(Some(&early));
+
+        self.builder.position_at_end(success_bb);
+        Ok(Some(self.extract_field(value, payload)?))
+    }
+
+    /// `object.method(args)` on a trait object: calls the function in the
+    /// method's vtable slot with the object's data pointer as receiver.
+    fn dyn_call(
+        &mut self,
+        object: &ASTNode,
+        args: &[ASTNode],
+        call: &DynCall,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let Type::Function {
+            params,
+            return_type,
+            ..
+        } = &call.signature
+        else {
+            return Err(IoError::codegen_error("Method without a function type"));
+        };
+        let object = self.value_of(object)?.into_struct_value();
+        let (data, vtable) = self
+            .builder
+            .build_extract_value(object, 0, "dyn.data")
+            .zip(self.builder.build_extract_value(object, 1, "dyn.vtable"))
+            .ok_or_else(|| IoError::codegen_error("Malformed trait object"))?;
+
+        let slot = self.i32_type().const_int(call.slot as u64, false);
+        let slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(vtable.into_pointer_value(), &[slot], "dyn.slot")
+        };
+        let method = self.builder.build_load(slot, "dyn.method");
+
+        let fn_type = self.erased_fn_type(params, return_type);
+        let method = self.builder.build_pointer_cast(
+            method.into_pointer_value(),
+            fn_type.ptr_type(AddressSpace::default()),
+            "dyn.fn",
+        );
+        let method = CallableValue::try_from(method)
+            .map_err(|_| IoError::codegen_error("Vtable slot is not a function"))?;
+
+        let mut compiled_args: Vec<BasicMetadataValueEnum> = vec![data.into()];
+        for arg in args {
+            compiled_args.push(self.value_of(arg)?.into());
+        }
+        let result = self.builder.build_call(method, &compiled_args, "dyn.call");
+        Ok(result.try_as_basic_value().left())
+    }
+
+    /// The type of a function taking an untyped pointer before `params`, as
+    /// vtable entries and the code of closures do.
+    fn erased_fn_type(&self, params: &[Type], return_type: &Type) -> FunctionType<'ctx> {
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![self.string_type().into()];
+        param_types.extend(params.iter().map(|ty| ty.to_llvm_type(self.context).into()));
+        match return_type {
+            Type::Void => self.void_type().fn_type(&param_types, false),
+            ty => ty.to_llvm_type(self.context).fn_type(&param_types, false),
+        }
+    }
+
+    /// A closure value, laid out like `Type::Function`: the code, then the
+    /// environment, both as untyped pointers.
+    fn closure_value(
+        &self,
+        code: FunctionValue<'ctx>,
+        env: PointerValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let code = code
+            .as_global_value()
+            .as_pointer_value()
+            .const_cast(self.string_type());
+        let pair_type = self
+            .struct_type(
+                &[self.string_type().into(), self.string_type().into()],
+                None,
+            )
+            .into_struct_type();
+        let pair = self.insert_field(pair_type.get_undef(), code.into(), 0)?;
+        Ok(self.insert_field(pair, env.into(), 1)?.into())
+    }
+
+    /// A closure: its body becomes a function taking the environment before
+    /// the parameters, and the environment a heap struct holding the address
+    /// of each variable captured by reference and a copy of each one moved
+    /// in, which the body uses as those variables. Environments are never
+    /// freed.
+    fn closure(
+        &mut self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &ASTNode,
+        is_move: bool,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        // The enclosing variables the body uses, in order of first use; whatever
+        // else it uses is a function or a global.
+        let mut captured: Vec<(usize, SymbolId, String, PointerValue<'ctx>)> = self
+            .named_values
+            .iter()
+            .filter_map(|(symbol, slot)| {
+                let (_, first_use) = self
+                    .resolution
+                    .uses_of(*symbol)
+                    .into_iter()
+                    .find(|(_, span)| body.span().encloses(*span))?;
+                let name = self.resolution.symbol(*symbol).name.clone();
+                Some((first_use.start, *symbol, name, slot.into_pointer_value()))
+            })
+            .collect();
+        captured.sort_by_key(|(first_use, ..)| *first_use);
+        let mut fields = Vec::with_capacity(captured.len());
+        for (_, symbol, name, slot) in &captured {
+            fields.push(if is_move {
+                self.disarm(*symbol);
+                self.builder.build_load(*slot, name)
+            } else {
+                (*slot).into()
+            });
+        }
+        let field_types: Vec<BasicTypeEnum> = fields.iter().map(|field| field.get_type()).collect();
+        let env_type = self.context.struct_type(&field_types, false);
+        let env = if captured.is_empty() {
+            self.string_type().const_null()
+        } else {
+            let mut value = env_type.get_undef();
+            for (i, field) in fields.into_iter().enumerate() {
+                value = self.insert_field(value, field, i as u32)?;
+            }
+            let env = self
+                .builder
+                .build_malloc(env_type, "closure.env")
+                .map_err(|err| IoError::codegen_error(err.to_string()))?;
+            self.builder.build_store(env, value);
+            self.builder
+                .build_pointer_cast(env, self.string_type(), "closure.erased")
+        };
+
+        let param_types: Vec<Type> = params
+            .iter()
+            .map(|param| param.type_annotation.clone())
+            .collect();
+        let fn_type = self.erased_fn_type(&param_types, return_type.unwrap_or(&Type::Void));
+        // LLVM numbers the closures of a module apart.
+        let function = self.module.add_function("closure", fn_type, None);
+
+        let previous_block = self.builder.get_insert_block();
+        let previous_function = self.current_function.replace(function);
+        let previous_values = std::mem::take(&mut self.named_values);
+        let previous_callables = self.callables.clone();
+        let previous_scopes = std::mem::replace(&mut self.drop_scopes, vec![Vec::new()]);
+        let generate = |this: &mut Self| -> Result<()> {
+            this.builder
+                .position_at_end(this.context.append_basic_block(function, "entry"));
+            let env = function
+                .get_nth_param(0)
+                .ok_or_else(|| IoError::codegen_error("Closure without an environment"))?
+                .into_pointer_value();
+            let env = this.builder.build_pointer_cast(
+                env,
+                env_type.ptr_type(AddressSpace::default()),
+                "env",
+            );
+            for (i, (_, symbol, name, _)) in captured.iter().enumerate() {
+                let field = this
+                    .builder
+                    .build_struct_gep(env, i as u32, name)
+                    .map_err(|_| IoError::codegen_error("Malformed closure environment"))?;
+                let slot = if is_move {
+                    field
+                } else {
+                    this.builder.build_load(field, name).into_pointer_value()
+                };
+                this.named_values.insert(*symbol, slot.into());
+            }
+            for (param, value) in params.iter().zip(function.get_param_iter().skip(1)) {
+                let symbol = this.declared(param.id, &param.name)?;
+                let alloca =
+                    this.create_entry_block_alloca(function, &param.name, value.get_type());
+                this.builder.build_store(alloca.into_pointer_value(), value);
+                this.named_values.insert(symbol, alloca);
+                if let ty @ Type::Function { .. } = &param.type_annotation {
+                    this.callables.insert(symbol, ty.clone());
+                }
+                if matches!(param.type_annotation, Type::Dyn(_)) {
+                    this.own(symbol)?;
+                }
+            }
+
+            let value = this.visit_node(body)?;
+            if this.is_open() {
+                this.emit_drops(0)?;
+                match value.filter(|_| !matches!(return_type, Some(Type::Void) | None)) {
+                    Some(value) => this.builder.build_return(Some(&value)),
+                    None => this.builder.build_return(None),
+                };
+            }
+            Ok(())
+        };
+        let result = generate(self);
+        self.drop_scopes = previous_scopes;
+        self.callables = previous_callables;
+        self.named_values = previous_values;
+        self.current_function = previous_function;
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        result?;
+
+        if !function.verify(true) {
+            return Err(IoError::codegen_error("Invalid generated closure"));
+        }
+        self.closure_value(function, env)
+    }
+
+    /// The function `function` as a closure value, through a wrapper that
+    /// takes an environment and ignores it.
+    fn function_value(&mut self, function: FunctionValue<'ctx>) -> Result<BasicValueEnum<'ctx>> {
+        let name = format!("{}.closure", function.get_name().to_string_lossy());
+        let wrapper = match self.get_function(&name) {
+            Some(wrapper) => wrapper,
+            None => {
+                let mut param_types: Vec<BasicMetadataTypeEnum> = vec![self.string_type().into()];
+                param_types.extend(
+                    function
+                        .get_type()
+                        .get_param_types()
+                        .into_iter()
+                        .map(Into::into),
+                );
+                let wrapper_type = match function.get_type().get_return_type() {
+                    Some(ty) => ty.fn_type(&param_types, false),
+                    None => self.void_type().fn_type(&param_types, false),
+                };
+                let wrapper = self.module.add_function(&name, wrapper_type, None);
+                let previous_block = self.builder.get_insert_block();
+                self.builder
+                    .position_at_end(self.context.append_basic_block(wrapper, "entry"));
+                let args: Vec<BasicMetadataValueEnum> =
+                    wrapper.get_param_iter().skip(1).map(Into::into).collect();
+                let call = self.builder.build_call(function, &args, "calltmp");
+                match call.try_as_basic_value().left() {
+                    Some(value) => self.builder.build_return(Some(&value)),
+                    None => self.builder.build_return(None),
+                };
+                if let Some(block) = previous_block {
+                    self.builder.position_at_end(block);
+                }
+                wrapper
+            }
+        };
+        self.closure_value(wrapper, self.string_type().const_null())
+    }
+
+    /// `name(args)` where the variable `name`, used by the identifier `node`,
+    /// holds a closure of type `signature`: calls its code with its
+    /// environment first.
+    fn closure_call(
+        &mut self,
+        name: &str,
+        node: NodeId,
+        signature: &Type,
+        args: &[ASTNode],
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let Type::Function {
+            params,
+            return_type,
+            ..
+        } = signature
+        else {
+            return Err(IoError::codegen_error(format!(
+                "{} is not a function",
+                name
+            )));
+        };
+        let closure = self
+            .identifier(name, node)?
+            .ok_or_else(|| IoError::codegen_error(format!("Unknown variable name: {}", name)))?
+            .into_struct_value();
+        let code = self.extract_field(closure, 0)?.into_pointer_value();
+        let env = self.extract_field(closure, 1)?;
+
+        let fn_type = self.erased_fn_type(params, return_type);
+        let code = self.builder.build_pointer_cast(
+            code,
+            fn_type.ptr_type(AddressSpace::default()),
+            "closure.fn",
+        );
+        let code = CallableValue::try_from(code)
+            .map_err(|_| IoError::codegen_error("Closure code is not a function"))?;
+
+        let mut compiled_args: Vec<BasicMetadataValueEnum> = vec![env.into()];
+        for arg in args {
+            compiled_args.push(self.value_of(arg)?.into());
+        }
+        let result = self
+            .builder
+            .build_call(code, &compiled_args, "closure.call");
+        Ok(result.try_as_basic_value().left())
+    }
+
+    fn generate_comparison(
+        &self,
+        op: &BinaryOperator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        operand_type: Option<&Type>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        use inkwell::{FloatPredicate, IntPredicate};
+        if lhs.is_float_value() {
+            let predicate = match op {
+                BinaryOperator::Equal => FloatPredicate::OEQ,
+                BinaryOperator::NotEqual => FloatPredicate::UNE,
+                BinaryOperator::LessThan => FloatPredicate::OLT,
+                BinaryOperator::LessThanEqual => FloatPredicate::OLE,
+                BinaryOperator::GreaterThan => FloatPredicate::OGT,
+                BinaryOperator::GreaterThanEqual => FloatPredicate::OGE,
+                _ => return Err(IoError::codegen_error("Not a comparison operator")),
+            };
+            let cmp = self.builder.build_float_compare(
+                predicate,
+                lhs.into_float_value(),
+                rhs.into_float_value(),
+                "cmptmp",
+            );
+            return Ok(cmp.into());
+        }
+        let unsigned = operand_type.is_some_and(|ty| ty.is_integer() && !ty.is_signed());
+        let predicate = match op {
+            BinaryOperator::Equal => IntPredicate::EQ,
+            BinaryOperator::NotEqual => IntPredicate::NE,
+            BinaryOperator::LessThan if unsigned => IntPredicate::ULT,
+            BinaryOperator::LessThan => IntPredicate::SLT,
+            BinaryOperator::LessThanEqual if unsigned => IntPredicate::ULE,
+            BinaryOperator::LessThanEqual => IntPredicate::SLE,
+            BinaryOperator::GreaterThan if unsigned => IntPredicate::UGT,
+            BinaryOperator::GreaterThan => IntPredicate::SGT,
+            BinaryOperator::GreaterThanEqual if unsigned => IntPredicate::UGE,
+            BinaryOperator::GreaterThanEqual => IntPredicate::SGE,
+            _ => return Err(IoError::codegen_error("Not a comparison operator")),
+        };
+        let cmp = self.builder.build_int_compare(
+            predicate,
+            lhs.into_int_value(),
+            rhs.into_int_value(),
+            "cmptmp",
+        );
+        Ok(cmp.into())
+    }
+
+    fn binary(
+        &mut self,
+        op: &BinaryOperator,
+        left: &ASTNode,
+        right: &ASTNode,
+        operand_type: Option<&Type>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        let lhs = self.value_of(left)?;
+        let rhs = self.value_of(right)?;
+        let value = if op.is_comparison() {
+            self.generate_comparison(op, lhs, rhs, operand_type)?
+        } else {
+            self.generate_binary_op(op, lhs, rhs, operand_type)?
+        };
+        Ok(Some(value))
+    }
+
+    /// `left op right` for `+`, `-` and `*`, trapping if the result overflows
+    /// in `OverflowMode::Trap` and wrapping around otherwise.
+    fn overflowing(
+        &self,
+        op: &BinaryOperator,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+        unsigned: bool,
+    ) -> Result<IntValue<'ctx>> {
+        if self.overflow == OverflowMode::Wrap {
+            return self.wrapping(op, left, right);
+        }
+        let function = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("Arithmetic outside function"))?;
+        let (result, overflowed) = self.with_overflow(op, left, right, unsigned)?;
+        let trap_bb = self.context.append_basic_block(function, "overflow.trap");
+        let ok_bb = self.context.append_basic_block(function, "overflow.ok");
+        self.builder
+            .build_conditional_branch(overflowed, trap_bb, ok_bb);
+
+        self.builder.position_at_end(trap_bb);
+        let trap = self.declare_intrinsic("llvm.trap", self.void_type().fn_type(&[], false));
+        self.builder.build_call(trap, &[], "trap");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_bb);
+        Ok(result)
+    }
+
+    fn wrapping(
+        &self,
+        op: &BinaryOperator,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+    ) -> Result<IntValue<'ctx>> {
+        Ok(match op {
+            BinaryOperator::Add => self.builder.build_int_add(left, right, "addtmp"),
+            BinaryOperator::Subtract => self.builder.build_int_sub(left, right, "subtmp"),
+            BinaryOperator::Multiply => self.builder.build_int_mul(left, right, "multmp"),
+            _ => return Err(IoError::codegen_error("Not an arithmetic operator")),
+        })
+    }
+
+    /// The wrapped-around result of `left op right` and whether it overflowed,
+    /// from LLVM's `*.with.overflow` intrinsics.
+    fn with_overflow(
+        &self,
+        op: &BinaryOperator,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+        unsigned: bool,
+    ) -> Result<(IntValue<'ctx>, IntValue<'ctx>)> {
+        let operation = match op {
+            BinaryOperator::Add => "add",
+            BinaryOperator::Subtract => "sub",
+            BinaryOperator::Multiply => "mul",
+            _ => return Err(IoError::codegen_error("Not an arithmetic operator")),
+        };
+        let ty = left.get_type();
+        let name = format!(
+            "llvm.{}{}.with.overflow.i{}",
+            if unsigned { "u" } else { "s" },
+            operation,
+            ty.get_bit_width()
+        );
+        let result_type = self
+            .context
+            .struct_type(&[ty.into(), self.bool_type().into()], false);
+        let function =
+            self.declare_intrinsic(&name, result_type.fn_type(&[ty.into(), ty.into()], false));
+        let pair = self
+            .builder
+            .build_call(function, &[left.into(), right.into()], "overflow")
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| IoError::codegen_error("Overflow intrinsic returned no value"))?
+            .into_struct_value();
+        Ok((
+            self.extract_field(pair, 0)?.into_int_value(),
+            self.extract_field(pair, 1)?.into_int_value(),
+        ))
+    }
+
+    /// Marks `function` with the LLVM attributes its `#[inline]`,
+    /// `#[inline(always)]`, `#[inline(never)]` and `#[cold]` attributes stand for.
+    fn add_function_attributes(&self, function: FunctionValue<'ctx>, attributes: &[Attribute]) {
+        for attribute in attributes {
+            let kind = match (attribute.name.as_str(), attribute.words().next()) {
+                ("inline", None) => "inlinehint",
+                ("inline", Some("always")) => "alwaysinline",
+                ("inline", Some(_)) => "noinline",
+                ("cold", _) => "cold",
+                _ => continue,
+            };
+            let kind = LLVMAttribute::get_named_enum_kind_id(kind);
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind, 0),
+            );
+        }
+    }
+
+    /// The LLVM intrinsic `name`, declared on first use.
+    fn declare_intrinsic(&self, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, ty, None))
+    }
+
+    /// A number literal as a constant of the type the checker gave it.
+    fn number_literal(&self, value: &Literal, ty: &Type) -> Result<BasicValueEnum<'ctx>> {
+        let llvm_type = ty.to_llvm_type(self.context);
+        let value = match value {
+            Literal::Integer(value) => *value as i128,
+            Literal::TypedInteger(value, _) => *value,
+            Literal::Float(value) | Literal::TypedFloat(value, _) => {
+                return Ok(llvm_type.into_float_type().const_float(*value).into())
+            }
+            _ => return Err(IoError::codegen_error("Not a number literal")),
+        };
+        let words = [value as u64, (value >> 64) as u64];
+        Ok(llvm_type
+            .into_int_type()
+            .const_int_arbitrary_precision(&words)
+            .into())
+    }
+
+    /// `value as target`. Integers are truncated, or extended by the
+    /// signedness of `source`; floats convert to integers saturating at the
+    /// target's bounds, with NaN becoming 0, as in the interpreter.
+    fn cast(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        source: &Type,
+        target: &Type,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let target_type = target.to_llvm_type(self.context);
+        if source.is_float() {
+            let value = value.into_float_value();
+            if target.is_float() {
+                let to = target_type.into_float_type();
+                return Ok(match (source, target) {
+                    (Type::F32, Type::F64) => self.builder.build_float_ext(value, to, "fpext"),
+                    (Type::F64, Type::F32) => self.builder.build_float_trunc(value, to, "fptrunc"),
+                    _ => value,
+                }
+                .into());
+            }
+            let to = target_type.into_int_type();
+            let name = format!(
+                "llvm.fpto{}i.sat.i{}.{}",
+                if target.is_signed() { "s" } else { "u" },
+                to.get_bit_width(),
+                source
+            );
+            let function =
+                self.declare_intrinsic(&name, to.fn_type(&[value.get_type().into()], false));
+            return self
+                .builder
+                .build_call(function, &[value.into()], "fptoint")
+                .try_as_basic_value()
+                .left()
+                .ok_or_else(|| IoError::codegen_error("Conversion returned no value"));
+        }
+
+        let value = value.into_int_value();
+        if target.is_float() {
+            let to = target_type.into_float_type();
+            return Ok(if source.is_signed() {
+                self.builder.build_signed_int_to_float(value, to, "sitofp")
+            } else {
+                self.builder
+                    .build_unsigned_int_to_float(value, to, "uitofp")
+            }
+            .into());
+        }
+        // `bool` and `char` extend like unsigned integers
+        let to = target_type.into_int_type();
+        Ok(
+            match value.get_type().get_bit_width().cmp(&to.get_bit_width()) {
+                Ordering::Less if source.is_signed() => {
+                    self.builder.build_int_s_extend(value, to, "sext")
+                }
+                Ordering::Less => self.builder.build_int_z_extend(value, to, "zext"),
+                Ordering::Greater => self.builder.build_int_truncate(value, to, "trunc"),
+                Ordering::Equal => value,
+            }
+            .into(),
+        )
+    }
+
+    /// `wrapping_*(a, b)` wraps around at the bounds of `operand_type`;
+    /// `checked_*(a, b)` builds an `Option` of the result, `None` if it
+    /// overflowed.
+    fn integer_intrinsic(
+        &mut self,
+        name: &str,
+        args: &[ASTNode],
+        operand_type: &Type,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let [left, right] = args else {
+            return Err(IoError::codegen_error(format!(
+                "{} takes two arguments",
+                name
+            )));
+        };
+        let (mode, operation) = name
+            .split_once('_')
+            .ok_or_else(|| IoError::codegen_error(format!("Unknown intrinsic: {}", name)))?;
+        let op = match operation {
+            "add" => BinaryOperator::Add,
+            "sub" => BinaryOperator::Subtract,
+            "mul" => BinaryOperator::Multiply,
+            _ => {
+                return Err(IoError::codegen_error(format!(
+                    "Unknown intrinsic: {}",
+                    name
+                )))
+            }
+        };
+        let left = self.value_of(left)?.into_int_value();
+        let right = self.value_of(right)?.into_int_value();
+        if mode == "wrapping" {
+            return Ok(self.wrapping(&op, left, right)?.into());
+        }
+
+        let (result, overflowed) =
+            self.with_overflow(&op, left, right, !operand_type.is_signed())?;
+        let (some, payload) = self.variant_slot("Option", "Some")?;
+        let (none, _) = self.variant_slot("Option", "None")?;
+        let tag = self.builder.build_select(
+            overflowed,
+            self.i32_type().const_int(none, false),
+            self.i32_type().const_int(some, false),
+            "option.tag",
+        );
+        let ty = self
+            .context
+            .struct_type(&[self.i32_type().into(), result.get_type().into()], false);
+        let option = self.insert_field(ty.get_undef(), tag, 0)?;
+        Ok(self.insert_field(option, result.into(), payload)?.into())
+    }
+}
+
+impl<'ctx> Visitor for LLVMCodeGen<'ctx> {
+    /// The value an expression evaluates to; statements produce `None`.
+    type Output = Option<BasicValueEnum<'ctx>>;
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<Self::Output> {
+        if let ASTNode::Call {
+            callee, args, id, ..
+        } = node
+        {
+            if let (ASTNode::MemberAccess { object, .. }, Some(call)) =
+                (callee.as_ref(), self.dyn_calls.get(id).cloned())
+            {
+                return self
+                    .dyn_call(object, args, &call)
+                    .map_err(|err| err.or_span(node.span()));
+            }
+            // Integer intrinsics the program does not define itself
+            if let ASTNode::Identifier { name, .. } = callee.as_ref() {
+                if prelude::INTEGER_INTRINSICS.contains(&name.as_str())
+                    && self.get_function(name).is_none()
+                {
+                    let operand_type = self.numeric_types.get(id).cloned().ok_or_else(|| {
+                        IoError::codegen_error(format!("An unchecked call to {}", name))
+                            .with_span(node.span())
+                    })?;
+                    return self
+                        .integer_intrinsic(name, args, &operand_type)
+                        .map(Some)
+                        .map_err(|err| err.or_span(node.span()));
+                }
+            }
+        }
+        match node {
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Try,
+                operand,
+                id,
+                ..
+            } => {
+                let enum_name = self.tries.get(id).cloned().ok_or_else(|| {
+                    IoError::codegen_error("The ? operator on an unchecked operand")
+                })?;
+                self.try_operator(operand, &enum_name)
+                    .map_err(|err| err.or_span(node.span()))
+            }
+            ASTNode::Literal { value, id, .. } if self.numeric_types.contains_key(id) => self
+                .number_literal(value, &self.numeric_types[id])
+                .map(Some)
+                .map_err(|err| err.or_span(node.span())),
+            ASTNode::BinaryOp {
+                op,
+                left,
+                right,
+                id,
+                ..
+            } => {
+                let operand_type = self.numeric_types.get(id).cloned();
+                self.binary(op, left, right, operand_type.as_ref())
+                    .map_err(|err| err.or_span(node.span()))
+            }
+            ASTNode::Cast {
+                value, target, id, ..
+            } => {
+                let source = self.numeric_types.get(id).cloned().ok_or_else(|| {
+                    IoError::codegen_error("An unchecked `as` conversion").with_span(node.span())
+                })?;
+                let value = self.value_of(value)?;
+                self.cast(value, &source, target)
+                    .map(Some)
+                    .map_err(|err| err.or_span(node.span()))
+            }
+            ASTNode::Closure {
+                params,
+                return_type,
+                body,
+                is_move,
+                ..
+            } => self
+                .closure(params, return_type.as_ref(), body, *is_move)
+                .map(Some)
+                .map_err(|err| err.or_span(node.span())),
+            ASTNode::Identifier { name, id, .. } => {
+                if let Some((symbol, _)) = self.local(*id).filter(|_| self.moves.contains(id)) {
+                    self.disarm(symbol);
+                }
+                self.identifier(name, *id)
+                    .map_err(|err| err.or_span(node.span()))
+            }
+            ASTNode::For {
+                pattern:
+                    Pattern::Binding {
+                        name, id: binding, ..
+                    },
+                iterable,
+                body,
+                id,
+                ..
+            } => match iterable.as_ref() {
+                ASTNode::BinaryOp {
+                    op, left, right, ..
+                } if op.is_range() => {
+                    let variable = self.declared(*binding, name)?;
+                    self.range_loop(variable, *id, op, left, right, body)
+                        .map_err(|err| err.or_span(node.span()))
+                }
+                _ => walk_node(self, node),
+            },
+            ASTNode::LetPattern {
+                pattern,
+                type_annotation,
+                value,
+                ..
+            } => {
+                let ty = type_annotation.as_ref().ok_or_else(|| {
+                    IoError::codegen_error("An unchecked let pattern").with_span(node.span())
+                })?;
+                let value = self.value_of(value)?;
+                self.bind_pattern(pattern, value, ty)
+                    .map(|()| None)
+                    .map_err(|err| err.or_span(node.span()))
+            }
+            ASTNode::Assignment { target, value, .. } => {
+                let value = self.value_of(value)?;
+                let slot = self.place(target).map_err(|err| err.or_span(node.span()))?;
+                self.builder.build_store(slot, value);
+                Ok(None)
+            }
+            ASTNode::CompoundAssignment {
+                target,
+                op,
+                value,
+                id,
+                ..
+            } => {
+                let slot = self.place(target).map_err(|err| err.or_span(node.span()))?;
+                let current = self.builder.build_load(slot, "current");
+                let value = self.value_of(value)?;
+                let operand_type = self.numeric_types.get(id).cloned();
+                let result = self
+                    .generate_binary_op(op, current, value, operand_type.as_ref())
+                    .map_err(|err| err.or_span(node.span()))?;
+                self.builder.build_store(slot, result);
+                Ok(None)
+            }
+            ASTNode::MemberAccess { object, id, .. } => self
+                .member_value(object, *id)
+                .map(Some)
+                .map_err(|err| err.or_span(node.span())),
+            ASTNode::TupleLiteral { elements, .. } => self.tuple_value(elements).map(Some),
+            ASTNode::StructLiteral { fields, id, .. } => {
+                let ty = self.struct_types.get(id).cloned().ok_or_else(|| {
+                    IoError::codegen_error(
+                        "Struct variants cannot be compiled yet; run the program with `io run`",
+                    )
+                    .with_span(node.span())
+                })?;
+                self.struct_value(&ty, fields)
+                    .map(Some)
+                    .map_err(|err| err.or_span(node.span()))
+            }
+            ASTNode::Let {
+                name,
+                type_annotation,
+                value,
+                id,
+                ..
+            } => {
+                let value = value
+                    .as_deref()
+                    .map(|value| self.value_of(value))
+                    .transpose()?;
+                let symbol = self
+                    .declare_variable(*id, name, value, type_annotation.as_ref())
+                    .map_err(|err| err.or_span(node.span()))?;
+                if matches!(self.binding_types.get(id), Some(Type::Dyn(_))) {
+                    self.own(symbol).map_err(|err| err.or_span(node.span()))?;
+                }
+                Ok(None)
+            }
+            ASTNode::Function {
+                name, attributes, ..
+            } if !attributes.is_empty() => {
+                let value = walk_node(self, node)?;
+                if let Some(function) = self.module.get_function(name) {
+                    self.add_function_attributes(function, attributes);
+                }
+                Ok(value)
+            }
+            _ => walk_node(self, node),
+        }
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &str,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &[ASTNode],
+        // Tasks run to completion when called, as in the interpreter, so an
+        // async function compiles like any other and `await` yields its operand.
+        _is_async: bool,
+    ) -> Result<Self::Output> {
+        let param_types: Vec<BasicMetadataTypeEnum> = params
+            .iter()
+            .map(|p| p.type_annotation.to_llvm_type(self.context).into())
+            .collect();
+
+        let fn_type = match return_type {
+            Some(Type::Void) | None => self.void_type().fn_type(&param_types, false),
+            Some(ty) => ty.to_llvm_type(self.context).fn_type(&param_types, false),
+        };
+        let function = self.module.add_function(name, fn_type, None);
+
+        // Create basic block
+        let basic_block = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(basic_block);
+
+        // Save current function
+        let previous_function = self.current_function;
+        self.current_function = Some(function);
+
+        // Add parameters to scope
+        self.named_values.clear();
+        self.callables.clear();
+        let previous_scopes = std::mem::replace(&mut self.drop_scopes, vec![Vec::new()]);
+        for (param, value) in params.iter().zip(function.get_param_iter()) {
+            let symbol = self.declared(param.id, &param.name)?;
+            let alloca = self.create_entry_block_alloca(function, &param.name, value.get_type());
+            self.builder.build_store(alloca.into_pointer_value(), value);
+            self.named_values.insert(symbol, alloca);
+            if let Type::Function { .. } = param.type_annotation {
+                self.callables.insert(symbol, param.type_annotation.clone());
+            }
+            if matches!(param.type_annotation, Type::Dyn(_)) {
+                self.own(symbol)?;
+            }
+        }
+
+        // Generate function body
+        walk_nodes(self, body)?;
+        if self.is_open() {
+            self.emit_drops(0)?;
+        }
+        self.drop_scopes = previous_scopes;
+
+        // Restore previous function
+        self.current_function = previous_function;
+
+        if function.verify(true) {
+            Ok(Some(function.as_global_value().as_pointer_value().into()))
+        } else {
+            Err(IoError::codegen_error("Invalid generated function"))
+        }
+    }
+
+    fn visit_binary(
+        &mut self,
+        op: &BinaryOperator,
+        left: &ASTNode,
+        right: &ASTNode,
+    ) -> Result<Self::Output> {
+        self.binary(op, left, right, None)
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> Result<Self::Output> {
+        let value = match value {
+            Literal::Integer(value) => self.i32_type().const_int(*value as u64, true).into(),
+            Literal::Float(value) => self.f64_type().const_float(*value).into(),
+            Literal::Boolean(value) => self.bool_type().const_int(*value as u64, false).into(),
+            Literal::Char(value) => self.i32_type().const_int(*value as u64, false).into(),
+            Literal::String(value) => self
+                .builder
+                .build_global_string_ptr(value, "str")
+                .as_pointer_value()
+                .into(),
+        };
+        Ok(Some(value))
+    }
+
+    fn visit_block(&mut self, statements: &[ASTNode]) -> Result<Self::Output> {
+        self.scoped(statements)
+    }
+
+    /// A reference is the address of its referent: the variable's own slot,
+    /// or a temporary one holding the value of any other expression.
+    fn visit_unary(&mut self, op: &UnaryOperator, operand: &ASTNode) -> Result<Self::Output> {
+        match op {
+            UnaryOperator::Ref | UnaryOperator::RefMut => {
+                if let ASTNode::Identifier { id, .. } = operand {
+                    if let Some((_, slot)) = self.local(*id) {
+                        return Ok(Some(slot));
+                    }
+                }
+                let function = self
+                    .current_function
+                    .ok_or_else(|| IoError::codegen_error("Borrow outside function"))?;
+                let value = self.value_of(operand)?;
+                let slot = self.create_entry_block_alloca(function, "ref.tmp", value.get_type());
+                self.builder.build_store(slot.into_pointer_value(), value);
+                Ok(Some(slot))
+            }
+            UnaryOperator::Deref => {
+                let reference = self.value_of(operand)?;
+                Ok(Some(
+                    self.builder
+                        .build_load(reference.into_pointer_value(), "deref"),
+                ))
+            }
+            UnaryOperator::Negate => {
+                let value = self.value_of(operand)?;
+                Ok(Some(if value.is_float_value() {
+                    self.builder
+                        .build_float_neg(value.into_float_value(), "negtmp")
+                        .into()
+                } else {
+                    self.builder
+                        .build_int_neg(value.into_int_value(), "negtmp")
+                        .into()
+                }))
+            }
+            UnaryOperator::Not => {
+                let value = self.value_of(operand)?;
+                Ok(Some(
+                    self.builder
+                        .build_not(value.into_int_value(), "nottmp")
+                        .into(),
+                ))
+            }
+            // The awaited call has already run to completion.
+            UnaryOperator::Await => self.visit_node(operand),
+            // Checked `?`s are taken apart before reaching the visitor.
+            UnaryOperator::Try => Err(IoError::codegen_error(
+                "The ? operator on an unchecked operand",
+            )),
+        }
+    }
+
+    fn visit_return(&mut self, value: Option<&ASTNode>) -> Result<Self::Output> {
+        match value {
+            Some(expr) => {
+                let val = self.value_of(expr)?;
+                self.emit_drops(0)?;
+                self.builder.build_return(Some(&val));
+            }
+            None => {
+                self.emit_drops(0)?;
                 self.builder.build_return(None);
-                Ok(self.context.i32_type().const_zero().into())
             }
         }
+        Ok(None)
+    }
+
+    fn visit_call(&mut self, callee: &ASTNode, args: &[ASTNode]) -> Result<Self::Output> {
+        let (name, id) = match callee {
+            ASTNode::Identifier { name, id, .. } => (name, *id),
+            _ => return Err(IoError::codegen_error("Only named functions can be called")),
+        };
+        if let Some(path) = self.variant_paths.get(name).cloned() {
+            let mut fields = Vec::with_capacity(args.len());
+            for arg in args {
+                fields.push(self.value_of(arg)?);
+            }
+            return self.build_variant(&path, &fields).map(Some);
+        }
+        let callable = self
+            .local(id)
+            .and_then(|(symbol, _)| self.callables.get(&symbol).cloned());
+        if let Some(signature) = callable {
+            return self.closure_call(name, id, &signature, args);
+        }
+        if prelude::ITERATOR_FUNCTIONS.contains(&name.as_str()) && self.get_function(name).is_none()
+        {
+            return Err(IoError::codegen_error(format!(
+                "{} is not supported by the LLVM backend yet; use `io run`",
+                name
+            )));
+        }
+        let function = self
+            .get_function(name)
+            .ok_or_else(|| IoError::codegen_error(format!("Unknown function: {}", name)))?;
+
+        let mut compiled_args = Vec::with_capacity(args.len());
+        for arg in args {
+            compiled_args.push(self.value_of(arg)?.into());
+        }
+
+        let call = self.builder.build_call(function, &compiled_args, "calltmp");
+        Ok(call.try_as_basic_value().left())
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &ASTNode,
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+    ) -> Result<Self::Output> {
+        let cond_val = self.value_of(condition)?;
+        let current_fn = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("If statement outside function"))?;
+
+        let then_bb = self.context.append_basic_block(current_fn, "then");
+        let else_bb = self.context.append_basic_block(current_fn, "else");
+        let merge_bb = self.context.append_basic_block(current_fn, "merge");
+
+        self.builder
+            .build_conditional_branch(cond_val.into_int_value(), then_bb, else_bb);
+
+        // Generate then block
+        self.builder.position_at_end(then_bb);
+        self.scoped(then_branch)?;
+        self.builder.build_unconditional_branch(merge_bb);
+
+        // Generate else block
+        self.builder.position_at_end(else_bb);
+        if let Some(else_nodes) = else_branch {
+            self.scoped(else_nodes)?;
+        }
+        self.builder.build_unconditional_branch(merge_bb);
+
+        // Continue in merge block
+        self.builder.position_at_end(merge_bb);
+        Ok(None)
+    }
+
+    fn visit_while(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<Self::Output> {
+        let current_fn = self
+            .current_function
+            .ok_or_else(|| IoError::codegen_error("While loop outside function"))?;
+
+        let cond_bb = self.context.append_basic_block(current_fn, "while.cond");
+        let body_bb = self.context.append_basic_block(current_fn, "while.body");
+        let end_bb = self.context.append_basic_block(current_fn, "while.end");
+
+        // Jump to condition block
+        self.builder.build_unconditional_branch(cond_bb);
+
+        // Generate condition
+        self.builder.position_at_end(cond_bb);
+        let cond_val = self.value_of(condition)?;
+        self.builder
+            .build_conditional_branch(cond_val.into_int_value(), body_bb, end_bb);
+
+        // Generate body
+        self.builder.position_at_end(body_bb);
+        self.scoped(body)?;
+        self.builder.build_unconditional_branch(cond_bb);
+
+        // Continue after loop
+        self.builder.position_at_end(end_bb);
+        Ok(None)
+    }
+
+    fn visit_match(&mut self, _scrutinee: &ASTNode, _arms: &[MatchArm]) -> Result<Self::Output> {
+        Err(IoError::codegen_error(
+            "match expressions are not supported by the LLVM backend yet; use `io run`",
+        ))
+    }
+
+    /// Only loops over a range are compiled, by `range_loop`.
+    fn visit_for(
+        &mut self,
+        _pattern: &Pattern,
+        _iterable: &ASTNode,
+        _body: &[ASTNode],
+    ) -> Result<Self::Output> {
+        Err(IoError::codegen_error(
+            "for-in loops over anything but a range are not supported by the LLVM backend yet; \
+             use `io run`",
+        ))
     }
 }
 
@@ -1350,3 +2815,51 @@ impl<'ctx> LLVMImplementation<'ctx> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, semantic::resolve, span::FileId};
+
+    fn emit(source: &str) -> Result<String> {
+        let program = parse_source(source, FileId(0)).unwrap();
+        let context = Context::create();
+        let mut codegen = LLVMCodeGen::new(&context, "test");
+        codegen.generate(&program, &resolve::resolve(&program)?)?;
+        Ok(codegen.emit_ir())
+    }
+
+    #[test]
+    fn test_question_mark_in_async_functions() {
+        let source = "\
+fn half(n: int) -> Result<int, string> {
+    if n % 2 == 1 { return Result::Err(\"odd\"); }
+    return Result::Ok(n / 2);
+}
+async fn quarter(n: int) -> Result<int, string> { let h = half(n)?; return half(h); }
+async fn eighth(n: int) -> Result<int, string> { let q = await quarter(n)?; return half(q); }";
+        let ir = emit(source).unwrap();
+        assert!(ir.contains("@quarter("));
+        assert!(ir.contains("@eighth("));
+    }
+
+    #[test]
+    fn test_shadowed_locals_keep_their_own_slots() {
+        let source = "\
+fn f(x: int) -> int {
+    let x = x + 1;
+    let g = |n: int| n + x;
+    { let x = 10; }
+    for x in 0..3 {}
+    return g(x);
+}";
+        let ir = emit(source).unwrap();
+        assert_eq!(ir.matches("alloca i32").count(), 6);
+    }
+
+    #[test]
+    fn test_not() {
+        let ir = emit("fn flip(b: bool) -> bool { return !b; }").unwrap();
+        assert!(ir.contains("xor i1"));
+    }
+}
@@ -1,10 +1,11 @@
 pub mod debug;
 pub mod llvm;
+pub mod monomorphize;
 pub mod passes;
 pub mod types;
 
 use crate::{
-    ast::{ASTNode, Declaration, Module as AstModule, Parameter},
+    ast::{ASTNode, BinaryOperator, Literal, Parameter},
     error::IoError,
     types::Type,
 };
@@ -20,9 +21,8 @@ use std::{collections::HashMap, path::Path};
 
 pub trait CodeGenTrait {
     fn initialize(&mut self) -> Result<()>;
-    fn generate_module(&mut self, ast: &AstModule) -> Result<()>;
-    fn write_output<P: AsRef<Path>>(&self, path: P) -> Result<()>;
-    fn generate_module(&mut self, ast: &AstModule) -> Result<()>;
+    /// Generates code for a whole `ASTNode::Program`.
+    fn generate_module(&mut self, ast: &ASTNode) -> Result<()>;
     fn write_output<P: AsRef<Path>>(&self, path: P) -> Result<()>;
     fn optimize(&mut self) -> Result<()>;
     fn verify(&self) -> Result<()>;
@@ -96,44 +96,22 @@ impl<'ctx> CodeGeneratorImpl<'ctx> {
                 return_type,
                 body,
                 is_async,
-            } => self.generate_function(name, params, return_type, body, *is_async),
-            ASTNode::Statement(stmt) => self.generate_statement(stmt),
-            ASTNode::Expression(expr) => {
-                self.generate_expression(expr)?;
-                Ok(())
-            }
-            ASTNode::Block(nodes) => {
-                for node in nodes {
+                ..
+            } => self.generate_function(name, params, return_type.as_ref(), body, *is_async),
+            ASTNode::Block { statements, .. } => {
+                for node in statements {
                     self.generate(node)?;
                 }
                 Ok(())
             }
-            ASTNode::Call { name, args } => {
-                self.generate_function_call(name, args)?;
+            ASTNode::Let { .. }
+            | ASTNode::Return { .. }
+            | ASTNode::If { .. }
+            | ASTNode::While { .. } => self.generate_statement(ast),
+            _ => {
+                self.generate_expression(ast)?;
                 Ok(())
             }
-            ASTNode::If {
-                condition,
-                then_branch,
-                else_branch,
-            } => self.generate_if_statement(condition, then_branch, else_branch),
-
-            ASTNode::While { condition, body } => self.generate_while_loop(condition, body),
-            ASTNode::Return(expr) => {
-                let value = if let Some(expr) = expr {
-                    self.generate_expression(expr)?
-                } else {
-                    self.context.i32_type().const_zero().into()
-                };
-                self.builder.build_return(Some(&value));
-                Ok(())
-            }
-            ASTNode::Let { name, value } => {
-                let value = self.generate_expression(value)?;
-                self.variables.insert(name.clone(), value);
-                Ok(())
-            }
-            _ => Err(IoError::runtime_error("Unsupported AST node")),
         }
     }
 
@@ -141,7 +119,7 @@ impl<'ctx> CodeGeneratorImpl<'ctx> {
         &mut self,
         name: &str,
         params: &[Parameter],
-        return_type: &Option<String>,
+        return_type: Option<&Type>,
         body: &[ASTNode],
         is_async: bool,
     ) -> Result<()> {
@@ -172,12 +150,16 @@ impl<'ctx> CodeGeneratorImpl<'ctx> {
 
     fn generate_statement(&mut self, node: &ASTNode) -> Result<()> {
         match node {
-            ASTNode::Let { name, value } => {
+            ASTNode::Let {
+                name,
+                value: Some(value),
+                ..
+            } => {
                 let value = self.generate_expression(value)?;
                 self.variables.insert(name.clone(), value);
                 Ok(())
             }
-            ASTNode::Return(expr) => {
+            ASTNode::Return { value: expr, .. } => {
                 let value = if let Some(expr) = expr {
                     self.generate_expression(expr)?
                 } else {
@@ -190,49 +172,62 @@ impl<'ctx> CodeGeneratorImpl<'ctx> {
                 condition,
                 then_branch,
                 else_branch,
+                ..
             } => self.generate_if_statement(condition, then_branch, else_branch),
-            ASTNode::While { condition, body } => self.generate_while_loop(condition, body),
+            ASTNode::While {
+                condition, body, ..
+            } => self.generate_while_loop(condition, body),
             _ => Err(IoError::runtime_error("Unsupported statement type")),
         }
     }
 
     fn generate_expression(&mut self, node: &ASTNode) -> Result<BasicValueEnum<'ctx>> {
         match node {
-            ASTNode::Number(value) => Ok(self
+            ASTNode::Literal {
+                value: Literal::Integer(value),
+                ..
+            } => Ok(self
                 .context
                 .i32_type()
                 .const_int(*value as u64, false)
                 .into()),
-            ASTNode::BinaryOp { op, left, right } => {
+            ASTNode::BinaryOp {
+                op, left, right, ..
+            } => {
                 let lhs = self.generate_expression(left)?;
                 let rhs = self.generate_expression(right)?;
 
-                match op.as_str() {
-                    "+" => Ok(self
+                match op {
+                    BinaryOperator::Add => Ok(self
                         .builder
                         .build_int_add(lhs.into_int_value(), rhs.into_int_value(), "addtmp")
                         .into()),
-                    "-" => Ok(self
+                    BinaryOperator::Subtract => Ok(self
                         .builder
                         .build_int_sub(lhs.into_int_value(), rhs.into_int_value(), "subtmp")
                         .into()),
-                    "*" => Ok(self
+                    BinaryOperator::Multiply => Ok(self
                         .builder
                         .build_int_mul(lhs.into_int_value(), rhs.into_int_value(), "multmp")
                         .into()),
-                    "/" => Ok(self
+                    BinaryOperator::Divide => Ok(self
                         .builder
                         .build_int_signed_div(lhs.into_int_value(), rhs.into_int_value(), "divtmp")
                         .into()),
                     _ => Err(IoError::runtime_error("Unknown operator")),
                 }
             }
-            ASTNode::Identifier(name) => self
+            ASTNode::Identifier { name, .. } => self
                 .variables
                 .get(name)
                 .cloned()
                 .ok_or_else(|| IoError::runtime_error("Undefined variable")),
-            ASTNode::Call { name, args } => self.generate_function_call(name, args),
+            ASTNode::Call { args, .. } => {
+                let name = node
+                    .callee_name()
+                    .ok_or_else(|| IoError::codegen_error("Only named functions can be called"))?;
+                self.generate_function_call(name, args)
+            }
             _ => Err(IoError::runtime_error("Unsupported expression type")),
         }
     }
@@ -316,7 +311,7 @@ impl<'ctx> CodeGeneratorImpl<'ctx> {
             Type::I64 => Ok(self.context.i64_type().into()),
             Type::F32 => Ok(self.context.f32_type().into()),
             Type::String => Ok(self.context.ptr_type(inkwell::AddressSpace::Generic).into()),
-            Type::Array(elem_ty) => {
+            Type::Array { elem_type: elem_ty, .. } => {
                 let llvm_ty = self.get_llvm_type(elem_ty)?;
                 Ok(self.context.array_type(&llvm_ty, 0).into())
             }
@@ -369,20 +364,24 @@ impl<'ctx> CodeGenerator for LLVMGenerator<'ctx> {
         Ok(())
     }
 
-    fn generate_module(&mut self, ast: &Module) -> Result<()> {
+    fn generate_module(&mut self, ast: &ASTNode) -> Result<()> {
+        let items = match ast {
+            ASTNode::Program(items) => items,
+            _ => return Err(IoError::codegen_error("Expected a program")),
+        };
+
         // Generate declarations
-        for decl in ast.declarations() {
-            match decl {
-                Declaration::Function(func) => self.generate_function(func)?,
-                Declaration::Struct(struct_def) => self.generate_struct(struct_def)?,
-                Declaration::Import(import) => self.process_import(import)?,
-                Declaration::Global(global) => self.generate_global(global)?,
+        for item in items {
+            if let ASTNode::Function { .. } = item {
+                self.generate_function(item)?;
             }
         }
 
         // Generate function implementations
-        for func in ast.functions() {
-            self.generate_function_body(func)?;
+        for item in items {
+            if let ASTNode::Function { .. } = item {
+                self.generate_function_body(item)?;
+            }
         }
 
         // Generate initialization code
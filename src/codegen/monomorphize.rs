@@ -0,0 +1,865 @@
+//! Monomorphization: every generic function is compiled once per distinct list of
+//! type arguments it is called with, as an ordinary function named after that
+//! instantiation (`map<i32, f64>`). Struct annotations are laid out as
+//! `Type::Struct` on the way, so code generation only sees concrete types.
+//!
+//! Trait methods become ordinary functions too, one per impl (`<f64 as
+//! Area>::area`), and method calls on concrete receivers call them directly.
+//! Calls on `dyn Trait` receivers are left for code generation to send
+//! through a vtable.
+//!
+//! Enum values are laid out as structs too: an `i32` tag, the index of the
+//! value's variant, followed by the fields of every variant in turn. The
+//! generic functions of the prelude are instantiated like the program's own.
+
+use crate::{
+    ast::{ASTNode, NodeId, TraitMethod, Type, UnaryOperator},
+    error::IoError,
+    semantic::{borrowck::BorrowChecker, flow, resolve::Resolution},
+    span::Span,
+    stdlib::prelude,
+    types::checker::{Implementation, TypeChecker},
+    visitor::{fold_children, Folder},
+    Result,
+};
+use std::collections::{HashMap, HashSet};
+
+/// A monomorphized program, with what code generation needs to build and call
+/// trait objects.
+#[derive(Debug)]
+pub struct Monomorphized {
+    pub program: ASTNode,
+    /// The methods of each trait in declaration order: the slots of its vtables.
+    pub vtables: HashMap<String, Vec<String>>,
+    /// Values converted to `dyn Trait`, by node id.
+    pub coercions: HashMap<NodeId, Implementation>,
+    /// Method calls on `dyn Trait` receivers, by call node id.
+    pub dyn_calls: HashMap<NodeId, DynCall>,
+    /// The type of each variable, by the node id declaring it.
+    pub binding_types: HashMap<NodeId, Type>,
+    /// Identifiers whose use moves the value out of their variable.
+    pub moves: HashSet<NodeId>,
+    /// The variants of each enum in declaration order, with their field counts.
+    pub enums: HashMap<String, Vec<(String, usize)>>,
+    /// What each `Enum::Variant` path builds, by the path's name qualified
+    /// with the enum's type arguments (`Option<i32>::Some`).
+    pub variant_paths: HashMap<String, VariantPath>,
+    /// The enum each `?` takes apart, by node id.
+    pub tries: HashMap<NodeId, String>,
+    /// The type of each number literal, the operand type of each operator and
+    /// integer intrinsic, and the source type of each `as`, by node id.
+    pub numeric_types: HashMap<NodeId, Type>,
+    /// The position of the field each member access reads or assigns in its
+    /// struct or tuple, by node id.
+    pub field_indices: HashMap<NodeId, usize>,
+    /// The struct each struct literal without an enum builds, laid out, by
+    /// node id.
+    pub struct_types: HashMap<NodeId, Type>,
+}
+
+/// An `Enum::Variant` path with its enum laid out.
+#[derive(Debug, Clone)]
+pub struct VariantPath {
+    pub enum_name: String,
+    pub variant: String,
+    pub ty: Type,
+}
+
+/// A method call through a vtable.
+#[derive(Debug, Clone)]
+pub struct DynCall {
+    pub slot: usize,
+    /// The method's type without `self`, laid out.
+    pub signature: Type,
+}
+
+/// The name the method of an impl is compiled under.
+pub fn method_symbol(self_type: &Type, trait_name: &str, method: &str) -> String {
+    format!("<{} as {}>::{}", self_type, trait_name, method)
+}
+
+/// Type-, flow- and borrow-checks `program`, whose names `resolution`
+/// resolved, and returns it without generic functions, type definitions,
+/// traits or impls. Trait methods come first, then instances, callees before
+/// their callers. Instances keep the node ids of the generic function they
+/// were made from, so their variables resolve to its symbols.
+pub fn monomorphize(program: &ASTNode, resolution: &Resolution) -> Result<Monomorphized> {
+    let items = match program {
+        ASTNode::Program(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let mut checker = TypeChecker::new();
+    checker.check(program, resolution)?;
+    // Flow warnings don't stop compilation.
+    if let Some(error) = flow::check(program)?.into_iter().find(|d| d.is_error()) {
+        return Err(error.into_error());
+    }
+    let binding_types = checker.binding_types();
+    let mut borrowck = BorrowChecker::new(binding_types.clone());
+    borrowck.check(program)?;
+
+    // The program's own definitions come last and so win over the prelude's.
+    let generics: HashMap<&str, &ASTNode> = prelude::items()
+        .iter()
+        .chain(items)
+        .filter_map(|item| match item {
+            ASTNode::Function {
+                name, type_params, ..
+            } if !type_params.is_empty() => Some((name.as_str(), item)),
+            _ => None,
+        })
+        .collect();
+
+    let traits: HashMap<&str, (&[String], &[TraitMethod])> = prelude::items()
+        .iter()
+        .chain(items)
+        .filter_map(|item| match item {
+            ASTNode::TraitDef {
+                name,
+                type_params,
+                methods,
+                ..
+            } => Some((name.as_str(), (type_params.as_slice(), methods.as_slice()))),
+            _ => None,
+        })
+        .collect();
+    let vtables: HashMap<String, Vec<String>> = traits
+        .iter()
+        .map(|(name, (_, methods))| {
+            let slots = methods.iter().map(|method| method.name.clone()).collect();
+            (name.to_string(), slots)
+        })
+        .collect();
+
+    let mut instantiator = Instantiator {
+        checker: &checker,
+        instantiations: checker.instantiations(),
+        method_calls: checker.method_calls(),
+        enum_values: checker.enum_values(),
+        closure_types: checker.closure_types(),
+        binding_types: binding_types.clone(),
+        struct_literals: checker.struct_literals(),
+        variant_paths: HashMap::new(),
+        struct_types: HashMap::new(),
+        tries: HashMap::new(),
+        bindings: HashMap::new(),
+        requested: Vec::new(),
+        dyn_calls: HashMap::new(),
+    };
+    let mut methods = Vec::new();
+    let mut rest = Vec::new();
+    for item in items {
+        match item {
+            ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } | ASTNode::TraitDef { .. } => {}
+            ASTNode::Function { name, .. } if generics.contains_key(name.as_str()) => {}
+            ASTNode::Impl {
+                trait_name,
+                trait_args,
+                self_type,
+                methods: defined,
+                ..
+            } => {
+                // The type checker keeps an inherent impl's methods as a trait
+                // named after the type.
+                let trait_name = trait_name.clone().unwrap_or_else(|| self_type.to_string());
+                let (type_params, declared) =
+                    traits.get(trait_name.as_str()).copied().unwrap_or_default();
+                for method in impl_methods(declared, defined) {
+                    let ASTNode::Function { name, .. } = &method else {
+                        continue;
+                    };
+                    let symbol = method_symbol(self_type, &trait_name, name);
+                    let mut bindings: HashMap<String, Type> = type_params
+                        .iter()
+                        .cloned()
+                        .zip(trait_args.clone())
+                        .collect();
+                    bindings.insert("Self".to_string(), self_type.clone());
+                    methods.push(instantiator.instantiate(&method, symbol, bindings)?);
+                }
+            }
+            item => rest.push(instantiator.fold_node(item.clone())?),
+        }
+    }
+
+    // Instantiating a function can request further instances from its body.
+    let mut instances = Vec::new();
+    let mut seen = HashSet::new();
+    while let Some((name, type_args)) = instantiator.requested.pop() {
+        let mangled = instance_name(&name, &type_args);
+        if !seen.insert(mangled.clone()) {
+            continue;
+        }
+        let generic = generics.get(name.as_str()).ok_or_else(|| {
+            IoError::codegen_error(format!("No generic function {} to instantiate", name))
+        })?;
+        let ASTNode::Function { type_params, .. } = generic else {
+            return Err(IoError::codegen_error("Only functions can be instantiated"));
+        };
+        let bindings = type_params.iter().cloned().zip(type_args).collect();
+        instances.push(instantiator.instantiate(generic, mangled, bindings)?);
+    }
+
+    instances.reverse();
+    methods.extend(instances);
+    methods.extend(rest);
+    Ok(Monomorphized {
+        program: ASTNode::Program(methods),
+        coercions: checker.coercions().clone(),
+        dyn_calls: instantiator.dyn_calls,
+        vtables,
+        binding_types,
+        moves: borrowck.moves().clone(),
+        enums: checker.enum_variants(),
+        variant_paths: instantiator.variant_paths,
+        tries: instantiator.tries,
+        numeric_types: checker.numeric_types(),
+        field_indices: checker.field_indices().clone(),
+        struct_types: instantiator.struct_types,
+    })
+}
+
+/// The methods an impl provides: its own, then the trait's default methods
+/// it does not override.
+fn impl_methods(declared: &[TraitMethod], defined: &[ASTNode]) -> Vec<ASTNode> {
+    let overrides = |method: &TraitMethod| {
+        defined
+            .iter()
+            .any(|node| matches!(node, ASTNode::Function { name, .. } if *name == method.name))
+    };
+    let defaults = declared.iter().filter_map(|method| {
+        let body = method.body.clone()?;
+        if overrides(method) {
+            return None;
+        }
+        Some(ASTNode::Function {
+            name: method.name.clone(),
+            type_params: Vec::new(),
+            bounds: Vec::new(),
+            params: method.params.clone(),
+            return_type: method.return_type.clone(),
+            body,
+            is_async: false,
+            is_const: false,
+            is_pub: false,
+            attributes: Vec::new(),
+            id: method.id,
+            span: method.span,
+        })
+    });
+    defined.iter().cloned().chain(defaults).collect()
+}
+
+/// The name an instance of `function` is compiled under.
+fn instance_name(function: &str, type_args: &[Type]) -> String {
+    Type::Generic {
+        name: function.to_string(),
+        args: type_args.to_vec(),
+    }
+    .to_string()
+}
+
+struct Instantiator<'a> {
+    checker: &'a TypeChecker,
+    /// Type arguments of each generic call, by call node id.
+    instantiations: HashMap<NodeId, Vec<Type>>,
+    /// The implementation each method call dispatches to, by call node id.
+    method_calls: HashMap<NodeId, Implementation>,
+    /// Type arguments of the instance being built, by parameter name.
+    bindings: HashMap<String, Type>,
+    /// Instances called so far, as (generic function, type arguments).
+    requested: Vec<(String, Vec<Type>)>,
+    /// Method calls left to go through a vtable, by call node id.
+    dyn_calls: HashMap<NodeId, DynCall>,
+    /// The enum type of each `Enum::Variant` path and `?` operand, by node id.
+    enum_values: HashMap<NodeId, Type>,
+    variant_paths: HashMap<String, VariantPath>,
+    tries: HashMap<NodeId, String>,
+    /// The function type of each closure, by node id.
+    closure_types: HashMap<NodeId, Type>,
+    /// The type of each variable, by the node id declaring it.
+    binding_types: HashMap<NodeId, Type>,
+    /// The type each struct literal builds, by node id.
+    struct_literals: HashMap<NodeId, Type>,
+    struct_types: HashMap<NodeId, Type>,
+}
+
+impl Instantiator<'_> {
+    /// `function` compiled as `mangled`, with its type parameters (or `Self`)
+    /// replaced by `bindings`.
+    fn instantiate(
+        &mut self,
+        function: &ASTNode,
+        mangled: String,
+        bindings: HashMap<String, Type>,
+    ) -> Result<ASTNode> {
+        self.bindings = bindings;
+        let instance = self.fold_node(function.clone());
+        self.bindings.clear();
+
+        match instance? {
+            ASTNode::Function {
+                params,
+                return_type,
+                body,
+                is_async,
+                is_const,
+                is_pub,
+                attributes,
+                id,
+                span,
+                ..
+            } => Ok(ASTNode::Function {
+                name: mangled,
+                type_params: Vec::new(),
+                bounds: Vec::new(),
+                params,
+                return_type,
+                body,
+                is_async,
+                is_const,
+                is_pub,
+                attributes,
+                id,
+                span,
+            }),
+            other => Ok(other),
+        }
+    }
+
+    fn concrete(&self, ty: &Type) -> Result<Type> {
+        self.checker.layout(&ty.substitute(&self.bindings))
+    }
+
+    fn instance_for(&mut self, call: NodeId, callee: &str) -> Option<String> {
+        let type_args: Vec<Type> = self
+            .instantiations
+            .get(&call)?
+            .iter()
+            .map(|arg| arg.substitute(&self.bindings))
+            .collect();
+        let mangled = instance_name(callee, &type_args);
+        self.requested.push((callee.to_string(), type_args));
+        Some(mangled)
+    }
+
+    /// `path` qualified with the type arguments its enum has in the instance
+    /// being built, so each instance builds values of its own layout.
+    fn variant_path(&mut self, path: String, id: NodeId) -> Result<String> {
+        let (Some(enum_type), Some((_, variant))) =
+            (self.enum_values.get(&id), path.rsplit_once("::"))
+        else {
+            return Ok(path);
+        };
+        let enum_name = match enum_type {
+            Type::Generic { name, .. } | Type::Named(name) => name.clone(),
+            other => other.to_string(),
+        };
+        let ty = self.concrete(enum_type)?;
+        let Type::Struct { name: laid_out, .. } = &ty else {
+            return Err(IoError::codegen_error(format!(
+                "Cannot lay out {} to build {}",
+                ty, path
+            )));
+        };
+        let qualified = format!("{}::{}", laid_out, variant);
+        self.variant_paths.insert(
+            qualified.clone(),
+            VariantPath {
+                enum_name,
+                variant: variant.to_string(),
+                ty,
+            },
+        );
+        Ok(qualified)
+    }
+
+    /// A method call with its receiver known: a direct call to the impl's
+    /// method with the receiver as first argument, or, on a `dyn Trait`
+    /// receiver, a call code generation sends through the vtable.
+    fn method_call(
+        &mut self,
+        callee: ASTNode,
+        args: Vec<ASTNode>,
+        implementation: Implementation,
+        id: NodeId,
+        span: Span,
+    ) -> Result<ASTNode> {
+        let self_type = implementation.self_type.substitute(&self.bindings);
+        let ASTNode::MemberAccess {
+            object,
+            member,
+            id: callee_id,
+            span: callee_span,
+        } = callee
+        else {
+            return Err(IoError::codegen_error("Method call without a receiver"));
+        };
+
+        if let Type::Dyn(trait_name) = &self_type {
+            let methods = self.checker.trait_methods(trait_name).unwrap_or_default();
+            let slot = methods
+                .iter()
+                .position(|(name, _)| *name == member)
+                .ok_or_else(|| {
+                    IoError::codegen_error(format!("Trait {} has no method {}", trait_name, member))
+                })?;
+            let signature = self.concrete(&methods[slot].1)?;
+            self.dyn_calls.insert(id, DynCall { slot, signature });
+            return Ok(ASTNode::Call {
+                callee: Box::new(ASTNode::MemberAccess {
+                    object: Box::new(self.fold_node(*object)?),
+                    member,
+                    id: callee_id,
+                    span: callee_span,
+                }),
+                args: self.fold_nodes(args)?,
+                id,
+                span,
+            });
+        }
+
+        let mut receiver_and_args = vec![self.fold_node(*object)?];
+        receiver_and_args.extend(self.fold_nodes(args)?);
+        Ok(ASTNode::Call {
+            callee: Box::new(ASTNode::Identifier {
+                name: method_symbol(&self_type, &implementation.trait_name, &member),
+                id: callee_id,
+                span: callee_span,
+            }),
+            args: receiver_and_args,
+            id,
+            span,
+        })
+    }
+}
+
+impl Folder for Instantiator<'_> {
+    fn fold_node(&mut self, node: ASTNode) -> Result<ASTNode> {
+        match node {
+            ASTNode::Function {
+                name,
+                type_params,
+                bounds,
+                mut params,
+                return_type,
+                body,
+                is_async,
+                is_const,
+                is_pub,
+                attributes,
+                id,
+                span,
+            } => {
+                for param in &mut params {
+                    param.type_annotation = self
+                        .concrete(&param.type_annotation)
+                        .map_err(|err| err.or_span(param.span))?;
+                }
+                Ok(ASTNode::Function {
+                    name,
+                    type_params,
+                    bounds,
+                    params,
+                    return_type: return_type.map(|ty| self.concrete(&ty)).transpose()?,
+                    body: self.fold_nodes(body)?,
+                    is_async,
+                    is_const,
+                    is_pub,
+                    attributes,
+                    id,
+                    span,
+                })
+            }
+            ASTNode::Let {
+                name,
+                type_annotation,
+                value,
+                id,
+                span,
+            } => {
+                // Code generation calls a closure through the type of the
+                // variable holding it.
+                let ty = type_annotation.or_else(|| {
+                    self.binding_types
+                        .get(&id)
+                        .filter(|ty| matches!(ty, Type::Function { .. }))
+                        .cloned()
+                });
+                Ok(ASTNode::Let {
+                    name,
+                    type_annotation: ty.map(|ty| self.concrete(&ty)).transpose()?,
+                    value: value
+                        .map(|value| self.fold_node(*value).map(Box::new))
+                        .transpose()?,
+                    id,
+                    span,
+                })
+            }
+            // Code generation takes the value apart by its laid-out type.
+            ASTNode::LetPattern {
+                pattern,
+                type_annotation,
+                value,
+                id,
+                span,
+            } => {
+                let ty = type_annotation.or_else(|| self.binding_types.get(&id).cloned());
+                Ok(ASTNode::LetPattern {
+                    pattern,
+                    type_annotation: ty
+                        .map(|ty| self.concrete(&ty))
+                        .transpose()
+                        .map_err(|err| err.or_span(span))?,
+                    value: Box::new(self.fold_node(*value)?),
+                    id,
+                    span,
+                })
+            }
+            ASTNode::StructLiteral { ref name, id, .. } if !name.contains("::") => {
+                if let Some(ty) = self.struct_literals.get(&id).cloned() {
+                    let ty = self.concrete(&ty).map_err(|err| err.or_span(node.span()))?;
+                    self.struct_types.insert(id, ty);
+                }
+                fold_children(self, node)
+            }
+            ASTNode::Closure {
+                mut params,
+                body,
+                is_move,
+                id,
+                span,
+                ..
+            } => {
+                let Some(Type::Function {
+                    params: param_types,
+                    return_type,
+                    ..
+                }) = self.closure_types.get(&id).cloned()
+                else {
+                    return Err(IoError::codegen_error("An unchecked closure").with_span(span));
+                };
+                for (param, ty) in params.iter_mut().zip(&param_types) {
+                    param.type_annotation =
+                        self.concrete(ty).map_err(|err| err.or_span(param.span))?;
+                }
+                Ok(ASTNode::Closure {
+                    params,
+                    return_type: Some(
+                        self.concrete(&return_type)
+                            .map_err(|err| err.or_span(span))?,
+                    ),
+                    body: Box::new(self.fold_node(*body)?),
+                    is_move,
+                    id,
+                    span,
+                })
+            }
+            ASTNode::Call {
+                callee,
+                args,
+                id,
+                span,
+            } => {
+                if let Some(implementation) = self.method_calls.get(&id).cloned() {
+                    return self.method_call(*callee, args, implementation, id, span);
+                }
+                let callee = match *callee {
+                    ASTNode::Identifier {
+                        name,
+                        id: callee_id,
+                        span: callee_span,
+                    } if !self.enum_values.contains_key(&callee_id) => ASTNode::Identifier {
+                        name: self.instance_for(id, &name).unwrap_or(name),
+                        id: callee_id,
+                        span: callee_span,
+                    },
+                    other => self.fold_node(other)?,
+                };
+                Ok(ASTNode::Call {
+                    callee: Box::new(callee),
+                    args: self.fold_nodes(args)?,
+                    id,
+                    span,
+                })
+            }
+            ASTNode::Identifier { name, id, span } => Ok(ASTNode::Identifier {
+                name: self
+                    .variant_path(name, id)
+                    .map_err(|err| err.or_span(span))?,
+                id,
+                span,
+            }),
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Try,
+                operand,
+                id,
+                span,
+            } => {
+                if let Some(Type::Generic { name, .. }) = self.enum_values.get(&id) {
+                    self.tries.insert(id, name.clone());
+                }
+                Ok(ASTNode::UnaryOp {
+                    op: UnaryOperator::Try,
+                    operand: Box::new(self.fold_node(*operand)?),
+                    id,
+                    span,
+                })
+            }
+            other => fold_children(self, other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, semantic::resolve, span::FileId};
+
+    fn lower(source: &str) -> Result<Monomorphized> {
+        let program = parse_source(source, FileId(0)).unwrap();
+        monomorphize(&program, &resolve::resolve(&program)?)
+    }
+
+    fn functions(program: &ASTNode) -> Vec<String> {
+        let ASTNode::Program(items) = program else {
+            panic!("expected program");
+        };
+        items
+            .iter()
+            .filter_map(|item| match item {
+                ASTNode::Function { name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_one_instance_per_type_argument_list() {
+        let source = "\
+fn id<T>(x: T) -> T { return x; }
+fn twice<T>(x: T, y: T) -> [T] { return [id(x), id(y)]; }
+fn main() {
+    let a = id(1);
+    let b = id(2);
+    let c = twice(true, false);
+}";
+        let program = lower(source).unwrap().program;
+        assert_eq!(
+            functions(&program),
+            ["id<i32>", "id<bool>", "twice<bool>", "main"]
+        );
+    }
+
+    #[test]
+    fn test_struct_annotations_are_laid_out() {
+        let source = "\
+struct Pair<A, B> { first: A, second: B }
+fn first<A, B>(p: Pair<A, B>) -> A { return p.first; }
+fn f(p: Pair<int, bool>) -> int { return first(p); }";
+        let program = lower(source).unwrap().program;
+        let ASTNode::Program(items) = &program else {
+            panic!("expected program");
+        };
+        let ASTNode::Function { name, params, .. } = &items[0] else {
+            panic!("expected function, got {:?}", items[0]);
+        };
+        assert_eq!(name, "first<i32, bool>");
+        assert_eq!(
+            params[0].type_annotation,
+            Type::Struct {
+                name: "Pair<i32, bool>".into(),
+                fields: vec![("first".into(), Type::I32), ("second".into(), Type::Bool)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_method_calls_become_direct_calls() {
+        let source = "\
+trait Area {
+    fn area(self) -> float;
+    fn double(self) -> float { return self.area() * 2.0; }
+}
+impl Area for float { fn area(self) -> float { return self * self; } }
+fn total<T: Area>(shape: T) -> float { return shape.double(); }
+fn main() {
+    let a = total(2.0);
+    let d: dyn Area = 1.0;
+    let b = d.area();
+}";
+        let lowered = lower(source).unwrap();
+        assert_eq!(
+            functions(&lowered.program),
+            [
+                "<f64 as Area>::area",
+                "<f64 as Area>::double",
+                "total<f64>",
+                "main"
+            ]
+        );
+        let ASTNode::Program(items) = &lowered.program else {
+            panic!("expected program");
+        };
+        assert!(format!("{:?}", items[2]).contains("\"<f64 as Area>::double\""));
+
+        assert_eq!(lowered.vtables["Area"], ["area", "double"]);
+        assert_eq!(lowered.coercions.len(), 1);
+        let slots: Vec<usize> = lowered.dyn_calls.values().map(|call| call.slot).collect();
+        assert_eq!(slots, [0]);
+    }
+
+    #[test]
+    fn test_variant_paths_are_qualified_per_instance() {
+        let source = "\
+fn wrap<T>(x: T) -> Option<T> { return Option::Some(x); }
+fn half(n: int) -> Result<int, string> { return Result::Ok(n / 2); }
+fn quarter(n: int) -> Result<int, string> { return half(half(n)?); }
+fn main() {
+    let a = wrap(1);
+    let b = wrap(true);
+    let c = unwrap_or(a, 0);
+}";
+        let lowered = lower(source).unwrap();
+        let mut paths: Vec<&str> = lowered.variant_paths.keys().map(String::as_str).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "Option<bool>::Some",
+                "Option<i32>::Some",
+                "Result<i32, string>::Ok"
+            ]
+        );
+        assert_eq!(
+            lowered.variant_paths["Option<i32>::Some"].ty,
+            Type::Struct {
+                name: "Option<i32>".into(),
+                fields: vec![("tag".into(), Type::I32), ("Some.0".into(), Type::I32)],
+            }
+        );
+        assert_eq!(lowered.tries.values().collect::<Vec<_>>(), ["Result"]);
+        assert!(functions(&lowered.program).contains(&"unwrap_or<i32>".to_string()));
+    }
+
+    #[test]
+    fn test_closures_are_annotated_per_instance() {
+        let source = "\
+fn apply<T>(x: T, f: fn(T) -> T) -> T { let g = f; return g(x); }
+fn main() {
+    let n = 2;
+    let a = apply(1, |x| x * n);
+    let b = apply(true, |b| !b);
+}";
+        let lowered = lower(source).unwrap();
+        let ASTNode::Program(items) = &lowered.program else {
+            panic!("expected program");
+        };
+        let ASTNode::Function { body, .. } = &items[2] else {
+            panic!("expected main");
+        };
+        let ASTNode::Let {
+            value: Some(value), ..
+        } = &body[1]
+        else {
+            panic!("expected let");
+        };
+        let ASTNode::Call { args, .. } = value.as_ref() else {
+            panic!("expected call");
+        };
+        let ASTNode::Closure {
+            params,
+            return_type,
+            ..
+        } = &args[1]
+        else {
+            panic!("expected closure");
+        };
+        assert_eq!(params[0].type_annotation, Type::I32);
+        assert_eq!(return_type.as_ref(), Some(&Type::I32));
+
+        let instance = items
+            .iter()
+            .find(|item| matches!(item, ASTNode::Function { name, .. } if name == "apply<bool>"))
+            .expect("expected apply<bool>");
+        let ASTNode::Function { body, .. } = instance else {
+            unreachable!();
+        };
+        assert!(matches!(
+            &body[0],
+            ASTNode::Let { type_annotation: Some(Type::Function { params, .. }), .. }
+                if params == &[Type::Bool]
+        ));
+    }
+
+    #[test]
+    fn test_struct_literals_and_let_patterns_are_laid_out() {
+        let source = "\
+struct Point { x: int, y: bool }
+fn f() -> bool {
+    let (n, p) = (1, Point { x: 2, y: true });
+    p.x += n;
+    return p.y;
+}";
+        let lowered = lower(source).unwrap();
+        let point = Type::Struct {
+            name: "Point".into(),
+            fields: vec![("x".into(), Type::I32), ("y".into(), Type::Bool)],
+        };
+        let ASTNode::Program(items) = &lowered.program else {
+            panic!("expected program");
+        };
+        let ASTNode::Function { body, .. } = &items[0] else {
+            panic!("expected function, got {:?}", items[0]);
+        };
+        let ASTNode::LetPattern {
+            type_annotation: Some(ty),
+            ..
+        } = &body[0]
+        else {
+            panic!("expected an annotated let pattern, got {:?}", body[0]);
+        };
+        assert_eq!(ty, &Type::Tuple(vec![Type::I32, point.clone()]));
+        assert_eq!(lowered.struct_types.values().collect::<Vec<_>>(), [&point]);
+
+        let mut indices: Vec<usize> = lowered.field_indices.values().copied().collect();
+        indices.sort();
+        assert_eq!(indices, [0, 1]);
+    }
+
+    #[test]
+    fn test_instances_resolve_to_their_generic_functions_symbols() {
+        let source = "\
+fn id<T>(x: T) -> T { let y = x; return y; }
+fn main() { let a = id(1); let b = id(true); }";
+        let program = parse_source(source, FileId(0)).unwrap();
+        let resolution = resolve::resolve(&program).unwrap();
+        let lowered = monomorphize(&program, &resolution).unwrap();
+        let ASTNode::Program(items) = &lowered.program else {
+            panic!("expected program");
+        };
+        for item in &items[..2] {
+            let ASTNode::Function { params, body, .. } = item else {
+                panic!("expected function, got {:?}", item);
+            };
+            let x = resolution.declared(params[0].id).unwrap();
+            let (
+                ASTNode::Let {
+                    value: Some(value),
+                    id,
+                    ..
+                },
+                ASTNode::Return {
+                    value: Some(returned),
+                    ..
+                },
+            ) = (&body[0], &body[1])
+            else {
+                panic!("expected a let and a return, got {:?}", body);
+            };
+            assert_eq!(resolution.resolved(value.id()), Some(x));
+            let y = resolution.declared(*id).unwrap();
+            assert_eq!(resolution.resolved(returned.id()), Some(y));
+        }
+    }
+}
@@ -1,38 +1,7 @@
 use crate::error::{IoError, Result};
-use inkwell::types::{BasicType, BasicTypeEnum};
+use inkwell::types::BasicTypeEnum;
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum TypeKind {
-    Void,
-    Bool,
-    Int(u32),   // bit width
-    Float(u32), // bit width
-    Pointer(Box<Type>),
-    Array(Box<Type>, Option<u32>),
-    Struct(Vec<Type>, bool), // (fields, packed)
-    Function {
-        params: Vec<Type>,
-        return_type: Box<Type>,
-        varargs: bool,
-    },
-    Vector(Box<Type>, u32),
-    Opaque(String),
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub struct Type {
-    pub kind: TypeKind,
-    pub attributes: TypeAttributes,
-}
-
-#[derive(Debug, Clone, PartialEq, Default)]
-pub struct TypeAttributes {
-    pub volatile: bool,
-    pub atomic: bool,
-    pub address_space: Option<u32>,
-}
-
 pub struct TypeRegistry<'ctx> {
     types: HashMap<String, BasicTypeEnum<'ctx>>,
     opaque_types: HashMap<String, inkwell::types::StructType<'ctx>>,
@@ -81,131 +50,25 @@ impl<'ctx> TypeRegistry<'ctx> {
     }
 }
 
-impl Type {
-    pub fn void() -> Self {
-        Self {
-            kind: TypeKind::Void,
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn bool() -> Self {
-        Self {
-            kind: TypeKind::Bool,
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn i8() -> Self {
-        Self {
-            kind: TypeKind::Int(8),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn i32() -> Self {
-        Self {
-            kind: TypeKind::Int(32),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn i64() -> Self {
-        Self {
-            kind: TypeKind::Int(64),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn f32() -> Self {
-        Self {
-            kind: TypeKind::Float(32),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn f64() -> Self {
-        Self {
-            kind: TypeKind::Float(64),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn ptr(ty: Type) -> Self {
-        Self {
-            kind: TypeKind::Pointer(Box::new(ty)),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn array(ty: Type, size: Option<u32>) -> Self {
-        Self {
-            kind: TypeKind::Array(Box::new(ty), size),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn function(params: Vec<Type>, return_type: Type, varargs: bool) -> Self {
-        Self {
-            kind: TypeKind::Function {
-                params,
-                return_type: Box::new(return_type),
-                varargs,
-            },
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn struct_type(fields: Vec<Type>, packed: bool) -> Self {
-        Self {
-            kind: TypeKind::Struct(fields, packed),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn opaque(name: &str) -> Self {
-        Self {
-            kind: TypeKind::Opaque(name.to_string()),
-            attributes: TypeAttributes::default(),
-        }
-    }
-
-    pub fn with_volatile(mut self) -> Self {
-        self.attributes.volatile = true;
-        self
-    }
-
-    pub fn with_atomic(mut self) -> Self {
-        self.attributes.atomic = true;
-        self
-    }
-
-    pub fn with_address_space(mut self, space: u32) -> Self {
-        self.attributes.address_space = Some(space);
-        self
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::Type;
     use inkwell::context::Context;
 
     #[test]
-    fn test_type_creation() {
+    fn test_type_lowering() {
         let context = Context::create();
-        let i32_type = Type::i32();
-        let ptr_type = Type::ptr(i32_type.clone());
-        let array_type = Type::array(i32_type, Some(10));
-
-        let llvm_i32 = i32_type.into_llvm(&context);
-        assert!(llvm_i32.is_int_type());
-
-        let llvm_ptr = ptr_type.into_llvm(&context);
-        assert!(llvm_ptr.is_pointer_type());
+        let i32_type = Type::I32;
+        let ptr_type = Type::Pointer(Box::new(i32_type.clone()));
+        let array_type = Type::Array {
+            elem_type: Box::new(i32_type.clone()),
+            size: 10,
+        };
 
-        let llvm_array = array_type.into_llvm(&context);
-        assert!(llvm_array.is_array_type());
+        assert!(i32_type.to_llvm_type(&context).is_int_type());
+        assert!(ptr_type.to_llvm_type(&context).is_pointer_type());
+        assert!(array_type.to_llvm_type(&context).is_array_type());
     }
 
     #[test]
@@ -213,7 +76,7 @@ mod tests {
         let context = Context::create();
         let mut registry = TypeRegistry::new();
 
-        let i32_type = Type::i32().into_llvm(&context);
+        let i32_type = Type::I32.to_llvm_type(&context);
         registry.register_type("i32", i32_type).unwrap();
 
         assert!(registry.get_type("i32").is_some());
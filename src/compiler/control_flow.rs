@@ -1,23 +1,95 @@
-use std::collections::{HashMap, HashSet};
 use crate::{
-    ast::{ASTNode, ASTVisitor},
+    ast::{ASTNode, Literal, MatchArm, NodeId},
     error::IoError,
+    span::Span,
     Result,
 };
+use std::collections::{HashMap, HashSet};
+
+/// One step of a basic block. Control flow inside a statement is broken out
+/// into blocks where it decides a `let`, an assignment or a `return`; deeper
+/// nesting, such as an `if` among a call's arguments, stays inside its node.
+#[derive(Debug, Clone)]
+pub enum Statement {
+    /// A node evaluated in full.
+    Node(ASTNode),
+    /// The condition of an `if` or `while`, the scrutinee or a guard of a
+    /// `match`, or the iterable of a `for`, evaluated before branching on it.
+    Branch(ASTNode),
+    /// The value an arm of an `if` or `match` produces for the `Complete`
+    /// statement that follows it.
+    Yield(ASTNode),
+    /// A `let`, assignment or `return` whose value was produced by `Yield`s in
+    /// the preceding blocks.
+    Complete(ASTNode),
+    /// The variables a `for` loop or a match arm, identified by `id`, binds.
+    Bind {
+        id: NodeId,
+        names: Vec<String>,
+        span: Span,
+    },
+    /// The variables declared by the `let`s, loops and match arms in
+    /// `declarations` go out of scope at the end of a block; `span` is its
+    /// closing brace.
+    ScopeEnd {
+        declarations: Vec<NodeId>,
+        span: Span,
+    },
+}
 
 #[derive(Debug)]
 pub struct BasicBlock {
     id: usize,
-    statements: Vec<ASTNode>,
+    statements: Vec<Statement>,
     successors: Vec<usize>,
     predecessors: Vec<usize>,
     dominators: HashSet<usize>,
 }
 
+impl BasicBlock {
+    fn new(id: usize) -> Self {
+        Self {
+            id,
+            statements: Vec::new(),
+            successors: Vec::new(),
+            predecessors: Vec::new(),
+            dominators: HashSet::new(),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    pub fn successors(&self) -> &[usize] {
+        &self.successors
+    }
+
+    pub fn predecessors(&self) -> &[usize] {
+        &self.predecessors
+    }
+}
+
+/// A `while` or `for` loop, whose condition or iterable is evaluated in
+/// `header` and which continues in `after`.
+#[derive(Debug, Clone, Copy)]
+pub struct Loop {
+    pub header: usize,
+    pub after: usize,
+    pub span: Span,
+}
+
 pub struct ControlFlowGraph {
     blocks: HashMap<usize, BasicBlock>,
     entry: usize,
     exit: usize,
+    /// The block the body ends in, which falls through to the exit.
+    end: usize,
+    loops: Vec<Loop>,
     current_id: usize,
 }
 
@@ -27,38 +99,66 @@ impl ControlFlowGraph {
             blocks: HashMap::new(),
             entry: 0,
             exit: 1,
+            end: 0,
+            loops: Vec::new(),
             current_id: 2,
         };
 
         // Create entry and exit blocks
-        cfg.blocks.insert(0, BasicBlock {
-            id: 0,
-            statements: Vec::new(),
-            successors: Vec::new(),
-            predecessors: Vec::new(),
-            dominators: HashSet::new(),
-        });
-
-        cfg.blocks.insert(1, BasicBlock {
-            id: 1,
-            statements: Vec::new(),
-            successors: Vec::new(),
-            predecessors: Vec::new(),
-            dominators: HashSet::new(),
-        });
+        cfg.blocks.insert(0, BasicBlock::new(0));
+        cfg.blocks.insert(1, BasicBlock::new(1));
 
         cfg
     }
 
+    /// Builds the graph of a function body, whose `return`s lead to the exit
+    /// block.
     pub fn analyze_function(&mut self, body: &[ASTNode]) -> Result<()> {
         let mut analyzer = BlockAnalyzer::new(self);
+        analyzer.scopes.push(Vec::new());
         for node in body {
-            analyzer.visit_node(node)?;
+            analyzer.statement(node)?;
         }
+        let last = analyzer.current_block;
+        analyzer.add_edge(last, analyzer.cfg.exit);
+        self.end = last;
         self.compute_dominators()?;
         Ok(())
     }
 
+    pub fn entry(&self) -> usize {
+        self.entry
+    }
+
+    pub fn exit(&self) -> usize {
+        self.exit
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The body's loops, outermost first.
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    pub fn block(&self, id: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&id)
+    }
+
+    /// Every block, in the order they were created, which follows the source.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        (0..self.current_id).filter_map(|id| self.blocks.get(&id))
+    }
+
+    /// Whether every path from the entry to `block` passes through `dominator`.
+    pub fn dominates(&self, dominator: usize, block: usize) -> bool {
+        self.blocks
+            .get(&block)
+            .is_some_and(|block| block.dominators.contains(&dominator))
+    }
+
     fn compute_dominators(&mut self) -> Result<()> {
         // Initialize dominators
         let mut changed = true;
@@ -86,11 +186,12 @@ impl ControlFlowGraph {
 
                 if let Some(block) = self.blocks.get(&id) {
                     let mut new_doms = all_blocks.clone();
-                    
+
                     // Intersect dominators of all predecessors
                     for &pred in &block.predecessors {
                         if let Some(pred_block) = self.blocks.get(&pred) {
-                            new_doms = new_doms.intersection(&pred_block.dominators)
+                            new_doms = new_doms
+                                .intersection(&pred_block.dominators)
                                 .cloned()
                                 .collect();
                         }
@@ -113,10 +214,10 @@ impl ControlFlowGraph {
         Ok(())
     }
 
-    pub fn verify(&self) -> Result<()> {
-        // Verify that all blocks are reachable from entry
+    /// The blocks some path from `start` leads to, `start` included.
+    pub fn reachable_from(&self, start: usize) -> HashSet<usize> {
         let mut visited = HashSet::new();
-        let mut stack = vec![self.entry];
+        let mut stack = vec![start];
 
         while let Some(id) = stack.pop() {
             if !visited.insert(id) {
@@ -127,13 +228,20 @@ impl ControlFlowGraph {
                 stack.extend(&block.successors);
             }
         }
+        visited
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        // Verify that all blocks are reachable from entry
+        let visited = self.reachable_from(self.entry);
 
         // Check if any blocks are unreachable
         for &id in self.blocks.keys() {
             if !visited.contains(&id) {
-                return Err(IoError::validation_error(
-                    format!("Basic block {} is unreachable", id)
-                ));
+                return Err(IoError::validation_error(format!(
+                    "Basic block {} is unreachable",
+                    id
+                )));
             }
         }
 
@@ -141,29 +249,36 @@ impl ControlFlowGraph {
     }
 }
 
+impl Default for ControlFlowGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 struct BlockAnalyzer<'a> {
     cfg: &'a mut ControlFlowGraph,
     current_block: usize,
+    /// The `continue` and `break` targets of each enclosing loop.
+    loops: Vec<(usize, usize)>,
+    /// What each enclosing scope declares, innermost last.
+    scopes: Vec<Vec<NodeId>>,
 }
 
 impl<'a> BlockAnalyzer<'a> {
     fn new(cfg: &'a mut ControlFlowGraph) -> Self {
+        let current_block = cfg.entry;
         Self {
             cfg,
-            current_block: cfg.entry,
+            current_block,
+            loops: Vec::new(),
+            scopes: Vec::new(),
         }
     }
 
     fn create_block(&mut self) -> usize {
         let id = self.cfg.current_id;
         self.cfg.current_id += 1;
-        self.cfg.blocks.insert(id, BasicBlock {
-            id,
-            statements: Vec::new(),
-            successors: Vec::new(),
-            predecessors: Vec::new(),
-            dominators: HashSet::new(),
-        });
+        self.cfg.blocks.insert(id, BasicBlock::new(id));
         id
     }
 
@@ -179,4 +294,422 @@ impl<'a> BlockAnalyzer<'a> {
             }
         }
     }
+
+    fn push(&mut self, statement: Statement) {
+        if let Some(block) = self.cfg.blocks.get_mut(&self.current_block) {
+            block.statements.push(statement);
+        }
+    }
+
+    /// Continues in a new block that `from` branches to.
+    fn branch_from(&mut self, from: usize) -> usize {
+        let block = self.create_block();
+        self.add_edge(from, block);
+        self.current_block = block;
+        block
+    }
+
+    /// Ends the current block with a jump to `target`; what follows is only
+    /// reachable if something else jumps to it.
+    fn jump(&mut self, target: usize) {
+        self.add_edge(self.current_block, target);
+        self.current_block = self.create_block();
+    }
+
+    fn declare(&mut self, id: NodeId) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(id);
+        }
+    }
+
+    /// Lowers `statements` in a scope of their own. When `yields` is set, the
+    /// last one is the value of the enclosing expression.
+    fn scoped(&mut self, statements: &[ASTNode], yields: bool, span: Span) -> Result<()> {
+        self.scopes.push(Vec::new());
+        for (i, statement) in statements.iter().enumerate() {
+            if yields && i + 1 == statements.len() {
+                self.value(statement)?;
+            } else {
+                self.statement(statement)?;
+            }
+        }
+        self.end_scope(span);
+        Ok(())
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        let declarations = self.scopes.pop().unwrap_or_default();
+        if !declarations.is_empty() {
+            self.push(Statement::ScopeEnd {
+                declarations,
+                span: closing(span),
+            });
+        }
+    }
+
+    fn statement(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Let {
+                value: Some(value),
+                id,
+                ..
+            }
+            | ASTNode::LetPattern { value, id, .. } => {
+                self.complete(node, value)?;
+                self.declare(*id);
+            }
+            ASTNode::Let {
+                value: None, id, ..
+            } => {
+                self.push(Statement::Node(node.clone()));
+                self.declare(*id);
+            }
+            ASTNode::Assignment { value, .. } => self.complete(node, value)?,
+            ASTNode::Return { value, .. } => {
+                match value {
+                    Some(value) => self.complete(node, value)?,
+                    None => self.push(Statement::Node(node.clone())),
+                }
+                self.jump(self.cfg.exit);
+            }
+            ASTNode::Break { .. } | ASTNode::Continue { .. } => {
+                let &(continue_target, break_target) = self.loops.last().ok_or_else(|| {
+                    IoError::validation_error("Break or continue outside of a loop")
+                        .with_span(node.span())
+                })?;
+                self.push(Statement::Node(node.clone()));
+                match node {
+                    ASTNode::Break { .. } => self.jump(break_target),
+                    _ => self.jump(continue_target),
+                }
+            }
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+                ..
+            } => self.lower_if(condition, then_branch, else_branch.as_deref(), false, *span)?,
+            ASTNode::Match {
+                scrutinee, arms, ..
+            } => self.lower_match(scrutinee, arms, false)?,
+            ASTNode::Block {
+                statements, span, ..
+            } => self.scoped(statements, false, *span)?,
+            ASTNode::While {
+                condition,
+                body,
+                span,
+                ..
+            } => {
+                let header = self.branch_from(self.current_block);
+                self.push(Statement::Branch(condition.as_ref().clone()));
+                let after = self.create_block();
+                // Only a `break` leaves `while true`.
+                if !is_true(condition) {
+                    self.add_edge(header, after);
+                }
+                self.branch_from(header);
+                self.lower_loop(header, after, None, body, *span)?;
+            }
+            ASTNode::For {
+                pattern,
+                iterable,
+                body,
+                id,
+                span,
+            } => {
+                self.push(Statement::Branch(iterable.as_ref().clone()));
+                let header = self.branch_from(self.current_block);
+                let after = self.create_block();
+                self.add_edge(header, after);
+                self.branch_from(header);
+                let bind = Statement::Bind {
+                    id: *id,
+                    names: pattern
+                        .bindings()
+                        .into_iter()
+                        .map(|(name, _)| name.to_string())
+                        .collect(),
+                    span: *span,
+                };
+                self.lower_loop(header, after, Some(bind), body, *span)?;
+            }
+            // Definitions nested in a body have graphs of their own.
+            ASTNode::Function { .. }
+            | ASTNode::StructDef { .. }
+            | ASTNode::EnumDef { .. }
+            | ASTNode::TraitDef { .. }
+            | ASTNode::Impl { .. } => {}
+            _ => self.push(Statement::Node(node.clone())),
+        }
+        Ok(())
+    }
+
+    /// Lowers a loop body that starts in the current block, jumping back to
+    /// `header` at its end and continuing in `after`. `variable` binds the
+    /// loop variable of a `for`.
+    fn lower_loop(
+        &mut self,
+        header: usize,
+        after: usize,
+        variable: Option<Statement>,
+        body: &[ASTNode],
+        span: Span,
+    ) -> Result<()> {
+        self.cfg.loops.push(Loop {
+            header,
+            after,
+            span,
+        });
+        self.loops.push((header, after));
+        self.scopes.push(Vec::new());
+        if let Some(bind) = variable {
+            if let Statement::Bind { id, .. } = &bind {
+                self.declare(*id);
+            }
+            self.push(bind);
+        }
+        let result = body.iter().try_for_each(|node| self.statement(node));
+        self.end_scope(span);
+        self.loops.pop();
+        result?;
+        self.add_edge(self.current_block, header);
+        self.current_block = after;
+        Ok(())
+    }
+
+    /// A `let`, assignment or `return` of `value`. An `if`, `match` or block
+    /// value is broken out into blocks that yield to a `Complete` statement.
+    fn complete(&mut self, node: &ASTNode, value: &ASTNode) -> Result<()> {
+        if has_control_flow(value) {
+            self.value(value)?;
+            self.push(Statement::Complete(node.clone()));
+        } else {
+            self.push(Statement::Node(node.clone()));
+        }
+        Ok(())
+    }
+
+    /// Lowers `node` as the value of an enclosing expression.
+    fn value(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch: Some(else_branch),
+                span,
+                ..
+            } => self.lower_if(condition, then_branch, Some(else_branch), true, *span),
+            ASTNode::Match {
+                scrutinee, arms, ..
+            } => self.lower_match(scrutinee, arms, true),
+            ASTNode::Block {
+                statements, span, ..
+            } => self.scoped(statements, true, *span),
+            _ if is_statement(node) => self.statement(node),
+            _ => {
+                self.push(Statement::Yield(node.clone()));
+                Ok(())
+            }
+        }
+    }
+
+    fn lower_if(
+        &mut self,
+        condition: &ASTNode,
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+        yields: bool,
+        span: Span,
+    ) -> Result<()> {
+        self.push(Statement::Branch(condition.clone()));
+        let start = self.current_block;
+        let join = self.create_block();
+
+        self.branch_from(start);
+        self.scoped(then_branch, yields, span)?;
+        self.add_edge(self.current_block, join);
+
+        match else_branch {
+            Some(else_branch) => {
+                self.branch_from(start);
+                self.scoped(else_branch, yields, span)?;
+                self.add_edge(self.current_block, join);
+            }
+            None => self.add_edge(start, join),
+        }
+        self.current_block = join;
+        Ok(())
+    }
+
+    /// Every arm is reachable from the scrutinee; a failed guard falls
+    /// through to the next arm.
+    fn lower_match(&mut self, scrutinee: &ASTNode, arms: &[MatchArm], yields: bool) -> Result<()> {
+        self.push(Statement::Branch(scrutinee.clone()));
+        let start = self.current_block;
+        let join = self.create_block();
+        let mut previous_guard = None;
+
+        for arm in arms {
+            let block = self.branch_from(start);
+            if let Some(guard) = previous_guard.take() {
+                self.add_edge(guard, block);
+            }
+            let names: Vec<String> = arm
+                .pattern
+                .bindings()
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect();
+            if names.is_empty() {
+                self.scopes.push(Vec::new());
+            } else {
+                self.scopes.push(vec![arm.id]);
+                self.push(Statement::Bind {
+                    id: arm.id,
+                    names,
+                    span: arm.pattern.span(),
+                });
+            }
+            if let Some(guard) = &arm.guard {
+                self.push(Statement::Branch(guard.clone()));
+                previous_guard = Some(self.current_block);
+                self.branch_from(self.current_block);
+            }
+            match (&arm.body, yields) {
+                (
+                    ASTNode::Block {
+                        statements, span, ..
+                    },
+                    _,
+                ) => self.scoped(statements, yields, *span)?,
+                (body, true) => self.value(body)?,
+                (body, false) => self.statement(body)?,
+            }
+            self.end_scope(arm.span);
+            self.add_edge(self.current_block, join);
+        }
+        if let Some(guard) = previous_guard {
+            self.add_edge(guard, join);
+        }
+        self.current_block = join;
+        Ok(())
+    }
+}
+
+/// Whether `node` as a value decides between branches.
+fn has_control_flow(node: &ASTNode) -> bool {
+    matches!(
+        node,
+        ASTNode::If {
+            else_branch: Some(_),
+            ..
+        } | ASTNode::Match { .. }
+            | ASTNode::Block { .. }
+    )
+}
+
+/// Whether `node` is the literal `true`.
+fn is_true(node: &ASTNode) -> bool {
+    matches!(
+        node,
+        ASTNode::Literal {
+            value: Literal::Boolean(true),
+            ..
+        }
+    )
+}
+
+/// Whether `node` produces no value of its own.
+pub fn is_statement(node: &ASTNode) -> bool {
+    matches!(
+        node,
+        ASTNode::Return { .. }
+            | ASTNode::Break { .. }
+            | ASTNode::Continue { .. }
+            | ASTNode::Let { .. }
+            | ASTNode::LetPattern { .. }
+            | ASTNode::Assignment { .. }
+            | ASTNode::CompoundAssignment { .. }
+            | ASTNode::While { .. }
+            | ASTNode::For { .. }
+            | ASTNode::If { .. }
+    )
+}
+
+/// The last character of `span`, the closing brace of a scope.
+fn closing(span: Span) -> Span {
+    Span::new(
+        span.file_id,
+        span.end.saturating_sub(1).max(span.start),
+        span.end,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+
+    fn graph(body: &str) -> ControlFlowGraph {
+        let source = format!("fn f(c: bool) {{ {} }}", body);
+        let ASTNode::Program(items) = parse_source(&source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::Function { body, .. } = &items[0] else {
+            panic!("expected function, got {:?}", items[0]);
+        };
+        let mut cfg = ControlFlowGraph::new();
+        cfg.analyze_function(body).unwrap();
+        cfg
+    }
+
+    fn kinds(block: &BasicBlock) -> Vec<&'static str> {
+        block
+            .statements()
+            .iter()
+            .map(|statement| match statement {
+                Statement::Node(_) => "node",
+                Statement::Branch(_) => "branch",
+                Statement::Yield(_) => "yield",
+                Statement::Complete(_) => "complete",
+                Statement::Bind { .. } => "bind",
+                Statement::ScopeEnd { .. } => "scope end",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_branches_and_loops_become_blocks() {
+        let cfg = graph(
+            "let x = match c { true => 1, _ => { let y = 2; y } };\n\
+             while c { if c { break; } x += 1; }\n\
+             return;",
+        );
+        let entry = cfg.block(cfg.entry()).unwrap();
+        assert_eq!(kinds(entry), ["branch"]);
+        let [first_arm, second_arm] = entry.successors() else {
+            panic!("expected two successors, got {:?}", entry.successors());
+        };
+        assert_eq!(kinds(cfg.block(*first_arm).unwrap()), ["yield"]);
+        assert_eq!(
+            kinds(cfg.block(*second_arm).unwrap()),
+            ["node", "yield", "scope end"]
+        );
+
+        let join = cfg.block(*first_arm).unwrap().successors()[0];
+        assert_eq!(kinds(cfg.block(join).unwrap()), ["complete"]);
+        let header = cfg.block(join).unwrap().successors()[0];
+        assert_eq!(kinds(cfg.block(header).unwrap()), ["branch"]);
+        // The loop's body and its `break` both lead back out of it.
+        assert!(cfg
+            .blocks()
+            .any(|block| block.successors().contains(&header) && block.id() > header));
+        assert!(cfg.dominates(header, cfg.exit()));
+        assert!(!cfg.dominates(*first_arm, cfg.exit()));
+
+        // Nothing jumps to the code after the `return`.
+        assert!(cfg.verify().is_err());
+    }
 }
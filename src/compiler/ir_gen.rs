@@ -10,7 +10,7 @@ use inkwell::{
 };
 use std::collections::HashMap;
 use crate::{
-    ast::{ASTNode, BinaryOperator},
+    ast::{ASTNode, BinaryOperator, Parameter},
     compiler::control_flow::ControlFlowGraph,
     error::IoError,
     types::Type,
@@ -74,8 +74,14 @@ impl<'ctx> IRGenerator<'ctx> {
 
     pub fn generate_ir(&mut self, ast: &ASTNode, cfg: &ControlFlowGraph) -> Result<()> {
         match ast {
-            ASTNode::Function { name, params, return_type, body, is_async } => {
-                self.generate_function(name, params, return_type, body, cfg, *is_async)
+            ASTNode::Function {
+                name,
+                params,
+                return_type,
+                body,
+                is_async,
+                ..
+            } => self.generate_function(name, params, return_type.as_ref(), body, cfg, *is_async),
             }
             // Handle other nodes...
             _ => Err(IoError::runtime_error("Unsupported node type for IR generation")),
@@ -86,15 +92,15 @@ impl<'ctx> IRGenerator<'ctx> {
         &mut self,
         name: &str,
         params: &[Parameter],
-        return_type: &Option<String>,
+        return_type: Option<&Type>,
         body: &[ASTNode],
         cfg: &ControlFlowGraph,
         is_async: bool,
     ) -> Result<()> {
-        let ret_type = self.get_llvm_type(return_type.as_deref().unwrap_or("unit"))?;
+        let ret_type = self.get_llvm_type(&return_type.unwrap_or(&Type::Void).to_string())?;
         let param_types: Vec<_> = params
             .iter()
-            .map(|p| self.get_llvm_type(&p.type_annotation))
+            .map(|p| self.get_llvm_type(&p.type_annotation.to_string()))
             .collect::<Result<_>>()?;
 
         let fn_type = ret_type.fn_type(&param_types, false);
@@ -221,21 +227,22 @@ impl<'ctx> IRGenerator<'ctx> {
 
     fn generate_statement(&mut self, stmt: &ASTNode) -> Result<()> {
         match stmt {
-            ASTNode::VariableDeclaration { name, type_annotation, value, is_mutable } => {
-                self.generate_variable_declaration(name, type_annotation, value, *is_mutable)
-            }
-            ASTNode::Assignment { target, value } => {
-                self.generate_assignment(target, value)
-            }
-            ASTNode::If { condition, then_branch, else_branch } => {
-                self.generate_if_statement(condition, then_branch, else_branch)
-            }
-            ASTNode::While { condition, body } => {
-                self.generate_while_loop(condition, body)
-            }
-            ASTNode::Try { body, catch_clauses, finally } => {
-                self.generate_try_catch(body, catch_clauses, finally)
-            }
+            ASTNode::Let {
+                name,
+                type_annotation,
+                value,
+                ..
+            } => self.generate_variable_declaration(name, type_annotation.as_ref(), value),
+            ASTNode::Assignment { target, value, .. } => self.generate_assignment(target, value),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => self.generate_if_statement(condition, then_branch, else_branch),
+            ASTNode::While {
+                condition, body, ..
+            } => self.generate_while_loop(condition, body),
             _ => Err(IoError::runtime_error("Unsupported statement type")),
         }
     }
@@ -1,11 +1,20 @@
+pub mod control_flow;
+
 use crate::{
     ast::ASTNode,
+    build::{cfg::Cfg, Target},
     error::IoError,
     lexer::Lexer,
-    parser::Parser,
-    semantic::analyzer::SemanticAnalyzer,
+    semantic::{
+        analyzer::SemanticAnalyzer,
+        deprecated,
+        resolve::{self, Resolution},
+    },
     codegen::llvm::LLVMCodeGen,
-    optimizer::Optimizer,
+    diagnostics::SourceMap,
+    module::ModuleManager,
+    optimizer::{LoopFuser, Optimizer},
+    visitor::{walk_node, Visitable, Visitor},
     Result,
 };
 use inkwell::{
@@ -59,15 +68,15 @@ impl<'ctx> Compiler<'ctx> {
     pub fn compile(&mut self, input: PathBuf, output: PathBuf) -> Result<()> {
         let start = std::time::Instant::now();
 
-        // Parse source file
-        let source = std::fs::read_to_string(&input)?;
-        let ast = self.parse_source(&source)?;
+        // Parse the source file and the modules it imports into one program,
+        // resolved once for every later pass
+        let (ast, resolution) = self.parse_program(&input)?;
 
         // Optimize AST
         let optimized_ast = self.optimize_ast(ast)?;
 
         // Generate LLVM IR
-        let module = self.generate_ir(&optimized_ast)?;
+        let module = self.generate_ir(&optimized_ast, &resolution)?;
 
         // Run optimization passes
         self.run_optimization_passes(&module)?;
@@ -83,12 +92,26 @@ impl<'ctx> Compiler<'ctx> {
         Ok(())
     }
 
-    fn parse_source(&mut self, source: &str) -> Result<ASTNode> {
+    fn parse_program(&mut self, input: &Path) -> Result<(ASTNode, Resolution)> {
         let start = std::time::Instant::now();
-        let mut parser = Parser::new(source);
-        let ast = parser.parse()?;
+        let source = std::fs::read_to_string(input)?;
+        let mut source_map = SourceMap::new();
+        let file_id = source_map.add_file(input.to_path_buf(), source);
+        let target = self
+            .options
+            .target_triple
+            .as_deref()
+            .and_then(Target::from_triple)
+            .unwrap_or(Target::Native);
+        let ast = ModuleManager::for_file(input)
+            .with_cfg(Cfg::new(target))
+            .load_program(file_id, &mut source_map)?;
+        let resolution = resolve::resolve(&ast)?;
+        for warning in deprecated::check(&ast, &resolution)? {
+            eprintln!("{}", warning.report(&source_map));
+        }
         self.metrics.parse_time = start.elapsed();
-        Ok(ast)
+        Ok((ast, resolution))
     }
 
     fn optimize_ast(&mut self, ast: ASTNode) -> Result<ASTNode> {
@@ -96,16 +119,20 @@ impl<'ctx> Compiler<'ctx> {
         
         // Constant folding
         let mut folder = ConstantFolder::new();
-        let ast = folder.fold(&ast)?;
+        let ast = folder.fold(ast)?;
 
         // Constant propagation
         let mut propagator = ConstantPropagator::new();
-        let ast = propagator.optimize(&ast)?;
+        let ast = propagator.optimize(ast)?;
 
         // Dead code elimination
         let mut eliminator = DeadCodeEliminator::new();
         let ast = eliminator.eliminate(&ast)?;
 
+        // Loops over `map` and `filter` run the closures themselves
+        let mut fuser = LoopFuser::new();
+        let ast = fuser.fuse(ast)?;
+
         self.metrics.optimization_time = start.elapsed();
         self.metrics.optimized_nodes = self.count_ast_nodes(&ast);
         
@@ -199,64 +226,10 @@ impl<'ctx> Compiler<'ctx> {
     }
 
     fn count_ast_nodes(&self, ast: &ASTNode) -> usize {
-        match ast {
-            ASTNode::Program(nodes) => {
-                1 + nodes.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>()
-            }
-            ASTNode::Function { body, .. } => {
-                1 + body.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>()
-            }
-            ASTNode::BinaryOp { left, right, .. } => {
-                1 + self.count_ast_nodes(left) + self.count_ast_nodes(right)
-            }
-            ASTNode::UnaryOp { expr, .. } => {
-                1 + self.count_ast_nodes(expr)
-            }
-            ASTNode::If { condition, then_branch, else_branch } => {
-                1 + self.count_ast_nodes(condition) 
-                  + then_branch.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>()
-                  + else_branch.as_ref().map_or(0, |nodes| 
-                      nodes.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>())
-            }
-            ASTNode::While { condition, body } => {
-                1 + self.count_ast_nodes(condition)
-                  + body.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>()
-            }
-            ASTNode::For { init, condition, update, body } => {
-                1 + self.count_ast_nodes(init)
-                  + self.count_ast_nodes(condition)
-                  + self.count_ast_nodes(update)
-                  + body.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>()
-            }
-            ASTNode::Call { args, .. } => {
-                1 + args.iter().map(|arg| self.count_ast_nodes(arg)).sum::<usize>()
-            }
-            ASTNode::Return(expr) => {
-                1 + expr.as_ref().map_or(0, |e| self.count_ast_nodes(e))
-            }
-            ASTNode::Block(stmts) => {
-                1 + stmts.iter().map(|stmt| self.count_ast_nodes(stmt)).sum::<usize>()
-            }
-            ASTNode::VariableDeclaration { initializer, .. } => {
-                1 + initializer.as_ref().map_or(0, |init| self.count_ast_nodes(init))
-            }
-            ASTNode::Assignment { value, .. } => {
-                1 + self.count_ast_nodes(value)
-            }
-            ASTNode::Try { body, catch_clauses, finally } => {
-                1 + body.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>()
-                  + catch_clauses.iter().map(|catch| self.count_ast_nodes(&catch.body)).sum::<usize>()
-                  + finally.as_ref().map_or(0, |nodes| 
-                      nodes.iter().map(|node| self.count_ast_nodes(node)).sum::<usize>())
-            }
-            ASTNode::IntegerLiteral(_) |
-            ASTNode::FloatLiteral(_) |
-            ASTNode::StringLiteral(_) |
-            ASTNode::BooleanLiteral(_) |
-            ASTNode::Identifier(_) |
-            ASTNode::Break |
-            ASTNode::Continue => 1,
-        }
+        let mut counter = NodeCounter::default();
+        // Counting cannot fail.
+        let _ = ast.accept(&mut counter);
+        counter.count
     }
 
     // Builder-style configuration methods
@@ -324,3 +297,17 @@ mod tests {
         Ok(())
     }
 }
+
+#[derive(Default)]
+struct NodeCounter {
+    count: usize,
+}
+
+impl Visitor for NodeCounter {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        self.count += 1;
+        walk_node(self, node)
+    }
+}
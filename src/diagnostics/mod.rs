@@ -1,5 +1,7 @@
 use crate::error::IoError;
+use crate::span::{ExpansionId, FileId, Span};
 use colored::*;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
@@ -15,7 +17,10 @@ pub struct Diagnostic {
     pub level: DiagnosticLevel,
     pub message: String,
     pub location: Option<SourceLocation>,
+    pub span: Option<Span>,
     pub hints: Vec<String>,
+    /// Secondary locations, each shown with its own snippet.
+    pub notes: Vec<(Span, String)>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -31,20 +36,64 @@ impl Diagnostic {
             level: DiagnosticLevel::Error,
             message: message.into(),
             location: None,
+            span: None,
             hints: Vec::new(),
+            notes: Vec::new(),
         }
     }
 
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            level: DiagnosticLevel::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    /// Builds an error diagnostic from a compiler error, keeping its span and notes.
+    pub fn from_error(error: &IoError) -> Self {
+        let mut diagnostic = Self::error(error.message());
+        diagnostic.span = error.span();
+        diagnostic.notes = error.notes().to_vec();
+        diagnostic
+    }
+
+    /// The compiler error an error diagnostic stands for, keeping its span and
+    /// notes.
+    pub fn into_error(self) -> IoError {
+        let mut error = IoError::validation_error(self.message);
+        if let Some(span) = self.span {
+            error = error.with_span(span);
+        }
+        for (span, note) in self.notes {
+            error = error.with_note(span, note);
+        }
+        error
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self.level, DiagnosticLevel::Error)
+    }
+
     pub fn with_location(mut self, location: SourceLocation) -> Self {
         self.location = Some(location);
         self
     }
 
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
     pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
         self.hints.push(hint.into());
         self
     }
 
+    pub fn with_note(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.notes.push((span, message.into()));
+        self
+    }
+
     pub fn report(&self, source_map: &SourceMap) -> String {
         let mut output = String::new();
 
@@ -56,22 +105,22 @@ impl Diagnostic {
 
         output.push_str(&format!("{}: {}\n", prefix, self.message));
 
-        if let Some(location) = &self.location {
-            if let Some(source) = source_map.get_source(&location.file) {
-                let line = source.get_line(location.line);
-                output.push_str(&format!(
-                    " --> {}:{}:{}\n",
-                    location.file.display(),
-                    location.line,
-                    location.column
-                ));
-                output.push_str(&format!("  |\n"));
-                output.push_str(&format!("{:3} | {}\n", location.line, line));
-                output.push_str(&format!(
-                    "  | {}{}",
-                    " ".repeat(location.column),
-                    "^".repeat(location.length).green()
-                ));
+        let location = self
+            .location
+            .clone()
+            .or_else(|| self.span.and_then(|span| source_map.resolve(span)));
+
+        if let Some(location) = &location {
+            output.push_str(&snippet(location, source_map));
+        }
+
+        // Code a macro produced is reported with the invocations it came from.
+        let backtrace = self.span.map(|span| source_map.backtrace(span));
+        for (span, note) in self.notes.iter().chain(backtrace.iter().flatten()) {
+            output.push_str(&format!("\n{}: {}", "note".bold(), note));
+            if let Some(location) = source_map.resolve(*span) {
+                output.push('\n');
+                output.push_str(&snippet(&location, source_map));
             }
         }
 
@@ -83,35 +132,132 @@ impl Diagnostic {
     }
 }
 
+/// The `--> file:line:column` header and the underlined source line.
+fn snippet(location: &SourceLocation, source_map: &SourceMap) -> String {
+    let Some(source) = source_map.get_source(&location.file) else {
+        return String::new();
+    };
+    let line = source.get_line(location.line);
+    let gutter = " ".repeat(location.line.to_string().len());
+    // Never underline past the end of the reported line.
+    let available = line.chars().count().saturating_sub(location.column - 1);
+    let length = location.length.clamp(1, available.max(1));
+    format!(
+        "{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+        gutter,
+        location.file.display(),
+        location.line,
+        location.column,
+        gutter,
+        location.line,
+        line,
+        gutter,
+        " ".repeat(location.column - 1),
+        "^".repeat(length).green()
+    )
+}
+
+/// One invocation of a macro, from which the tokens of its expansion came.
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub macro_name: String,
+    /// The whole invocation, `name!(...)`.
+    pub call_site: Span,
+}
+
+#[derive(Default)]
 pub struct SourceMap {
     sources: HashMap<PathBuf, Source>,
+    files: Vec<PathBuf>,
+    /// Expansion `ExpansionId(n)` is at index `n - 1`.
+    expansions: Vec<Expansion>,
 }
 
 impl SourceMap {
     pub fn new() -> Self {
         Self {
             sources: HashMap::new(),
+            files: Vec::new(),
+            expansions: Vec::new(),
         }
     }
 
-    pub fn add_file(&mut self, path: PathBuf, content: String) {
+    /// Registers a file and returns the id its spans should carry.
+    pub fn add_file(&mut self, path: PathBuf, content: String) -> FileId {
+        if let Some(index) = self.files.iter().position(|p| *p == path) {
+            self.sources.insert(path, Source::new(content));
+            return FileId(index as u32);
+        }
+        let file_id = FileId(self.files.len() as u32);
+        self.files.push(path.clone());
         self.sources.insert(path, Source::new(content));
+        file_id
     }
 
     pub fn get_source(&self, path: &PathBuf) -> Option<&Source> {
         self.sources.get(path)
     }
+
+    pub fn path(&self, file_id: FileId) -> Option<&PathBuf> {
+        self.files.get(file_id.0 as usize)
+    }
+
+    /// Registers a macro expansion and returns the id its tokens' spans carry.
+    pub fn add_expansion(&mut self, expansion: Expansion) -> ExpansionId {
+        self.expansions.push(expansion);
+        ExpansionId(self.expansions.len() as u32)
+    }
+
+    pub fn expansion(&self, id: ExpansionId) -> Option<&Expansion> {
+        let index = id.0.checked_sub(1)?;
+        self.expansions.get(index as usize)
+    }
+
+    /// Notes pointing at the macro invocations `span` was expanded from,
+    /// innermost first.
+    pub fn backtrace(&self, span: Span) -> Vec<(Span, String)> {
+        let mut notes = Vec::new();
+        let mut expansion = span.expansion;
+        while let Some(Expansion {
+            macro_name,
+            call_site,
+        }) = self.expansion(expansion)
+        {
+            notes.push((
+                *call_site,
+                format!("in this expansion of `{}!`", macro_name),
+            ));
+            expansion = call_site.expansion;
+        }
+        notes
+    }
+
+    /// Resolves a byte span to a 1-based line/column location.
+    pub fn resolve(&self, span: Span) -> Option<SourceLocation> {
+        let path = self.path(span.file_id)?;
+        let source = self.sources.get(path)?;
+        let (line, column) = source.line_column(span.start);
+        let end = span.end.min(source.content.len());
+        let start = span.start.min(end);
+        Some(SourceLocation {
+            file: path.clone(),
+            line,
+            column,
+            length: source.content[start..end].chars().count(),
+        })
+    }
 }
 
+#[derive(Debug)]
 pub struct Source {
     content: String,
-    lines: Vec<usize>, // Line start positions
+    lines: Vec<usize>, // Line start byte offsets
 }
 
 impl Source {
     pub fn new(content: String) -> Self {
         let mut lines = vec![0];
-        for (i, c) in content.chars().enumerate() {
+        for (i, c) in content.char_indices() {
             if c == '\n' {
                 lines.push(i + 1);
             }
@@ -119,9 +265,39 @@ impl Source {
         Self { content, lines }
     }
 
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
     pub fn get_line(&self, line: usize) -> &str {
         let start = self.lines[line - 1];
         let end = self.lines.get(line).copied().unwrap_or(self.content.len());
-        &self.content[start..end].trim_end()
+        self.content[start..end].trim_end()
+    }
+
+    /// 1-based line and column (in characters) of a byte offset.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.content.len());
+        let line_idx = match self.lines.binary_search(&offset) {
+            Ok(exact) => exact,
+            Err(insert) => insert - 1,
+        };
+        let column = self.content[self.lines[line_idx]..offset].chars().count() + 1;
+        (line_idx + 1, column)
+    }
+
+    /// The byte offset of a 1-based line and column (in characters), clamped to
+    /// the end of the line.
+    pub fn offset(&self, line: usize, column: usize) -> usize {
+        let Some(&start) = self.lines.get(line.saturating_sub(1)) else {
+            return self.content.len();
+        };
+        let text = &self.content[start..];
+        let line_len = text.find('\n').unwrap_or(text.len());
+        let column = text[..line_len]
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(line_len, |(i, _)| i);
+        start + column
     }
 }
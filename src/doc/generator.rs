@@ -1,5 +1,39 @@
 use std::path::PathBuf;
-use crate::{Result, ast::ASTNode};
+use crate::{Result, ast::ASTNode, lexer::Lexer, token::TokenKind};
+
+/// Documentation attached to a single item via `///` comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemDoc {
+    pub name: String,
+    pub docs: String,
+}
+
+/// Collects `///` comments from `source` and attaches each run of them to the
+/// `fn` or `let` item that immediately follows it.
+pub fn extract_doc_comments(source: &str) -> Result<Vec<ItemDoc>> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let mut items = Vec::new();
+    let mut pending: Vec<String> = Vec::new();
+    let mut iter = tokens.iter().peekable();
+
+    while let Some(token) = iter.next() {
+        match token.kind {
+            TokenKind::DocComment => pending.push(token.lexeme.clone()),
+            TokenKind::Function | TokenKind::Let if !pending.is_empty() => {
+                if let Some(name) = iter.next_if(|t| t.kind == TokenKind::Identifier) {
+                    items.push(ItemDoc {
+                        name: name.lexeme.clone(),
+                        docs: pending.join("\n"),
+                    });
+                }
+                pending.clear();
+            }
+            _ => pending.clear(),
+        }
+    }
+
+    Ok(items)
+}
 
 pub struct DocumentationGenerator {
     output_dir: PathBuf,
@@ -1,8 +1,11 @@
+use crate::span::Span;
 use std::{fmt, io};
 
+pub mod handler;
+
 pub type Result<T> = std::result::Result<T, IoError>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ErrorKind {
     TypeError,
     RuntimeError,
@@ -29,13 +32,69 @@ pub enum ErrorKind {
 pub struct IoError {
     kind: ErrorKind,
     message: String,
+    span: Option<Span>,
+    /// Secondary locations that explain the error, e.g. where a type was inferred.
+    notes: Vec<(Span, String)>,
 }
 
 impl IoError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn notes(&self) -> &[(Span, String)] {
+        &self.notes
+    }
+
+    /// Attaches the source span the error refers to.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Adds a secondary location with a message explaining its part in the error.
+    pub fn with_note(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.notes.push((span, message.into()));
+        self
+    }
+
+    /// Replaces the message, keeping the kind and span.
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// Attaches `span` only if the error does not already point somewhere more precise.
+    pub fn or_span(mut self, span: Span) -> Self {
+        if self.span.is_none() && !span.is_dummy() {
+            self.span = Some(span);
+        }
+        self
+    }
+
     pub fn lexer_error(position: usize, message: impl Into<String>) -> Self {
         Self {
             kind: ErrorKind::LexerError,
             message: format!("Lexer error at position {}: {}", position, message.into()),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -43,6 +102,8 @@ impl IoError {
         Self {
             kind: ErrorKind::ParserError,
             message: message.into(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -50,6 +111,8 @@ impl IoError {
         Self {
             kind: ErrorKind::TypeError,
             message: message.into(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -57,6 +120,8 @@ impl IoError {
         Self {
             kind: ErrorKind::RuntimeError,
             message: message.into(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -64,6 +129,26 @@ impl IoError {
         Self {
             kind: ErrorKind::CodegenError,
             message: message.into(),
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn validation_error(message: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::ValidationError,
+            message: message.into(),
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn module_error(message: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::ModuleNotFound,
+            message: message.into(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -72,6 +157,8 @@ impl IoError {
         Self {
             kind: ErrorKind::RuntimeError,
             message: "Stack overflow".into(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -79,6 +166,8 @@ impl IoError {
         Self {
             kind: ErrorKind::RuntimeError,
             message: "Out of memory".into(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 
@@ -86,6 +175,8 @@ impl IoError {
         Self {
             kind: ErrorKind::RuntimeError,
             message: format!("Deadlock: {}", msg.into()),
+            span: None,
+            notes: Vec::new(),
         }
     }
 }
@@ -95,6 +186,8 @@ impl From<io::Error> for IoError {
         IoError {
             kind: ErrorKind::Io,
             message: err.to_string(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 }
@@ -104,6 +197,8 @@ impl From<inkwell::builder::BuilderError> for IoError {
         Self {
             kind: ErrorKind::BuilderError,
             message: err.to_string(),
+            span: None,
+            notes: Vec::new(),
         }
     }
 }
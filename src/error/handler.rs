@@ -1,14 +1,16 @@
 use std::sync::Arc;
 use parking_lot::RwLock;
-use crate::error::IoError;
+use crate::error::{ErrorKind, IoError};
+use crate::token::TokenKind;
+use crate::types::Type;
 use std::collections::HashMap;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ErrorContext {
-    file: String,
-    line: usize,
-    column: usize,
-    source_line: String,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub source_line: String,
 }
 
 #[derive(Debug)]
@@ -18,6 +20,12 @@ pub struct ErrorHandler {
     recovery_strategy: Option<RecoveryStrategy>,
 }
 
+impl Default for ErrorHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ErrorHandler {
     pub fn new() -> Self {
         Self {
@@ -41,97 +49,16 @@ impl ErrorHandler {
 
         let contexts = self.contexts.read();
         if let Some(context) = contexts.last() {
-            match &error {
-                IoError::LexerError { position, message } => {
-                    IoError::LexerError {
-                        position: *position,
-                        message: format!(
-                            "{}\nIn file {} at line {}:{}\n{}\n{}^",
-                            message,
-                            context.file,
-                            context.line,
-                            context.column,
-                            context.source_line,
-                            " ".repeat(context.column)
-                        ),
-                    }
-                }
-                IoError::ParserError { span, message } => {
-                    IoError::ParserError {
-                        span: *span,
-                        message: format!(
-                            "{}\nIn file {} at line {}:{}\n{}\n{}^",
-                            message,
-                            context.file,
-                            context.line,
-                            context.column,
-                            context.source_line,
-                            " ".repeat(context.column)
-                        ),
-                    }
-                }
-                IoError::TypeError { expected, found, span } => {
-                    IoError::TypeError {
-                        expected: expected.clone(),
-                        found: found.clone(),
-                        span: *span,
-                        message: format!(
-                            "Type mismatch at {}:{}:{}\nExpected: {}\nFound: {}\n{}\n{}^",
-                            context.file,
-                            context.line,
-                            context.column,
-                            expected,
-                            found,
-                            context.source_line,
-                            " ".repeat(context.column)
-                        ),
-                    }
-                }
-                IoError::RuntimeError { message, stack_trace } => {
-                    IoError::RuntimeError {
-                        message: format!(
-                            "{}\nAt {}:{}:{}\n{}\n{}^",
-                            message,
-                            context.file,
-                            context.line,
-                            context.column,
-                            context.source_line,
-                            " ".repeat(context.column)
-                        ),
-                        stack_trace: stack_trace.clone(),
-                    }
-                }
-                IoError::UnresolvedSymbol { name, scope } => {
-                    IoError::UnresolvedSymbol {
-                        name: name.clone(),
-                        scope: scope.clone(),
-                        message: format!(
-                            "Unresolved symbol '{}' in scope '{}'\nAt {}:{}:{}\n{}\n{}^",
-                            name,
-                            scope,
-                            context.file,
-                            context.line,
-                            context.column,
-                            context.source_line,
-                            " ".repeat(context.column)
-                        ),
-                    }
-                }
-                IoError::ValidationError { code, details } => {
-                    IoError::ValidationError {
-                        code: *code,
-                        details: format!(
-                            "{}\nAt {}:{}:{}\n{}\n{}^",
-                            details,
-                            context.file,
-                            context.line,
-                            context.column,
-                            context.source_line,
-                            " ".repeat(context.column)
-                        ),
-                    }
-                }
-            }
+            let message = format!(
+                "{}\nIn file {} at line {}:{}\n{}\n{}^",
+                error.message(),
+                context.file,
+                context.line,
+                context.column,
+                context.source_line,
+                " ".repeat(context.column)
+            );
+            error.with_message(message)
         } else {
             error
         }
@@ -147,92 +74,19 @@ impl ErrorHandler {
         }
 
         if let Some(context) = contexts.last() {
-            match &error {
-                IoError::LexerError { position, message } => {
-                    let location = self.get_error_location(context, *position);
-                    IoError::LexerError {
-                        position: *position,
-                        message: format!(
-                            "{}\n{}\nAt {}:{}:{}\n{}\n{}^{}",
-                            message,
-                            enhanced_message,
-                            context.file,
-                            location.line,
-                            location.column,
-                            self.get_context_lines(context, location.line),
-                            " ".repeat(location.column),
-                            if chain.is_empty() { "" } else { "\nError chain:" }
-                        ),
-                    }
-                }
-                IoError::ParserError { span, message } => {
-                    let location = self.get_error_location(context, span.start);
-                    IoError::ParserError {
-                        span: *span,
-                        message: format!(
-                            "{}\n{}\nAt {}:{}:{}\n{}\n{}^",
-                            message,
-                            enhanced_message,
-                            context.file,
-                            location.line,
-                            location.column,
-                            self.get_context_lines(context, location.line),
-                            " ".repeat(location.column)
-                        ),
-                    }
-                }
-                IoError::TypeError { expected, found, span } => {
-                    let location = self.get_error_location(context, span.start);
-                    IoError::TypeError {
-                        expected: expected.clone(),
-                        found: found.clone(),
-                        span: *span,
-                        message: format!(
-                            "Type mismatch\n{}\nAt {}:{}:{}\n{}\n{}^",
-                            enhanced_message,
-                            context.file,
-                            location.line,
-                            location.column,
-                            self.get_context_lines(context, location.line),
-                            " ".repeat(location.column)
-                        ),
-                    }
-                }
-                IoError::RuntimeError { message, stack_trace } => {
-                    IoError::RuntimeError {
-                        message: format!(
-                            "{}\n{}\nStack trace:\n{}",
-                            message,
-                            enhanced_message,
-                            stack_trace.join("\n")
-                        ),
-                        stack_trace: stack_trace.clone(),
-                    }
-                }
-                IoError::UnresolvedSymbol { name, scope } => {
-                    IoError::UnresolvedSymbol {
-                        name: name.clone(),
-                        scope: scope.clone(),
-                        message: format!(
-                            "Symbol '{}' not found in scope '{}'\n{}",
-                            name,
-                            scope,
-                            enhanced_message
-                        ),
-                    }
-                }
-                IoError::ValidationError { code, details } => {
-                    IoError::ValidationError {
-                        code: *code,
-                        details: format!(
-                            "Validation error {}: {}\n{}",
-                            code,
-                            details,
-                            enhanced_message
-                        ),
-                    }
-                }
-            }
+            let position = error.span().map_or(0, |span| span.start);
+            let location = self.get_error_location(context, position);
+            let message = format!(
+                "{}\n{}\nAt {}:{}:{}\n{}\n{}^",
+                error.message(),
+                enhanced_message,
+                context.file,
+                location.line,
+                location.column,
+                self.get_context_lines(context, location.line),
+                " ".repeat(location.column)
+            );
+            error.with_message(message)
         } else {
             error
         }
@@ -309,16 +163,18 @@ impl ErrorHandler {
         error: &IoError,
         strategy: &RecoveryStrategy,
     ) -> Result<RecoveryAction, IoError> {
-        match (error, strategy) {
-            (IoError::LexerError { .. }, RecoveryStrategy::SkipToNextToken) => {
+        match (error.kind(), strategy) {
+            (ErrorKind::LexerError, RecoveryStrategy::SkipToNextToken) => {
                 Ok(RecoveryAction::SkipToken)
             }
-            (IoError::ParserError { .. }, RecoveryStrategy::SynchronizeTo(tokens)) => {
-                Ok(RecoveryAction::SynchronizeTo(tokens.clone()))
-            }
-            (IoError::TypeError { .. }, RecoveryStrategy::UseDefaultType(default_type)) => {
-                Ok(RecoveryAction::UseType(default_type.clone()))
-            }
+            (
+                ErrorKind::ParserError | ErrorKind::SyntaxError,
+                RecoveryStrategy::SynchronizeTo(tokens),
+            ) => Ok(RecoveryAction::SynchronizeTo(tokens.clone())),
+            (
+                ErrorKind::TypeError | ErrorKind::TypeMismatch,
+                RecoveryStrategy::UseDefaultType(default_type),
+            ) => Ok(RecoveryAction::UseType(default_type.clone())),
             _ => Err(IoError::runtime_error("No suitable recovery action found")),
         }
     }
@@ -343,22 +199,12 @@ impl ErrorHandler {
     }
 
     pub fn handle_error_with_recovery(&self, error: IoError) -> Result<RecoveryAction, IoError> {
-        let mut count = self.error_count.write();
-        *count += 1;
+        *self.error_count.write() += 1;
 
         match &self.recovery_strategy {
-            Some(strategy) => match (&error, strategy) {
-                (IoError::LexerError { .. }, RecoveryStrategy::SkipToNextToken) => {
-                    Ok(RecoveryAction::SkipToken)
-                }
-                (IoError::ParserError { .. }, RecoveryStrategy::SynchronizeTo(tokens)) => {
-                    Ok(RecoveryAction::SynchronizeTo(tokens.clone()))
-                }
-                (IoError::TypeError { .. }, RecoveryStrategy::UseDefaultType(default_type)) => {
-                    Ok(RecoveryAction::UseType(default_type.clone()))
-                }
-                _ => Err(error),
-            },
+            Some(strategy) => self
+                .determine_recovery_action(&error, strategy)
+                .map_err(|_| error),
             None => Err(error),
         }
     }
@@ -424,6 +270,7 @@ impl ErrorReport {
                 .as_secs()
         ));
         summary.push_str("==============\n");
+        summary.push_str(&format!("Total errors: {}\n", self.total_errors));
 
         // Print error categories
         summary.push_str("\nError Categories:\n");
@@ -497,12 +344,7 @@ mod tests {
         let mut handler = ErrorHandler::new();
         handler.with_recovery_strategy(RecoveryStrategy::SkipToNextToken);
 
-        let result = handler.handle_error_with_recovery(
-            IoError::LexerError {
-                position: 0,
-                message: "test error".to_string(),
-            }
-        );
+        let result = handler.handle_error_with_recovery(IoError::lexer_error(0, "test error"));
 
         assert!(matches!(result, Ok(RecoveryAction::SkipToken)));
     }
@@ -520,12 +362,10 @@ mod tests {
         handler.push_context(context);
         assert_eq!(handler.error_count(), 0);
 
-        let error = IoError::LexerError {
-            position: 5,
-            message: "unexpected token".to_string(),
-        };
+        let error = IoError::lexer_error(5, "unexpected token");
 
         let enhanced = handler.handle_error(error);
-        assert!(matches!(enhanced, IoError::LexerError { .. }));
+        assert_eq!(enhanced.kind(), ErrorKind::LexerError);
+        assert!(enhanced.message().contains("In file test.io at line 1:5"));
     }
 }
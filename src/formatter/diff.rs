@@ -0,0 +1,122 @@
+//! Line diffs between a file and its formatted form, for `io fmt --check` and
+//! for turning a reformat into minimal editor edits.
+
+/// A run of changed lines: `old_len` lines starting at `old_start` (zero-based) in
+/// the original become `new_lines`, which start at `new_start` in the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_lines: Vec<String>,
+}
+
+/// The hunks that turn `old` into `new`, in order.
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Only the middle that differs needs the quadratic table.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j]: length of the longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = match hunks.last_mut() {
+            Some(hunk) if hunk.old_start + hunk.old_len == prefix + i => hunk,
+            _ => {
+                hunks.push(Hunk {
+                    old_start: prefix + i,
+                    old_len: 0,
+                    new_start: prefix + j,
+                    new_lines: Vec::new(),
+                });
+                hunks.last_mut().expect("hunk was just pushed")
+            }
+        };
+        if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            hunk.new_lines.push(b[j].to_string());
+            j += 1;
+        } else {
+            hunk.old_len += 1;
+            i += 1;
+        }
+    }
+    hunks
+}
+
+/// A unified-style diff of `old` against `new`, without context lines.
+pub fn unified(path: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let mut out = format!("--- {}\n+++ {} (formatted)\n", path, path);
+    for hunk in hunks(old, new) {
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk.old_start + 1,
+            hunk.old_len,
+            hunk.new_start + 1,
+            hunk.new_lines.len()
+        ));
+        for line in &old_lines[hunk.old_start..hunk.old_start + hunk.old_len] {
+            out.push_str(&format!("-{}\n", line));
+        }
+        for line in &hunk.new_lines {
+            out.push_str(&format!("+{}\n", line));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hunks_cover_only_changed_lines() {
+        let old = "a\nb\nc\nd\n";
+        let new = "a\nB\nc\nd\ne\n";
+        assert_eq!(
+            hunks(old, new),
+            vec![
+                Hunk {
+                    old_start: 1,
+                    old_len: 1,
+                    new_start: 1,
+                    new_lines: vec!["B".to_string()],
+                },
+                Hunk {
+                    old_start: 4,
+                    old_len: 0,
+                    new_start: 4,
+                    new_lines: vec!["e".to_string()],
+                },
+            ]
+        );
+        assert!(hunks(old, old).is_empty());
+    }
+}
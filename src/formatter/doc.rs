@@ -0,0 +1,179 @@
+//! A Wadler-style document algebra. Printers describe the layout as a `Doc`;
+//! `render` decides, group by group, whether it fits on one line or breaks.
+
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Nil,
+    Text(String),
+    /// A space when its group is flat, a newline otherwise.
+    Line,
+    /// Nothing when its group is flat, a newline otherwise.
+    SoftLine,
+    /// Always a newline; forces every enclosing group to break.
+    HardLine,
+    Concat(Vec<Doc>),
+    /// Adds the given number of columns to the indentation after each newline inside.
+    Nest(usize, Box<Doc>),
+    /// Laid out flat if it fits in the remaining width, broken otherwise.
+    Group(Box<Doc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+type Command<'a> = (usize, Mode, &'a Doc);
+
+impl Doc {
+    pub fn text(text: impl Into<String>) -> Doc {
+        Doc::Text(text.into())
+    }
+
+    pub fn concat(docs: impl IntoIterator<Item = Doc>) -> Doc {
+        Doc::Concat(docs.into_iter().collect())
+    }
+
+    pub fn nest(indent: usize, doc: Doc) -> Doc {
+        Doc::Nest(indent, Box::new(doc))
+    }
+
+    pub fn group(doc: Doc) -> Doc {
+        Doc::Group(Box::new(doc))
+    }
+
+    /// `docs` separated by `separator`.
+    pub fn join(docs: impl IntoIterator<Item = Doc>, separator: Doc) -> Doc {
+        let mut parts = Vec::new();
+        for (i, doc) in docs.into_iter().enumerate() {
+            if i > 0 {
+                parts.push(separator.clone());
+            }
+            parts.push(doc);
+        }
+        Doc::Concat(parts)
+    }
+
+    /// Lays the document out in `width` columns. Lines never end in whitespace.
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut stack: Vec<Command> = vec![(0, Mode::Break, self)];
+
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Nil => {}
+                Doc::Text(text) => {
+                    out.push_str(text);
+                    column = match text.rfind('\n') {
+                        Some(newline) => text[newline + 1..].chars().count(),
+                        None => column + text.chars().count(),
+                    };
+                }
+                Doc::Line if mode == Mode::Flat => {
+                    out.push(' ');
+                    column += 1;
+                }
+                Doc::SoftLine if mode == Mode::Flat => {}
+                Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                    out.truncate(out.trim_end_matches(' ').len());
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                Doc::Concat(docs) => {
+                    stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)));
+                }
+                Doc::Nest(extra, doc) => stack.push((indent + extra, mode, doc)),
+                Doc::Group(doc) => {
+                    let flat = (indent, Mode::Flat, &**doc);
+                    let mode = if mode == Mode::Flat
+                        || fits(width as isize - column as isize, flat, &stack)
+                    {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    };
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+
+        out.truncate(out.trim_end_matches(' ').len());
+        out
+    }
+}
+
+/// Whether `next`, followed by the rest of its line from `rest`, fits in `remaining` columns.
+fn fits(mut remaining: isize, next: Command, rest: &[Command]) -> bool {
+    let mut stack = vec![next];
+    let mut rest = rest.iter().rev();
+
+    while remaining >= 0 {
+        let (indent, mode, doc) = match stack.pop() {
+            Some(command) => command,
+            None => match rest.next() {
+                Some(&command) => command,
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Nil => {}
+            Doc::Text(text) => match text.split_once('\n') {
+                Some((first, _)) => return first.chars().count() as isize <= remaining,
+                None => remaining -= text.chars().count() as isize,
+            },
+            Doc::Line if mode == Mode::Flat => remaining -= 1,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::HardLine if mode == Mode::Flat => return false,
+            Doc::Line | Doc::SoftLine | Doc::HardLine => return true,
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Nest(extra, doc) => stack.push((indent + extra, mode, doc)),
+            Doc::Group(doc) => stack.push((indent, mode, doc)),
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[&str]) -> Doc {
+        Doc::group(Doc::concat([
+            Doc::text(format!("{}(", name)),
+            Doc::nest(
+                4,
+                Doc::concat([
+                    Doc::SoftLine,
+                    Doc::join(
+                        args.iter().map(|arg| Doc::text(*arg)),
+                        Doc::concat([Doc::text(","), Doc::Line]),
+                    ),
+                ]),
+            ),
+            Doc::SoftLine,
+            Doc::text(")"),
+        ]))
+    }
+
+    #[test]
+    fn test_group_stays_flat_when_it_fits() {
+        assert_eq!(call("f", &["a", "b"]).render(80), "f(a, b)");
+    }
+
+    #[test]
+    fn test_group_breaks_when_too_wide() {
+        assert_eq!(
+            call("f", &["alpha", "beta"]).render(10),
+            "f(\n    alpha,\n    beta\n)"
+        );
+    }
+
+    #[test]
+    fn test_text_after_group_counts_towards_width() {
+        let doc = Doc::concat([call("f", &["a"]), Doc::text(";;;;")]);
+        assert_eq!(doc.render(7), "f(\n    a\n);;;;");
+    }
+}
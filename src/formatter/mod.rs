@@ -0,0 +1,380 @@
+//! The source formatter behind `io fmt` and the language server's formatting
+//! requests.
+
+mod diff;
+mod doc;
+mod printer;
+
+pub use diff::{hunks, unified, Hunk};
+pub use doc::Doc;
+
+use crate::{ast::ASTNode, error::IoError, lexer::Lexer, parser::Parser, Result};
+use printer::Printer;
+use serde::Deserialize;
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// Project manifest whose `[format]` table configures the formatter.
+pub const MANIFEST_NAME: &str = "io.toml";
+
+/// Layout options, read from the `[format]` table of `io.toml`:
+///
+/// ```toml
+/// [format]
+/// indent_width = 4
+/// max_line_length = 100
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormattingConfig {
+    /// Spaces per indentation level.
+    pub indent_width: usize,
+    /// Lines longer than this are broken wherever the layout allows.
+    pub max_line_length: usize,
+}
+
+impl Default for FormattingConfig {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_line_length: 100,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    format: FormattingConfig,
+}
+
+impl FormattingConfig {
+    /// Configuration from the nearest `io.toml` at or above `path`, or the
+    /// defaults if there is none.
+    pub fn load(path: &Path) -> Result<Self> {
+        let Some(manifest) = find_manifest(path) else {
+            return Ok(Self::default());
+        };
+        let contents = std::fs::read_to_string(&manifest)?;
+        Self::from_manifest(&contents).map_err(|err| {
+            IoError::validation_error(format!("{}: {}", manifest.display(), err.message()))
+        })
+    }
+
+    /// Parses the contents of an `io.toml`; only its `[format]` table is read.
+    pub fn from_manifest(contents: &str) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(contents).map_err(|err| {
+            IoError::validation_error(format!("Invalid {}: {}", MANIFEST_NAME, err))
+        })?;
+        let config = manifest.format;
+        if config.indent_width == 0 || config.max_line_length == 0 {
+            return Err(IoError::validation_error(
+                "indent_width and max_line_length must be positive",
+            ));
+        }
+        Ok(config)
+    }
+}
+
+/// The nearest `io.toml` at or above `path`.
+pub fn find_manifest(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let start = if path.is_dir() {
+        path.as_path()
+    } else {
+        path.parent().unwrap_or(&path)
+    };
+    start
+        .ancestors()
+        .map(|dir| dir.join(MANIFEST_NAME))
+        .find(|manifest| manifest.is_file())
+}
+
+pub struct CodeFormatter {
+    config: FormattingConfig,
+}
+
+impl CodeFormatter {
+    pub fn new(config: &FormattingConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Formats a whole file, keeping its comments. Source with syntax errors is
+    /// rejected rather than partially formatted. Formatting is idempotent:
+    /// formatting the output again returns it unchanged.
+    pub fn format(&self, source: &str) -> Result<String> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let program = Parser::new(tokens.clone().into_iter()).parse_program()?;
+        let items = match &program {
+            ASTNode::Program(items) => items.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+
+        let mut printer = Printer::new(source, &tokens, self.config.indent_width);
+        let mut formatted = printer.program(items).render(self.config.max_line_length);
+        if !formatted.is_empty() {
+            formatted.push('\n');
+        }
+        Ok(formatted)
+    }
+
+    /// The line changes that format `source`. With `lines` (zero-based, inclusive),
+    /// only changes touching those lines are returned, for range formatting.
+    pub fn edits(&self, source: &str, lines: Option<RangeInclusive<usize>>) -> Result<Vec<Hunk>> {
+        let formatted = self.format(source)?;
+        let mut edits = hunks(source, &formatted);
+        if let Some(lines) = lines {
+            edits.retain(|hunk| {
+                hunk.old_start <= *lines.end()
+                    && hunk.old_start + hunk.old_len.max(1) > *lines.start()
+            });
+        }
+        Ok(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        CodeFormatter::new(&FormattingConfig::default())
+            .format(source)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_format_normalizes_layout_and_keeps_comments() {
+        let source = "\
+// Entry point.
+fn main()->int{
+let xs:[int]=[1,2,3]; // small
+  let total = (xs[0]+xs[1])*xs[2];
+
+
+/* block */ if total>=9 {println(\"big\")} else if total==0 {return 0}
+  return total
+}
+fn helper(a:int) {}";
+        let expected = "\
+// Entry point.
+fn main() -> int {
+    let xs: [int] = [1, 2, 3]; // small
+    let total = (xs[0] + xs[1]) * xs[2];
+
+    /* block */
+    if total >= 9 {
+        println(\"big\");
+    } else if total == 0 {
+        return 0;
+    }
+    return total;
+}
+
+fn helper(a: int) {}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        for source in [
+            include_str!("../../examples/hello_world.io"),
+            include_str!("../../examples/calculator.io"),
+            "fn f() {\n    g(a, // first\n      b);\n    // dangling\n}\n",
+            "let x = 0x1F + -(1 - 2) * 3; let r = 0..=x; let y = (a = 1) + f()?;",
+            "struct Pair<A,B>{first:A,second:B}fn map<T,U>(xs:[T],f:fn(T)->U)->[U]{}",
+            "let n = match xs { [] => 0, [x, .., -1] | [x] => x, _ => { // many\n f(xs) } };",
+            "let y = -x as u8 + 255u8; let z = (a + b) as f32 * 1.5f32; let w = (y as i64) as u64;",
+        ] {
+            let once = format(source);
+            assert_eq!(format(&once), once, "not idempotent for:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn test_format_generic_items() {
+        let source = "struct Pair<A,B>{first:A,second:Pair< B ,int >}\n\
+                      fn map<T,U>(xs:[T],f:fn(T)->U)->[U]{}";
+        let expected = "\
+struct Pair<A, B> {
+    first: A,
+    second: Pair<B, int>,
+}
+
+fn map<T, U>(xs: [T], f: fn(T) -> U) -> [U] {}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_format_enums_and_match() {
+        let source = "enum Shape<T>{Circle(T),Rect{w:T,h:T},Empty}\n\
+                      fn area(s:Shape<float>)->float{match s{Shape::Circle(r)=>r*r,\n\
+                      // squares\n\
+                      Shape::Rect{w:w,h:0.0}|Shape::Empty=>0.0,Rect{w,..} if w>1.0=>{w}\n\
+                      _=>area(Shape::Rect{w:1.0,h:2.0})}}";
+        let expected = "\
+enum Shape<T> {
+    Circle(T),
+    Rect { w: T, h: T },
+    Empty,
+}
+
+fn area(s: Shape<float>) -> float {
+    match s {
+        Shape::Circle(r) => r * r,
+        // squares
+        Shape::Rect { w, h: 0.0 } | Shape::Empty => 0.0,
+        Rect { w, .. } if w > 1.0 => {
+            w;
+        }
+        _ => area(Shape::Rect { w: 1.0, h: 2.0 }),
+    }
+}
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn test_format_traits_and_impls() {
+        let source = "trait Shape{fn area(self)->float;\n\n\
+                      // a default\n\
+                      fn scaled(self,k:float)->float{return self.area()*k;}}\n\
+                      impl Shape for Circle{fn area(self)->float{return 3.0;}}\n\
+                      fn total<T:Shape+Named,U>(s:T,all:[dyn Shape])->float{return s.area();}";
+        let expected = "\
+trait Shape {
+    fn area(self) -> float;
+
+    // a default
+    fn scaled(self, k: float) -> float {
+        return self.area() * k;
+    }
+}
+
+impl Shape for Circle {
+    fn area(self) -> float {
+        return 3.0;
+    }
+}
+
+fn total<T: Shape + Named, U>(s: T, all: [dyn Shape]) -> float {
+    return s.area();
+}
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_format_references() {
+        let source = "fn swap(a:&mut int,b:& mut int){let t=*a;keep(& t,&mut *b);}";
+        let expected = "\
+fn swap(a: &mut int, b: &mut int) {
+    let t = *a;
+    keep(&t, &mut *b);
+}
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_format_imports_and_pub_items() {
+        let source = "import net::http::{get,post};import util::log;\n\
+                      pub struct Point{x:int}pub fn origin()->Point{}";
+        let expected = "\
+import net::http::{get, post};
+import util::log;
+
+pub struct Point {
+    x: int,
+}
+
+pub fn origin() -> Point {}
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_format_attributes() {
+        let source = "#![cfg( test )]  #![deprecated]\n\
+                      #[allow( unused_imports )]import util::log;\n\
+                      #[allow(unused_variables,shadowing)] #[allow(complexity)] fn f(x:int){}\n\
+                      #[deprecated(note=\"use `Vec2`\")]struct Point{#[cfg(not(test))]x:int}\n\
+                      enum E{A{#[cfg(any(test,target_os=\"linux\"))]x:int}}";
+        let expected = "\
+#![cfg(test)]
+#![deprecated]
+
+#[allow(unused_imports)]
+import util::log;
+
+#[allow(unused_variables, shadowing)]
+#[allow(complexity)]
+fn f(x: int) {}
+
+#[deprecated(note = \"use `Vec2`\")]
+struct Point {
+    #[cfg(not(test))]
+    x: int,
+}
+
+enum E {
+    A { #[cfg(any(test, target_os = \"linux\"))] x: int },
+}
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_format_closures() {
+        let source = "fn f(){let add=|a,b:int|a+b;let g=move||->int{return n;};apply(|x|x*2);}";
+        let expected = "\
+fn f() {
+    let add = |a, b: int| a + b;
+    let g = move || -> int {
+        return n;
+    };
+    apply(|x| x * 2);
+}
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_long_lines_break_at_configured_width() {
+        let config = FormattingConfig::from_manifest(
+            "[package]\nname = \"demo\"\n\n[format]\nindent_width = 2\nmax_line_length = 30\n",
+        )
+        .unwrap();
+        let formatted = CodeFormatter::new(&config)
+            .format("fn f() { report(first_argument, second_argument); }")
+            .unwrap();
+        assert_eq!(
+            formatted,
+            "fn f() {\n  report(\n    first_argument,\n    second_argument\n  );\n}\n"
+        );
+        assert!(FormattingConfig::from_manifest("[format]\nindent = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_edits_can_be_limited_to_a_range() {
+        let formatter = CodeFormatter::new(&FormattingConfig::default());
+        let source = "let a=1;\nlet b = 2;\nlet c=3;\n";
+        assert_eq!(formatter.edits(source, None).unwrap().len(), 2);
+
+        let edits = formatter.edits(source, Some(2..=2)).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].old_start, 2);
+        assert_eq!(edits[0].new_lines, vec!["let c = 3;".to_string()]);
+    }
+}
@@ -0,0 +1,1191 @@
+//! Lays a parsed program out as a `Doc`. The parser drops comments and
+//! parentheses, so comments are recovered from the gaps between tokens and
+//! parentheses are re-inserted from operator precedence.
+
+use super::doc::Doc;
+use crate::{
+    ast::{
+        ASTNode, Attribute, Field, MatchArm, Parameter, Pattern, PatternFields, TraitMethod, Type,
+        UnaryOperator, Variant, VariantFields,
+    },
+    span::Span,
+    token::{Token, TokenKind},
+};
+
+const ASSIGNMENT_PRECEDENCE: u8 = 0;
+const CAST_PRECEDENCE: u8 = 12;
+const PREFIX_PRECEDENCE: u8 = 13;
+const POSTFIX_PRECEDENCE: u8 = 14;
+const ATOM_PRECEDENCE: u8 = 15;
+
+/// Byte range of a comment in the original source.
+#[derive(Debug, Clone, Copy)]
+struct Comment {
+    start: usize,
+    end: usize,
+}
+
+pub(super) struct Printer<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    comments: Vec<Comment>,
+    /// Comments before this index have been printed.
+    next_comment: usize,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    pub(super) fn new(source: &'a str, tokens: &'a [Token], indent: usize) -> Self {
+        Self {
+            source,
+            tokens,
+            comments: collect_comments(source, tokens),
+            next_comment: 0,
+            indent,
+        }
+    }
+
+    pub(super) fn program(&mut self, items: &[ASTNode]) -> Doc {
+        Doc::concat(self.items(items, 0, self.source.len(), true))
+    }
+
+    /// One statement per line, with the comments found between `start` and `end`
+    /// (the braces around them, or the whole file) kept in place. A blank line in
+    /// the source survives as one blank line.
+    fn items(
+        &mut self,
+        statements: &[ASTNode],
+        start: usize,
+        end: usize,
+        top_level: bool,
+    ) -> Vec<Doc> {
+        let mut parts = Vec::new();
+        let mut last_end = start;
+        let mut after_item = false;
+
+        for statement in statements {
+            let span = statement.span();
+            let is_item = matches!(
+                statement,
+                ASTNode::Function { .. }
+                    | ASTNode::StructDef { .. }
+                    | ASTNode::EnumDef { .. }
+                    | ASTNode::TraitDef { .. }
+                    | ASTNode::Impl { .. }
+                    | ASTNode::ModuleAttributes { .. }
+            );
+            // Top-level functions and type definitions are always set apart by a
+            // blank line.
+            let mut blank = top_level && (is_item || after_item);
+
+            while let Some(comment) = self.take_comment_before(span.start) {
+                self.separate(&mut parts, last_end, comment.start, blank);
+                blank = false;
+                parts.push(Doc::text(self.comment_text(comment)));
+                last_end = comment.end;
+            }
+            self.separate(&mut parts, last_end, span.start, blank);
+            parts.push(self.statement(statement));
+            last_end = span.end;
+
+            // Comments on the statement's last line, or inside an expression, follow it.
+            let mut first = true;
+            while let Some(&comment) = self.comments.get(self.next_comment) {
+                if comment.start >= last_end && self.source[last_end..comment.start].contains('\n')
+                {
+                    break;
+                }
+                self.next_comment += 1;
+                parts.push(if first { Doc::text(" ") } else { Doc::HardLine });
+                parts.push(Doc::text(self.comment_text(comment)));
+                last_end = last_end.max(comment.end);
+                first = false;
+            }
+            after_item = is_item;
+        }
+
+        while let Some(comment) = self.take_comment_before(end) {
+            self.separate(&mut parts, last_end, comment.start, false);
+            parts.push(Doc::text(self.comment_text(comment)));
+            last_end = comment.end;
+        }
+        parts
+    }
+
+    fn separate(&self, parts: &mut Vec<Doc>, from: usize, to: usize, force_blank: bool) {
+        if parts.is_empty() {
+            return;
+        }
+        parts.push(Doc::HardLine);
+        if force_blank || self.has_blank_line(from, to) {
+            parts.push(Doc::HardLine);
+        }
+    }
+
+    fn statement(&mut self, node: &ASTNode) -> Doc {
+        match node {
+            ASTNode::Function {
+                name,
+                type_params,
+                bounds,
+                params,
+                return_type,
+                body,
+                is_async,
+                is_const,
+                is_pub,
+                attributes,
+                span,
+                ..
+            } => {
+                let keyword = match (is_async, is_const) {
+                    (true, _) => "async fn ",
+                    (false, true) => "const fn ",
+                    (false, false) => "fn ",
+                };
+                let head = format!(
+                    "{}{}{}{}",
+                    visibility(*is_pub),
+                    keyword,
+                    name,
+                    type_param_list(type_params, bounds)
+                );
+                let mut parts = attribute_lines(attributes);
+                parts.extend(self.signature(head, params, return_type.as_ref(), *span));
+                parts.push(Doc::text(" "));
+                let open = self.open_brace(span.start);
+                parts.push(self.block(body, open));
+                Doc::concat(parts)
+            }
+            ASTNode::StructDef {
+                name,
+                type_params,
+                fields,
+                is_pub,
+                attributes,
+                ..
+            } => {
+                let mut parts = attribute_lines(attributes);
+                parts.push(Doc::text(visibility(*is_pub)));
+                parts.push(self.struct_def(name, type_params, fields));
+                Doc::concat(parts)
+            }
+            ASTNode::TraitDef {
+                name,
+                type_params,
+                methods,
+                is_pub,
+                span,
+                ..
+            } => Doc::concat([
+                Doc::text(visibility(*is_pub)),
+                self.trait_def(name, type_params, methods, *span),
+            ]),
+            ASTNode::Import {
+                path,
+                items,
+                attributes,
+                ..
+            } => {
+                let items = match items.as_slice() {
+                    [item] => item.clone(),
+                    items => format!("{{{}}}", items.join(", ")),
+                };
+                let mut parts = attribute_lines(attributes);
+                parts.push(Doc::text(format!("import {}::{};", path.join("::"), items)));
+                Doc::concat(parts)
+            }
+            ASTNode::ModuleAttributes { attributes, .. } => {
+                let mut parts = Vec::new();
+                for attribute in attributes {
+                    if !parts.is_empty() {
+                        parts.push(Doc::HardLine);
+                    }
+                    parts.push(Doc::text(attribute.to_string().replacen('#', "#!", 1)));
+                }
+                Doc::concat(parts)
+            }
+            ASTNode::Impl {
+                trait_name,
+                trait_args,
+                self_type,
+                methods,
+                span,
+                ..
+            } => {
+                let head = match trait_name {
+                    Some(trait_name) => {
+                        let trait_ref = match trait_args.as_slice() {
+                            [] => Type::Named(trait_name.clone()),
+                            args => Type::Generic {
+                                name: trait_name.clone(),
+                                args: args.to_vec(),
+                            },
+                        };
+                        let trait_ref = self.annotation(
+                            &trait_ref,
+                            TokenKind::Impl,
+                            TokenKind::For,
+                            span.start,
+                        );
+                        let self_type = self.annotation(
+                            self_type,
+                            TokenKind::For,
+                            TokenKind::LeftBrace,
+                            span.start,
+                        );
+                        format!("impl {} for {} ", trait_ref, self_type)
+                    }
+                    None => {
+                        let self_type = self.annotation(
+                            self_type,
+                            TokenKind::Impl,
+                            TokenKind::LeftBrace,
+                            span.start,
+                        );
+                        format!("impl {} ", self_type)
+                    }
+                };
+                let open = self.open_brace(span.start);
+                Doc::concat([Doc::text(head), self.block(methods, open)])
+            }
+            ASTNode::EnumDef {
+                name,
+                type_params,
+                variants,
+                is_pub,
+                ..
+            } => Doc::concat([
+                Doc::text(visibility(*is_pub)),
+                self.enum_def(name, type_params, variants),
+            ]),
+            ASTNode::Match {
+                scrutinee,
+                arms,
+                span,
+                ..
+            } => self.match_expr(scrutinee, arms, *span),
+            ASTNode::Global {
+                name,
+                type_annotation,
+                value,
+                is_pub,
+                span,
+                ..
+            } => {
+                let mut head = format!("{}const {}", visibility(*is_pub), name);
+                if let Some(ty) = type_annotation {
+                    let annotation =
+                        self.annotation(ty, TokenKind::Colon, TokenKind::Equal, span.start);
+                    head.push_str(&format!(": {}", annotation));
+                }
+                head.push_str(" = ");
+                Doc::concat([
+                    Doc::text(head),
+                    self.expr(value, ASSIGNMENT_PRECEDENCE),
+                    Doc::text(";"),
+                ])
+            }
+            ASTNode::Let {
+                name,
+                type_annotation,
+                value,
+                span,
+                ..
+            } => {
+                let mut head = format!("let {}", name);
+                if let Some(ty) = type_annotation {
+                    let until = match value {
+                        Some(_) => TokenKind::Equal,
+                        None => TokenKind::Semicolon,
+                    };
+                    let annotation = self.annotation(ty, TokenKind::Colon, until, span.start);
+                    head.push_str(&format!(": {}", annotation));
+                }
+                let Some(value) = value else {
+                    return Doc::text(format!("{};", head));
+                };
+                head.push_str(" = ");
+                Doc::concat([
+                    Doc::text(head),
+                    self.expr(value, ASSIGNMENT_PRECEDENCE),
+                    Doc::text(";"),
+                ])
+            }
+            ASTNode::LetPattern {
+                pattern,
+                type_annotation,
+                value,
+                ..
+            } => {
+                let mut head = format!("let {}", self.pattern(pattern));
+                if let Some(ty) = type_annotation {
+                    let from = pattern.span().end;
+                    let annotation = self.annotation(ty, TokenKind::Colon, TokenKind::Equal, from);
+                    head.push_str(&format!(": {}", annotation));
+                }
+                head.push_str(" = ");
+                Doc::concat([
+                    Doc::text(head),
+                    self.expr(value, ASSIGNMENT_PRECEDENCE),
+                    Doc::text(";"),
+                ])
+            }
+            ASTNode::Return { value: None, .. } => Doc::text("return;"),
+            ASTNode::Return {
+                value: Some(value), ..
+            } => Doc::concat([
+                Doc::text("return "),
+                self.expr(value, ASSIGNMENT_PRECEDENCE),
+                Doc::text(";"),
+            ]),
+            ASTNode::Break { .. } => Doc::text("break;"),
+            ASTNode::Continue { .. } => Doc::text("continue;"),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => self.if_statement(condition, then_branch, else_branch.as_deref()),
+            ASTNode::While {
+                condition, body, ..
+            } => {
+                let open = self.open_brace(condition.span().end);
+                Doc::concat([
+                    Doc::text("while "),
+                    self.expr(condition, ASSIGNMENT_PRECEDENCE),
+                    Doc::text(" "),
+                    self.block(body, open),
+                ])
+            }
+            ASTNode::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                let open = self.open_brace(iterable.span().end);
+                Doc::concat([
+                    Doc::text(format!("for {} in ", self.pattern(pattern))),
+                    self.expr(iterable, ASSIGNMENT_PRECEDENCE),
+                    Doc::text(" "),
+                    self.block(body, open),
+                ])
+            }
+            ASTNode::Block {
+                statements, span, ..
+            } => {
+                let open = self.open_brace(span.start);
+                self.block(statements, open)
+            }
+            ASTNode::Program(items) => self.program(items),
+            expr => Doc::concat([self.expr(expr, ASSIGNMENT_PRECEDENCE), Doc::text(";")]),
+        }
+    }
+
+    fn if_statement(
+        &mut self,
+        condition: &ASTNode,
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+    ) -> Doc {
+        let open = self.open_brace(condition.span().end);
+        let close = self.closing_brace(then_branch, open);
+        let mut parts = vec![
+            Doc::text("if "),
+            self.expr(condition, ASSIGNMENT_PRECEDENCE),
+            Doc::text(" "),
+            self.block(then_branch, open),
+        ];
+
+        if let Some(else_branch) = else_branch {
+            parts.push(Doc::text(" else "));
+            let else_if = self
+                .token_index(TokenKind::Else, close)
+                .and_then(|i| self.tokens.get(i + 1))
+                .is_some_and(|token| token.kind == TokenKind::If);
+            match else_branch {
+                [chained @ ASTNode::If { .. }] if else_if => parts.push(self.statement(chained)),
+                _ => {
+                    let open = self.open_brace(close + 1);
+                    parts.push(self.block(else_branch, open));
+                }
+            }
+        }
+        Doc::concat(parts)
+    }
+
+    /// `{ statements }`, where `open` is just past the `{`.
+    fn block(&mut self, statements: &[ASTNode], open: usize) -> Doc {
+        let close = self.closing_brace(statements, open);
+        let body = self.items(statements, open, close, false);
+        if body.is_empty() {
+            return Doc::text("{}");
+        }
+        Doc::concat([
+            Doc::text("{"),
+            Doc::nest(
+                self.indent,
+                Doc::concat(std::iter::once(Doc::HardLine).chain(body)),
+            ),
+            Doc::HardLine,
+            Doc::text("}"),
+        ])
+    }
+
+    fn expr(&mut self, node: &ASTNode, min_precedence: u8) -> Doc {
+        let doc = self.expr_unparenthesized(node);
+        if precedence(node) < min_precedence {
+            Doc::concat([Doc::text("("), doc, Doc::text(")")])
+        } else {
+            doc
+        }
+    }
+
+    fn expr_unparenthesized(&mut self, node: &ASTNode) -> Doc {
+        match node {
+            ASTNode::Literal { value, span, .. } => match self.slice(*span) {
+                // Keep literals as written: `0xFF`, `1_000`, escapes.
+                Some(text) => Doc::text(text),
+                None => Doc::text(value.to_string()),
+            },
+            ASTNode::Identifier { name, .. } => Doc::text(name.as_str()),
+            ASTNode::BinaryOp {
+                op, left, right, ..
+            } if op.is_range() => Doc::concat([
+                self.expr(left, op.precedence() + 1),
+                Doc::text(op.to_string()),
+                self.expr(right, op.precedence() + 1),
+            ]),
+            ASTNode::BinaryOp { op, .. } => {
+                // A left-leaning chain at one precedence, `a + b - c`, breaks as a unit.
+                let level = op.precedence();
+                let mut current = node;
+                let mut rest = Vec::new();
+                while let ASTNode::BinaryOp {
+                    op, left, right, ..
+                } = current
+                {
+                    if op.precedence() != level {
+                        break;
+                    }
+                    rest.push((op, right));
+                    current = left;
+                }
+                let first = self.expr(current, level);
+                let tail: Vec<Doc> = rest
+                    .iter()
+                    .rev()
+                    .map(|(op, right)| {
+                        Doc::concat([
+                            Doc::text(format!(" {}", op)),
+                            Doc::Line,
+                            self.expr(right, level + 1),
+                        ])
+                    })
+                    .collect();
+                Doc::group(Doc::concat([
+                    first,
+                    Doc::nest(self.indent, Doc::concat(tail)),
+                ]))
+            }
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Try,
+                operand,
+                ..
+            } => Doc::concat([self.expr(operand, POSTFIX_PRECEDENCE), Doc::text("?")]),
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Await,
+                operand,
+                ..
+            } => Doc::concat([Doc::text("await "), self.expr(operand, PREFIX_PRECEDENCE)]),
+            ASTNode::UnaryOp { op, operand, .. } => Doc::concat([
+                Doc::text(op.to_string()),
+                self.expr(operand, PREFIX_PRECEDENCE),
+            ]),
+            ASTNode::Cast {
+                value,
+                target,
+                span,
+                ..
+            } => {
+                let written = self
+                    .token_index(TokenKind::As, value.span().end)
+                    .map(|i| self.type_text(self.tokens[i].span.end, span.end))
+                    .filter(|ty| !ty.is_empty())
+                    .unwrap_or_else(|| target.to_string());
+                Doc::concat([
+                    self.expr(value, CAST_PRECEDENCE),
+                    Doc::text(format!(" as {}", written)),
+                ])
+            }
+            ASTNode::Call { callee, args, .. } => {
+                let callee = self.expr(callee, POSTFIX_PRECEDENCE);
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg, ASSIGNMENT_PRECEDENCE))
+                    .collect();
+                Doc::concat([callee, self.delimited("(", args, ")")])
+            }
+            ASTNode::MemberAccess { object, member, .. } => Doc::concat([
+                self.expr(object, POSTFIX_PRECEDENCE),
+                Doc::text(format!(".{}", member)),
+            ]),
+            ASTNode::Index { array, index, .. } => Doc::concat([
+                self.expr(array, POSTFIX_PRECEDENCE),
+                Doc::text("["),
+                self.expr(index, ASSIGNMENT_PRECEDENCE),
+                Doc::text("]"),
+            ]),
+            ASTNode::Closure {
+                params,
+                return_type,
+                body,
+                is_move,
+                span,
+                ..
+            } => {
+                let names: Vec<String> = params
+                    .iter()
+                    .map(|param| match param.type_annotation {
+                        Type::Unknown => param.name.clone(),
+                        _ => self.parameter(param),
+                    })
+                    .collect();
+                let mut head = format!(
+                    "{}|{}| ",
+                    if *is_move { "move " } else { "" },
+                    names.join(", ")
+                );
+                if let Some(ty) = return_type {
+                    let after = params.last().map_or(span.start, |param| param.span.end);
+                    let annotation =
+                        self.annotation(ty, TokenKind::Arrow, TokenKind::LeftBrace, after);
+                    head.push_str(&format!("-> {} ", annotation));
+                }
+                match body.as_ref() {
+                    ASTNode::Block {
+                        statements, span, ..
+                    } => {
+                        let open = self.open_brace(span.start);
+                        Doc::concat([Doc::text(head), self.block(statements, open)])
+                    }
+                    body => Doc::concat([Doc::text(head), self.expr(body, ASSIGNMENT_PRECEDENCE)]),
+                }
+            }
+            ASTNode::ArrayLiteral { elements, .. } => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.expr(element, ASSIGNMENT_PRECEDENCE))
+                    .collect();
+                self.delimited("[", elements, "]")
+            }
+            ASTNode::TupleLiteral { elements, .. } => match elements.as_slice() {
+                [element] => Doc::concat([
+                    Doc::text("("),
+                    self.expr(element, ASSIGNMENT_PRECEDENCE),
+                    Doc::text(",)"),
+                ]),
+                elements => {
+                    let elements = elements
+                        .iter()
+                        .map(|element| self.expr(element, ASSIGNMENT_PRECEDENCE))
+                        .collect();
+                    self.delimited("(", elements, ")")
+                }
+            },
+            ASTNode::StructLiteral { name, fields, .. } => {
+                let fields: Vec<Doc> = fields
+                    .iter()
+                    .map(|(field, value)| match value {
+                        ASTNode::Identifier { name, .. } if name == field => {
+                            Doc::text(field.as_str())
+                        }
+                        value => Doc::concat([
+                            Doc::text(format!("{}: ", field)),
+                            self.expr(value, ASSIGNMENT_PRECEDENCE),
+                        ]),
+                    })
+                    .collect();
+                if fields.is_empty() {
+                    return Doc::text(format!("{} {{}}", name));
+                }
+                Doc::group(Doc::concat([
+                    Doc::text(format!("{} {{", name)),
+                    Doc::nest(
+                        self.indent,
+                        Doc::concat([
+                            Doc::Line,
+                            Doc::join(fields, Doc::concat([Doc::text(","), Doc::Line])),
+                        ]),
+                    ),
+                    Doc::Line,
+                    Doc::text("}"),
+                ]))
+            }
+            ASTNode::Match {
+                scrutinee,
+                arms,
+                span,
+                ..
+            } => self.match_expr(scrutinee, arms, *span),
+            ASTNode::Assignment { target, value, .. } => Doc::concat([
+                self.expr(target, ASSIGNMENT_PRECEDENCE),
+                Doc::text(" = "),
+                self.expr(value, ASSIGNMENT_PRECEDENCE),
+            ]),
+            ASTNode::CompoundAssignment {
+                target, op, value, ..
+            } => Doc::concat([
+                self.expr(target, ASSIGNMENT_PRECEDENCE),
+                Doc::text(format!(" {}= ", op)),
+                self.expr(value, ASSIGNMENT_PRECEDENCE),
+            ]),
+            // Statements never appear inside expressions; print whatever was written.
+            other => Doc::text(self.slice(other.span()).unwrap_or_default()),
+        }
+    }
+
+    /// `match x { pattern if guard => body, ... }`, one arm per line. Arms
+    /// with a block body take no comma.
+    fn match_expr(&mut self, scrutinee: &ASTNode, arms: &[MatchArm], span: Span) -> Doc {
+        let head = Doc::concat([
+            Doc::text("match "),
+            self.expr(scrutinee, ASSIGNMENT_PRECEDENCE),
+            Doc::text(" "),
+        ]);
+        if arms.is_empty() && self.comments_before(span.end) == 0 {
+            return Doc::concat([head, Doc::text("{}")]);
+        }
+
+        let mut lines = Vec::new();
+        for arm in arms {
+            while let Some(comment) = self.take_comment_before(arm.span.start) {
+                lines.push(Doc::HardLine);
+                lines.push(Doc::text(self.comment_text(comment)));
+            }
+            lines.push(Doc::HardLine);
+            lines.push(self.match_arm(arm));
+        }
+        while let Some(comment) = self.take_comment_before(span.end) {
+            lines.push(Doc::HardLine);
+            lines.push(Doc::text(self.comment_text(comment)));
+        }
+        Doc::concat([
+            head,
+            Doc::text("{"),
+            Doc::nest(self.indent, Doc::concat(lines)),
+            Doc::HardLine,
+            Doc::text("}"),
+        ])
+    }
+
+    fn match_arm(&mut self, arm: &MatchArm) -> Doc {
+        let mut parts = vec![Doc::text(self.pattern(&arm.pattern))];
+        if let Some(guard) = &arm.guard {
+            parts.push(Doc::text(" if "));
+            parts.push(self.expr(guard, ASSIGNMENT_PRECEDENCE));
+        }
+        parts.push(Doc::text(" => "));
+        match &arm.body {
+            ASTNode::Block {
+                statements, span, ..
+            } => {
+                let open = self.open_brace(span.start);
+                parts.push(self.block(statements, open));
+            }
+            body => {
+                parts.push(self.expr(body, ASSIGNMENT_PRECEDENCE));
+                parts.push(Doc::text(","));
+            }
+        }
+        Doc::concat(parts)
+    }
+
+    /// A pattern, with literals as written and `name: name` fields shortened.
+    fn pattern(&self, pattern: &Pattern) -> String {
+        let list = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .map(|pattern| self.pattern(pattern))
+                .collect::<Vec<_>>()
+        };
+        match pattern {
+            Pattern::Wildcard { .. } => "_".to_string(),
+            Pattern::Binding { name, .. } => name.clone(),
+            Pattern::Literal { value, span } => self
+                .slice(*span)
+                .map_or_else(|| value.to_string(), str::to_string),
+            Pattern::Array { elements, rest, .. } => {
+                let mut parts = list(elements);
+                if let Some(at) = rest {
+                    parts.insert(*at, "..".to_string());
+                }
+                format!("[{}]", parts.join(", "))
+            }
+            Pattern::Tuple { elements, .. } => match list(elements).as_slice() {
+                [element] => format!("({},)", element),
+                elements => format!("({})", elements.join(", ")),
+            },
+            Pattern::Or { alternatives, .. } => list(alternatives).join(" | "),
+            Pattern::Struct { path, fields, .. } => match fields {
+                PatternFields::Unit => path.clone(),
+                PatternFields::Tuple(patterns) => {
+                    format!("{}({})", path, list(patterns).join(", "))
+                }
+                PatternFields::Named { fields, rest } => {
+                    let mut parts: Vec<String> = fields
+                        .iter()
+                        .map(|(field, pattern)| match pattern {
+                            Pattern::Binding { name, .. } if name == field => field.clone(),
+                            pattern => format!("{}: {}", field, self.pattern(pattern)),
+                        })
+                        .collect();
+                    if *rest {
+                        parts.push("..".to_string());
+                    }
+                    if parts.is_empty() {
+                        format!("{} {{}}", path)
+                    } else {
+                        format!("{} {{ {} }}", path, parts.join(", "))
+                    }
+                }
+            },
+        }
+    }
+
+    /// `open a, b, c close` on one line, or one item per line if that is too long.
+    fn delimited(&self, open: &str, items: Vec<Doc>, close: &str) -> Doc {
+        if items.is_empty() {
+            return Doc::text(format!("{}{}", open, close));
+        }
+        Doc::group(Doc::concat([
+            Doc::text(open),
+            Doc::nest(
+                self.indent,
+                Doc::concat([
+                    Doc::SoftLine,
+                    Doc::join(items, Doc::concat([Doc::text(","), Doc::Line])),
+                ]),
+            ),
+            Doc::SoftLine,
+            Doc::text(close),
+        ]))
+    }
+
+    /// `head(params) -> Type` for a function spanning `span`.
+    fn signature(
+        &self,
+        head: String,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        span: Span,
+    ) -> Vec<Doc> {
+        let param_docs = params.iter().map(|param| Doc::text(self.parameter(param)));
+        let mut parts = vec![
+            Doc::text(head),
+            self.delimited("(", param_docs.collect(), ")"),
+        ];
+        if let Some(ty) = return_type {
+            // Parameter types may contain arrows too, so look past the `)`.
+            let after_params = params.last().map_or(span.start, |param| param.span.end);
+            let close = self
+                .token_index(TokenKind::RightParen, after_params)
+                .map_or(span.start, |i| self.tokens[i].span.start);
+            // A required trait method ends at its `;` rather than a body.
+            let until = match (
+                self.token_index(TokenKind::Semicolon, close),
+                self.token_index(TokenKind::LeftBrace, close),
+            ) {
+                (Some(semicolon), Some(body)) if body < semicolon => TokenKind::LeftBrace,
+                (Some(_), _) => TokenKind::Semicolon,
+                _ => TokenKind::LeftBrace,
+            };
+            let annotation = self.annotation(ty, TokenKind::Arrow, until, close);
+            parts.push(Doc::text(format!(" -> {}", annotation)));
+        }
+        parts
+    }
+
+    fn parameter(&self, param: &Parameter) -> String {
+        if param.name == "self" && param.type_annotation == Type::Param("Self".to_string()) {
+            return param.name.clone();
+        }
+        self.typed_name(&param.name, &param.type_annotation, param.span)
+    }
+
+    /// `trait Name { ... }`, one method per line: required methods as a
+    /// signature ending in `;`, defaults with their body.
+    fn trait_def(
+        &mut self,
+        name: &str,
+        type_params: &[String],
+        methods: &[TraitMethod],
+        span: Span,
+    ) -> Doc {
+        let header = format!("trait {}{} ", name, type_param_list(type_params, &[]));
+        if methods.is_empty() && self.comments_before(span.end) == 0 {
+            return Doc::text(format!("{}{{}}", header));
+        }
+
+        let mut lines = Vec::new();
+        let mut last_end = None;
+        for method in methods {
+            if let Some(last_end) = last_end {
+                if self.has_blank_line(last_end, method.span.start) {
+                    lines.push(Doc::HardLine);
+                }
+            }
+            while let Some(comment) = self.take_comment_before(method.span.start) {
+                lines.push(Doc::HardLine);
+                lines.push(Doc::text(self.comment_text(comment)));
+            }
+            lines.push(Doc::HardLine);
+            let head = format!("fn {}", method.name);
+            let mut parts = self.signature(
+                head,
+                &method.params,
+                method.return_type.as_ref(),
+                method.span,
+            );
+            match &method.body {
+                Some(body) => {
+                    parts.push(Doc::text(" "));
+                    let open = self.open_brace(method.span.start);
+                    parts.push(self.block(body, open));
+                }
+                None => parts.push(Doc::text(";")),
+            }
+            lines.push(Doc::concat(parts));
+            last_end = Some(method.span.end);
+        }
+        while let Some(comment) = self.take_comment_before(span.end) {
+            lines.push(Doc::HardLine);
+            lines.push(Doc::text(self.comment_text(comment)));
+        }
+        Doc::concat([
+            Doc::text(format!("{}{{", header)),
+            Doc::nest(self.indent, Doc::concat(lines)),
+            Doc::HardLine,
+            Doc::text("}"),
+        ])
+    }
+
+    /// `struct Name<A, B> { field: Type, ... }`, one field per line.
+    fn struct_def(&self, name: &str, type_params: &[String], fields: &[Field]) -> Doc {
+        let header = format!("struct {}{} ", name, type_param_list(type_params, &[]));
+        if fields.is_empty() {
+            return Doc::text(format!("{}{{}}", header));
+        }
+        let fields = fields.iter().map(|field| {
+            let mut parts = vec![Doc::HardLine];
+            parts.extend(attribute_lines(&field.attributes));
+            parts.push(Doc::text(self.typed_name(
+                &field.name,
+                &field.type_annotation,
+                field.span,
+            )));
+            parts.push(Doc::text(","));
+            Doc::concat(parts)
+        });
+        Doc::concat([
+            Doc::text(format!("{}{{", header)),
+            Doc::nest(self.indent, Doc::concat(fields)),
+            Doc::HardLine,
+            Doc::text("}"),
+        ])
+    }
+
+    /// `enum Name<T> { Variant(Type), Variant { field: Type }, ... }`, one
+    /// variant per line.
+    fn enum_def(&self, name: &str, type_params: &[String], variants: &[Variant]) -> Doc {
+        let header = format!("enum {}{} ", name, type_param_list(type_params, &[]));
+        if variants.is_empty() {
+            return Doc::text(format!("{}{{}}", header));
+        }
+        let variants = variants.iter().map(|variant| {
+            let text = match &variant.fields {
+                VariantFields::Unit => variant.name.clone(),
+                VariantFields::Tuple(types) => {
+                    // Types as written, between the parentheses.
+                    let written = self
+                        .token_index(TokenKind::LeftParen, variant.span.start)
+                        .map(|i| self.type_text(self.tokens[i].span.end, variant.span.end - 1))
+                        .filter(|text| !text.is_empty());
+                    let types = written.unwrap_or_else(|| {
+                        types
+                            .iter()
+                            .map(Type::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    });
+                    format!("{}({})", variant.name, types)
+                }
+                VariantFields::Named(fields) if fields.is_empty() => {
+                    format!("{} {{}}", variant.name)
+                }
+                VariantFields::Named(fields) => {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .map(|field| {
+                            let attributes = field
+                                .attributes
+                                .iter()
+                                .map(|attribute| format!("{} ", attribute));
+                            attributes
+                                .chain([self.typed_name(
+                                    &field.name,
+                                    &field.type_annotation,
+                                    field.span,
+                                )])
+                                .collect::<String>()
+                        })
+                        .collect();
+                    format!("{} {{ {} }}", variant.name, fields.join(", "))
+                }
+            };
+            Doc::concat([Doc::HardLine, Doc::text(text), Doc::text(",")])
+        });
+        Doc::concat([
+            Doc::text(format!("{}{{", header)),
+            Doc::nest(self.indent, Doc::concat(variants)),
+            Doc::HardLine,
+            Doc::text("}"),
+        ])
+    }
+
+    /// `name: Type` for a parameter or field spanning `span`, with the type as written.
+    fn typed_name(&self, name: &str, ty: &Type, span: Span) -> String {
+        let written = self
+            .token_index(TokenKind::Colon, span.start)
+            .map(|i| self.type_text(self.tokens[i].span.end, span.end))
+            .filter(|ty| !ty.is_empty());
+        format!("{}: {}", name, written.unwrap_or_else(|| ty.to_string()))
+    }
+
+    /// A type annotation as written between the `after` and `until` tokens, so
+    /// aliases such as `int` survive; `ty` is the fallback.
+    fn annotation(&self, ty: &Type, after: TokenKind, until: TokenKind, from: usize) -> String {
+        self.token_index(after, from)
+            .and_then(|i| {
+                let start = self.tokens[i].span.end;
+                let end = self.tokens[self.token_index(until, start)?].span.start;
+                Some(self.type_text(start, end))
+            })
+            .filter(|text| !text.is_empty())
+            .unwrap_or_else(|| ty.to_string())
+    }
+
+    /// The type written between `start` and `end`, respaced: `Pair<int, [T]>`,
+    /// `fn(T) -> U`, `(T,)`.
+    fn type_text(&self, start: usize, end: usize) -> String {
+        let first = self
+            .tokens
+            .partition_point(|token| token.span.start < start);
+        let mut text = String::new();
+        let mut tokens = self.tokens[first..]
+            .iter()
+            .take_while(|token| token.span.end <= end)
+            .peekable();
+        while let Some(token) = tokens.next() {
+            let closes = tokens
+                .peek()
+                .is_some_and(|next| next.kind == TokenKind::RightParen);
+            match token.kind {
+                TokenKind::Comma if closes => text.push(','),
+                TokenKind::Comma => text.push_str(", "),
+                TokenKind::Semicolon => text.push_str("; "),
+                TokenKind::Arrow => text.push_str(" -> "),
+                TokenKind::Dyn => text.push_str("dyn "),
+                TokenKind::Mut => text.push_str("mut "),
+                _ => text.push_str(&self.source[token.span.start..token.span.end]),
+            }
+        }
+        text
+    }
+
+    /// Offset just past the first `{` at or after `from`.
+    fn open_brace(&self, from: usize) -> usize {
+        self.token_index(TokenKind::LeftBrace, from)
+            .map_or(from, |i| self.tokens[i].span.end)
+    }
+
+    /// Offset of the `}` closing a block whose contents start at `open`.
+    fn closing_brace(&self, statements: &[ASTNode], open: usize) -> usize {
+        let after = statements.last().map_or(open, |last| last.span().end);
+        self.token_index(TokenKind::RightBrace, after)
+            .map_or(self.source.len(), |i| self.tokens[i].span.start)
+    }
+
+    /// Index of the first `kind` token starting at or after `from`.
+    fn token_index(&self, kind: TokenKind, from: usize) -> Option<usize> {
+        let first = self.tokens.partition_point(|token| token.span.start < from);
+        (first..self.tokens.len()).find(|&i| self.tokens[i].kind == kind)
+    }
+
+    /// How many unprinted comments start before `offset`.
+    fn comments_before(&self, offset: usize) -> usize {
+        self.comments[self.next_comment..]
+            .iter()
+            .take_while(|comment| comment.start < offset)
+            .count()
+    }
+
+    fn take_comment_before(&mut self, offset: usize) -> Option<Comment> {
+        let comment = *self.comments.get(self.next_comment)?;
+        if comment.start < offset {
+            self.next_comment += 1;
+            Some(comment)
+        } else {
+            None
+        }
+    }
+
+    fn comment_text(&self, comment: Comment) -> &'a str {
+        self.source[comment.start..comment.end].trim_end()
+    }
+
+    fn slice(&self, span: Span) -> Option<&'a str> {
+        if span.is_dummy() {
+            return None;
+        }
+        self.source.get(span.start..span.end)
+    }
+
+    fn has_blank_line(&self, from: usize, to: usize) -> bool {
+        let Some(between) = self.source.get(from..to) else {
+            return false;
+        };
+        let lines: Vec<&str> = between.split('\n').collect();
+        lines.len() > 2
+            && lines[1..lines.len() - 1]
+                .iter()
+                .any(|line| line.trim().is_empty())
+    }
+}
+
+fn precedence(node: &ASTNode) -> u8 {
+    match node {
+        ASTNode::Assignment { .. }
+        | ASTNode::CompoundAssignment { .. }
+        | ASTNode::Closure { .. } => ASSIGNMENT_PRECEDENCE,
+        ASTNode::BinaryOp { op, .. } => op.precedence(),
+        ASTNode::UnaryOp {
+            op: UnaryOperator::Try,
+            ..
+        } => POSTFIX_PRECEDENCE,
+        ASTNode::UnaryOp { .. } => PREFIX_PRECEDENCE,
+        ASTNode::Cast { .. } => CAST_PRECEDENCE,
+        ASTNode::Call { .. } | ASTNode::MemberAccess { .. } | ASTNode::Index { .. } => {
+            POSTFIX_PRECEDENCE
+        }
+        _ => ATOM_PRECEDENCE,
+    }
+}
+
+/// Each of an item's attributes on a line of its own.
+fn attribute_lines(attributes: &[Attribute]) -> Vec<Doc> {
+    attributes
+        .iter()
+        .flat_map(|attribute| [Doc::text(attribute.to_string()), Doc::HardLine])
+        .collect()
+}
+
+/// The keyword an item's visibility starts with.
+fn visibility(is_pub: bool) -> &'static str {
+    if is_pub {
+        "pub "
+    } else {
+        ""
+    }
+}
+
+/// `<A, B: Trait + Other>`, or nothing for a non-generic item.
+fn type_param_list(type_params: &[String], bounds: &[(String, String)]) -> String {
+    if type_params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = type_params
+        .iter()
+        .map(|param| {
+            let traits: Vec<&str> = bounds
+                .iter()
+                .filter(|(bounded, _)| bounded == param)
+                .map(|(_, bound)| bound.as_str())
+                .collect();
+            if traits.is_empty() {
+                param.clone()
+            } else {
+                format!("{}: {}", param, traits.join(" + "))
+            }
+        })
+        .collect();
+    format!("<{}>", params.join(", "))
+}
+
+/// Every comment in `source`, in order. Comments are whatever lies between tokens
+/// (besides whitespace), plus `///` doc comments, which the lexer keeps as tokens.
+fn collect_comments(source: &str, tokens: &[Token]) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let mut gap_start = 0;
+    for token in tokens {
+        scan_gap(source, gap_start, token.span.start, &mut comments);
+        if token.kind == TokenKind::DocComment {
+            comments.push(Comment {
+                start: token.span.start,
+                end: token.span.end,
+            });
+        }
+        gap_start = gap_start.max(token.span.end);
+    }
+    scan_gap(source, gap_start, source.len(), &mut comments);
+    comments
+}
+
+fn scan_gap(source: &str, start: usize, end: usize, comments: &mut Vec<Comment>) {
+    let Some(gap) = source.get(start..end) else {
+        return;
+    };
+    let mut offset = 0;
+    while let Some(found) = gap[offset..].find('/') {
+        let at = offset + found;
+        let rest = &gap[at..];
+        let len = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            block_comment_len(rest)
+        } else {
+            offset = at + 1;
+            continue;
+        };
+        comments.push(Comment {
+            start: start + at,
+            end: start + at + len,
+        });
+        offset = at + len;
+    }
+}
+
+/// Length of the (possibly nested) block comment at the start of `text`.
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        match (bytes[i], bytes[i + 1]) {
+            (b'/', b'*') => {
+                depth += 1;
+                i += 2;
+            }
+            (b'*', b'/') => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    text.len()
+}
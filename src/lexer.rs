@@ -1,170 +1,536 @@
 use crate::{
+    span::{FileId, Span},
     token::{Token, TokenKind},
     IoError, Result,
 };
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while1},
+    bytes::complete::{tag, take_while},
     character::complete::{alpha1, multispace1},
     combinator::{map, recognize},
     sequence::pair,
     IResult,
 };
 
+const INTEGER_SUFFIXES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+];
+const FLOAT_SUFFIXES: &[&str] = &["f32", "f64"];
+
+/// Operators and punctuation, longest first so that `..=` wins over `..` and `.`.
+const OPERATORS: &[(&str, TokenKind)] = &[
+    ("..=", TokenKind::DotDotEqual),
+    ("->", TokenKind::Arrow),
+    ("=>", TokenKind::FatArrow),
+    ("::", TokenKind::ColonColon),
+    ("..", TokenKind::DotDot),
+    ("==", TokenKind::EqualEqual),
+    ("!=", TokenKind::BangEqual),
+    ("<=", TokenKind::LessEqual),
+    (">=", TokenKind::GreaterEqual),
+    ("<<", TokenKind::LessLess),
+    (">>", TokenKind::GreaterGreater),
+    ("&&", TokenKind::And),
+    ("||", TokenKind::Or),
+    ("+=", TokenKind::PlusEqual),
+    ("-=", TokenKind::MinusEqual),
+    ("*=", TokenKind::StarEqual),
+    ("/=", TokenKind::SlashEqual),
+    ("%=", TokenKind::PercentEqual),
+    ("+", TokenKind::Plus),
+    ("-", TokenKind::Minus),
+    ("*", TokenKind::Star),
+    ("/", TokenKind::Slash),
+    ("%", TokenKind::Percent),
+    ("=", TokenKind::Equal),
+    ("!", TokenKind::Bang),
+    ("<", TokenKind::Less),
+    (">", TokenKind::Greater),
+    ("&", TokenKind::Ampersand),
+    ("|", TokenKind::Pipe),
+    ("^", TokenKind::Caret),
+    ("?", TokenKind::Question),
+    ("$", TokenKind::Dollar),
+    ("#", TokenKind::Hash),
+    ("(", TokenKind::LeftParen),
+    (")", TokenKind::RightParen),
+    ("{", TokenKind::LeftBrace),
+    ("}", TokenKind::RightBrace),
+    ("[", TokenKind::LeftBracket),
+    ("]", TokenKind::RightBracket),
+    (",", TokenKind::Comma),
+    (".", TokenKind::Dot),
+    (";", TokenKind::Semicolon),
+    (":", TokenKind::Colon),
+];
+
 pub struct Lexer<'a> {
     input: &'a str,
     position: usize,
+    file_id: FileId,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { input, position: 0 }
+        Self::with_file_id(input, FileId::default())
+    }
+
+    /// Creates a lexer whose token spans point into the file registered as `file_id`.
+    pub fn with_file_id(input: &'a str, file_id: FileId) -> Self {
+        Self {
+            input,
+            position: 0,
+            file_id,
+        }
     }
 
     pub fn tokenize(&mut self) -> Result<Vec<Token>> {
+        let (tokens, mut errors) = self.tokenize_with_recovery();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Tokenizes the whole input, skipping past malformed tokens instead of stopping
+    /// at the first one. Returns every token that could be read plus all errors.
+    pub fn tokenize_with_recovery(&mut self) -> (Vec<Token>, Vec<IoError>) {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
         while !self.input.is_empty() {
             // Skip whitespace
             if let Ok((remaining, _)) = multispace1::<&str, ()>(self.input) {
-                let consumed = self.input.len() - remaining.len();
-                self.position += consumed;
-                self.input = remaining;
+                self.advance(remaining);
                 continue;
             }
 
-            // Try to match a token
-            let token = self.next_token()?;
-            tokens.push(token);
+            // Skip comments, keeping `///` doc comments for the doc generator
+            let result = if self.input.starts_with("//") || self.input.starts_with("/*") {
+                self.comment()
+            } else {
+                self.next_token().map(Some)
+            };
+
+            match result {
+                Ok(Some(token)) => tokens.push(token),
+                Ok(None) => {}
+                Err(err) => {
+                    errors.push(err);
+                    // Leave a placeholder so the parser does not report the gap again.
+                    let start = self.position;
+                    let skipped = self.skip_invalid();
+                    tokens.push(Token::new(TokenKind::Error, skipped, self.span_from(start)));
+                }
+            }
         }
 
         // Add EOF token
-        tokens.push(Token::new(TokenKind::EOF, String::from(""), self.position));
-        Ok(tokens)
+        tokens.push(Token::new(
+            TokenKind::EOF,
+            "",
+            self.span_from(self.position),
+        ));
+        (tokens, errors)
+    }
+
+    /// After an error, skips the rest of the malformed token so lexing can resume.
+    /// Returns the skipped text.
+    fn skip_invalid(&mut self) -> &'a str {
+        let bytes = self.input.as_bytes();
+        let len = match self.input.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let mut escaped = false;
+                self.input
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| {
+                        let end = !escaped && (c == quote || (quote == '\'' && c == '\n'));
+                        escaped = !escaped && c == '\\';
+                        end
+                    })
+                    .map_or(self.input.len(), |(i, c)| i + c.len_utf8())
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut i = 0;
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric()
+                        || bytes[i] == b'_'
+                        || (bytes[i] == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)))
+                {
+                    i += 1;
+                }
+                i
+            }
+            // Only an unterminated block comment fails here; it runs to the end.
+            Some('/') => self.input.len(),
+            Some(c) => c.len_utf8(),
+            None => 0,
+        };
+        let skipped = &self.input[..len];
+        self.advance(&self.input[len..]);
+        skipped
+    }
+
+    /// Consumes a line or (nested) block comment. Returns a token only for `///` doc comments.
+    fn comment(&mut self) -> Result<Option<Token>> {
+        let start = self.position;
+
+        if self.input.starts_with("//") {
+            let end = self.input.find('\n').unwrap_or(self.input.len());
+            let text = &self.input[..end];
+            let is_doc = text.starts_with("///") && !text.starts_with("////");
+            let doc = text.trim_start_matches("///");
+            let doc = doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string();
+            self.advance(&self.input[end..]);
+
+            return Ok(if is_doc {
+                Some(Token::new(
+                    TokenKind::DocComment,
+                    doc,
+                    self.span_from(start),
+                ))
+            } else {
+                None
+            });
+        }
+
+        let mut depth = 0;
+        let mut chars = self.input.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match (c, chars.peek().map(|&(_, next)| next)) {
+                ('/', Some('*')) => {
+                    depth += 1;
+                    chars.next();
+                }
+                ('*', Some('/')) => {
+                    depth -= 1;
+                    chars.next();
+                    if depth == 0 {
+                        self.advance(&self.input[i + 2..]);
+                        return Ok(None);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.error(start, start + 2, "Unterminated block comment"))
     }
 
     fn next_token(&mut self) -> Result<Token> {
+        let start = self.position;
+
         // Match keywords and identifiers
         if let Ok((remaining, ident)) = self.identifier(self.input) {
-            let token = match ident.as_str() {
-                "fn" => Token::new(TokenKind::Function, ident, self.position),
-                "let" => Token::new(TokenKind::Let, ident, self.position),
-                "return" => Token::new(TokenKind::Return, ident, self.position),
-                "if" => Token::new(TokenKind::If, ident, self.position),
-                "else" => Token::new(TokenKind::Else, ident, self.position),
-                "while" => Token::new(TokenKind::While, ident, self.position),
-                "for" => Token::new(TokenKind::For, ident, self.position),
-                "break" => Token::new(TokenKind::Break, ident, self.position),
-                "continue" => Token::new(TokenKind::Continue, ident, self.position),
-                "true" => Token::new(TokenKind::Boolean(true), ident, self.position),
-                "false" => Token::new(TokenKind::Boolean(false), ident, self.position),
-                _ => Token::new(TokenKind::Identifier, ident, self.position),
+            let kind = match ident.as_str() {
+                "fn" => TokenKind::Function,
+                "struct" => TokenKind::Struct,
+                "enum" => TokenKind::Enum,
+                "match" => TokenKind::Match,
+                "trait" => TokenKind::Trait,
+                "impl" => TokenKind::Impl,
+                "dyn" => TokenKind::Dyn,
+                "mut" => TokenKind::Mut,
+                "let" => TokenKind::Let,
+                "return" => TokenKind::Return,
+                "if" => TokenKind::If,
+                "else" => TokenKind::Else,
+                "while" => TokenKind::While,
+                "for" => TokenKind::For,
+                "in" => TokenKind::In,
+                "break" => TokenKind::Break,
+                "continue" => TokenKind::Continue,
+                "true" => TokenKind::True,
+                "false" => TokenKind::False,
+                "async" => TokenKind::Async,
+                "await" => TokenKind::Await,
+                "as" => TokenKind::As,
+                "import" => TokenKind::Import,
+                "pub" => TokenKind::Pub,
+                "move" => TokenKind::Move,
+                "macro" => TokenKind::Macro,
+                "const" => TokenKind::Const,
+                _ => TokenKind::Identifier,
             };
             self.advance(remaining);
-            return Ok(token);
+            return Ok(Token::new(kind, ident, self.span_from(start)));
         }
 
         // Match numbers
-        if let Ok((remaining, number)) = self.number(self.input) {
-            let token = Token::new(TokenKind::Number, number, self.position);
-            self.advance(remaining);
-            return Ok(token);
+        if self.input.starts_with(|c: char| c.is_ascii_digit()) {
+            let len = self.number()?;
+            let lexeme = self.input[..len].to_string();
+            self.advance(&self.input[len..]);
+            return Ok(Token::new(TokenKind::Number, lexeme, self.span_from(start)));
         }
 
         // Match strings
-        if let Ok((remaining, string)) = self.string(self.input) {
-            let token = Token::new(TokenKind::String, string, self.position);
-            self.advance(remaining);
-            return Ok(token);
+        if self.input.starts_with('"') {
+            let (len, value) = self.string()?;
+            self.advance(&self.input[len..]);
+            return Ok(Token::new(TokenKind::String, value, self.span_from(start)));
+        }
+
+        // Match character literals
+        if self.input.starts_with('\'') {
+            let (len, value) = self.char_literal()?;
+            self.advance(&self.input[len..]);
+            return Ok(Token::new(
+                TokenKind::Char,
+                value.to_string(),
+                self.span_from(start),
+            ));
+        }
+
+        // Match operators and symbols, preferring the longest one
+        if let Some((op, kind)) = OPERATORS.iter().find(|(op, _)| self.input.starts_with(op)) {
+            self.advance(&self.input[op.len()..]);
+            return Ok(Token::new(*kind, *op, self.span_from(start)));
         }
 
-        // Match operators and symbols
         match self.input.chars().next() {
-            Some(ch) => {
-                let (kind, len) = match ch {
-                    '+' => (TokenKind::Plus, 1),
-                    '-' => (TokenKind::Minus, 1),
-                    '*' => (TokenKind::Star, 1),
-                    '/' => (TokenKind::Slash, 1),
-                    '=' => {
-                        if self.input.starts_with("==") {
-                            (TokenKind::EqualEqual, 2)
-                        } else {
-                            (TokenKind::Equal, 1)
-                        }
-                    }
-                    '!' => {
-                        if self.input.starts_with("!=") {
-                            (TokenKind::BangEqual, 2)
-                        } else {
-                            (TokenKind::Bang, 1)
-                        }
-                    }
-                    '<' => {
-                        if self.input.starts_with("<=") {
-                            (TokenKind::LessEqual, 2)
-                        } else {
-                            (TokenKind::Less, 1)
-                        }
-                    }
-                    '>' => {
-                        if self.input.starts_with(">=") {
-                            (TokenKind::GreaterEqual, 2)
-                        } else {
-                            (TokenKind::Greater, 1)
-                        }
-                    }
-                    '(' => (TokenKind::LeftParen, 1),
-                    ')' => (TokenKind::RightParen, 1),
-                    '{' => (TokenKind::LeftBrace, 1),
-                    '}' => (TokenKind::RightBrace, 1),
-                    '[' => (TokenKind::LeftBracket, 1),
-                    ']' => (TokenKind::RightBracket, 1),
-                    ',' => (TokenKind::Comma, 1),
-                    '.' => (TokenKind::Dot, 1),
-                    ';' => (TokenKind::Semicolon, 1),
-                    ':' => (TokenKind::Colon, 1),
-                    _ => {
-                        return Err(IoError::lexer_error(
-                            self.position,
-                            format!("Unexpected character: {}", ch),
-                        ))
-                    }
+            Some(ch) => Err(self.error(
+                start,
+                start + ch.len_utf8(),
+                format!("Unexpected character: {}", ch),
+            )),
+            None => Ok(Token::new(TokenKind::EOF, "", self.span_from(start))),
+        }
+    }
+
+    /// Scans a numeric literal and returns its length in bytes. The token keeps the
+    /// raw text (prefix, `_` separators and suffix); `NumberLiteral::parse` decodes it.
+    fn number(&self) -> Result<usize> {
+        let bytes = self.input.as_bytes();
+        let radix = match (bytes.first(), bytes.get(1)) {
+            (Some(b'0'), Some(b'x')) => 16,
+            (Some(b'0'), Some(b'o')) => 8,
+            (Some(b'0'), Some(b'b')) => 2,
+            _ => 10,
+        };
+
+        let mut end = if radix == 10 { 0 } else { 2 };
+        let digits_start = end;
+        let mut is_float = false;
+
+        let scan_digits = |from: usize, radix: u32| {
+            let mut i = from;
+            while i < bytes.len() && ((bytes[i] as char).is_digit(radix) || bytes[i] == b'_') {
+                i += 1;
+            }
+            i
+        };
+
+        if radix == 10 {
+            end = scan_digits(end, 10);
+
+            // A fraction needs a digit after the dot so that `0..n` and `t.0` still lex.
+            if bytes.get(end) == Some(&b'.')
+                && bytes.get(end + 1).is_some_and(|b| b.is_ascii_digit())
+            {
+                is_float = true;
+                end = scan_digits(end + 1, 10);
+            }
+
+            if matches!(bytes.get(end), Some(b'e') | Some(b'E')) {
+                let mut exp = end + 1;
+                if matches!(bytes.get(exp), Some(b'+') | Some(b'-')) {
+                    exp += 1;
+                }
+                if bytes.get(exp).is_some_and(|b| b.is_ascii_digit()) {
+                    is_float = true;
+                    end = scan_digits(exp, 10);
+                }
+            }
+        } else {
+            // Scan any alphanumerics so that `0b102` reports the bad digit instead of
+            // silently splitting into two tokens.
+            end = scan_digits(end, 16);
+            let digits = &self.input[digits_start..end];
+            if let Some(bad) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+                let name = match radix {
+                    16 => "hexadecimal",
+                    8 => "octal",
+                    _ => "binary",
                 };
+                return Err(self.error(
+                    self.position,
+                    self.position + end,
+                    format!("Invalid digit '{}' in {} literal", bad, name),
+                ));
+            }
+        }
+
+        if !self.input[digits_start..end].chars().any(|c| c != '_') {
+            return Err(self.error(
+                self.position,
+                self.position + end,
+                "Missing digits in numeric literal",
+            ));
+        }
+
+        // Type suffix, e.g. `10u8` or `2.5f32`
+        let suffix_start = end;
+        while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+            end += 1;
+        }
+        let suffix = self.input[suffix_start..end].trim_start_matches('_');
+        if !suffix.is_empty() {
+            let valid = if is_float {
+                FLOAT_SUFFIXES.contains(&suffix)
+            } else if radix == 10 {
+                INTEGER_SUFFIXES.contains(&suffix) || FLOAT_SUFFIXES.contains(&suffix)
+            } else {
+                INTEGER_SUFFIXES.contains(&suffix)
+            };
+            if !valid {
+                return Err(self.error(
+                    self.position + suffix_start,
+                    self.position + end,
+                    format!("Invalid suffix '{}' for numeric literal", suffix),
+                ));
+            }
+        }
+
+        Ok(end)
+    }
 
-                let token = Token::new(kind, self.input[..len].to_string(), self.position);
-                self.advance(&self.input[len..]);
-                Ok(token)
+    /// Scans a string literal, returning its length in bytes and its unescaped contents.
+    fn string(&self) -> Result<(usize, String)> {
+        let mut value = String::new();
+        let mut chars = self.input.char_indices().skip(1);
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((i + 1, value)),
+                '\\' => value.push(self.escape(i, &mut chars)?),
+                _ => value.push(c),
             }
-            None => Ok(Token::new(TokenKind::EOF, String::new(), self.position)),
         }
+
+        Err(self.error(
+            self.position,
+            self.position + 1,
+            "Unterminated string literal",
+        ))
     }
 
-    fn number<'b>(&self, input: &'b str) -> IResult<&'b str, String> {
-        use nom::character::complete::{char, digit1};
-        use nom::combinator::opt;
-        use nom::sequence::tuple;
+    /// Scans a character literal, returning its length in bytes and its value.
+    fn char_literal(&self) -> Result<(usize, char)> {
+        let mut chars = self.input.char_indices().skip(1);
+        let value = match chars.next() {
+            Some((i, '\\')) => self.escape(i, &mut chars)?,
+            Some((_, '\'')) => {
+                return Err(self.error(self.position, self.position + 2, "Empty character literal"))
+            }
+            Some((_, '\n')) | None => {
+                return Err(self.error(
+                    self.position,
+                    self.position + 1,
+                    "Unterminated character literal",
+                ))
+            }
+            Some((_, c)) => c,
+        };
+
+        match chars.next() {
+            Some((i, '\'')) => Ok((i + 1, value)),
+            Some((i, _)) => {
+                let end = self.input[i..]
+                    .find(['\'', '\n'])
+                    .map_or(i, |offset| i + offset + 1);
+                Err(self.error(
+                    self.position,
+                    self.position + end,
+                    "Character literal may only contain one character",
+                ))
+            }
+            None => Err(self.error(
+                self.position,
+                self.position + 1,
+                "Unterminated character literal",
+            )),
+        }
+    }
 
-        let (input, (int_part, decimal_part)) =
-            tuple((digit1, opt(tuple((char('.'), digit1)))))(input)?;
+    /// Decodes the escape sequence following the backslash at byte offset `backslash`.
+    fn escape(
+        &self,
+        backslash: usize,
+        chars: &mut impl Iterator<Item = (usize, char)>,
+    ) -> Result<char> {
+        let start = self.position + backslash;
+        let (i, c) = chars
+            .next()
+            .ok_or_else(|| self.error(start, start + 1, "Unterminated escape sequence"))?;
 
-        let number = match decimal_part {
-            Some(('.', decimal)) => format!("{}.{}", int_part, decimal),
-            None => int_part.to_string(),
-            Some(('\0'..='-', _)) | Some(('/', ..)) | Some(('\u{e000}'..='\u{10ffff}', _)) => {
-                todo!()
-            } // Added wildcard patterns
+        let simple = match c {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            _ => None,
         };
+        if let Some(ch) = simple {
+            return Ok(ch);
+        }
 
-        Ok((input, number))
+        match c {
+            'x' => {
+                let hex: String = chars.take(2).map(|(_, c)| c).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 && byte <= 0x7f => Ok(byte as char),
+                    _ => Err(self.error(
+                        start,
+                        start + 2 + hex.len(),
+                        "Invalid \\x escape: expected two hex digits up to 7f",
+                    )),
+                }
+            }
+            'u' => {
+                let mut digits = String::new();
+                let mut end = i + 1;
+                let mut closed = false;
+                if let Some((_, '{')) = chars.next() {
+                    for (j, c) in chars.by_ref() {
+                        end = j + c.len_utf8();
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        digits.push(c);
+                    }
+                }
+                let digits = digits.replace('_', "");
+                let code = u32::from_str_radix(&digits, 16).ok();
+                match code.and_then(char::from_u32) {
+                    Some(ch) if closed && (1..=6).contains(&digits.len()) => Ok(ch),
+                    _ => Err(self.error(
+                        start,
+                        self.position + end,
+                        "Invalid unicode escape: expected \\u{...} with 1-6 hex digits",
+                    )),
+                }
+            }
+            _ => Err(self.error(
+                start,
+                start + 1 + c.len_utf8(),
+                format!("Unknown escape sequence '\\{}'", c),
+            )),
+        }
     }
 
-    fn string<'b>(&self, input: &'b str) -> IResult<&'b str, String> {
-        use nom::bytes::complete::take_until;
-        use nom::character::complete::char;
-        use nom::sequence::delimited;
+    fn error(&self, start: usize, end: usize, message: impl Into<String>) -> IoError {
+        IoError::lexer_error(start, message).with_span(Span::new(self.file_id, start, end))
+    }
 
-        delimited(char('"'), map(take_until("\""), String::from), char('"'))(input)
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.file_id, start, self.position)
     }
 
     fn advance(&mut self, remaining: &'a str) {
@@ -177,9 +543,208 @@ impl<'a> Lexer<'a> {
         map(
             recognize(pair(
                 alt((alpha1, tag("_"))),
-                take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+                take_while(|c: char| c.is_alphanumeric() || c == '_'),
             )),
             String::from,
         )(input)
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberValue {
+    Integer(u128),
+    Float(f64),
+}
+
+/// A decoded numeric literal: its value plus the type suffix, if one was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberLiteral {
+    pub value: NumberValue,
+    pub suffix: Option<String>,
+}
+
+impl NumberLiteral {
+    /// Decodes the lexeme of a `TokenKind::Number` token.
+    pub fn parse(lexeme: &str) -> std::result::Result<Self, String> {
+        let (radix, body) = match lexeme.get(..2) {
+            Some("0x") => (16, &lexeme[2..]),
+            Some("0o") => (8, &lexeme[2..]),
+            Some("0b") => (2, &lexeme[2..]),
+            _ => (10, lexeme),
+        };
+
+        let suffix = INTEGER_SUFFIXES
+            .iter()
+            .chain(FLOAT_SUFFIXES.iter())
+            .filter(|suffix| radix == 10 || !FLOAT_SUFFIXES.contains(suffix))
+            .find(|suffix| body.ends_with(*suffix))
+            .map(|suffix| suffix.to_string());
+
+        let digits = &body[..body.len() - suffix.as_ref().map_or(0, String::len)];
+        let digits = digits.trim_end_matches('_').replace('_', "");
+
+        let is_float = radix == 10
+            && (digits.contains(['.', 'e', 'E'])
+                || suffix.as_deref().is_some_and(|s| s.starts_with('f')));
+
+        let value = if is_float {
+            digits
+                .parse::<f64>()
+                .map(NumberValue::Float)
+                .map_err(|_| format!("Invalid float literal '{}'", lexeme))?
+        } else {
+            u128::from_str_radix(&digits, radix)
+                .map(NumberValue::Integer)
+                .map_err(|_| format!("Integer literal '{}' is too large", lexeme))?
+        };
+
+        Ok(Self { value, suffix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_lexemes(source: &str) -> Vec<(TokenKind, String)> {
+        Lexer::new(source)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|token| (token.kind, token.lexeme))
+            .collect()
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        let tokens = kinds_and_lexemes("a // line\n/* block /* nested */ */ b");
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Identifier, "a".to_string()),
+                (TokenKind::Identifier, "b".to_string()),
+                (TokenKind::EOF, String::new()),
+            ]
+        );
+        assert!(Lexer::new("/* open").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_doc_comments_are_tokens() {
+        let tokens = kinds_and_lexemes("/// Adds numbers.\n//// not docs\nfn");
+        assert_eq!(
+            tokens[0],
+            (TokenKind::DocComment, "Adds numbers.".to_string())
+        );
+        assert_eq!(tokens[1].0, TokenKind::Function);
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tokens = kinds_and_lexemes(r#""say \"hi\"\n\t\u{1F600}\x41""#);
+        assert_eq!(tokens[0].1, "say \"hi\"\n\t\u{1F600}A");
+        assert!(Lexer::new(r#""\q""#).tokenize().is_err());
+        assert!(Lexer::new(r#""open"#).tokenize().is_err());
+    }
+
+    #[test]
+    fn test_char_literals() {
+        let tokens = kinds_and_lexemes(r"'A' '\n' '😀'");
+        assert_eq!(tokens[0], (TokenKind::Char, "A".to_string()));
+        assert_eq!(tokens[1], (TokenKind::Char, "\n".to_string()));
+        assert_eq!(tokens[2], (TokenKind::Char, "😀".to_string()));
+        assert!(Lexer::new("'ab'").tokenize().is_err());
+        assert!(Lexer::new("''").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        let cases = [
+            ("98_222", NumberValue::Integer(98222), None),
+            ("0xff", NumberValue::Integer(255), None),
+            ("0o77", NumberValue::Integer(63), None),
+            ("0b1111_0000", NumberValue::Integer(240), None),
+            ("42u32", NumberValue::Integer(42), Some("u32")),
+            ("0xffu8", NumberValue::Integer(255), Some("u8")),
+            ("2.5f64", NumberValue::Float(2.5), Some("f64")),
+            ("1e3", NumberValue::Float(1000.0), None),
+            ("7f32", NumberValue::Float(7.0), Some("f32")),
+        ];
+
+        for (source, value, suffix) in cases {
+            let tokens = kinds_and_lexemes(source);
+            assert_eq!(tokens[0], (TokenKind::Number, source.to_string()));
+            let literal = NumberLiteral::parse(&tokens[0].1).unwrap();
+            assert_eq!(literal.value, value, "{}", source);
+            assert_eq!(literal.suffix.as_deref(), suffix, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_malformed_numbers_are_errors() {
+        for source in ["0b102", "0x", "12abc", "1.5u8", "0o8"] {
+            assert!(Lexer::new(source).tokenize().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_operators_use_longest_match() {
+        let kinds: Vec<TokenKind> = kinds_and_lexemes("a += b..=c && d::e => f >> 1 ?")
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Identifier,
+                TokenKind::PlusEqual,
+                TokenKind::Identifier,
+                TokenKind::DotDotEqual,
+                TokenKind::Identifier,
+                TokenKind::And,
+                TokenKind::Identifier,
+                TokenKind::ColonColon,
+                TokenKind::Identifier,
+                TokenKind::FatArrow,
+                TokenKind::Identifier,
+                TokenKind::GreaterGreater,
+                TokenKind::Number,
+                TokenKind::Question,
+                TokenKind::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_after_integer() {
+        let tokens = kinds_and_lexemes("0..10");
+        assert_eq!(tokens[0], (TokenKind::Number, "0".to_string()));
+        assert_eq!(tokens[1].0, TokenKind::DotDot);
+    }
+
+    #[test]
+    fn test_recovery_reports_every_error() {
+        let (tokens, errors) =
+            Lexer::new("let a = 0b12;\nlet s = \"\\q\";\nlet c = @;").tokenize_with_recovery();
+        assert_eq!(errors.len(), 3);
+        let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds.iter().filter(|kind| **kind == TokenKind::Let).count(),
+            3
+        );
+        assert_eq!(
+            kinds
+                .iter()
+                .filter(|kind| **kind == TokenKind::Error)
+                .count(),
+            3
+        );
+        assert_eq!(kinds.last(), Some(&TokenKind::EOF));
+    }
+
+    #[test]
+    fn test_dot_after_integer_is_not_a_fraction() {
+        let tokens = kinds_and_lexemes("t.0");
+        assert_eq!(tokens[2], (TokenKind::Number, "0".to_string()));
+    }
+}
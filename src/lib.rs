@@ -1,9 +1,22 @@
+pub mod analysis;
 pub mod ast;
+pub mod build;
 pub mod codegen;
+pub mod compiler;
+pub mod diagnostics;
 pub mod error;
+pub mod formatter;
 pub mod lexer;
+pub mod macro_system;
+pub mod module;
+pub mod optimizer;
 pub mod parser;
+pub mod pattern;
+pub mod repl;
 pub mod runtime;
+pub mod semantic;
+pub mod source_location;
+pub mod span;
 pub mod stdlib;
 pub mod token; // Add token module
 pub mod types;
@@ -11,15 +24,16 @@ pub mod validation;
 pub mod visitor; // Add visitor module
 
 // Export common types to avoid import conflicts
-pub use ast::{ASTNode, Expression, Function, Module, Parameter, Statement};
+pub use ast::{ASTNode, NodeId, Parameter, Type};
 pub use error::IoError;
+pub use span::{FileId, Span};
 pub type Result<T> = std::result::Result<T, IoError>;
 
 // Re-export debug info types
 pub use codegen::debug::{DebugInfo, SourceLocation};
 
 // Re-export visitor trait
-pub use visitor::{Visitable, Visitor};
+pub use visitor::{Folder, Visitable, Visitor};
 
 mod prelude;
 pub use prelude::*;
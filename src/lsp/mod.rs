@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::RangeInclusive, sync::RwLock};
 use tower_lsp::{
     jsonrpc::Result,
     lsp_types::*,
@@ -6,15 +6,22 @@ use tower_lsp::{
 };
 use crate::{
     ast::ASTNode,
+    diagnostics::{Diagnostic as IoDiagnostic, DiagnosticLevel, Source},
     error::IoError,
-    parser::Parser,
-    semantic::analyzer::SemanticAnalyzer,
+    formatter::{CodeFormatter, FormattingConfig, Hunk},
+    parser::parse_source_with_recovery,
+    semantic::{
+        self,
+        resolve::{self, Resolution},
+    },
+    span::{FileId, Span},
     Result as IoResult,
 };
 
 pub struct IoLanguageServer {
     client: Client,
-    workspace: Workspace,
+    /// Set up by `initialize`, which like every handler only gets `&self`.
+    workspace: RwLock<Workspace>,
     document_map: HashMap<Url, TextDocumentItem>,
     ast_cache: HashMap<Url, ASTNode>,
     semantic_cache: HashMap<Url, SemanticData>,
@@ -32,20 +39,76 @@ struct WorkspaceConfig {
     max_problems: i32,
     trace: TraceValue,
     format_on_save: bool,
+    /// From the workspace's `io.toml`.
+    formatting: FormattingConfig,
 }
 
 #[derive(Debug)]
 struct SemanticData {
     symbols: Vec<SymbolInformation>,
-    references: HashMap<Position, Vec<Location>>,
+    /// What each name refers to, when the document resolved.
+    resolution: Option<Resolution>,
+    source: Source,
     diagnostics: Vec<Diagnostic>,
 }
 
+impl SemanticData {
+    fn range(&self, span: Span) -> Range {
+        let position = |offset| {
+            let (line, column) = self.source.line_column(offset);
+            Position::new(line as u32 - 1, column as u32 - 1)
+        };
+        Range::new(position(span.start), position(span.end))
+    }
+
+    /// The symbol under the cursor, with what resolved it.
+    fn symbol_at(&self, position: Position) -> Option<(&Resolution, resolve::SymbolId)> {
+        let resolution = self.resolution.as_ref()?;
+        let offset = self
+            .source
+            .offset(position.line as usize + 1, position.character as usize + 1);
+        Some((resolution, resolution.symbol_at(offset, FileId::default())?))
+    }
+}
+
+/// Converts a compiler diagnostic into an LSP one, mapping its byte span to
+/// zero-based line/character positions.
+fn to_lsp_diagnostic(source: &Source, diagnostic: &IoDiagnostic) -> Diagnostic {
+    let span = diagnostic.span.unwrap_or_default();
+    let position = |offset| {
+        let (line, column) = source.line_column(offset);
+        Position::new(line as u32 - 1, column as u32 - 1)
+    };
+
+    Diagnostic {
+        range: Range::new(position(span.start), position(span.end)),
+        severity: Some(match diagnostic.level {
+            DiagnosticLevel::Error => DiagnosticSeverity::ERROR,
+            DiagnosticLevel::Warning => DiagnosticSeverity::WARNING,
+            DiagnosticLevel::Info => DiagnosticSeverity::INFORMATION,
+        }),
+        source: Some("io".to_string()),
+        message: diagnostic.message.clone(),
+        ..Diagnostic::default()
+    }
+}
+
+/// Replaces whole lines, so positions never fall inside a multi-byte character.
+fn to_text_edit(hunk: &Hunk) -> TextEdit {
+    TextEdit {
+        range: Range::new(
+            Position::new(hunk.old_start as u32, 0),
+            Position::new((hunk.old_start + hunk.old_len) as u32, 0),
+        ),
+        new_text: hunk.new_lines.iter().map(|line| format!("{}\n", line)).collect(),
+    }
+}
+
 impl IoLanguageServer {
     pub fn new(client: Client) -> Self {
         Self {
             client,
-            workspace: Workspace::default(),
+            workspace: RwLock::default(),
             document_map: HashMap::new(),
             ast_cache: HashMap::new(),
             semantic_cache: HashMap::new(),
@@ -54,25 +117,39 @@ impl IoLanguageServer {
 
     async fn analyze_document(&mut self, uri: &Url) -> IoResult<()> {
         if let Some(document) = self.document_map.get(uri) {
-            // Parse document
-            let mut parser = Parser::new(document.text.as_str());
-            let ast = parser.parse()?;
-            
-            // Perform semantic analysis
-            let mut analyzer = SemanticAnalyzer::new();
-            let analysis_result = analyzer.check(&ast)?;
-            
-            // Cache results
-            self.ast_cache.insert(uri.clone(), ast);
-            
+            // Parse document, collecting every syntax error instead of stopping at the first
+            let (ast, syntax_errors) = parse_source_with_recovery(&document.text, FileId::default());
+            let source = Source::new(document.text.clone());
+
             // Collect symbols and references
             let mut semantic_data = SemanticData {
                 symbols: Vec::new(),
-                references: HashMap::new(),
-                diagnostics: Vec::new(),
+                resolution: None,
+                diagnostics: syntax_errors
+                    .iter()
+                    .map(|diagnostic| to_lsp_diagnostic(&source, diagnostic))
+                    .collect(),
+                source,
             };
-            
-            self.collect_symbols(&analysis_result, &mut semantic_data)?;
+
+            // Name resolution only makes sense on a tree without error placeholders.
+            // Type checking goes by the same resolution navigation uses.
+            if syntax_errors.is_empty() {
+                let checked = resolve::resolve(&ast).and_then(|resolution| {
+                    let checked = semantic::analyze(&ast, &resolution);
+                    semantic_data.resolution = Some(resolution);
+                    checked
+                });
+                if let Err(err) = checked {
+                    let diagnostic = IoDiagnostic::from_error(&err);
+                    let diagnostic = to_lsp_diagnostic(&semantic_data.source, &diagnostic);
+                    semantic_data.diagnostics.push(diagnostic);
+                }
+                self.collect_symbols(&ast, &mut semantic_data)?;
+            }
+
+            // Cache results
+            self.ast_cache.insert(uri.clone(), ast);
             self.semantic_cache.insert(uri.clone(), semantic_data);
             
             // Report diagnostics
@@ -169,7 +246,11 @@ impl IoLanguageServer {
 impl LanguageServer for IoLanguageServer {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
         if let Some(root_uri) = params.root_uri {
-            self.workspace.root_path = Some(root_uri.to_string());
+            let mut workspace = self.workspace.write().unwrap();
+            if let Ok(root) = root_uri.to_file_path() {
+                workspace.config.formatting = FormattingConfig::load(&root).unwrap_or_default();
+            }
+            workspace.root_path = Some(root_uri.to_string());
         }
 
         Ok(InitializeResult {
@@ -194,6 +275,7 @@ impl LanguageServer for IoLanguageServer {
                 document_highlight_provider: Some(OneOf::Left(true)),
                 code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
                 document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
                 document_symbol_provider: Some(OneOf::Left(true)),
                 workspace_symbol_provider: Some(OneOf::Left(true)),
                 implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
@@ -269,7 +351,7 @@ impl LanguageServer for IoLanguageServer {
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        if self.workspace.config.format_on_save {
+        if self.workspace.read().unwrap().config.format_on_save {
             self.format_document(&params.text_document.uri).await.unwrap_or_else(|e| {
                 self.client.log_message(MessageType::ERROR, format!("Format error: {}", e)).await;
             });
@@ -328,50 +410,61 @@ impl LanguageServer for IoLanguageServer {
     }
 
     async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
-        if let Some(semantic_data) = self.semantic_cache.get(&params.text_document_position_params.text_document.uri) {
-            if let Some(symbol) = self.find_symbol_at_position(
-                &semantic_data.symbols,
-                params.text_document_position_params.position,
-            ) {
-                return Ok(Some(GotoDefinitionResponse::Scalar(symbol.location.clone())));
+        let uri = &params.text_document_position_params.text_document.uri;
+        if let Some(semantic_data) = self.semantic_cache.get(uri) {
+            let position = params.text_document_position_params.position;
+            if let Some((resolution, symbol)) = semantic_data.symbol_at(position) {
+                let span = resolution.symbol(symbol).span;
+                return Ok(Some(GotoDefinitionResponse::Scalar(Location {
+                    uri: uri.clone(),
+                    range: semantic_data.range(span),
+                })));
             }
         }
         Ok(None)
     }
 
     async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
-        if let Some(semantic_data) = self.semantic_cache.get(&params.text_document_position.text_document.uri) {
-            if let Some(references) = semantic_data.references.get(&params.text_document_position.position) {
-                return Ok(Some(references.clone()));
+        let uri = &params.text_document_position.text_document.uri;
+        if let Some(semantic_data) = self.semantic_cache.get(uri) {
+            let position = params.text_document_position.position;
+            if let Some((resolution, symbol)) = semantic_data.symbol_at(position) {
+                let declaration = params
+                    .context
+                    .include_declaration
+                    .then(|| resolution.symbol(symbol).span);
+                let locations = declaration
+                    .into_iter()
+                    .chain(resolution.uses_of(symbol).into_iter().map(|(_, span)| span))
+                    .map(|span| Location {
+                        uri: uri.clone(),
+                        range: semantic_data.range(span),
+                    })
+                    .collect();
+                return Ok(Some(locations));
             }
         }
         Ok(None)
     }
 
     async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
-        self.format_document(&params.text_document.uri).await?;
-        Ok(None)
+        Ok(self.format_edits(&params.text_document.uri, None))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let lines = params.range.start.line as usize..=params.range.end.line as usize;
+        Ok(self.format_edits(&params.text_document.uri, Some(lines)))
     }
 }
 
 impl IoLanguageServer {
     async fn format_document(&self, uri: &Url) -> IoResult<()> {
-        if let Some(doc) = self.document_map.get(uri) {
-            // Parse and format the document
-            let formatted = self.format_code(&doc.text)?;
-            
-            // Apply formatting
+        if let Some(edits) = self.format_edits(uri, None).filter(|edits| !edits.is_empty()) {
             self.client.apply_edit(WorkspaceEdit {
-                changes: Some(HashMap::from([(
-                    uri.clone(),
-                    vec![TextEdit {
-                        range: Range {
-                            start: Position::new(0, 0),
-                            end: Position::new(u32::MAX, u32::MAX),
-                        },
-                        new_text: formatted,
-                    }],
-                )])),
+                changes: Some(HashMap::from([(uri.clone(), edits)])),
                 document_changes: None,
                 change_annotations: None,
             }).await?;
@@ -379,40 +472,18 @@ impl IoLanguageServer {
         Ok(())
     }
 
+    /// Edits that format the document, or only the given lines of it. Documents
+    /// with syntax errors are left alone.
+    fn format_edits(&self, uri: &Url, lines: Option<RangeInclusive<usize>>) -> Option<Vec<TextEdit>> {
+        let document = self.document_map.get(uri)?;
+        let formatter = CodeFormatter::new(&self.workspace.read().unwrap().config.formatting);
+        let hunks = formatter.edits(&document.text, lines).ok()?;
+        Some(hunks.iter().map(to_text_edit).collect())
+    }
+
     fn format_code(&self, code: &str) -> IoResult<String> {
-        let mut formatted = String::new();
-        let mut indent_level = 0;
-        let indent_str = "    ";  // 4 spaces
-        
-        for line in code.lines() {
-            let trimmed = line.trim();
-            
-            // Decrease indent for closing braces
-            if trimmed.starts_with('}') {
-                indent_level = indent_level.saturating_sub(1);
-            }
-            
-            // Add indentation
-            if !trimmed.is_empty() {
-                formatted.push_str(&indent_str.repeat(indent_level));
-                formatted.push_str(trimmed);
-                formatted.push('\n');
-            }
-            
-            // Increase indent after opening braces
-            if trimmed.ends_with('{') {
-                indent_level += 1;
-            }
-            
-            // Handle one-line control structures
-            if trimmed.starts_with("if ") || trimmed.starts_with("for ") || trimmed.starts_with("while ") {
-                if !trimmed.ends_with('{') {
-                    indent_level += 1;
-                }
-            }
-        }
-        
-        Ok(formatted)
+        let formatter = CodeFormatter::new(&self.workspace.read().unwrap().config.formatting);
+        formatter.format(code)
     }
 
     fn find_symbol_at_position(
@@ -614,11 +685,7 @@ println("deep");
         let formatted = server.format_code(unformatted).unwrap();
         assert_eq!(formatted, expected);
 
-        // Test formatting with one-line conditions
-        let unformatted = "if true println(\"one line\");";
-        let expected = "if true\n    println(\"one line\");\n";
-        
-        let formatted = server.format_code(unformatted).unwrap();
-        assert_eq!(formatted, expected);
+        // Code that doesn't parse is left alone; diagnostics explain why
+        assert!(server.format_code("if true println(\"one line\");").is_err());
     }
 }
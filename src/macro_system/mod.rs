@@ -1,314 +1,1038 @@
+//! Declarative macros, expanded on the token stream before it is parsed:
+//!
+//! ```text
+//! macro max {
+//!     ($a:expr) => { $a };
+//!     ($a:expr, $($rest:expr),+) => { larger($a, max!($($rest),+)) }
+//! }
+//! ```
+//!
+//! An invocation `max!(...)` expands the first rule whose pattern matches the
+//! tokens between its parentheses. In a pattern `$name:kind` matches an
+//! `expr`, `ident`, `ty` or `block`, and `$(...)` followed by an optional
+//! separator and `*`, `+` or `?` repeats. In a template `$name` is replaced by
+//! what it matched, and `$(...)` is repeated once for each match of the
+//! variables inside it. An expansion that is an expression is parenthesized,
+//! and so is each `expr` it substitutes.
+//!
+//! Expansion is hygienic: the names a template binds with `let` or `for` are
+//! renamed in every expansion, so they neither capture nor hide the caller's
+//! variables. Tokens from a template keep their place in the definition, with
+//! the id of their expansion in their spans, and diagnostics follow that back
+//! to the invocations.
+
 use crate::{
-    ast::{ASTNode, ASTVisitor},
+    diagnostics::{Expansion, SourceMap},
     error::IoError,
+    lexer::Lexer,
     parser::Parser,
+    span::{ExpansionId, FileId, Span},
+    token::{Token, TokenKind},
     Result,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// How deeply expansions may contain further invocations.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// What a `$name:kind` variable of a pattern matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fragment {
+    Expr,
+    Ident,
+    Ty,
+    Block,
+}
+
+impl Fragment {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "expr" => Some(Fragment::Expr),
+            "ident" => Some(Fragment::Ident),
+            "ty" => Some(Fragment::Ty),
+            "block" => Some(Fragment::Block),
+            _ => None,
+        }
+    }
+}
 
+/// A `macro` item: its rules, tried in order.
 #[derive(Debug)]
 pub struct MacroDefinition {
     name: String,
-    params: Vec<String>,
-    body: Vec<ASTNode>,
+    rules: Vec<Rule>,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Vec<PatternPart>,
+    template: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone)]
+enum PatternPart {
+    /// A token the input must repeat exactly.
+    Token(Token),
+    Fragment(String, Fragment),
+    Repeat(Repetition<PatternPart>),
 }
 
-pub struct MacroExpander {
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Token(Token),
+    /// An identifier the template binds itself, renamed in each expansion.
+    Local(Token),
+    Variable(String, Span),
+    Repeat(Repetition<TemplatePart>),
+}
+
+/// `$(body) separator kleene`
+#[derive(Debug, Clone)]
+struct Repetition<T> {
+    body: Vec<T>,
+    separator: Option<Token>,
+    kleene: Kleene,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kleene {
+    /// `*`
+    ZeroOrMore,
+    /// `+`
+    OneOrMore,
+    /// `?`
+    ZeroOrOne,
+}
+
+/// What a variable matched: its tokens or, inside a repetition, what it
+/// matched in each round.
+#[derive(Debug, Clone)]
+enum Binding {
+    Tokens(Vec<Token>, Fragment),
+    Repeated(Vec<Binding>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+/// Where matching goes on after a part of a pattern has matched, given the
+/// position reached and the variables bound so far.
+type Continuation<'a> = &'a dyn Fn(usize, Bindings) -> Option<Bindings>;
+
+/// Expands the macros of one file, recording each expansion in a `SourceMap`.
+pub struct MacroExpander<'a> {
     definitions: HashMap<String, MacroDefinition>,
-    expansion_depth: usize,
-    max_depth: usize,
+    source_map: &'a mut SourceMap,
+    /// Every identifier of the file; renamed locals must not collide with them.
+    taken: HashSet<String>,
+    renamed: usize,
 }
 
-impl MacroExpander {
-    pub fn new(max_depth: usize) -> Self {
+impl<'a> MacroExpander<'a> {
+    pub fn new(source_map: &'a mut SourceMap) -> Self {
         Self {
             definitions: HashMap::new(),
-            expansion_depth: 0,
-            max_depth,
+            source_map,
+            taken: HashSet::new(),
+            renamed: 0,
+        }
+    }
+
+    /// Removes the `macro` items from `tokens` and replaces every invocation
+    /// with its expansion. Macros can be used anywhere in the file they are
+    /// defined in, before their definition too.
+    pub fn expand(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>> {
+        self.taken.extend(
+            tokens
+                .iter()
+                .filter(|token| token.kind == TokenKind::Identifier)
+                .map(|token| token.lexeme.clone()),
+        );
+
+        let mut remaining = Vec::with_capacity(tokens.len());
+        let mut depth = 0usize;
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i].kind {
+                TokenKind::Macro if depth == 0 => {
+                    let (definition, end) = parse_definition(&tokens, i)?;
+                    self.register_macro(definition)?;
+                    i = end;
+                    continue;
+                }
+                TokenKind::Macro => {
+                    return Err(IoError::parser_error(
+                        "Macros can only be defined at the top level",
+                    )
+                    .with_span(tokens[i].span))
+                }
+                kind if opens(kind) => depth += 1,
+                kind if closes(kind) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            remaining.push(tokens[i].clone());
+            i += 1;
         }
+        self.expand_invocations(remaining, 0)
     }
 
-    pub fn register_macro(&mut self, def: MacroDefinition) -> Result<()> {
-        if self.definitions.contains_key(&def.name) {
+    pub fn register_macro(&mut self, definition: MacroDefinition) -> Result<()> {
+        if let Some(existing) = self.definitions.get(&definition.name) {
             return Err(IoError::validation_error(format!(
                 "Macro '{}' is already defined",
-                def.name
-            )));
+                definition.name
+            ))
+            .with_span(definition.span)
+            .with_note(existing.span, "first defined here"));
         }
-        self.definitions.insert(def.name.clone(), def);
+        self.definitions.insert(definition.name.clone(), definition);
         Ok(())
     }
 
-    pub fn expand(&mut self, node: &ASTNode) -> Result<ASTNode> {
-        if self.expansion_depth >= self.max_depth {
-            return Err(IoError::runtime_error(
-                "Maximum macro expansion depth exceeded",
-            ));
+    /// Replaces each `name!(...)` in `tokens`; `depth` counts the expansions
+    /// `tokens` came from.
+    fn expand_invocations(&mut self, tokens: Vec<Token>, depth: usize) -> Result<Vec<Token>> {
+        let mut output = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let is_invocation = tokens[i].kind == TokenKind::Identifier
+                && tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Bang)
+                && tokens
+                    .get(i + 2)
+                    .is_some_and(|t| t.kind == TokenKind::LeftParen);
+            if !is_invocation {
+                output.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+            let close = closing(&tokens, i + 2).ok_or_else(|| {
+                IoError::parser_error("Unclosed `(` in macro invocation")
+                    .with_span(tokens[i + 2].span)
+            })?;
+            let call_site = tokens[i].span.to(tokens[close].span);
+            // Invocations in the arguments expand first, so that they match
+            // `expr` and the other fragments.
+            let args = self.expand_invocations(tokens[i + 3..close].to_vec(), depth)?;
+            let (expansion, is_expression) =
+                self.expand_invocation(&tokens[i].lexeme, &args, call_site, depth)?;
+            output.extend(expansion);
+            i = close + 1;
+            // The `;` of `name!(...);` ends statements the expansion already ends.
+            if !is_expression
+                && tokens
+                    .get(i)
+                    .is_some_and(|t| t.kind == TokenKind::Semicolon)
+            {
+                i += 1;
+            }
         }
+        Ok(output)
+    }
+
+    /// The expansion of `name!(args)`, and whether it is an expression.
+    fn expand_invocation(
+        &mut self,
+        name: &str,
+        args: &[Token],
+        call_site: Span,
+        depth: usize,
+    ) -> Result<(Vec<Token>, bool)> {
+        if depth >= MAX_EXPANSION_DEPTH {
+            // Reported where the recursion happens rather than with a backtrace
+            // through every level of it.
+            return Err(IoError::validation_error(format!(
+                "Maximum macro expansion depth exceeded while expanding `{}!`",
+                name
+            ))
+            .with_span(call_site.in_expansion(ExpansionId::ROOT)));
+        }
+        let definition = self.definitions.get(name).ok_or_else(|| {
+            IoError::validation_error(format!("Unknown macro `{}!`", name)).with_span(call_site)
+        })?;
+        let (rule, bindings) = definition
+            .rules
+            .iter()
+            .find_map(|rule| rule.matches(args).map(|bindings| (rule.clone(), bindings)))
+            .ok_or_else(|| {
+                IoError::validation_error(format!(
+                    "No rule of macro `{}!` matches these arguments",
+                    name
+                ))
+                .with_span(call_site)
+                .with_note(definition.span, format!("`{}` is defined here", name))
+            })?;
+
+        let expansion = self.source_map.add_expansion(Expansion {
+            macro_name: name.to_string(),
+            call_site,
+        });
+        let renames = rule
+            .locals()
+            .into_iter()
+            .map(|local| {
+                let fresh = self.fresh_name(&local);
+                (local, fresh)
+            })
+            .collect();
+        let transcription = Transcription {
+            expansion,
+            renames: &renames,
+        };
+        let mut tokens = Vec::new();
+        transcription.transcribe(&rule.template, &bindings, &mut tokens)?;
+
+        let tokens = self.expand_invocations(tokens, depth + 1)?;
+        Ok(if parses(Fragment::Expr, &tokens) {
+            (parenthesized(tokens, call_site, call_site), true)
+        } else {
+            (tokens, false)
+        })
+    }
+
+    /// A name for `local` that appears nowhere else in the file.
+    fn fresh_name(&mut self, local: &str) -> String {
+        loop {
+            self.renamed += 1;
+            let name = format!("{}__{}", local, self.renamed);
+            if self.taken.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+}
 
-        match node {
-            ASTNode::CallExpression { callee, arguments } => {
-                if let ASTNode::Identifier(name) = &**callee {
-                    if let Some(macro_def) = self.definitions.get(name) {
-                        self.expansion_depth += 1;
-                        let expanded = self.expand_macro(macro_def, arguments)?;
-                        self.expansion_depth -= 1;
-                        return Ok(expanded);
+impl Rule {
+    /// The bindings of the variables in the pattern, if it matches all of `args`.
+    fn matches(&self, args: &[Token]) -> Option<Bindings> {
+        match_parts(&self.pattern, args, 0, Bindings::new(), &|end, bindings| {
+            (end == args.len()).then_some(bindings)
+        })
+    }
+
+    /// The names the template binds with `let` or `for`.
+    fn locals(&self) -> HashSet<String> {
+        let mut locals = HashSet::new();
+        collect_locals(&self.template, &mut locals);
+        locals
+    }
+}
+
+fn collect_locals(parts: &[TemplatePart], locals: &mut HashSet<String>) {
+    for part in parts {
+        match part {
+            TemplatePart::Local(token) => {
+                locals.insert(token.lexeme.clone());
+            }
+            TemplatePart::Repeat(repetition) => collect_locals(&repetition.body, locals),
+            _ => {}
+        }
+    }
+}
+
+/// Matches `parts` against `input` from `pos`, then hands the position reached
+/// to `rest`. Choices made here, where a fragment ends and how often a
+/// repetition repeats, are retried until `rest` succeeds.
+fn match_parts(
+    parts: &[PatternPart],
+    input: &[Token],
+    pos: usize,
+    bindings: Bindings,
+    rest: Continuation,
+) -> Option<Bindings> {
+    let Some((first, after)) = parts.split_first() else {
+        return rest(pos, bindings);
+    };
+    match first {
+        PatternPart::Token(expected) => {
+            if !same_token(input.get(pos)?, expected) {
+                return None;
+            }
+            match_parts(after, input, pos + 1, bindings, rest)
+        }
+        PatternPart::Fragment(name, fragment) => fragment_ends(input, pos)
+            .into_iter()
+            .filter(|&end| parses(*fragment, &input[pos..end]))
+            .find_map(|end| {
+                let mut bindings = bindings.clone();
+                let tokens = input[pos..end].to_vec();
+                bindings.insert(name.clone(), Binding::Tokens(tokens, *fragment));
+                match_parts(after, input, end, bindings, rest)
+            }),
+        PatternPart::Repeat(repetition) => {
+            match_repetition(repetition, after, input, pos, bindings, Vec::new(), rest)
+        }
+    }
+}
+
+/// Matches further rounds of `repetition`, after the `rounds` matched so far,
+/// and then `after`. Repeats as often as it can.
+fn match_repetition(
+    repetition: &Repetition<PatternPart>,
+    after: &[PatternPart],
+    input: &[Token],
+    pos: usize,
+    bindings: Bindings,
+    rounds: Vec<Bindings>,
+    rest: Continuation,
+) -> Option<Bindings> {
+    let may_repeat = repetition.kleene != Kleene::ZeroOrOne || rounds.is_empty();
+    let start = match (&repetition.separator, rounds.is_empty()) {
+        (Some(separator), false) => input
+            .get(pos)
+            .filter(|token| same_token(token, separator))
+            .map(|_| pos + 1),
+        _ => Some(pos),
+    };
+    if let Some(start) = start.filter(|_| may_repeat) {
+        let found = match_parts(
+            &repetition.body,
+            input,
+            start,
+            Bindings::new(),
+            &|end, round| {
+                // A round that matches nothing would repeat forever.
+                if end == pos {
+                    return None;
+                }
+                let mut rounds = rounds.clone();
+                rounds.push(round);
+                match_repetition(
+                    repetition,
+                    after,
+                    input,
+                    end,
+                    bindings.clone(),
+                    rounds,
+                    rest,
+                )
+            },
+        );
+        if found.is_some() {
+            return found;
+        }
+    }
+
+    if repetition.kleene == Kleene::OneOrMore && rounds.is_empty() {
+        return None;
+    }
+    let mut bindings = bindings;
+    for name in pattern_variables(&repetition.body) {
+        let matched = rounds.iter().map(|round| round[&name].clone()).collect();
+        bindings.insert(name, Binding::Repeated(matched));
+    }
+    match_parts(after, input, pos, bindings, rest)
+}
+
+/// Where a fragment starting at `pos` may end: after each complete token tree,
+/// shortest first.
+fn fragment_ends(input: &[Token], pos: usize) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let mut depth = 0usize;
+    for (i, token) in input.iter().enumerate().skip(pos) {
+        if opens(token.kind) {
+            depth += 1;
+        } else if closes(token.kind) {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        }
+        if depth == 0 {
+            ends.push(i + 1);
+        }
+    }
+    ends
+}
+
+/// Whether `tokens` are exactly one `fragment`.
+fn parses(fragment: Fragment, tokens: &[Token]) -> bool {
+    let Some(last) = tokens.last() else {
+        return false;
+    };
+    let end = Token::new(TokenKind::EOF, "", last.span);
+    let tokens = tokens.iter().cloned().chain(std::iter::once(end));
+    Parser::new(tokens).parse_fragment(fragment).is_ok()
+}
+
+fn same_token(token: &Token, expected: &Token) -> bool {
+    token.kind == expected.kind && token.lexeme == expected.lexeme
+}
+
+/// Fills in one expansion's template.
+struct Transcription<'a> {
+    expansion: ExpansionId,
+    renames: &'a HashMap<String, String>,
+}
+
+impl Transcription<'_> {
+    fn transcribe(
+        &self,
+        parts: &[TemplatePart],
+        bindings: &Bindings,
+        output: &mut Vec<Token>,
+    ) -> Result<()> {
+        for part in parts {
+            match part {
+                TemplatePart::Token(token) => output.push(self.template_token(token)),
+                TemplatePart::Local(token) => {
+                    let mut token = self.template_token(token);
+                    token.lexeme = self.renames[&token.lexeme].clone();
+                    output.push(token);
+                }
+                TemplatePart::Variable(name, span) => match &bindings[name] {
+                    Binding::Tokens(tokens, Fragment::Expr) if tokens.len() > 1 => {
+                        let (open, close) = (tokens[0].span, tokens[tokens.len() - 1].span);
+                        output.extend(parenthesized(tokens.clone(), open, close));
+                    }
+                    Binding::Tokens(tokens, _) => output.extend(tokens.iter().cloned()),
+                    Binding::Repeated(_) => {
+                        return Err(IoError::validation_error(format!(
+                            "`${}` matched a repetition, so it can only be used inside `$(...)`",
+                            name
+                        ))
+                        .with_span(span.in_expansion(self.expansion)))
                     }
+                },
+                TemplatePart::Repeat(repetition) => {
+                    self.transcribe_repetition(repetition, bindings, output)?
                 }
-                self.expand_children(node)
             }
-            _ => self.expand_children(node),
         }
+        Ok(())
     }
 
-    fn expand_macro(&mut self, def: &MacroDefinition, args: &[ASTNode]) -> Result<ASTNode> {
-        if args.len() != def.params.len() {
+    /// Repeats `repetition` once per round of the repeated variables it uses.
+    fn transcribe_repetition(
+        &self,
+        repetition: &Repetition<TemplatePart>,
+        bindings: &Bindings,
+        output: &mut Vec<Token>,
+    ) -> Result<()> {
+        let span = |part: Option<&TemplatePart>| match part {
+            Some(TemplatePart::Token(token) | TemplatePart::Local(token)) => token.span,
+            Some(TemplatePart::Variable(_, span)) => *span,
+            _ => Span::dummy(),
+        };
+        let span = span(repetition.body.first()).in_expansion(self.expansion);
+        let repeated: Vec<(String, &Vec<Binding>)> = template_variables(&repetition.body)
+            .into_iter()
+            .filter_map(|name| match &bindings[&name] {
+                Binding::Repeated(rounds) => Some((name, rounds)),
+                Binding::Tokens(..) => None,
+            })
+            .collect();
+        let Some(count) = repeated.first().map(|(_, rounds)| rounds.len()) else {
+            return Err(IoError::validation_error(
+                "`$(...)` must use a variable that matched a repetition",
+            )
+            .with_span(span));
+        };
+        if let Some((name, rounds)) = repeated.iter().find(|(_, rounds)| rounds.len() != count) {
             return Err(IoError::validation_error(format!(
-                "Macro '{}' expects {} arguments but got {}",
-                def.name,
-                def.params.len(),
-                args.len()
-            )));
+                "`${}` repeats {} times but `${}` repeats {} times",
+                repeated[0].0,
+                count,
+                name,
+                rounds.len()
+            ))
+            .with_span(span));
+        }
+
+        for round in 0..count {
+            if let (Some(separator), true) = (&repetition.separator, round > 0) {
+                output.push(self.template_token(separator));
+            }
+            let mut bindings = bindings.clone();
+            for (name, rounds) in &repeated {
+                bindings.insert(name.clone(), rounds[round].clone());
+            }
+            self.transcribe(&repetition.body, &bindings, output)?;
         }
+        Ok(())
+    }
 
-        let mut replacements = HashMap::new();
-        for (param, arg) in def.params.iter().zip(args.iter()) {
-            replacements.insert(param.clone(), arg.clone());
+    fn template_token(&self, token: &Token) -> Token {
+        Token {
+            span: token.span.in_expansion(self.expansion),
+            ..token.clone()
         }
+    }
+}
+
+/// `tokens` in parentheses, the `(` at `open` and the `)` at `close`.
+fn parenthesized(tokens: Vec<Token>, open: Span, close: Span) -> Vec<Token> {
+    std::iter::once(Token::new(TokenKind::LeftParen, "(", open))
+        .chain(tokens)
+        .chain(std::iter::once(Token::new(
+            TokenKind::RightParen,
+            ")",
+            close,
+        )))
+        .collect()
+}
 
-        let mut expander = MacroReplacer { replacements };
-        let mut expanded_body = Vec::new();
+/// Parses `macro name { (pattern) => { template }; ... }` starting at the
+/// `macro` token `tokens[start]`. Returns the definition and the index after it.
+fn parse_definition(tokens: &[Token], start: usize) -> Result<(MacroDefinition, usize)> {
+    let name = expect(tokens, start + 1, TokenKind::Identifier)?;
+    let open = start + 2;
+    expect(tokens, open, TokenKind::LeftBrace)?;
+    let close = closing(tokens, open).ok_or_else(|| unclosed(&tokens[open]))?;
 
-        for node in &def.body {
-            expanded_body.push(expander.visit_node(node)?);
+    let mut rules = Vec::new();
+    let mut i = open + 1;
+    while i < close {
+        expect(tokens, i, TokenKind::LeftParen)?;
+        let pattern_end = closing(tokens, i).ok_or_else(|| unclosed(&tokens[i]))?;
+        let pattern = pattern_parts(&tokens[i + 1..pattern_end])?;
+        expect(tokens, pattern_end + 1, TokenKind::FatArrow)?;
+        let template_start = pattern_end + 2;
+        expect(tokens, template_start, TokenKind::LeftBrace)?;
+        let template_end =
+            closing(tokens, template_start).ok_or_else(|| unclosed(&tokens[template_start]))?;
+        let template = template_parts(&tokens[template_start + 1..template_end])?;
+        check_variables(&pattern, &template)?;
+        rules.push(Rule { pattern, template });
+
+        i = template_end + 1;
+        if i < close && tokens[i].kind == TokenKind::Semicolon {
+            i += 1;
         }
+    }
 
-        Ok(ASTNode::Block(expanded_body))
+    let span = tokens[start].span.to(tokens[close].span);
+    if rules.is_empty() {
+        return Err(
+            IoError::parser_error(format!("Macro `{}` has no rules", name.lexeme)).with_span(span),
+        );
     }
+    let definition = MacroDefinition {
+        name: name.lexeme.clone(),
+        rules,
+        span,
+    };
+    Ok((definition, close + 1))
+}
 
-    fn expand_children(&mut self, node: &ASTNode) -> Result<ASTNode> {
-        match node {
-            ASTNode::Block(nodes) => {
-                let mut expanded = Vec::new();
-                for node in nodes {
-                    expanded.push(self.expand(node)?);
-                }
-                Ok(ASTNode::Block(expanded))
-            }
-            ASTNode::Function { name, params, return_type, body, is_async } => {
-                let expanded_body = body.iter()
-                    .map(|node| self.expand(node))
-                    .collect::<Result<Vec<_>>>()?;
-                Ok(ASTNode::Function {
-                    name: name.clone(),
-                    params: params.clone(),
-                    return_type: return_type.clone(),
-                    body: expanded_body,
-                    is_async: *is_async,
-                })
+fn pattern_parts(tokens: &[Token]) -> Result<Vec<PatternPart>> {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].kind != TokenKind::Dollar {
+            parts.push(PatternPart::Token(tokens[i].clone()));
+            i += 1;
+            continue;
+        }
+        match tokens.get(i + 1).map(|token| token.kind) {
+            Some(TokenKind::Identifier) => {
+                let name = tokens[i + 1].lexeme.clone();
+                expect(tokens, i + 2, TokenKind::Colon)?;
+                let kind = expect(tokens, i + 3, TokenKind::Identifier)?;
+                let fragment = Fragment::from_name(&kind.lexeme).ok_or_else(|| {
+                    IoError::parser_error(format!(
+                        "Unknown fragment `{}`; expected expr, ident, ty or block",
+                        kind.lexeme
+                    ))
+                    .with_span(kind.span)
+                })?;
+                parts.push(PatternPart::Fragment(name, fragment));
+                i += 4;
             }
-            ASTNode::If { condition, then_branch, else_branch } => {
-                Ok(ASTNode::If {
-                    condition: Box::new(self.expand(condition)?),
-                    then_branch: then_branch.iter()
-                        .map(|node| self.expand(node))
-                        .collect::<Result<Vec<_>>>()?,
-                    else_branch: if let Some(else_nodes) = else_branch {
-                        Some(else_nodes.iter()
-                            .map(|node| self.expand(node))
-                            .collect::<Result<Vec<_>>>()?)
-                    } else {
-                        None
-                    },
-                })
+            Some(TokenKind::LeftParen) => {
+                let close = closing(tokens, i + 1).ok_or_else(|| unclosed(&tokens[i + 1]))?;
+                let body = pattern_parts(&tokens[i + 2..close])?;
+                let (separator, kleene, next) = repetition_suffix(tokens, close)?;
+                parts.push(PatternPart::Repeat(Repetition {
+                    body,
+                    separator,
+                    kleene,
+                }));
+                i = next;
             }
-            ASTNode::While { condition, body } => {
-                Ok(ASTNode::While {
-                    condition: Box::new(self.expand(condition)?),
-                    body: body.iter()
-                        .map(|node| self.expand(node))
-                        .collect::<Result<Vec<_>>>()?,
-                })
+            _ => return Err(misplaced_dollar(&tokens[i])),
+        }
+    }
+    Ok(parts)
+}
+
+fn template_parts(tokens: &[Token]) -> Result<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        if token.kind != TokenKind::Dollar {
+            // `let x` and `for x` bind `x`; a name after `.` or `::` is a field
+            // or a path, never a local.
+            let previous = i.checked_sub(1).map(|j| tokens[j].kind);
+            let binds = token.kind == TokenKind::Identifier
+                && matches!(previous, Some(TokenKind::Let | TokenKind::For));
+            parts.push(if binds {
+                TemplatePart::Local(token.clone())
+            } else {
+                TemplatePart::Token(token.clone())
+            });
+            i += 1;
+            continue;
+        }
+        match tokens.get(i + 1).map(|token| token.kind) {
+            Some(TokenKind::Identifier) => {
+                let variable = &tokens[i + 1];
+                let span = token.span.to(variable.span);
+                parts.push(TemplatePart::Variable(variable.lexeme.clone(), span));
+                i += 2;
             }
-            ASTNode::For { init, condition, update, body } => {
-                Ok(ASTNode::For {
-                    init: Box::new(self.expand(init)?),
-                    condition: Box::new(self.expand(condition)?),
-                    update: Box::new(self.expand(update)?),
-                    body: body.iter()
-                        .map(|node| self.expand(node))
-                        .collect::<Result<Vec<_>>>()?,
-                })
+            Some(TokenKind::LeftParen) => {
+                let close = closing(tokens, i + 1).ok_or_else(|| unclosed(&tokens[i + 1]))?;
+                let body = template_parts(&tokens[i + 2..close])?;
+                let (separator, kleene, next) = repetition_suffix(tokens, close)?;
+                parts.push(TemplatePart::Repeat(Repetition {
+                    body,
+                    separator,
+                    kleene,
+                }));
+                i = next;
             }
-            ASTNode::BinaryOp { left, op, right } => {
-                Ok(ASTNode::BinaryOp {
-                    left: Box::new(self.expand(left)?),
-                    op: op.clone(),
-                    right: Box::new(self.expand(right)?),
-                })
+            _ => return Err(misplaced_dollar(token)),
+        }
+    }
+    mark_locals(&mut parts);
+    Ok(parts)
+}
+
+/// Marks every use of a name the template binds, except after `.` or `::`.
+fn mark_locals(parts: &mut [TemplatePart]) {
+    let mut locals = HashSet::new();
+    collect_locals(parts, &mut locals);
+    mark_uses(parts, &locals);
+}
+
+fn mark_uses(parts: &mut [TemplatePart], locals: &HashSet<String>) {
+    let mut previous = None;
+    for part in parts.iter_mut() {
+        match part {
+            TemplatePart::Token(token)
+                if token.kind == TokenKind::Identifier
+                    && locals.contains(&token.lexeme)
+                    && !matches!(previous, Some(TokenKind::Dot | TokenKind::ColonColon)) =>
+            {
+                *part = TemplatePart::Local(token.clone());
             }
-            // Nodes that don't need expansion
-            ASTNode::IntegerLiteral(_) |
-            ASTNode::FloatLiteral(_) |
-            ASTNode::StringLiteral(_) |
-            ASTNode::BooleanLiteral(_) |
-            ASTNode::Identifier(_) => Ok(node.clone()),
+            TemplatePart::Repeat(repetition) => mark_uses(&mut repetition.body, locals),
+            _ => {}
         }
+        previous = match part {
+            TemplatePart::Token(token) | TemplatePart::Local(token) => Some(token.kind),
+            _ => None,
+        };
     }
 }
 
-struct MacroReplacer {
-    replacements: HashMap<String, ASTNode>,
+/// The separator and kleene operator after the `)` of `$(...)` at `close`,
+/// and the index after them.
+fn repetition_suffix(tokens: &[Token], close: usize) -> Result<(Option<Token>, Kleene, usize)> {
+    let kleene = |index: usize| match tokens.get(index).map(|token| token.kind) {
+        Some(TokenKind::Star) => Some(Kleene::ZeroOrMore),
+        Some(TokenKind::Plus) => Some(Kleene::OneOrMore),
+        Some(TokenKind::Question) => Some(Kleene::ZeroOrOne),
+        _ => None,
+    };
+    if let Some(kleene) = kleene(close + 1) {
+        return Ok((None, kleene, close + 2));
+    }
+    if let Some(kleene) = kleene(close + 2) {
+        return Ok((Some(tokens[close + 1].clone()), kleene, close + 3));
+    }
+    Err(
+        IoError::parser_error("Expected `*`, `+` or `?` after `$(...)`")
+            .with_span(tokens[close].span),
+    )
 }
 
-impl ASTVisitor for MacroReplacer {
-    type Output = ASTNode;
+/// Every variable a template uses must be bound by its pattern, once.
+fn check_variables(pattern: &[PatternPart], template: &[TemplatePart]) -> Result<()> {
+    let bound = pattern_variables(pattern);
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = bound.iter().find(|name| !seen.insert(*name)) {
+        return Err(IoError::parser_error(format!(
+            "Macro variable `${}` is bound more than once",
+            duplicate
+        )));
+    }
+    check_template(template, &seen)
+}
 
-    fn visit_identifier(&mut self, name: &str) -> Result<Self::Output> {
-        Ok(self
-            .replacements
-            .get(name)
-            .cloned()
-            .unwrap_or_else(|| ASTNode::Identifier(name.to_string())))
+fn check_template(parts: &[TemplatePart], bound: &HashSet<&String>) -> Result<()> {
+    for part in parts {
+        match part {
+            TemplatePart::Variable(name, span) if !bound.contains(name) => {
+                return Err(
+                    IoError::parser_error(format!("Unknown macro variable `${}`", name))
+                        .with_span(*span),
+                )
+            }
+            TemplatePart::Repeat(repetition) => check_template(&repetition.body, bound)?,
+            _ => {}
+        }
     }
+    Ok(())
+}
 
-    fn visit_function(
-        &mut self,
-        name: &str,
-        params: &[Parameter],
-        return_type: &Option<String>,
-        body: &[ASTNode],
-        is_async: bool,
-    ) -> Result<Self::Output> {
-        let visited_body = body.iter()
-            .map(|node| self.visit_node(node))
-            .collect::<Result<Vec<_>>>()?;
-        
-        Ok(ASTNode::Function {
-            name: name.to_string(),
-            params: params.clone(),
-            return_type: return_type.clone(),
-            body: visited_body,
-            is_async,
-        })
+fn pattern_variables(parts: &[PatternPart]) -> Vec<String> {
+    let mut names = Vec::new();
+    for part in parts {
+        match part {
+            PatternPart::Fragment(name, _) => names.push(name.clone()),
+            PatternPart::Repeat(repetition) => names.extend(pattern_variables(&repetition.body)),
+            PatternPart::Token(_) => {}
+        }
     }
+    names
+}
 
-    fn visit_binary_op(
-        &mut self,
-        left: &ASTNode,
-        op: &BinaryOperator,
-        right: &ASTNode,
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::BinaryOp {
-            left: Box::new(self.visit_node(left)?),
-            op: op.clone(),
-            right: Box::new(self.visit_node(right)?),
-        })
+fn template_variables(parts: &[TemplatePart]) -> Vec<String> {
+    let mut names = Vec::new();
+    for part in parts {
+        match part {
+            TemplatePart::Variable(name, _) if !names.contains(name) => names.push(name.clone()),
+            TemplatePart::Repeat(repetition) => {
+                for name in template_variables(&repetition.body) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            _ => {}
+        }
     }
+    names
+}
 
-    fn visit_if(
-        &mut self,
-        condition: &ASTNode,
-        then_branch: &[ASTNode],
-        else_branch: &Option<Vec<ASTNode>>,
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::If {
-            condition: Box::new(self.visit_node(condition)?),
-            then_branch: then_branch.iter()
-                .map(|node| self.visit_node(node))
-                .collect::<Result<Vec<_>>>()?,
-            else_branch: if let Some(else_nodes) = else_branch {
-                Some(else_nodes.iter()
-                    .map(|node| self.visit_node(node))
-                    .collect::<Result<Vec<_>>>()?)
-            } else {
-                None
-            },
-        })
+fn opens(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket
+    )
+}
+
+fn closes(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket
+    )
+}
+
+/// The index of the token closing the one opened at `open`.
+fn closing(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if opens(token.kind) {
+            depth += 1;
+        } else if closes(token.kind) {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
     }
+    None
+}
 
-    fn visit_while(
-        &mut self,
-        condition: &ASTNode,
-        body: &[ASTNode],
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::While {
-            condition: Box::new(self.visit_node(condition)?),
-            body: body.iter()
-                .map(|node| self.visit_node(node))
-                .collect::<Result<Vec<_>>>()?,
-        })
+fn expect(tokens: &[Token], index: usize, kind: TokenKind) -> Result<&Token> {
+    match tokens.get(index) {
+        Some(token) if token.kind == kind => Ok(token),
+        Some(token) if token.kind != TokenKind::EOF => Err(IoError::parser_error(format!(
+            "Expected {:?}, found '{}'",
+            kind, token.lexeme
+        ))
+        .with_span(token.span)),
+        found => {
+            let span = found
+                .or(tokens.last())
+                .map_or(Span::dummy(), |token| token.span);
+            Err(
+                IoError::parser_error(format!("Expected {:?}, found end of input", kind))
+                    .with_span(span),
+            )
+        }
     }
+}
 
-    fn visit_for(
-        &mut self,
-        init: &ASTNode,
-        condition: &ASTNode,
-        update: &ASTNode,
-        body: &[ASTNode],
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::For {
-            init: Box::new(self.visit_node(init)?),
-            condition: Box::new(self.visit_node(condition)?),
-            update: Box::new(self.visit_node(update)?),
-            body: body.iter()
-                .map(|node| self.visit_node(node))
-                .collect::<Result<Vec<_>>>()?,
-        })
+fn unclosed(open: &Token) -> IoError {
+    IoError::parser_error(format!("Unclosed `{}` in macro definition", open.lexeme))
+        .with_span(open.span)
+}
+
+fn misplaced_dollar(dollar: &Token) -> IoError {
+    IoError::parser_error("Expected a variable name or `(` after `$`").with_span(dollar.span)
+}
+
+/// The program in `source` with its macros expanded, as source text for
+/// `io expand`. The expansion is parsed first, so that syntax errors point
+/// into `source` and the macro definitions.
+pub fn expanded_source(
+    source: &str,
+    file_id: FileId,
+    source_map: &mut SourceMap,
+) -> Result<String> {
+    let tokens = Lexer::with_file_id(source, file_id).tokenize()?;
+    let tokens = MacroExpander::new(source_map).expand(tokens)?;
+    Parser::new(tokens.clone().into_iter()).parse_program()?;
+    Ok(render(&tokens))
+}
+
+/// Source text the lexer reads back as `tokens`, one space between each two;
+/// the formatter lays it out again.
+pub fn render(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match token.kind {
+            TokenKind::EOF => continue,
+            TokenKind::String => text.push_str(&quote(&token.lexeme, '"')),
+            TokenKind::Char => text.push_str(&quote(&token.lexeme, '\'')),
+            TokenKind::DocComment => {
+                text.push_str("/// ");
+                text.push_str(&token.lexeme);
+                text.push('\n');
+                continue;
+            }
+            _ => text.push_str(&token.lexeme),
+        }
+        text.push(' ');
     }
+    text
+}
 
-    fn visit_call(
-        &mut self,
-        callee: &ASTNode,
-        args: &[ASTNode],
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::CallExpression {
-            callee: Box::new(self.visit_node(callee)?),
-            arguments: args.iter()
-                .map(|arg| self.visit_node(arg))
-                .collect::<Result<Vec<_>>>()?,
-        })
+/// A string or character literal with the value `value`.
+fn quote(value: &str, delimiter: char) -> String {
+    let mut quoted = String::from(delimiter);
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            c if c == delimiter => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
     }
+    quoted.push(delimiter);
+    quoted
+}
 
-    fn visit_variable_declaration(
-        &mut self,
-        name: &str,
-        initializer: &Option<ASTNode>,
-        type_annotation: &Option<String>,
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::VariableDeclaration {
-            name: name.to_string(),
-            initializer: initializer.as_ref()
-                .map(|init| self.visit_node(init))
-                .transpose()?
-                .map(Box::new),
-            type_annotation: type_annotation.clone(),
-        })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diagnostics::Diagnostic,
+        formatter::{CodeFormatter, FormattingConfig},
+        parser::parse_source,
+        runtime::{Interpreter, Value},
+    };
+
+    fn run(source: &str) -> Value {
+        let program = parse_source(source, FileId(0)).unwrap();
+        Interpreter::new().run(&program).unwrap()
     }
 
-    fn visit_assignment(
-        &mut self,
-        target: &ASTNode,
-        value: &ASTNode,
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::Assignment {
-            target: Box::new(self.visit_node(target)?),
-            value: Box::new(self.visit_node(value)?),
-        })
+    fn expansion_error(source: &str) -> String {
+        parse_source(source, FileId(0))
+            .unwrap_err()
+            .message()
+            .to_string()
     }
 
-    fn visit_return(
-        &mut self,
-        value: &Option<ASTNode>,
-    ) -> Result<Self::Output> {
-        Ok(ASTNode::Return(
-            value.as_ref()
-                .map(|v| self.visit_node(v))
-                .transpose()?
-                .map(Box::new)
-        ))
+    #[test]
+    fn test_fragments_and_repetitions() {
+        let source = "\
+macro square { ($x:expr) => { $x * $x } }
+macro sum {
+    () => { 0 };
+    ($first:expr $(, $rest:expr)*) => { $first $(+ $rest)* }
+}
+macro declare { ($name:ident: $t:ty = $value:expr) => { let $name: $t = $value; } }
+macro twice { ($body:block) => { $body $body } }
+fn main() -> int {
+    declare!(total: int = sum!());
+    twice!({ total += square!(1 + 2); });
+    return total - sum!(1, 2, 3) * 2;
+}";
+        assert_eq!(run(source), Value::Integer(6));
+
+        assert_eq!(
+            expansion_error("macro one { ($x:ident) => { $x } } fn main() { one!(1 + 2); }"),
+            "No rule of macro `one!` matches these arguments"
+        );
+        assert_eq!(
+            expansion_error("fn main() { missing!(1); }"),
+            "Unknown macro `missing!`"
+        );
+        assert_eq!(
+            expansion_error("macro all { ($($x:expr),*) => { $x } } fn main() { all!(1, 2); }"),
+            "`$x` matched a repetition, so it can only be used inside `$(...)`"
+        );
     }
 
-    // TODO: Implement other visitor methods...
+    #[test]
+    fn test_expansion_is_hygienic() {
+        let source = "\
+macro swap { ($a:ident, $b:ident) => { let tmp = $a; $a = $b; $b = tmp; } }
+fn main() -> int {
+    let tmp = 1;
+    let other = 2;
+    swap!(tmp, other);
+    return tmp * 10 + other;
+}";
+        assert_eq!(run(source), Value::Integer(21));
+
+        let mut source_map = SourceMap::new();
+        let expanded = expanded_source(source, FileId(0), &mut source_map).unwrap();
+        let formatted = CodeFormatter::new(&FormattingConfig::default())
+            .format(&expanded)
+            .unwrap();
+        assert_eq!(
+            formatted,
+            "\
+fn main() -> int {
+    let tmp = 1;
+    let other = 2;
+    let tmp__1 = tmp;
+    tmp = other;
+    other = tmp__1;
+    return tmp * 10 + other;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_errors_in_expansions_have_a_backtrace() {
+        let source = "macro broken { ($x:expr) => { let = $x; } }\nfn main() { broken!(1); }";
+        let mut source_map = SourceMap::new();
+        let file_id = source_map.add_file("main.io".into(), source.to_string());
+        let err = expanded_source(source, file_id, &mut source_map).unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "=");
+        let backtrace = source_map.backtrace(span);
+        assert_eq!(backtrace.len(), 1);
+        let (call_site, note) = &backtrace[0];
+        assert_eq!(&source[call_site.start..call_site.end], "broken!(1)");
+        assert_eq!(note, "in this expansion of `broken!`");
+        assert!(Diagnostic::from_error(&err)
+            .report(&source_map)
+            .contains("in this expansion of `broken!`"));
+
+        assert!(expansion_error(
+            "macro forever { () => { forever!() } } fn main() { forever!(); }"
+        )
+        .starts_with("Maximum macro expansion depth exceeded"));
+        assert_eq!(
+            expansion_error("macro m { () => { 1 } } macro m { () => { 2 } }"),
+            "Macro 'm' is already defined"
+        );
+    }
 }
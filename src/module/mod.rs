@@ -1,403 +1,612 @@
+//! Modules. `import net::http::{get, post};` loads `src/net/http.io` under the
+//! project root (the directory holding `io.toml`) and brings its `pub` items
+//! `get` and `post` into scope. A program and every module it imports are
+//! merged into one program, each module's items renamed after its path
+//! (`net::http::get`), so the checkers and backends see unique names and the
+//! compiler emits a single LLVM module.
+
+mod rename;
+
+use crate::{
+    ast::{ASTNode, Attribute},
+    build::{cfg::Cfg, Target},
+    diagnostics::SourceMap,
+    error::IoError,
+    formatter::MANIFEST_NAME,
+    lexer::Lexer,
+    macro_system::MacroExpander,
+    optimizer::const_eval::{ConstEvaluator, STATIC_ASSERT},
+    parser::Parser,
+    semantic::deprecated,
+    span::{FileId, Span},
+    Result,
+};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     path::{Path, PathBuf},
-    sync::Arc,
 };
-use parking_lot::RwLock;
-use crate::{error::IoError, Result};
 
+/// A parsed source file.
 #[derive(Debug, Clone)]
 pub struct Module {
+    /// The module's path, e.g. `net::http`; empty for the file being run.
     pub name: String,
     pub path: PathBuf,
     pub exports: HashMap<String, Export>,
-    pub imports: HashSet<Import>,
-    pub dependencies: Vec<ModuleRef>,
-    pub visibility: ModuleVisibility,
+    pub imports: Vec<Import>,
+    /// The `#![...]` attributes at the top of the file.
+    pub attributes: Vec<Attribute>,
+    /// Everything in the file but its imports and attributes.
+    pub items: Vec<ASTNode>,
 }
 
+/// An item a module defines, importable by other modules if it is public.
 #[derive(Debug, Clone)]
 pub struct Export {
-    name: String,
-    kind: ExportKind,
-    visibility: Visibility,
-    documentation: Option<String>,
+    pub name: String,
+    pub kind: ExportKind,
+    pub visibility: ModuleVisibility,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExportKind {
-    Function(FunctionSignature),
-    Type(TypeInfo),
-    Value(ValueInfo),
-    Module(ModuleRef),
+    Function,
+    Constant,
+    Struct,
+    Enum,
+    Trait,
 }
 
-#[derive(Debug, Clone)]
-pub struct Import {
-    module_path: String,
-    symbols: Vec<ImportedSymbol>,
-    is_wildcard: bool,
-    alias: Option<String>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleVisibility {
+    /// Declared `pub`.
+    Public,
+    Private,
 }
 
+/// `import module_path::{items}`
 #[derive(Debug, Clone)]
-pub struct ModuleRef {
-    name: String,
-    version: Option<String>,
-    path: PathBuf,
+pub struct Import {
+    pub module_path: String,
+    pub items: Vec<String>,
+    pub span: Span,
 }
 
-#[derive(Debug, Clone)]
-pub enum ModuleVisibility {
-    Public,
-    Private,
-    Internal(HashSet<String>),
+impl Module {
+    /// Sorts a parsed file into imports and items. Imported modules may only
+    /// contain items; statements are allowed in the program being run.
+    fn new(name: String, path: PathBuf, program: ASTNode) -> Result<Self> {
+        let nodes = match program {
+            ASTNode::Program(nodes) => nodes,
+            node => vec![node],
+        };
+
+        let mut module = Self {
+            name,
+            path,
+            exports: HashMap::new(),
+            imports: Vec::new(),
+            attributes: Vec::new(),
+            items: Vec::new(),
+        };
+        for node in nodes {
+            let (name, kind, is_pub, span) = match &node {
+                ASTNode::Import {
+                    path, items, span, ..
+                } => {
+                    module.imports.push(Import {
+                        module_path: path.join("::"),
+                        items: items.clone(),
+                        span: *span,
+                    });
+                    continue;
+                }
+                ASTNode::ModuleAttributes { attributes, .. } => {
+                    module.attributes.extend(attributes.iter().cloned());
+                    continue;
+                }
+                ASTNode::Function {
+                    name, is_pub, span, ..
+                } => (name, ExportKind::Function, is_pub, span),
+                ASTNode::Global {
+                    name, is_pub, span, ..
+                } => (name, ExportKind::Constant, is_pub, span),
+                ASTNode::StructDef {
+                    name, is_pub, span, ..
+                } => (name, ExportKind::Struct, is_pub, span),
+                ASTNode::EnumDef {
+                    name, is_pub, span, ..
+                } => (name, ExportKind::Enum, is_pub, span),
+                ASTNode::TraitDef {
+                    name, is_pub, span, ..
+                } => (name, ExportKind::Trait, is_pub, span),
+                ASTNode::Impl { .. } => {
+                    module.items.push(node);
+                    continue;
+                }
+                // Checked at compile time, like an item.
+                _ if node.callee_name() == Some(STATIC_ASSERT) => {
+                    module.items.push(node);
+                    continue;
+                }
+                node if !module.name.is_empty() => {
+                    return Err(IoError::module_error(format!(
+                        "Expected an item at the top level of module {}",
+                        module.name
+                    ))
+                    .with_span(node.span()));
+                }
+                _ => {
+                    module.items.push(node);
+                    continue;
+                }
+            };
+            let visibility = if *is_pub {
+                ModuleVisibility::Public
+            } else {
+                ModuleVisibility::Private
+            };
+            module.exports.insert(
+                name.clone(),
+                Export {
+                    name: name.clone(),
+                    kind,
+                    visibility,
+                    span: *span,
+                },
+            );
+            module.items.push(node);
+        }
+        Ok(module)
+    }
+
+    /// The name `item` of this module is compiled under.
+    fn qualify(&self, item: &str) -> String {
+        if self.name.is_empty() {
+            item.to_string()
+        } else {
+            format!("{}::{}", self.name, item)
+        }
+    }
 }
 
+/// Finds, loads and links the modules of one program.
 pub struct ModuleManager {
-    modules: Arc<RwLock<HashMap<String, Module>>>,
-    module_cache: Arc<RwLock<HashMap<String, ModuleRef>>>,
     root_path: PathBuf,
+    modules: BTreeMap<String, Module>,
+    /// Where node ids continue in the next file, so they stay unique across
+    /// the merged program.
+    next_id: u32,
+    /// What `#[cfg]` attributes are evaluated against.
+    cfg: Cfg,
 }
 
 impl ModuleManager {
     pub fn new<P: AsRef<Path>>(root_path: P) -> Self {
         Self {
-            modules: Arc::new(RwLock::new(HashMap::new())),
-            module_cache: Arc::new(RwLock::new(HashMap::new())),
             root_path: root_path.as_ref().to_path_buf(),
+            modules: BTreeMap::new(),
+            next_id: 0,
+            cfg: Cfg::new(Target::Native),
         }
     }
 
-    pub fn register_module(&self, module: Module) -> Result<()> {
-        let mut modules = self.modules.write();
-        if modules.contains_key(&module.name) {
-            return Err(IoError::validation_error(
-                format!("Module {} already exists", module.name)
-            ));
-        }
-
-        // Validate module path
-        if !module.path.starts_with(&self.root_path) {
-            return Err(IoError::validation_error(
-                format!("Module path must be within root path: {}", self.root_path.display())
-            ));
-        }
-
-        // Validate and resolve dependencies
-        self.validate_dependencies(&module)?;
-        
-        // Cache module reference
-        self.module_cache.write().insert(
-            module.name.clone(),
-            ModuleRef {
-                name: module.name.clone(),
-                version: None,
-                path: module.path.clone(),
-            },
-        );
-
-        modules.insert(module.name.clone(), module);
-        Ok(())
+    /// Loads modules for `cfg` rather than for the host.
+    pub fn with_cfg(mut self, cfg: Cfg) -> Self {
+        self.cfg = cfg;
+        self
     }
 
-    pub fn resolve_module(&self, name: &str) -> Result<Arc<Module>> {
-        let modules = self.modules.read();
-        modules.get(name)
-            .cloned()
-            .map(Arc::new)
-            .ok_or_else(|| IoError::runtime_error(format!("Module {} not found", name)))
+    /// A manager for the project `file` belongs to: rooted at the nearest
+    /// directory holding `io.toml`, or else at the file's own directory.
+    pub fn for_file(file: &Path) -> Self {
+        let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        let dir = file.parent().unwrap_or(Path::new("."));
+        let root = dir
+            .ancestors()
+            .find(|dir| dir.join(MANIFEST_NAME).is_file())
+            .unwrap_or(dir);
+        Self::new(root)
     }
 
-    pub fn validate_dependencies(&self, module: &Module) -> Result<()> {
-        let mut visited = HashSet::new();
-        self.check_circular_dependencies(&module.name, &mut visited, &Vec::new())?;
-        
-        for dep in &module.dependencies {
-            if !self.module_exists(&dep.name) {
-                return Err(IoError::validation_error(
-                    format!("Dependency {} not found for module {}", dep.name, module.name)
-                ));
-            }
-        }
-        Ok(())
+    /// The file holding module `name`: `a::b` is `src/a/b.io` under the root,
+    /// or `a/b.io` in a project without a `src` directory.
+    pub fn module_path(&self, name: &str) -> PathBuf {
+        let src = self.root_path.join("src");
+        let mut path = if src.is_dir() {
+            src
+        } else {
+            self.root_path.clone()
+        };
+        path.extend(name.split("::"));
+        path.set_extension("io");
+        path
     }
 
-    fn check_circular_dependencies(
-        &self,
-        module_name: &str,
-        visited: &mut HashSet<String>,
-        path: &Vec<String>,
-    ) -> Result<()> {
-        if visited.contains(module_name) {
-            if path.contains(&module_name.to_string()) {
-                let cycle = path.iter()
-                    .skip_while(|&n| n != module_name)
-                    .chain(std::iter::once(module_name))
-                    .collect::<Vec<_>>()
-                    .join(" -> ");
-                return Err(IoError::validation_error(
-                    format!("Circular dependency detected: {}", cycle)
-                ));
-            }
-            return Ok(());
+    /// Parses the file `file_id` of `source_map` together with every module it
+    /// imports, directly or not, adding their files to `source_map`. Returns
+    /// one program with the imports resolved and the imported items first, and
+    /// its constants evaluated.
+    pub fn load_program(&mut self, file_id: FileId, source_map: &mut SourceMap) -> Result<ASTNode> {
+        let path = source_map
+            .path(file_id)
+            .cloned()
+            .ok_or_else(|| IoError::module_error("Unknown source file"))?;
+        let source = source_map
+            .get_source(&path)
+            .map(|source| source.content().to_string())
+            .unwrap_or_default();
+        let root = self.parse_module(String::new(), path, &source, file_id, source_map)?;
+        self.register_module(root)?;
+        self.load_imports("", source_map)?;
+
+        let mut items = Vec::new();
+        for name in self.get_dependency_graph().order()? {
+            let module = &self.modules[&name];
+            let names = self.scope(module)?;
+            let renamed = rename::rename(module.items.clone(), &names)?;
+            items.extend(deprecated::inherit(&module.attributes, renamed));
         }
+        ConstEvaluator::new().evaluate(ASTNode::Program(items))
+    }
 
-        visited.insert(module_name.to_string());
-        let mut new_path = path.clone();
-        new_path.push(module_name.to_string());
-
-        if let Some(module) = self.modules.read().get(module_name) {
-            for dep in &module.dependencies {
-                self.check_circular_dependencies(&dep.name, visited, &new_path)?;
-            }
+    pub fn register_module(&mut self, module: Module) -> Result<()> {
+        if self.modules.contains_key(&module.name) {
+            return Err(IoError::validation_error(format!(
+                "Module {} already exists",
+                module.name
+            )));
         }
-
+        self.modules.insert(module.name.clone(), module);
         Ok(())
     }
 
-    pub fn module_exists(&self, name: &str) -> bool {
-        self.modules.read().contains_key(name)
-    }
-
-    pub fn get_module_exports(&self, name: &str) -> Result<HashMap<String, Export>> {
-        self.modules.read()
-            .get(name)
-            .map(|m| m.exports.clone())
-            .ok_or_else(|| IoError::runtime_error(format!("Module {} not found", name)))
+    pub fn resolve_module(&self, name: &str) -> Option<&Module> {
+        self.modules.get(name)
     }
 
-    pub fn verify_module_integrity(&self) -> Result<()> {
-        let modules = self.modules.read();
-        
-        // Verify all imports can be resolved
-        for module in modules.values() {
+    /// Which loaded module imports which.
+    pub fn get_dependency_graph(&self) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for (name, module) in &self.modules {
+            graph.add_node(name);
             for import in &module.imports {
-                if !self.module_exists(&import.module_path) {
-                    return Err(IoError::validation_error(format!(
-                        "Module {} imports from non-existent module {}",
-                        module.name,
-                        import.module_path
-                    )));
-                }
-
-                // Verify imported symbols exist
-                if !import.is_wildcard {
-                    let exported_symbols = self.get_module_exports(&import.module_path)?;
-                    for symbol in &import.symbols {
-                        if !exported_symbols.contains_key(&symbol.name) {
-                            return Err(IoError::validation_error(format!(
-                                "Symbol {} not found in module {}",
-                                symbol.name,
-                                import.module_path
-                            )));
-                        }
-                    }
-                }
+                graph.add_edge(name, &import.module_path);
             }
         }
-        
-        Ok(())
+        graph
     }
 
-    pub fn hot_reload(&mut self, module_name: &str, new_source: &str) -> Result<()> {
-        let mut parser = Parser::new(new_source);
-        let ast = parser.parse()?;
-        
-        // Create temporary module
-        let temp_module = Module {
-            name: module_name.to_string(),
-            path: self.modules.read().get(module_name)
-                .ok_or_else(|| IoError::runtime_error("Module not found"))?
-                .path.clone(),
-            exports: HashMap::new(),
-            imports: HashSet::new(),
-            dependencies: Vec::new(),
-            visibility: ModuleVisibility::Private,
-        };
-
-        // Validate new module
-        self.validate_dependencies(&temp_module)?;
-        
-        // Update module atomically
-        let mut modules = self.modules.write();
-        if let Some(module) = modules.get_mut(module_name) {
-            *module = temp_module;
-            Ok(())
-        } else {
-            Err(IoError::runtime_error("Module not found"))
-        }
+    fn parse_module(
+        &mut self,
+        name: String,
+        path: PathBuf,
+        source: &str,
+        file_id: FileId,
+        source_map: &mut SourceMap,
+    ) -> Result<Module> {
+        let tokens = Lexer::with_file_id(source, file_id).tokenize()?;
+        let tokens = MacroExpander::new(source_map).expand(tokens)?;
+        let mut parser = Parser::new(tokens.into_iter()).with_first_id(self.next_id);
+        let program = parser.parse_program()?;
+        self.next_id = parser.next_node_id();
+        Module::new(name, path, self.cfg.strip(program)?)
     }
 
-    pub fn get_dependency_graph(&self) -> Result<DependencyGraph> {
-        let modules = self.modules.read();
-        let mut graph = DependencyGraph::new();
-
-        for (name, module) in modules.iter() {
-            graph.add_node(name.clone());
-            for dep in &module.dependencies {
-                graph.add_edge(name.clone(), dep.name.clone())?;
+    /// Loads the modules `name` imports that are not loaded yet, and theirs.
+    fn load_imports(&mut self, name: &str, source_map: &mut SourceMap) -> Result<()> {
+        let imports = self.modules[name].imports.clone();
+        for import in imports {
+            if self.modules.contains_key(&import.module_path) {
+                continue;
             }
+            let path = self.module_path(&import.module_path);
+            let source = std::fs::read_to_string(&path).map_err(|_| {
+                IoError::module_error(format!(
+                    "Cannot find module {}: no file {}",
+                    import.module_path,
+                    path.display()
+                ))
+                .with_span(import.span)
+            })?;
+            let file_id = source_map.add_file(path.clone(), source.clone());
+            let module_name = import.module_path.clone();
+            let module = self.parse_module(module_name, path, &source, file_id, source_map)?;
+            self.register_module(module)?;
+            self.load_imports(&import.module_path, source_map)?;
         }
-
-        graph.validate()?;
-        Ok(graph)
-    }
-
-    pub fn analyze_module_metrics(&self, name: &str) -> Result<ModuleMetrics> {
-        let modules = self.modules.read();
-        let module = modules.get(name)
-            .ok_or_else(|| IoError::runtime_error("Module not found"))?;
-
-        Ok(ModuleMetrics {
-            export_count: module.exports.len(),
-            import_count: module.imports.len(),
-            dependency_count: module.dependencies.len(),
-            is_cyclic: self.has_cyclic_dependencies(name)?,
-            visibility: module.visibility.clone(),
-        })
+        Ok(())
     }
 
-    fn has_cyclic_dependencies(&self, start: &str) -> Result<bool> {
-        let mut visited = HashSet::new();
-        let mut stack = HashSet::new();
-        
-        fn dfs(
-            current: &str,
-            modules: &HashMap<String, Module>,
-            visited: &mut HashSet<String>,
-            stack: &mut HashSet<String>,
-        ) -> bool {
-            if stack.contains(current) {
-                return true;
-            }
-            if visited.contains(current) {
-                return false;
-            }
-
-            visited.insert(current.to_string());
-            stack.insert(current.to_string());
-
-            if let Some(module) = modules.get(current) {
-                for dep in &module.dependencies {
-                    if dfs(&dep.name, modules, visited, stack) {
-                        return true;
-                    }
+    /// The names `module` can refer to, its own items and those it imports,
+    /// each mapped to the name it is compiled under.
+    fn scope(&self, module: &Module) -> Result<HashMap<String, String>> {
+        let mut names: HashMap<String, String> = module
+            .exports
+            .keys()
+            .map(|item| (item.clone(), module.qualify(item)))
+            .collect();
+
+        for import in &module.imports {
+            let target = &self.modules[&import.module_path];
+            for item in &import.items {
+                let export = target.exports.get(item).ok_or_else(|| {
+                    IoError::module_error(format!("Module {} has no item {}", target.name, item))
+                        .with_span(import.span)
+                })?;
+                if export.visibility == ModuleVisibility::Private {
+                    return Err(IoError::module_error(format!(
+                        "{} is private to module {}",
+                        item, target.name
+                    ))
+                    .with_span(import.span)
+                    .with_note(export.span, "declared here without `pub`"));
+                }
+                if names.insert(item.clone(), target.qualify(item)).is_some() {
+                    return Err(IoError::module_error(format!(
+                        "{} is defined more than once in this module",
+                        item
+                    ))
+                    .with_span(import.span));
                 }
             }
-
-            stack.remove(current);
-            false
         }
-
-        Ok(dfs(start, &self.modules.read(), &mut visited, &mut stack))
+        Ok(names)
     }
 }
 
-#[derive(Debug)]
+/// The import relation between modules, which must be acyclic.
+#[derive(Debug, Default)]
 pub struct DependencyGraph {
-    nodes: HashSet<String>,
-    edges: HashMap<String, HashSet<String>>,
+    /// The modules each module imports. Sorted, so errors and the order of
+    /// items in the merged program do not change from run to run.
+    edges: BTreeMap<String, BTreeSet<String>>,
 }
 
 impl DependencyGraph {
-    pub fn new() -> Self {
-        Self {
-            nodes: HashSet::new(),
-            edges: HashMap::new(),
-        }
+    pub fn add_node(&mut self, name: &str) {
+        self.edges.entry(name.to_string()).or_default();
     }
 
-    pub fn add_node(&mut self, name: String) {
-        self.nodes.insert(name);
+    /// Records that `from` imports `to`.
+    pub fn add_edge(&mut self, from: &str, to: &str) {
+        self.add_node(to);
+        self.edges
+            .entry(from.to_string())
+            .or_default()
+            .insert(to.to_string());
     }
 
-    pub fn add_edge(&mut self, from: String, to: String) -> Result<()> {
-        if !self.nodes.contains(&from) || !self.nodes.contains(&to) {
-            return Err(IoError::validation_error("Node not found"));
-        }
-        self.edges.entry(from)
-            .or_insert_with(HashSet::new)
-            .insert(to);
-        Ok(())
+    pub fn validate(&self) -> Result<()> {
+        self.order().map(|_| ())
     }
 
-    pub fn validate(&self) -> Result<()> {
-        // Check for cycles
-        let mut visited = HashSet::new();
-        let mut stack = HashSet::new();
-
-        for node in &self.nodes {
-            if !visited.contains(node) {
-                if self.has_cycle(node, &mut visited, &mut stack) {
-                    return Err(IoError::validation_error("Cyclic dependency detected"));
-                }
-            }
+    /// Every module after the modules it imports, or an error naming a cycle.
+    pub fn order(&self) -> Result<Vec<String>> {
+        let mut order = Vec::new();
+        let mut done = BTreeSet::new();
+        for node in self.edges.keys() {
+            self.visit(node, &mut Vec::new(), &mut done, &mut order)?;
         }
-        Ok(())
+        Ok(order)
     }
 
-    fn has_cycle(&self, node: &str, visited: &mut HashSet<String>, stack: &mut HashSet<String>) -> bool {
-        if stack.contains(node) {
-            return true;
+    fn visit<'a>(
+        &'a self,
+        node: &'a str,
+        path: &mut Vec<&'a str>,
+        done: &mut BTreeSet<&'a str>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if done.contains(node) {
+            return Ok(());
         }
-        if visited.contains(node) {
-            return false;
+        if let Some(start) = path.iter().position(|visiting| *visiting == node) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(node);
+            return Err(IoError::validation_error(format!(
+                "Import cycle: {}",
+                cycle.join(" -> ")
+            )));
         }
 
-        visited.insert(node.to_string());
-        stack.insert(node.to_string());
+        path.push(node);
+        for next in &self.edges[node] {
+            self.visit(next, path, done, order)?;
+        }
+        path.pop();
+        done.insert(node);
+        order.push(node.to_string());
+        Ok(())
+    }
+}
 
-        if let Some(deps) = self.edges.get(node) {
-            for dep in deps {
-                if self.has_cycle(dep, visited, stack) {
-                    return true;
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::Interpreter, runtime::Value, semantic::resolve, types::checker::TypeChecker,
+    };
+
+    /// Writes `files` into a fresh project directory with an `io.toml`.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("io-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(MANIFEST_NAME), "").unwrap();
+        for (path, source) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
         }
+        root
+    }
 
-        stack.remove(node);
-        false
+    fn load(root: &Path, main: &str) -> Result<ASTNode> {
+        let path = root.join(main);
+        let source = std::fs::read_to_string(&path).unwrap();
+        let mut source_map = SourceMap::new();
+        let file_id = source_map.add_file(path.clone(), source);
+        ModuleManager::for_file(&path).load_program(file_id, &mut source_map)
     }
-}
 
-#[derive(Debug)]
-pub struct ModuleMetrics {
-    export_count: usize,
-    import_count: usize,
-    dependency_count: usize,
-    is_cyclic: bool,
-    visibility: ModuleVisibility,
-}
+    fn item_names(program: &ASTNode) -> Vec<String> {
+        let ASTNode::Program(items) = program else {
+            return Vec::new();
+        };
+        items
+            .iter()
+            .filter_map(|item| match item {
+                ASTNode::Function { name, .. } | ASTNode::EnumDef { name, .. } => {
+                    Some(name.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
 
-#[derive(Debug, Clone)]
-pub struct FunctionSignature {
-    params: Vec<(String, String)>, // (name, type)
-    return_type: Option<String>,
-    is_async: bool,
-}
+    #[test]
+    fn test_imports_resolve_to_qualified_items() {
+        let root = project(
+            "modules-resolve",
+            &[
+                (
+                    "src/main.io",
+                    "import net::http::{get, Status};
+                     fn main() -> int {
+                         return match get(20) {
+                             Status::Ok(code) => code,
+                             Status::Failed => 0,
+                         };
+                     }",
+                ),
+                (
+                    "src/net/http.io",
+                    "import net::codes::{base};
+                     pub enum Status { Ok(int), Failed }
+                     fn double(code: int) -> int { return code * 2; }
+                     pub fn get(code: int) -> Status { return Status::Ok(double(code) + base()); }",
+                ),
+                ("src/net/codes.io", "pub fn base() -> int { return 160; }"),
+            ],
+        );
+        let program = load(&root, "src/main.io").unwrap();
+        assert_eq!(
+            item_names(&program),
+            [
+                "net::codes::base",
+                "net::http::Status",
+                "net::http::double",
+                "net::http::get",
+                "main"
+            ]
+        );
 
-#[derive(Debug, Clone)]
-pub struct TypeInfo {
-    fields: Vec<(String, String)>, // (name, type)
-    methods: Vec<FunctionSignature>,
-}
+        let resolution = resolve::resolve(&program).unwrap();
+        TypeChecker::new().check(&program, &resolution).unwrap();
+        let value = Interpreter::new().run(&program).unwrap();
+        assert!(matches!(value, Value::Integer(200)), "{:?}", value);
+    }
 
-#[derive(Debug, Clone)]
-pub struct ValueInfo {
-    type_name: String,
-    is_constant: bool,
-}
+    #[test]
+    fn test_import_errors() {
+        let root = project(
+            "modules-errors",
+            &[
+                ("private.io", "import util::{secret};"),
+                ("missing_item.io", "import util::{nothing};"),
+                ("missing_module.io", "import gone::{x};"),
+                ("cycle.io", "import a::{f};"),
+                ("util.io", "fn secret() {}"),
+                ("a.io", "import b::{g}; pub fn f() { g(); }"),
+                ("b.io", "import a::{f}; pub fn g() { f(); }"),
+            ],
+        );
+        let error = |main: &str| load(&root, main).unwrap_err().message().to_string();
+        assert_eq!(error("private.io"), "secret is private to module util");
+        assert_eq!(error("missing_item.io"), "Module util has no item nothing");
+        assert!(error("missing_module.io").starts_with("Cannot find module gone: no file "));
+        assert_eq!(error("cycle.io"), "Import cycle: a -> b -> a");
+    }
 
-#[derive(Debug, Clone)]
-pub struct ImportedSymbol {
-    name: String,
-    alias: Option<String>,
-}
+    #[test]
+    fn test_cfg_leaves_modules_unloaded() {
+        let root = project(
+            "modules-cfg",
+            &[(
+                "main.io",
+                "#[cfg(target_os = \"windows\")]\n\
+                 import win::{console};\n\
+                 #[cfg(not(target_os = \"windows\"))]\n\
+                 fn console() -> int { return 1; }\n\
+                 #[test]\n\
+                 fn console_works() {}",
+            )],
+        );
+        let path = root.join("main.io");
+        let source = std::fs::read_to_string(&path).unwrap();
+        let mut source_map = SourceMap::new();
+        let file_id = source_map.add_file(path.clone(), source);
+        let program = ModuleManager::for_file(&path)
+            .with_cfg(Cfg::new(Target::X86_64Linux).with_test(true))
+            .load_program(file_id, &mut source_map)
+            .unwrap();
+        assert_eq!(item_names(&program), ["console", "console_works"]);
+
+        // On Windows the import is kept, and `win.io` doesn't exist.
+        let mut source_map = SourceMap::new();
+        let file_id = source_map.add_file(path.clone(), std::fs::read_to_string(&path).unwrap());
+        let err = ModuleManager::for_file(&path)
+            .with_cfg(Cfg::new(Target::X86_64Windows))
+            .load_program(file_id, &mut source_map)
+            .unwrap_err();
+        assert!(err.message().starts_with("Cannot find module win"));
+    }
 
-#[derive(Debug, Clone)]
-pub enum Visibility {
-    Public,
-    Private,
-    Protected(Vec<String>),
+    #[test]
+    fn test_module_attributes() {
+        let root = project(
+            "modules-attributes",
+            &[
+                (
+                    "main.io",
+                    "import legacy::{old};\n\
+                     import win::{console};\n\
+                     fn main() -> int { return old(); }",
+                ),
+                (
+                    "legacy.io",
+                    "#![deprecated(note = \"use `fresh`\")]\n\
+                     pub fn old() -> int { return helper(); }\n\
+                     fn helper() -> int { return 1; }",
+                ),
+                ("win.io", "#![cfg(target_os = \"windows\")]\npub fn console() {}"),
+            ],
+        );
+        let path = root.join("main.io");
+        let load_for = |target| {
+            let mut source_map = SourceMap::new();
+            let file_id =
+                source_map.add_file(path.clone(), std::fs::read_to_string(&path).unwrap());
+            ModuleManager::for_file(&path)
+                .with_cfg(Cfg::new(target))
+                .load_program(file_id, &mut source_map)
+        };
+
+        // Off Windows, `win.io` is empty.
+        let err = load_for(Target::X86_64Linux).unwrap_err();
+        assert_eq!(err.message(), "Module win has no item console");
+
+        // Only the use outside the deprecated module is reported.
+        let program = load_for(Target::X86_64Windows).unwrap();
+        let resolution = resolve::resolve(&program).unwrap();
+        let warnings = deprecated::check(&program, &resolution).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "Use of deprecated function `legacy::old`"
+        );
+        assert_eq!(warnings[0].hints, ["use `fresh`"]);
+    }
 }
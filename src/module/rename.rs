@@ -0,0 +1,466 @@
+//! Renames a module's items to their qualified names, and every reference to
+//! an item, its own or imported, to match.
+
+use crate::{
+    ast::{
+        ASTNode, Field, MatchArm, Parameter, Pattern, PatternFields, TraitMethod, Type, Variant,
+        VariantFields,
+    },
+    visitor::{fold_children, Folder},
+    Result,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Renames `items` using `names`, which maps each name the module can refer
+/// to (its own items and the items it imports) to the name it is compiled
+/// under. Local variables and type parameters shadow items as usual.
+pub(super) fn rename(items: Vec<ASTNode>, names: &HashMap<String, String>) -> Result<Vec<ASTNode>> {
+    let mut renamer = Renamer {
+        names,
+        locals: HashSet::new(),
+    };
+    items
+        .into_iter()
+        .map(|item| {
+            let item = renamer.fold_node(item)?;
+            Ok(renamer.definition(item))
+        })
+        .collect()
+}
+
+struct Renamer<'a> {
+    names: &'a HashMap<String, String>,
+    /// Variables and type parameters in scope.
+    locals: HashSet<String>,
+}
+
+impl Renamer<'_> {
+    /// The name `name` refers to. In a path only the first segment is renamed,
+    /// so `Shape::Circle` follows its enum.
+    fn name(&self, name: String) -> String {
+        let head = name.split("::").next().unwrap_or(&name);
+        if self.locals.contains(head) {
+            return name;
+        }
+        match self.names.get(head) {
+            Some(renamed) => format!("{}{}", renamed, &name[head.len()..]),
+            None => name,
+        }
+    }
+
+    fn ty(&self, ty: Type) -> Type {
+        match ty {
+            Type::Named(name) => Type::Named(self.name(name)),
+            Type::Dyn(name) => Type::Dyn(self.name(name)),
+            Type::Generic { name, args } => Type::Generic {
+                name: self.name(name),
+                args: args.into_iter().map(|arg| self.ty(arg)).collect(),
+            },
+            Type::Struct { name, fields } => Type::Struct {
+                name: self.name(name),
+                fields: fields
+                    .into_iter()
+                    .map(|(field, ty)| (field, self.ty(ty)))
+                    .collect(),
+            },
+            Type::Array { elem_type, size } => Type::Array {
+                elem_type: Box::new(self.ty(*elem_type)),
+                size,
+            },
+            Type::ConstArray { elem_type, length } => Type::ConstArray {
+                elem_type: Box::new(self.ty(*elem_type)),
+                length: self.name(length),
+            },
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => Type::Function {
+                params: params.into_iter().map(|param| self.ty(param)).collect(),
+                return_type: Box::new(self.ty(*return_type)),
+                is_async,
+            },
+            Type::Pointer(inner) => Type::Pointer(Box::new(self.ty(*inner))),
+            Type::Ref { mutable, inner } => Type::Ref {
+                mutable,
+                inner: Box::new(self.ty(*inner)),
+            },
+            ty => ty,
+        }
+    }
+
+    /// Renames the parameter types and brings the parameters into scope.
+    fn params(&mut self, params: Vec<Parameter>) -> Vec<Parameter> {
+        let params: Vec<Parameter> = params
+            .into_iter()
+            .map(|param| Parameter {
+                type_annotation: self.ty(param.type_annotation),
+                ..param
+            })
+            .collect();
+        self.locals
+            .extend(params.iter().map(|param| param.name.clone()));
+        params
+    }
+
+    /// Renames the paths in `pattern` and brings its bindings into scope.
+    fn pattern(&mut self, pattern: Pattern) -> Pattern {
+        match pattern {
+            Pattern::Binding { name, id, span } => {
+                self.locals.insert(name.clone());
+                Pattern::Binding { name, id, span }
+            }
+            Pattern::Struct { path, fields, span } => Pattern::Struct {
+                path: self.name(path),
+                fields: match fields {
+                    PatternFields::Unit => PatternFields::Unit,
+                    PatternFields::Tuple(patterns) => PatternFields::Tuple(
+                        patterns.into_iter().map(|p| self.pattern(p)).collect(),
+                    ),
+                    PatternFields::Named { fields, rest } => PatternFields::Named {
+                        fields: fields
+                            .into_iter()
+                            .map(|(field, p)| (field, self.pattern(p)))
+                            .collect(),
+                        rest,
+                    },
+                },
+                span,
+            },
+            Pattern::Array {
+                elements,
+                rest,
+                span,
+            } => Pattern::Array {
+                elements: elements.into_iter().map(|p| self.pattern(p)).collect(),
+                rest,
+                span,
+            },
+            Pattern::Or { alternatives, span } => Pattern::Or {
+                alternatives: alternatives.into_iter().map(|p| self.pattern(p)).collect(),
+                span,
+            },
+            pattern => pattern,
+        }
+    }
+
+    /// Runs `f` in a new scope, forgetting the variables it declares.
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = self.locals.clone();
+        let result = f(self);
+        self.locals = saved;
+        result
+    }
+
+    /// Gives a top-level item its qualified name. Impl methods keep theirs:
+    /// they are found through the type they are implemented for.
+    fn definition(&self, mut item: ASTNode) -> ASTNode {
+        if let ASTNode::Function { name, .. }
+        | ASTNode::Global { name, .. }
+        | ASTNode::StructDef { name, .. }
+        | ASTNode::EnumDef { name, .. }
+        | ASTNode::TraitDef { name, .. } = &mut item
+        {
+            if let Some(qualified) = self.names.get(name.as_str()) {
+                *name = qualified.clone();
+            }
+        }
+        item
+    }
+}
+
+impl Folder for Renamer<'_> {
+    fn fold_node(&mut self, node: ASTNode) -> Result<ASTNode> {
+        match node {
+            ASTNode::Identifier { name, id, span } => Ok(ASTNode::Identifier {
+                name: self.name(name),
+                id,
+                span,
+            }),
+            ASTNode::StructLiteral {
+                name,
+                fields,
+                id,
+                span,
+            } => {
+                let name = self.name(name);
+                fold_children(
+                    self,
+                    ASTNode::StructLiteral {
+                        name,
+                        fields,
+                        id,
+                        span,
+                    },
+                )
+            }
+            ASTNode::Cast {
+                value,
+                target,
+                id,
+                span,
+            } => {
+                let target = self.ty(target);
+                fold_children(
+                    self,
+                    ASTNode::Cast {
+                        value,
+                        target,
+                        id,
+                        span,
+                    },
+                )
+            }
+            ASTNode::Let {
+                name,
+                type_annotation,
+                value,
+                id,
+                span,
+            } => {
+                let type_annotation = type_annotation.map(|ty| self.ty(ty));
+                let node = fold_children(
+                    self,
+                    ASTNode::Let {
+                        name: name.clone(),
+                        type_annotation,
+                        value,
+                        id,
+                        span,
+                    },
+                )?;
+                self.locals.insert(name);
+                Ok(node)
+            }
+            ASTNode::Function {
+                name,
+                type_params,
+                bounds,
+                params,
+                return_type,
+                body,
+                is_async,
+                is_const,
+                is_pub,
+                attributes,
+                id,
+                span,
+            } => self.scoped(|this| {
+                this.locals.extend(type_params.iter().cloned());
+                let node = ASTNode::Function {
+                    name,
+                    bounds: bounds
+                        .into_iter()
+                        .map(|(param, bound)| (param, this.name(bound)))
+                        .collect(),
+                    params: this.params(params),
+                    return_type: return_type.map(|ty| this.ty(ty)),
+                    type_params,
+                    body,
+                    is_async,
+                    is_const,
+                    is_pub,
+                    attributes,
+                    id,
+                    span,
+                };
+                fold_children(this, node)
+            }),
+            ASTNode::StructDef {
+                name,
+                type_params,
+                fields,
+                is_pub,
+                attributes,
+                id,
+                span,
+            } => Ok(self.scoped(|this| {
+                this.locals.extend(type_params.iter().cloned());
+                let fields = fields
+                    .into_iter()
+                    .map(|field| Field {
+                        type_annotation: this.ty(field.type_annotation),
+                        ..field
+                    })
+                    .collect();
+                ASTNode::StructDef {
+                    name,
+                    type_params,
+                    fields,
+                    is_pub,
+                    attributes,
+                    id,
+                    span,
+                }
+            })),
+            ASTNode::EnumDef {
+                name,
+                type_params,
+                variants,
+                is_pub,
+                id,
+                span,
+            } => Ok(self.scoped(|this| {
+                this.locals.extend(type_params.iter().cloned());
+                let variants = variants
+                    .into_iter()
+                    .map(|variant| Variant {
+                        fields: match variant.fields {
+                            VariantFields::Unit => VariantFields::Unit,
+                            VariantFields::Tuple(types) => VariantFields::Tuple(
+                                types.into_iter().map(|ty| this.ty(ty)).collect(),
+                            ),
+                            VariantFields::Named(fields) => VariantFields::Named(
+                                fields
+                                    .into_iter()
+                                    .map(|field| Field {
+                                        type_annotation: this.ty(field.type_annotation),
+                                        ..field
+                                    })
+                                    .collect(),
+                            ),
+                        },
+                        ..variant
+                    })
+                    .collect();
+                ASTNode::EnumDef {
+                    name,
+                    type_params,
+                    variants,
+                    is_pub,
+                    id,
+                    span,
+                }
+            })),
+            ASTNode::TraitDef {
+                name,
+                type_params,
+                methods,
+                is_pub,
+                id,
+                span,
+            } => {
+                let methods = methods
+                    .into_iter()
+                    .map(|method| {
+                        self.scoped(|this| {
+                            this.locals.extend(type_params.iter().cloned());
+                            let params = this.params(method.params);
+                            let return_type = method.return_type.map(|ty| this.ty(ty));
+                            let body = method.body.map(|body| this.fold_nodes(body)).transpose()?;
+                            Ok(TraitMethod {
+                                params,
+                                return_type,
+                                body,
+                                ..method
+                            })
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(ASTNode::TraitDef {
+                    name,
+                    type_params,
+                    methods,
+                    is_pub,
+                    id,
+                    span,
+                })
+            }
+            ASTNode::Impl {
+                trait_name,
+                trait_args,
+                self_type,
+                methods,
+                id,
+                span,
+            } => {
+                let trait_name = trait_name.map(|name| self.name(name));
+                let trait_args = trait_args.into_iter().map(|arg| self.ty(arg)).collect();
+                let self_type = self.ty(self_type);
+                fold_children(
+                    self,
+                    ASTNode::Impl {
+                        trait_name,
+                        trait_args,
+                        self_type,
+                        methods,
+                        id,
+                        span,
+                    },
+                )
+            }
+            ASTNode::Closure {
+                params,
+                return_type,
+                body,
+                is_move,
+                id,
+                span,
+            } => self.scoped(|this| {
+                let node = ASTNode::Closure {
+                    params: this.params(params),
+                    return_type: return_type.map(|ty| this.ty(ty)),
+                    body,
+                    is_move,
+                    id,
+                    span,
+                };
+                fold_children(this, node)
+            }),
+            ASTNode::For {
+                pattern,
+                iterable,
+                body,
+                id,
+                span,
+            } => {
+                let iterable = Box::new(self.fold_node(*iterable)?);
+                let (pattern, body) = self.scoped(|this| {
+                    let pattern = this.pattern(pattern);
+                    this.fold_nodes(body).map(|body| (pattern, body))
+                })?;
+                Ok(ASTNode::For {
+                    pattern,
+                    iterable,
+                    body,
+                    id,
+                    span,
+                })
+            }
+            ASTNode::Match {
+                scrutinee,
+                arms,
+                id,
+                span,
+            } => {
+                let scrutinee = Box::new(self.fold_node(*scrutinee)?);
+                let arms = arms
+                    .into_iter()
+                    .map(|arm| {
+                        self.scoped(|this| {
+                            let pattern = this.pattern(arm.pattern);
+                            Ok(MatchArm {
+                                pattern,
+                                guard: arm.guard.map(|guard| this.fold_node(guard)).transpose()?,
+                                body: this.fold_node(arm.body)?,
+                                ..arm
+                            })
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(ASTNode::Match {
+                    scrutinee,
+                    arms,
+                    id,
+                    span,
+                })
+            }
+            node => fold_children(self, node),
+        }
+    }
+
+    /// Each list of statements is a scope of its own.
+    fn fold_nodes(&mut self, nodes: Vec<ASTNode>) -> Result<Vec<ASTNode>> {
+        self.scoped(|this| nodes.into_iter().map(|node| this.fold_node(node)).collect())
+    }
+}
@@ -0,0 +1,850 @@
+//! Compile-time evaluation of `const` items, `static_assert`s and the lengths
+//! of `[T; N]` array types. Constant expressions may call `const fn`s, which
+//! loop and recurse like other functions but can't reach anything with an
+//! effect: I/O, closures, `await`, globals that aren't constants. The
+//! interpreter runs them under a step budget, and the values are inlined, so
+//! the passes after this one see literals where constants were used.
+
+use crate::{
+    ast::{captures, ASTNode, Literal, NodeId, Parameter, Type, UnaryOperator},
+    error::IoError,
+    runtime::{convert, Interpreter, Value},
+    span::Span,
+    stdlib::prelude,
+    visitor::{fold_children, walk_nodes, walk_place, Folder, Visitor},
+    Result,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+/// The builtin checking a condition at compile time: `static_assert(condition)`
+/// or `static_assert(condition, message)`.
+pub const STATIC_ASSERT: &str = "static_assert";
+
+/// Loop iterations and calls one constant may take to evaluate.
+pub const DEFAULT_STEP_BUDGET: usize = 1_000_000;
+
+/// A `const` item.
+struct Constant {
+    type_annotation: Option<Type>,
+    value: ASTNode,
+    span: Span,
+    /// Set once evaluated.
+    result: Option<Value>,
+}
+
+/// What a name used in a constant expression refers to.
+enum Referent {
+    Constant,
+    ConstFn,
+    /// A function that isn't a `const fn`, defined at the span.
+    Function(Span),
+    Builtin,
+    Variant,
+    Other,
+}
+
+pub struct ConstEvaluator {
+    budget: usize,
+    interpreter: Interpreter,
+    /// Top-level functions: whether each is a `const fn`, and its span.
+    functions: HashMap<String, (bool, Span)>,
+    /// The names each `const fn` uses without declaring them.
+    const_fn_uses: HashMap<String, Vec<String>>,
+    enums: HashSet<String>,
+    constants: HashMap<String, Constant>,
+    /// Constants being evaluated, innermost last.
+    pending: Vec<String>,
+}
+
+impl ConstEvaluator {
+    pub fn new() -> Self {
+        Self::with_step_budget(DEFAULT_STEP_BUDGET)
+    }
+
+    pub fn with_step_budget(budget: usize) -> Self {
+        Self {
+            budget,
+            interpreter: Interpreter::with_io(Box::new(io::empty()), Box::new(io::sink())),
+            functions: HashMap::new(),
+            const_fn_uses: HashMap::new(),
+            enums: HashSet::new(),
+            constants: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Evaluates the constants of `program` and checks its `static_assert`s.
+    /// Returns the program without either, every use of a constant replaced
+    /// by its value and every array length by the number.
+    pub fn evaluate(&mut self, program: ASTNode) -> Result<ASTNode> {
+        let items = match program {
+            ASTNode::Program(items) => items,
+            item => vec![item],
+        };
+        let (globals, items): (Vec<ASTNode>, Vec<ASTNode>) = items
+            .into_iter()
+            .partition(|item| matches!(item, ASTNode::Global { .. }));
+
+        self.declare(prelude::items().iter().chain(&items))?;
+        let mut order = Vec::new();
+        for global in globals {
+            let ASTNode::Global {
+                name,
+                type_annotation,
+                value,
+                span,
+                ..
+            } = global
+            else {
+                unreachable!("only `const` items were partitioned off");
+            };
+            if let Some(previous) = self.constants.get(&name) {
+                return Err(IoError::validation_error(format!(
+                    "Constant `{}` is defined twice",
+                    name
+                ))
+                .with_span(span)
+                .with_note(previous.span, "first defined here"));
+            }
+            self.constants.insert(
+                name.clone(),
+                Constant {
+                    type_annotation,
+                    value: *value,
+                    span,
+                    result: None,
+                },
+            );
+            order.push(name);
+        }
+
+        for item in prelude::items().iter().chain(&items) {
+            if let ASTNode::Function {
+                params,
+                body,
+                is_const: true,
+                ..
+            } = item
+            {
+                self.check_const_fn(params, body)?;
+            }
+        }
+        for name in &order {
+            self.constant(name)?;
+        }
+
+        Ok(ASTNode::Program(self.fold_nodes(items)?))
+    }
+
+    /// Records the top-level functions and enums, and defines the `const fn`s
+    /// and enums in the interpreter.
+    fn declare<'a>(&mut self, items: impl Iterator<Item = &'a ASTNode>) -> Result<()> {
+        let mut definitions = Vec::new();
+        for item in items {
+            match item {
+                ASTNode::Function {
+                    name,
+                    params,
+                    body,
+                    is_const,
+                    span,
+                    ..
+                } => {
+                    self.functions.insert(name.clone(), (*is_const, *span));
+                    if *is_const {
+                        let uses = captures(params, &block(body));
+                        self.const_fn_uses.insert(
+                            name.clone(),
+                            uses.into_iter().map(|capture| capture.name).collect(),
+                        );
+                        definitions.push(item.clone());
+                    }
+                }
+                ASTNode::EnumDef { name, .. } => {
+                    self.enums.insert(name.clone());
+                    definitions.push(item.clone());
+                }
+                _ => {}
+            }
+        }
+        self.interpreter
+            .run_entry(&ASTNode::Program(definitions))
+            .map(|_| ())
+    }
+
+    fn referent(&self, name: &str) -> Referent {
+        if self.constants.contains_key(name) {
+            return Referent::Constant;
+        }
+        if let Some((is_const, span)) = self.functions.get(name) {
+            return if *is_const {
+                Referent::ConstFn
+            } else {
+                Referent::Function(*span)
+            };
+        }
+        // Printing and reading input are the builtins with effects.
+        let is_builtin = prelude::is_builtin(name)
+            && !prelude::VARIADIC_BUILTINS.contains(&name)
+            && name != "read_line";
+        if is_builtin {
+            return Referent::Builtin;
+        }
+        match name.rsplit_once("::") {
+            Some((enum_name, _)) if self.enums.contains(enum_name) => Referent::Variant,
+            _ => Referent::Other,
+        }
+    }
+
+    /// The value of the constant `name`, evaluating it and the constants it
+    /// depends on first if that hasn't happened yet.
+    fn constant(&mut self, name: &str) -> Result<Value> {
+        if let Some(value) = &self.constants[name].result {
+            return Ok(value.clone());
+        }
+        if let Some(start) = self.pending.iter().position(|pending| pending == name) {
+            return Err(self.cycle(start));
+        }
+
+        self.pending.push(name.to_string());
+        let value = self.compute(name);
+        self.pending.pop();
+        let value = value?;
+
+        self.interpreter
+            .context_mut()
+            .define(name.to_string(), value.clone());
+        if let Some(constant) = self.constants.get_mut(name) {
+            constant.result = Some(value.clone());
+        }
+        Ok(value)
+    }
+
+    fn compute(&mut self, name: &str) -> Result<Value> {
+        let constant = &self.constants[name];
+        let (expr, type_annotation, span) = (
+            constant.value.clone(),
+            constant.type_annotation.clone(),
+            constant.span,
+        );
+
+        self.check(&expr, "a constant expression")?;
+        for dependency in self.dependencies(&expr) {
+            self.constant(&dependency)?;
+        }
+        let mut value = self
+            .run(&expr)
+            .map_err(|err| err.with_note(span, format!("while evaluating `{}`", name)))?;
+
+        if let Some(ty) = &type_annotation {
+            let type_name = value.type_name();
+            value = convert(value, ty)
+                .ok()
+                .filter(|value| has_type(value, ty))
+                .ok_or_else(|| {
+                    IoError::type_error(format!(
+                        "Constant `{}` is declared {} but its value is of type {}",
+                        name, ty, type_name
+                    ))
+                    .with_span(expr.span())
+                })?;
+        }
+        if literal(&value, type_annotation.as_ref(), NodeId::DUMMY, span).is_none() {
+            return Err(IoError::validation_error(format!(
+                "The value of constant `{}` is of type {}; a constant must be a number, \
+                 bool, char, string or an array of them",
+                name,
+                value.type_name()
+            ))
+            .with_span(expr.span()));
+        }
+        Ok(value)
+    }
+
+    /// The constants `expr` uses, directly or through the `const fn`s it calls.
+    fn dependencies(&self, expr: &ASTNode) -> Vec<String> {
+        let mut names: Vec<String> = captures(&[], expr)
+            .into_iter()
+            .map(|capture| capture.name)
+            .collect();
+        let mut seen = HashSet::new();
+        let mut dependencies = Vec::new();
+        while let Some(name) = names.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if self.constants.contains_key(&name) {
+                dependencies.push(name);
+            } else if let Some(uses) = self.const_fn_uses.get(&name) {
+                names.extend(uses.iter().cloned());
+            }
+        }
+        dependencies
+    }
+
+    /// The error for the constants from `pending[start]` on, each of which
+    /// needs the next, and the last the first.
+    fn cycle(&self, start: usize) -> IoError {
+        let cycle = &self.pending[start..];
+        let first = &cycle[0];
+        let mut err = IoError::validation_error(format!("Constant `{}` depends on itself", first))
+            .with_span(self.constants[first].span);
+        let next = cycle.iter().skip(1).chain(std::iter::once(first));
+        for (user, used) in cycle.iter().zip(next) {
+            err = err.with_note(
+                self.constants[user].span,
+                format!("`{}` uses `{}`", user, used),
+            );
+        }
+        err
+    }
+
+    /// Runs `expr` in the interpreter, within the step budget.
+    fn run(&mut self, expr: &ASTNode) -> Result<Value> {
+        self.interpreter.set_step_budget(Some(self.budget));
+        let value = self.interpreter.evaluate(expr);
+        self.interpreter.set_step_budget(None);
+        value
+    }
+
+    /// Fails on the first operation in `expr` that can't run at compile time.
+    fn check(&self, expr: &ASTNode, context: &'static str) -> Result<()> {
+        let free = captures(&[], expr)
+            .into_iter()
+            .map(|capture| capture.name)
+            .collect();
+        ConstChecker {
+            evaluator: self,
+            free,
+            context,
+        }
+        .visit_node(expr)
+    }
+
+    fn check_const_fn(&self, params: &[Parameter], body: &[ASTNode]) -> Result<()> {
+        let free = captures(params, &block(body))
+            .into_iter()
+            .map(|capture| capture.name)
+            .collect();
+        let mut checker = ConstChecker {
+            evaluator: self,
+            free,
+            context: "a `const fn`",
+        };
+        walk_nodes(&mut checker, body)
+    }
+
+    /// Checks and evaluates an expression outside a `const` item, once every
+    /// constant has its value.
+    fn evaluate_expression(&mut self, expr: &ASTNode) -> Result<Value> {
+        self.check(expr, "a constant expression")?;
+        self.run(expr)
+    }
+
+    fn static_assert(&mut self, args: &[ASTNode], span: Span) -> Result<()> {
+        let (condition, message) = match args {
+            [condition] => (condition, None),
+            [condition, message] => (condition, Some(message)),
+            _ => {
+                return Err(IoError::validation_error(
+                    "static_assert takes a condition and an optional message",
+                )
+                .with_span(span))
+            }
+        };
+        let holds = match self.evaluate_expression(condition)? {
+            Value::Boolean(holds) => holds,
+            other => {
+                return Err(IoError::type_error(format!(
+                    "static_assert expects a bool, found {}",
+                    other.type_name()
+                ))
+                .with_span(condition.span()))
+            }
+        };
+        if holds {
+            return Ok(());
+        }
+        let message = match message.map(|message| self.evaluate_expression(message)) {
+            Some(Ok(Value::String(message))) => format!("Static assertion failed: {}", message),
+            Some(Ok(other)) => {
+                return Err(IoError::type_error(format!(
+                    "The message of static_assert must be a string, found {}",
+                    other.type_name()
+                ))
+                .with_span(args[1].span()))
+            }
+            Some(Err(err)) => return Err(err),
+            None => "Static assertion failed".to_string(),
+        };
+        Err(IoError::validation_error(message).with_span(condition.span()))
+    }
+
+    /// The size the constant `length` gives an array type.
+    fn array_length(&self, length: &str) -> Result<usize> {
+        let value = self
+            .constants
+            .get(length)
+            .and_then(|constant| constant.result.as_ref())
+            .ok_or_else(|| {
+                IoError::validation_error(format!("Array length `{}` is not a constant", length))
+            })?;
+        let size = match value {
+            Value::Integer(n) => Some(i128::from(*n)),
+            Value::Sized(n) => n.to_i128(),
+            _ => None,
+        };
+        size.and_then(|size| usize::try_from(size).ok())
+            .filter(|size| *size > 0)
+            .ok_or_else(|| {
+                IoError::validation_error(format!(
+                    "Array length `{}` must be a positive integer, not {}",
+                    length, value
+                ))
+            })
+    }
+
+    /// Rejects declaring a variable named like a constant: uses of the name
+    /// would be taken for the constant.
+    fn check_binding(&self, name: &str, span: Span) -> Result<()> {
+        if self.constants.contains_key(name) {
+            return Err(IoError::validation_error(format!(
+                "`{}` is a constant and can't be declared again",
+                name
+            ))
+            .with_span(span));
+        }
+        Ok(())
+    }
+
+    fn check_params(&self, params: &[Parameter]) -> Result<()> {
+        params
+            .iter()
+            .try_for_each(|param| self.check_binding(&param.name, param.span))
+    }
+}
+
+impl Default for ConstEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Folder for ConstEvaluator {
+    fn fold_node(&mut self, node: ASTNode) -> Result<ASTNode> {
+        match &node {
+            ASTNode::Identifier { name, id, span } => {
+                if let Some(constant) = self.constants.get(name) {
+                    let value = constant
+                        .result
+                        .as_ref()
+                        .expect("constants are evaluated before they are inlined");
+                    return Ok(
+                        literal(value, constant.type_annotation.as_ref(), *id, *span)
+                            .expect("constants hold values literals can express"),
+                    );
+                }
+            }
+            ASTNode::Global { span, .. } => {
+                return Err(IoError::validation_error(
+                    "Constants can only be declared at the top level",
+                )
+                .with_span(*span));
+            }
+            ASTNode::Let { name, span, .. } => self.check_binding(name, *span)?,
+            ASTNode::LetPattern { pattern, .. } => {
+                for (name, span) in pattern.bindings() {
+                    self.check_binding(name, span)?;
+                }
+            }
+            ASTNode::Assignment { target, span, .. }
+            | ASTNode::CompoundAssignment { target, span, .. } => {
+                if let Some(name) = target.place_root() {
+                    if self.constants.contains_key(name) {
+                        return Err(IoError::validation_error(format!(
+                            "Cannot assign to the constant `{}`",
+                            name
+                        ))
+                        .with_span(*span));
+                    }
+                }
+            }
+            ASTNode::Function { params, .. } | ASTNode::Closure { params, .. } => {
+                self.check_params(params)?
+            }
+            ASTNode::For { pattern, .. } => {
+                for (name, span) in pattern.bindings() {
+                    self.check_binding(name, span)?;
+                }
+            }
+            ASTNode::Match { arms, .. } => {
+                for (name, span) in arms.iter().flat_map(|arm| arm.pattern.bindings()) {
+                    self.check_binding(name, span)?;
+                }
+            }
+            _ => {}
+        }
+        fold_children(self, node)
+    }
+
+    /// Checks and drops `static_assert`s.
+    fn fold_nodes(&mut self, nodes: Vec<ASTNode>) -> Result<Vec<ASTNode>> {
+        let mut folded = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            match &node {
+                ASTNode::Call { args, span, .. } if node.callee_name() == Some(STATIC_ASSERT) => {
+                    self.static_assert(args, *span)?
+                }
+                _ => folded.push(self.fold_node(node)?),
+            }
+        }
+        Ok(folded)
+    }
+
+    fn fold_type(&mut self, ty: Type) -> Result<Type> {
+        Ok(match ty {
+            Type::ConstArray { elem_type, length } => Type::Array {
+                elem_type: Box::new(self.fold_type(*elem_type)?),
+                size: self.array_length(&length)?,
+            },
+            Type::Array { elem_type, size } => Type::Array {
+                elem_type: Box::new(self.fold_type(*elem_type)?),
+                size,
+            },
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => Type::Function {
+                params: params
+                    .into_iter()
+                    .map(|param| self.fold_type(param))
+                    .collect::<Result<_>>()?,
+                return_type: Box::new(self.fold_type(*return_type)?),
+                is_async,
+            },
+            Type::Generic { name, args } => Type::Generic {
+                name,
+                args: args
+                    .into_iter()
+                    .map(|arg| self.fold_type(arg))
+                    .collect::<Result<_>>()?,
+            },
+            Type::Pointer(inner) => Type::Pointer(Box::new(self.fold_type(*inner)?)),
+            Type::Ref { mutable, inner } => Type::Ref {
+                mutable,
+                inner: Box::new(self.fold_type(*inner)?),
+            },
+            ty => ty,
+        })
+    }
+}
+
+/// Finds the first operation that can't run at compile time.
+struct ConstChecker<'a> {
+    evaluator: &'a ConstEvaluator,
+    /// The names the checked code uses without declaring them.
+    free: HashSet<String>,
+    /// What is being checked, for the errors: "a `const fn`".
+    context: &'static str,
+}
+
+impl Visitor for ConstChecker<'_> {
+    type Output = ();
+
+    fn visit_call(&mut self, callee: &ASTNode, args: &[ASTNode]) -> Result<()> {
+        match callee {
+            ASTNode::Identifier { name, .. } if self.free.contains(name) => {
+                match self.evaluator.referent(name) {
+                    Referent::ConstFn | Referent::Builtin | Referent::Variant => {}
+                    Referent::Function(span) => {
+                        return Err(IoError::validation_error(format!(
+                            "Cannot call `{}` in {}: it is not a `const fn`",
+                            name, self.context
+                        ))
+                        .with_note(span, format!("`{}` is defined here", name)))
+                    }
+                    Referent::Constant | Referent::Other => {
+                        return Err(IoError::validation_error(format!(
+                            "Cannot call `{}` in {}",
+                            name, self.context
+                        )))
+                    }
+                }
+            }
+            ASTNode::MemberAccess { member, .. } => {
+                return Err(IoError::validation_error(format!(
+                    "Cannot call the method `{}` in {}",
+                    member, self.context
+                )))
+            }
+            callee => self.visit_node(callee)?,
+        }
+        walk_nodes(self, args)
+    }
+
+    fn visit_identifier(&mut self, name: &str) -> Result<()> {
+        if !self.free.contains(name) {
+            return Ok(());
+        }
+        match self.evaluator.referent(name) {
+            Referent::Constant | Referent::ConstFn | Referent::Builtin | Referent::Variant => {
+                Ok(())
+            }
+            Referent::Function(span) => Err(IoError::validation_error(format!(
+                "Cannot use `{}` in {}: it is not a `const fn`",
+                name, self.context
+            ))
+            .with_note(span, format!("`{}` is defined here", name))),
+            Referent::Other => Err(IoError::validation_error(format!(
+                "Cannot use `{}` in {}: it is not a constant",
+                name, self.context
+            ))),
+        }
+    }
+
+    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<()> {
+        self.assign(target)?;
+        self.visit_node(value)?;
+        walk_place(self, target)
+    }
+
+    fn visit_compound_assignment(
+        &mut self,
+        target: &ASTNode,
+        _op: &crate::ast::BinaryOperator,
+        value: &ASTNode,
+    ) -> Result<()> {
+        self.assign(target)?;
+        self.visit_node(value)?;
+        walk_place(self, target)
+    }
+
+    fn visit_unary(&mut self, op: &UnaryOperator, operand: &ASTNode) -> Result<()> {
+        if *op == UnaryOperator::Await {
+            return Err(IoError::validation_error(format!(
+                "`await` is not allowed in {}",
+                self.context
+            )));
+        }
+        self.visit_node(operand)
+    }
+
+    fn visit_closure(
+        &mut self,
+        _params: &[Parameter],
+        _return_type: Option<&Type>,
+        _body: &ASTNode,
+        _is_move: bool,
+    ) -> Result<()> {
+        Err(IoError::validation_error(format!(
+            "Closures are not allowed in {}",
+            self.context
+        )))
+    }
+}
+
+impl ConstChecker<'_> {
+    fn assign(&self, target: &ASTNode) -> Result<()> {
+        match target.place_root() {
+            Some(name) if self.free.contains(name) => Err(IoError::validation_error(format!(
+                "Cannot assign to `{}` in {}",
+                name, self.context
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn block(statements: &[ASTNode]) -> ASTNode {
+    ASTNode::Block {
+        statements: statements.to_vec(),
+        id: NodeId::DUMMY,
+        span: Span::dummy(),
+    }
+}
+
+/// Whether `value`, converted to `ty`, has that type.
+fn has_type(value: &Value, ty: &Type) -> bool {
+    match (value, ty) {
+        (Value::Integer(_), Type::I32 | Type::I64) => true,
+        (Value::Sized(n), ty) => n.ty() == ty,
+        (Value::Float(_), ty) => ty.is_float(),
+        (Value::Boolean(_), Type::Bool)
+        | (Value::Char(_), Type::Char)
+        | (Value::String(_), Type::String) => true,
+        (Value::Array(items), Type::Array { elem_type, size }) => {
+            (*size == 0 || *size == items.len())
+                && items.iter().all(|item| has_type(item, elem_type))
+        }
+        (Value::Tuple(items), Type::Tuple(types)) => {
+            items.len() == types.len()
+                && items.iter().zip(types).all(|(item, ty)| has_type(item, ty))
+        }
+        _ => false,
+    }
+}
+
+/// `value` as an expression with the given id and span, or `None` if no
+/// literal can express it. A number takes the type `ty`, if it has one.
+/// Elements of arrays and tuples always get a type, as their ids can't tell
+/// them apart.
+fn literal(value: &Value, ty: Option<&Type>, id: NodeId, span: Span) -> Option<ASTNode> {
+    let element = |i: usize, item: &Value| {
+        let elem_type = match ty {
+            Some(Type::Array { elem_type, .. }) => Some(elem_type.as_ref()),
+            Some(Type::Tuple(types)) => types.get(i),
+            _ => None,
+        };
+        let default = match item {
+            Value::Float(_) => Type::F64,
+            _ => Type::I32,
+        };
+        literal(
+            item,
+            Some(elem_type.unwrap_or(&default)),
+            NodeId::DUMMY,
+            span,
+        )
+    };
+    let value = match (value, ty) {
+        (Value::Array(items), _) => {
+            let elements = items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| element(i, item))
+                .collect::<Option<_>>()?;
+            return Some(ASTNode::ArrayLiteral { elements, id, span });
+        }
+        (Value::Tuple(items), _) => {
+            let elements = items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| element(i, item))
+                .collect::<Option<_>>()?;
+            return Some(ASTNode::TupleLiteral { elements, id, span });
+        }
+        (Value::Integer(n), Some(ty)) if ty.is_integer() => {
+            Literal::TypedInteger(i128::from(*n), ty.clone())
+        }
+        (Value::Integer(n), _) => Literal::Integer(*n),
+        (Value::Sized(n), _) => Literal::TypedInteger(n.to_i128()?, n.ty().clone()),
+        (Value::Float(n), Some(ty)) if ty.is_float() => Literal::TypedFloat(*n, ty.clone()),
+        (Value::Float(n), _) => Literal::Float(*n),
+        (Value::Boolean(b), _) => Literal::Boolean(*b),
+        (Value::Char(c), _) => Literal::Char(*c),
+        (Value::String(s), _) => Literal::String(s.clone()),
+        _ => return None,
+    };
+    Some(ASTNode::Literal { value, id, span })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+
+    fn evaluate(source: &str) -> Result<ASTNode> {
+        ConstEvaluator::new().evaluate(parse_source(source, FileId(0)).unwrap())
+    }
+
+    #[test]
+    fn test_constants_are_evaluated_and_inlined() {
+        let source = "
+            const fn fib(n: i32) -> i32 {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            const fn sum_to(n: i32) -> i32 {
+                let total = 0;
+                let i = 1;
+                while i <= n { total = total + i; i = i + 1; }
+                return total;
+            }
+            const SIZE: i32 = fib(6);
+            const TOTAL: u8 = sum_to(SIZE);
+            static_assert(TOTAL == 36, \"sum_to is off\");
+            fn main() -> i32 {
+                let buffer: [i32; SIZE] = [0, 0, 0, 0, 0, 0, 0, 0];
+                return len(buffer) + SIZE;
+            }
+        ";
+        let program = evaluate(source).unwrap();
+        let ASTNode::Program(items) = &program else {
+            panic!("expected a program");
+        };
+        assert!(!items
+            .iter()
+            .any(|item| matches!(item, ASTNode::Global { .. } | ASTNode::Call { .. })));
+        let ASTNode::Function { body, .. } = items.last().unwrap() else {
+            panic!("expected main");
+        };
+        let ASTNode::Let {
+            type_annotation, ..
+        } = &body[0]
+        else {
+            panic!("expected a let");
+        };
+        assert_eq!(
+            type_annotation,
+            &Some(Type::Array {
+                elem_type: Box::new(Type::I32),
+                size: 8,
+            })
+        );
+
+        let value = Interpreter::new().run(&program).unwrap();
+        assert!(matches!(value, Value::Integer(16)));
+    }
+
+    #[test]
+    fn test_failing_static_assert() {
+        let source = "const LIMIT: i32 = 3;\nstatic_assert(LIMIT > 4, \"limit too small\");";
+        let err = evaluate(source).unwrap_err();
+        assert_eq!(err.message(), "Static assertion failed: limit too small");
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "LIMIT > 4");
+    }
+
+    #[test]
+    fn test_calling_a_function_that_is_not_const() {
+        let source = "fn read() -> i32 { return 1; }\nconst X: i32 = read();";
+        let err = evaluate(source).unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Cannot call `read` in a constant expression: it is not a `const fn`"
+        );
+        let (span, note) = &err.notes()[0];
+        assert_eq!(note, "`read` is defined here");
+        assert!(source[span.start..span.end].starts_with("fn read()"));
+
+        let err = evaluate("const fn f() -> i32 { print(1); return 1; }").unwrap_err();
+        assert_eq!(err.message(), "Cannot call `print` in a `const fn`");
+    }
+
+    #[test]
+    fn test_step_budget_and_cycles() {
+        let source = "
+            const fn spin() -> i32 { while true {} return 0; }
+            const X: i32 = spin();
+        ";
+        let err = ConstEvaluator::with_step_budget(1000)
+            .evaluate(parse_source(source, FileId(0)).unwrap())
+            .unwrap_err();
+        assert_eq!(err.message(), "Evaluation took more than 1000 steps");
+        assert_eq!(err.notes()[0].1, "while evaluating `X`");
+
+        let source = "
+            const fn next(n: i32) -> i32 { return B + n; }
+            const A: i32 = next(1);
+            const B: i32 = A * 2;
+        ";
+        let err = evaluate(source).unwrap_err();
+        assert_eq!(err.message(), "Constant `A` depends on itself");
+        let notes: Vec<&str> = err.notes().iter().map(|(_, note)| note.as_str()).collect();
+        assert_eq!(notes, ["`A` uses `B`", "`B` uses `A`"]);
+    }
+}
@@ -0,0 +1,326 @@
+//! Loop fusion: a `for` loop over `map` or `filter` of a closure literal runs
+//! the closure's body in the loop itself, so no iterator is built and no
+//! closure called. Loops over `zip`, `enumerate` and `take` are left alone.
+
+use crate::{
+    ast::{captures, ASTNode, NodeId, Pattern, Type, UnaryOperator},
+    span::Span,
+    visitor::{fold_children, Folder, Visitor},
+    Result,
+};
+use std::collections::HashSet;
+
+pub struct LoopFuser {
+    /// Functions the program defines, which shadow the built-in adapters.
+    defined: HashSet<String>,
+    changed: bool,
+}
+
+/// The adapter a fusible loop iterates over.
+#[derive(Clone, Copy)]
+enum Adapter {
+    Map,
+    Filter,
+}
+
+impl LoopFuser {
+    pub fn new() -> Self {
+        Self {
+            defined: HashSet::new(),
+            changed: false,
+        }
+    }
+
+    pub fn fuse(&mut self, ast: ASTNode) -> Result<ASTNode> {
+        self.changed = false;
+        self.defined = match &ast {
+            ASTNode::Program(items) => items
+                .iter()
+                .filter_map(|item| match item {
+                    ASTNode::Function { name, .. } => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => HashSet::new(),
+        };
+        self.fold_node(ast)
+    }
+
+    /// Whether the last `fuse` rewrote anything.
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// The adapter `for pattern in iterable { body }` can be fused through:
+    /// the loop binds a single name and the adapter is given a closure of one
+    /// unannotated parameter, whose body can move into the loop unchanged.
+    fn adapter(&self, pattern: &Pattern, iterable: &ASTNode, body: &[ASTNode]) -> Option<Adapter> {
+        let Pattern::Binding { name: variable, .. } = pattern else {
+            return None;
+        };
+        let ASTNode::Call { callee, args, .. } = iterable else {
+            return None;
+        };
+        let ASTNode::Identifier { name, .. } = callee.as_ref() else {
+            return None;
+        };
+        let adapter = match name.as_str() {
+            "map" => Adapter::Map,
+            "filter" => Adapter::Filter,
+            _ => return None,
+        };
+        if self.defined.contains(name) || args.len() != 2 {
+            return None;
+        }
+        let ASTNode::Closure {
+            params,
+            return_type: None,
+            body: closure_body,
+            is_move: false,
+            ..
+        } = &args[1]
+        else {
+            return None;
+        };
+        let [param] = params.as_slice() else {
+            return None;
+        };
+        // Inlined, a `return` or `?` would leave the enclosing function, and the
+        // parameter would hide a variable of the same name from the loop body.
+        let hidden = param.name != *variable && free_in(&param.name, body);
+        let fusible = param.type_annotation == Type::Unknown
+            && !matches!(closure_body.as_ref(), ASTNode::Block { .. })
+            && !escapes(closure_body)
+            && !hidden;
+        fusible.then_some(adapter)
+    }
+}
+
+impl Default for LoopFuser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Folder for LoopFuser {
+    fn fold_node(&mut self, node: ASTNode) -> Result<ASTNode> {
+        let mut node = fold_children(self, node)?;
+        // The iterable of a fused loop may be an adapter in turn.
+        while let ASTNode::For {
+            pattern,
+            iterable,
+            body,
+            ..
+        } = &node
+        {
+            let Some(adapter) = self.adapter(pattern, iterable, body) else {
+                break;
+            };
+            self.changed = true;
+            node = fused(adapter, node);
+        }
+        Ok(node)
+    }
+}
+
+/// Rewrites a loop `adapter` accepted:
+/// `for x in map(items, |y| e) { body }` to `for y in items { let x = e; body }`
+/// and `for x in filter(items, |y| c) { body }` to
+/// `for y in items { if c { let x = y; body } }`, leaving out `let x = y`
+/// where the names agree. The nodes built take the ids of those dropped.
+fn fused(adapter: Adapter, node: ASTNode) -> ASTNode {
+    let ASTNode::For {
+        pattern: Pattern::Binding { name: variable, .. },
+        iterable,
+        body,
+        id,
+        span,
+    } = node
+    else {
+        unreachable!("only loops over an adapter are fused");
+    };
+    let ASTNode::Call {
+        callee,
+        args,
+        id: call_id,
+        span: call_span,
+    } = *iterable
+    else {
+        unreachable!("only loops over an adapter are fused");
+    };
+    let [items, closure]: [ASTNode; 2] = args
+        .try_into()
+        .unwrap_or_else(|_| unreachable!("adapters take two arguments"));
+    let ASTNode::Closure {
+        mut params,
+        body: value,
+        id: closure_id,
+        span: closure_span,
+        ..
+    } = closure
+    else {
+        unreachable!("only adapters given a closure are fused");
+    };
+    let param = params.remove(0);
+
+    let pattern = Pattern::Binding {
+        name: param.name.clone(),
+        id: param.id,
+        span: param.span,
+    };
+    let body = match adapter {
+        Adapter::Map => {
+            let binding = ASTNode::Let {
+                name: variable,
+                type_annotation: None,
+                value: Some(value),
+                id: closure_id,
+                span: closure_span,
+            };
+            std::iter::once(binding).chain(body).collect()
+        }
+        Adapter::Filter => {
+            let mut then_branch = body;
+            if variable != param.name {
+                let element = ASTNode::Identifier {
+                    name: param.name,
+                    id: callee.id(),
+                    span: param.span,
+                };
+                then_branch.insert(
+                    0,
+                    ASTNode::Let {
+                        name: variable,
+                        type_annotation: None,
+                        value: Some(Box::new(element)),
+                        id: closure_id,
+                        span: closure_span,
+                    },
+                );
+            }
+            vec![ASTNode::If {
+                condition: value,
+                then_branch,
+                else_branch: None,
+                id: call_id,
+                span: call_span,
+            }]
+        }
+    };
+    ASTNode::For {
+        pattern,
+        iterable: Box::new(items),
+        body,
+        id,
+        span,
+    }
+}
+
+/// Whether `body` uses `name` without declaring it first.
+fn free_in(name: &str, body: &[ASTNode]) -> bool {
+    let block = ASTNode::Block {
+        statements: body.to_vec(),
+        id: NodeId::DUMMY,
+        span: Span::dummy(),
+    };
+    captures(&[], &block)
+        .iter()
+        .any(|capture| capture.name == name)
+}
+
+/// Whether `node` contains a `return` or `?`.
+fn escapes(node: &ASTNode) -> bool {
+    let mut finder = EscapeFinder { found: false };
+    finder
+        .visit_node(node)
+        .expect("finding returns cannot fail");
+    finder.found
+}
+
+struct EscapeFinder {
+    found: bool,
+}
+
+impl Visitor for EscapeFinder {
+    type Output = ();
+
+    fn visit_return(&mut self, _value: Option<&ASTNode>) -> Result<()> {
+        self.found = true;
+        Ok(())
+    }
+
+    fn visit_unary(&mut self, op: &UnaryOperator, operand: &ASTNode) -> Result<()> {
+        self.found |= *op == UnaryOperator::Try;
+        self.visit_node(operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, runtime::Interpreter, runtime::Value, span::FileId};
+
+    fn fuse(source: &str) -> (ASTNode, bool) {
+        let mut fuser = LoopFuser::new();
+        let program = fuser
+            .fuse(parse_source(source, FileId(0)).unwrap())
+            .unwrap();
+        (program, fuser.changed())
+    }
+
+    fn main_body(program: &ASTNode) -> &[ASTNode] {
+        match program {
+            ASTNode::Program(items) => match items.last() {
+                Some(ASTNode::Function { body, .. }) => body,
+                other => panic!("expected function, got {:?}", other),
+            },
+            other => panic!("expected program, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_loops_over_map_and_filter_are_fused() {
+        let source = "\
+fn main() -> int {
+    let total = 0;
+    for x in filter(map([1, 2, 3, 4], |n| n * 2), |m| m > 4) { total += x; }
+    return total;
+}";
+        let (program, changed) = fuse(source);
+        assert!(changed);
+        let ASTNode::For {
+            pattern, iterable, ..
+        } = &main_body(&program)[1]
+        else {
+            panic!("expected a loop");
+        };
+        assert!(matches!(pattern, Pattern::Binding { name, .. } if name == "n"));
+        assert!(matches!(iterable.as_ref(), ASTNode::ArrayLiteral { .. }));
+        assert_eq!(
+            Interpreter::new().run(&program).unwrap(),
+            Value::Integer(14)
+        );
+
+        for unchanged in [
+            "fn main() { for x in map(items, |n| n + 1) { println(n, x); } }",
+            "fn main() { for x in map(items, move |n| n) { println(x); } }",
+            "fn main() { for x in filter(items, |n: int| n > 0) { println(x); } }",
+            "fn main() { for x in take(items, 2) { println(x); } }",
+            "fn map(xs: [int], f: fn(int) -> int) -> [int] { return xs; }
+fn main() { for x in map(items, |n| n) { println(x); } }",
+        ] {
+            assert!(!fuse(unchanged).1, "{}", unchanged);
+        }
+    }
+
+    #[test]
+    fn test_changed_reports_the_last_fuse_only() {
+        let mut fuser = LoopFuser::new();
+        let fused = "fn main() { for x in map([1, 2], |n| n + 1) { println(x); } }";
+        fuser.fuse(parse_source(fused, FileId(0)).unwrap()).unwrap();
+        assert!(fuser.changed());
+        let plain = "fn main() { for x in [1, 2] { println(x); } }";
+        fuser.fuse(parse_source(plain, FileId(0)).unwrap()).unwrap();
+        assert!(!fuser.changed());
+    }
+}
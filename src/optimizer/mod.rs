@@ -1,3 +1,13 @@
+pub mod const_eval;
+pub mod const_fold;
+pub mod const_prop;
+pub mod fusion;
+
+pub use const_eval::ConstEvaluator;
+pub use const_fold::ConstantFolder;
+pub use const_prop::ConstantPropagator;
+pub use fusion::LoopFuser;
+
 use crate::error::IoError;
 use inkwell::module::Module;
 use inkwell::passes::PassManager;
@@ -1,13 +1,124 @@
 use crate::{
-    ast::{ASTNode, BinaryOperator, Parameter},
+    ast::{
+        ASTNode, Attribute, AttributeArg, BinaryOperator, Field, Literal, MatchArm, NodeId,
+        Parameter, Pattern, PatternFields, TraitMethod, Type, UnaryOperator, Variant,
+        VariantFields,
+    },
+    diagnostics::{Diagnostic, SourceMap},
+    error::{handler::RecoveryStrategy, IoError},
+    lexer::{Lexer, NumberLiteral, NumberValue},
+    macro_system::{Fragment, MacroExpander},
+    span::{FileId, Span},
     token::{Token, TokenKind},
     Result,
 };
 use std::iter::Peekable;
 
+/// Type parameter names, and the `(parameter, trait)` bounds on them.
+type TypeParams = (Vec<String>, Vec<(String, String)>);
+
+/// A built-in attribute: its name, the kinds of item it may be written on and
+/// whether its arguments are well formed.
+type BuiltinAttribute = (
+    &'static str,
+    &'static [&'static str],
+    fn(&[AttributeArg]) -> bool,
+);
+
+const ATTRIBUTES: &[BuiltinAttribute] = &[
+    (
+        "allow",
+        &["function", "struct", "import", "module"],
+        |args| !args.is_empty() && args.iter().all(|arg| matches!(arg, AttributeArg::Word(_))),
+    ),
+    (
+        "cfg",
+        &["function", "struct", "field", "import", "module"],
+        |args| args.len() == 1,
+    ),
+    ("cold", &["function"], <[AttributeArg]>::is_empty),
+    (
+        "deprecated",
+        &["function", "struct", "module"],
+        |args| match args {
+            [] => true,
+            [AttributeArg::Value(key, _)] => key == "note",
+            _ => false,
+        },
+    ),
+    ("inline", &["function"], |args| match args {
+        [] => true,
+        [AttributeArg::Word(word)] => word == "always" || word == "never",
+        _ => false,
+    }),
+    ("test", &["function"], <[AttributeArg]>::is_empty),
+];
+
+/// Lexes a whole source file, expands its macros and parses it into an
+/// `ASTNode::Program`. The expansions are not recorded, so errors in them come
+/// without a backtrace; `ModuleManager` keeps them in its `SourceMap`.
+pub fn parse_source(source: &str, file_id: FileId) -> Result<ASTNode> {
+    let tokens = Lexer::with_file_id(source, file_id).tokenize()?;
+    let tokens = MacroExpander::new(&mut SourceMap::new()).expand(tokens)?;
+    Parser::new(tokens.into_iter()).parse_program()
+}
+
+/// Lexes and parses a whole source file, recovering from syntax errors. Always
+/// returns a (possibly partial) program together with every error found. A
+/// file whose macros fail to expand gives an empty program.
+pub fn parse_source_with_recovery(source: &str, file_id: FileId) -> (ASTNode, Vec<Diagnostic>) {
+    let (tokens, errors) = Lexer::with_file_id(source, file_id).tokenize_with_recovery();
+    let (program, parse_diagnostics) =
+        match MacroExpander::new(&mut SourceMap::new()).expand(tokens) {
+            Ok(tokens) => Parser::new(tokens.into_iter()).parse_program_with_recovery(),
+            Err(err) => (
+                ASTNode::Program(Vec::new()),
+                vec![Diagnostic::from_error(&err)],
+            ),
+        };
+
+    let mut diagnostics: Vec<Diagnostic> = errors.iter().map(Diagnostic::from_error).collect();
+    diagnostics.extend(parse_diagnostics);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.map_or(0, |span| span.start));
+    (program, diagnostics)
+}
+
+/// Tokens that start or end a statement; the default place to resume after an error.
+pub fn statement_boundaries() -> Vec<TokenKind> {
+    vec![
+        TokenKind::Semicolon,
+        TokenKind::RightBrace,
+        TokenKind::Function,
+        TokenKind::Struct,
+        TokenKind::Enum,
+        TokenKind::Trait,
+        TokenKind::Impl,
+        TokenKind::Async,
+        TokenKind::Const,
+        TokenKind::Let,
+        TokenKind::If,
+        TokenKind::Match,
+        TokenKind::While,
+        TokenKind::For,
+        TokenKind::Return,
+        TokenKind::Break,
+        TokenKind::Continue,
+    ]
+}
+
 pub struct Parser<I: Iterator<Item = Token>> {
     tokens: Peekable<I>,
     current: Option<Token>,
+    previous_span: Span,
+    /// How to continue after a syntax error; `None` stops at the first one.
+    recovery: Option<RecoveryStrategy>,
+    diagnostics: Vec<Diagnostic>,
+    next_id: u32,
+    /// Type parameters of the function or struct being parsed.
+    type_params: Vec<String>,
+    /// Set while parsing the head of an `if`, `while`, `for` or `match`, where a
+    /// `{` opens the body rather than a struct literal.
+    no_struct_literals: bool,
 }
 
 impl<I: Iterator<Item = Token>> Parser<I> {
@@ -15,22 +126,75 @@ impl<I: Iterator<Item = Token>> Parser<I> {
         let mut parser = Self {
             tokens: tokens.peekable(),
             current: None,
+            previous_span: Span::dummy(),
+            recovery: None,
+            diagnostics: Vec::new(),
+            next_id: 0,
+            type_params: Vec::new(),
+            no_struct_literals: false,
         };
-        parser.advance();
+        parser.current = parser.next_significant();
         parser
     }
 
+    pub fn with_recovery(mut self, strategy: RecoveryStrategy) -> Self {
+        self.recovery = Some(strategy);
+        self
+    }
+
+    /// Numbers nodes from `first` on, keeping their ids apart from those of
+    /// programs parsed from 0.
+    pub fn with_first_id(mut self, first: u32) -> Self {
+        self.next_id = first;
+        self
+    }
+
+    /// The id the next node will get, where numbering continues for the next
+    /// file of a program.
+    pub fn next_node_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// Next token from the stream, skipping doc comments (only the doc generator reads them).
+    fn next_significant(&mut self) -> Option<Token> {
+        self.tokens
+            .by_ref()
+            .find(|token| token.kind != TokenKind::DocComment)
+    }
+
+    /// Hands out ids in parse order; every node of one program gets a distinct id.
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Consumes the current token and returns it.
     fn advance(&mut self) -> Option<Token> {
-        self.current = self.tokens.next();
-        self.current.clone()
+        let next = self.next_significant();
+        let token = std::mem::replace(&mut self.current, next);
+        if let Some(token) = &token {
+            self.previous_span = token.span;
+        }
+        token
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        matches!(&self.current, Some(token) if token.kind == kind)
     }
 
-    fn peek(&mut self) -> Option<&Token> {
-        self.tokens.peek()
+    fn is_at_end(&self) -> bool {
+        matches!(
+            &self.current,
+            None | Some(Token {
+                kind: TokenKind::EOF,
+                ..
+            })
+        )
     }
 
     fn match_token(&mut self, kinds: &[TokenKind]) -> bool {
-        if let Some(token) = self.peek() {
+        if let Some(token) = &self.current {
             if kinds.contains(&token.kind) {
                 self.advance();
                 return true;
@@ -40,335 +204,2560 @@ impl<I: Iterator<Item = Token>> Parser<I> {
     }
 
     fn expect_token(&mut self, kind: TokenKind) -> Result<Token> {
-        if let Some(token) = self.advance() {
-            if token.kind == kind {
-                Ok(token)
-            } else {
-                Err(format!("Expected {:?}, got {:?}", kind, token.kind).into())
-            }
-        } else {
-            Err("Unexpected end of input".into())
+        if self.check(kind) {
+            return Ok(self.advance().expect("checked token is present"));
         }
+        let found = match &self.current {
+            Some(token) if token.kind != TokenKind::EOF => format!("'{}'", token.lexeme),
+            _ => "end of input".to_string(),
+        };
+        Err(self.error_at_current(format!("Expected {:?}, found {}", kind, found)))
+    }
+
+    fn current_span(&self) -> Span {
+        self.current
+            .as_ref()
+            .map(|token| token.span)
+            .unwrap_or(self.previous_span)
+    }
+
+    /// Span from `start` up to the end of the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span)
+    }
+
+    fn error_at_current(&self, message: impl Into<String>) -> IoError {
+        IoError::parser_error(message).with_span(self.current_span())
     }
 
-    pub fn parse_program(&mut self) -> Result<Vec<ASTNode>> {
+    pub fn parse_program(&mut self) -> Result<ASTNode> {
         let mut nodes = Vec::new();
-        while self.current.is_some() {
+        if self.at_module_attribute() {
+            nodes.push(self.parse_module_attributes()?);
+        }
+        while !self.is_at_end() {
             nodes.push(self.parse_declaration()?);
         }
-        Ok(nodes)
+        Ok(ASTNode::Program(nodes))
     }
 
-    fn parse_declaration(&mut self) -> Result<ASTNode> {
-        match &self.current {
-            Some(Token {
-                kind: TokenKind::Function,
-                ..
-            }) => self.parse_function(),
-            Some(Token {
-                kind: TokenKind::Let,
-                ..
-            }) => self.parse_variable_declaration(),
-            Some(Token {
-                kind: TokenKind::Type,
-                ..
-            }) => self.parse_type_declaration(),
-            _ => self.parse_statement(),
+    /// Parses the whole input as one fragment of a macro invocation, which the
+    /// macro expander uses to decide where an `expr` or `ty` fragment ends.
+    pub fn parse_fragment(&mut self, fragment: Fragment) -> Result<()> {
+        match fragment {
+            Fragment::Expr => drop(self.parse_expression()?),
+            Fragment::Ident => drop(self.expect_token(TokenKind::Identifier)?),
+            Fragment::Ty => drop(self.parse_type_annotation()?),
+            Fragment::Block => drop(self.parse_block()?),
         }
+        self.expect_token(TokenKind::EOF).map(drop)
     }
 
-    fn parse_function(&mut self) -> Result<ASTNode> {
-        self.advance(); // consume 'fn'
-        let name = self.expect_token(TokenKind::Identifier)?.value;
+    /// Parses the whole program, replacing each declaration or statement that fails
+    /// to parse with an `ASTNode::Error`. Unless another strategy was configured, the
+    /// parser resynchronizes on `statement_boundaries`.
+    pub fn parse_program_with_recovery(&mut self) -> (ASTNode, Vec<Diagnostic>) {
+        if self.recovery.is_none() {
+            self.recovery = Some(RecoveryStrategy::SynchronizeTo(statement_boundaries()));
+        }
 
-        self.expect_token(TokenKind::LeftParen)?;
-        let parameters = self.parse_parameters()?;
-        self.expect_token(TokenKind::RightParen)?;
+        let mut nodes = Vec::new();
+        if self.at_module_attribute() {
+            match self.parse_module_attributes() {
+                Ok(node) => nodes.push(node),
+                Err(err) => self.diagnostics.push(Diagnostic::from_error(&err)),
+            }
+        }
+        while !self.is_at_end() {
+            match self.parse_declaration_recovering() {
+                Ok(node) => nodes.push(node),
+                Err(err) => {
+                    self.diagnostics.push(Diagnostic::from_error(&err));
+                    break;
+                }
+            }
+        }
 
-        let return_type = if self.match_token(&[TokenKind::Arrow]) {
-            Some(self.expect_token(TokenKind::Identifier)?.value)
-        } else {
-            None
+        (
+            ASTNode::Program(nodes),
+            std::mem::take(&mut self.diagnostics),
+        )
+    }
+
+    /// Parses one declaration. When a recovery strategy is set, a syntax error is
+    /// recorded and the failed source becomes an `ASTNode::Error`.
+    fn parse_declaration_recovering(&mut self) -> Result<ASTNode> {
+        let start = self.current_span();
+        let err = match self.parse_declaration() {
+            Ok(node) => return Ok(node),
+            Err(err) => err,
         };
 
-        self.expect_token(TokenKind::LeftBrace)?;
-        let body = self.parse_block()?;
-        self.expect_token(TokenKind::RightBrace)?;
+        match self.recovery.clone() {
+            Some(RecoveryStrategy::SynchronizeTo(boundaries)) => self.synchronize(&boundaries),
+            Some(RecoveryStrategy::SkipToNextToken) => {
+                self.advance();
+            }
+            Some(RecoveryStrategy::UseDefaultType(_) | RecoveryStrategy::Abort) | None => {
+                return Err(err)
+            }
+        }
 
-        Ok(ASTNode::Function {
-            name,
-            parameters,
-            return_type,
-            body,
-            is_async: false,
+        // Always make progress, or an error on a boundary token would repeat forever.
+        if self.current_span() == start && !self.is_at_end() {
+            self.advance();
+        }
+
+        self.diagnostics.push(Diagnostic::from_error(&err));
+        Ok(ASTNode::Error {
+            id: self.next_id(),
+            span: self.span_from(start),
         })
     }
 
-    fn parse_parameters(&mut self) -> Result<Vec<Parameter>> {
-        let mut parameters = Vec::new();
+    /// Skips tokens up to the next boundary. A `;` is consumed since it ends the
+    /// broken statement; any other boundary starts (or closes) the next construct.
+    fn synchronize(&mut self, boundaries: &[TokenKind]) {
+        while let Some(kind) = self.current.as_ref().map(|token| token.kind) {
+            if kind == TokenKind::EOF {
+                break;
+            }
+            if boundaries.contains(&kind) {
+                if kind == TokenKind::Semicolon {
+                    self.advance();
+                }
+                break;
+            }
+            self.advance();
+        }
+    }
 
-        if !self.match_token(&[TokenKind::RightParen]) {
-            loop {
-                let name = self.expect_token(TokenKind::Identifier)?.value;
-                self.expect_token(TokenKind::Colon)?;
-                let type_name = self.expect_token(TokenKind::Identifier)?.value;
+    fn parse_declaration(&mut self) -> Result<ASTNode> {
+        match self.current.as_ref().map(|token| token.kind) {
+            Some(TokenKind::Function) | Some(TokenKind::Async) => self.parse_function(),
+            Some(TokenKind::Struct) => self.parse_struct(),
+            Some(TokenKind::Enum) => self.parse_enum(),
+            Some(TokenKind::Trait) => self.parse_trait(),
+            Some(TokenKind::Impl) => self.parse_impl(),
+            Some(TokenKind::Const) => self.parse_const_item(),
+            Some(TokenKind::Pub) => self.parse_pub_item(),
+            Some(TokenKind::Import) => self.parse_import(),
+            Some(TokenKind::Let) => self.parse_variable_declaration(),
+            Some(TokenKind::Hash) => {
+                let start = self.current_span();
+                let attributes = self.parse_attributes()?;
+                let item = self.parse_declaration()?;
+                self.with_attributes(item, attributes, start)
+            }
+            _ => self.parse_statement(),
+        }
+    }
 
-                parameters.push(Parameter {
-                    name,
-                    type_annotation: type_name,
-                });
+    /// `#[name]` or `#[name(arg, ...)]`, any number of times.
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>> {
+        let mut attributes = Vec::new();
+        while self.check(TokenKind::Hash) {
+            let start = self.expect_token(TokenKind::Hash)?.span;
+            if self.check(TokenKind::Bang) {
+                return Err(self.error_at_current(
+                    "Module attributes `#![...]` must come before everything else in the file",
+                ));
+            }
+            attributes.push(self.parse_attribute(start)?);
+        }
+        Ok(attributes)
+    }
 
-                if !self.match_token(&[TokenKind::Comma]) {
-                    break;
-                }
+    /// Whether the current token starts a `#![...]` attribute.
+    fn at_module_attribute(&mut self) -> bool {
+        self.check(TokenKind::Hash)
+            && self.tokens.peek().map(|token| token.kind) == Some(TokenKind::Bang)
+    }
+
+    /// `#![name]` or `#![name(arg, ...)]` at the top of a file, any number of
+    /// times.
+    fn parse_module_attributes(&mut self) -> Result<ASTNode> {
+        let start = self.current_span();
+        let mut attributes = Vec::new();
+        while self.at_module_attribute() {
+            let hash = self.expect_token(TokenKind::Hash)?.span;
+            self.expect_token(TokenKind::Bang)?;
+            attributes.push(self.parse_attribute(hash)?);
+        }
+        self.check_attributes(&attributes, "module")?;
+        Ok(ASTNode::ModuleAttributes {
+            attributes,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    /// The `[name(arg, ...)]` of an attribute whose `#` (or `#!`) starts at
+    /// `start`.
+    fn parse_attribute(&mut self, start: Span) -> Result<Attribute> {
+        self.expect_token(TokenKind::LeftBracket)?;
+        let name_token = self.expect_token(TokenKind::Identifier)?;
+        if !ATTRIBUTES
+            .iter()
+            .any(|(name, ..)| *name == name_token.lexeme)
+        {
+            return Err(IoError::parser_error(format!(
+                "Unknown attribute `{}`",
+                name_token.lexeme
+            ))
+            .with_span(name_token.span));
+        }
+        let args = if self.check(TokenKind::LeftParen) {
+            self.parse_attribute_args()?
+        } else {
+            Vec::new()
+        };
+        self.expect_token(TokenKind::RightBracket)?;
+        Ok(Attribute {
+            name: name_token.lexeme,
+            args,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `(arg, ...)`, where each argument is `name`, `name = "value"` or
+    /// `name(arg, ...)`.
+    fn parse_attribute_args(&mut self) -> Result<Vec<AttributeArg>> {
+        self.expect_token(TokenKind::LeftParen)?;
+        let mut args = Vec::new();
+        while !self.check(TokenKind::RightParen) && !self.is_at_end() {
+            let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+            args.push(if self.match_token(&[TokenKind::Equal]) {
+                AttributeArg::Value(name, self.expect_token(TokenKind::String)?.lexeme)
+            } else if self.check(TokenKind::LeftParen) {
+                AttributeArg::List(name, self.parse_attribute_args()?)
+            } else {
+                AttributeArg::Word(name)
+            });
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
             }
         }
+        self.expect_token(TokenKind::RightParen)?;
+        Ok(args)
+    }
 
-        Ok(parameters)
+    /// Checks that each of `attributes` may be written on a `kind` of item, and
+    /// has well-formed arguments.
+    fn check_attributes(&self, attributes: &[Attribute], kind: &str) -> Result<()> {
+        for attribute in attributes {
+            let Some((_, kinds, well_formed)) =
+                ATTRIBUTES.iter().find(|(name, ..)| *name == attribute.name)
+            else {
+                continue;
+            };
+            if !kinds.contains(&kind) {
+                return Err(IoError::parser_error(format!(
+                    "`#[{}]` cannot be applied to a {}",
+                    attribute.name, kind
+                ))
+                .with_span(attribute.span));
+            }
+            if !well_formed(&attribute.args) {
+                return Err(IoError::parser_error(format!("Malformed `{}`", attribute))
+                    .with_span(attribute.span));
+            }
+        }
+        Ok(())
     }
 
-    fn parse_block(&mut self) -> Result<Vec<ASTNode>> {
-        let mut statements = Vec::new();
-        while !self.match_token(&[TokenKind::RightBrace]) {
-            statements.push(self.parse_statement()?);
+    /// `item` with `attributes`, which start at `start`, attached.
+    fn with_attributes(
+        &self,
+        mut item: ASTNode,
+        attributes: Vec<Attribute>,
+        start: Span,
+    ) -> Result<ASTNode> {
+        let kind = match &item {
+            ASTNode::Function { .. } => "function",
+            ASTNode::StructDef { .. } => "struct",
+            ASTNode::Import { .. } => "import",
+            other => {
+                return Err(IoError::parser_error(
+                    "Attributes can only be applied to functions, structs, fields and imports",
+                )
+                .with_span(other.span()))
+            }
+        };
+        self.check_attributes(&attributes, kind)?;
+        if let ASTNode::Function {
+            attributes: slot,
+            span,
+            ..
         }
-        Ok(statements)
+        | ASTNode::StructDef {
+            attributes: slot,
+            span,
+            ..
+        }
+        | ASTNode::Import {
+            attributes: slot,
+            span,
+            ..
+        } = &mut item
+        {
+            slot.extend(attributes);
+            *span = start.to(*span);
+        }
+        Ok(item)
     }
 
-    fn parse_statement(&mut self) -> Result<ASTNode> {
-        match &self.current {
-            Some(Token {
-                kind: TokenKind::If,
-                ..
-            }) => self.parse_if_statement(),
-            Some(Token {
-                kind: TokenKind::While,
-                ..
-            }) => self.parse_while_statement(),
-            Some(Token {
-                kind: TokenKind::For,
-                ..
-            }) => self.parse_for_statement(),
-            Some(Token {
-                kind: TokenKind::Return,
-                ..
-            }) => self.parse_return_statement(),
-            Some(Token {
-                kind: TokenKind::Break,
-                ..
-            }) => {
-                self.advance();
-                Ok(ASTNode::Break)
+    /// `pub fn ...`, `pub struct ...`, `pub enum ...`, `pub trait ...` or
+    /// `pub const ...`
+    fn parse_pub_item(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Pub)?.span;
+        let mut item = match self.current.as_ref().map(|token| token.kind) {
+            Some(TokenKind::Function) | Some(TokenKind::Async) => self.parse_function()?,
+            Some(TokenKind::Struct) => self.parse_struct()?,
+            Some(TokenKind::Enum) => self.parse_enum()?,
+            Some(TokenKind::Trait) => self.parse_trait()?,
+            Some(TokenKind::Const) => self.parse_const_item()?,
+            _ => {
+                return Err(self.error_at_current(
+                    "Expected a function, struct, enum, trait or constant after pub",
+                ))
             }
-            Some(Token {
-                kind: TokenKind::Continue,
-                ..
-            }) => {
-                self.advance();
-                Ok(ASTNode::Continue)
+        };
+        if let ASTNode::Function { is_pub, span, .. }
+        | ASTNode::Global { is_pub, span, .. }
+        | ASTNode::StructDef { is_pub, span, .. }
+        | ASTNode::EnumDef { is_pub, span, .. }
+        | ASTNode::TraitDef { is_pub, span, .. } = &mut item
+        {
+            *is_pub = true;
+            *span = start.to(*span);
+        }
+        Ok(item)
+    }
+
+    /// `import net::http::{get, post};` or `import net::http::get;`
+    fn parse_import(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Import)?.span;
+        let mut path = vec![self.expect_token(TokenKind::Identifier)?.lexeme];
+        let mut items = Vec::new();
+        while self.match_token(&[TokenKind::ColonColon]) {
+            if self.match_token(&[TokenKind::LeftBrace]) {
+                while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+                    items.push(self.expect_token(TokenKind::Identifier)?.lexeme);
+                    if !self.match_token(&[TokenKind::Comma]) {
+                        break;
+                    }
+                }
+                self.expect_token(TokenKind::RightBrace)?;
+                break;
             }
-            _ => self.parse_expression_statement(),
+            path.push(self.expect_token(TokenKind::Identifier)?.lexeme);
+        }
+        if items.is_empty() {
+            if path.len() < 2 {
+                return Err(IoError::parser_error(
+                    "Expected the items to import, e.g. `import net::http::{get}`",
+                )
+                .with_span(self.span_from(start)));
+            }
+            items.extend(path.pop());
         }
+        self.expect_token(TokenKind::Semicolon)?;
+
+        Ok(ASTNode::Import {
+            path,
+            items,
+            attributes: Vec::new(),
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_if_statement(&mut self) -> Result<ASTNode> {
-        self.advance(); // consume 'if'
-        let condition = Box::new(self.parse_expression()?);
+    fn parse_function(&mut self) -> Result<ASTNode> {
+        let start = self.current_span();
+        let is_async = self.match_token(&[TokenKind::Async]);
+        self.expect_token(TokenKind::Function)?;
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+        let (type_params, bounds) = self.parse_type_params(true)?;
 
-        self.expect_token(TokenKind::LeftBrace)?;
-        let then_branch = self.parse_block()?;
-        self.expect_token(TokenKind::RightBrace)?;
+        // Methods keep seeing the `Self` of their trait or impl.
+        let scope = self
+            .type_params
+            .iter()
+            .chain(&type_params)
+            .cloned()
+            .collect();
+        let outer = std::mem::replace(&mut self.type_params, scope);
+        let signature = self.parse_signature();
+        self.type_params = outer;
+        let (params, return_type) = signature?;
 
-        let else_branch = if self.match_token(&[TokenKind::Else]) {
-            self.expect_token(TokenKind::LeftBrace)?;
-            let branch = self.parse_block()?;
-            self.expect_token(TokenKind::RightBrace)?;
-            Some(branch)
+        let body = self.parse_block()?;
+
+        Ok(ASTNode::Function {
+            name,
+            type_params,
+            bounds,
+            params,
+            return_type,
+            body,
+            is_async,
+            is_const: false,
+            is_pub: false,
+            attributes: Vec::new(),
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    /// `const NAME: Type = value;`, or a `const fn`.
+    fn parse_const_item(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Const)?.span;
+        if self.check(TokenKind::Function) {
+            let mut function = self.parse_function()?;
+            if let ASTNode::Function { is_const, span, .. } = &mut function {
+                *is_const = true;
+                *span = start.to(*span);
+            }
+            return Ok(function);
+        }
+
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+        let type_annotation = if self.match_token(&[TokenKind::Colon]) {
+            Some(self.parse_type_annotation()?)
         } else {
             None
         };
+        self.expect_token(TokenKind::Equal)?;
+        let value = Box::new(self.parse_expression()?);
+        self.expect_token(TokenKind::Semicolon)?;
 
-        Ok(ASTNode::If {
-            condition,
-            then_branch,
-            else_branch,
+        Ok(ASTNode::Global {
+            name,
+            type_annotation,
+            value,
+            is_pub: false,
+            id: self.next_id(),
+            span: self.span_from(start),
         })
     }
 
-    fn parse_expression(&mut self) -> Result<ASTNode> {
-        self.parse_assignment()
-    }
-
-    fn parse_assignment(&mut self) -> Result<ASTNode> {
-        let expr = self.parse_logical_or()?;
+    /// `(params) -> ReturnType`, with the return type optional.
+    fn parse_signature(&mut self) -> Result<(Vec<Parameter>, Option<Type>)> {
+        self.expect_token(TokenKind::LeftParen)?;
+        let params = self.parse_parameters()?;
+        self.expect_token(TokenKind::RightParen)?;
 
-        if self.match_token(&[TokenKind::Equal]) {
-            let value = Box::new(self.parse_assignment()?);
-            match expr {
-                ASTNode::Identifier(name) => Ok(ASTNode::Assignment {
-                    target: Box::new(ASTNode::Identifier(name)),
-                    value,
-                }),
-                _ => Err("Invalid assignment target".into()),
-            }
+        let return_type = if self.match_token(&[TokenKind::Arrow]) {
+            Some(self.parse_type_annotation()?)
         } else {
-            Ok(expr)
-        }
+            None
+        };
+        Ok((params, return_type))
     }
 
-    fn parse_logical_or(&mut self) -> Result<ASTNode> {
-        let mut expr = self.parse_logical_and()?;
+    /// `struct Name<A, B> { field: Type, ... }`
+    fn parse_struct(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Struct)?.span;
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+        let (type_params, _) = self.parse_type_params(false)?;
 
-        while self.match_token(&[TokenKind::Or]) {
-            let operator = BinaryOperator::Or;
-            let right = self.parse_logical_and()?;
-            expr = ASTNode::BinaryOp {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
+        let outer = std::mem::replace(&mut self.type_params, type_params.clone());
+        let fields = self.parse_fields();
+        self.type_params = outer;
+        let fields = fields?;
 
-        Ok(expr)
+        Ok(ASTNode::StructDef {
+            name,
+            type_params,
+            fields,
+            is_pub: false,
+            attributes: Vec::new(),
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_logical_and(&mut self) -> Result<ASTNode> {
-        let mut expr = self.parse_equality()?;
+    /// `enum Name<T> { Unit, Tuple(T), Named { field: T } }`
+    fn parse_enum(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Enum)?.span;
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+        let (type_params, _) = self.parse_type_params(false)?;
 
-        while self.match_token(&[TokenKind::And]) {
-            let operator = BinaryOperator::And;
-            let right = self.parse_equality()?;
-            expr = ASTNode::BinaryOp {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
+        let outer = std::mem::replace(&mut self.type_params, type_params.clone());
+        let variants = self.parse_variants();
+        self.type_params = outer;
+        let variants = variants?;
 
-        Ok(expr)
+        Ok(ASTNode::EnumDef {
+            name,
+            type_params,
+            variants,
+            is_pub: false,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_equality(&mut self) -> Result<ASTNode> {
-        let mut expr = self.parse_comparison()?;
+    /// `trait Name<T> { fn required(self) -> T; fn provided(self) { ... } }`
+    fn parse_trait(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Trait)?.span;
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+        let (type_params, _) = self.parse_type_params(false)?;
 
-        while self.match_token(&[TokenKind::EqualEqual, TokenKind::BangEqual]) {
-            let operator = match self.current.as_ref().unwrap().kind {
-                TokenKind::EqualEqual => BinaryOperator::Equal,
-                TokenKind::BangEqual => BinaryOperator::NotEqual,
-                _ => unreachable!(),
-            };
-            let right = self.parse_comparison()?;
-            expr = ASTNode::BinaryOp {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
+        let in_scope = std::iter::once("Self".to_string())
+            .chain(type_params.iter().cloned())
+            .collect();
+        let outer = std::mem::replace(&mut self.type_params, in_scope);
+        let methods = self.parse_trait_methods();
+        self.type_params = outer;
+        let methods = methods?;
+
+        Ok(ASTNode::TraitDef {
+            name,
+            type_params,
+            methods,
+            is_pub: false,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_trait_methods(&mut self) -> Result<Vec<TraitMethod>> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut methods = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            let start = self.expect_token(TokenKind::Function)?.span;
+            let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+            let (params, return_type) = self.parse_signature()?;
+            let body = if self.match_token(&[TokenKind::Semicolon]) {
+                None
+            } else {
+                Some(self.parse_block()?)
             };
+            methods.push(TraitMethod {
+                name,
+                params,
+                return_type,
+                body,
+                id: self.next_id(),
+                span: self.span_from(start),
+            });
         }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(methods)
+    }
 
-        Ok(expr)
+    /// `impl Trait<Arg> for Type { fn method(self) { ... } ... }`
+    fn parse_impl(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Impl)?.span;
+        // `impl Type {` has no trait; otherwise the first type names the trait.
+        let first = self.parse_type_annotation()?;
+        let (trait_name, trait_args, self_type) = if self.match_token(&[TokenKind::For]) {
+            let (trait_name, trait_args) = match first {
+                Type::Named(name) => (name, Vec::new()),
+                Type::Generic { name, args } => (name, args),
+                other => {
+                    return Err(IoError::parser_error(format!(
+                        "Expected a trait name, found {}",
+                        other
+                    ))
+                    .with_span(self.span_from(start)))
+                }
+            };
+            (Some(trait_name), trait_args, self.parse_type_annotation()?)
+        } else {
+            (None, Vec::new(), first)
+        };
+
+        let outer = std::mem::replace(&mut self.type_params, vec!["Self".to_string()]);
+        let methods = self.parse_impl_methods();
+        self.type_params = outer;
+        let methods = methods?;
+
+        Ok(ASTNode::Impl {
+            trait_name,
+            trait_args,
+            self_type,
+            methods,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_primary(&mut self) -> Result<ASTNode> {
-        match &self.current {
-            Some(Token {
-                kind: TokenKind::Number,
-                value,
-            }) => {
-                self.advance();
-                Ok(ASTNode::NumberLiteral(value.parse()?))
-            }
-            Some(Token {
-                kind: TokenKind::String,
-                value,
-            }) => {
-                self.advance();
-                Ok(ASTNode::StringLiteral(value.clone()))
-            }
-            Some(Token {
-                kind: TokenKind::True,
-            }) => {
-                self.advance();
-                Ok(ASTNode::BooleanLiteral(true))
-            }
-            Some(Token {
-                kind: TokenKind::False,
-            }) => {
-                self.advance();
-                Ok(ASTNode::BooleanLiteral(false))
-            }
-            Some(Token {
-                kind: TokenKind::Identifier,
-                value,
-            }) => {
-                self.advance();
-                Ok(ASTNode::Identifier(value.clone()))
+    fn parse_impl_methods(&mut self) -> Result<Vec<ASTNode>> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut methods = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            let start = self.current_span();
+            let attributes = self.parse_attributes()?;
+            if !self.check(TokenKind::Function) {
+                return Err(self.error_at_current("Expected a method in impl block"));
             }
-            Some(Token {
-                kind: TokenKind::LeftParen,
-                ..
-            }) => {
-                self.advance();
-                let expr = self.parse_expression()?;
+            let method = self.parse_function()?;
+            methods.push(self.with_attributes(method, attributes, start)?);
+        }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(methods)
+    }
+
+    fn parse_variants(&mut self) -> Result<Vec<Variant>> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut variants = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            let name_token = self.expect_token(TokenKind::Identifier)?;
+            let fields = if self.match_token(&[TokenKind::LeftParen]) {
+                let mut types = Vec::new();
+                while !self.check(TokenKind::RightParen) && !self.is_at_end() {
+                    types.push(self.parse_type_annotation()?);
+                    if !self.match_token(&[TokenKind::Comma]) {
+                        break;
+                    }
+                }
                 self.expect_token(TokenKind::RightParen)?;
-                Ok(expr)
+                VariantFields::Tuple(types)
+            } else if self.check(TokenKind::LeftBrace) {
+                VariantFields::Named(self.parse_fields()?)
+            } else {
+                VariantFields::Unit
+            };
+            variants.push(Variant {
+                name: name_token.lexeme,
+                fields,
+                id: self.next_id(),
+                span: self.span_from(name_token.span),
+            });
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
             }
-            Some(Token {
-                kind: TokenKind::LeftBracket,
-                ..
-            }) => self.parse_array_literal(),
-            _ => Err("Unexpected token in primary expression".into()),
         }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(variants)
     }
 
-    fn parse_array_literal(&mut self) -> Result<ASTNode> {
-        self.advance(); // consume '['
-        let mut elements = Vec::new();
+    fn parse_fields(&mut self) -> Result<Vec<Field>> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut fields = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            let attributes = self.parse_attributes()?;
+            self.check_attributes(&attributes, "field")?;
+            let name_token = self.expect_token(TokenKind::Identifier)?;
+            self.expect_token(TokenKind::Colon)?;
+            let type_annotation = self.parse_type_annotation()?;
+            fields.push(Field {
+                name: name_token.lexeme,
+                type_annotation,
+                attributes,
+                id: self.next_id(),
+                span: self.span_from(name_token.span),
+            });
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(fields)
+    }
 
-        if !self.match_token(&[TokenKind::RightBracket]) {
-            loop {
-                elements.push(self.parse_expression()?);
-                if !self.match_token(&[TokenKind::Comma]) {
-                    break;
+    /// An optional `<A, B: Trait + Other>` list of type parameter names, with
+    /// the trait bounds of each when `allow_bounds` is set.
+    fn parse_type_params(&mut self, allow_bounds: bool) -> Result<TypeParams> {
+        let mut params: Vec<String> = Vec::new();
+        let mut bounds = Vec::new();
+        if !self.match_token(&[TokenKind::Less]) {
+            return Ok((params, bounds));
+        }
+        loop {
+            let token = self.expect_token(TokenKind::Identifier)?;
+            if params.contains(&token.lexeme) || token.lexeme.parse::<Type>().is_ok() {
+                return Err(IoError::parser_error(format!(
+                    "Invalid type parameter name '{}'",
+                    token.lexeme
+                ))
+                .with_span(token.span));
+            }
+            if self.check(TokenKind::Colon) {
+                if !allow_bounds {
+                    return Err(self.error_at_current("Trait bounds are only allowed on functions"));
+                }
+                self.advance();
+                loop {
+                    let bound = self.expect_token(TokenKind::Identifier)?.lexeme;
+                    bounds.push((token.lexeme.clone(), bound));
+                    if !self.match_token(&[TokenKind::Plus]) {
+                        break;
+                    }
                 }
             }
-            self.expect_token(TokenKind::RightBracket)?;
+            params.push(token.lexeme);
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
+            }
         }
-
-        Ok(ASTNode::ArrayLiteral(elements))
+        self.expect_closing_angle()?;
+        Ok((params, bounds))
     }
-}
+
+    /// Consumes a `>` that closes a type argument list. The lexer reads `>>` and
+    /// `>=` as single tokens, so those are split and their remainder kept.
+    fn expect_closing_angle(&mut self) -> Result<()> {
+        let rest = match self.current.as_ref().map(|token| token.kind) {
+            Some(TokenKind::GreaterGreater) => TokenKind::Greater,
+            Some(TokenKind::GreaterEqual) => TokenKind::Equal,
+            _ => return self.expect_token(TokenKind::Greater).map(|_| ()),
+        };
+        let token = self.current.as_mut().expect("checked token is present");
+        let closing = Span::new(token.span.file_id, token.span.start, token.span.start + 1);
+        token.kind = rest;
+        token.lexeme.remove(0);
+        token.span.start += 1;
+        self.previous_span = closing;
+        Ok(())
+    }
+
+    fn parse_parameters(&mut self) -> Result<Vec<Parameter>> {
+        let mut parameters = Vec::new();
+
+        if self.check(TokenKind::RightParen) {
+            return Ok(parameters);
+        }
+
+        loop {
+            let name_token = self.expect_token(TokenKind::Identifier)?;
+            // A method's receiver, `self`, is written without a type.
+            let is_receiver = name_token.lexeme == "self"
+                && parameters.is_empty()
+                && self.type_params.iter().any(|param| param == "Self")
+                && !self.check(TokenKind::Colon);
+            let type_annotation = if is_receiver {
+                Type::Param("Self".to_string())
+            } else {
+                self.expect_token(TokenKind::Colon)?;
+                self.parse_type_annotation()?
+            };
+
+            parameters.push(Parameter {
+                name: name_token.lexeme,
+                type_annotation,
+                id: self.next_id(),
+                span: self.span_from(name_token.span),
+            });
+
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    /// Parses a type. Names that aren't builtin types are kept as `Type::Named`
+    /// for the type checker to resolve, or become `Type::Param` inside a generic
+    /// function or struct that declares them.
+    fn parse_type_annotation(&mut self) -> Result<Type> {
+        if self.match_token(&[TokenKind::LeftBracket]) {
+            let elem_type = Box::new(self.parse_type_annotation()?);
+            let ty = if self.match_token(&[TokenKind::Semicolon]) {
+                self.parse_array_length(elem_type)?
+            } else {
+                Type::Array { elem_type, size: 0 }
+            };
+            self.expect_token(TokenKind::RightBracket)?;
+            return Ok(ty);
+        }
+        if self.match_token(&[TokenKind::Dyn]) {
+            let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+            return Ok(Type::Dyn(name));
+        }
+        if self.match_token(&[TokenKind::Ampersand]) {
+            let mutable = self.match_token(&[TokenKind::Mut]);
+            return Ok(Type::Ref {
+                mutable,
+                inner: Box::new(self.parse_type_annotation()?),
+            });
+        }
+        if self.match_token(&[TokenKind::LeftParen]) {
+            // `(A, B)`, or `(A,)` for a tuple of one; `(A)` is just `A`.
+            let mut elements = Vec::new();
+            let mut trailing_comma = false;
+            while !self.check(TokenKind::RightParen) {
+                elements.push(self.parse_type_annotation()?);
+                trailing_comma = self.match_token(&[TokenKind::Comma]);
+                if !trailing_comma {
+                    break;
+                }
+            }
+            self.expect_token(TokenKind::RightParen)?;
+            if elements.len() == 1 && !trailing_comma {
+                return Ok(elements.remove(0));
+            }
+            return Ok(Type::Tuple(elements));
+        }
+        if self.match_token(&[TokenKind::Function]) {
+            self.expect_token(TokenKind::LeftParen)?;
+            let mut params = Vec::new();
+            while !self.check(TokenKind::RightParen) {
+                params.push(self.parse_type_annotation()?);
+                if !self.match_token(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+            self.expect_token(TokenKind::RightParen)?;
+            let return_type = if self.match_token(&[TokenKind::Arrow]) {
+                self.parse_type_annotation()?
+            } else {
+                Type::Void
+            };
+            return Ok(Type::Function {
+                params,
+                return_type: Box::new(return_type),
+                is_async: false,
+            });
+        }
+
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+        if self.match_token(&[TokenKind::Less]) {
+            let args = self.parse_type_args()?;
+            return Ok(Type::Generic { name, args });
+        }
+        if self.type_params.contains(&name) {
+            return Ok(Type::Param(name));
+        }
+        Ok(name.parse().unwrap_or(Type::Named(name)))
+    }
+
+    /// The length of `[T; N]`, after the `;`: a number, or the name of a
+    /// constant.
+    fn parse_array_length(&mut self, elem_type: Box<Type>) -> Result<Type> {
+        if self.check(TokenKind::Number) {
+            let token = self.advance().expect("checked token is present");
+            let size = match self.parse_number_literal(token)? {
+                ASTNode::Literal {
+                    value: Literal::Integer(size),
+                    ..
+                } => usize::try_from(size).ok().filter(|size| *size > 0),
+                _ => None,
+            };
+            return size
+                .map(|size| Type::Array { elem_type, size })
+                .ok_or_else(|| {
+                    IoError::parser_error("Array length must be a positive integer")
+                        .with_span(self.previous_span)
+                });
+        }
+        let mut length = self.expect_token(TokenKind::Identifier)?.lexeme;
+        while self.match_token(&[TokenKind::ColonColon]) {
+            length.push_str("::");
+            length.push_str(&self.expect_token(TokenKind::Identifier)?.lexeme);
+        }
+        Ok(Type::ConstArray { elem_type, length })
+    }
+
+    /// The type arguments of `Name<A, B>`, after the `<`.
+    fn parse_type_args(&mut self) -> Result<Vec<Type>> {
+        let mut args = Vec::new();
+        loop {
+            args.push(self.parse_type_annotation()?);
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+        self.expect_closing_angle()?;
+        Ok(args)
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<ASTNode>> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut statements = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            statements.push(self.parse_declaration_recovering()?);
+        }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(statements)
+    }
+
+    /// `let name = value`, `let name: T;` to assign it later, or
+    /// `let pattern = value` to destructure the value.
+    fn parse_variable_declaration(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Let)?.span;
+        let plain = self.check(TokenKind::Identifier)
+            && matches!(
+                self.tokens.peek().map(|token| token.kind),
+                Some(TokenKind::Colon | TokenKind::Equal | TokenKind::Semicolon)
+            );
+        if !plain {
+            return self.parse_let_pattern(start);
+        }
+        let name = self.expect_token(TokenKind::Identifier)?.lexeme;
+
+        let type_annotation = if self.match_token(&[TokenKind::Colon]) {
+            Some(self.parse_type_annotation()?)
+        } else {
+            None
+        };
+
+        let value = if self.match_token(&[TokenKind::Semicolon]) {
+            None
+        } else {
+            self.expect_token(TokenKind::Equal)?;
+            let value = self.parse_expression()?;
+            self.match_token(&[TokenKind::Semicolon]);
+            Some(Box::new(value))
+        };
+
+        Ok(ASTNode::Let {
+            name,
+            type_annotation,
+            value,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_let_pattern(&mut self, start: Span) -> Result<ASTNode> {
+        let pattern = self.parse_pattern()?;
+        let type_annotation = if self.match_token(&[TokenKind::Colon]) {
+            Some(self.parse_type_annotation()?)
+        } else {
+            None
+        };
+
+        self.expect_token(TokenKind::Equal)?;
+        let value = Box::new(self.parse_expression()?);
+        self.match_token(&[TokenKind::Semicolon]);
+
+        Ok(ASTNode::LetPattern {
+            pattern,
+            type_annotation,
+            value,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_statement(&mut self) -> Result<ASTNode> {
+        match self.current.as_ref().map(|token| token.kind) {
+            Some(TokenKind::If) => self.parse_if_statement(),
+            Some(TokenKind::While) => self.parse_while_statement(),
+            Some(TokenKind::For) => self.parse_for_statement(),
+            Some(TokenKind::Return) => self.parse_return_statement(),
+            Some(TokenKind::Break) => {
+                let span = self.expect_token(TokenKind::Break)?.span;
+                self.match_token(&[TokenKind::Semicolon]);
+                Ok(ASTNode::Break {
+                    id: self.next_id(),
+                    span,
+                })
+            }
+            Some(TokenKind::Continue) => {
+                let span = self.expect_token(TokenKind::Continue)?.span;
+                self.match_token(&[TokenKind::Semicolon]);
+                Ok(ASTNode::Continue {
+                    id: self.next_id(),
+                    span,
+                })
+            }
+            Some(TokenKind::LeftBrace) => {
+                let start = self.current_span();
+                let statements = self.parse_block()?;
+                Ok(ASTNode::Block {
+                    statements,
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                })
+            }
+            _ => self.parse_expression_statement(),
+        }
+    }
+
+    fn parse_if_statement(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::If)?.span;
+        let condition = Box::new(self.parse_head_expression()?);
+        let then_branch = self.parse_block()?;
+
+        let else_branch = if self.match_token(&[TokenKind::Else]) {
+            if self.check(TokenKind::If) {
+                Some(vec![self.parse_if_statement()?])
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+
+        Ok(ASTNode::If {
+            condition,
+            then_branch,
+            else_branch,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_while_statement(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::While)?.span;
+        let condition = Box::new(self.parse_head_expression()?);
+        let body = self.parse_block()?;
+
+        Ok(ASTNode::While {
+            condition,
+            body,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_for_statement(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::For)?.span;
+        let pattern = self.parse_pattern()?;
+        self.expect_token(TokenKind::In)?;
+        let iterable = Box::new(self.parse_head_expression()?);
+        let body = self.parse_block()?;
+
+        Ok(ASTNode::For {
+            pattern,
+            iterable,
+            body,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_return_statement(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Return)?.span;
+        let value = if self.check(TokenKind::Semicolon) || self.check(TokenKind::RightBrace) {
+            None
+        } else {
+            Some(Box::new(self.parse_expression()?))
+        };
+        self.match_token(&[TokenKind::Semicolon]);
+
+        Ok(ASTNode::Return {
+            value,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_expression_statement(&mut self) -> Result<ASTNode> {
+        let expr = self.parse_expression()?;
+        self.match_token(&[TokenKind::Semicolon]);
+        Ok(expr)
+    }
+
+    fn parse_expression(&mut self) -> Result<ASTNode> {
+        self.parse_assignment()
+    }
+
+    /// An expression directly followed by a block, which a struct literal would
+    /// otherwise swallow.
+    fn parse_head_expression(&mut self) -> Result<ASTNode> {
+        let outer = std::mem::replace(&mut self.no_struct_literals, true);
+        let expr = self.parse_expression();
+        self.no_struct_literals = outer;
+        expr
+    }
+
+    /// An expression inside brackets, where struct literals are allowed again.
+    fn parse_nested_expression(&mut self) -> Result<ASTNode> {
+        let outer = std::mem::replace(&mut self.no_struct_literals, false);
+        let expr = self.parse_expression();
+        self.no_struct_literals = outer;
+        expr
+    }
+
+    fn parse_assignment(&mut self) -> Result<ASTNode> {
+        let expr = self.parse_binary(0)?;
+
+        let kind = match self.current.as_ref().map(|token| token.kind) {
+            Some(kind) if kind == TokenKind::Equal || compound_operator(kind).is_some() => kind,
+            _ => return Ok(expr),
+        };
+        self.advance();
+        let value = Box::new(self.parse_assignment()?);
+
+        // A variable, or a field or element of one: `p.pos.x`, `grid[i][j]`.
+        if expr.place_root().is_none() {
+            return Err(IoError::parser_error("Invalid assignment target").with_span(expr.span()));
+        }
+        let span = self.span_from(expr.span());
+        let target = Box::new(expr);
+        let id = self.next_id();
+
+        Ok(match compound_operator(kind) {
+            Some(op) => ASTNode::CompoundAssignment {
+                target,
+                op,
+                value,
+                id,
+                span,
+            },
+            None => ASTNode::Assignment {
+                target,
+                value,
+                id,
+                span,
+            },
+        })
+    }
+
+    /// Precedence climbing over `binary_operator`; only operators binding at least as
+    /// tightly as `min_precedence` are consumed at this level.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<ASTNode> {
+        let mut expr = self.parse_cast()?;
+
+        while let Some((precedence, op)) = self
+            .current
+            .as_ref()
+            .and_then(|token| binary_operator(token.kind))
+        {
+            if precedence < min_precedence {
+                break;
+            }
+            let op_span = self.advance().expect("operator token is present").span;
+
+            if op.is_range() && is_range(&expr) {
+                return Err(
+                    IoError::parser_error("Range operators cannot be chained").with_span(op_span)
+                );
+            }
+
+            let right = self.parse_binary(precedence + 1)?;
+            let span = expr.span().to(right.span());
+            expr = ASTNode::BinaryOp {
+                op,
+                left: Box::new(expr),
+                right: Box::new(right),
+                id: self.next_id(),
+                span,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// `value as target`, binding tighter than any binary operator and looser
+    /// than the unary ones: `-x as u8` converts `-x`.
+    fn parse_cast(&mut self) -> Result<ASTNode> {
+        let mut expr = self.parse_unary()?;
+        while self.match_token(&[TokenKind::As]) {
+            let target = self.parse_type_annotation()?;
+            let span = self.span_from(expr.span());
+            expr = ASTNode::Cast {
+                value: Box::new(expr),
+                target,
+                id: self.next_id(),
+                span,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<ASTNode> {
+        let op = match self.current.as_ref().map(|token| token.kind) {
+            Some(TokenKind::Minus) => UnaryOperator::Negate,
+            Some(TokenKind::Bang) => UnaryOperator::Not,
+            Some(TokenKind::Await) => UnaryOperator::Await,
+            Some(TokenKind::Star) => UnaryOperator::Deref,
+            Some(TokenKind::Ampersand) => UnaryOperator::Ref,
+            _ => return self.parse_postfix(),
+        };
+        let start = self.advance().expect("operator token is present").span;
+        let op = match op {
+            UnaryOperator::Ref if self.match_token(&[TokenKind::Mut]) => UnaryOperator::RefMut,
+            op => op,
+        };
+        let operand = Box::new(self.parse_unary()?);
+
+        Ok(ASTNode::UnaryOp {
+            op,
+            operand,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_postfix(&mut self) -> Result<ASTNode> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            if self.match_token(&[TokenKind::LeftParen]) {
+                let args = self.parse_arguments()?;
+                let span = self.span_from(expr.span());
+                expr = ASTNode::Call {
+                    callee: Box::new(expr),
+                    args,
+                    id: self.next_id(),
+                    span,
+                };
+            } else if self.match_token(&[TokenKind::Dot]) {
+                for member in self.parse_members()? {
+                    let span = self.span_from(expr.span());
+                    expr = ASTNode::MemberAccess {
+                        object: Box::new(expr),
+                        member,
+                        id: self.next_id(),
+                        span,
+                    };
+                }
+            } else if self.check(TokenKind::Question) {
+                let end = self.advance().expect("operator token is present").span;
+                let span = expr.span().to(end);
+                expr = ASTNode::UnaryOp {
+                    op: UnaryOperator::Try,
+                    operand: Box::new(expr),
+                    id: self.next_id(),
+                    span,
+                };
+            } else if self.match_token(&[TokenKind::LeftBracket]) {
+                let index = Box::new(self.parse_nested_expression()?);
+                self.expect_token(TokenKind::RightBracket)?;
+                let span = self.span_from(expr.span());
+                expr = ASTNode::Index {
+                    array: Box::new(expr),
+                    index,
+                    id: self.next_id(),
+                    span,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// The field after a `.`: a name, or the position of a tuple field. The
+    /// lexer reads the positions in `pair.0.1` as the one number `0.1`.
+    fn parse_members(&mut self) -> Result<Vec<String>> {
+        if !self.check(TokenKind::Number) {
+            return Ok(vec![self.expect_token(TokenKind::Identifier)?.lexeme]);
+        }
+        let token = self.advance().expect("number token is present");
+        let positions: Vec<String> = token.lexeme.split('.').map(str::to_string).collect();
+        let valid = |position: &String| {
+            !position.is_empty() && position.bytes().all(|byte| byte.is_ascii_digit())
+        };
+        if !positions.iter().all(valid) {
+            return Err(
+                IoError::parser_error(format!("Invalid tuple field '{}'", token.lexeme))
+                    .with_span(token.span),
+            );
+        }
+        Ok(positions)
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<ASTNode>> {
+        let mut args = Vec::new();
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                args.push(self.parse_nested_expression()?);
+                if !self.match_token(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.expect_token(TokenKind::RightParen)?;
+        Ok(args)
+    }
+
+    fn parse_primary(&mut self) -> Result<ASTNode> {
+        let kind = match &self.current {
+            Some(token) if token.kind != TokenKind::EOF => token.kind,
+            _ => return Err(self.error_at_current("Unexpected end of input")),
+        };
+
+        match kind {
+            TokenKind::Number => {
+                let token = self.advance().expect("number token is present");
+                self.parse_number_literal(token)
+            }
+            TokenKind::String => {
+                let token = self.advance().expect("string token is present");
+                Ok(ASTNode::Literal {
+                    value: Literal::String(token.lexeme),
+                    id: self.next_id(),
+                    span: token.span,
+                })
+            }
+            TokenKind::Char => {
+                let token = self.advance().expect("char token is present");
+                let value = token.lexeme.chars().next().unwrap_or_default();
+                Ok(ASTNode::Literal {
+                    value: Literal::Char(value),
+                    id: self.next_id(),
+                    span: token.span,
+                })
+            }
+            TokenKind::True | TokenKind::False => {
+                let token = self.advance().expect("boolean token is present");
+                Ok(ASTNode::Literal {
+                    value: Literal::Boolean(token.kind == TokenKind::True),
+                    id: self.next_id(),
+                    span: token.span,
+                })
+            }
+            TokenKind::Identifier => {
+                let token = self.advance().expect("identifier token is present");
+                // Paths such as `math::max` are kept as a single qualified name.
+                let mut name = token.lexeme;
+                while self.match_token(&[TokenKind::ColonColon]) {
+                    name.push_str("::");
+                    name.push_str(&self.expect_token(TokenKind::Identifier)?.lexeme);
+                }
+                if !self.no_struct_literals && self.check(TokenKind::LeftBrace) {
+                    return self.parse_struct_literal(name, token.span);
+                }
+                Ok(ASTNode::Identifier {
+                    name,
+                    id: self.next_id(),
+                    span: self.span_from(token.span),
+                })
+            }
+            TokenKind::LeftParen => {
+                let start = self.advance().expect("parenthesis token is present").span;
+                let expr = self.parse_nested_expression()?;
+                if !self.check(TokenKind::Comma) {
+                    self.expect_token(TokenKind::RightParen)?;
+                    return Ok(expr);
+                }
+                // `(a, b)`, or `(a,)` for a tuple of one.
+                let mut elements = vec![expr];
+                while self.match_token(&[TokenKind::Comma]) && !self.check(TokenKind::RightParen) {
+                    elements.push(self.parse_nested_expression()?);
+                }
+                self.expect_token(TokenKind::RightParen)?;
+                Ok(ASTNode::TupleLiteral {
+                    elements,
+                    id: self.next_id(),
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::LeftBracket => self.parse_array_literal(),
+            TokenKind::Match => self.parse_match(),
+            TokenKind::Pipe | TokenKind::Or | TokenKind::Move => self.parse_closure(),
+            TokenKind::Error => {
+                let token = self.advance().expect("error token is present");
+                Ok(ASTNode::Error {
+                    id: self.next_id(),
+                    span: token.span,
+                })
+            }
+            _ => Err(self.error_at_current(format!(
+                "Unexpected token '{}' in expression",
+                self.current
+                    .as_ref()
+                    .map(|t| t.lexeme.as_str())
+                    .unwrap_or("")
+            ))),
+        }
+    }
+
+    fn parse_number_literal(&mut self, token: Token) -> Result<ASTNode> {
+        let literal = NumberLiteral::parse(&token.lexeme)
+            .map_err(|msg| IoError::parser_error(msg).with_span(token.span))?;
+        let span = token.span;
+
+        let out_of_range = || {
+            IoError::parser_error(format!("Integer literal '{}' out of range", token.lexeme))
+                .with_span(span)
+        };
+        let suffix = literal.suffix.map(|suffix| {
+            suffix
+                .parse::<Type>()
+                .expect("the lexer only accepts type names as suffixes")
+        });
+        let value = match (literal.value, suffix) {
+            (NumberValue::Float(value), None) => Literal::Float(value),
+            (NumberValue::Integer(value), None) => {
+                Literal::Integer(i64::try_from(value).map_err(|_| out_of_range())?)
+            }
+            (NumberValue::Float(value), Some(ty)) => Literal::TypedFloat(value, ty),
+            (NumberValue::Integer(value), Some(ty)) if ty.is_float() => {
+                Literal::TypedFloat(value as f64, ty)
+            }
+            (NumberValue::Integer(value), Some(ty)) => {
+                Literal::TypedInteger(i128::try_from(value).map_err(|_| out_of_range())?, ty)
+            }
+        };
+        Ok(ASTNode::Literal {
+            value,
+            id: self.next_id(),
+            span,
+        })
+    }
+
+    /// `{ field: value, ... }` after the name of what is being built; a bare
+    /// `field` takes the value of the variable of that name.
+    fn parse_struct_literal(&mut self, name: String, start: Span) -> Result<ASTNode> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut fields = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            let field = self.expect_token(TokenKind::Identifier)?;
+            let value = if self.match_token(&[TokenKind::Colon]) {
+                self.parse_nested_expression()?
+            } else {
+                ASTNode::Identifier {
+                    name: field.lexeme.clone(),
+                    id: self.next_id(),
+                    span: field.span,
+                }
+            };
+            fields.push((field.lexeme, value));
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(ASTNode::StructLiteral {
+            name,
+            fields,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    /// `match scrutinee { pattern if guard => body, ... }`. Arms whose body is a
+    /// block need no trailing comma.
+    fn parse_match(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::Match)?.span;
+        let scrutinee = Box::new(self.parse_head_expression()?);
+        self.expect_token(TokenKind::LeftBrace)?;
+
+        let outer = std::mem::replace(&mut self.no_struct_literals, false);
+        let mut arms = Vec::new();
+        let result = loop {
+            if self.check(TokenKind::RightBrace) || self.is_at_end() {
+                break Ok(());
+            }
+            let arm = match self.parse_match_arm() {
+                Ok(arm) => arm,
+                Err(err) => break Err(err),
+            };
+            let block_body = matches!(arm.body, ASTNode::Block { .. });
+            arms.push(arm);
+            if !self.match_token(&[TokenKind::Comma])
+                && !block_body
+                && !self.check(TokenKind::RightBrace)
+            {
+                break Err(self.error_at_current("Expected ',' after match arm"));
+            }
+        };
+        self.no_struct_literals = outer;
+        result?;
+        self.expect_token(TokenKind::RightBrace)?;
+
+        Ok(ASTNode::Match {
+            scrutinee,
+            arms,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_match_arm(&mut self) -> Result<MatchArm> {
+        let start = self.current_span();
+        let pattern = self.parse_pattern()?;
+        let guard = if self.match_token(&[TokenKind::If]) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        self.expect_token(TokenKind::FatArrow)?;
+        let body = if self.check(TokenKind::LeftBrace) {
+            let body_start = self.current_span();
+            let statements = self.parse_block()?;
+            ASTNode::Block {
+                statements,
+                id: self.next_id(),
+                span: self.span_from(body_start),
+            }
+        } else {
+            self.parse_expression()?
+        };
+        Ok(MatchArm {
+            pattern,
+            guard,
+            body,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    /// A pattern, with `|` separating alternatives.
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let first = self.parse_pattern_alternative()?;
+        if !self.check(TokenKind::Pipe) {
+            return Ok(first);
+        }
+        let start = first.span();
+        let mut alternatives = vec![first];
+        while self.match_token(&[TokenKind::Pipe]) {
+            alternatives.push(self.parse_pattern_alternative()?);
+        }
+        Ok(Pattern::Or {
+            alternatives,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_pattern_alternative(&mut self) -> Result<Pattern> {
+        let start = self.current_span();
+        match self.current.as_ref().map(|token| token.kind) {
+            Some(TokenKind::Number) | Some(TokenKind::Minus) => {
+                let negative = self.match_token(&[TokenKind::Minus]);
+                let token = self.expect_token(TokenKind::Number)?;
+                let ASTNode::Literal { value, .. } = self.parse_number_literal(token)? else {
+                    unreachable!("number literals parse to literals");
+                };
+                let value = match value {
+                    Literal::Integer(n) if negative => Literal::Integer(-n),
+                    Literal::Float(n) if negative => Literal::Float(-n),
+                    Literal::TypedInteger(n, ty) if negative => Literal::TypedInteger(-n, ty),
+                    Literal::TypedFloat(n, ty) if negative => Literal::TypedFloat(-n, ty),
+                    value => value,
+                };
+                Ok(Pattern::Literal {
+                    value,
+                    span: self.span_from(start),
+                })
+            }
+            Some(TokenKind::String)
+            | Some(TokenKind::Char)
+            | Some(TokenKind::True)
+            | Some(TokenKind::False) => {
+                let ASTNode::Literal { value, span, .. } = self.parse_primary()? else {
+                    unreachable!("literal tokens parse to literals");
+                };
+                Ok(Pattern::Literal { value, span })
+            }
+            Some(TokenKind::LeftParen) => {
+                self.advance();
+                let mut elements = Vec::new();
+                let mut trailing_comma = false;
+                while !self.check(TokenKind::RightParen) && !self.is_at_end() {
+                    elements.push(self.parse_pattern()?);
+                    trailing_comma = self.match_token(&[TokenKind::Comma]);
+                    if !trailing_comma {
+                        break;
+                    }
+                }
+                self.expect_token(TokenKind::RightParen)?;
+                // `(p)` is just `p`; a tuple of one is written `(p,)`.
+                if elements.len() == 1 && !trailing_comma {
+                    return Ok(elements.remove(0));
+                }
+                Ok(Pattern::Tuple {
+                    elements,
+                    span: self.span_from(start),
+                })
+            }
+            Some(TokenKind::LeftBracket) => {
+                self.advance();
+                let mut elements = Vec::new();
+                let mut rest = None;
+                while !self.check(TokenKind::RightBracket) && !self.is_at_end() {
+                    if self.check(TokenKind::DotDot) {
+                        let dots = self.advance().expect("checked token is present").span;
+                        if rest.is_some() {
+                            return Err(IoError::parser_error(
+                                "`..` can only be used once per array pattern",
+                            )
+                            .with_span(dots));
+                        }
+                        rest = Some(elements.len());
+                    } else {
+                        elements.push(self.parse_pattern()?);
+                    }
+                    if !self.match_token(&[TokenKind::Comma]) {
+                        break;
+                    }
+                }
+                self.expect_token(TokenKind::RightBracket)?;
+                Ok(Pattern::Array {
+                    elements,
+                    rest,
+                    span: self.span_from(start),
+                })
+            }
+            Some(TokenKind::Identifier) => {
+                let token = self.advance().expect("identifier token is present");
+                let mut path = token.lexeme;
+                while self.match_token(&[TokenKind::ColonColon]) {
+                    path.push_str("::");
+                    path.push_str(&self.expect_token(TokenKind::Identifier)?.lexeme);
+                }
+                let fields = if self.match_token(&[TokenKind::LeftParen]) {
+                    let mut patterns = Vec::new();
+                    while !self.check(TokenKind::RightParen) && !self.is_at_end() {
+                        patterns.push(self.parse_pattern()?);
+                        if !self.match_token(&[TokenKind::Comma]) {
+                            break;
+                        }
+                    }
+                    self.expect_token(TokenKind::RightParen)?;
+                    PatternFields::Tuple(patterns)
+                } else if self.check(TokenKind::LeftBrace) {
+                    self.parse_field_patterns()?
+                } else if path == "_" {
+                    return Ok(Pattern::Wildcard { span: token.span });
+                } else if !path.contains("::") {
+                    return Ok(Pattern::Binding {
+                        name: path,
+                        id: self.next_id(),
+                        span: token.span,
+                    });
+                } else {
+                    PatternFields::Unit
+                };
+                Ok(Pattern::Struct {
+                    path,
+                    fields,
+                    span: self.span_from(start),
+                })
+            }
+            _ => Err(self.error_at_current(format!(
+                "Expected a pattern, found '{}'",
+                self.current
+                    .as_ref()
+                    .map(|t| t.lexeme.as_str())
+                    .unwrap_or("")
+            ))),
+        }
+    }
+
+    /// `{ w, h: pattern, .. }`; a bare field name binds the field to that name.
+    fn parse_field_patterns(&mut self) -> Result<PatternFields> {
+        self.expect_token(TokenKind::LeftBrace)?;
+        let mut fields = Vec::new();
+        let mut rest = false;
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            if self.match_token(&[TokenKind::DotDot]) {
+                rest = true;
+                break;
+            }
+            let name_token = self.expect_token(TokenKind::Identifier)?;
+            let pattern = if self.match_token(&[TokenKind::Colon]) {
+                self.parse_pattern()?
+            } else {
+                Pattern::Binding {
+                    name: name_token.lexeme.clone(),
+                    id: self.next_id(),
+                    span: name_token.span,
+                }
+            };
+            fields.push((name_token.lexeme, pattern));
+            if !self.match_token(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+        self.expect_token(TokenKind::RightBrace)?;
+        Ok(PatternFields::Named { fields, rest })
+    }
+
+    /// `move |a, b: T| -> R { ... }`. Parameter types may be left out for the
+    /// type checker to infer; a return type needs a block body.
+    fn parse_closure(&mut self) -> Result<ASTNode> {
+        let start = self.current_span();
+        let is_move = self.match_token(&[TokenKind::Move]);
+
+        let mut params = Vec::new();
+        if !self.match_token(&[TokenKind::Or]) {
+            self.expect_token(TokenKind::Pipe)?;
+            while !self.check(TokenKind::Pipe) {
+                let name_token = self.expect_token(TokenKind::Identifier)?;
+                let type_annotation = if self.match_token(&[TokenKind::Colon]) {
+                    self.parse_type_annotation()?
+                } else {
+                    Type::Unknown
+                };
+                params.push(Parameter {
+                    name: name_token.lexeme,
+                    type_annotation,
+                    id: self.next_id(),
+                    span: self.span_from(name_token.span),
+                });
+                if !self.match_token(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+            self.expect_token(TokenKind::Pipe)?;
+        }
+
+        let return_type = if self.match_token(&[TokenKind::Arrow]) {
+            Some(self.parse_type_annotation()?)
+        } else {
+            None
+        };
+        let body = if return_type.is_some() || self.check(TokenKind::LeftBrace) {
+            let body_start = self.current_span();
+            let statements = self.parse_block()?;
+            ASTNode::Block {
+                statements,
+                id: self.next_id(),
+                span: self.span_from(body_start),
+            }
+        } else {
+            self.parse_expression()?
+        };
+
+        Ok(ASTNode::Closure {
+            params,
+            return_type,
+            body: Box::new(body),
+            is_move,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_array_literal(&mut self) -> Result<ASTNode> {
+        let start = self.expect_token(TokenKind::LeftBracket)?.span;
+        let mut elements = Vec::new();
+
+        if !self.check(TokenKind::RightBracket) {
+            loop {
+                elements.push(self.parse_nested_expression()?);
+                if !self.match_token(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.expect_token(TokenKind::RightBracket)?;
+
+        Ok(ASTNode::ArrayLiteral {
+            elements,
+            id: self.next_id(),
+            span: self.span_from(start),
+        })
+    }
+}
+
+/// The binary operator a token stands for; see `BinaryOperator::precedence`.
+fn binary_operator(kind: TokenKind) -> Option<(u8, BinaryOperator)> {
+    let op = match kind {
+        TokenKind::DotDot => BinaryOperator::Range,
+        TokenKind::DotDotEqual => BinaryOperator::RangeInclusive,
+        TokenKind::Or => BinaryOperator::Or,
+        TokenKind::And => BinaryOperator::And,
+        TokenKind::EqualEqual => BinaryOperator::Equal,
+        TokenKind::BangEqual => BinaryOperator::NotEqual,
+        TokenKind::Less => BinaryOperator::LessThan,
+        TokenKind::LessEqual => BinaryOperator::LessThanEqual,
+        TokenKind::Greater => BinaryOperator::GreaterThan,
+        TokenKind::GreaterEqual => BinaryOperator::GreaterThanEqual,
+        TokenKind::Pipe => BinaryOperator::BitwiseOr,
+        TokenKind::Caret => BinaryOperator::BitwiseXor,
+        TokenKind::Ampersand => BinaryOperator::BitwiseAnd,
+        TokenKind::LessLess => BinaryOperator::LeftShift,
+        TokenKind::GreaterGreater => BinaryOperator::RightShift,
+        TokenKind::Plus => BinaryOperator::Add,
+        TokenKind::Minus => BinaryOperator::Subtract,
+        TokenKind::Star => BinaryOperator::Multiply,
+        TokenKind::Slash => BinaryOperator::Divide,
+        TokenKind::Percent => BinaryOperator::Modulo,
+        _ => return None,
+    };
+    Some((op.precedence(), op))
+}
+
+/// The operator applied by a compound assignment token such as `+=`.
+fn compound_operator(kind: TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::PlusEqual => Some(BinaryOperator::Add),
+        TokenKind::MinusEqual => Some(BinaryOperator::Subtract),
+        TokenKind::StarEqual => Some(BinaryOperator::Multiply),
+        TokenKind::SlashEqual => Some(BinaryOperator::Divide),
+        TokenKind::PercentEqual => Some(BinaryOperator::Modulo),
+        _ => None,
+    }
+}
+
+fn is_range(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::BinaryOp { op, .. } if op.is_range())
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parser_for(source: &str) -> Parser<std::vec::IntoIter<Token>> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        Parser::new(tokens.into_iter())
+    }
+
     #[test]
     fn test_parse_function() {
-        let tokens = vec![
-            Token::new(TokenKind::Function, "fn"),
-            Token::new(TokenKind::Identifier, "test"),
-            Token::new(TokenKind::LeftParen, "("),
-            Token::new(TokenKind::RightParen, ")"),
-            Token::new(TokenKind::LeftBrace, "{"),
-            Token::new(TokenKind::RightBrace, "}"),
-        ];
-
-        let mut parser = Parser::new(tokens.into_iter());
+        let mut parser = parser_for("fn test() {}");
         let result = parser.parse_function();
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_parse_if_statement() {
-        let tokens = vec![
-            Token::new(TokenKind::If, "if"),
-            Token::new(TokenKind::True, "true"),
-            Token::new(TokenKind::LeftBrace, "{"),
-            Token::new(TokenKind::RightBrace, "}"),
-        ];
-
-        let mut parser = Parser::new(tokens.into_iter());
+        let mut parser = parser_for("if true {}");
         let result = parser.parse_if_statement();
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_doc_comments_and_literals() {
+        let source = "/// The answer.\nlet x = 0xff_ff;\nlet c = '\\n';";
+        let program = parse_source(source, FileId(0)).unwrap();
+        let items = match program {
+            ASTNode::Program(items) => items,
+            other => panic!("expected program, got {:?}", other),
+        };
+        assert!(matches!(
+            &items[0],
+            ASTNode::Let { value: Some(value), .. }
+                if matches!(**value, ASTNode::Literal { value: Literal::Integer(65535), .. })
+        ));
+        assert!(matches!(
+            &items[1],
+            ASTNode::Let { value: Some(value), .. }
+                if matches!(**value, ASTNode::Literal { value: Literal::Char('\n'), .. })
+        ));
+    }
+
+    fn parse_expr(source: &str) -> ASTNode {
+        parser_for(source).parse_expression().unwrap()
+    }
+
+    /// Renders an expression with explicit parentheses to make grouping visible.
+    fn grouped(node: &ASTNode) -> String {
+        match node {
+            ASTNode::BinaryOp {
+                op, left, right, ..
+            } => format!("({} {} {})", grouped(left), op, grouped(right)),
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Try,
+                operand,
+                ..
+            } => format!("{}?", grouped(operand)),
+            ASTNode::UnaryOp { op, operand, .. } => format!("({}{})", op, grouped(operand)),
+            ASTNode::Identifier { name, .. } => name.clone(),
+            ASTNode::Call { callee, args, .. } => {
+                let args: Vec<String> = args.iter().map(grouped).collect();
+                format!("{}({})", grouped(callee), args.join(", "))
+            }
+            ASTNode::Literal { value, .. } => value.to_string(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let cases = [
+            ("a + b * c % d", "(a + ((b * c) % d))"),
+            ("a || b && c == d", "(a || (b && (c == d)))"),
+            ("a & b | c ^ d", "((a & b) | (c ^ d))"),
+            ("1 << 2 + 3 < x", "((1 << (2 + 3)) < x)"),
+            ("a - b - c", "((a - b) - c)"),
+            ("0..n + 1", "(0 .. (n + 1))"),
+            ("-a? * b", "((-a?) * b)"),
+            ("math::max", "math::max"),
+            ("f(x)(y)", "f(x)(y)"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(grouped(&parse_expr(source)), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_ranges_do_not_chain() {
+        assert!(parser_for("a..b..c").parse_expression().is_err());
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        match parse_expr("total += x * 2") {
+            ASTNode::CompoundAssignment { target, op, .. } => {
+                assert_eq!(target.place_root(), Some("total"));
+                assert_eq!(op, BinaryOperator::Add);
+            }
+            other => panic!("expected compound assignment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_async_function() {
+        let program = parse_source("async fn f() { return await g(); }", FileId(0)).unwrap();
+        match program {
+            ASTNode::Program(items) => {
+                assert!(matches!(items[0], ASTNode::Function { is_async: true, .. }))
+            }
+            other => panic!("expected program, got {:?}", other),
+        }
+    }
+
+    fn recover(source: &str) -> (Vec<ASTNode>, Vec<Diagnostic>) {
+        match parse_source_with_recovery(source, FileId(0)) {
+            (ASTNode::Program(items), diagnostics) => (items, diagnostics),
+            (other, _) => panic!("expected program, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recovery_reports_every_error() {
+        let (items, diagnostics) = recover("let a = ;\nlet b = 2;\nlet c = / 3;\nlet d = 4;");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(items.len(), 4);
+        assert!(matches!(items[0], ASTNode::Error { .. }));
+        assert!(matches!(items[1], ASTNode::Let { .. }));
+        assert!(matches!(items[2], ASTNode::Error { .. }));
+        assert!(matches!(items[3], ASTNode::Let { .. }));
+    }
+
+    #[test]
+    fn test_recovery_inside_function_bodies() {
+        let source = "fn main() {\n    let x = ;\n    print(x);\n}\nfn other() { return 1 + ; }";
+        let (items, diagnostics) = recover(source);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].span, Some(Span::new(FileId(0), 24, 25)));
+        match &items[..] {
+            [ASTNode::Function { body, .. }, ASTNode::Function { name, .. }] => {
+                assert!(matches!(body[0], ASTNode::Error { .. }));
+                assert!(matches!(body[1], ASTNode::Call { .. }));
+                assert_eq!(name, "other");
+            }
+            other => panic!("expected two functions, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lexer_errors_do_not_cascade() {
+        let (items, diagnostics) = recover("let a = @;\nlet s = \"\\q\";");
+        assert_eq!(diagnostics.len(), 2);
+        assert!(items.iter().all(|item| matches!(item, ASTNode::Let { .. })));
+    }
+
+    #[test]
+    fn test_recovery_skips_stray_tokens() {
+        let (items, diagnostics) = recover("}\nlet a = 1;");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(items.last(), Some(ASTNode::Let { .. })));
+    }
+
+    #[test]
+    fn test_nodes_carry_spans() {
+        let source = "fn add(a: int, b: int) -> int {\n    return a + b;\n}";
+        let program = parse_source(source, FileId(3)).unwrap();
+        let function = match program {
+            ASTNode::Program(items) => items.into_iter().next().unwrap(),
+            other => panic!("expected program, got {:?}", other),
+        };
+        assert_eq!(function.span(), Span::new(FileId(3), 0, source.len()));
+
+        let body = match function {
+            ASTNode::Function { body, .. } => body,
+            other => panic!("expected function, got {:?}", other),
+        };
+        let ret = &body[0];
+        assert_eq!(&source[ret.span().start..ret.span().end], "return a + b;");
+        match ret {
+            ASTNode::Return {
+                value: Some(value), ..
+            } => assert_eq!(&source[value.span().start..value.span().end], "a + b"),
+            other => panic!("expected return, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_node_ids_are_unique() {
+        fn collect(node: &ASTNode, ids: &mut Vec<NodeId>) {
+            if !matches!(node, ASTNode::Program(_)) {
+                ids.push(node.id());
+            }
+            match node {
+                ASTNode::Program(items) | ASTNode::Function { body: items, .. } => {
+                    items.iter().for_each(|item| collect(item, ids))
+                }
+                ASTNode::Return {
+                    value: Some(value), ..
+                } => collect(value, ids),
+                ASTNode::BinaryOp { left, right, .. } => {
+                    collect(left, ids);
+                    collect(right, ids);
+                }
+                _ => {}
+            }
+        }
+
+        let program = parse_source("fn f(a: int) -> int { return a + 1; }", FileId(0)).unwrap();
+        let mut ids = Vec::new();
+        collect(&program, &mut ids);
+        assert_eq!(ids.len(), 5);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+    }
+
+    #[test]
+    fn test_type_annotations_are_typed() {
+        let program = parse_source("fn f(xs: [int], p: Point) -> float {}", FileId(0)).unwrap();
+        let ASTNode::Program(items) = program else {
+            panic!("expected program");
+        };
+        match &items[0] {
+            ASTNode::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                assert_eq!(
+                    params[0].type_annotation,
+                    Type::Array {
+                        elem_type: Box::new(Type::I32),
+                        size: 0
+                    }
+                );
+                assert_eq!(params[1].type_annotation, Type::Named("Point".into()));
+                assert_eq!(return_type, &Some(Type::F64));
+            }
+            other => panic!("expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_let_without_value() {
+        let program = parse_source("let x: int;\nx = 1;", FileId(0)).unwrap();
+        let ASTNode::Program(items) = program else {
+            panic!("expected program");
+        };
+        assert!(matches!(
+            &items[0],
+            ASTNode::Let { name, type_annotation: Some(Type::I32), value: None, .. } if name == "x"
+        ));
+        assert!(parse_source("let x: int", FileId(0)).is_err());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let source = "#[allow(unused_variables, shadowing)]\nfn f(x: int) {}";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let attributes = items[0].attributes();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name, "allow");
+        let lints: Vec<&str> = attributes[0].words().collect();
+        assert_eq!(lints, ["unused_variables", "shadowing"]);
+        assert_eq!(items[0].span().start, 0);
+
+        let source = "#[deprecated(note = \"use `Vec2`\")]\n\
+                      struct Point { #[cfg(not(target_os = \"windows\"))] x: int }";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::StructDef { fields, .. } = &items[0] else {
+            panic!("expected struct, got {:?}", items[0]);
+        };
+        let deprecated = items[0].attribute("deprecated").unwrap();
+        assert_eq!(deprecated.value("note"), Some("use `Vec2`"));
+        assert_eq!(
+            fields[0].attributes[0].args,
+            [AttributeArg::List(
+                "not".to_string(),
+                vec![AttributeArg::Value(
+                    "target_os".to_string(),
+                    "windows".to_string()
+                )]
+            )]
+        );
+
+        let error = |source| {
+            parse_source(source, FileId(0))
+                .unwrap_err()
+                .message()
+                .to_string()
+        };
+        assert_eq!(
+            error("#[inlined]\nfn f() {}"),
+            "Unknown attribute `inlined`"
+        );
+        assert_eq!(
+            error("#[inline(sometimes)]\nfn f() {}"),
+            "Malformed `#[inline(sometimes)]`"
+        );
+        assert_eq!(
+            error("#[test]\nstruct S {}"),
+            "`#[test]` cannot be applied to a struct"
+        );
+        assert_eq!(
+            error("#[allow(complexity)]\nlet x = 1;"),
+            "Attributes can only be applied to functions, structs, fields and imports"
+        );
+    }
+
+    #[test]
+    fn test_module_attributes() {
+        let source = "#![cfg(test)]\n#![allow(deprecated)]\n#[test]\nfn f() {}";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::ModuleAttributes {
+            attributes, span, ..
+        } = &items[0]
+        else {
+            panic!("expected module attributes, got {:?}", items[0]);
+        };
+        let names: Vec<&str> = attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["cfg", "allow"]);
+        assert_eq!(
+            &source[span.start..span.end],
+            "#![cfg(test)]\n#![allow(deprecated)]"
+        );
+        assert_eq!(items[1].attributes()[0].name, "test");
+
+        let error = |source| {
+            parse_source(source, FileId(0))
+                .unwrap_err()
+                .message()
+                .to_string()
+        };
+        assert_eq!(
+            error("#![test]\nfn f() {}"),
+            "`#[test]` cannot be applied to a module"
+        );
+        assert_eq!(
+            error("fn f() {}\n#![deprecated]"),
+            "Module attributes `#![...]` must come before everything else in the file"
+        );
+    }
+
+    #[test]
+    fn test_generic_functions_and_structs() {
+        let source = "struct Pair<A, B> { first: A, second: B, }\n\
+                      fn map<T, U>(xs: [T], f: fn(T) -> U) -> [U] {}\n\
+                      fn nest(p: Pair<Pair<int, T>, bool>) {}";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+
+        let ASTNode::StructDef {
+            type_params,
+            fields,
+            ..
+        } = &items[0]
+        else {
+            panic!("expected struct, got {:?}", items[0]);
+        };
+        assert_eq!(type_params, &["A", "B"]);
+        assert_eq!(fields[1].type_annotation, Type::Param("B".into()));
+
+        let ASTNode::Function {
+            type_params,
+            params,
+            return_type,
+            ..
+        } = &items[1]
+        else {
+            panic!("expected function, got {:?}", items[1]);
+        };
+        assert_eq!(type_params, &["T", "U"]);
+        assert_eq!(params[1].type_annotation.to_string(), "fn(T) -> U");
+        assert!(params[1].type_annotation.has_params());
+        assert_eq!(return_type.as_ref().unwrap().to_string(), "[U]");
+
+        // `>>` closes two argument lists; `T` is not in scope outside `map`.
+        let ASTNode::Function { params, .. } = &items[2] else {
+            panic!("expected function, got {:?}", items[2]);
+        };
+        assert_eq!(
+            params[0].type_annotation.to_string(),
+            "Pair<Pair<i32, T>, bool>"
+        );
+        assert!(!params[0].type_annotation.has_params());
+    }
+
+    #[test]
+    fn test_enums_and_match() {
+        let source = "enum Shape<T> { Circle(T), Rect { w: T, h: T }, Empty }\n\
+                      let n = match s { Shape::Circle(r) if r > 0 => r, Rect { w, h: 0, .. } => w,\n\
+                      [first, .., -1] | Empty => { 0 } _ => Shape::Rect { w: 1, h: 2 } };";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+
+        let ASTNode::EnumDef { variants, .. } = &items[0] else {
+            panic!("expected enum, got {:?}", items[0]);
+        };
+        let names: Vec<&str> = variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["Circle", "Rect", "Empty"]);
+        assert!(
+            matches!(&variants[0].fields, VariantFields::Tuple(types) if types == &[Type::Param("T".into())])
+        );
+
+        let ASTNode::Let {
+            value: Some(value), ..
+        } = &items[1]
+        else {
+            panic!("expected let, got {:?}", items[1]);
+        };
+        let ASTNode::Match { arms, .. } = value.as_ref() else {
+            panic!("expected match, got {:?}", value);
+        };
+        assert_eq!(arms.len(), 4);
+        assert!(arms[0].guard.is_some());
+        assert_eq!(
+            arms[1]
+                .pattern
+                .bindings()
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+            ["w"]
+        );
+        let Pattern::Or { alternatives, .. } = &arms[2].pattern else {
+            panic!("expected or-pattern, got {:?}", arms[2].pattern);
+        };
+        assert!(matches!(
+            alternatives[0],
+            Pattern::Array { rest: Some(1), .. }
+        ));
+        assert!(matches!(arms[2].body, ASTNode::Block { .. }));
+        assert!(matches!(arms[3].body, ASTNode::StructLiteral { .. }));
+
+        // A struct literal can't follow a match head, whose block comes next.
+        assert!(parse_source("match Shape::Empty { _ => 1 }", FileId(0)).is_ok());
+    }
+
+    #[test]
+    fn test_traits_and_impls() {
+        let source =
+            "trait Shape { fn area(self) -> float; fn name(self) -> string { \"shape\" } }\n\
+                      impl Shape for Circle { fn area(self) -> float { self.r * self.r } }\n\
+                      fn total<T: Shape + Named>(s: T, all: [dyn Shape]) -> float { s.area() }";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+
+        let ASTNode::TraitDef { methods, .. } = &items[0] else {
+            panic!("expected trait, got {:?}", items[0]);
+        };
+        assert!(methods[0].body.is_none());
+        assert!(methods[1].body.is_some());
+        assert_eq!(
+            methods[0].params[0].type_annotation,
+            Type::Param("Self".into())
+        );
+
+        let ASTNode::Impl {
+            trait_name,
+            self_type,
+            methods,
+            ..
+        } = &items[1]
+        else {
+            panic!("expected impl, got {:?}", items[1]);
+        };
+        assert_eq!(trait_name.as_deref(), Some("Shape"));
+        assert_eq!(self_type, &Type::Named("Circle".into()));
+        assert!(matches!(methods[0], ASTNode::Function { .. }));
+
+        let ASTNode::Function {
+            bounds,
+            params,
+            body,
+            ..
+        } = &items[2]
+        else {
+            panic!("expected function, got {:?}", items[2]);
+        };
+        assert_eq!(
+            bounds,
+            &[
+                ("T".to_string(), "Shape".to_string()),
+                ("T".into(), "Named".into())
+            ]
+        );
+        assert_eq!(params[1].type_annotation.to_string(), "[dyn Shape]");
+        assert!(matches!(
+            &body[0],
+            ASTNode::Call { callee, .. } if matches!(callee.as_ref(), ASTNode::MemberAccess { .. })
+        ));
+
+        // `self` needs a type outside of traits and impls.
+        assert!(parse_source("fn f(self) {}", FileId(0)).is_err());
+        assert!(parse_source("struct S<T: Shape> { x: T }", FileId(0)).is_err());
+    }
+
+    #[test]
+    fn test_references() {
+        let source = "fn f(a: &[int], b: &mut Point) { let c = &mut *b; g(&a, *c); }";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::Function { params, body, .. } = &items[0] else {
+            panic!("expected function, got {:?}", items[0]);
+        };
+        assert_eq!(params[0].type_annotation.to_string(), "&[i32]");
+        assert_eq!(
+            params[1].type_annotation,
+            Type::Ref {
+                mutable: true,
+                inner: Box::new(Type::Named("Point".into()))
+            }
+        );
+
+        let ASTNode::Let {
+            value: Some(value), ..
+        } = &body[0]
+        else {
+            panic!("expected let, got {:?}", body[0]);
+        };
+        let ASTNode::UnaryOp {
+            op: UnaryOperator::RefMut,
+            operand,
+            ..
+        } = value.as_ref()
+        else {
+            panic!("expected a mutable borrow, got {:?}", value);
+        };
+        assert!(matches!(
+            operand.as_ref(),
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Deref,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_casts_and_literal_suffixes() {
+        let source = "let y = -x as u8 + 2u8; let z = 1.5f32;";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::Let {
+            value: Some(value), ..
+        } = &items[0]
+        else {
+            panic!("expected let, got {:?}", items[0]);
+        };
+        // `as` binds tighter than `+` and looser than `-`
+        let ASTNode::BinaryOp { left, right, .. } = value.as_ref() else {
+            panic!("expected addition, got {:?}", value);
+        };
+        let ASTNode::Cast { value, target, .. } = left.as_ref() else {
+            panic!("expected cast, got {:?}", left);
+        };
+        assert_eq!(*target, Type::U8);
+        assert!(matches!(
+            value.as_ref(),
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Negate,
+                ..
+            }
+        ));
+        assert!(matches!(
+            right.as_ref(),
+            ASTNode::Literal {
+                value: Literal::TypedInteger(2, Type::U8),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &items[1],
+            ASTNode::Let { value: Some(value), .. } if matches!(
+                value.as_ref(),
+                ASTNode::Literal { value: Literal::TypedFloat(_, Type::F32), .. }
+            )
+        ));
+    }
+
+    #[test]
+    fn test_pub_items_and_imports() {
+        let source = "import net::http::{get, post}; pub fn main() {} enum E { A }";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        assert!(matches!(
+            &items[0],
+            ASTNode::Import { path, items, .. } if path == &["net", "http"] && items == &["get", "post"]
+        ));
+        assert!(
+            matches!(&items[1], ASTNode::Function { is_pub: true, span, .. } if span.start == 31)
+        );
+        assert!(matches!(&items[2], ASTNode::EnumDef { is_pub: false, .. }));
+
+        let err = parse_source("pub let x = 1;", FileId(0)).unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Expected a function, struct, enum, trait or constant after pub"
+        );
+    }
+
+    #[test]
+    fn test_const_items_and_sized_arrays() {
+        let source = "pub const N: i32 = 4; const fn f(xs: [i8; N]) -> [f64; 2] {}";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        assert!(matches!(
+            &items[0],
+            ASTNode::Global { name, type_annotation: Some(Type::I32), is_pub: true, .. }
+                if name == "N"
+        ));
+        let ASTNode::Function {
+            params,
+            return_type,
+            is_const: true,
+            ..
+        } = &items[1]
+        else {
+            panic!("expected const fn, got {:?}", items[1]);
+        };
+        assert_eq!(params[0].type_annotation.to_string(), "[i8; N]");
+        assert_eq!(
+            return_type,
+            &Some(Type::Array {
+                elem_type: Box::new(Type::F64),
+                size: 2,
+            })
+        );
+
+        let err = parse_source("let xs: [i32; 0] = [];", FileId(0)).unwrap_err();
+        assert_eq!(err.message(), "Array length must be a positive integer");
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = "let f = |x, y: i32| x + y; let g = move || -> i32 { n }; let h = || {};";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let closures: Vec<&ASTNode> = items
+            .iter()
+            .map(|item| match item {
+                ASTNode::Let {
+                    value: Some(value), ..
+                } => value.as_ref(),
+                other => panic!("expected let, got {:?}", other),
+            })
+            .collect();
+        let ASTNode::Closure {
+            params,
+            body,
+            is_move: false,
+            ..
+        } = closures[0]
+        else {
+            panic!("expected closure, got {:?}", closures[0]);
+        };
+        assert_eq!(params[0].type_annotation, Type::Unknown);
+        assert_eq!(params[1].type_annotation, Type::I32);
+        assert!(matches!(body.as_ref(), ASTNode::BinaryOp { .. }));
+        assert!(matches!(
+            closures[1],
+            ASTNode::Closure { params, return_type: Some(Type::I32), is_move: true, .. }
+                if params.is_empty()
+        ));
+        assert!(matches!(
+            closures[2],
+            ASTNode::Closure { body, .. } if matches!(body.as_ref(), ASTNode::Block { .. })
+        ));
+    }
+
+    #[test]
+    fn test_generic_traits_and_for_patterns() {
+        let source = "trait Iterator<T> { fn next(self) -> Option<T>; }\n\
+                      impl Iterator<int> for Counter { fn next(self) -> Option<int> { none() } }\n\
+                      fn f() { for Pair { first, second: _ } in zip(a, 0..=9) { } }";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::TraitDef {
+            type_params,
+            methods,
+            ..
+        } = &items[0]
+        else {
+            panic!("expected trait, got {:?}", items[0]);
+        };
+        assert_eq!(type_params, &["T"]);
+        assert_eq!(
+            methods[0].return_type.as_ref().map(Type::to_string),
+            Some("Option<T>".to_string())
+        );
+        assert!(
+            matches!(&items[1], ASTNode::Impl { trait_args, .. } if trait_args == &[Type::I32])
+        );
+
+        let ASTNode::Function { body, .. } = &items[2] else {
+            panic!("expected function, got {:?}", items[2]);
+        };
+        let ASTNode::For {
+            pattern, iterable, ..
+        } = &body[0]
+        else {
+            panic!("expected for loop, got {:?}", body[0]);
+        };
+        let names: Vec<&str> = pattern.bindings().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["first"]);
+        assert!(matches!(iterable.as_ref(), ASTNode::Call { args, .. }
+            if matches!(&args[1], ASTNode::BinaryOp { op: BinaryOperator::RangeInclusive, .. })));
+    }
+
+    #[test]
+    fn test_tuples_places_let_patterns_and_inherent_impls() {
+        let source = "let (a, (b,), _) = (1, (2,), t.0.1);\n\
+                      grid[i].x += 1;\n\
+                      let p = Point { x, y: 2 };\n\
+                      let t: (int, bool) = f();\n\
+                      impl Point { fn norm(self) -> int { self.x * self.x } }";
+        let ASTNode::Program(items) = parse_source(source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::LetPattern { pattern, value, .. } = &items[0] else {
+            panic!("expected let pattern, got {:?}", items[0]);
+        };
+        let names: Vec<&str> = pattern.bindings().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["a", "b"]);
+        let ASTNode::TupleLiteral { elements, .. } = value.as_ref() else {
+            panic!("expected tuple, got {:?}", value);
+        };
+        assert!(
+            matches!(&elements[1], ASTNode::TupleLiteral { elements, .. } if elements.len() == 1)
+        );
+        let ASTNode::MemberAccess { object, member, .. } = &elements[2] else {
+            panic!("expected member access, got {:?}", elements[2]);
+        };
+        assert_eq!(member, "1");
+        assert!(matches!(object.as_ref(), ASTNode::MemberAccess { member, .. } if member == "0"));
+
+        let ASTNode::CompoundAssignment { target, .. } = &items[1] else {
+            panic!("expected compound assignment, got {:?}", items[1]);
+        };
+        assert_eq!(target.place_root(), Some("grid"));
+        assert!(matches!(target.as_ref(), ASTNode::MemberAccess { member, .. } if member == "x"));
+
+        let ASTNode::Let {
+            value: Some(value), ..
+        } = &items[2]
+        else {
+            panic!("expected let, got {:?}", items[2]);
+        };
+        let ASTNode::StructLiteral { name, fields, .. } = value.as_ref() else {
+            panic!("expected struct literal, got {:?}", value);
+        };
+        assert_eq!(name, "Point");
+        assert_eq!(fields[0].0, "x");
+        assert!(matches!(&fields[0].1, ASTNode::Identifier { name, .. } if name == "x"));
+
+        assert!(
+            matches!(&items[3], ASTNode::Let { type_annotation: Some(Type::Tuple(elements)), .. }
+            if elements == &[Type::I32, Type::Bool])
+        );
+        assert!(
+            matches!(&items[4], ASTNode::Impl { trait_name: None, self_type, .. }
+            if self_type == &Type::Named("Point".into()))
+        );
+        assert!(parse_source("f() = 1;", FileId(0)).is_err());
+    }
+
+    #[test]
+    fn test_error_points_at_offending_token() {
+        let err = parse_source("let x = ;", FileId(0)).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(FileId(0), 8, 9)));
+    }
 }
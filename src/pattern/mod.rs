@@ -1,199 +1,669 @@
-use crate::{
-    ast::{Pattern, ASTNode},
-    types::Type,
-    error::IoError,
-    symbol_table::{Scope, Symbol},
-    Result,
-};
-
-#[derive(Debug)]
-pub struct PatternMatcher {
-    scope: Box<Scope>,
-}
-
-impl PatternMatcher {
-    pub fn new(parent_scope: Box<Scope>) -> Self {
-        Self {
-            scope: Box::new(Scope::with_parent(parent_scope)),
+//! Exhaustiveness and reachability of `match` arms, decided by the usefulness
+//! algorithm: a pattern is useful after some rows if it matches a value none of
+//! them match. The match is exhaustive when `_` is useless after every
+//! unguarded arm, and an arm is unreachable when it is useless after the
+//! unguarded arms before it. The values that make `_` useful become the
+//! "missing pattern" examples in the error. A `let` pattern is checked as a
+//! `match` of one arm, which must be exhaustive.
+//!
+//! Patterns are expected to have been type-checked; parts that don't fit their
+//! type are treated as wildcards.
+
+use crate::ast::{Literal, MatchArm, Pattern, PatternFields, Type};
+
+/// How a constructor's fields are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldStyle {
+    Unit,
+    Tuple,
+    Named,
+}
+
+/// One way of building a value of an enum or struct type: a variant, or the
+/// struct itself. Tuple fields are named `0`, `1`, ...
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constructor {
+    pub name: String,
+    pub style: FieldStyle,
+    pub fields: Vec<(String, Type)>,
+}
+
+/// The user-defined types patterns refer to.
+pub trait TypeDefinitions {
+    /// The constructors of `ty`, with field types instantiated, if it is an enum
+    /// or a struct.
+    fn constructors(&self, ty: &Type) -> Option<Vec<Constructor>>;
+}
+
+/// The outcome of checking one `match`.
+#[derive(Debug, Default, PartialEq)]
+pub struct MatchReport {
+    /// Examples of values no arm matches, as patterns.
+    pub missing: Vec<String>,
+    /// Indices of arms that can never be reached.
+    pub unreachable: Vec<usize>,
+}
+
+impl MatchReport {
+    /// "missing pattern `Rect { .. }`", listing at most three examples.
+    pub fn missing_message(&self) -> String {
+        const SHOWN: usize = 3;
+        let quoted: Vec<String> = self
+            .missing
+            .iter()
+            .take(SHOWN)
+            .map(|pattern| format!("`{}`", pattern))
+            .collect();
+        match self.missing.len() {
+            0 => String::new(),
+            1 => format!("missing pattern {}", quoted[0]),
+            n if n <= SHOWN => format!(
+                "missing patterns {} and {}",
+                quoted[..n - 1].join(", "),
+                quoted[n - 1]
+            ),
+            n => format!(
+                "missing patterns {} and {} more",
+                quoted.join(", "),
+                n - SHOWN
+            ),
         }
     }
+}
 
-    pub fn match_pattern(&mut self, pattern: &Pattern, value: &ASTNode, value_type: &Type) -> Result<()> {
-        match pattern {
-            Pattern::Literal(lit) => self.match_literal(lit, value),
-            Pattern::Variable(name) => self.bind_variable(name, value, value_type),
-            Pattern::Wildcard => Ok(()), // Always matches
-            Pattern::Constructor { name, fields } => self.match_constructor(name, fields, value),
+/// Checks the arms of a `match` on a value of type `ty`. Guarded arms may
+/// fail, so they never make later arms unreachable or the match exhaustive.
+pub fn check_match(arms: &[MatchArm], ty: &Type, definitions: &dyn TypeDefinitions) -> MatchReport {
+    let checker = Usefulness { definitions };
+    let tys = [ty.clone()];
+    let mut rows: Vec<Vec<Pat>> = Vec::new();
+    let mut report = MatchReport::default();
+
+    for (index, arm) in arms.iter().enumerate() {
+        let pat = checker.lower(&arm.pattern, ty);
+        if checker
+            .witnesses(&rows, std::slice::from_ref(&pat), &tys)
+            .is_empty()
+        {
+            report.unreachable.push(index);
+        }
+        if arm.guard.is_none() {
+            rows.push(vec![pat]);
         }
     }
 
-    fn match_literal(&self, lit: &ASTNode, value: &ASTNode) -> Result<()> {
-        match (lit, value) {
-            (ASTNode::IntegerLiteral(l), ASTNode::IntegerLiteral(r)) if l == r => Ok(()),
-            (ASTNode::StringLiteral(l), ASTNode::StringLiteral(r)) if l == r => Ok(()),
-            (ASTNode::BooleanLiteral(l), ASTNode::BooleanLiteral(r)) if l == r => Ok(()),
-            _ => Err(IoError::runtime_error("Pattern match failed")),
+    report.missing = checker
+        .witnesses(&rows, &[Pat::Wild], &tys)
+        .into_iter()
+        .map(|mut witness| witness.remove(0).to_string())
+        .collect();
+    report
+}
+
+/// Checks that the pattern of a `let` matches every value of type `ty`; the
+/// report lists the values it misses.
+pub fn check_irrefutable(
+    pattern: &Pattern,
+    ty: &Type,
+    definitions: &dyn TypeDefinitions,
+) -> MatchReport {
+    let checker = Usefulness { definitions };
+    let rows = vec![vec![checker.lower(pattern, ty)]];
+    MatchReport {
+        missing: checker
+            .witnesses(&rows, &[Pat::Wild], std::slice::from_ref(ty))
+            .into_iter()
+            .map(|mut witness| witness.remove(0).to_string())
+            .collect(),
+        unreachable: Vec::new(),
+    }
+}
+
+/// A pattern reduced to what matters for coverage.
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+    /// An array pattern; `suffix` is present when there is a `..` before it.
+    Array {
+        prefix: Vec<Pat>,
+        suffix: Option<Vec<Pat>>,
+    },
+    Or(Vec<Pat>),
+}
+
+/// The head of a value.
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    /// The constructor at this index of `TypeDefinitions::constructors`.
+    Variant(usize),
+    Bool(bool),
+    /// Any other literal, by its text.
+    Literal(String),
+    /// Arrays of exactly this length.
+    Length(usize),
+    /// Arrays of at least this length, all of whose elements lie in the
+    /// prefix and suffix of the array patterns being compared.
+    AtLeast(usize),
+    /// The only constructor of a tuple type.
+    Tuple,
+}
+
+/// An example value, as a pattern.
+#[derive(Debug, Clone)]
+enum Witness {
+    Wild,
+    Pattern(String),
+}
+
+impl std::fmt::Display for Witness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Witness::Wild => write!(f, "_"),
+            Witness::Pattern(text) => write!(f, "{}", text),
         }
     }
+}
 
-    fn bind_variable(&mut self, name: &str, value: &ASTNode, value_type: &Type) -> Result<()> {
-        self.scope.define(
-            name.to_string(),
-            Symbol::Variable {
-                name: name.to_string(),
-                type_name: value_type.to_string(),
-                mutable: false,
+struct Usefulness<'a> {
+    definitions: &'a dyn TypeDefinitions,
+}
+
+impl Usefulness<'_> {
+    fn lower(&self, pattern: &Pattern, ty: &Type) -> Pat {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => Pat::Wild,
+            Pattern::Literal {
+                value: Literal::Boolean(value),
+                ..
+            } => Pat::Ctor(Ctor::Bool(*value), Vec::new()),
+            Pattern::Literal { value, .. } => {
+                Pat::Ctor(Ctor::Literal(value.to_string()), Vec::new())
+            }
+            Pattern::Or { alternatives, .. } => Pat::Or(
+                alternatives
+                    .iter()
+                    .map(|alternative| self.lower(alternative, ty))
+                    .collect(),
+            ),
+            Pattern::Array { elements, rest, .. } => {
+                let elem_type = match ty {
+                    Type::Array { elem_type, .. } => elem_type.as_ref().clone(),
+                    _ => Type::Unknown,
+                };
+                let mut lowered = elements
+                    .iter()
+                    .map(|element| self.lower(element, &elem_type));
+                match rest {
+                    Some(at) => Pat::Array {
+                        prefix: lowered.by_ref().take(*at).collect(),
+                        suffix: Some(lowered.collect()),
+                    },
+                    None => Pat::Array {
+                        prefix: lowered.collect(),
+                        suffix: None,
+                    },
+                }
+            }
+            Pattern::Tuple { elements, .. } => match ty {
+                Type::Tuple(types) if types.len() == elements.len() => Pat::Ctor(
+                    Ctor::Tuple,
+                    elements
+                        .iter()
+                        .zip(types)
+                        .map(|(element, ty)| self.lower(element, ty))
+                        .collect(),
+                ),
+                _ => Pat::Wild,
             },
-        )
-    }
-
-    fn match_constructor(&mut self, name: &str, fields: &[Pattern], value: &ASTNode) -> Result<()> {
-        match value {
-            ASTNode::CallExpression { callee, arguments } => {
-                if let ASTNode::Identifier(callee_name) = &**callee {
-                    // Verify constructor exists
-                    let constructor_type = self.scope.lookup_type(callee_name)
-                        .ok_or_else(|| IoError::runtime_error(
-                            format!("Unknown constructor: {}", callee_name)
-                        ))?;
-
-                    // Verify arity matches
-                    if arguments.len() != fields.len() {
-                        return Err(IoError::runtime_error(format!(
-                            "Constructor {} expects {} arguments, but got {}",
-                            name,
-                            fields.len(),
-                            arguments.len()
-                        )));
-                    }
+            Pattern::Struct { path, fields, .. } => {
+                let name = path.rsplit("::").next().unwrap_or(path);
+                let constructors = self.definitions.constructors(ty).unwrap_or_default();
+                let Some(index) = constructors.iter().position(|ctor| ctor.name == name) else {
+                    return Pat::Wild;
+                };
+                let ctor = &constructors[index];
+                let sub_patterns = ctor
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (field, field_type))| {
+                        let pattern = match fields {
+                            PatternFields::Unit => None,
+                            PatternFields::Tuple(patterns) => patterns.get(i),
+                            PatternFields::Named { fields, .. } => fields
+                                .iter()
+                                .find(|(name, _)| name == field)
+                                .map(|(_, pattern)| pattern),
+                        };
+                        pattern.map_or(Pat::Wild, |pattern| self.lower(pattern, field_type))
+                    })
+                    .collect();
+                Pat::Ctor(Ctor::Variant(index), sub_patterns)
+            }
+        }
+    }
 
-                    // Get field types from constructor definition
-                    let field_types = self.get_constructor_field_types(constructor_type)?;
-
-                    // Match each field with corresponding argument
-                    for ((field, arg), field_type) in fields.iter().zip(arguments).zip(field_types) {
-                        // Infer argument type
-                        let arg_type = self.infer_type(arg)?;
-                        
-                        // Verify type compatibility
-                        if !self.types_compatible(&field_type, &arg_type) {
-                            return Err(IoError::type_error(format!(
-                                "Type mismatch in constructor {}: expected {}, found {}",
-                                name, field_type, arg_type
-                            )));
-                        }
+    /// Values matched by `v` but by no row, as one witness per example; each
+    /// witness has one pattern per column. Empty when `v` is useless.
+    fn witnesses(&self, rows: &[Vec<Pat>], v: &[Pat], tys: &[Type]) -> Vec<Vec<Witness>> {
+        let Some(head) = v.first() else {
+            return if rows.is_empty() {
+                vec![Vec::new()]
+            } else {
+                Vec::new()
+            };
+        };
+        let rows = expand_or_heads(rows);
 
-                        // Match the pattern
-                        self.match_pattern(field, arg, &field_type)?;
-                    }
+        if let Pat::Or(alternatives) = head {
+            return alternatives
+                .iter()
+                .flat_map(|alternative| {
+                    let mut v = v.to_vec();
+                    v[0] = alternative.clone();
+                    self.witnesses(&rows, &v, tys)
+                })
+                .collect();
+        }
 
-                    Ok(())
-                } else {
-                    Err(IoError::runtime_error("Expected constructor identifier"))
+        let heads: Vec<&Pat> = rows.iter().map(|row| &row[0]).chain([head]).collect();
+        let all = self.all_ctors(&tys[0], &heads);
+
+        let tried: Vec<Ctor> = match head {
+            Pat::Wild => match &all {
+                Some(all)
+                    if all
+                        .iter()
+                        .all(|ctor| rows.iter().any(|row| covers(&row[0], ctor))) =>
+                {
+                    all.clone()
                 }
+                _ => return self.default_witnesses(&rows, v, tys, all.as_deref()),
+            },
+            Pat::Ctor(ctor, _) => vec![ctor.clone()],
+            Pat::Array { .. } => all
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|ctor| covers(head, ctor))
+                .collect(),
+            Pat::Or(_) => unreachable!("or-patterns were handled above"),
+        };
+
+        let mut found = Vec::new();
+        for ctor in tried {
+            let fields = self.field_types(&tys[0], &ctor);
+            let arity = fields.len();
+            let specialized: Vec<Vec<Pat>> = rows
+                .iter()
+                .filter(|row| covers(&row[0], &ctor))
+                .map(|row| specialize(row, &ctor, arity))
+                .collect();
+            let v = specialize(v, &ctor, arity);
+            let column_tys: Vec<Type> =
+                fields.into_iter().chain(tys[1..].iter().cloned()).collect();
+            for mut witness in self.witnesses(&specialized, &v, &column_tys) {
+                let rest = witness.split_off(arity);
+                let mut wrapped = vec![self.apply(&ctor, witness, &tys[0])];
+                wrapped.extend(rest);
+                found.push(wrapped);
             }
-            _ => Err(IoError::runtime_error("Expected constructor application")),
         }
+        found
     }
 
-    fn get_constructor_field_types(&self, constructor_type: &Type) -> Result<Vec<Type>> {
-        match constructor_type {
-            Type::Constructor { fields, .. } => Ok(fields.clone()),
-            Type::Enum { variants, .. } => {
-                // Handle enum constructors
-                Ok(variants.iter()
-                    .flat_map(|v| v.fields.clone())
-                    .collect())
-            }
-            _ => Err(IoError::type_error(format!(
-                "Expected constructor type, found {:?}",
-                constructor_type
-            ))),
+    /// Usefulness of a wildcard head when the rows don't cover every
+    /// constructor: only rows starting with a wildcard matter, and any missing
+    /// constructor makes an example.
+    fn default_witnesses(
+        &self,
+        rows: &[Vec<Pat>],
+        v: &[Pat],
+        tys: &[Type],
+        all: Option<&[Ctor]>,
+    ) -> Vec<Vec<Witness>> {
+        let defaults: Vec<Vec<Pat>> = rows
+            .iter()
+            .filter(|row| matches!(row[0], Pat::Wild))
+            .map(|row| row[1..].to_vec())
+            .collect();
+        let rest = self.witnesses(&defaults, &v[1..], &tys[1..]);
+        if rest.is_empty() {
+            return rest;
         }
+
+        let heads: Vec<Witness> = match all {
+            Some(all) if rows.iter().any(|row| !matches!(row[0], Pat::Wild)) => all
+                .iter()
+                .filter(|ctor| !rows.iter().any(|row| covers(&row[0], ctor)))
+                .map(|ctor| {
+                    let arity = self.field_types(&tys[0], ctor).len();
+                    self.apply(ctor, vec![Witness::Wild; arity], &tys[0])
+                })
+                .collect(),
+            _ => vec![Witness::Wild],
+        };
+        heads
+            .into_iter()
+            .flat_map(|head| {
+                rest.iter().map(move |witness| {
+                    std::iter::once(head.clone())
+                        .chain(witness.iter().cloned())
+                        .collect()
+                })
+            })
+            .collect()
     }
 
-    fn infer_type(&self, node: &ASTNode) -> Result<Type> {
-        match node {
-            ASTNode::IntegerLiteral(_) => Ok(Type::Integer),
-            ASTNode::FloatLiteral(_) => Ok(Type::Float),
-            ASTNode::StringLiteral(_) => Ok(Type::String),
-            ASTNode::BooleanLiteral(_) => Ok(Type::Boolean),
-            ASTNode::ArrayLiteral(elements) => {
-                if elements.is_empty() {
-                    Ok(Type::Array(Box::new(Type::Unknown)))
-                } else {
-                    let element_type = self.infer_type(&elements[0])?;
-                    // Verify all elements have same type
-                    for element in &elements[1..] {
-                        let t = self.infer_type(element)?;
-                        if !self.types_compatible(&element_type, &t) {
-                            return Err(IoError::type_error(
-                                "Inconsistent array element types"
-                            ));
-                        }
+    /// Every constructor of `ty`, or `None` if there are too many to list.
+    /// Array lengths depend on the patterns in the column.
+    fn all_ctors(&self, ty: &Type, heads: &[&Pat]) -> Option<Vec<Ctor>> {
+        match ty {
+            Type::Bool => Some(vec![Ctor::Bool(true), Ctor::Bool(false)]),
+            Type::Tuple(_) => Some(vec![Ctor::Tuple]),
+            Type::Array { .. } => {
+                // Lengths past every fixed-length pattern, and past every
+                // pattern's prefix and suffix, are all matched alike.
+                let mut threshold = 0;
+                for head in heads {
+                    if let Pat::Array { prefix, suffix } = head {
+                        threshold = threshold.max(match suffix {
+                            None => prefix.len() + 1,
+                            Some(suffix) => prefix.len() + suffix.len(),
+                        });
                     }
-                    Ok(Type::Array(Box::new(element_type)))
                 }
+                Some(
+                    (0..threshold)
+                        .map(Ctor::Length)
+                        .chain([Ctor::AtLeast(threshold)])
+                        .collect(),
+                )
             }
-            ASTNode::Identifier(name) => {
-                self.scope.lookup_type(name)
-                    .ok_or_else(|| IoError::runtime_error(
-                        format!("Cannot infer type of undefined variable: {}", name)
-                    ))
+            _ => self
+                .definitions
+                .constructors(ty)
+                .map(|ctors| (0..ctors.len()).map(Ctor::Variant).collect()),
+        }
+    }
+
+    fn field_types(&self, ty: &Type, ctor: &Ctor) -> Vec<Type> {
+        match ctor {
+            Ctor::Variant(index) => self
+                .definitions
+                .constructors(ty)
+                .and_then(|mut ctors| (*index < ctors.len()).then(|| ctors.swap_remove(*index)))
+                .map(|ctor| ctor.fields.into_iter().map(|(_, ty)| ty).collect())
+                .unwrap_or_default(),
+            Ctor::Length(len) | Ctor::AtLeast(len) => {
+                let elem_type = match ty {
+                    Type::Array { elem_type, .. } => elem_type.as_ref().clone(),
+                    _ => Type::Unknown,
+                };
+                vec![elem_type; *len]
             }
-            ASTNode::CallExpression { callee, arguments } => {
-                if let ASTNode::Identifier(name) = &**callee {
-                    let fn_type = self.scope.lookup_type(name)
-                        .ok_or_else(|| IoError::runtime_error(
-                            format!("Unknown function: {}", name)
-                        ))?;
-                    
-                    match fn_type {
-                        Type::Function { return_type, .. } => Ok(*return_type),
-                        _ => Err(IoError::type_error("Expected function type")),
+            Ctor::Tuple => match ty {
+                Type::Tuple(types) => types.clone(),
+                _ => Vec::new(),
+            },
+            Ctor::Bool(_) | Ctor::Literal(_) => Vec::new(),
+        }
+    }
+
+    /// `ctor` applied to `fields`, written the way the source would.
+    fn apply(&self, ctor: &Ctor, fields: Vec<Witness>, ty: &Type) -> Witness {
+        let list = |fields: &[Witness]| {
+            fields
+                .iter()
+                .map(Witness::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let text = match ctor {
+            Ctor::Bool(value) => value.to_string(),
+            Ctor::Literal(text) => text.clone(),
+            Ctor::Length(_) => format!("[{}]", list(&fields)),
+            Ctor::AtLeast(0) => "[..]".to_string(),
+            Ctor::AtLeast(_) => format!("[{}, ..]", list(&fields)),
+            Ctor::Tuple if fields.len() == 1 => format!("({},)", fields[0]),
+            Ctor::Tuple => format!("({})", list(&fields)),
+            Ctor::Variant(index) => {
+                let Some(constructor) = self
+                    .definitions
+                    .constructors(ty)
+                    .and_then(|ctors| ctors.into_iter().nth(*index))
+                else {
+                    return Witness::Wild;
+                };
+                match constructor.style {
+                    FieldStyle::Unit => constructor.name,
+                    FieldStyle::Tuple => format!("{}({})", constructor.name, list(&fields)),
+                    FieldStyle::Named => {
+                        let mentioned: Vec<String> = constructor
+                            .fields
+                            .iter()
+                            .zip(&fields)
+                            .filter(|(_, witness)| !matches!(witness, Witness::Wild))
+                            .map(|((name, _), witness)| format!("{}: {}", name, witness))
+                            .collect();
+                        if mentioned.is_empty() {
+                            format!("{} {{ .. }}", constructor.name)
+                        } else if mentioned.len() < fields.len() {
+                            format!("{} {{ {}, .. }}", constructor.name, mentioned.join(", "))
+                        } else {
+                            format!("{} {{ {} }}", constructor.name, mentioned.join(", "))
+                        }
                     }
-                } else {
-                    Err(IoError::runtime_error("Expected function identifier"))
                 }
             }
-            _ => Err(IoError::runtime_error(
-                format!("Cannot infer type of {:?}", node)
-            )),
-        }
+        };
+        Witness::Pattern(text)
     }
+}
 
-    fn types_compatible(&self, expected: &Type, actual: &Type) -> bool {
-        match (expected, actual) {
-            // Basic type equality
-            (Type::Integer, Type::Integer) |
-            (Type::Float, Type::Float) |
-            (Type::String, Type::String) |
-            (Type::Boolean, Type::Boolean) => true,
-
-            // Array type compatibility
-            (Type::Array(t1), Type::Array(t2)) => 
-                self.types_compatible(t1, t2),
-
-            // Function type compatibility
-            (Type::Function { params: p1, return_type: r1 },
-             Type::Function { params: p2, return_type: r2 }) => {
-                p1.len() == p2.len() &&
-                p1.iter().zip(p2).all(|(t1, t2)| self.types_compatible(t1, t2)) &&
-                self.types_compatible(r1, r2)
+/// Splits rows whose first pattern is an or-pattern into one row per alternative.
+fn expand_or_heads(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    let mut expanded = Vec::with_capacity(rows.len());
+    for row in rows {
+        match row.first() {
+            Some(Pat::Or(alternatives)) => {
+                let alternatives: Vec<Vec<Pat>> = alternatives
+                    .iter()
+                    .map(|alternative| {
+                        let mut row = row.clone();
+                        row[0] = alternative.clone();
+                        row
+                    })
+                    .collect();
+                expanded.extend(expand_or_heads(&alternatives));
             }
+            _ => expanded.push(row.clone()),
+        }
+    }
+    expanded
+}
 
-            // Constructor type compatibility
-            (Type::Constructor { name: n1, fields: f1 },
-             Type::Constructor { name: n2, fields: f2 }) => {
-                n1 == n2 && f1.len() == f2.len() &&
-                f1.iter().zip(f2).all(|(t1, t2)| self.types_compatible(t1, t2))
-            }
+/// Whether `pat`, which is not an or-pattern, matches every value built by `ctor`.
+fn covers(pat: &Pat, ctor: &Ctor) -> bool {
+    match (pat, ctor) {
+        (Pat::Wild, _) => true,
+        (Pat::Ctor(own, _), ctor) => own == ctor,
+        (
+            Pat::Array {
+                prefix,
+                suffix: None,
+            },
+            Ctor::Length(len),
+        ) => prefix.len() == *len,
+        (
+            Pat::Array {
+                prefix,
+                suffix: Some(suffix),
+            },
+            Ctor::Length(len) | Ctor::AtLeast(len),
+        ) => prefix.len() + suffix.len() <= *len,
+        _ => false,
+    }
+}
+
+/// Replaces the first pattern of `row`, which covers `ctor`, by the patterns for
+/// its `arity` fields.
+fn specialize(row: &[Pat], ctor: &Ctor, arity: usize) -> Vec<Pat> {
+    let mut fields = match &row[0] {
+        Pat::Ctor(_, fields) => fields.clone(),
+        Pat::Array {
+            prefix,
+            suffix: None,
+        } => prefix.clone(),
+        Pat::Array {
+            prefix,
+            suffix: Some(suffix),
+        } => {
+            let middle = arity.saturating_sub(prefix.len() + suffix.len());
+            let mut fields = prefix.clone();
+            fields.resize(fields.len() + middle, Pat::Wild);
+            fields.extend(suffix.iter().cloned());
+            fields
+        }
+        Pat::Wild | Pat::Or(_) => Vec::new(),
+    };
+    if matches!(ctor, Ctor::Bool(_) | Ctor::Literal(_)) {
+        fields.clear();
+    }
+    fields.resize(arity, Pat::Wild);
+    fields.extend(row[1..].iter().cloned());
+    fields
+}
 
-            // Unknown type is compatible with anything
-            (_, Type::Unknown) | (Type::Unknown, _) => true,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::ASTNode, parser::parse_source, span::FileId};
 
-            // Everything else is incompatible
-            _ => false,
+    /// `enum Shape { Circle(f64), Rect { w: f64, h: f64 }, Empty }` and
+    /// `Option<Shape>`-like `Maybe`.
+    struct Shapes;
+
+    impl TypeDefinitions for Shapes {
+        fn constructors(&self, ty: &Type) -> Option<Vec<Constructor>> {
+            let ctor = |name: &str, style, fields: &[(&str, Type)]| Constructor {
+                name: name.to_string(),
+                style,
+                fields: fields
+                    .iter()
+                    .map(|(name, ty)| (name.to_string(), ty.clone()))
+                    .collect(),
+            };
+            match ty {
+                Type::Named(name) if name == "Shape" => Some(vec![
+                    ctor("Circle", FieldStyle::Tuple, &[("0", Type::F64)]),
+                    ctor(
+                        "Rect",
+                        FieldStyle::Named,
+                        &[("w", Type::F64), ("h", Type::F64)],
+                    ),
+                    ctor("Empty", FieldStyle::Unit, &[]),
+                ]),
+                Type::Named(name) if name == "Maybe" => Some(vec![
+                    ctor(
+                        "Some",
+                        FieldStyle::Tuple,
+                        &[("0", Type::Named("Shape".into()))],
+                    ),
+                    ctor("None", FieldStyle::Unit, &[]),
+                ]),
+                _ => None,
+            }
         }
     }
+
+    fn check(ty: Type, arms: &str) -> MatchReport {
+        let source = format!("match x {{ {} }}", arms);
+        let ASTNode::Program(items) = parse_source(&source, FileId(0)).unwrap() else {
+            panic!("expected program");
+        };
+        let ASTNode::Match { arms, .. } = &items[0] else {
+            panic!("expected match, got {:?}", items[0]);
+        };
+        check_match(arms, &ty, &Shapes)
+    }
+
+    fn shape() -> Type {
+        Type::Named("Shape".into())
+    }
+
+    #[test]
+    fn test_missing_variants_are_reported() {
+        let report = check(shape(), "Shape::Circle(r) => r, Shape::Empty => 0.0");
+        assert_eq!(report.missing, ["Rect { .. }"]);
+        assert_eq!(report.missing_message(), "missing pattern `Rect { .. }`");
+
+        let report = check(
+            shape(),
+            "Rect { w, h: 0.0 } => w, Circle(_) | Shape::Empty => 1.0",
+        );
+        assert_eq!(report.missing, ["Rect { .. }"]);
+
+        let report = check(
+            Type::Named("Maybe".into()),
+            "Some(Circle(_)) => 1, Maybe::None => 0",
+        );
+        assert_eq!(report.missing, ["Some(Rect { .. })", "Some(Empty)"]);
+        assert_eq!(
+            report.missing_message(),
+            "missing patterns `Some(Rect { .. })` and `Some(Empty)`"
+        );
+    }
+
+    #[test]
+    fn test_guards_and_redundant_arms() {
+        let report = check(Type::Bool, "true => 1, b if b => 2, false => 3, _ => 4");
+        assert!(report.missing.is_empty());
+        assert_eq!(report.unreachable, [3]);
+
+        let report = check(Type::Bool, "true if ready => 1, false => 0");
+        assert_eq!(report.missing, ["true"]);
+
+        let report = check(Type::I32, "0 => 1, 1 => 2, 0 => 3");
+        assert_eq!(report.missing, ["_"]);
+        assert_eq!(report.unreachable, [2]);
+    }
+
+    #[test]
+    fn test_array_patterns() {
+        let ints = Type::Array {
+            elem_type: Box::new(Type::I32),
+            size: 0,
+        };
+        let report = check(ints.clone(), "[] => 0, [x] => x, [x, .., y] => x + y");
+        assert_eq!(report, MatchReport::default());
+
+        let report = check(ints.clone(), "[] => 0, [first, ..] => first, [a, b] => a");
+        assert_eq!(report.unreachable, [2]);
+
+        let report = check(ints, "[] => 0, [0, ..] => 1");
+        assert_eq!(report.missing, ["[_, ..]"]);
+    }
+
+    #[test]
+    fn test_tuple_patterns() {
+        let pair = Type::Tuple(vec![Type::Bool, shape()]);
+        let report = check(
+            pair.clone(),
+            "(true, _) => 1, (false, Circle(_) | Shape::Empty) => 2",
+        );
+        assert_eq!(report.missing, ["(false, Rect { .. })"]);
+
+        let ASTNode::Program(items) =
+            parse_source("let (n, Rect { w, h }) = x;", FileId(0)).unwrap()
+        else {
+            panic!("expected program");
+        };
+        let ASTNode::LetPattern { pattern, .. } = &items[0] else {
+            panic!("expected let pattern, got {:?}", items[0]);
+        };
+        let ty = Type::Tuple(vec![Type::I32, shape()]);
+        let report = check_irrefutable(pattern, &ty, &Shapes);
+        assert_eq!(report.missing, ["(_, Circle(_))", "(_, Empty)"]);
+    }
 }
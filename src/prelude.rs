@@ -1,4 +1,4 @@
-pub use crate::ast::{Expression, Function, Module, Parameter, Statement};
+pub use crate::ast::{ASTNode, NodeId, Parameter, Type};
 pub use crate::error::Result;
 
 // Inkwell re-exports
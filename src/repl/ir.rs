@@ -0,0 +1,82 @@
+use crate::{
+    ast::{ASTNode, NodeId},
+    codegen::llvm::LLVMCodeGen,
+    semantic::resolve,
+    Result,
+};
+use inkwell::context::Context;
+
+/// Name of the function that wraps an entry's top-level statements.
+const ENTRY_FUNCTION: &str = "__repl_entry";
+
+/// Generates LLVM IR for `program` in a module that also holds the session's
+/// function and struct `definitions`. Top-level statements are wrapped in a
+/// function of their own.
+pub(super) fn emit(definitions: &[ASTNode], program: &ASTNode) -> Result<String> {
+    let entry_items = match program {
+        ASTNode::Program(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let (defined, statements): (Vec<&ASTNode>, Vec<&ASTNode>) = entry_items
+        .iter()
+        .partition(|item| definition_name(item).is_some());
+
+    // Redefinitions in this entry replace the session's versions.
+    let mut items: Vec<ASTNode> = definitions
+        .iter()
+        .filter(|definition| {
+            !defined
+                .iter()
+                .any(|item| definition_name(item) == definition_name(definition))
+        })
+        .cloned()
+        .collect();
+    items.extend(defined.into_iter().cloned());
+    if !statements.is_empty() {
+        let span = statements[0]
+            .span()
+            .to(statements[statements.len() - 1].span());
+        items.push(ASTNode::Function {
+            name: ENTRY_FUNCTION.to_string(),
+            type_params: Vec::new(),
+            bounds: Vec::new(),
+            params: Vec::new(),
+            return_type: None,
+            body: statements.into_iter().cloned().collect(),
+            is_async: false,
+            is_const: false,
+            is_pub: false,
+            attributes: Vec::new(),
+            id: NodeId::DUMMY,
+            span,
+        });
+    }
+
+    let context = Context::create();
+    let mut codegen = LLVMCodeGen::new(&context, "repl");
+    let program = ASTNode::Program(items);
+    codegen.generate(&program, &resolve::resolve(&program)?)?;
+    Ok(codegen.emit_ir())
+}
+
+/// The name a function, type or trait definition binds; impls are named
+/// after the trait and type they join, or the type alone.
+pub(super) fn definition_name(node: &ASTNode) -> Option<String> {
+    match node {
+        ASTNode::Function { name, .. }
+        | ASTNode::StructDef { name, .. }
+        | ASTNode::EnumDef { name, .. }
+        | ASTNode::TraitDef { name, .. } => Some(name.clone()),
+        ASTNode::Impl {
+            trait_name: Some(trait_name),
+            self_type,
+            ..
+        } => Some(format!("impl {} for {}", trait_name, self_type)),
+        ASTNode::Impl {
+            trait_name: None,
+            self_type,
+            ..
+        } => Some(format!("impl {}", self_type)),
+        _ => None,
+    }
+}
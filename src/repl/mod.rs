@@ -0,0 +1,381 @@
+mod ir;
+
+use crate::{
+    ast::{ASTNode, Type},
+    build::{cfg::Cfg, Target},
+    diagnostics::{Diagnostic, SourceMap},
+    error::IoError,
+    lexer::Lexer,
+    parser::parse_source,
+    runtime::{Interpreter, Value},
+    semantic::resolve::Resolver,
+    token::TokenKind,
+    types::checker::TypeChecker,
+    Result,
+};
+use std::{
+    fs,
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+const HELP: &str = "\
+:type <expr>   show the static type of an expression
+:ast <code>    print the syntax tree of some code
+:ir <code>     print the LLVM IR generated for some code
+:history       list previous entries
+:help          show this message
+:quit          leave the REPL";
+
+/// What the REPL did with a line of input.
+#[derive(Debug)]
+pub enum ReplOutcome {
+    /// The entry is unfinished (unbalanced brackets); keep reading.
+    NeedMore,
+    /// The entry ran; the text (possibly empty) is what to show the user.
+    Output(String),
+    Error(String),
+    Quit,
+}
+
+/// An interactive session. Definitions and variables persist across entries.
+pub struct Repl {
+    interpreter: Interpreter,
+    /// Resolves each entry in the scope the entries before it left.
+    resolver: Resolver,
+    checker: TypeChecker,
+    /// Every function and struct defined so far, for `:ir`.
+    definitions: Vec<ASTNode>,
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+    pending: String,
+    source_map: SourceMap,
+}
+
+impl Repl {
+    pub fn new(interpreter: Interpreter) -> Self {
+        Self {
+            interpreter,
+            resolver: Resolver::with_prelude(),
+            checker: TypeChecker::new(),
+            definitions: Vec::new(),
+            history: Vec::new(),
+            history_path: None,
+            pending: String::new(),
+            source_map: SourceMap::new(),
+        }
+    }
+
+    /// Loads history from `path` and appends new entries to it.
+    pub fn with_history_file(mut self, path: PathBuf) -> Self {
+        if let Ok(contents) = fs::read_to_string(&path) {
+            self.history = contents
+                .split('\0')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        self.history_path = Some(path);
+        self
+    }
+
+    /// `~/.io_history`, when a home directory is known.
+    pub fn default_history_path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".io_history"))
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            "io> "
+        } else {
+            "... "
+        }
+    }
+
+    /// Reads entries from `input` until EOF or `:quit`, writing results to `output`.
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<()> {
+        writeln!(output, "Io REPL. Type :help for commands, :quit to exit.")?;
+        loop {
+            write!(output, "{}", self.prompt())?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+
+            match self.feed_line(&line) {
+                ReplOutcome::NeedMore => {}
+                ReplOutcome::Output(text) if text.is_empty() => {}
+                ReplOutcome::Output(text) => writeln!(output, "{}", text)?,
+                ReplOutcome::Error(report) => writeln!(output, "{}", report)?,
+                ReplOutcome::Quit => return Ok(()),
+            }
+        }
+    }
+
+    /// Adds a line to the current entry and evaluates the entry once it is complete.
+    pub fn feed_line(&mut self, line: &str) -> ReplOutcome {
+        self.pending.push_str(line);
+        if !self.pending.ends_with('\n') {
+            self.pending.push('\n');
+        }
+        if is_incomplete(&self.pending) {
+            return ReplOutcome::NeedMore;
+        }
+
+        let entry = std::mem::take(&mut self.pending);
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return ReplOutcome::Output(String::new());
+        }
+        // A history file that can't be written doesn't stop the session; it
+        // is reported once and the rest of the history stays in memory.
+        let warning = self.record_history(entry).err().map(|err| {
+            self.history_path = None;
+            format!("warning: could not save history: {}", err)
+        });
+
+        let outcome = match entry.strip_prefix(':') {
+            Some(command) => self.command(command),
+            None => self.evaluate(entry),
+        };
+        match (warning, outcome) {
+            (Some(warning), ReplOutcome::Output(text)) if text.is_empty() => {
+                ReplOutcome::Output(warning)
+            }
+            (Some(warning), ReplOutcome::Output(text)) => {
+                ReplOutcome::Output(format!("{}\n{}", warning, text))
+            }
+            (Some(warning), ReplOutcome::Error(report)) => {
+                ReplOutcome::Error(format!("{}\n{}", warning, report))
+            }
+            (_, outcome) => outcome,
+        }
+    }
+
+    fn command(&mut self, command: &str) -> ReplOutcome {
+        let (name, arg) = match command.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (command, ""),
+        };
+
+        let result = match name {
+            "q" | "quit" => return ReplOutcome::Quit,
+            "help" => Ok(HELP.to_string()),
+            "history" => Ok(self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| format!("{:>4}  {}", i + 1, entry))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "type" => self.type_of(arg),
+            "ast" => self.parse(arg).map(|program| format!("{:#?}", program)),
+            "ir" => self
+                .parse(arg)
+                .and_then(|program| ir::emit(&self.definitions, &program)),
+            _ => Err(IoError::runtime_error(format!(
+                "Unknown command :{} (try :help)",
+                name
+            ))),
+        };
+
+        match result {
+            Ok(text) => ReplOutcome::Output(text),
+            Err(err) => ReplOutcome::Error(self.report(&err)),
+        }
+    }
+
+    fn evaluate(&mut self, entry: &str) -> ReplOutcome {
+        let result = self.parse(entry).and_then(|program| {
+            let value = self.interpreter.run_entry(&program)?;
+            self.remember_definitions(program);
+            Ok(value)
+        });
+
+        match result {
+            Ok(Value::Void) => ReplOutcome::Output(String::new()),
+            Ok(Value::String(s)) => ReplOutcome::Output(format!("{:?}", s)),
+            Ok(value) => ReplOutcome::Output(value.to_string()),
+            Err(err) => ReplOutcome::Error(self.report(&err)),
+        }
+    }
+
+    fn type_of(&mut self, expr: &str) -> Result<String> {
+        let mut ty = Type::Void;
+        if let ASTNode::Program(items) = self.parse(expr)? {
+            for item in &items {
+                self.resolver.resolve(item)?;
+                ty = self.checker.check(item, self.resolver.resolution())?;
+            }
+        }
+        Ok(ty.to_string())
+    }
+
+    /// Makes a successful entry's functions and variables known to `:type` and `:ir`.
+    fn remember_definitions(&mut self, program: ASTNode) {
+        let ASTNode::Program(items) = program else {
+            return;
+        };
+        for item in items {
+            if matches!(
+                item,
+                ASTNode::Function { .. }
+                    | ASTNode::StructDef { .. }
+                    | ASTNode::EnumDef { .. }
+                    | ASTNode::TraitDef { .. }
+                    | ASTNode::Impl { .. }
+                    | ASTNode::Let { .. }
+                    | ASTNode::Global { .. }
+            ) {
+                // The interpreter is dynamically typed, so an entry can run without
+                // type-checking; such definitions just stay unknown to `:type`.
+                if self.resolver.resolve(&item).is_ok() {
+                    let _ = self.checker.check(&item, self.resolver.resolution());
+                }
+            }
+            if let Some(name) = ir::definition_name(&item) {
+                self.definitions
+                    .retain(|definition| ir::definition_name(definition).as_ref() != Some(&name));
+                self.definitions.push(item);
+            }
+        }
+    }
+
+    /// Parses an entry, registering it so errors can point into it, and
+    /// drops what `#[cfg]` configures out on the host.
+    fn parse(&mut self, source: &str) -> Result<ASTNode> {
+        let file_id = self
+            .source_map
+            .add_file(PathBuf::from("<repl>"), source.to_string());
+        Cfg::new(Target::Native).strip(parse_source(source, file_id)?)
+    }
+
+    fn report(&self, err: &IoError) -> String {
+        Diagnostic::from_error(err).report(&self.source_map)
+    }
+
+    fn record_history(&mut self, entry: &str) -> Result<()> {
+        self.history.push(entry.to_string());
+        if let Some(path) = &self.history_path {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            // Entries may span lines, so they are NUL-separated.
+            write!(file, "{}\0", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `source` has unclosed brackets and needs more lines.
+pub fn is_incomplete(source: &str) -> bool {
+    let (tokens, _) = Lexer::new(source).tokenize_with_recovery();
+    let mut depth: i32 = 0;
+    for token in tokens {
+        match token.kind {
+            TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket => depth += 1,
+            TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn repl() -> Repl {
+        Repl::new(Interpreter::with_io(
+            Box::new(Cursor::new(Vec::new())),
+            Box::new(std::io::sink()),
+        ))
+    }
+
+    fn output(outcome: ReplOutcome) -> String {
+        match outcome {
+            ReplOutcome::Output(text) => text,
+            other => panic!("expected output, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_definitions_persist_across_entries() {
+        let mut repl = repl();
+        output(repl.feed_line("let base = 40;"));
+        assert!(matches!(
+            repl.feed_line("fn add(a: int, b: int) -> int {"),
+            ReplOutcome::NeedMore
+        ));
+        assert_eq!(repl.prompt(), "... ");
+        assert!(matches!(
+            repl.feed_line("    return a + b;"),
+            ReplOutcome::NeedMore
+        ));
+        output(repl.feed_line("}"));
+        assert_eq!(output(repl.feed_line("add(base, 2)")), "42");
+        assert_eq!(repl.history().len(), 3);
+    }
+
+    #[test]
+    fn test_type_command_sees_session_definitions() {
+        let mut repl = repl();
+        output(repl.feed_line("fn half(x: float) -> float { return x / 2.0; }"));
+        output(repl.feed_line("let xs = [1, 2, 3];"));
+        assert_eq!(output(repl.feed_line(":type half(1.0)")), "f64");
+        assert_eq!(output(repl.feed_line(":type xs[0] < 2")), "bool");
+    }
+
+    #[test]
+    fn test_errors_are_reported_and_session_continues() {
+        let mut repl = repl();
+        assert!(matches!(
+            repl.feed_line("missing + 1"),
+            ReplOutcome::Error(_)
+        ));
+        assert_eq!(output(repl.feed_line("1 + 1")), "2");
+        assert!(matches!(repl.feed_line(":quit"), ReplOutcome::Quit));
+    }
+
+    #[test]
+    fn test_history_persists_across_sessions() {
+        let path = std::env::temp_dir().join(format!("io-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut first = repl().with_history_file(path.clone());
+        output(first.feed_line("let x = 1;"));
+        assert!(matches!(first.feed_line("fn f() {"), ReplOutcome::NeedMore));
+        output(first.feed_line("}"));
+
+        let second = repl().with_history_file(path.clone());
+        assert_eq!(second.history(), ["let x = 1;", "fn f() {\n}"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unwritable_history_still_evaluates() {
+        let path = std::env::temp_dir()
+            .join(format!("io-missing-{}", std::process::id()))
+            .join("history");
+        let mut repl = repl().with_history_file(path);
+        let text = output(repl.feed_line("1 + 1"));
+        assert!(text.starts_with("warning: could not save history: "));
+        assert!(text.ends_with("\n2"));
+        assert_eq!(output(repl.feed_line("2 + 2")), "4");
+        assert_eq!(repl.history().len(), 2);
+    }
+
+    #[test]
+    fn test_brackets_inside_strings_do_not_count() {
+        assert!(!is_incomplete("println(\"{\")"));
+        assert!(is_incomplete("if x {\n  y("));
+    }
+}
@@ -0,0 +1,1922 @@
+use super::{
+    numeric::{self, SizedInt},
+    value::{Builtin, BuiltinFn, Function, Payload, Sequence, Value},
+};
+use crate::{
+    ast::{
+        captures, ASTNode, BinaryOperator, Literal, MatchArm, Pattern, PatternFields, TraitMethod,
+        Type, UnaryOperator, VariantFields,
+    },
+    error::IoError,
+    stdlib::prelude,
+    Result,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    mem,
+    rc::Rc,
+};
+
+/// Deepest call nesting before the interpreter reports a stack overflow.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Where a local variable lives. Closures capturing the variable by
+/// reference share it.
+type Slot = Rc<RefCell<Value>>;
+
+/// Variables visible to running code: globals plus the local scopes of the
+/// function currently executing.
+pub struct ExecutionContext {
+    globals: HashMap<String, Value>,
+    scopes: Vec<HashMap<String, Slot>>,
+    /// Trait methods by implementing type, as named by `receiver_type`, and
+    /// method name.
+    methods: HashMap<String, HashMap<String, Value>>,
+    /// The methods of each trait, for the defaults impls do not override.
+    traits: HashMap<String, Vec<TraitMethod>>,
+}
+
+impl ExecutionContext {
+    pub fn new() -> Self {
+        let mut context = Self {
+            globals: HashMap::new(),
+            scopes: Vec::new(),
+            methods: HashMap::new(),
+            traits: HashMap::new(),
+        };
+        context.register_builtin_functions();
+        context
+    }
+
+    /// Binds `name` in the innermost scope, shadowing any outer binding.
+    pub fn define(&mut self, name: impl Into<String>, value: Value) {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name.into(), Rc::new(RefCell::new(value)));
+            }
+            None => {
+                self.globals.insert(name.into(), value);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.slot(name) {
+            Some(slot) => Some(slot.borrow().clone()),
+            None => self.globals.get(name).cloned(),
+        }
+    }
+
+    pub fn assign(&mut self, name: &str, value: Value) -> Result<()> {
+        if let Some(slot) = self.slot(name) {
+            *slot.borrow_mut() = value;
+            return Ok(());
+        }
+        let global = self
+            .globals
+            .get_mut(name)
+            .ok_or_else(|| IoError::runtime_error(format!("Undefined variable {}", name)))?;
+        *global = value;
+        Ok(())
+    }
+
+    /// The local variable `name`, if one is in scope.
+    fn slot(&self, name: &str) -> Option<Slot> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+    }
+
+    /// The method `name` of the receiver's type, if it implements one.
+    pub fn method(&self, receiver: &Value, name: &str) -> Option<&Value> {
+        self.methods.get(receiver_type(receiver))?.get(name)
+    }
+
+    /// Exposes the program's command-line arguments as the global `args`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let args = args.into_iter().map(Value::String).collect();
+        self.globals.insert("args".to_string(), Value::Array(args));
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Hides the caller's locals; the callee only sees globals and the
+    /// variables `function` captured.
+    fn enter_call(&mut self, function: &Function) -> Vec<HashMap<String, Slot>> {
+        let captured = function
+            .captures
+            .iter()
+            .map(|(name, slot)| (name.clone(), slot.clone()))
+            .collect();
+        mem::replace(&mut self.scopes, vec![captured])
+    }
+
+    fn exit_call(&mut self, caller_scopes: Vec<HashMap<String, Slot>>) {
+        self.scopes = caller_scopes;
+    }
+
+    fn register_builtin_functions(&mut self) {
+        let builtins = [
+            Builtin {
+                name: "print",
+                arity: None,
+                func: builtin_print,
+            },
+            Builtin {
+                name: "println",
+                arity: None,
+                func: builtin_println,
+            },
+            Builtin {
+                name: "read_line",
+                arity: Some(0),
+                func: builtin_read_line,
+            },
+            Builtin {
+                name: "to_string",
+                arity: Some(1),
+                func: builtin_to_string,
+            },
+            Builtin {
+                name: "parse_int",
+                arity: Some(1),
+                func: builtin_parse_int,
+            },
+            Builtin {
+                name: "parse_float",
+                arity: Some(1),
+                func: builtin_parse_float,
+            },
+            Builtin {
+                name: "len",
+                arity: Some(1),
+                func: builtin_len,
+            },
+            Builtin {
+                name: "map",
+                arity: Some(2),
+                func: builtin_map,
+            },
+            Builtin {
+                name: "filter",
+                arity: Some(2),
+                func: builtin_filter,
+            },
+            Builtin {
+                name: "zip",
+                arity: Some(2),
+                func: builtin_zip,
+            },
+            Builtin {
+                name: "enumerate",
+                arity: Some(1),
+                func: builtin_enumerate,
+            },
+            Builtin {
+                name: "take",
+                arity: Some(2),
+                func: builtin_take,
+            },
+            Builtin {
+                name: "fold",
+                arity: Some(3),
+                func: builtin_fold,
+            },
+            Builtin {
+                name: "collect",
+                arity: Some(1),
+                func: builtin_collect,
+            },
+        ];
+
+        let intrinsics: [(&'static str, BuiltinFn); 6] = [
+            ("wrapping_add", |_, args| {
+                numeric::intrinsic("wrapping_add", &args[0], &args[1])
+            }),
+            ("wrapping_sub", |_, args| {
+                numeric::intrinsic("wrapping_sub", &args[0], &args[1])
+            }),
+            ("wrapping_mul", |_, args| {
+                numeric::intrinsic("wrapping_mul", &args[0], &args[1])
+            }),
+            ("checked_add", |_, args| {
+                numeric::intrinsic("checked_add", &args[0], &args[1])
+            }),
+            ("checked_sub", |_, args| {
+                numeric::intrinsic("checked_sub", &args[0], &args[1])
+            }),
+            ("checked_mul", |_, args| {
+                numeric::intrinsic("checked_mul", &args[0], &args[1])
+            }),
+        ];
+        let intrinsics = intrinsics.into_iter().map(|(name, func)| Builtin {
+            name,
+            arity: Some(2),
+            func,
+        });
+
+        for builtin in builtins.into_iter().chain(intrinsics) {
+            self.globals
+                .insert(builtin.name.to_string(), Value::BuiltinFunction(builtin));
+        }
+        debug_assert!(
+            prelude::builtins().all(|name| self.globals.contains_key(name)),
+            "a prelude builtin has no implementation"
+        );
+    }
+}
+
+impl Default for ExecutionContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a statement finished: normally with its value, or by transferring control.
+enum Flow {
+    Normal(Value),
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// Executes a parsed program directly, without going through LLVM.
+pub struct Interpreter {
+    context: ExecutionContext,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    call_depth: usize,
+    /// The `None` or `Err` a `?` is returning from the innermost call. The
+    /// evaluation in between unwinds as an error until the call catches it.
+    propagating: Option<Value>,
+    /// How many steps, loop iterations and calls, may run; `None` is no limit.
+    step_budget: Option<usize>,
+    steps: usize,
+}
+
+impl Interpreter {
+    /// An interpreter reading from stdin and writing to stdout.
+    pub fn new() -> Self {
+        Self::with_io(Box::new(io::stdin().lock()), Box::new(io::stdout()))
+    }
+
+    /// An interpreter with the prelude declared.
+    pub fn with_io(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        let mut interpreter = Self {
+            context: ExecutionContext::new(),
+            input,
+            output,
+            call_depth: 0,
+            propagating: None,
+            step_budget: None,
+            steps: 0,
+        };
+        for item in prelude::items() {
+            interpreter
+                .execute(item)
+                .expect("the prelude only declares items");
+        }
+        interpreter
+    }
+
+    pub fn context(&self) -> &ExecutionContext {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut ExecutionContext {
+        &mut self.context
+    }
+
+    /// Limits the loop iterations and calls that may run before execution
+    /// fails, counting from now; `None` lifts the limit.
+    pub fn set_step_budget(&mut self, budget: Option<usize>) {
+        self.step_budget = budget;
+        self.steps = 0;
+    }
+
+    /// Counts a loop iteration or call against the step budget.
+    fn step(&mut self) -> Result<()> {
+        self.steps += 1;
+        match self.step_budget {
+            Some(budget) if self.steps > budget => Err(IoError::runtime_error(format!(
+                "Evaluation took more than {} steps",
+                budget
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a program: declares its functions, executes its top-level
+    /// statements, then calls `main` if the program defines one.
+    ///
+    /// Returns the value of `main`, or of the last top-level expression.
+    pub fn run(&mut self, program: &ASTNode) -> Result<Value> {
+        let mut last = self.run_entry(program)?;
+
+        let defines_main = match program {
+            ASTNode::Program(items) => items
+                .iter()
+                .any(|item| matches!(item, ASTNode::Function { name, .. } if name == "main")),
+            _ => false,
+        };
+        if defines_main {
+            let main = self.context.get("main").unwrap_or_default();
+            last = self.call(main, Vec::new())?;
+        }
+
+        self.output.flush()?;
+        Ok(last)
+    }
+
+    /// Executes top-level items against the current context without calling
+    /// `main`, so definitions accumulate across calls (as in the REPL).
+    pub fn run_entry(&mut self, program: &ASTNode) -> Result<Value> {
+        let items = match program {
+            ASTNode::Program(items) => items.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+
+        // Declare every function, enum, trait and impl first so uses may precede
+        // definitions. Traits go first, as impls pick up their default methods.
+        let is_declaration = |item: &ASTNode| {
+            matches!(
+                item,
+                ASTNode::Function { .. }
+                    | ASTNode::EnumDef { .. }
+                    | ASTNode::TraitDef { .. }
+                    | ASTNode::Impl { .. }
+            )
+        };
+        let (traits, declarations): (Vec<&ASTNode>, Vec<&ASTNode>) = items
+            .iter()
+            .filter(|item| is_declaration(item))
+            .partition(|item| matches!(item, ASTNode::TraitDef { .. }));
+        for item in traits.into_iter().chain(declarations) {
+            self.execute(item)?;
+        }
+
+        let mut last = Value::Void;
+        for item in items {
+            if is_declaration(item) {
+                continue;
+            }
+            match self.execute(item)? {
+                Flow::Normal(value) => last = value,
+                Flow::Return(value) => return Ok(value),
+                Flow::Break | Flow::Continue => {
+                    return Err(IoError::runtime_error("Break or continue outside loop")
+                        .with_span(item.span()))
+                }
+            }
+        }
+
+        self.output.flush()?;
+        Ok(last)
+    }
+
+    /// Evaluates a single expression in the current context.
+    pub fn evaluate(&mut self, node: &ASTNode) -> Result<Value> {
+        self.eval(node)
+    }
+
+    fn execute(&mut self, node: &ASTNode) -> Result<Flow> {
+        self.execute_node(node)
+            .map_err(|err| err.or_span(node.span()))
+    }
+
+    fn execute_node(&mut self, node: &ASTNode) -> Result<Flow> {
+        match node {
+            ASTNode::Function {
+                name,
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                let function = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    return_type: return_type.clone(),
+                    body: body.clone(),
+                    captures: Vec::new(),
+                };
+                self.context
+                    .define(name.clone(), Value::Function(Rc::new(function)));
+                Ok(Flow::Normal(Value::Void))
+            }
+            // Values are dynamically typed, so struct definitions have no runtime effect.
+            ASTNode::StructDef { .. } => Ok(Flow::Normal(Value::Void)),
+            // Unit variants are values and tuple variants functions; variants
+            // with named fields are built by struct literals.
+            ASTNode::EnumDef { name, variants, .. } => {
+                for variant in variants {
+                    let value = match &variant.fields {
+                        VariantFields::Unit => Value::Variant {
+                            enum_name: name.clone(),
+                            variant: variant.name.clone(),
+                            payload: Payload::Unit,
+                        },
+                        VariantFields::Tuple(types) => Value::VariantConstructor {
+                            enum_name: name.clone(),
+                            variant: variant.name.clone(),
+                            arity: types.len(),
+                        },
+                        VariantFields::Named(_) => continue,
+                    };
+                    self.context
+                        .define(format!("{}::{}", name, variant.name), value);
+                }
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::TraitDef { name, methods, .. } => {
+                self.context.traits.insert(name.clone(), methods.clone());
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::Impl {
+                trait_name,
+                self_type,
+                methods,
+                ..
+            } => {
+                let mut implemented: HashMap<String, Value> = HashMap::new();
+                for method in methods {
+                    if let ASTNode::Function {
+                        name,
+                        params,
+                        return_type,
+                        body,
+                        ..
+                    } = method
+                    {
+                        let function = Function {
+                            name: name.clone(),
+                            params: params.clone(),
+                            return_type: return_type.clone(),
+                            body: body.clone(),
+                            captures: Vec::new(),
+                        };
+                        implemented.insert(name.clone(), Value::Function(Rc::new(function)));
+                    }
+                }
+                let declared = trait_name
+                    .as_ref()
+                    .and_then(|name| self.context.traits.get(name))
+                    .into_iter()
+                    .flatten();
+                for method in declared {
+                    if let Some(body) = &method.body {
+                        implemented.entry(method.name.clone()).or_insert_with(|| {
+                            Value::Function(Rc::new(Function {
+                                name: method.name.clone(),
+                                params: method.params.clone(),
+                                return_type: method.return_type.clone(),
+                                body: body.clone(),
+                                captures: Vec::new(),
+                            }))
+                        });
+                    }
+                }
+                self.context
+                    .methods
+                    .entry(implementing_type(self_type))
+                    .or_default()
+                    .extend(implemented);
+                Ok(Flow::Normal(Value::Void))
+            }
+            // A variable declared without a value holds nothing until it is
+            // assigned; the control-flow checks reject reading it before then.
+            ASTNode::Let {
+                name, value: None, ..
+            } => {
+                self.context.define(name.clone(), Value::Void);
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::Let {
+                name,
+                type_annotation,
+                value: Some(value),
+                ..
+            }
+            | ASTNode::Global {
+                name,
+                type_annotation,
+                value,
+                ..
+            } => {
+                let mut value = self.eval(value)?;
+                if let Some(ty) = type_annotation {
+                    value = numeric::convert(value, ty)?;
+                }
+                self.context.define(name.clone(), value);
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::LetPattern {
+                pattern,
+                type_annotation,
+                value,
+                ..
+            } => {
+                let mut value = self.eval(value)?;
+                if let Some(ty) = type_annotation {
+                    value = numeric::convert(value, ty)?;
+                }
+                let mut bindings = Vec::new();
+                if !match_pattern(pattern, &value, &mut bindings) {
+                    return Err(IoError::runtime_error(format!(
+                        "Pattern does not match {}",
+                        value
+                    ))
+                    .with_span(pattern.span()));
+                }
+                for (name, value) in bindings {
+                    self.context.define(name, value);
+                }
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::Assignment { target, value, .. } => {
+                let value = self.eval(value)?;
+                self.store(target, value)?;
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::CompoundAssignment {
+                target, op, value, ..
+            } => {
+                let current = self.eval(target)?;
+                let value = self.eval(value)?;
+                let result = binary_operation(op, current, value)?;
+                self.store(target, result)?;
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::Block { statements, .. } => self.execute_block(statements),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if self.eval(condition)?.is_truthy() {
+                    self.execute_block(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute_block(else_branch)
+                } else {
+                    Ok(Flow::Normal(Value::Void))
+                }
+            }
+            ASTNode::While {
+                condition, body, ..
+            } => {
+                while self.eval(condition)?.is_truthy() {
+                    self.step()?;
+                    match self.execute_block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal(_) | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                let iterated = self.eval(iterable)?;
+                let mut sequence = self
+                    .sequence(iterated)
+                    .map_err(|err| err.with_span(iterable.span()))?;
+                while let Some(item) = self.next_element(&mut sequence)? {
+                    self.step()?;
+                    let mut bindings = Vec::new();
+                    if !match_pattern(pattern, &item, &mut bindings) {
+                        return Err(IoError::runtime_error(format!(
+                            "Loop pattern does not match {}",
+                            item
+                        ))
+                        .with_span(pattern.span()));
+                    }
+                    self.context.push_scope();
+                    for (name, value) in bindings {
+                        self.context.define(name, value);
+                    }
+                    let flow = self.execute_block(body);
+                    self.context.pop_scope();
+                    match flow? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal(_) | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal(Value::Void))
+            }
+            ASTNode::Match {
+                scrutinee, arms, ..
+            } => {
+                let value = self.eval(scrutinee)?;
+                for arm in arms {
+                    self.context.push_scope();
+                    let flow = self.execute_arm(arm, &value);
+                    self.context.pop_scope();
+                    if let Some(flow) = flow? {
+                        return Ok(flow);
+                    }
+                }
+                Err(
+                    IoError::runtime_error(format!("No match arm matches {}", value))
+                        .with_span(scrutinee.span()),
+                )
+            }
+            ASTNode::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Void,
+                };
+                Ok(Flow::Return(value))
+            }
+            ASTNode::Break { .. } => Ok(Flow::Break),
+            ASTNode::Continue { .. } => Ok(Flow::Continue),
+            // `module::ModuleManager` replaces imports with the items they name
+            ASTNode::Import { path, .. } => Err(IoError::runtime_error(format!(
+                "Unresolved import of module {}",
+                path.join("::")
+            ))),
+            // `module::ModuleManager` applies them as it loads the file.
+            ASTNode::ModuleAttributes { .. } => Ok(Flow::Normal(Value::Void)),
+            expr => Ok(Flow::Normal(self.eval(expr)?)),
+        }
+    }
+
+    /// Runs `statements` in a fresh scope, stopping at the first control transfer.
+    fn execute_block(&mut self, statements: &[ASTNode]) -> Result<Flow> {
+        self.context.push_scope();
+        let result = self.execute_statements(statements);
+        self.context.pop_scope();
+        result
+    }
+
+    /// Runs `arm` if `value` matches its pattern and guard, with the pattern's
+    /// bindings in the current scope.
+    fn execute_arm(&mut self, arm: &MatchArm, value: &Value) -> Result<Option<Flow>> {
+        let mut bindings = Vec::new();
+        if !match_pattern(&arm.pattern, value, &mut bindings) {
+            return Ok(None);
+        }
+        for (name, value) in bindings {
+            self.context.define(name, value);
+        }
+        if let Some(guard) = &arm.guard {
+            if !self.eval(guard)?.is_truthy() {
+                return Ok(None);
+            }
+        }
+        self.execute(&arm.body).map(Some)
+    }
+
+    fn execute_statements(&mut self, statements: &[ASTNode]) -> Result<Flow> {
+        let mut last = Value::Void;
+        for statement in statements {
+            match self.execute(statement)? {
+                Flow::Normal(value) => last = value,
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal(last))
+    }
+
+    fn eval(&mut self, node: &ASTNode) -> Result<Value> {
+        self.eval_node(node).map_err(|err| err.or_span(node.span()))
+    }
+
+    fn eval_node(&mut self, node: &ASTNode) -> Result<Value> {
+        match node {
+            ASTNode::Literal { value, .. } => literal_value(value),
+            ASTNode::Identifier { name, .. } => self
+                .context
+                .get(name)
+                .ok_or_else(|| IoError::runtime_error(format!("Undefined variable {}", name))),
+            ASTNode::Closure {
+                params,
+                return_type,
+                body,
+                is_move,
+                ..
+            } => {
+                // Globals stay visible to the closure; only locals are captured.
+                let captures = captures(params, body)
+                    .into_iter()
+                    .filter_map(|capture| {
+                        let slot = self.context.slot(&capture.name)?;
+                        if *is_move {
+                            let copy = slot.borrow().clone();
+                            return Some((capture.name, Rc::new(RefCell::new(copy))));
+                        }
+                        Some((capture.name, slot))
+                    })
+                    .collect();
+                Ok(Value::Function(Rc::new(Function {
+                    name: "closure".to_string(),
+                    params: params.clone(),
+                    return_type: return_type.clone(),
+                    body: vec![body.as_ref().clone()],
+                    captures,
+                })))
+            }
+            ASTNode::ArrayLiteral { elements, .. } => Ok(Value::Array(
+                elements
+                    .iter()
+                    .map(|element| self.eval(element))
+                    .collect::<Result<_>>()?,
+            )),
+            ASTNode::TupleLiteral { elements, .. } => Ok(Value::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.eval(element))
+                    .collect::<Result<_>>()?,
+            )),
+            ASTNode::StructLiteral { name, fields, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, value)| Ok((field.clone(), self.eval(value)?)))
+                    .collect::<Result<_>>()?;
+                let Some((enum_name, variant)) = name.rsplit_once("::") else {
+                    return Ok(Value::Object {
+                        name: name.clone(),
+                        fields,
+                    });
+                };
+                Ok(Value::Variant {
+                    enum_name: enum_name.to_string(),
+                    variant: variant.to_string(),
+                    payload: Payload::Named(fields),
+                })
+            }
+            ASTNode::BinaryOp {
+                op: BinaryOperator::And,
+                left,
+                right,
+                ..
+            } => Ok(Value::Boolean(
+                self.eval(left)?.is_truthy() && self.eval(right)?.is_truthy(),
+            )),
+            ASTNode::BinaryOp {
+                op: BinaryOperator::Or,
+                left,
+                right,
+                ..
+            } => Ok(Value::Boolean(
+                self.eval(left)?.is_truthy() || self.eval(right)?.is_truthy(),
+            )),
+            ASTNode::BinaryOp {
+                op, left, right, ..
+            } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary_operation(op, left, right)
+            }
+            ASTNode::UnaryOp { op, operand, .. } => {
+                // `-128i8` is in range though `128i8` isn't.
+                if let (
+                    UnaryOperator::Negate,
+                    ASTNode::Literal {
+                        value: Literal::TypedInteger(n, ty),
+                        ..
+                    },
+                ) = (op, operand.as_ref())
+                {
+                    return literal_value(&Literal::TypedInteger(-n, ty.clone()));
+                }
+                let value = self.eval(operand)?;
+                match (op, value) {
+                    (UnaryOperator::Negate, Value::Integer(n)) => n
+                        .checked_neg()
+                        .map(Value::Integer)
+                        .ok_or_else(|| IoError::runtime_error("Integer overflow")),
+                    (UnaryOperator::Negate, Value::Sized(n)) => {
+                        n.checked_neg().map(Value::Sized).ok_or_else(|| {
+                            IoError::runtime_error(format!("Cannot negate {} {}", n.ty(), n))
+                        })
+                    }
+                    (UnaryOperator::Negate, Value::Float(n)) => Ok(Value::Float(-n)),
+                    (UnaryOperator::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+                    // Tasks run to completion, so an awaited value is already resolved.
+                    (UnaryOperator::Await, value) => Ok(value),
+                    // The borrow checker rules out writes while a value is borrowed,
+                    // so a reference behaves like the value it points to.
+                    (UnaryOperator::Ref | UnaryOperator::RefMut | UnaryOperator::Deref, value) => {
+                        Ok(value)
+                    }
+                    (UnaryOperator::Try, value) => self.try_value(value),
+                    (op, value) => Err(IoError::runtime_error(format!(
+                        "Cannot apply {} to a value of type {}",
+                        op,
+                        value.type_name()
+                    ))),
+                }
+            }
+            ASTNode::Call { callee, args, .. } => {
+                let mut values = Vec::with_capacity(args.len() + 1);
+                let callee = match callee.as_ref() {
+                    // A method takes the receiver as its first argument; without
+                    // one, the member is a field holding a function.
+                    ASTNode::MemberAccess { object, member, .. } => {
+                        let object = self.eval(object)?;
+                        match self.context.method(&object, member).cloned() {
+                            Some(method) => {
+                                values.push(object);
+                                method
+                            }
+                            None => field_value(object, member)?,
+                        }
+                    }
+                    callee => self.eval(callee)?,
+                };
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.call(callee, values)
+            }
+            ASTNode::Cast { value, target, .. } => {
+                let value = self.eval(value)?;
+                numeric::cast(value, target)
+            }
+            ASTNode::Index { array, index, .. } => {
+                let container = self.eval(array)?;
+                let index = self.eval(index)?;
+                index_value(container, index)
+            }
+            ASTNode::MemberAccess { object, member, .. } => {
+                let object = self.eval(object)?;
+                field_value(object, member)
+            }
+            ASTNode::Error { .. } => Err(IoError::runtime_error(
+                "Cannot run a program that failed to parse",
+            )),
+            statement => match self.execute(statement)? {
+                Flow::Normal(value) => Ok(value),
+                _ => Err(IoError::runtime_error(
+                    "Control flow statement used as a value",
+                )),
+            },
+        }
+    }
+
+    /// Assigns `value` to the variable, field or element `target` names.
+    fn store(&mut self, target: &ASTNode, value: Value) -> Result<()> {
+        let (root, path) = self.place(target)?;
+        let mut current = self
+            .context
+            .get(root)
+            .ok_or_else(|| IoError::runtime_error(format!("Undefined variable {}", root)))?;
+        let slot =
+            path.into_iter()
+                .try_fold(&mut current, |slot, projection| match projection {
+                    Projection::Field(member) => field_slot(slot, member),
+                    Projection::Index(index) => element_slot(slot, index),
+                })?;
+        *slot = match slot {
+            Value::Sized(n) => numeric::convert(value, n.ty())?,
+            _ => value,
+        };
+        self.context.assign(root, current)
+    }
+
+    /// The variable `target` is in and the way from its value to the place,
+    /// with the indices on the way evaluated in order.
+    fn place<'t>(&mut self, target: &'t ASTNode) -> Result<(&'t str, Vec<Projection<'t>>)> {
+        match target {
+            ASTNode::Identifier { name, .. } => Ok((name, Vec::new())),
+            ASTNode::MemberAccess { object, member, .. } => {
+                let (root, mut path) = self.place(object)?;
+                path.push(Projection::Field(member));
+                Ok((root, path))
+            }
+            ASTNode::Index { array, index, .. } => {
+                let (root, mut path) = self.place(array)?;
+                path.push(Projection::Index(self.eval(index)?));
+                Ok((root, path))
+            }
+            other => {
+                Err(IoError::runtime_error("Invalid assignment target").with_span(other.span()))
+            }
+        }
+    }
+
+    /// `value?`: the payload of `Some` or `Ok`. A `None` or `Err` is returned
+    /// from the enclosing function.
+    fn try_value(&mut self, value: Value) -> Result<Value> {
+        let Value::Variant {
+            enum_name,
+            variant,
+            payload,
+        } = value
+        else {
+            return Err(IoError::runtime_error(format!(
+                "The ? operator applies to an Option or Result, not a value of type {}",
+                value.type_name()
+            )));
+        };
+        if self.call_depth == 0 {
+            return Err(IoError::runtime_error(
+                "The ? operator can only be used in a function",
+            ));
+        }
+        match (variant.as_str(), payload) {
+            ("Some" | "Ok", Payload::Tuple(mut fields)) if fields.len() == 1 => {
+                Ok(fields.remove(0))
+            }
+            ("None" | "Err", payload) => {
+                self.propagating = Some(Value::Variant {
+                    enum_name,
+                    variant,
+                    payload,
+                });
+                Err(IoError::runtime_error("Returning early from ?"))
+            }
+            (_, _) => Err(IoError::runtime_error(format!(
+                "The ? operator applies to an Option or Result, not {}",
+                enum_name
+            ))),
+        }
+    }
+
+    /// Calls a function value with already-evaluated arguments.
+    pub fn call(&mut self, callee: Value, args: Vec<Value>) -> Result<Value> {
+        match callee {
+            Value::BuiltinFunction(builtin) => {
+                if let Some(arity) = builtin.arity {
+                    check_arity(builtin.name, arity, args.len())?;
+                }
+                (builtin.func)(self, args)
+            }
+            Value::Function(function) => {
+                check_arity(&function.name, function.params.len(), args.len())?;
+                self.step()?;
+                if self.call_depth >= MAX_CALL_DEPTH {
+                    return Err(IoError::runtime_error(format!(
+                        "Stack overflow: more than {} nested calls",
+                        MAX_CALL_DEPTH
+                    )));
+                }
+
+                let args = function
+                    .params
+                    .iter()
+                    .zip(args)
+                    .map(|(param, arg)| numeric::convert(arg, &param.type_annotation))
+                    .collect::<Result<Vec<_>>>()?;
+                let caller_scopes = self.context.enter_call(&function);
+                for (param, arg) in function.params.iter().zip(args) {
+                    self.context.define(param.name.clone(), arg);
+                }
+
+                self.call_depth += 1;
+                let flow = self.execute_statements(&function.body).or_else(|err| {
+                    match self.propagating.take() {
+                        Some(value) => Ok(Flow::Return(value)),
+                        None => Err(err),
+                    }
+                });
+                self.call_depth -= 1;
+                self.context.exit_call(caller_scopes);
+
+                match flow? {
+                    Flow::Normal(value) | Flow::Return(value) => match &function.return_type {
+                        Some(ty) => numeric::convert(value, ty),
+                        None => Ok(value),
+                    },
+                    Flow::Break | Flow::Continue => Err(IoError::runtime_error(format!(
+                        "Break or continue escaped function {}",
+                        function.name
+                    ))),
+                }
+            }
+            Value::VariantConstructor {
+                enum_name,
+                variant,
+                arity,
+            } => {
+                check_arity(&format!("{}::{}", enum_name, variant), arity, args.len())?;
+                Ok(Value::Variant {
+                    enum_name,
+                    variant,
+                    payload: Payload::Tuple(args),
+                })
+            }
+            other => Err(IoError::runtime_error(format!(
+                "Value of type {} is not callable",
+                other.type_name()
+            ))),
+        }
+    }
+
+    /// Calls the method `name` of the receiver's type on `receiver`.
+    fn call_method(&mut self, receiver: Value, name: &str) -> Result<Value> {
+        let method = self
+            .context
+            .method(&receiver, name)
+            .cloned()
+            .ok_or_else(|| {
+                IoError::runtime_error(format!(
+                    "Value of type {} has no method {}",
+                    receiver.type_name(),
+                    name
+                ))
+            })?;
+        self.call(method, vec![receiver])
+    }
+
+    /// What iterating over `value` produces: the elements of an array, the
+    /// characters of a string, or the elements an iterator has left.
+    fn sequence(&self, value: Value) -> Result<Sequence> {
+        match value {
+            Value::Array(items) => Ok(Sequence::Items(items.into_iter())),
+            Value::String(s) => {
+                let chars: Vec<Value> = s.chars().map(Value::Char).collect();
+                Ok(Sequence::Items(chars.into_iter()))
+            }
+            Value::Iterator(sequence) => Ok(*sequence),
+            value if self.context.method(&value, "next").is_some() => Ok(Sequence::User(value)),
+            other => Err(IoError::runtime_error(format!(
+                "Cannot iterate over a value of type {}",
+                other.type_name()
+            ))),
+        }
+    }
+
+    /// The next element of `sequence`, or `None` once it is exhausted.
+    fn next_element(&mut self, sequence: &mut Sequence) -> Result<Option<Value>> {
+        match sequence {
+            Sequence::Range { next, end, ty } => {
+                if next >= end {
+                    return Ok(None);
+                }
+                let n = *next;
+                *next += 1;
+                Ok(Some(match ty {
+                    Some(ty) => Value::from(SizedInt::wrapping(n as u128, ty.clone())),
+                    None => Value::Integer(n as i64),
+                }))
+            }
+            Sequence::Items(items) => Ok(items.next()),
+            Sequence::User(state) => match self.call_method(state.clone(), "next")? {
+                Value::Variant {
+                    variant,
+                    payload: Payload::Tuple(mut values),
+                    ..
+                } if variant == "Some" && values.len() == 1 => match values.pop() {
+                    Some(Value::Object { name, mut fields }) if name == prelude::PAIR_TYPE => {
+                        let (Some(item), Some(rest)) =
+                            (fields.remove("first"), fields.remove("second"))
+                        else {
+                            return Err(IoError::runtime_error(
+                                "Malformed Pair from Iterator next",
+                            ));
+                        };
+                        *state = rest;
+                        Ok(Some(item))
+                    }
+                    other => Err(IoError::runtime_error(format!(
+                        "Iterator next must give a Pair, not {}",
+                        other.unwrap_or_default()
+                    ))),
+                },
+                Value::Variant { variant, .. } if variant == "None" => Ok(None),
+                other => Err(IoError::runtime_error(format!(
+                    "Iterator next must return an Option, not {}",
+                    other
+                ))),
+            },
+            Sequence::Map(inner, f) => match self.next_element(inner)? {
+                Some(item) => self.call(f.clone(), vec![item]).map(Some),
+                None => Ok(None),
+            },
+            Sequence::Filter(inner, keep) => {
+                while let Some(item) = self.next_element(inner)? {
+                    if self.call(keep.clone(), vec![item.clone()])?.is_truthy() {
+                        return Ok(Some(item));
+                    }
+                }
+                Ok(None)
+            }
+            // `b` is only drawn from while `a` has elements left.
+            Sequence::Zip(a, b) => match self.next_element(a)? {
+                Some(first) => Ok(self.next_element(b)?.map(|second| pair(first, second))),
+                None => Ok(None),
+            },
+            Sequence::Enumerate(inner, index) => match self.next_element(inner)? {
+                Some(item) => {
+                    let element = pair(Value::Integer(*index), item);
+                    *index += 1;
+                    Ok(Some(element))
+                }
+                None => Ok(None),
+            },
+            Sequence::Take(inner, remaining) => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+                *remaining -= 1;
+                self.next_element(inner)
+            }
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A step from a value to a part of it that can be assigned.
+enum Projection<'t> {
+    Field(&'t str),
+    Index(Value),
+}
+
+fn field_value(mut object: Value, member: &str) -> Result<Value> {
+    field_slot(&mut object, member).map(std::mem::take)
+}
+
+/// The field `member` of a struct, or the element of a tuple at that position.
+fn field_slot<'v>(object: &'v mut Value, member: &str) -> Result<&'v mut Value> {
+    let type_name = object.type_name();
+    let slot = match object {
+        Value::Object { fields, .. } => fields.get_mut(member),
+        Value::Tuple(values) => member.parse().ok().and_then(|i: usize| values.get_mut(i)),
+        _ => {
+            return Err(IoError::runtime_error(format!(
+                "Value of type {} has no field {}",
+                type_name, member
+            )))
+        }
+    };
+    slot.ok_or_else(|| IoError::runtime_error(format!("No field {}", member)))
+}
+
+/// The name methods of `value` are registered under: its enum or struct, or
+/// its runtime type name.
+fn receiver_type(value: &Value) -> &str {
+    match value {
+        Value::Variant { enum_name, .. } => enum_name,
+        Value::Object { name, .. } => name,
+        Value::Sized(_) => "int",
+        other => other.type_name(),
+    }
+}
+
+/// The name methods implemented for `ty` are registered under, matching
+/// `receiver_type` for its values.
+fn implementing_type(ty: &Type) -> String {
+    match ty {
+        ty if ty.is_integer() => "int".to_string(),
+        ty if ty.is_float() => "float".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Char => "char".to_string(),
+        Type::String => "string".to_string(),
+        Type::Array { .. } => "array".to_string(),
+        Type::Named(name) | Type::Generic { name, .. } => name.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn literal_value(literal: &Literal) -> Result<Value> {
+    Ok(match literal {
+        Literal::Integer(n) => Value::Integer(*n),
+        Literal::Float(n) => Value::Float(*n),
+        Literal::TypedInteger(n, ty) => {
+            SizedInt::new(*n, ty.clone())
+                .map(Value::from)
+                .ok_or_else(|| {
+                    IoError::runtime_error(format!(
+                        "Integer literal {} is out of range for {}",
+                        n, ty
+                    ))
+                })?
+        }
+        Literal::TypedFloat(n, ty) => numeric::convert(Value::Float(*n), ty)?,
+        Literal::String(s) => Value::String(s.clone()),
+        Literal::Boolean(b) => Value::Boolean(*b),
+        Literal::Char(c) => Value::Char(*c),
+    })
+}
+
+/// Whether `value` matches `pattern`, collecting the names it binds.
+fn match_pattern(pattern: &Pattern, value: &Value, bindings: &mut Vec<(String, Value)>) -> bool {
+    let all = |patterns: &[Pattern], values: &[Value], bindings: &mut Vec<_>| {
+        patterns
+            .iter()
+            .zip(values)
+            .all(|(pattern, value)| match_pattern(pattern, value, bindings))
+    };
+    match (pattern, value) {
+        (Pattern::Wildcard { .. }, _) => true,
+        (Pattern::Binding { name, .. }, value) => {
+            bindings.push((name.clone(), value.clone()));
+            true
+        }
+        (Pattern::Literal { value: literal, .. }, value) => {
+            literal_value(literal).is_ok_and(|literal| literal == *value)
+        }
+        (Pattern::Or { alternatives, .. }, value) => alternatives.iter().any(|alternative| {
+            let bound = bindings.len();
+            let matched = match_pattern(alternative, value, bindings);
+            if !matched {
+                bindings.truncate(bound);
+            }
+            matched
+        }),
+        (Pattern::Tuple { elements, .. }, Value::Tuple(items)) => {
+            elements.len() == items.len() && all(elements, items, bindings)
+        }
+        (Pattern::Array { elements, rest, .. }, Value::Array(items)) => match rest {
+            None => elements.len() == items.len() && all(elements, items, bindings),
+            Some(at) => {
+                let (prefix, suffix) = elements.split_at(*at);
+                items.len() >= elements.len()
+                    && all(prefix, items, bindings)
+                    && all(suffix, &items[items.len() - suffix.len()..], bindings)
+            }
+        },
+        (
+            Pattern::Struct { path, fields, .. },
+            Value::Variant {
+                variant, payload, ..
+            },
+        ) => {
+            path.rsplit("::").next() == Some(variant.as_str())
+                && match (fields, payload) {
+                    (PatternFields::Unit, Payload::Unit) => true,
+                    (PatternFields::Tuple(patterns), Payload::Tuple(values)) => {
+                        patterns.len() == values.len() && all(patterns, values, bindings)
+                    }
+                    (PatternFields::Named { fields, .. }, Payload::Named(values)) => {
+                        fields.iter().all(|(name, pattern)| {
+                            values
+                                .get(name)
+                                .is_some_and(|value| match_pattern(pattern, value, bindings))
+                        })
+                    }
+                    _ => false,
+                }
+        }
+        (
+            Pattern::Struct {
+                fields: PatternFields::Named { fields, .. },
+                ..
+            },
+            Value::Object { fields: values, .. },
+        ) => fields.iter().all(|(name, pattern)| {
+            values
+                .get(name)
+                .is_some_and(|value| match_pattern(pattern, value, bindings))
+        }),
+        _ => false,
+    }
+}
+
+fn check_arity(name: &str, expected: usize, found: usize) -> Result<()> {
+    if expected != found {
+        return Err(IoError::runtime_error(format!(
+            "Function {} expects {} arguments but got {}",
+            name, expected, found
+        )));
+    }
+    Ok(())
+}
+
+fn binary_operation(op: &BinaryOperator, left: Value, right: Value) -> Result<Value> {
+    use BinaryOperator::*;
+
+    // A plain integer, like an unsuffixed literal, takes the type of a sized
+    // one it meets.
+    let (left, right) = match (left, right) {
+        (Value::Sized(l), Value::Integer(r)) => (Value::Sized(l.clone()), adopt(r, l.ty())?),
+        (Value::Integer(l), Value::Sized(r)) => (adopt(l, r.ty())?, Value::Sized(r)),
+        operands => operands,
+    };
+    let result = match (&left, &right) {
+        (Value::Integer(l), Value::Integer(r)) => integer_operation(op, *l, *r)?,
+        (Value::Sized(l), Value::Sized(r)) if l.ty() == r.ty() => l.operation(op, r)?,
+        (Value::Float(l), Value::Float(r)) => float_operation(op, *l, *r),
+        (Value::Integer(l), Value::Float(r)) => float_operation(op, *l as f64, *r),
+        (Value::Float(l), Value::Integer(r)) => float_operation(op, *l, *r as f64),
+        (Value::String(l), Value::String(r)) => match op {
+            Add => Some(Value::String(format!("{}{}", l, r))),
+            LessThan => Some(Value::Boolean(l < r)),
+            LessThanEqual => Some(Value::Boolean(l <= r)),
+            GreaterThan => Some(Value::Boolean(l > r)),
+            GreaterThanEqual => Some(Value::Boolean(l >= r)),
+            _ => None,
+        },
+        (Value::Char(l), Value::Char(r)) => match op {
+            LessThan => Some(Value::Boolean(l < r)),
+            LessThanEqual => Some(Value::Boolean(l <= r)),
+            GreaterThan => Some(Value::Boolean(l > r)),
+            GreaterThanEqual => Some(Value::Boolean(l >= r)),
+            _ => None,
+        },
+        (Value::Boolean(l), Value::Boolean(r)) => match op {
+            BitwiseAnd => Some(Value::Boolean(l & r)),
+            BitwiseOr => Some(Value::Boolean(l | r)),
+            BitwiseXor => Some(Value::Boolean(l ^ r)),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match (result, op) {
+        (Some(value), _) => Ok(value),
+        (None, Equal) => Ok(Value::Boolean(left == right)),
+        (None, NotEqual) => Ok(Value::Boolean(left != right)),
+        (None, _) => Err(IoError::runtime_error(format!(
+            "Cannot apply {} to values of type {} and {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        ))),
+    }
+}
+
+fn adopt(n: i64, ty: &Type) -> Result<Value> {
+    numeric::convert(Value::Integer(n), ty)
+}
+
+fn integer_operation(op: &BinaryOperator, l: i64, r: i64) -> Result<Option<Value>> {
+    use BinaryOperator::*;
+
+    let overflow = || IoError::runtime_error("Integer overflow");
+    if matches!(op, Divide | Modulo) && r == 0 {
+        return Err(IoError::runtime_error("Division by zero"));
+    }
+
+    let value = match op {
+        Add => Value::Integer(l.checked_add(r).ok_or_else(overflow)?),
+        Subtract => Value::Integer(l.checked_sub(r).ok_or_else(overflow)?),
+        Multiply => Value::Integer(l.checked_mul(r).ok_or_else(overflow)?),
+        Divide => Value::Integer(l.checked_div(r).ok_or_else(overflow)?),
+        Modulo => Value::Integer(l.checked_rem(r).ok_or_else(overflow)?),
+        BitwiseAnd => Value::Integer(l & r),
+        BitwiseOr => Value::Integer(l | r),
+        BitwiseXor => Value::Integer(l ^ r),
+        LeftShift | RightShift => {
+            let shift = u32::try_from(r)
+                .ok()
+                .filter(|shift| *shift < 64)
+                .ok_or_else(|| IoError::runtime_error(format!("Invalid shift amount {}", r)))?;
+            Value::Integer(if *op == LeftShift {
+                l << shift
+            } else {
+                l >> shift
+            })
+        }
+        LessThan => Value::Boolean(l < r),
+        LessThanEqual => Value::Boolean(l <= r),
+        GreaterThan => Value::Boolean(l > r),
+        GreaterThanEqual => Value::Boolean(l >= r),
+        Range | RangeInclusive => Value::Iterator(Box::new(Sequence::Range {
+            next: l.into(),
+            end: if *op == Range {
+                r.into()
+            } else {
+                i128::from(r) + 1
+            },
+            ty: None,
+        })),
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+fn float_operation(op: &BinaryOperator, l: f64, r: f64) -> Option<Value> {
+    use BinaryOperator::*;
+
+    let value = match op {
+        Add => Value::Float(l + r),
+        Subtract => Value::Float(l - r),
+        Multiply => Value::Float(l * r),
+        Divide => Value::Float(l / r),
+        Modulo => Value::Float(l % r),
+        LessThan => Value::Boolean(l < r),
+        LessThanEqual => Value::Boolean(l <= r),
+        GreaterThan => Value::Boolean(l > r),
+        GreaterThanEqual => Value::Boolean(l >= r),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn index_value(mut container: Value, index: Value) -> Result<Value> {
+    if let (Value::String(s), Value::Integer(i)) = (&container, integer_index(&index)) {
+        return usize::try_from(i)
+            .ok()
+            .and_then(|i| s.chars().nth(i))
+            .map(Value::Char)
+            .ok_or_else(|| {
+                IoError::runtime_error(format!(
+                    "Index {} out of bounds for string of length {}",
+                    i,
+                    s.chars().count()
+                ))
+            });
+    }
+    element_slot(&mut container, index).map(std::mem::take)
+}
+
+/// The element of an array at `index`, or the field of an object it names.
+fn element_slot(container: &mut Value, index: Value) -> Result<&mut Value> {
+    let index = integer_index(&index);
+    match (container, index) {
+        (Value::Array(items), Value::Integer(i)) => {
+            let len = items.len();
+            usize::try_from(i)
+                .ok()
+                .filter(|i| *i < len)
+                .map(|i| &mut items[i])
+                .ok_or_else(|| {
+                    IoError::runtime_error(format!(
+                        "Index {} out of bounds for array of length {}",
+                        i, len
+                    ))
+                })
+        }
+        (Value::Object { fields, .. }, Value::String(key)) => fields
+            .get_mut(&key)
+            .ok_or_else(|| IoError::runtime_error(format!("No field {}", key))),
+        (container, index) => Err(IoError::runtime_error(format!(
+            "Cannot index a value of type {} with {}",
+            container.type_name(),
+            index.type_name()
+        ))),
+    }
+}
+
+/// A sized integer index as a plain one; other values as they are.
+fn integer_index(index: &Value) -> Value {
+    match index {
+        Value::Sized(n) => Value::Integer(
+            n.to_i128()
+                .and_then(|n| i64::try_from(n).ok())
+                .unwrap_or(i64::MAX),
+        ),
+        index => index.clone(),
+    }
+}
+
+fn write_values(interpreter: &mut Interpreter, args: &[Value]) -> Result<()> {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(interpreter.output, " ")?;
+        }
+        write!(interpreter.output, "{}", arg)?;
+    }
+    Ok(())
+}
+
+fn builtin_print(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    write_values(interpreter, &args)?;
+    interpreter.output.flush()?;
+    Ok(Value::Void)
+}
+
+fn builtin_println(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    write_values(interpreter, &args)?;
+    writeln!(interpreter.output)?;
+    Ok(Value::Void)
+}
+
+fn builtin_read_line(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value> {
+    interpreter.output.flush()?;
+    let mut line = String::new();
+    interpreter.input.read_line(&mut line)?;
+    let trimmed = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(trimmed);
+    Ok(Value::String(line))
+}
+
+fn builtin_to_string(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+fn builtin_parse_int(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| IoError::runtime_error(format!("Failed to parse integer: '{}'", s))),
+        other => Err(IoError::runtime_error(format!(
+            "parse_int requires a string argument, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn builtin_parse_float(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| IoError::runtime_error(format!("Failed to parse float: '{}'", s))),
+        other => Err(IoError::runtime_error(format!(
+            "parse_float requires a string argument, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn builtin_len(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let len = match &args[0] {
+        Value::Array(items) => items.len(),
+        Value::String(s) => s.chars().count(),
+        Value::Object { fields, .. } => fields.len(),
+        other => {
+            return Err(IoError::runtime_error(format!(
+                "len requires an array or string argument, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(Value::Integer(len as i64))
+}
+
+/// A `Pair` holding `first` and `second`.
+fn pair(first: Value, second: Value) -> Value {
+    Value::Object {
+        name: prelude::PAIR_TYPE.to_string(),
+        fields: HashMap::from([("first".to_string(), first), ("second".to_string(), second)]),
+    }
+}
+
+/// The sequence of the argument an iterator builtin was passed.
+fn sequence_argument(interpreter: &Interpreter, name: &str, value: Value) -> Result<Sequence> {
+    let type_name = value.type_name();
+    interpreter.sequence(value).map_err(|_| {
+        IoError::runtime_error(format!(
+            "{} requires an iterable argument, got {}",
+            name, type_name
+        ))
+    })
+}
+
+/// `map(items, f)`: `f` applied to each element, as it is drawn.
+fn builtin_map(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let f = args.pop().unwrap_or_default();
+    let items = sequence_argument(interpreter, "map", args.pop().unwrap_or_default())?;
+    Ok(Value::Iterator(Box::new(Sequence::Map(Box::new(items), f))))
+}
+
+/// `filter(items, keep)`: the elements `keep` returns true for.
+fn builtin_filter(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let keep = args.pop().unwrap_or_default();
+    let items = sequence_argument(interpreter, "filter", args.pop().unwrap_or_default())?;
+    Ok(Value::Iterator(Box::new(Sequence::Filter(
+        Box::new(items),
+        keep,
+    ))))
+}
+
+/// `zip(a, b)`: pairs of elements of `a` and `b`, as long as both last.
+fn builtin_zip(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let b = sequence_argument(interpreter, "zip", args.pop().unwrap_or_default())?;
+    let a = sequence_argument(interpreter, "zip", args.pop().unwrap_or_default())?;
+    Ok(Value::Iterator(Box::new(Sequence::Zip(
+        Box::new(a),
+        Box::new(b),
+    ))))
+}
+
+/// `enumerate(items)`: each element paired with its index.
+fn builtin_enumerate(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let items = sequence_argument(interpreter, "enumerate", args.pop().unwrap_or_default())?;
+    Ok(Value::Iterator(Box::new(Sequence::Enumerate(
+        Box::new(items),
+        0,
+    ))))
+}
+
+/// `take(items, n)`: the first `n` elements.
+fn builtin_take(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let count = match args.pop().unwrap_or_default() {
+        Value::Integer(n) => usize::try_from(n).map_err(|_| {
+            IoError::runtime_error(format!("take requires a count of at least 0, got {}", n))
+        })?,
+        other => {
+            return Err(IoError::runtime_error(format!(
+                "take requires an int count, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    let items = sequence_argument(interpreter, "take", args.pop().unwrap_or_default())?;
+    Ok(Value::Iterator(Box::new(Sequence::Take(
+        Box::new(items),
+        count,
+    ))))
+}
+
+/// `fold(items, initial, f)`: `f(f(initial, first), second)` and so on.
+fn builtin_fold(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let f = args.pop().unwrap_or_default();
+    let mut accumulator = args.pop().unwrap_or_default();
+    let mut items = sequence_argument(interpreter, "fold", args.pop().unwrap_or_default())?;
+    while let Some(item) = interpreter.next_element(&mut items)? {
+        accumulator = interpreter.call(f.clone(), vec![accumulator, item])?;
+    }
+    Ok(accumulator)
+}
+
+/// `collect(items)`: the elements gathered into an array.
+fn builtin_collect(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    let mut items = sequence_argument(interpreter, "collect", args.pop().unwrap_or_default())?;
+    let mut collected = Vec::new();
+    while let Some(item) = interpreter.next_element(&mut items)? {
+        collected.push(item);
+    }
+    Ok(Value::Array(collected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+    use std::io::Cursor;
+
+    /// Output sink the test can read back after the interpreter is done.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_with_input(source: &str, input: &str) -> (Result<Value>, String) {
+        let program = parse_source(source, FileId(0)).unwrap();
+        let output = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_io(
+            Box::new(Cursor::new(input.to_string())),
+            Box::new(output.clone()),
+        );
+        let result = interpreter.run(&program);
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (result, text)
+    }
+
+    #[test]
+    fn test_runs_hello_world_example() {
+        let source = include_str!("../../examples/hello_world.io");
+        let (result, output) = run_with_input(source, "Ada\n");
+        result.unwrap();
+        assert_eq!(
+            output,
+            "Welcome to IO Lang Beta!\nWhat's your name?\nNice to meet you, Ada!\n\
+             Let me show you some basic arithmetic...\nNumbers: [1, 2, 3, 4, 5]\n\
+             Sum of numbers: 15\nAverage: 3\n"
+        );
+    }
+
+    #[test]
+    fn test_runs_calculator_example() {
+        let source = include_str!("../../examples/calculator.io");
+        let (result, output) = run_with_input(source, "7\n0\n4\n");
+        result.unwrap();
+        assert!(
+            output.ends_with("Error: Cannot divide by zero!\n"),
+            "{}",
+            output
+        );
+
+        let (_, output) = run_with_input(source, "6\n7\n3\n");
+        assert!(output.ends_with("Product = 42\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_recursion_and_loop_control() {
+        let source = "fn fib(n: int) -> int { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }\n\
+                      fn main() -> int {\n\
+                          let total = 0;\n\
+                          for i in 0..10 { if i == 3 { continue; } if i == 6 { break; } total += fib(i); }\n\
+                          return total;\n\
+                      }";
+        let (result, _) = run_with_input(source, "");
+        // fib(0) + fib(1) + fib(2) + fib(4) + fib(5)
+        assert_eq!(result.unwrap(), Value::Integer(10));
+    }
+
+    #[test]
+    fn test_runtime_errors_carry_spans() {
+        let source = "let xs = [1, 2];\nlet y = xs[5];";
+        let (result, _) = run_with_input(source, "");
+        let err = result.unwrap_err();
+        assert_eq!(err.message(), "Index 5 out of bounds for array of length 2");
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "xs[5]");
+    }
+    #[test]
+    fn test_match_on_enum_variants() {
+        let source = "\
+enum Shape { Circle(float), Rect { w: float, h: float }, Empty }
+fn area(s: Shape) -> float {
+    match s {
+        Shape::Circle(r) => 3.0 * r * r,
+        Shape::Rect { w, h } if w == h => { println(\"square\"); w * w }
+        Shape::Rect { w, h } => w * h,
+        Shape::Empty => 0.0,
+    }
+}
+fn main() -> float {
+    let shapes = [Shape::Circle(1.0), Shape::Rect { w: 2.0, h: 3.0 }, Shape::Rect { h: 2.0, w: 2.0 }, Shape::Empty];
+    let total = 0.0;
+    for s in shapes { total += area(s); }
+    return total;
+}";
+        let (result, output) = run_with_input(source, "");
+        assert_eq!(result.unwrap(), Value::Float(13.0));
+        assert_eq!(output, "square\n");
+
+        let source = "let xs = [1, 2, 3];\nmatch xs { [] => 0, [x] => x }";
+        let (result, _) = run_with_input(source, "");
+        let err = result.unwrap_err();
+        assert_eq!(err.message(), "No match arm matches [1, 2, 3]");
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "xs");
+    }
+
+    #[test]
+    fn test_trait_methods_dispatch_on_the_receiver() {
+        let source = "\
+trait Describe {
+    fn name(self) -> string;
+    fn describe(self) -> string { return \"a \" + self.name(); }
+}
+enum Pet { Cat, Dog }
+impl Describe for Pet {
+    fn name(self) -> string { return match self { Pet::Cat => \"cat\", Pet::Dog => \"dog\" }; }
+}
+impl Describe for int {
+    fn name(self) -> string { return \"number\"; }
+    fn describe(self) -> string { return \"the number \" + to_string(self); }
+}
+fn show(x: dyn Describe) { println(x.describe()); }
+fn main() {
+    show(Pet::Dog);
+    show(7);
+}";
+        let (result, output) = run_with_input(source, "");
+        result.unwrap();
+        assert_eq!(output, "a dog\nthe number 7\n");
+    }
+
+    #[test]
+    fn test_question_mark_returns_early_with_the_failure() {
+        let source = "\
+fn half(n: int) -> Result<int, string> {
+    if n % 2 == 1 { return Result::Err(\"odd\"); }
+    return Result::Ok(n / 2);
+}
+fn quarter(n: int) -> Result<int, string> { return half(half(n)?); }
+async fn eighth(n: int) -> Result<int, string> { let q = quarter(n)?; return half(q); }
+fn first_even(xs: [int]) -> Option<int> {
+    for x in xs { if x % 2 == 0 { return Option::Some(x); } }
+    return Option::None;
+}
+fn twice_first_even(xs: [int]) -> Option<int> { return Option::Some(first_even(xs)? * 2); }
+fn plus_one(n: int) -> int { return n + 1; }
+fn main() -> int {
+    println(unwrap_or(err(quarter(6)), \"none\"));
+    println(unwrap_or(err(await eighth(16)), \"none\"));
+    let none = twice_first_even([1, 3]);
+    return unwrap_or(ok(await eighth(16)), 0)
+        + unwrap_or(map_some(twice_first_even([1, 4]), plus_one), 0)
+        + unwrap_or(none, 100);
+}";
+        let (result, output) = run_with_input(source, "");
+        assert_eq!(result.unwrap(), Value::Integer(111));
+        assert_eq!(output, "odd\nnone\n");
+
+        let (result, _) = run_with_input("let o = Option::Some(1);\nlet n = o?;", "");
+        assert_eq!(
+            result.unwrap_err().message(),
+            "The ? operator can only be used in a function"
+        );
+    }
+
+    #[test]
+    fn test_sized_integers_cast_and_check_overflow() {
+        let source = "\
+fn main() -> int {
+    let a: u8 = 250;
+    println(wrapping_add(a, 10));
+    println(unwrap_or(checked_add(a, 10), 0));
+    println(300 as u8);
+    println(-1 as u8);
+    println(3.9 as i32);
+    println(3000000000.0 as i32);
+    return a as int + 1;
+}";
+        let (result, output) = run_with_input(source, "");
+        assert_eq!(result.unwrap(), Value::Integer(251));
+        assert_eq!(output, "4\n0\n44\n255\n3\n2147483647\n");
+
+        let (result, _) = run_with_input("let a: u8 = 250;\nlet b = a + 10;", "");
+        assert_eq!(result.unwrap_err().message(), "Integer overflow in u8");
+    }
+
+    #[test]
+    fn test_closures_capture_by_reference_or_by_move() {
+        let source = "\
+fn make_adder(n: int) -> fn(int) -> int {
+    return |x| x + n;
+}
+fn main() -> int {
+    let count = 0;
+    let bump = || { count += 1; };
+    bump();
+    bump();
+    let base = 10;
+    let snapshot = move || base;
+    base = 20;
+    let add = make_adder(5);
+    println(count, snapshot(), add(1));
+    let xs = [1, 2, 3, 4];
+    let doubled = collect(map(xs, |x| x * 2));
+    let even = collect(filter(doubled, |x| x % 4 == 0));
+    println(doubled, even);
+    return fold(xs, base, |total, x| total + x);
+}";
+        let (result, output) = run_with_input(source, "");
+        assert_eq!(result.unwrap(), Value::Integer(30));
+        assert_eq!(output, "2 10 6\n[2, 4, 6, 8] [4, 8]\n");
+    }
+
+    #[test]
+    fn test_iterators_are_lazy() {
+        let source = "\
+enum Countdown { From(int) }
+impl Iterator<int> for Countdown {
+    fn next(self) -> Option<Pair<int, Self>> {
+        return match self {
+            Countdown::From(0) => Option::None,
+            Countdown::From(n) => Option::Some(Pair { first: n, second: Countdown::From(n - 1) }),
+        };
+    }
+}
+fn main() -> int {
+    let calls = 0;
+    let squares = map(0..1000000, |n| { calls += 1; n * n });
+    println(collect(take(squares, 3)), calls);
+    for Pair { first, second } in enumerate(\"ab\") { println(first, second); }
+    let total = 0;
+    for n in Countdown::From(3) { total += n; }
+    for i in 1u8..=255u8 { total += 1; }
+    println(collect(zip(Countdown::From(2), 10..20)));
+    return total;
+}";
+        let (result, output) = run_with_input(source, "");
+        assert_eq!(result.unwrap(), Value::Integer(261));
+        assert_eq!(
+            output,
+            "[0, 1, 4] 3\n0 a\n1 b\n[{ first: 2, second: 10 }, { first: 1, second: 11 }]\n"
+        );
+    }
+
+    #[test]
+    fn test_places_destructuring_and_struct_methods() {
+        let source = "\
+struct Point { x: int, y: int }
+impl Point {
+    fn sum(self) -> int { return self.x + self.y; }
+}
+fn main() -> int {
+    let grid = [[Point { x: 0, y: 0 }], [Point { x: 1, y: 1 }]];
+    grid[1][0].x += 10;
+    let i = 0;
+    grid[i][0].y = 5;
+    let (first, (Point { x, y }, label)) = (grid[0][0], (grid[1][0], \"p\"));
+    let pair = (x, label);
+    pair.0 *= 2;
+    println(first, pair, pair.1);
+    return grid[1][0].sum() + pair.0;
+}";
+        let (result, output) = run_with_input(source, "");
+        assert_eq!(result.unwrap(), Value::Integer(11 + 1 + 22));
+        assert_eq!(output, "{ x: 0, y: 5 } (22, p) p\n");
+    }
+}
@@ -1,5 +1,14 @@
+mod interpreter;
+mod numeric;
+mod value;
+
+pub use interpreter::{ExecutionContext, Interpreter};
+pub(crate) use numeric::convert;
+pub use numeric::SizedInt;
+pub use value::{Builtin, BuiltinFn, Function, Payload, Sequence, Value};
+
 use crate::error::IoError as RuntimeError;
-use crate::Result;
+use crate::{parser::parse_source, span::FileId, Result};
 use futures::{
     pin_mut,
     task::{Context, Poll},
@@ -62,222 +71,12 @@ impl Runtime {
                 std::thread::yield_now();
             });
         }
-
-        // Initialize built-in functions
-        let mut context = ExecutionContext::new();
-        context.register_builtin_functions();
     }
 
+    /// Parses and interprets `source`, returning the value of its `main`.
     pub fn run_program(&self, source: &str) -> Result<Value> {
-        let mut context = ExecutionContext::new();
-        self.execute_program(source, &mut context)
-    }
-
-    fn execute_program(&self, source: &str, context: &mut ExecutionContext) -> Result<Value> {
-        // Basic command parsing and execution
-        let commands: Vec<&str> = source
-            .lines()
-            .map(str::trim)
-            .filter(|l| !l.is_empty())
-            .collect();
-
-        let mut last_value = Value::Void;
-
-        for cmd in commands {
-            last_value = self.execute_command(cmd, context)?;
-        }
-
-        Ok(last_value)
-    }
-
-    fn execute_command(&self, cmd: &str, context: &mut ExecutionContext) -> Result<Value> {
-        if cmd.starts_with("println") {
-            let content = self.parse_print_args(cmd)?;
-            println!("{}", content);
-            Ok(Value::Void)
-        } else if cmd.starts_with("let") {
-            self.handle_variable_assignment(cmd, context)
-        } else if cmd.starts_with("if") {
-            self.handle_conditional(cmd, context)
-        } else {
-            self.evaluate_expression(cmd, context)
-        }
-    }
-
-    fn parse_print_args(&self, cmd: &str) -> Result<String> {
-        let content = cmd
-            .trim_start_matches("println")
-            .trim_start_matches('(')
-            .trim_end_matches(')')
-            .trim();
-
-        Ok(content.trim_matches('"').to_string())
-    }
-
-    fn handle_variable_assignment(
-        &self,
-        cmd: &str,
-        context: &mut ExecutionContext,
-    ) -> Result<Value> {
-        let parts: Vec<&str> = cmd.splitn(2, '=').collect();
-        if parts.len() != 2 {
-            return Err(RuntimeError::SyntaxError("Invalid assignment".into()));
-        }
-
-        let var_name = parts[0].trim_start_matches("let").trim().to_string();
-        let value = self.evaluate_expression(parts[1].trim(), context)?;
-
-        context.variables.insert(var_name, value.clone());
-        Ok(value)
-    }
-
-    fn evaluate_expression(&self, expr: &str, context: &ExecutionContext) -> Result<Value> {
-        if expr.starts_with("read_line") {
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
-            Ok(Value::String(input.trim().to_string()))
-        } else if let Ok(num) = expr.parse::<f64>() {
-            Ok(Value::Number(num))
-        } else if expr.starts_with('"') && expr.ends_with('"') {
-            Ok(Value::String(expr.trim_matches('"').to_string()))
-        } else if let Some(value) = context.variables.get(expr) {
-            Ok(value.clone())
-        } else {
-            self.evaluate_operation(expr, context)
-        }
-    }
-
-    fn evaluate_operation(&self, expr: &str, context: &ExecutionContext) -> Result<Value> {
-        let tokens: Vec<&str> = expr.split_whitespace().collect();
-        if tokens.len() != 3 {
-            return Err(RuntimeError::SyntaxError("Invalid operation".into()));
-        }
-
-        let left = self.evaluate_expression(tokens[0], context)?;
-        let right = self.evaluate_expression(tokens[2], context)?;
-
-        match tokens[1] {
-            "+" => self.add_values(left, right),
-            "-" => self.subtract_values(left, right),
-            "*" => self.multiply_values(left, right),
-            "/" => self.divide_values(left, right),
-            _ => Err(RuntimeError::SyntaxError("Unknown operator".into())),
-        }
-    }
-
-    fn add_values(&self, left: Value, right: Value) -> Result<Value> {
-        match (left, right) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
-            (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
-            _ => Err(RuntimeError::TypeError("Invalid types for addition".into())),
-        }
-    }
-
-    fn subtract_values(&self, left: Value, right: Value) -> Result<Value> {
-        match (left, right) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
-            _ => Err(RuntimeError::TypeError(
-                "Invalid types for subtraction".into(),
-            )),
-        }
-    }
-
-    fn multiply_values(&self, left: Value, right: Value) -> Result<Value> {
-        match (left, right) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
-            _ => Err(RuntimeError::TypeError(
-                "Invalid types for multiplication".into(),
-            )),
-        }
-    }
-
-    fn divide_values(&self, left: Value, right: Value) -> Result<Value> {
-        match (left, right) {
-            (Value::Number(a), Value::Number(b)) => {
-                if b == 0.0 {
-                    Err(RuntimeError::RuntimeError("Division by zero".into()))
-                } else {
-                    Ok(Value::Number(a / b))
-                }
-            }
-            _ => Err(RuntimeError::TypeError("Invalid types for division".into())),
-        }
-    }
-
-    fn handle_conditional(&self, cmd: &str, context: &mut ExecutionContext) -> Result<Value> {
-        let condition_start = cmd.find("if").unwrap() + 2;
-        let condition_end = cmd.find("{").unwrap_or_else(|| cmd.len());
-        let condition = cmd[condition_start..condition_end].trim();
-
-        let condition_value = self.evaluate_expression(condition, context)?;
-        match condition_value {
-            Value::Number(n) => Ok(Value::Number(n)),
-            Value::Boolean(b) => Ok(Value::Boolean(b)),
-            _ => Err(RuntimeError::TypeError(
-                "Condition must evaluate to a boolean".into(),
-            )),
-        }
-    }
-
-    fn handle_loop(&self, cmd: &str, context: &mut ExecutionContext) -> Result<Value> {
-        let loop_type = if cmd.starts_with("while") {
-            "while"
-        } else {
-            "for"
-        };
-        let body_start = cmd
-            .find("{")
-            .ok_or_else(|| RuntimeError::SyntaxError("Missing loop body".into()))?;
-        let body = &cmd[body_start + 1..cmd.len() - 1];
-
-        match loop_type {
-            "while" => self.execute_while_loop(&cmd[5..body_start].trim(), body, context),
-            "for" => self.execute_for_loop(&cmd[3..body_start].trim(), body, context),
-            _ => unreachable!(),
-        }
-    }
-
-    fn execute_while_loop(
-        &self,
-        condition: &str,
-        body: &str,
-        context: &mut ExecutionContext,
-    ) -> Result<Value> {
-        while self.evaluate_expression(condition, context)?.is_truthy() {
-            self.execute_block(body, context)?;
-        }
-        Ok(Value::Void)
-    }
-
-    fn execute_for_loop(
-        &self,
-        setup: &str,
-        body: &str,
-        context: &mut ExecutionContext,
-    ) -> Result<Value> {
-        let parts: Vec<&str> = setup.split(";").collect();
-        if parts.len() != 3 {
-            return Err(RuntimeError::SyntaxError("Invalid for loop syntax".into()));
-        }
-
-        // Initialize
-        self.execute_command(parts[0], context)?;
-
-        // Condition and iteration
-        while self.evaluate_expression(parts[1], context)?.is_truthy() {
-            self.execute_block(body, context)?;
-            self.execute_command(parts[2], context)?;
-        }
-
-        Ok(Value::Void)
-    }
-
-    fn execute_block(&self, block: &str, context: &mut ExecutionContext) -> Result<Value> {
-        let mut last_value = Value::Void;
-        for cmd in block.split(";").map(str::trim).filter(|s| !s.is_empty()) {
-            last_value = self.execute_command(cmd, context)?;
-        }
-        Ok(last_value)
+        let program = parse_source(source, FileId(0))?;
+        Interpreter::new().run(&program)
     }
 
     fn enter(&self) -> RuntimeGuard {
@@ -563,106 +362,6 @@ impl<T> Future for JoinHandle<T> {
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Value {
-    Number(f64),
-    String(String),
-    Array(Vec<Value>),
-    Void,
-    BuiltinFunction(Box<dyn Fn(Vec<Value>) -> Result<Value> + Send + Sync>),
-    Boolean(bool),
-    Object(HashMap<String, Value>),
-}
-
-impl Value {
-    fn is_truthy(&self) -> bool {
-        match self {
-            Value::Number(n) => *n != 0.0,
-            Value::Boolean(b) => *b,
-            Value::String(s) => !s.is_empty(),
-            Value::Array(arr) => !arr.is_empty(),
-            Value::Void => false,
-            Value::BuiltinFunction(_) => true,
-            Value::Object(obj) => !obj.is_empty(),
-        }
-    }
-
-    fn to_string(&self) -> String {
-        match self {
-            Value::Number(n) => n.to_string(),
-            Value::Boolean(b) => b.to_string(),
-            Value::String(s) => s.clone(),
-            Value::Array(arr) => format!(
-                "[{}]",
-                arr.iter()
-                    .map(|v| v.to_string())
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            ),
-            Value::Void => "void".to_string(),
-            Value::BuiltinFunction(_) => "<function>".to_string(),
-            Value::Object(obj) => format!("{:?}", obj),
-        }
-    }
-}
-
-struct ExecutionContext {
-    variables: HashMap<String, Value>,
-}
-
-impl ExecutionContext {
-    fn new() -> Self {
-        Self {
-            variables: HashMap::new(),
-        }
-    }
-
-    fn register_builtin_functions(&mut self) {
-        // Register built-in functions
-        self.variables.insert(
-            "to_string".to_string(),
-            Value::BuiltinFunction(Box::new(|args| {
-                if let Some(arg) = args.get(0) {
-                    Ok(Value::String(format!("{:?}", arg)))
-                } else {
-                    Err(RuntimeError::RuntimeError(
-                        "to_string requires one argument".into(),
-                    ))
-                }
-            })),
-        );
-
-        self.variables.insert(
-            "parse_int".to_string(),
-            Value::BuiltinFunction(Box::new(|args| {
-                if let Some(Value::String(s)) = args.get(0) {
-                    match s.parse::<f64>() {
-                        Ok(n) => Ok(Value::Number(n)),
-                        Err(_) => Err(RuntimeError::RuntimeError("Failed to parse integer".into())),
-                    }
-                } else {
-                    Err(RuntimeError::TypeError(
-                        "parse_int requires a string argument".into(),
-                    ))
-                }
-            })),
-        );
-
-        self.variables.insert(
-            "len".to_string(),
-            Value::BuiltinFunction(Box::new(|args| {
-                if let Some(Value::Array(arr)) = args.get(0) {
-                    Ok(Value::Number(arr.len() as f64))
-                } else {
-                    Err(RuntimeError::TypeError(
-                        "len requires an array argument".into(),
-                    ))
-                }
-            })),
-        );
-    }
-}
-
 // Add comprehensive error types
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum ErrorCategory {
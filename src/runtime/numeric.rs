@@ -0,0 +1,392 @@
+//! Integers of the sized types. `int` and `i64` values are `Value::Integer`,
+//! checked for overflow at 64 bits; every other integer type is a `SizedInt`,
+//! checked at its own width. Overflow is an error, as in a debug build, unless
+//! a program asks for wrapping with the `wrapping_*` intrinsics.
+
+use super::value::{Payload, Sequence, Value};
+use crate::{
+    ast::{BinaryOperator, Type},
+    error::IoError,
+    Result,
+};
+use std::cmp::Ordering;
+
+/// An integer of a sized type, held as its two's complement bits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizedInt {
+    bits: u128,
+    ty: Type,
+}
+
+impl SizedInt {
+    /// `value` as an integer of type `ty`, if it is in range.
+    pub fn new(value: i128, ty: Type) -> Option<Self> {
+        ty.fits_integer(value)
+            .then(|| Self::wrapping(value as u128, ty))
+    }
+
+    /// The low bits of `bits` as an integer of type `ty`, dropping the rest.
+    pub fn wrapping(bits: u128, ty: Type) -> Self {
+        let width = ty.int_bits().unwrap_or(128);
+        let mask = u128::MAX >> (128 - width);
+        Self {
+            bits: bits & mask,
+            ty,
+        }
+    }
+
+    /// `value` converted to integer type `ty`, saturating at the bounds of the
+    /// type; NaN becomes 0.
+    pub fn saturating_from_f64(value: f64, ty: Type) -> Self {
+        let (min, max) = ty.int_range().unwrap_or((i128::MIN, u128::MAX));
+        if ty.is_signed() {
+            // `as` saturates at the bounds of i128 and maps NaN to 0.
+            let value = (value as i128).clamp(min, max as i128);
+            Self::wrapping(value as u128, ty)
+        } else {
+            Self::wrapping((value as u128).min(max), ty)
+        }
+    }
+
+    pub fn ty(&self) -> &Type {
+        &self.ty
+    }
+
+    /// The name of the value's type, for runtime error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self.ty {
+            Type::I8 => "i8",
+            Type::I16 => "i16",
+            Type::I128 => "i128",
+            Type::Isize => "isize",
+            Type::U8 => "u8",
+            Type::U16 => "u16",
+            Type::U32 => "u32",
+            Type::U64 => "u64",
+            Type::U128 => "u128",
+            Type::Usize => "usize",
+            _ => "int",
+        }
+    }
+
+    /// The value's bits extended to 128 bits by its signedness.
+    fn extended(&self) -> u128 {
+        match self.ty.int_bits() {
+            Some(width) if self.ty.is_signed() && width < 128 => {
+                let shift = 128 - width;
+                (((self.bits << shift) as i128) >> shift) as u128
+            }
+            _ => self.bits,
+        }
+    }
+
+    /// The value, if it fits in an `i128`; only large `u128` values don't.
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.ty.is_signed() {
+            Some(self.extended() as i128)
+        } else {
+            i128::try_from(self.bits).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        if self.ty.is_signed() {
+            self.extended() as i128 as f64
+        } else {
+            self.bits as f64
+        }
+    }
+
+    /// The value converted to integer type `ty` with `as`: sign- or
+    /// zero-extended by the source's signedness, then truncated.
+    pub fn cast(&self, ty: Type) -> Self {
+        Self::wrapping(self.extended(), ty)
+    }
+
+    /// `self op rhs` if the result is in range; `None` on overflow or division
+    /// by zero.
+    pub fn checked(&self, op: &BinaryOperator, rhs: &Self) -> Option<Self> {
+        use BinaryOperator::*;
+
+        if self.ty.is_signed() {
+            let (l, r) = (self.extended() as i128, rhs.extended() as i128);
+            let value = match op {
+                Add => l.checked_add(r),
+                Subtract => l.checked_sub(r),
+                Multiply => l.checked_mul(r),
+                Divide => l.checked_div(r),
+                Modulo => l.checked_rem(r),
+                _ => None,
+            }?;
+            Self::new(value, self.ty.clone())
+        } else {
+            let (l, r) = (self.bits, rhs.bits);
+            let value = match op {
+                Add => l.checked_add(r),
+                Subtract => l.checked_sub(r),
+                Multiply => l.checked_mul(r),
+                Divide => l.checked_div(r),
+                Modulo => l.checked_rem(r),
+                _ => None,
+            }?;
+            let fits = self.ty.int_range().is_some_and(|(_, max)| value <= max);
+            fits.then(|| Self::wrapping(value, self.ty.clone()))
+        }
+    }
+
+    /// `self op rhs` wrapped around at the bounds of the type, for `+`, `-`
+    /// and `*`.
+    pub fn wrapping_op(&self, op: &BinaryOperator, rhs: &Self) -> Self {
+        let bits = match op {
+            BinaryOperator::Add => self.bits.wrapping_add(rhs.bits),
+            BinaryOperator::Subtract => self.bits.wrapping_sub(rhs.bits),
+            _ => self.bits.wrapping_mul(rhs.bits),
+        };
+        Self::wrapping(bits, self.ty.clone())
+    }
+
+    /// `-self`, failing for the minimum of a signed type and for unsigned ones.
+    pub fn checked_neg(&self) -> Option<Self> {
+        if !self.ty.is_signed() {
+            return None;
+        }
+        Self::new((self.extended() as i128).checked_neg()?, self.ty.clone())
+    }
+
+    /// Applies a binary operator to two integers of the same type; `None` if it
+    /// doesn't apply to integers.
+    pub fn operation(&self, op: &BinaryOperator, rhs: &Self) -> Result<Option<Value>> {
+        use BinaryOperator::*;
+
+        let overflow = || IoError::runtime_error(format!("Integer overflow in {}", self.ty));
+        if matches!(op, Divide | Modulo) && rhs.bits == 0 {
+            return Err(IoError::runtime_error("Division by zero"));
+        }
+        let ty = self.ty.clone();
+        let value = match op {
+            Add | Subtract | Multiply | Divide | Modulo => {
+                Value::from(self.checked(op, rhs).ok_or_else(overflow)?)
+            }
+            BitwiseAnd => Value::from(Self::wrapping(self.bits & rhs.bits, ty)),
+            BitwiseOr => Value::from(Self::wrapping(self.bits | rhs.bits, ty)),
+            BitwiseXor => Value::from(Self::wrapping(self.bits ^ rhs.bits, ty)),
+            LeftShift | RightShift => {
+                let width = ty.int_bits().unwrap_or(128);
+                let shift = rhs
+                    .to_i128()
+                    .and_then(|shift| u32::try_from(shift).ok())
+                    .filter(|shift| *shift < width)
+                    .ok_or_else(|| {
+                        IoError::runtime_error(format!("Invalid shift amount {}", rhs))
+                    })?;
+                // Right shifts of signed values keep the sign.
+                let bits = match op {
+                    LeftShift => self.bits << shift,
+                    _ if ty.is_signed() => ((self.extended() as i128) >> shift) as u128,
+                    _ => self.bits >> shift,
+                };
+                Value::from(Self::wrapping(bits, ty))
+            }
+            LessThan => Value::Boolean(self < rhs),
+            LessThanEqual => Value::Boolean(self <= rhs),
+            GreaterThan => Value::Boolean(self > rhs),
+            GreaterThanEqual => Value::Boolean(self >= rhs),
+            Range | RangeInclusive => {
+                let bound = |n: &Self| n.to_i128().ok_or_else(overflow);
+                let (start, end) = (bound(self)?, bound(rhs)?);
+                Value::Iterator(Box::new(Sequence::Range {
+                    next: start,
+                    end: if *op == Range { end } else { end + 1 },
+                    ty: Some(ty),
+                }))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
+
+impl PartialOrd for SizedInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.ty != other.ty {
+            return None;
+        }
+        Some(if self.ty.is_signed() {
+            (self.extended() as i128).cmp(&(other.extended() as i128))
+        } else {
+            self.bits.cmp(&other.bits)
+        })
+    }
+}
+
+impl std::fmt::Display for SizedInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ty.is_signed() {
+            write!(f, "{}", self.extended() as i128)
+        } else {
+            write!(f, "{}", self.bits)
+        }
+    }
+}
+
+/// `int` and `i64` values are plain integers.
+impl From<SizedInt> for Value {
+    fn from(n: SizedInt) -> Self {
+        match n.ty {
+            Type::I32 | Type::I64 => Value::Integer(n.extended() as i128 as i64),
+            _ => Value::Sized(n),
+        }
+    }
+}
+
+/// `value` as an integer of type `ty`, or `None` if it isn't an integer.
+/// Plain integers are treated as `i64`, so converting one wraps around.
+pub fn as_sized(value: &Value, ty: Type) -> Option<SizedInt> {
+    match value {
+        Value::Integer(n) => Some(SizedInt::wrapping(*n as i128 as u128, Type::I64).cast(ty)),
+        Value::Sized(n) => Some(n.cast(ty)),
+        _ => None,
+    }
+}
+
+/// A value of the declared type `ty`: a plain integer becomes a sized one if
+/// it fits, and an `f32` loses the precision it doesn't have. The elements of
+/// a tuple are converted to theirs.
+pub fn convert(value: Value, ty: &Type) -> Result<Value> {
+    match (value, ty) {
+        (Value::Tuple(values), Type::Tuple(types)) if values.len() == types.len() => values
+            .into_iter()
+            .zip(types)
+            .map(|(value, ty)| convert(value, ty))
+            .collect::<Result<_>>()
+            .map(Value::Tuple),
+        (Value::Integer(n), ty) if ty.is_integer() && !matches!(ty, Type::I32 | Type::I64) => {
+            SizedInt::new(n as i128, ty.clone())
+                .map(Value::Sized)
+                .ok_or_else(|| {
+                    IoError::runtime_error(format!("Integer {} is out of range for {}", n, ty))
+                })
+        }
+        (Value::Float(n), Type::F32) => Ok(Value::Float(n as f32 as f64)),
+        (value, _) => Ok(value),
+    }
+}
+
+/// `value as ty`. Integers are truncated to the target's width, floats
+/// saturate at the bounds of an integer target, with NaN becoming 0.
+pub fn cast(value: Value, ty: &Type) -> Result<Value> {
+    let integer = match &value {
+        Value::Integer(n) => Some(SizedInt::wrapping(*n as i128 as u128, Type::I64)),
+        Value::Sized(n) => Some(n.clone()),
+        Value::Boolean(b) => Some(SizedInt::wrapping(*b as u128, Type::U8)),
+        Value::Char(c) => Some(SizedInt::wrapping(*c as u128, Type::U32)),
+        _ => None,
+    };
+    let converted = match (integer, &value, ty) {
+        (Some(n), _, ty) if ty.is_integer() => Some(Value::from(n.cast(ty.clone()))),
+        (Some(n), _, ty) if ty.is_float() => convert(Value::Float(n.to_f64()), ty).ok(),
+        (Some(n), _, Type::Char) if *n.ty() == Type::U8 => {
+            Some(Value::Char(char::from(n.bits as u8)))
+        }
+        (None, Value::Float(f), ty) if ty.is_integer() => {
+            Some(Value::from(SizedInt::saturating_from_f64(*f, ty.clone())))
+        }
+        (None, Value::Float(_), ty) if ty.is_float() => convert(value.clone(), ty).ok(),
+        _ => None,
+    };
+    converted.ok_or_else(|| {
+        IoError::runtime_error(format!(
+            "Cannot convert a value of type {} to {}",
+            value.type_name(),
+            ty
+        ))
+    })
+}
+
+/// `wrapping_add(a, b)` and the other integer intrinsics, on two integers
+/// of the same type.
+pub fn intrinsic(name: &str, left: &Value, right: &Value) -> Result<Value> {
+    let ty = match (left, right) {
+        (Value::Sized(n), _) | (_, Value::Sized(n)) => n.ty().clone(),
+        _ => Type::I64,
+    };
+    let (Some(l), Some(r)) = (as_sized(left, ty.clone()), as_sized(right, ty)) else {
+        return Err(IoError::runtime_error(format!(
+            "{} requires integer arguments, got {} and {}",
+            name,
+            left.type_name(),
+            right.type_name()
+        )));
+    };
+    let op = match name.rsplit('_').next() {
+        Some("add") => BinaryOperator::Add,
+        Some("sub") => BinaryOperator::Subtract,
+        _ => BinaryOperator::Multiply,
+    };
+    if name.starts_with("wrapping_") {
+        return Ok(Value::from(l.wrapping_op(&op, &r)));
+    }
+    let (variant, payload) = match l.checked(&op, &r) {
+        Some(n) => ("Some", Payload::Tuple(vec![Value::from(n)])),
+        None => ("None", Payload::Unit),
+    };
+    Ok(Value::Variant {
+        enum_name: "Option".to_string(),
+        variant: variant.to_string(),
+        payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized(value: i128, ty: Type) -> SizedInt {
+        SizedInt::new(value, ty).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_is_checked_at_the_width_of_the_type() {
+        let add =
+            |l, r, ty: Type| sized(l, ty.clone()).checked(&BinaryOperator::Add, &sized(r, ty));
+        assert_eq!(add(100, 27, Type::I8), Some(sized(127, Type::I8)));
+        assert_eq!(add(100, 28, Type::I8), None);
+        assert_eq!(add(255, 1, Type::U8), None);
+        assert_eq!(
+            sized(-128, Type::I8).checked(&BinaryOperator::Divide, &sized(-1, Type::I8)),
+            None
+        );
+        assert_eq!(
+            sized(250, Type::U8).wrapping_op(&BinaryOperator::Add, &sized(10, Type::U8)),
+            sized(4, Type::U8)
+        );
+    }
+
+    #[test]
+    fn test_casts_truncate_extend_and_saturate() {
+        assert_eq!(sized(-1, Type::I8).cast(Type::U16), sized(65535, Type::U16));
+        assert_eq!(sized(255, Type::U8).cast(Type::I32), sized(255, Type::I32));
+        assert_eq!(sized(300, Type::I32).cast(Type::U8), sized(44, Type::U8));
+        assert_eq!(
+            SizedInt::saturating_from_f64(1e10, Type::I32),
+            sized(i32::MAX as i128, Type::I32)
+        );
+        assert_eq!(
+            SizedInt::saturating_from_f64(-3.7, Type::U8),
+            sized(0, Type::U8)
+        );
+        assert_eq!(
+            SizedInt::saturating_from_f64(f64::NAN, Type::I16),
+            sized(0, Type::I16)
+        );
+        assert_eq!(
+            cast(Value::Integer(65), &Type::U8).unwrap(),
+            Value::Sized(sized(65, Type::U8))
+        );
+        assert_eq!(
+            cast(Value::Float(2.9), &Type::I64).unwrap(),
+            Value::Integer(2)
+        );
+        assert!(cast(Value::String("1".into()), &Type::I32).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use super::{interpreter::Interpreter, numeric::SizedInt};
+use crate::{
+    ast::{ASTNode, Parameter, Type},
+    Result,
+};
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+/// Native implementation of a builtin; receives the interpreter for I/O.
+pub type BuiltinFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value>;
+
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    /// `None` accepts any number of arguments.
+    pub arity: Option<usize>,
+    pub func: BuiltinFn,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<builtin {}>", self.name)
+    }
+}
+
+/// A function declared in Io source, or a closure.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    /// What returned values are converted to, if declared.
+    pub return_type: Option<Type>,
+    pub body: Vec<ASTNode>,
+    /// The variables a closure captured, each shared with the scope it was
+    /// created in, or a copy if the closure is `move`.
+    pub captures: Vec<(String, Rc<RefCell<Value>>)>,
+}
+
+/// The fields a variant was built with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    Unit,
+    Tuple(Vec<Value>),
+    Named(HashMap<String, Value>),
+}
+
+/// What an iterator has left to produce. Adapters wrap the sequence they
+/// draw from, so no element is computed before a loop or `collect` asks for it.
+#[derive(Debug, Clone)]
+pub enum Sequence {
+    /// The integers from `next` up to `end`, exclusive; `ty` is set for a
+    /// range of a sized type.
+    Range {
+        next: i128,
+        end: i128,
+        ty: Option<Type>,
+    },
+    /// The elements of an array or string.
+    Items(std::vec::IntoIter<Value>),
+    /// A value whose type implements `Iterator`, whose `next` gives its
+    /// current element paired with the value holding the rest.
+    User(Value),
+    Map(Box<Sequence>, Value),
+    Filter(Box<Sequence>, Value),
+    Zip(Box<Sequence>, Box<Sequence>),
+    /// Pairs each element with its index, counting from the `i64`.
+    Enumerate(Box<Sequence>, i64),
+    /// At most the `usize` elements remaining.
+    Take(Box<Sequence>, usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum Value {
+    /// An `int` or `i64`.
+    Integer(i64),
+    /// An integer of any other type, like `u8`.
+    Sized(SizedInt),
+    Float(f64),
+    Boolean(bool),
+    Char(char),
+    String(String),
+    Array(Vec<Value>),
+    /// `(a, b)`
+    Tuple(Vec<Value>),
+    /// A value of the struct `name`, by field name.
+    Object {
+        name: String,
+        fields: HashMap<String, Value>,
+    },
+    Function(Rc<Function>),
+    BuiltinFunction(Builtin),
+    /// A lazy `Iter<T>`, like a range or the result of `map`.
+    Iterator(Box<Sequence>),
+    /// A value of an enum, e.g. `Shape::Circle(1.0)`.
+    Variant {
+        enum_name: String,
+        variant: String,
+        payload: Payload,
+    },
+    /// A tuple variant used as a function that builds it.
+    VariantConstructor {
+        enum_name: String,
+        variant: String,
+        arity: usize,
+    },
+    #[default]
+    Void,
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Integer(n) => *n != 0,
+            Value::Sized(n) => n.to_f64() != 0.0,
+            Value::Float(n) => *n != 0.0,
+            Value::Boolean(b) => *b,
+            Value::Char(_) => true,
+            Value::String(s) => !s.is_empty(),
+            Value::Array(arr) => !arr.is_empty(),
+            Value::Tuple(_) => true,
+            Value::Object { fields, .. } => !fields.is_empty(),
+            Value::Function(_) | Value::BuiltinFunction(_) => true,
+            Value::Iterator(_) => true,
+            Value::Variant { .. } | Value::VariantConstructor { .. } => true,
+            Value::Void => false,
+        }
+    }
+
+    /// Name of the value's type as it appears in runtime error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "int",
+            Value::Sized(n) => n.type_name(),
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "bool",
+            Value::Char(_) => "char",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Tuple(_) => "tuple",
+            Value::Object { .. } => "object",
+            Value::Function(_) | Value::BuiltinFunction(_) => "function",
+            Value::VariantConstructor { .. } => "function",
+            Value::Iterator(_) => "iterator",
+            Value::Variant { .. } => "enum",
+            Value::Void => "void",
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Integer(l), Value::Integer(r)) => l == r,
+            (Value::Sized(l), Value::Sized(r)) => l == r,
+            (Value::Integer(l), Value::Sized(r)) | (Value::Sized(r), Value::Integer(l)) => {
+                r.to_i128() == Some(*l as i128)
+            }
+            (Value::Float(l), Value::Float(r)) => l == r,
+            (Value::Integer(l), Value::Float(r)) | (Value::Float(r), Value::Integer(l)) => {
+                *l as f64 == *r
+            }
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Char(l), Value::Char(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Array(l), Value::Array(r)) | (Value::Tuple(l), Value::Tuple(r)) => l == r,
+            (
+                Value::Object { name, fields },
+                Value::Object {
+                    name: other_name,
+                    fields: other_fields,
+                },
+            ) => name == other_name && fields == other_fields,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::BuiltinFunction(l), Value::BuiltinFunction(r)) => l.name == r.name,
+            (
+                Value::Variant {
+                    enum_name,
+                    variant,
+                    payload,
+                },
+                Value::Variant {
+                    enum_name: other_enum,
+                    variant: other_variant,
+                    payload: other_payload,
+                },
+            ) => enum_name == other_enum && variant == other_variant && payload == other_payload,
+            (
+                Value::VariantConstructor {
+                    enum_name, variant, ..
+                },
+                Value::VariantConstructor {
+                    enum_name: other_enum,
+                    variant: other_variant,
+                    ..
+                },
+            ) => enum_name == other_enum && variant == other_variant,
+            (Value::Void, Value::Void) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Sized(n) => write!(f, "{}", n),
+            // Keep a trailing `.0` so floats don't read back as integers.
+            Value::Float(n) if n.is_finite() && n.fract() == 0.0 => write!(f, "{:.1}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(arr) => {
+                write!(f, "[")?;
+                for (i, value) in arr.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                if values.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Object { fields, .. } => write_fields(f, fields),
+            Value::Variant {
+                enum_name,
+                variant,
+                payload,
+            } => {
+                write!(f, "{}::{}", enum_name, variant)?;
+                match payload {
+                    Payload::Unit => Ok(()),
+                    Payload::Tuple(values) => {
+                        write!(f, "(")?;
+                        for (i, value) in values.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, ", ")?;
+                            }
+                            write!(f, "{}", value)?;
+                        }
+                        write!(f, ")")
+                    }
+                    Payload::Named(fields) => {
+                        write!(f, " ")?;
+                        write_fields(f, fields)
+                    }
+                }
+            }
+            Value::VariantConstructor {
+                enum_name, variant, ..
+            } => write!(f, "<fn {}::{}>", enum_name, variant),
+            Value::Function(func) => write!(f, "<fn {}>", func.name),
+            Value::BuiltinFunction(builtin) => write!(f, "<builtin {}>", builtin.name),
+            Value::Iterator(_) => write!(f, "<iterator>"),
+            Value::Void => write!(f, "void"),
+        }
+    }
+}
+
+/// `{ a: 1, b: 2 }`, with fields in name order.
+fn write_fields(f: &mut fmt::Formatter<'_>, fields: &HashMap<String, Value>) -> fmt::Result {
+    let mut fields: Vec<_> = fields.iter().collect();
+    fields.sort_by_key(|(name, _)| *name);
+    write!(f, "{{")?;
+    for (i, (name, value)) in fields.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, " {}: {}", name, value)?;
+    }
+    write!(f, " }}")
+}
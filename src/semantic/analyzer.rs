@@ -1,36 +1,314 @@
+use super::resolve::{Resolution, SymbolId};
 use crate::{
-    ast::{ASTNode, ASTVisitor, BinaryOperator, Parameter},
+    ast::{
+        ASTNode, BinaryOperator, Literal, MatchArm, NodeId, Parameter, Pattern, PatternFields,
+        TraitMethod, Type, UnaryOperator, Variant, VariantFields,
+    },
     error::IoError,
-    symbol_table::{Scope, Symbol},
-    types::Type,
+    stdlib::prelude,
+    visitor::{walk_node, walk_nodes, Visitor},
     Result,
 };
+use std::collections::HashMap;
 
-pub struct SemanticAnalyzer {
-    current_scope: Box<Scope>,
-    in_loop: bool,
+pub struct SemanticAnalyzer<'r> {
+    /// What each identifier of the prelude and the program refers to.
+    resolution: &'r Resolution,
+    /// The type of each symbol declared so far.
+    types: HashMap<SymbolId, Type>,
     in_function: bool,
     return_type: Option<Type>,
+    /// Type parameters and variants of each enum declared so far.
+    enums: HashMap<String, (Vec<String>, Vec<Variant>)>,
+    /// Methods of each trait declared in the program.
+    traits: HashMap<String, (Vec<String>, Vec<TraitMethod>)>,
+    /// Traits implemented by each type, keyed by the type's name.
+    impls: HashMap<String, Vec<String>>,
+    /// The type arguments of each implementation of a generic trait, keyed by
+    /// the type's name and the trait.
+    impl_args: HashMap<(String, String), Vec<Type>>,
+    /// Trait bounds on each generic function's type parameters.
+    function_bounds: HashMap<String, Vec<(String, String)>>,
+    /// Bounds on the type parameters in scope.
+    bounds: Vec<(String, String)>,
 }
 
-impl SemanticAnalyzer {
-    pub fn new() -> Self {
-        Self {
-            current_scope: Box::new(Scope::new()),
-            in_loop: false,
+impl<'r> SemanticAnalyzer<'r> {
+    /// An analyzer for a program `resolution` resolved after the prelude.
+    pub fn new(resolution: &'r Resolution) -> Self {
+        let mut analyzer = Self {
+            resolution,
+            types: HashMap::new(),
             in_function: false,
             return_type: None,
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            impls: HashMap::new(),
+            impl_args: HashMap::new(),
+            function_bounds: HashMap::new(),
+            bounds: Vec::new(),
+        };
+        analyzer
+            .visit_program(prelude::items())
+            .expect("the prelude is well-formed");
+        analyzer
+    }
+
+    /// Gives the symbol the function, parameter, `let` or pattern binding
+    /// `node` declares its type.
+    fn declare(&mut self, node: NodeId, ty: Type) {
+        if let Some(symbol) = self.resolution.declared(node) {
+            self.types.insert(symbol, ty);
+        }
+    }
+
+    /// The type of the identifier `node`'s symbol, `Unknown` for a const not
+    /// yet analyzed.
+    fn identifier_type(&self, name: &str, node: NodeId) -> Result<Type> {
+        let symbol = self
+            .resolution
+            .resolved(node)
+            .ok_or_else(|| IoError::type_error(format!("Undefined variable {}", name)))?;
+        Ok(self.types.get(&symbol).cloned().unwrap_or(Type::Unknown))
+    }
+
+    /// Whether the callee `node` names a builtin, which no binding of the
+    /// program hides.
+    fn is_builtin(&self, node: NodeId) -> bool {
+        self.resolution.resolved(node).is_none()
+    }
+
+    fn analyze(&mut self, node: &ASTNode) -> Result<Type> {
+        self.visit_node(node)
+    }
+
+    fn expect_bool(&mut self, condition: &ASTNode, what: &str) -> Result<()> {
+        let cond_type = self.analyze(condition)?;
+        if cond_type != Type::Bool {
+            return Err(IoError::type_error(format!(
+                "{} condition must be a boolean expression, found {}",
+                what, cond_type
+            ))
+            .with_span(condition.span()));
+        }
+        Ok(())
+    }
+
+    /// The type of an enum's values, generic over its own type parameters.
+    fn enum_type(name: &str, type_params: &[String]) -> Type {
+        if type_params.is_empty() {
+            return Type::Named(name.to_string());
+        }
+        Type::Generic {
+            name: name.to_string(),
+            args: type_params.iter().cloned().map(Type::Param).collect(),
+        }
+    }
+
+    /// Field names and types of the variant or struct a pattern names, when
+    /// matched against a value of type `ty`; empty if unknown.
+    fn variant_fields(&self, path: &str, ty: &Type) -> Vec<(String, Type)> {
+        let (enum_name, variant_name) = match (path.rsplit_once("::"), ty) {
+            (Some((enum_name, variant)), _) => (enum_name, variant),
+            (None, Type::Named(name) | Type::Generic { name, .. }) => (name.as_str(), path),
+            (None, _) => return Vec::new(),
+        };
+        let Some((type_params, variants)) = self.enums.get(enum_name) else {
+            return Vec::new();
+        };
+        let Some(variant) = variants.iter().find(|variant| variant.name == variant_name) else {
+            return Vec::new();
+        };
+        let bindings: HashMap<String, Type> = match ty {
+            Type::Generic { name, args } if name == enum_name => {
+                type_params.iter().cloned().zip(args.iter().cloned()).collect()
+            }
+            _ => HashMap::new(),
+        };
+        match &variant.fields {
+            VariantFields::Unit => Vec::new(),
+            VariantFields::Tuple(types) => types
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (i.to_string(), ty.substitute(&bindings)))
+                .collect(),
+            VariantFields::Named(fields) => fields
+                .iter()
+                .map(|field| (field.name.clone(), field.type_annotation.substitute(&bindings)))
+                .collect(),
+        }
+    }
+
+    /// Types the names `pattern` binds from the value it matches, where that
+    /// is known.
+    fn bind_pattern(&mut self, pattern: &Pattern, ty: &Type) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Literal { .. } => Ok(()),
+            Pattern::Binding { id, .. } => {
+                self.declare(*id, ty.clone());
+                Ok(())
+            }
+            Pattern::Array { elements, .. } => {
+                let elem_type = match ty {
+                    Type::Array { elem_type, .. } => elem_type.as_ref().clone(),
+                    _ => Type::Unknown,
+                };
+                for element in elements {
+                    self.bind_pattern(element, &elem_type)?;
+                }
+                Ok(())
+            }
+            Pattern::Tuple { elements, .. } => {
+                for (i, element) in elements.iter().enumerate() {
+                    let elem_type = match ty {
+                        Type::Tuple(types) => types.get(i).cloned().unwrap_or(Type::Unknown),
+                        _ => Type::Unknown,
+                    };
+                    self.bind_pattern(element, &elem_type)?;
+                }
+                Ok(())
+            }
+            // Every alternative binds the same names.
+            Pattern::Or { alternatives, .. } => match alternatives.first() {
+                Some(first) => self.bind_pattern(first, ty),
+                None => Ok(()),
+            },
+            Pattern::Struct { path, fields, .. } => {
+                let field_types = self.variant_fields(path, ty);
+                let field_type = |name: &str| {
+                    field_types
+                        .iter()
+                        .find(|(field, _)| field == name)
+                        .map_or(Type::Unknown, |(_, ty)| ty.clone())
+                };
+                match fields {
+                    PatternFields::Unit => Ok(()),
+                    PatternFields::Tuple(patterns) => {
+                        for (i, pattern) in patterns.iter().enumerate() {
+                            self.bind_pattern(pattern, &field_type(&i.to_string()))?;
+                        }
+                        Ok(())
+                    }
+                    PatternFields::Named { fields, .. } => {
+                        for (name, pattern) in fields {
+                            self.bind_pattern(pattern, &field_type(name))?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    fn function_type(params: &[Parameter], return_type: Option<&Type>, is_async: bool) -> Type {
+        Type::Function {
+            params: params.iter().map(|p| p.type_annotation.clone()).collect(),
+            return_type: Box::new(return_type.cloned().unwrap_or(Type::Void)),
+            is_async,
+        }
+    }
+
+    /// Checks a function or method body, with its parameters in scope.
+    fn analyze_body(
+        &mut self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &[ASTNode],
+    ) -> Result<()> {
+        let was_in_function = self.in_function;
+        self.in_function = true;
+        let old_return_type = self
+            .return_type
+            .replace(return_type.cloned().unwrap_or(Type::Void));
+
+        for param in params {
+            self.declare(param.id, param.type_annotation.clone());
+        }
+        let result = walk_nodes(self, body);
+
+        self.in_function = was_in_function;
+        self.return_type = old_return_type;
+
+        result.map(|_| ())
+    }
+
+    /// Whether `ty` implements `trait_name`, by an impl, a bound or as `dyn`.
+    fn implements(&self, ty: &Type, trait_name: &str) -> bool {
+        match ty {
+            Type::Param(name) => self
+                .bounds
+                .iter()
+                .any(|(param, bound)| param == name && bound == trait_name),
+            Type::Dyn(name) => name == trait_name,
+            ty => self
+                .impls
+                .get(&ty.to_string())
+                .is_some_and(|traits| traits.iter().any(|name| name == trait_name)),
+        }
+    }
+
+    /// Whether a value of type `found` can be used where `expected` is: the
+    /// same type, or a type implementing the trait of an expected `dyn` type.
+    fn accepts(&self, expected: &Type, found: &Type) -> bool {
+        match expected {
+            Type::Dyn(trait_name) => self.implements(found, trait_name),
+            expected => fits(expected, found),
         }
     }
 
-    fn enter_scope(&mut self) {
-        let new_scope = Box::new(Scope::with_parent(self.current_scope.clone()));
-        self.current_scope = new_scope;
+    /// The type of `receiver.method`, without `self`, if a trait the receiver
+    /// implements has the method.
+    fn method_type(&self, receiver: &Type, method: &str) -> Option<Type> {
+        let traits: Vec<&String> = match receiver {
+            Type::Param(name) => self
+                .bounds
+                .iter()
+                .filter(|(param, _)| param == name)
+                .map(|(_, bound)| bound)
+                .collect(),
+            Type::Dyn(name) => vec![name],
+            ty => self.impls.get(&ty.to_string())?.iter().collect(),
+        };
+        let (trait_name, type_params, method) = traits.into_iter().find_map(|name| {
+            let (type_params, methods) = self.traits.get(name)?;
+            let method = methods.iter().find(|declared| declared.name == method)?;
+            Some((name, type_params, method))
+        })?;
+        let mut bindings = HashMap::from([("Self".to_string(), receiver.clone())]);
+        if let Some(args) = self
+            .impl_args
+            .get(&(receiver.to_string(), trait_name.clone()))
+        {
+            bindings.extend(type_params.iter().cloned().zip(args.iter().cloned()));
+        }
+        let params = method.params.get(1..).unwrap_or_default();
+        let method_type = Self::function_type(params, method.return_type.as_ref(), false);
+        Some(method_type.substitute(&bindings))
     }
 
-    fn exit_scope(&mut self) {
-        if let Some(parent) = self.current_scope.get_parent() {
-            self.current_scope = parent;
+    /// `value?` takes the value out of an `Option` or `Result`, so the enclosing
+    /// function must return the same kind of enum.
+    fn check_try(&self, operand_type: Type) -> Result<Type> {
+        let (name, args) = match operand_type {
+            Type::Generic { name, args } if name == "Option" || name == "Result" => (name, args),
+            other => {
+                return Err(IoError::type_error(format!(
+                    "The ? operator applies to an Option or Result, not {}",
+                    other
+                )))
+            }
+        };
+        match &self.return_type {
+            Some(Type::Generic { name: returned, .. }) if *returned == name => {
+                Ok(args.into_iter().next().unwrap_or_default())
+            }
+            Some(other) if self.in_function => Err(IoError::type_error(format!(
+                "The ? operator can only be used in a function returning {}, not {}",
+                name, other
+            ))),
+            _ => Err(IoError::type_error(
+                "The ? operator can only be used in a function",
+            )),
         }
     }
 
@@ -40,21 +318,50 @@ impl SemanticAnalyzer {
         op: &BinaryOperator,
         right: &ASTNode,
     ) -> Result<Type> {
-        let left_type = self.analyze(left)?;
-        let right_type = self.analyze(right)?;
+        let mut left_type = self.analyze(left)?;
+        let mut right_type = self.analyze(right)?;
+        // An unsuffixed literal takes the type of the other operand: `x + 1`.
+        if literal_fits(left, &right_type) {
+            left_type = right_type.clone();
+        } else if literal_fits(right, &left_type) {
+            right_type = left_type.clone();
+        }
+        // Unannotated closure parameters are left for the type checker to infer.
+        if left_type == Type::Unknown || right_type == Type::Unknown {
+            return Ok(match op {
+                op if op.is_comparison() || op.is_logical() => Type::Bool,
+                _ if left_type == Type::Unknown => right_type,
+                _ => left_type,
+            });
+        }
 
         match op {
+            BinaryOperator::Add if left_type == Type::String && right_type == Type::String => {
+                Ok(Type::String)
+            }
             BinaryOperator::Add
             | BinaryOperator::Subtract
             | BinaryOperator::Multiply
-            | BinaryOperator::Divide => match (&left_type, &right_type) {
-                (Type::Int, Type::Int) => Ok(Type::Int),
-                (Type::Float, Type::Float) => Ok(Type::Float),
-                _ => Err(IoError::type_error(format!(
-                    "Invalid operand types for arithmetic operation: {} and {}",
-                    left_type, right_type
-                ))),
-            },
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo => {
+                if left_type.is_numeric() && left_type == right_type {
+                    Ok(left_type)
+                } else if left_type.is_numeric() && right_type.is_numeric() {
+                    Err(IoError::type_error(format!(
+                        "Invalid operand types for arithmetic operation: {} and {}; \
+                         convert one of them with `as`, e.g. `{} as {}`",
+                        left_type,
+                        right_type,
+                        operand_text(right),
+                        left_type
+                    )))
+                } else {
+                    Err(IoError::type_error(format!(
+                        "Invalid operand types for arithmetic operation: {} and {}",
+                        left_type, right_type
+                    )))
+                }
+            }
             BinaryOperator::Equal | BinaryOperator::NotEqual => {
                 if left_type == right_type {
                     Ok(Type::Bool)
@@ -65,694 +372,1162 @@ impl SemanticAnalyzer {
                     )))
                 }
             }
-            BinaryOperator::LessThan | BinaryOperator::LessThanOrEqual |
-            BinaryOperator::GreaterThan | BinaryOperator::GreaterThanOrEqual => {
-                match (&left_type, &right_type) {
-                    (Type::Int, Type::Int) | (Type::Float, Type::Float) => Ok(Type::Bool),
-                    _ => Err(IoError::type_error(format!(
+            BinaryOperator::LessThan
+            | BinaryOperator::LessThanEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanEqual => {
+                if left_type.is_numeric() && left_type == right_type {
+                    Ok(Type::Bool)
+                } else {
+                    Err(IoError::type_error(format!(
                         "Cannot compare values of types {} and {}",
                         left_type, right_type
                     )))
                 }
-            },
+            }
             BinaryOperator::And | BinaryOperator::Or => {
                 if left_type == Type::Bool && right_type == Type::Bool {
                     Ok(Type::Bool)
                 } else {
-                    Err(IoError::type_error("Logical operators require boolean operands"))
+                    Err(IoError::type_error(
+                        "Logical operators require boolean operands",
+                    ))
                 }
-            },
-            BinaryOperator::BitwiseAnd | BinaryOperator::BitwiseOr | 
-            BinaryOperator::BitwiseXor => {
-                match (&left_type, &right_type) {
-                    (Type::Int, Type::Int) => Ok(Type::Int),
-                    _ => Err(IoError::type_error("Bitwise operators require integer operands"))
+            }
+            BinaryOperator::BitwiseAnd | BinaryOperator::BitwiseOr | BinaryOperator::BitwiseXor => {
+                if left_type.is_integer() && left_type == right_type {
+                    Ok(left_type)
+                } else {
+                    Err(IoError::type_error(
+                        "Bitwise operators require integer operands",
+                    ))
                 }
-            },
+            }
             BinaryOperator::LeftShift | BinaryOperator::RightShift => {
-                match (&left_type, &right_type) {
-                    (Type::Int, Type::Int) => Ok(Type::Int),
-                    _ => Err(IoError::type_error("Shift operators require integer operands"))
+                if left_type.is_integer() && right_type.is_integer() {
+                    Ok(left_type)
+                } else {
+                    Err(IoError::type_error(
+                        "Shift operators require integer operands",
+                    ))
                 }
-            },
-            BinaryOperator::Modulo => match (&left_type, &right_type) {
-                (Type::Int, Type::Int) => Ok(Type::Int),
-                (Type::Float, Type::Float) => Ok(Type::Float),
-                _ => Err(IoError::type_error("Modulo operator requires numeric operands"))
-            },
-            BinaryOperator::Concat => match (&left_type, &right_type) {
-                (Type::String, Type::String) => Ok(Type::String),
-                (Type::Array(t1), Type::Array(t2)) if t1 == t2 => Ok(Type::Array(t1.clone())),
-                _ => Err(IoError::type_error("Concatenation requires matching string or array types"))
-            },
-            _ => Err(IoError::type_error("Unsupported operator")),
+            }
+            BinaryOperator::Range | BinaryOperator::RangeInclusive => {
+                if left_type.is_integer() && left_type == right_type {
+                    Ok(Type::Generic {
+                        name: prelude::ITER_TYPE.to_string(),
+                        args: vec![left_type],
+                    })
+                } else {
+                    Err(IoError::type_error("Range bounds must be integers"))
+                }
+            }
         }
     }
 
-    fn analyze(&mut self, node: &ASTNode) -> Result<Type> {
-        match node {
-            ASTNode::VariableDeclaration {
-                name,
-                type_annotation,
-                value,
-                is_mutable,
-            } => {
-                let value_type = self.analyze(value)?;
-
-                if let Some(type_name) = type_annotation {
-                    let declared_type = Type::from_str(type_name)?;
-                    if !self.types_match(&declared_type, &value_type) {
-                        return Err(IoError::type_error(format!(
-                            "Type mismatch: expected {}, found {}",
-                            declared_type, value_type
-                        )));
-                    }
-                }
-
-                self.current_scope.define(
-                    name.clone(),
-                    Symbol::Variable {
-                        name: name.clone(),
-                        type_name: value_type.to_string(),
-                        mutable: *is_mutable,
-                    },
-                )?;
-
-                Ok(value_type)
+    /// `wrapping_add(a, b)` and the like: two integers of the same type, giving
+    /// that type, or an `Option` of it for the `checked_` ones.
+    fn check_intrinsic(&mut self, name: &str, arguments: &[ASTNode]) -> Result<Type> {
+        let [left, right] = arguments else {
+            return Err(IoError::type_error(format!(
+                "Expected 2 arguments, found {}",
+                arguments.len()
+            )));
+        };
+        let mut left_type = self.analyze(left)?;
+        let right_type = self.analyze(right)?;
+        if literal_fits(left, &right_type) {
+            left_type = right_type.clone();
+        }
+        if !left_type.is_integer() || !(left_type == right_type || literal_fits(right, &left_type))
+        {
+            return Err(IoError::type_error(format!(
+                "{} expects two integers of the same type, found {} and {}",
+                name, left_type, right_type
+            )));
+        }
+        Ok(if name.starts_with("checked_") {
+            Type::Generic {
+                name: "Option".to_string(),
+                args: vec![left_type],
             }
+        } else {
+            left_type
+        })
+    }
 
-            ASTNode::Assignment { target, value } => {
-                let target_type = self.analyze(target)?;
-                let value_type = self.analyze(value)?;
-
-                if !self.types_match(&target_type, &value_type) {
-                    return Err(IoError::type_error(format!(
-                        "Cannot assign {} to variable of type {}",
-                        value_type, target_type
-                    )));
-                }
-
-                // Check if target is mutable
-                if let ASTNode::Identifier(name) = &**target {
-                    if let Some(Symbol::Variable { mutable, .. }) = self.current_scope.lookup(name)
-                    {
-                        if !mutable {
-                            return Err(IoError::type_error(format!(
-                                "Cannot assign to immutable variable {}",
-                                name
-                            )));
-                        }
-                    }
-                }
+    /// `read_line`, `len`, `to_string`, `parse_int` and `parse_float`.
+    fn check_runtime_builtin(&mut self, name: &str, arguments: &[ASTNode]) -> Result<Type> {
+        let expected = if name == "read_line" { 0 } else { 1 };
+        if arguments.len() != expected {
+            return Err(IoError::type_error(format!(
+                "Expected {} arguments, found {}",
+                expected,
+                arguments.len()
+            )));
+        }
+        let Some(argument) = arguments.first() else {
+            return Ok(Type::String);
+        };
+        let ty = self.analyze(argument)?;
+        let accepted = match name {
+            "len" => matches!(ty, Type::Array { .. } | Type::String | Type::Unknown),
+            "to_string" => true,
+            _ => matches!(ty, Type::String | Type::Unknown),
+        };
+        if !accepted {
+            return Err(IoError::type_error(format!("{} can't take {}", name, ty))
+                .with_span(argument.span()));
+        }
+        Ok(match name {
+            "len" | "parse_int" => Type::I32,
+            "parse_float" => Type::F64,
+            _ => Type::String,
+        })
+    }
 
-                Ok(target_type)
+    /// `map`, `zip` and the other iterator functions. The elements and results
+    /// are as far as known without inference: what `map` gives is up to the
+    /// type checker.
+    fn check_iterator_function(&mut self, name: &str, arguments: &[ASTNode]) -> Result<Type> {
+        let expected = match name {
+            "enumerate" | "collect" => 1,
+            "fold" => 3,
+            _ => 2,
+        };
+        if arguments.len() != expected {
+            return Err(IoError::type_error(format!(
+                "Expected {} arguments, found {}",
+                expected,
+                arguments.len()
+            )));
+        }
+        let elem_type = self.iterable(&arguments[0])?;
+        let types = arguments[1..]
+            .iter()
+            .map(|argument| self.analyze(argument))
+            .collect::<Result<Vec<_>>>()?;
+        if let ("map" | "filter" | "fold", Some(function)) = (name, types.last()) {
+            if !matches!(function, Type::Function { .. } | Type::Unknown) {
+                return Err(IoError::type_error(format!(
+                    "{} expects a function, found {}",
+                    name, function
+                ))
+                .with_span(arguments[expected - 1].span()));
             }
-
-            ASTNode::BinaryOperation {
-                left,
-                operator,
-                right,
-            } => self.check_binary_operation(left, operator, right),
-
-            ASTNode::If {
-                condition,
-                then_branch,
-                else_branch,
-            } => {
-                let cond_type = self.analyze(condition)?;
-                if !matches!(cond_type, Type::Bool) {
-                    return Err(IoError::type_error(
-                        "If condition must be a boolean expression",
-                    ));
-                }
-
-                self.enter_scope();
-                for stmt in then_branch {
-                    self.analyze(stmt)?;
-                }
-                self.exit_scope();
-
-                if let Some(else_stmts) = else_branch {
-                    self.enter_scope();
-                    for stmt in else_stmts {
-                        self.analyze(stmt)?;
-                    }
-                    self.exit_scope();
-                }
-
-                Ok(Type::Unit)
+        }
+        let iter_of = |elem_type: Type| Type::Generic {
+            name: prelude::ITER_TYPE.to_string(),
+            args: vec![elem_type],
+        };
+        let pair_of = |first: Type, second: Type| Type::Generic {
+            name: prelude::PAIR_TYPE.to_string(),
+            args: vec![first, second],
+        };
+        Ok(match name {
+            "map" => iter_of(Type::Unknown),
+            "filter" | "take" => iter_of(elem_type),
+            "zip" => {
+                let other = self.element_type(&types[0]).unwrap_or(Type::Unknown);
+                iter_of(pair_of(elem_type, other))
             }
+            "enumerate" => iter_of(pair_of(Type::I32, elem_type)),
+            "collect" => Type::Array {
+                elem_type: Box::new(elem_type),
+                size: 0,
+            },
+            _ => types[0].clone(),
+        })
+    }
 
-            ASTNode::While { condition, body } => {
-                let cond_type = self.analyze(condition)?;
-                if !matches!(cond_type, Type::Bool) {
-                    return Err(IoError::type_error(
-                        "While condition must be a boolean expression",
-                    ));
-                }
+    /// Analyzes an expression a `for` loop or iterator function steps
+    /// through, giving the type of its elements.
+    fn iterable(&mut self, iterable: &ASTNode) -> Result<Type> {
+        let ty = self.analyze(iterable)?;
+        self.element_type(&ty).ok_or_else(|| {
+            IoError::type_error(format!("Cannot iterate over {}", ty)).with_span(iterable.span())
+        })
+    }
 
-                let was_in_loop = self.in_loop;
-                self.in_loop = true;
+    /// The type of the elements of an iterable type, `Unknown` where the type
+    /// checker has yet to tell.
+    fn element_type(&self, ty: &Type) -> Option<Type> {
+        match ty {
+            Type::Array { elem_type, .. } => Some(elem_type.as_ref().clone()),
+            Type::String => Some(Type::Char),
+            Type::Unknown => Some(Type::Unknown),
+            Type::Generic { name, args } if name == prelude::ITER_TYPE => args.first().cloned(),
+            ty => self
+                .impl_args
+                .get(&(ty.to_string(), prelude::ITERATOR_TRAIT.to_string()))
+                .and_then(|args| args.first().cloned()),
+        }
+    }
 
-                self.enter_scope();
-                for stmt in body {
-                    self.analyze(stmt)?;
-                }
-                self.exit_scope();
+    /// The type of the place `target` names, checking that its variable may
+    /// be assigned.
+    fn place_type(&mut self, target: &ASTNode) -> Result<Type> {
+        let (Some(root), Some(node)) = (target.place_root(), target.place_root_id()) else {
+            return Err(IoError::type_error("Invalid assignment target"));
+        };
+        let ty = self.identifier_type(root, node)?;
+        let resolution = self.resolution;
+        if !resolution.resolved(node).is_some_and(|symbol| resolution.symbol(symbol).is_mutable()) {
+            return Err(IoError::type_error(format!(
+                "Cannot assign to immutable variable {}",
+                root
+            )));
+        }
+        match target {
+            ASTNode::Identifier { .. } => Ok(ty),
+            _ => self.analyze(target),
+        }
+    }
 
-                self.in_loop = was_in_loop;
-                Ok(Type::Unit)
-            }
+    /// The type assigned to a place of type `target_type`: a literal takes the
+    /// place's type where it fits.
+    fn assigned_type(&mut self, target_type: &Type, value: &ASTNode) -> Result<Type> {
+        let value_type = self.analyze(value)?;
+        Ok(if literal_fits(value, target_type) {
+            target_type.clone()
+        } else {
+            value_type
+        })
+    }
 
-            ASTNode::Return(value) => {
-                if !self.in_function {
-                    return Err(IoError::type_error("Return statement outside function"));
-                }
+    fn assign(&mut self, target_type: &Type, value_type: &Type) -> Result<()> {
+        if !fits(target_type, value_type) {
+            return Err(IoError::type_error(format!(
+                "Cannot assign {} to variable of type {}",
+                value_type, target_type
+            )));
+        }
+        Ok(())
+    }
 
-                match (value, &self.return_type) {
-                    (Some(expr), Some(expected)) => {
-                        let actual = self.analyze(expr)?;
-                        if !self.types_match(expected, &actual) {
-                            return Err(IoError::type_error(format!(
-                                "Return type mismatch: expected {}, found {}",
-                                expected, actual
-                            )));
-                        }
-                    }
-                    (None, Some(_)) => {
-                        return Err(IoError::type_error("Expected return value"));
-                    }
-                    (Some(_), None) => {
-                        return Err(IoError::type_error("Unexpected return value"));
-                    }
-                    (None, None) => {}
-                }
+    /// `let` and `const`: the value must fit the declared type, if any.
+    fn check_let(
+        &mut self,
+        type_annotation: Option<&Type>,
+        value: Option<&ASTNode>,
+        id: NodeId,
+    ) -> Result<Type> {
+        // Assignments check what a variable declared without a value is given.
+        let Some(value) = value else {
+            self.declare(id, type_annotation.cloned().unwrap_or(Type::Unknown));
+            return Ok(Type::Void);
+        };
+        let value_type = self.analyze(value)?;
 
-                Ok(Type::Unit)
+        if let Some(declared_type) = type_annotation {
+            if let (true, Some(n)) = (declared_type.is_integer(), integer_literal(value)) {
+                typed_integer(n, declared_type).map_err(|err| err.with_span(value.span()))?;
             }
+            if !self.accepts(declared_type, &value_type) && !literal_fits(value, declared_type) {
+                return Err(IoError::type_error(format!(
+                    "Type mismatch: expected {}, found {}",
+                    declared_type, value_type
+                ))
+                .with_span(value.span()));
+            }
+        }
 
-            ASTNode::CallExpression { callee, arguments } => {
-                let callee_type = self.analyze(callee)?;
-
-                match callee_type {
-                    Type::Function {
-                        params,
-                        return_type,
-                        ..
-                    } => {
-                        if arguments.len() != params.len() {
-                            return Err(IoError::type_error(format!(
-                                "Expected {} arguments, found {}",
-                                params.len(),
-                                arguments.len()
-                            )));
-                        }
+        let ty = type_annotation.cloned().unwrap_or(value_type);
+        self.declare(id, ty);
+        Ok(Type::Void)
+    }
+}
 
-                        for (param, arg) in params.iter().zip(arguments) {
-                            let arg_type = self.analyze(arg)?;
-                            if !self.types_match(param, &arg_type) {
-                                return Err(IoError::type_error(format!(
-                                    "Type mismatch in function call: expected {}, found {}",
-                                    param, arg_type
-                                )));
-                            }
-                        }
+impl Visitor for SemanticAnalyzer<'_> {
+    type Output = Type;
 
-                        Ok(*return_type)
-                    }
-                    _ => Err(IoError::type_error("Called value is not a function")),
-                }
+    /// Type parameter bounds are not passed to `visit_function`, so functions
+    /// bring theirs into scope here.
+    /// Identifiers and `let`s are analyzed here too, where their node ids are
+    /// known.
+    fn visit_node(&mut self, node: &ASTNode) -> Result<Type> {
+        match node {
+            ASTNode::Function {
+                bounds,
+                params,
+                return_type,
+                is_async,
+                id,
+                ..
+            } => {
+                let fn_type = Self::function_type(params, return_type.as_ref(), *is_async);
+                self.declare(*id, fn_type);
+                let outer = std::mem::replace(&mut self.bounds, bounds.clone());
+                let result = walk_node(self, node);
+                self.bounds = outer;
+                result
             }
+            ASTNode::Identifier { name, id, span } => self
+                .identifier_type(name, *id)
+                .map_err(|err| err.with_span(*span)),
+            ASTNode::Let {
+                type_annotation,
+                value,
+                id,
+                ..
+            } => self
+                .check_let(type_annotation.as_ref(), value.as_deref(), *id)
+                .map_err(|err| err.or_span(node.span())),
+            ASTNode::Global {
+                type_annotation,
+                value,
+                id,
+                ..
+            } => self
+                .check_let(type_annotation.as_ref(), Some(value), *id)
+                .map_err(|err| err.or_span(node.span())),
+            node => walk_node(self, node),
+        }
+    }
 
-            ASTNode::Break => {
-                if !self.in_loop {
-                    Err(IoError::type_error("Break statement outside loop"))
-                } else {
-                    Ok(Type::Unit)
-                }
-            },
-            ASTNode::Continue => {
-                if !self.in_loop {
-                    Err(IoError::type_error("Continue statement outside loop"))
-                } else {
-                    Ok(Type::Unit)
-                }
-            },
-            ASTNode::ArrayLiteral(elements) => {
-                if elements.is_empty() {
-                    Ok(Type::Array(Box::new(Type::Unknown)))
-                } else {
-                    let first_type = self.analyze(&elements[0])?;
-                    for elem in &elements[1..] {
-                        let elem_type = self.analyze(elem)?;
-                        if !self.types_match(&first_type, &elem_type) {
-                            return Err(IoError::type_error("Array elements must have same type"));
-                        }
-                    }
-                    Ok(Type::Array(Box::new(first_type)))
+    fn visit_program(&mut self, nodes: &[ASTNode]) -> Result<Type> {
+        // Declare every function, enum, trait and impl first so uses may precede
+        // definitions.
+        for node in nodes {
+            match node {
+                ASTNode::EnumDef { .. } => {
+                    self.analyze(node)?;
                 }
-            },
-            ASTNode::IndexAccess { array, index } => {
-                let array_type = self.analyze(array)?;
-                let index_type = self.analyze(index)?;
-                
-                match (array_type, index_type) {
-                    (Type::Array(elem_type), Type::Int) => Ok(*elem_type),
-                    (Type::String, Type::Int) => Ok(Type::String),
-                    _ => Err(IoError::type_error("Invalid array access"))
+                ASTNode::Function {
+                    name,
+                    bounds,
+                    params,
+                    return_type,
+                    is_async,
+                    id,
+                    ..
+                } => {
+                    let fn_type = Self::function_type(params, return_type.as_ref(), *is_async);
+                    self.declare(*id, fn_type);
+                    self.function_bounds.insert(name.clone(), bounds.clone());
                 }
-            },
-            ASTNode::StructLiteral { name, fields } => {
-                if let Some(struct_type) = self.current_scope.lookup_type(name) {
-                    match struct_type {
-                        Type::Struct { fields: struct_fields } => {
-                            for (field_name, field_value) in fields {
-                                if let Some(expected_type) = struct_fields.get(field_name) {
-                                    let actual_type = self.analyze(field_value)?;
-                                    if !self.types_match(expected_type, &actual_type) {
-                                        return Err(IoError::type_error(format!(
-                                            "Type mismatch in field {}: expected {}, found {}",
-                                            field_name, expected_type, actual_type
-                                        )));
-                                    }
-                                } else {
-                                    return Err(IoError::type_error(format!(
-                                        "Unknown field {} in struct {}", field_name, name
-                                    )));
-                                }
-                            }
-                            Ok(struct_type)
-                        },
-                        _ => Err(IoError::type_error(format!("{} is not a struct type", name)))
-                    }
-                } else {
-                    Err(IoError::type_error(format!("Undefined struct type {}", name)))
+                ASTNode::TraitDef {
+                    name,
+                    type_params,
+                    methods,
+                    ..
+                } => {
+                    self.traits
+                        .insert(name.clone(), (type_params.clone(), methods.clone()));
                 }
-            },
-            ASTNode::Match { expr, arms } => {
-                let expr_type = self.analyze(expr)?;
-                let mut result_type = None;
-
-                for (pattern, body) in arms {
-                    self.enter_scope();
-                    self.check_pattern(pattern, &expr_type)?;
-                    let arm_type = self.analyze(body)?;
-                    
-                    if let Some(prev_type) = &result_type {
-                        if !self.types_match(prev_type, &arm_type) {
-                            return Err(IoError::type_error("All match arms must return same type"));
-                        }
-                    } else {
-                        result_type = Some(arm_type);
+                ASTNode::Impl {
+                    trait_name,
+                    trait_args,
+                    self_type,
+                    methods,
+                    ..
+                } => {
+                    // The methods of an inherent impl are kept as a trait
+                    // named after the type, which only that type implements.
+                    let trait_name = trait_name.clone().unwrap_or_else(|| {
+                        let name = self_type.to_string();
+                        let methods = methods.iter().filter_map(inherent_method).collect();
+                        self.traits.insert(name.clone(), (Vec::new(), methods));
+                        name
+                    });
+                    self.impls
+                        .entry(self_type.to_string())
+                        .or_default()
+                        .push(trait_name.clone());
+                    if !trait_args.is_empty() {
+                        self.impl_args.insert(
+                            (self_type.to_string(), trait_name.clone()),
+                            trait_args.clone(),
+                        );
                     }
-                    self.exit_scope();
                 }
-
-                Ok(result_type.unwrap_or(Type::Unit))
+                _ => {}
             }
-            // Add handling for other node types...
-            _ => Ok(Type::Unit),
         }
-    }
-
-    fn types_match(&self, expected: &Type, actual: &Type) -> bool {
-        match (expected, actual) {
-            (Type::Int, Type::Int)
-            | (Type::Float, Type::Float)
-            | (Type::Bool, Type::Bool)
-            | (Type::String, Type::String) => true,
-            (Type::Array(t1), Type::Array(t2)) => self.types_match(t1, t2),
-            (
-                Type::Function {
-                    params: p1,
-                    return_type: r1,
-                    ..
-                },
-                Type::Function {
-                    params: p2,
-                    return_type: r2,
-                    ..
-                },
-            ) => {
-                p1.len() == p2.len()
-                    && p1.iter().zip(p2).all(|(a, b)| self.types_match(a, b))
-                    && self.types_match(r1, r2)
+        for node in nodes {
+            if !matches!(node, ASTNode::EnumDef { .. }) {
+                self.analyze(node)?;
             }
-            _ => false,
         }
+        Ok(Type::Void)
     }
-}
-
-impl ASTVisitor for SemanticAnalyzer {
-    type Output = Type;
 
-    fn visit_program(&mut self, nodes: &[ASTNode]) -> Result<Self::Output> {
-        for node in nodes {
-            self.analyze(node)?;
+    /// Unit variants are values of the enum and tuple variants functions
+    /// returning one.
+    fn visit_enum_def(
+        &mut self,
+        name: &str,
+        type_params: &[String],
+        variants: &[Variant],
+    ) -> Result<Type> {
+        for variant in variants {
+            let fields = match &variant.fields {
+                VariantFields::Unit => Vec::new(),
+                VariantFields::Tuple(types) => types.clone(),
+                VariantFields::Named(_) => continue,
+            };
+            // A variant leaves the type parameters its fields don't mention
+            // unknown, so that `Option::None` fits any `Option`.
+            let mentioned = |param: &String| {
+                let unknown = HashMap::from([(param.clone(), Type::Unknown)]);
+                fields.iter().any(|ty| ty.substitute(&unknown) != *ty)
+            };
+            let args = type_params
+                .iter()
+                .map(|param| {
+                    if mentioned(param) {
+                        Type::Param(param.clone())
+                    } else {
+                        Type::Unknown
+                    }
+                })
+                .collect();
+            let enum_type = match Self::enum_type(name, type_params) {
+                Type::Generic { name, .. } => Type::Generic { name, args },
+                other => other,
+            };
+            let ty = match variant.fields {
+                VariantFields::Unit => enum_type,
+                _ => Type::Function {
+                    params: fields,
+                    return_type: Box::new(enum_type),
+                    is_async: false,
+                },
+            };
+            self.declare(variant.id, ty);
         }
-        Ok(Type::Unit)
+        self.enums.insert(
+            name.to_string(),
+            (type_params.to_vec(), variants.to_vec()),
+        );
+        Ok(Type::Void)
     }
 
     fn visit_function(
         &mut self,
-        name: &str,
+        _name: &str,
         params: &[Parameter],
-        return_type: &Option<String>,
+        return_type: Option<&Type>,
         body: &[ASTNode],
         is_async: bool,
-    ) -> Result<Self::Output> {
-        let was_in_function = self.in_function;
-        self.in_function = true;
-
-        let return_type = if let Some(rt) = return_type {
-            Type::from_str(rt)?
-        } else {
-            Type::Unit
-        };
-
-        self.enter_scope();
-
-        // Register parameters in function scope
-        for param in params {
-            let param_type = Type::from_str(&param.type_annotation)?;
-            self.current_scope.define(
-                param.name.clone(),
-                Symbol::Variable {
-                    name: param.name.clone(),
-                    type_name: param_type.to_string(),
-                    mutable: false,
-                },
-            )?;
-        }
+    ) -> Result<Type> {
+        let fn_type = Self::function_type(params, return_type, is_async);
+        self.analyze_body(params, return_type, body)?;
+        Ok(fn_type)
+    }
 
-        let old_return_type = self.return_type.replace(return_type.clone());
+    /// Default methods see `self` as a value of some type implementing the trait.
+    fn visit_trait_def(&mut self, name: &str, methods: &[TraitMethod]) -> Result<Type> {
+        let outer = std::mem::replace(
+            &mut self.bounds,
+            vec![("Self".to_string(), name.to_string())],
+        );
+        let result = methods.iter().try_for_each(|method| match &method.body {
+            Some(body) => self
+                .analyze_body(&method.params, method.return_type.as_ref(), body)
+                .map_err(|err| err.or_span(method.span)),
+            None => Ok(()),
+        });
+        self.bounds = outer;
+        result.map(|()| Type::Void)
+    }
 
-        // Analyze function body
-        for node in body {
-            self.analyze(node)?;
+    fn visit_impl(
+        &mut self,
+        trait_name: Option<&str>,
+        self_type: &Type,
+        methods: &[ASTNode],
+    ) -> Result<Type> {
+        if let Some(trait_name) = trait_name.filter(|name| !self.traits.contains_key(*name)) {
+            return Err(IoError::type_error(format!("Unknown trait {}", trait_name)));
         }
-
-        self.exit_scope();
-        self.in_function = was_in_function;
-        self.return_type = old_return_type;
-
-        Ok(Type::Function {
-            params: params
+        let bindings = HashMap::from([("Self".to_string(), self_type.clone())]);
+        for method in methods {
+            let ASTNode::Function {
+                params,
+                return_type,
+                body,
+                span,
+                ..
+            } = method
+            else {
+                continue;
+            };
+            let params: Vec<Parameter> = params
                 .iter()
-                .map(|p| Type::from_str(&p.type_annotation))
-                .collect::<Result<Vec<_>>>()?,
-            return_type: Box::new(return_type),
-            is_async,
-        })
+                .map(|param| Parameter {
+                    type_annotation: param.type_annotation.substitute(&bindings),
+                    ..param.clone()
+                })
+                .collect();
+            let return_type = return_type.as_ref().map(|ty| ty.substitute(&bindings));
+            self.analyze_body(&params, return_type.as_ref(), body)
+                .map_err(|err| err.or_span(*span))?;
+        }
+        Ok(Type::Void)
     }
 
-    fn visit_variable_declaration(
+    fn visit_let_pattern(
         &mut self,
-        name: &str,
-        type_annotation: &Option<String>,
-        initializer: &Option<ASTNode>,
-    ) -> Result<Self::Output> {
-        let var_type = if let Some(type_name) = type_annotation {
-            Type::from_str(type_name)?
-        } else if let Some(init) = initializer {
-            self.analyze(init)?
-        } else {
-            return Err(IoError::type_error("Cannot infer type without initializer"));
+        pattern: &Pattern,
+        type_annotation: Option<&Type>,
+        value: &ASTNode,
+    ) -> Result<Type> {
+        let value_type = self.analyze(value)?;
+        let ty = match type_annotation {
+            Some(declared_type) if !self.accepts(declared_type, &value_type) => {
+                return Err(IoError::type_error(format!(
+                    "Type mismatch: expected {}, found {}",
+                    declared_type, value_type
+                ))
+                .with_span(value.span()));
+            }
+            Some(declared_type) => declared_type.clone(),
+            None => value_type,
         };
+        self.bind_pattern(pattern, &ty)?;
+        Ok(Type::Void)
+    }
 
-        self.current_scope.define(
-            name.to_string(),
-            Symbol::Variable {
-                name: name.to_string(),
-                type_name: var_type.to_string(),
-                mutable: true,
-            },
-        )?;
+    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<Type> {
+        let target_type = self.place_type(target)?;
+        let value_type = self.assigned_type(&target_type, value)?;
+        self.assign(&target_type, &value_type)?;
+        Ok(Type::Void)
+    }
 
-        Ok(var_type)
+    fn visit_compound_assignment(
+        &mut self,
+        target: &ASTNode,
+        op: &BinaryOperator,
+        value: &ASTNode,
+    ) -> Result<Type> {
+        let target_type = self.place_type(target)?;
+        let value_type = self.assigned_type(&target_type, value)?;
+        let is_concat = *op == BinaryOperator::Add && value_type == Type::String;
+        if !value_type.is_numeric() && !is_concat && value_type != Type::Unknown {
+            return Err(IoError::type_error(format!(
+                "Operator {}= cannot be applied to {}",
+                op, value_type
+            )));
+        }
+        self.assign(&target_type, &value_type)?;
+        Ok(Type::Void)
     }
 
     fn visit_if(
         &mut self,
         condition: &ASTNode,
         then_branch: &[ASTNode],
-        else_branch: &Option<Vec<ASTNode>>,
-    ) -> Result<Self::Output> {
-        let cond_type = self.analyze(condition)?;
-        if !matches!(cond_type, Type::Bool) {
-            return Err(IoError::type_error("If condition must be boolean"));
-        }
-
-        self.enter_scope();
-        let then_type = self.analyze_block(then_branch)?;
-        self.exit_scope();
-
+        else_branch: Option<&[ASTNode]>,
+    ) -> Result<Type> {
+        self.expect_bool(condition, "If")?;
+        walk_nodes(self, then_branch)?;
         if let Some(else_stmts) = else_branch {
-            self.enter_scope();
-            let else_type = self.analyze_block(else_stmts)?;
-            self.exit_scope();
+            walk_nodes(self, else_stmts)?;
+        }
+        Ok(Type::Void)
+    }
 
-            if !self.types_match(&then_type, &else_type) {
-                return Err(IoError::type_error("If branches must have same type"));
+    /// A match has a value when all its arms agree on its type.
+    fn visit_match(&mut self, scrutinee: &ASTNode, arms: &[MatchArm]) -> Result<Type> {
+        let scrutinee_type = self.analyze(scrutinee)?;
+        let mut arm_types = Vec::with_capacity(arms.len());
+        for arm in arms {
+            self.bind_pattern(&arm.pattern, &scrutinee_type)?;
+            if let Some(guard) = &arm.guard {
+                self.expect_bool(guard, "Match guard")?;
             }
+            arm_types.push(self.analyze(&arm.body)?);
         }
+        match arm_types.split_first() {
+            Some((first, rest)) if rest.iter().all(|ty| fits(first, ty)) => {
+                // `Option::None` says less about the type than `Option::Some(1)`.
+                let known = arm_types.iter().find(|ty| {
+                    !matches!(ty, Type::Generic { args, .. } if args.contains(&Type::Unknown))
+                });
+                Ok(known.unwrap_or(first).clone())
+            }
+            _ => Ok(Type::Void),
+        }
+    }
 
-        Ok(Type::Unit)
+    fn visit_while(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<Type> {
+        self.expect_bool(condition, "While")?;
+        walk_nodes(self, body)?;
+        Ok(Type::Void)
     }
 
-    fn visit_match(
+    fn visit_for(
         &mut self,
-        expr: &ASTNode,
-        arms: &[(Pattern, ASTNode)],
-    ) -> Result<Self::Output> {
-        let expr_type = self.analyze(expr)?;
-        let mut result_type = None;
-
-        for (pattern, body) in arms {
-            self.enter_scope();
-            self.check_pattern(pattern, &expr_type)?;
-            let arm_type = self.analyze(body)?;
-            
-            if let Some(prev_type) = &result_type {
-                if !self.types_match(prev_type, &arm_type) {
-                    return Err(IoError::type_error("All match arms must return same type"));
-                }
-            } else {
-                result_type = Some(arm_type);
+        pattern: &Pattern,
+        iterable: &ASTNode,
+        body: &[ASTNode],
+    ) -> Result<Type> {
+        let elem_type = self.iterable(iterable)?;
+        self.bind_pattern(pattern, &elem_type)?;
+        walk_nodes(self, body)?;
+        Ok(Type::Void)
+    }
+
+    fn visit_return(&mut self, value: Option<&ASTNode>) -> Result<Type> {
+        if !self.in_function {
+            return Err(IoError::type_error("Return statement outside function"));
+        }
+
+        let expected = self.return_type.clone().unwrap_or(Type::Void);
+        let actual = match value {
+            Some(expr) => self.analyze(expr)?,
+            None => Type::Void,
+        };
+        let literal = value.is_some_and(|expr| literal_fits(expr, &expected));
+        if !self.accepts(&expected, &actual) && !literal {
+            return Err(IoError::type_error(format!(
+                "Return type mismatch: expected {}, found {}",
+                expected, actual
+            )));
+        }
+
+        Ok(Type::Void)
+    }
+
+    /// The resolver made sure there is a loop to leave.
+    fn visit_break(&mut self) -> Result<Type> {
+        Ok(Type::Void)
+    }
+
+    fn visit_continue(&mut self) -> Result<Type> {
+        Ok(Type::Void)
+    }
+
+    fn visit_call(&mut self, callee: &ASTNode, arguments: &[ASTNode]) -> Result<Type> {
+        if let ASTNode::Identifier { name, id, .. } = callee {
+            if prelude::VARIADIC_BUILTINS.contains(&name.as_str()) && self.is_builtin(*id) {
+                walk_nodes(self, arguments)?;
+                return Ok(Type::Void);
+            }
+            if prelude::RUNTIME_BUILTINS.contains(&name.as_str()) && self.is_builtin(*id) {
+                return self.check_runtime_builtin(name, arguments);
+            }
+            if prelude::INTEGER_INTRINSICS.contains(&name.as_str()) && self.is_builtin(*id) {
+                return self.check_intrinsic(name, arguments);
+            }
+            if prelude::ITERATOR_FUNCTIONS.contains(&name.as_str()) && self.is_builtin(*id) {
+                return self.check_iterator_function(name, arguments);
             }
-            self.exit_scope();
         }
 
-        Ok(result_type.unwrap_or(Type::Unit))
+        let callee_type = match callee {
+            ASTNode::MemberAccess { object, member, .. } => {
+                let receiver = self.analyze(object)?;
+                match self.method_type(&receiver, member) {
+                    Some(method) => method,
+                    None => self.visit_member_access(object, member)?,
+                }
+            }
+            callee => self.analyze(callee)?,
+        };
+        match callee_type {
+            Type::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                if arguments.len() != params.len() {
+                    return Err(IoError::type_error(format!(
+                        "Expected {} arguments, found {}",
+                        params.len(),
+                        arguments.len()
+                    )));
+                }
+
+                // Arguments of generic functions fix their type parameters.
+                let mut bindings = HashMap::new();
+                for (param, arg) in params.iter().zip(arguments) {
+                    let arg_type = self.analyze(arg)?;
+                    if !param.bind(&arg_type, &mut bindings)
+                        && !self.accepts(param, &arg_type)
+                        && !literal_fits(arg, param)
+                    {
+                        return Err(IoError::type_error(format!(
+                            "Type mismatch in function call: expected {}, found {}",
+                            param.substitute(&bindings),
+                            arg_type
+                        ))
+                        .with_span(arg.span()));
+                    }
+                }
+                if let ASTNode::Identifier { name, .. } = callee {
+                    let bounds = self.function_bounds.get(name).into_iter().flatten();
+                    for (param, bound) in bounds {
+                        let Some(ty) = bindings.get(param) else {
+                            continue;
+                        };
+                        if !self.implements(ty, bound) {
+                            return Err(IoError::type_error(format!(
+                                "Type {} does not implement trait {}",
+                                ty, bound
+                            )));
+                        }
+                    }
+                }
+
+                Ok(return_type.substitute(&bindings))
+            }
+            // A closure parameter the type checker has yet to infer.
+            Type::Unknown => {
+                walk_nodes(self, arguments)?;
+                Ok(Type::Unknown)
+            }
+            other => Err(IoError::type_error(format!(
+                "Called value of type {} is not a function",
+                other
+            ))),
+        }
     }
 
-    fn visit_binary_op(
+    fn visit_binary(
         &mut self,
-        left: &ASTNode,
         op: &BinaryOperator,
+        left: &ASTNode,
         right: &ASTNode,
-    ) -> Result<Self::Output> {
+    ) -> Result<Type> {
         self.check_binary_operation(left, op, right)
     }
 
-    fn visit_unary_op(&mut self, op: &str, expr: &ASTNode) -> Result<Self::Output> {
-        let expr_type = self.analyze(expr)?;
-        match op {
-            "-" => match expr_type {
-                Type::Int | Type::Float => Ok(expr_type),
-                _ => Err(IoError::type_error("Unary minus requires numeric operand"))
-            },
-            "!" => match expr_type {
-                Type::Bool => Ok(Type::Bool),
-                _ => Err(IoError::type_error("Logical not requires boolean operand"))
+    fn visit_unary(&mut self, op: &UnaryOperator, operand: &ASTNode) -> Result<Type> {
+        // `-128i8` is in range though `128i8` isn't.
+        if let (
+            UnaryOperator::Negate,
+            ASTNode::Literal {
+                value: Literal::TypedInteger(n, ty),
+                ..
             },
-            "~" => match expr_type {
-                Type::Int => Ok(Type::Int),
-                _ => Err(IoError::type_error("Bitwise not requires integer operand"))
+        ) = (op, operand)
+        {
+            return typed_integer(-n, ty);
+        }
+        let operand_type = self.analyze(operand)?;
+        match op {
+            UnaryOperator::Negate if operand_type.is_integer() && !operand_type.is_signed() => {
+                Err(IoError::type_error(format!(
+                    "Cannot negate a value of unsigned type {}",
+                    operand_type
+                )))
+            }
+            UnaryOperator::Negate if operand_type.is_numeric() => Ok(operand_type),
+            UnaryOperator::Negate => {
+                Err(IoError::type_error("Unary minus requires numeric operand"))
+            }
+            UnaryOperator::Not if operand_type == Type::Bool => Ok(Type::Bool),
+            UnaryOperator::Not => Err(IoError::type_error("Logical not requires boolean operand")),
+            UnaryOperator::Await => Ok(operand_type),
+            UnaryOperator::Try => self.check_try(operand_type),
+            UnaryOperator::Ref | UnaryOperator::RefMut => Ok(Type::Ref {
+                mutable: *op == UnaryOperator::RefMut,
+                inner: Box::new(operand_type),
+            }),
+            UnaryOperator::Deref => match operand_type {
+                Type::Ref { inner, .. } => Ok(*inner),
+                other => Err(IoError::type_error(format!("Cannot dereference {}", other))),
             },
-            _ => Err(IoError::type_error("Unknown unary operator"))
-        }
-    }
-
-    fn visit_for(&mut self, init: &ASTNode, cond: &ASTNode, step: &ASTNode, body: &[ASTNode]) -> Result<Self::Output> {
-        self.enter_scope();
-        
-        // Check initialization
-        self.analyze(init)?;
-        
-        // Verify condition is boolean
-        let cond_type = self.analyze(cond)?;
-        if !matches!(cond_type, Type::Bool) {
-            return Err(IoError::type_error("For loop condition must be boolean"));
-        }
-        
-        // Analyze step expression
-        self.analyze(step)?;
-        
-        // Handle loop body
-        let was_in_loop = self.in_loop;
-        self.in_loop = true;
-        
-        for node in body {
-            self.analyze(node)?;
-        }
-        
-        self.in_loop = was_in_loop;
-        self.exit_scope();
-        
-        Ok(Type::Unit)
-    }
-
-    fn visit_struct_declaration(&mut self, name: &str, fields: &[(String, String)]) -> Result<Self::Output> {
-        let field_types = fields.iter()
-            .map(|(name, type_name)| {
-                let field_type = Type::from_str(type_name)?;
-                Ok((name.clone(), field_type))
-            })
-            .collect::<Result<HashMap<String, Type>>>()?;
-
-        let struct_type = Type::Struct {
-            name: name.to_string(),
-            fields: field_types,
-        };
-
-        self.current_scope.define_type(name.to_string(), struct_type.clone())?;
-        Ok(struct_type)
+        }
     }
 
-    fn visit_enum_declaration(&mut self, name: &str, variants: &[(String, Vec<Type>)]) -> Result<Self::Output> {
-        let enum_type = Type::Enum {
-            name: name.to_string(),
-            variants: variants.iter()
-                .map(|(variant, types)| (variant.clone(), types.clone()))
-                .collect(),
-        };
+    fn visit_cast(&mut self, value: &ASTNode, target: &Type) -> Result<Type> {
+        let value_type = self.analyze(value)?;
+        if !value_type.can_cast_to(target) {
+            return Err(IoError::type_error(format!(
+                "Cannot convert {} to {} with as",
+                value_type, target
+            )));
+        }
+        Ok(target.clone())
+    }
 
-        self.current_scope.define_type(name.to_string(), enum_type.clone())?;
-        Ok(enum_type)
-    }
-
-    fn visit_try_catch(&mut self, try_block: &[ASTNode], catch_blocks: &[(String, String, Vec<ASTNode>)], finally: &Option<Vec<ASTNode>>) -> Result<Self::Output> {
-        // Analyze try block
-        self.enter_scope();
-        for node in try_block {
-            self.analyze(node)?;
-        }
-        self.exit_scope();
-
-        // Analyze catch blocks
-        for (exception_type, var_name, block) in catch_blocks {
-            self.enter_scope();
-            
-            // Register exception variable
-            let exc_type = Type::from_str(exception_type)?;
-            self.current_scope.define(
-                var_name.clone(),
-                Symbol::Variable {
-                    name: var_name.clone(),
-                    type_name: exc_type.to_string(),
-                    mutable: false,
-                },
-            )?;
+    fn visit_index(&mut self, array: &ASTNode, index: &ASTNode) -> Result<Type> {
+        let array_type = self.analyze(array)?;
+        let index_type = self.analyze(index)?;
 
-            // Analyze catch block
-            for node in block {
-                self.analyze(node)?;
+        match (array_type.referent(), index_type) {
+            (Type::Array { elem_type, .. }, index_type) if index_type.is_integer() => {
+                Ok(*elem_type)
             }
-            self.exit_scope();
+            (Type::String, index_type) if index_type.is_integer() => Ok(Type::Char),
+            _ => Err(IoError::type_error("Invalid array access")),
         }
+    }
 
-        // Analyze finally block
-        if let Some(finally_block) = finally {
-            self.enter_scope();
-            for node in finally_block {
-                self.analyze(node)?;
+    fn visit_member_access(&mut self, object: &ASTNode, member: &str) -> Result<Type> {
+        match self.analyze(object)?.referent() {
+            Type::Struct { name, fields } => fields
+                .into_iter()
+                .find(|(field, _)| field == member)
+                .map(|(_, ty)| ty)
+                .ok_or_else(|| {
+                    IoError::type_error(format!("Unknown field {} in struct {}", member, name))
+                }),
+            Type::Tuple(elements) => member
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| elements.get(i).cloned())
+                .ok_or_else(|| {
+                    IoError::type_error(format!(
+                        "Type {} has no field {}",
+                        Type::Tuple(elements.clone()),
+                        member
+                    ))
+                }),
+            // Like struct literals, the fields of named types are left to the
+            // type checker.
+            Type::Named(_) | Type::Generic { .. } | Type::Unknown => Ok(Type::Unknown),
+            other => Err(IoError::type_error(format!(
+                "Type {} has no field {}",
+                other, member
+            ))),
+        }
+    }
+
+    fn visit_array(&mut self, elements: &[ASTNode]) -> Result<Type> {
+        let mut elem_type = Type::Unknown;
+        for (i, elem) in elements.iter().enumerate() {
+            let ty = self.analyze(elem)?;
+            if i == 0 {
+                elem_type = ty;
+            } else if ty != elem_type {
+                return Err(IoError::type_error("Array elements must have same type")
+                    .with_span(elem.span()));
             }
-            self.exit_scope();
         }
+        Ok(Type::Array {
+            elem_type: Box::new(elem_type),
+            size: elements.len(),
+        })
+    }
 
-        Ok(Type::Unit)
+    fn visit_tuple(&mut self, elements: &[ASTNode]) -> Result<Type> {
+        let types = elements
+            .iter()
+            .map(|element| self.analyze(element))
+            .collect::<Result<_>>()?;
+        Ok(Type::Tuple(types))
     }
 
-    fn visit_async_block(&mut self, block: &[ASTNode]) -> Result<Self::Output> {
-        self.enter_scope();
-        
-        let mut block_type = Type::Unit;
-        for node in block {
-            block_type = self.analyze(node)?;
+    /// Struct definitions are left to the type checker, which checks the fields
+    /// of plain struct literals.
+    fn visit_struct_literal(&mut self, name: &str, fields: &[(String, ASTNode)]) -> Result<Type> {
+        let Some((enum_name, _)) = name.rsplit_once("::") else {
+            for (_, value) in fields {
+                self.analyze(value)?;
+            }
+            return Ok(Type::Named(name.to_string()));
+        };
+        let Some((type_params, _)) = self.enums.get(enum_name) else {
+            return Err(IoError::type_error(format!("Unknown variant {}", name)));
+        };
+        let enum_type = Self::enum_type(enum_name, type_params);
+        let field_types = self.variant_fields(name, &enum_type);
+
+        // Field values fix the enum's type parameters, as arguments do for calls.
+        let mut bindings = HashMap::new();
+        for (field, value) in fields {
+            let value_type = self.analyze(value)?;
+            let Some((_, field_type)) = field_types.iter().find(|(name, _)| name == field) else {
+                return Err(IoError::type_error(format!(
+                    "Unknown field {} in variant {}",
+                    field, name
+                ))
+                .with_span(value.span()));
+            };
+            if !field_type.bind(&value_type, &mut bindings) {
+                return Err(IoError::type_error(format!(
+                    "Type mismatch in field {}: expected {}, found {}",
+                    field,
+                    field_type.substitute(&bindings),
+                    value_type
+                ))
+                .with_span(value.span()));
+            }
         }
-        
-        self.exit_scope();
-        
-        Ok(Type::Future(Box::new(block_type)))
+        Ok(enum_type.substitute(&bindings))
+    }
+
+    /// Unannotated parameters, and the result if not declared, are `Unknown`
+    /// here; the type checker infers them.
+    fn visit_closure(
+        &mut self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &ASTNode,
+        _is_move: bool,
+    ) -> Result<Type> {
+        let return_type = return_type.cloned().unwrap_or(Type::Unknown);
+        self.analyze_body(params, Some(&return_type), std::slice::from_ref(body))?;
+        Ok(Type::Function {
+            params: params
+                .iter()
+                .map(|param| param.type_annotation.clone())
+                .collect(),
+            return_type: Box::new(return_type),
+            is_async: false,
+        })
     }
 
-    fn visit_await_expr(&mut self, expr: &ASTNode) -> Result<Self::Output> {
-        let expr_type = self.analyze(expr)?;
-        match expr_type {
-            Type::Future(inner_type) => Ok(*inner_type),
-            _ => Err(IoError::type_error("Can only await Future types")),
+    fn visit_literal(&mut self, value: &Literal) -> Result<Type> {
+        match value {
+            Literal::TypedInteger(n, ty) => typed_integer(*n, ty),
+            value => Ok(value.default_type()),
         }
     }
+
+    fn visit_error(&mut self) -> Result<Type> {
+        Ok(Type::Unknown)
+    }
 }
 
-impl SemanticAnalyzer {
-    // Add new analysis methods for additional node types
-    fn analyze_pattern(&mut self, pattern: &Pattern, value_type: &Type) -> Result<()> {
-        match pattern {
-            Pattern::Literal(lit) => {
-                let lit_type = self.analyze(lit)?;
-                if !self.types_match(&lit_type, value_type) {
-                    return Err(IoError::type_error(format!(
-                        "Pattern type mismatch: expected {}, found {}",
-                        value_type, lit_type
-                    )));
-                }
-            }
-            Pattern::Variable(name) => {
-                self.current_scope.define(
-                    name.clone(),
-                    Symbol::Variable {
-                        name: name.clone(),
-                        type_name: value_type.to_string(),
-                        mutable: false,
-                    },
-                )?;
-            }
-            Pattern::Constructor { name, patterns } => {
-                if let Some(Type::Enum { variants, .. }) = self.current_scope.lookup_type(name) {
-                    if let Some(variant_types) = variants.get(name) {
-                        if patterns.len() != variant_types.len() {
-                            return Err(IoError::type_error(format!(
-                                "Wrong number of fields for enum variant {}", name
-                            )));
-                        }
-                        for (pattern, expected_type) in patterns.iter().zip(variant_types) {
-                            self.analyze_pattern(pattern, expected_type)?;
-                        }
-                    }
-                }
-            }
-            Pattern::Tuple(patterns) => {
-                if let Type::Tuple(types) = value_type {
-                    if patterns.len() != types.len() {
-                        return Err(IoError::type_error("Tuple pattern length mismatch"));
-                    }
-                    for (pattern, ty) in patterns.iter().zip(types) {
-                        self.analyze_pattern(pattern, ty)?;
-                    }
-                }
-            }
-            Pattern::Rest => {} // Rest pattern matches anything
+/// A method of an inherent impl as a trait method with a default body.
+fn inherent_method(method: &ASTNode) -> Option<TraitMethod> {
+    let ASTNode::Function {
+        name,
+        params,
+        return_type,
+        body,
+        id,
+        span,
+        ..
+    } = method
+    else {
+        return None;
+    };
+    Some(TraitMethod {
+        name: name.clone(),
+        params: params.clone(),
+        return_type: return_type.clone(),
+        body: Some(body.clone()),
+        id: *id,
+        span: *span,
+    })
+}
+
+/// The type of the suffixed literal `n` followed by `ty`, if it is in range.
+fn typed_integer(n: i128, ty: &Type) -> Result<Type> {
+    if ty.fits_integer(n) {
+        Ok(ty.clone())
+    } else {
+        Err(IoError::type_error(format!(
+            "Integer literal {} is out of range for {}",
+            n, ty
+        )))
+    }
+}
+
+/// The value of `node` if it is an unsuffixed integer literal, maybe negated.
+fn integer_literal(node: &ASTNode) -> Option<i128> {
+    match node {
+        ASTNode::Literal {
+            value: Literal::Integer(n),
+            ..
+        } => Some(*n as i128),
+        ASTNode::UnaryOp {
+            op: UnaryOperator::Negate,
+            operand,
+            ..
+        } => integer_literal(operand).map(|n| -n),
+        _ => None,
+    }
+}
+
+/// Whether `node` is an unsuffixed number literal, maybe negated, that can be
+/// a value of `expected`: `200` can be a `u8` and `1.5` an `f32`.
+fn literal_fits(node: &ASTNode, expected: &Type) -> bool {
+    let (literal, negated) = match node {
+        ASTNode::UnaryOp {
+            op: UnaryOperator::Negate,
+            operand,
+            ..
+        } => match operand.as_ref() {
+            ASTNode::Literal { value, .. } => (value, true),
+            _ => return false,
+        },
+        ASTNode::Literal { value, .. } => (value, false),
+        _ => return false,
+    };
+    match literal {
+        Literal::Integer(n) => {
+            let n = *n as i128;
+            expected.fits_integer(if negated { -n } else { n })
         }
-        Ok(())
+        Literal::Float(_) => expected.is_float(),
+        _ => false,
     }
+}
 
-    fn analyze_block(&mut self, nodes: &[ASTNode]) -> Result<Type> {
-        let mut block_type = Type::Unit;
-        for node in nodes {
-            block_type = self.analyze(node)?;
+/// How an operand reads in a suggested cast: an identifier or literal as
+/// written, anything else as `value`.
+fn operand_text(node: &ASTNode) -> String {
+    match node {
+        ASTNode::Identifier { name, .. } => name.clone(),
+        ASTNode::Literal { value, .. } => value.to_string(),
+        _ => "value".to_string(),
+    }
+}
+
+/// Whether a value of type `found` can be used where `expected` is. Unknown
+/// type arguments, like those of `Option::None`, fit any type.
+fn fits(expected: &Type, found: &Type) -> bool {
+    match (expected, found) {
+        (Type::Unknown, _) | (_, Type::Unknown) => true,
+        (
+            Type::Generic { name, args },
+            Type::Generic {
+                name: found_name,
+                args: found_args,
+            },
+        ) => {
+            name == found_name
+                && args.len() == found_args.len()
+                && args.iter().zip(found_args).all(|(arg, found)| fits(arg, found))
         }
-        Ok(block_type)
+        // Literals of generic structs are typed without their type arguments.
+        (Type::Generic { name, .. }, Type::Named(found)) => name == found,
+        _ => expected == found,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, semantic::resolve, span::FileId, visitor::Visitable};
+
+    fn analyze(source: &str) -> Result<Type> {
+        let program = parse_source(source, FileId(0)).unwrap();
+        let resolution = resolve::resolve(&program)?;
+        program.accept(&mut SemanticAnalyzer::new(&resolution))
+    }
+
+    #[test]
+    fn test_well_typed_program() {
+        let source = "fn main() { let x = twice(21); println(x); }\n\
+                      fn twice(n: int) -> int { return n * 2; }";
+        assert!(analyze(source).is_ok());
+    }
+
+    #[test]
+    fn test_errors_point_at_offending_node() {
+        let source = "fn f(n: int) -> bool { let ok = n < 1.5; return ok; }";
+        let err = analyze(source).unwrap_err();
+        assert!(err.message().contains("i32 and f64"), "{}", err.message());
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "n < 1.5");
+    }
+
+    #[test]
+    fn test_match_arms_see_variant_fields() {
+        let source = "\
+enum Shape { Circle(float), Rect { w: float, h: float } }
+fn area(s: Shape) -> float {
+    let a = match s {
+        Shape::Circle(r) => r * r,
+        Shape::Rect { w, h } if w > 0.0 => w * h,
+        _ => 0.0,
+    };
+    return a;
+}
+fn main() { let x = area(Shape::Rect { w: 1.0, h: 2.0 }); }";
+        assert!(analyze(source).is_ok());
+
+        let source = "enum Shape { Circle(float) }\n\
+                      fn f(s: Shape) -> bool { return match s { Shape::Circle(r) => r }; }";
+        let err = analyze(source).unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Return type mismatch: expected bool, found f64"
+        );
+    }
+
+    #[test]
+    fn test_parameters_are_immutable() {
+        let err = analyze("fn f(n: int) { n = 2; }").unwrap_err();
+        assert_eq!(err.message(), "Cannot assign to immutable variable n");
+    }
+
+    #[test]
+    fn test_method_calls_resolve_through_impls() {
+        let source = "\
+trait Area { fn area(self) -> float; fn double(self) -> float { return self.area() * 2.0; } }
+impl Area for float { fn area(self) -> float { return self * self; } }
+fn total<T: Area>(shape: T) -> float { return shape.double(); }
+fn main() {
+    let a = total(2.0);
+    let d: dyn Area = 1.5;
+    let b: float = d.area();
+}";
+        assert!(analyze(source).is_ok());
+
+        let err = analyze(&source.replace("total(2.0)", "total(true)")).unwrap_err();
+        assert_eq!(err.message(), "Type bool does not implement trait Area");
+        let err = analyze(&source.replace("d.area()", "d.volume()")).unwrap_err();
+        assert_eq!(err.message(), "Type dyn Area has no field volume");
+    }
+
+    #[test]
+    fn test_numeric_literals_fit_their_context() {
+        let source = "\
+fn low(b: u8) -> u8 { return b & 15; }
+fn main() {
+    let a: u8 = 200;
+    let b: i64 = 5;
+    let c = low(a) + 1;
+    let d = b + a as i64;
+    let e = wrapping_mul(a, 3);
+}";
+        assert!(analyze(source).is_ok());
+
+        let err = analyze("fn f(a: u8, b: i32) -> i32 { return b + a; }").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Invalid operand types for arithmetic operation: i32 and u8; \
+             convert one of them with `as`, e.g. `a as i32`"
+        );
+        let err = analyze("fn f() { let a: u8 = 256; }").unwrap_err();
+        assert_eq!(err.message(), "Integer literal 256 is out of range for u8");
+    }
+
+    #[test]
+    fn test_closures_see_their_parameters_and_captures() {
+        let source = "\
+fn main() {
+    let offset = 2;
+    let add = |x| x + offset;
+    let apply = |f, x: int| f(x);
+    let evens = filter([1, 2, 3], |x| x % 2 == 0);
+    let total = fold(evens, 0, |sum: int, x: int| -> int { return sum + x; });
+}";
+        assert!(analyze(source).is_ok());
+
+        let err = analyze("fn f() { let g = |x| x + y; }").unwrap_err();
+        assert_eq!(err.message(), "Undefined variable y");
+        let err = analyze("fn f() { while true { let g = || { break; }; } }").unwrap_err();
+        assert_eq!(err.message(), "Break statement outside loop");
+        let err = analyze("fn f() { let xs = map(1, |x| x); }").unwrap_err();
+        assert_eq!(err.message(), "Cannot iterate over i32");
+    }
+
+    #[test]
+    fn test_shadowed_variables_keep_their_own_types() {
+        let source = "\
+fn main() {
+    let x = 1;
+    let x = \"one\";
+    { let x = true; }
+    let s: str = x;
+    let n = 2;
+    if true { let n = 1.5; }
+    let m: int = n + 1;
+}";
+        assert!(analyze(source).is_ok());
+
+        let err = analyze("fn f() { { let y = 1; } let z = y; }").unwrap_err();
+        assert_eq!(err.message(), "Undefined variable y");
     }
 }
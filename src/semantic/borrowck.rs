@@ -0,0 +1,1171 @@
+use crate::{
+    ast::{captures, ASTNode, NodeId, Parameter, Pattern, Type, UnaryOperator},
+    compiler::control_flow::{BasicBlock, ControlFlowGraph, Statement},
+    error::IoError,
+    span::Span,
+    visitor::{walk_node, walk_place, Visitor},
+    Result,
+};
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// A variable of the body being checked, as an index into its locals.
+type Local = usize;
+
+/// The locals that may still be read, each with the span of such a read.
+type Live = BTreeMap<Local, Span>;
+
+/// Checks the ownership rules of `docs/advanced/memory-management.md`. A value
+/// whose type isn't copied moves when it is bound, assigned, passed to a
+/// function of the program or returned, and its variable can't be used until
+/// it is assigned again. A variable has either one `&mut` borrow or any number
+/// of `&` borrows in use, and no borrow outlives it.
+///
+/// A borrow held by a variable is in use for as long as that variable may
+/// still be read, found by a liveness analysis over each function's
+/// [`ControlFlowGraph`]; a borrow that isn't bound ends with its statement.
+pub struct BorrowChecker {
+    binding_types: HashMap<NodeId, Type>,
+    /// Functions and variant constructors of the program. Calls to anything
+    /// else reach builtins, which only read their arguments.
+    functions: HashSet<String>,
+    moves: HashSet<NodeId>,
+}
+
+impl BorrowChecker {
+    /// `binding_types` are the variable types found by the type checker; a
+    /// variable missing from it is treated as copied.
+    pub fn new(binding_types: HashMap<NodeId, Type>) -> Self {
+        Self {
+            binding_types,
+            functions: HashSet::new(),
+            moves: HashSet::new(),
+        }
+    }
+
+    /// Checks every function and method of `program`, then its top-level
+    /// statements as one body. Errors point at the offending use, with notes
+    /// at the move or borrow it conflicts with.
+    pub fn check(&mut self, program: &ASTNode) -> Result<()> {
+        let items = match program {
+            ASTNode::Program(items) => items.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+        for item in items {
+            match item {
+                ASTNode::Function { name, .. } => {
+                    self.functions.insert(name.clone());
+                }
+                ASTNode::EnumDef { name, variants, .. } => {
+                    for variant in variants {
+                        self.functions.insert(format!("{}::{}", name, variant.name));
+                        self.functions.insert(variant.name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut statements = Vec::new();
+        for item in items {
+            match item {
+                ASTNode::Function {
+                    params,
+                    return_type,
+                    body,
+                    ..
+                } => self.check_body(params, return_type.as_ref(), body)?,
+                ASTNode::Impl { methods, .. } => {
+                    for method in methods {
+                        if let ASTNode::Function {
+                            params,
+                            return_type,
+                            body,
+                            ..
+                        } = method
+                        {
+                            self.check_body(params, return_type.as_ref(), body)?;
+                        }
+                    }
+                }
+                ASTNode::TraitDef { methods, .. } => {
+                    for method in methods {
+                        if let Some(body) = &method.body {
+                            self.check_body(&method.params, method.return_type.as_ref(), body)?;
+                        }
+                    }
+                }
+                ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } => {}
+                statement => statements.push(statement.clone()),
+            }
+        }
+        self.check_body(&[], None, &statements)
+    }
+
+    /// Identifiers whose use moves the value out of their variable, by node id.
+    pub fn moves(&self) -> &HashSet<NodeId> {
+        &self.moves
+    }
+
+    fn check_body(
+        &mut self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &[ASTNode],
+    ) -> Result<()> {
+        let mut cfg = ControlFlowGraph::new();
+        cfg.analyze_function(body)?;
+
+        let mut checker = BodyChecker {
+            checker: self,
+            locals: Vec::new(),
+            scopes: vec![HashMap::new()],
+            uses: HashMap::new(),
+            declarations: HashMap::new(),
+            closures: HashMap::new(),
+            returns_ref: return_type.is_some_and(Type::has_refs),
+        };
+        for param in params {
+            let ty = checker
+                .checker
+                .binding_types
+                .get(&param.id)
+                .unwrap_or(&param.type_annotation)
+                .clone();
+            checker.declare(param.id, &param.name, ty);
+        }
+        for node in body {
+            checker.visit_node(node)?;
+        }
+        checker.run(&cfg)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LocalInfo {
+    name: String,
+    ty: Type,
+}
+
+/// A borrow of `place` made at `span`. A loan without a `holder` belongs to
+/// the statement making it.
+#[derive(Debug, Clone, PartialEq)]
+struct Loan {
+    place: Local,
+    mutable: bool,
+    span: Span,
+    holder: Option<Local>,
+}
+
+/// What may hold on entry to a statement, over every path reaching it.
+#[derive(Debug, Clone, Default)]
+struct State {
+    /// Locals that may have been moved out of, with where.
+    moved: BTreeMap<Local, Span>,
+    /// Loans held by locals.
+    loans: Vec<Loan>,
+    /// Loans in the value yielded to a `Complete` statement.
+    pending: Vec<Loan>,
+}
+
+impl State {
+    /// Adds what may hold in `other`; returns whether that changed anything.
+    fn join(&mut self, other: &State) -> bool {
+        let mut changed = false;
+        for (&local, &span) in &other.moved {
+            if let Entry::Vacant(entry) = self.moved.entry(local) {
+                entry.insert(span);
+                changed = true;
+            }
+        }
+        for (mine, theirs) in [
+            (&mut self.loans, &other.loans),
+            (&mut self.pending, &other.pending),
+        ] {
+            for loan in theirs {
+                if !mine.contains(loan) {
+                    mine.push(loan.clone());
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Loans that conflict with accessing `local`, given the locals `live`
+    /// after the access.
+    fn loans_on<'s>(&'s self, local: Local, live: &'s Live) -> impl Iterator<Item = &'s Loan> {
+        self.loans.iter().filter(move |loan| {
+            loan.place == local && loan.holder.is_some_and(|holder| live.contains_key(&holder))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Read,
+    Move,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Access {
+    Read,
+    Move,
+    Write,
+    Borrow { mutable: bool },
+}
+
+/// One statement being checked.
+struct Step<'s> {
+    state: &'s mut State,
+    /// Locals read by this statement or after it.
+    live: &'s Live,
+    /// Borrows made by the statement so far, in use until it ends.
+    temporaries: Vec<Loan>,
+}
+
+/// Checks one function body.
+struct BodyChecker<'a> {
+    checker: &'a mut BorrowChecker,
+    locals: Vec<LocalInfo>,
+    scopes: Vec<HashMap<String, Local>>,
+    /// The local each identifier, assignment and compound assignment refers
+    /// to, by node id.
+    uses: HashMap<NodeId, Local>,
+    /// The locals each `let`, parameter, `for` loop and match arm declares,
+    /// by node id.
+    declarations: HashMap<NodeId, Vec<Local>>,
+    /// The locals each closure captures, each with whether the closure
+    /// assigns it, by node id.
+    closures: HashMap<NodeId, Vec<(Local, bool)>>,
+    /// Whether the function's result may hold a reference.
+    returns_ref: bool,
+}
+
+impl BodyChecker<'_> {
+    fn declare(&mut self, id: NodeId, name: &str, ty: Type) {
+        let local = self.locals.len();
+        self.locals.push(LocalInfo {
+            name: name.to_string(),
+            ty,
+        });
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), local);
+        }
+        self.declarations.entry(id).or_default().push(local);
+    }
+
+    fn refer(&mut self, id: NodeId, name: &str) {
+        if let Some(&local) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            self.uses.insert(id, local);
+        }
+    }
+
+    fn scoped(&mut self, statements: &[ASTNode]) -> Result<()> {
+        self.scopes.push(HashMap::new());
+        let result = statements.iter().try_for_each(|node| self.visit_node(node));
+        self.scopes.pop();
+        result
+    }
+
+    fn binding_type(&self, id: NodeId) -> Type {
+        self.checker
+            .binding_types
+            .get(&id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Runs the liveness analysis, then follows moves and borrows through
+    /// `cfg` until nothing changes.
+    fn run(&mut self, cfg: &ControlFlowGraph) -> Result<()> {
+        let live_out = self.liveness(cfg);
+        let mut states = HashMap::from([(cfg.entry(), State::default())]);
+        let mut worklist = BTreeSet::from([cfg.entry()]);
+
+        while let Some(id) = worklist.pop_first() {
+            let Some(block) = cfg.block(id) else {
+                continue;
+            };
+            let live = self.live_before_each(block, live_out[&id].clone());
+            let mut state = states[&id].clone();
+            for (i, statement) in block.statements().iter().enumerate() {
+                self.step(statement, &mut state, &live[i])?;
+                // A loan ends once no one can read the local holding it.
+                state.loans.retain(|loan| {
+                    loan.holder
+                        .is_some_and(|holder| live[i + 1].contains_key(&holder))
+                });
+            }
+            for &successor in block.successors() {
+                let changed = match states.get_mut(&successor) {
+                    Some(existing) => existing.join(&state),
+                    None => {
+                        states.insert(successor, state.clone());
+                        true
+                    }
+                };
+                if changed {
+                    worklist.insert(successor);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The locals live on exit from each block.
+    fn liveness(&self, cfg: &ControlFlowGraph) -> HashMap<usize, Live> {
+        let ids: Vec<usize> = cfg.blocks().map(BasicBlock::id).collect();
+        let mut live_in: HashMap<usize, Live> = HashMap::new();
+        let mut live_out: HashMap<usize, Live> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &id in ids.iter().rev() {
+                let block = cfg.block(id).expect("listed blocks exist");
+                let mut out = Live::new();
+                for successor in block.successors() {
+                    for (&local, &span) in live_in.get(successor).into_iter().flatten() {
+                        out.entry(local).or_insert(span);
+                    }
+                }
+                let before = self.live_before_each(block, out.clone()).swap_remove(0);
+                let known = live_in.get(&id).map(|live| live.keys().eq(before.keys()));
+                if known != Some(true) {
+                    live_in.insert(id, before);
+                    changed = true;
+                }
+                live_out.insert(id, out);
+            }
+        }
+        live_out
+    }
+
+    /// The locals live before each statement of `block`, then those live
+    /// after its last.
+    fn live_before_each(&self, block: &BasicBlock, live_out: Live) -> Vec<Live> {
+        let mut live = vec![live_out];
+        for statement in block.statements().iter().rev() {
+            let mut before = live.last().expect("starts with live_out").clone();
+            for local in self.defined(statement) {
+                before.remove(&local);
+            }
+            for (local, span) in self.used(statement) {
+                before.insert(local, span);
+            }
+            live.push(before);
+        }
+        live.reverse();
+        live
+    }
+
+    /// The locals `statement` assigns.
+    fn defined(&self, statement: &Statement) -> Vec<Local> {
+        let id = match statement {
+            Statement::Node(node) | Statement::Complete(node) => match node {
+                ASTNode::Let { id, .. } | ASTNode::LetPattern { id, .. } => *id,
+                // Assigning a field or element leaves the rest of the value.
+                ASTNode::Assignment { target, id, .. }
+                    if matches!(**target, ASTNode::Identifier { .. }) =>
+                {
+                    *id
+                }
+                _ => return Vec::new(),
+            },
+            Statement::Bind { id, .. } => *id,
+            _ => return Vec::new(),
+        };
+        match self.declarations.get(&id) {
+            Some(locals) => locals.clone(),
+            None => self.uses.get(&id).copied().into_iter().collect(),
+        }
+    }
+
+    /// The locals `statement` reads, with where.
+    fn used(&self, statement: &Statement) -> Vec<(Local, Span)> {
+        let mut uses = Uses {
+            uses: &self.uses,
+            found: Vec::new(),
+        };
+        if let Statement::Node(node) | Statement::Branch(node) | Statement::Yield(node) = statement
+        {
+            // Finding uses cannot fail.
+            let _ = uses.visit_node(node);
+        }
+        uses.found
+    }
+
+    fn step(&mut self, statement: &Statement, state: &mut State, live: &Live) -> Result<()> {
+        let mut step = Step {
+            state,
+            live,
+            temporaries: Vec::new(),
+        };
+        match statement {
+            Statement::Node(node) | Statement::Branch(node) => {
+                self.expr(node, Mode::Read, &mut step)?;
+            }
+            Statement::Yield(node) => {
+                let flows = self.expr(node, Mode::Move, &mut step)?;
+                step.state.pending.extend(flows);
+            }
+            Statement::Complete(node) => {
+                let flows = std::mem::take(&mut step.state.pending);
+                self.complete(node, flows, &mut step)?;
+            }
+            Statement::Bind { id, .. } => {
+                for local in self.declarations.get(id).cloned().unwrap_or_default() {
+                    self.bind(local, &[], &mut step);
+                }
+            }
+            Statement::ScopeEnd { declarations, span } => {
+                for id in declarations {
+                    for local in self.declarations.get(id).cloned().unwrap_or_default() {
+                        self.end(local, *span, &mut step)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks `node` as evaluated in `mode` and returns the loans its value
+    /// may carry.
+    fn expr(&mut self, node: &ASTNode, mode: Mode, step: &mut Step) -> Result<Vec<Loan>> {
+        match node {
+            ASTNode::Identifier { id, span, .. } => match self.uses.get(id) {
+                Some(&local) => self.use_local(local, *id, *span, mode, step),
+                None => Ok(Vec::new()),
+            },
+            ASTNode::UnaryOp {
+                op: op @ (UnaryOperator::Ref | UnaryOperator::RefMut),
+                operand,
+                span,
+                ..
+            } => self.borrow(operand, *op == UnaryOperator::RefMut, *span, step),
+            ASTNode::UnaryOp { operand, .. }
+            | ASTNode::Cast { value: operand, .. }
+            | ASTNode::MemberAccess {
+                object: operand, ..
+            } => self.expr(operand, Mode::Read, step),
+            ASTNode::Index { array, index, .. } => {
+                let flows = self.expr(array, Mode::Read, step)?;
+                self.expr(index, Mode::Read, step)?;
+                Ok(flows)
+            }
+            ASTNode::BinaryOp { left, right, .. } => {
+                self.expr(left, Mode::Read, step)?;
+                self.expr(right, Mode::Read, step)?;
+                Ok(Vec::new())
+            }
+            ASTNode::Call { callee, args, .. } => {
+                let (mut flows, arg_mode) = match callee.as_ref() {
+                    ASTNode::Identifier { name, id, .. } if !self.uses.contains_key(id) => {
+                        let defined = self.checker.functions.contains(name);
+                        (Vec::new(), if defined { Mode::Move } else { Mode::Read })
+                    }
+                    callee => (self.expr(callee, Mode::Read, step)?, Mode::Move),
+                };
+                // The result may borrow from any argument.
+                for arg in args {
+                    flows.extend(self.expr(arg, arg_mode, step)?);
+                }
+                Ok(flows)
+            }
+            ASTNode::ArrayLiteral { elements, .. } | ASTNode::TupleLiteral { elements, .. } => {
+                let mut flows = Vec::new();
+                for element in elements {
+                    flows.extend(self.expr(element, Mode::Move, step)?);
+                }
+                Ok(flows)
+            }
+            ASTNode::StructLiteral { fields, .. } => {
+                let mut flows = Vec::new();
+                for (_, value) in fields {
+                    flows.extend(self.expr(value, Mode::Move, step)?);
+                }
+                Ok(flows)
+            }
+            ASTNode::Let {
+                value: Some(value), ..
+            }
+            | ASTNode::LetPattern { value, .. }
+            | ASTNode::Assignment { value, .. }
+            | ASTNode::Return {
+                value: Some(value), ..
+            } => {
+                let flows = self.expr(value, Mode::Move, step)?;
+                self.complete(node, flows, step)?;
+                Ok(Vec::new())
+            }
+            ASTNode::CompoundAssignment {
+                target,
+                value,
+                id,
+                span,
+                ..
+            } => {
+                self.expr(value, Mode::Read, step)?;
+                self.place_indices(target, step)?;
+                if let Some(&local) = self.uses.get(id) {
+                    self.access(local, *span, Access::Read, step)?;
+                    self.access(local, *span, Access::Write, step)?;
+                }
+                Ok(Vec::new())
+            }
+            // Control flow the graph left inside a node: each branch starts
+            // from the state before it, and what follows sees all of them.
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition, Mode::Read, step)?;
+                let before = step.state.clone();
+                let mut flows = self.nested(then_branch, mode, step)?;
+                if let Some(else_branch) = else_branch {
+                    let after_then = std::mem::replace(step.state, before);
+                    flows.extend(self.nested(else_branch, mode, step)?);
+                    step.state.join(&after_then);
+                } else {
+                    step.state.join(&before);
+                }
+                Ok(flows)
+            }
+            ASTNode::Match {
+                scrutinee, arms, ..
+            } => {
+                self.expr(scrutinee, Mode::Read, step)?;
+                let before = step.state.clone();
+                let mut after = before.clone();
+                let mut flows = Vec::new();
+                for arm in arms {
+                    *step.state = before.clone();
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard, Mode::Read, step)?;
+                    }
+                    flows.extend(self.expr(&arm.body, mode, step)?);
+                    after.join(step.state);
+                }
+                *step.state = after;
+                Ok(flows)
+            }
+            ASTNode::Block { statements, .. } => self.nested(statements, mode, step),
+            // A `move` closure takes its captures; any other borrows them, and
+            // mutably those it assigns.
+            ASTNode::Closure {
+                is_move, id, span, ..
+            } => {
+                let mut flows = Vec::new();
+                for (local, assigned) in self.closures.get(id).cloned().unwrap_or_default() {
+                    if *is_move {
+                        flows.extend(self.use_local(local, *id, *span, Mode::Move, step)?);
+                        continue;
+                    }
+                    self.access(local, *span, Access::Borrow { mutable: assigned }, step)?;
+                    let loan = Loan {
+                        place: local,
+                        mutable: assigned,
+                        span: *span,
+                        holder: None,
+                    };
+                    step.temporaries.push(loan.clone());
+                    flows.push(loan);
+                }
+                Ok(flows)
+            }
+            ASTNode::While {
+                condition: head,
+                body,
+                ..
+            }
+            | ASTNode::For {
+                iterable: head,
+                body,
+                ..
+            } => {
+                self.expr(head, Mode::Read, step)?;
+                self.nested(body, Mode::Read, step)?;
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Checks `statements`, the last of which gives the value.
+    fn nested(&mut self, statements: &[ASTNode], mode: Mode, step: &mut Step) -> Result<Vec<Loan>> {
+        let mut flows = Vec::new();
+        for (i, statement) in statements.iter().enumerate() {
+            let statement_mode = if i + 1 == statements.len() {
+                mode
+            } else {
+                Mode::Read
+            };
+            flows = self.expr(statement, statement_mode, step)?;
+        }
+        Ok(flows)
+    }
+
+    /// A `let`, assignment or `return` of a value carrying `flows`.
+    fn complete(&mut self, node: &ASTNode, flows: Vec<Loan>, step: &mut Step) -> Result<()> {
+        match node {
+            ASTNode::Let { id, .. } | ASTNode::LetPattern { id, .. } => {
+                for local in self.declarations.get(id).cloned().unwrap_or_default() {
+                    self.bind(local, &flows, step);
+                }
+            }
+            ASTNode::Assignment {
+                target, id, span, ..
+            } => {
+                self.place_indices(target, step)?;
+                let Some(&local) = self.uses.get(id) else {
+                    return Ok(());
+                };
+                if matches!(**target, ASTNode::Identifier { .. }) {
+                    self.access(local, *span, Access::Write, step)?;
+                    self.bind(local, &flows, step);
+                } else {
+                    // A field or element is assigned in place, so the rest
+                    // of the value must still be there.
+                    self.access(local, *span, Access::Read, step)?;
+                    self.access(local, *span, Access::Write, step)?;
+                    self.hold(local, &flows, step);
+                }
+            }
+            ASTNode::Return { span, .. } if self.returns_ref => {
+                if let Some(loan) = flows.first() {
+                    return Err(IoError::validation_error(format!(
+                        "Cannot return a reference to local variable `{}`",
+                        self.locals[loan.place].name
+                    ))
+                    .with_span(loan.span)
+                    .with_note(*span, "the reference is returned here"));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Reads the indices of the field or element `target` assigns.
+    fn place_indices(&mut self, target: &ASTNode, step: &mut Step) -> Result<()> {
+        match target {
+            ASTNode::MemberAccess { object, .. } => self.place_indices(object, step),
+            ASTNode::Index { array, index, .. } => {
+                self.expr(index, Mode::Read, step)?;
+                self.place_indices(array, step)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Gives `local` a new value carrying `flows`.
+    fn bind(&mut self, local: Local, flows: &[Loan], step: &mut Step) {
+        step.state.moved.remove(&local);
+        step.state.loans.retain(|loan| loan.holder != Some(local));
+        self.hold(local, flows, step);
+    }
+
+    /// Adds the loans in `flows` to those `local` holds.
+    fn hold(&self, local: Local, flows: &[Loan], step: &mut Step) {
+        if self.locals[local].ty.has_refs() {
+            for loan in flows {
+                let held = Loan {
+                    holder: Some(local),
+                    ..loan.clone()
+                };
+                if !step.state.loans.contains(&held) {
+                    step.state.loans.push(held);
+                }
+            }
+        }
+    }
+
+    fn use_local(
+        &mut self,
+        local: Local,
+        id: NodeId,
+        span: Span,
+        mode: Mode,
+        step: &mut Step,
+    ) -> Result<Vec<Loan>> {
+        let moves = mode == Mode::Move && !self.locals[local].ty.is_copy();
+        let access = if moves { Access::Move } else { Access::Read };
+        self.access(local, span, access, step)?;
+        let flows = step
+            .state
+            .loans
+            .iter()
+            .filter(|loan| loan.holder == Some(local))
+            .map(|loan| Loan {
+                holder: None,
+                ..loan.clone()
+            })
+            .collect();
+        if moves {
+            step.state.moved.insert(local, span);
+            self.checker.moves.insert(id);
+        }
+        Ok(flows)
+    }
+
+    /// `&operand` or `&mut operand`, made at `span`.
+    fn borrow(
+        &mut self,
+        operand: &ASTNode,
+        mutable: bool,
+        span: Span,
+        step: &mut Step,
+    ) -> Result<Vec<Loan>> {
+        // A field or element is borrowed by borrowing the variable it is in.
+        let mut place = operand;
+        let mut projected = false;
+        loop {
+            match place {
+                ASTNode::MemberAccess { object, .. }
+                | ASTNode::UnaryOp {
+                    op: UnaryOperator::Deref,
+                    operand: object,
+                    ..
+                } => place = object,
+                ASTNode::Index { array, index, .. } => {
+                    self.expr(index, Mode::Read, step)?;
+                    place = array;
+                }
+                _ => break,
+            }
+            projected = true;
+        }
+        let ASTNode::Identifier { id, .. } = place else {
+            return self.expr(operand, Mode::Move, step);
+        };
+        let Some(&local) = self.uses.get(id) else {
+            return Ok(Vec::new());
+        };
+        if projected && matches!(self.locals[local].ty, Type::Ref { .. }) {
+            // Borrowing through a reference keeps whatever it borrows.
+            return self.use_local(local, *id, span, Mode::Read, step);
+        }
+
+        self.access(local, span, Access::Borrow { mutable }, step)?;
+        let loan = Loan {
+            place: local,
+            mutable,
+            span,
+            holder: None,
+        };
+        step.temporaries.push(loan.clone());
+        Ok(vec![loan])
+    }
+
+    /// Checks that `local` can be accessed at `span`: it must not have been
+    /// moved out of, and no borrow in use may conflict.
+    fn access(&self, local: Local, span: Span, access: Access, step: &Step) -> Result<()> {
+        let name = &self.locals[local].name;
+        if !matches!(access, Access::Write) {
+            if let Some(&moved_at) = step.state.moved.get(&local) {
+                let what = match access {
+                    Access::Borrow { .. } => "Borrow of",
+                    _ => "Use of",
+                };
+                let note = if moved_at == span {
+                    "value moved here, in a previous iteration of the loop"
+                } else {
+                    "value moved here"
+                };
+                return Err(
+                    IoError::validation_error(format!("{} moved value `{}`", what, name))
+                        .with_span(span)
+                        .with_note(moved_at, note),
+                );
+            }
+        }
+
+        let conflict = step
+            .temporaries
+            .iter()
+            .filter(|loan| loan.place == local)
+            .chain(step.state.loans_on(local, step.live))
+            .find(|loan| match access {
+                Access::Read | Access::Borrow { mutable: false } => loan.mutable,
+                Access::Move | Access::Write | Access::Borrow { mutable: true } => true,
+            });
+        let Some(loan) = conflict else {
+            return Ok(());
+        };
+        let message = match access {
+            Access::Read => format!("Cannot use `{}` because it is mutably borrowed", name),
+            Access::Move => format!("Cannot move out of `{}` because it is borrowed", name),
+            Access::Write => format!("Cannot assign to `{}` because it is borrowed", name),
+            Access::Borrow { mutable: false } => format!(
+                "Cannot borrow `{}` as immutable because it is also borrowed as mutable",
+                name
+            ),
+            Access::Borrow { mutable: true } if loan.mutable => format!(
+                "Cannot borrow `{}` as mutable more than once at a time",
+                name
+            ),
+            Access::Borrow { mutable: true } => format!(
+                "Cannot borrow `{}` as mutable because it is also borrowed as immutable",
+                name
+            ),
+        };
+        let kind = if loan.mutable { "mutable" } else { "immutable" };
+        let mut err = IoError::validation_error(message)
+            .with_span(span)
+            .with_note(loan.span, format!("{} borrow occurs here", kind));
+        if let Some(&used) = loan.holder.and_then(|holder| step.live.get(&holder)) {
+            err = err.with_note(used, "borrow later used here");
+        }
+        Err(err)
+    }
+
+    /// `local` goes out of scope at `span`; nothing may still borrow it.
+    fn end(&self, local: Local, span: Span, step: &mut Step) -> Result<()> {
+        let dangling = step
+            .state
+            .loans_on(local, step.live)
+            .chain(step.state.pending.iter().filter(|loan| loan.place == local))
+            .next();
+        if let Some(loan) = dangling {
+            let name = &self.locals[local].name;
+            let mut err =
+                IoError::validation_error(format!("`{}` does not live long enough", name))
+                    .with_span(loan.span)
+                    .with_note(
+                        span,
+                        format!("`{}` goes out of scope here while still borrowed", name),
+                    );
+            if let Some(&used) = loan.holder.and_then(|holder| step.live.get(&holder)) {
+                err = err.with_note(used, "borrow later used here");
+            }
+            return Err(err);
+        }
+        step.state.moved.remove(&local);
+        step.state
+            .loans
+            .retain(|loan| loan.holder != Some(local) && loan.place != local);
+        Ok(())
+    }
+}
+
+/// Resolves the variables of a body in source order, giving shadowing
+/// declarations locals of their own.
+impl Visitor for BodyChecker<'_> {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Identifier { name, id, .. } => {
+                self.refer(*id, name);
+                Ok(())
+            }
+            ASTNode::Let {
+                name, value, id, ..
+            } => {
+                if let Some(value) = value {
+                    self.visit_node(value)?;
+                }
+                let ty = self.binding_type(*id);
+                self.declare(*id, name, ty);
+                Ok(())
+            }
+            ASTNode::LetPattern {
+                pattern, value, id, ..
+            } => {
+                self.visit_node(value)?;
+                for (name, binding) in pattern.binding_ids() {
+                    let ty = self.binding_type(binding);
+                    self.declare(*id, name, ty);
+                }
+                Ok(())
+            }
+            ASTNode::Assignment {
+                target, value, id, ..
+            }
+            | ASTNode::CompoundAssignment {
+                target, value, id, ..
+            } => {
+                self.visit_node(value)?;
+                if let Some(root) = target.place_root() {
+                    self.refer(*id, root);
+                }
+                walk_place(self, target)
+            }
+            ASTNode::Block { statements, .. } => self.scoped(statements),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.visit_node(condition)?;
+                self.scoped(then_branch)?;
+                match else_branch {
+                    Some(else_branch) => self.scoped(else_branch),
+                    None => Ok(()),
+                }
+            }
+            ASTNode::While {
+                condition, body, ..
+            } => {
+                self.visit_node(condition)?;
+                self.scoped(body)
+            }
+            ASTNode::For {
+                pattern,
+                iterable,
+                body,
+                id,
+                ..
+            } => {
+                self.visit_node(iterable)?;
+                self.scopes.push(HashMap::new());
+                match pattern {
+                    Pattern::Binding { name, .. } => {
+                        let ty = self.binding_type(*id);
+                        self.declare(*id, name, ty);
+                    }
+                    pattern => {
+                        for (name, _) in pattern.bindings() {
+                            self.declare(*id, name, Type::Unknown);
+                        }
+                    }
+                }
+                let result = self.scoped(body);
+                self.scopes.pop();
+                result
+            }
+            ASTNode::Match {
+                scrutinee, arms, ..
+            } => {
+                self.visit_node(scrutinee)?;
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    for (name, _) in arm.pattern.bindings() {
+                        self.declare(arm.id, name, Type::Unknown);
+                    }
+                    let result = arm
+                        .guard
+                        .iter()
+                        .chain([&arm.body])
+                        .try_for_each(|node| self.visit_node(node));
+                    self.scopes.pop();
+                    result?;
+                }
+                Ok(())
+            }
+            ASTNode::Closure {
+                params, body, id, ..
+            } => {
+                let captured = captures(params, body)
+                    .into_iter()
+                    .filter_map(|capture| {
+                        let mut scopes = self.scopes.iter().rev();
+                        let local = scopes.find_map(|scope| scope.get(&capture.name))?;
+                        Some((*local, capture.assigned))
+                    })
+                    .collect();
+                self.closures.insert(*id, captured);
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    let ty = self.binding_type(param.id);
+                    self.declare(param.id, &param.name, ty);
+                }
+                let result = self.visit_node(body);
+                self.scopes.pop();
+                result
+            }
+            ASTNode::Function { .. }
+            | ASTNode::StructDef { .. }
+            | ASTNode::EnumDef { .. }
+            | ASTNode::TraitDef { .. }
+            | ASTNode::Impl { .. } => Ok(()),
+            _ => walk_node(self, node),
+        }
+    }
+}
+
+/// Collects the locals a node reads.
+struct Uses<'a> {
+    uses: &'a HashMap<NodeId, Local>,
+    found: Vec<(Local, Span)>,
+}
+
+impl Visitor for Uses<'_> {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Identifier { id, span, .. } | ASTNode::CompoundAssignment { id, span, .. } => {
+                if let Some(&local) = self.uses.get(id) {
+                    self.found.push((local, *span));
+                }
+            }
+            ASTNode::Function { .. } | ASTNode::Impl { .. } | ASTNode::TraitDef { .. } => {
+                return Ok(())
+            }
+            _ => {}
+        }
+        walk_node(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::parse_source, semantic::resolve, span::FileId, types::checker::TypeChecker,
+    };
+
+    fn check(source: &str) -> Result<BorrowChecker> {
+        let program = parse_source(source, FileId(0)).unwrap();
+        let resolution = resolve::resolve(&program).unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check(&program, &resolution).unwrap();
+        let mut borrowck = BorrowChecker::new(checker.binding_types());
+        borrowck.check(&program)?;
+        Ok(borrowck)
+    }
+
+    /// The error's message, then the source text of its span and its notes.
+    fn error(source: &str) -> Vec<String> {
+        let err = check(source).err().expect("expected an ownership error");
+        let text = |span: Span| source[span.start..span.end].to_string();
+        let mut parts = vec![err.message().to_string(), text(err.span().unwrap())];
+        for (span, note) in err.notes() {
+            parts.push(format!("{}: {}", note, text(*span)));
+        }
+        parts
+    }
+
+    #[test]
+    fn test_moves_and_borrows_that_follow_the_rules() {
+        let source = "\
+fn consume(s: string) {}
+fn first(xs: &[int]) -> &int { return &xs[0]; }
+fn main(c: bool) {
+    let s = \"hello\";
+    let n = 1;
+    let m = n;
+    println(s);
+    let t = match c { true => s, _ => \"other\" };
+    consume(t);
+
+    let data = [1, 2, 3];
+    let r1 = &data;
+    let r2 = &data;
+    println(*r1, *r2);
+    let r3 = &mut data;
+    println(*r3);
+    let x = first(&data);
+
+    let u = \"again\";
+    while c {
+        consume(u);
+        u = \"fresh\";
+    }
+}";
+        let borrowck = check(source).unwrap();
+        // `s` into `t`, then `t` and `u` into `consume`.
+        assert_eq!(borrowck.moves().len(), 3);
+    }
+
+    #[test]
+    fn test_use_after_move() {
+        assert_eq!(
+            error("fn f(a: string) { let b = a; println(a); }"),
+            ["Use of moved value `a`", "a", "value moved here: a",]
+        );
+        assert_eq!(
+            error(
+                "fn consume(s: string) {}\n\
+                 fn f(c: bool, s: string) { if c { consume(s); } let t = &s; }"
+            ),
+            ["Borrow of moved value `s`", "&s", "value moved here: s"]
+        );
+        assert_eq!(
+            error("fn consume(s: string) {}\nfn f(s: string) { while true { consume(s); } }")[2],
+            "value moved here, in a previous iteration of the loop: s"
+        );
+    }
+
+    #[test]
+    fn test_conflicting_borrows() {
+        assert_eq!(
+            error("fn f() { let data = [1, 2]; let r = &data; let m = &mut data; println(*r); }"),
+            [
+                "Cannot borrow `data` as mutable because it is also borrowed as immutable",
+                "&mut data",
+                "immutable borrow occurs here: &data",
+                "borrow later used here: r",
+            ]
+        );
+        assert_eq!(
+            error("fn f() { let n = 1; let r = &mut n; n = 2; println(*r); }")[..2],
+            ["Cannot assign to `n` because it is borrowed", "n = 2"]
+        );
+        assert_eq!(
+            error("fn g(a: &mut int, b: &int) {}\nfn f() { let n = 1; g(&mut n, &n); }"),
+            [
+                "Cannot borrow `n` as immutable because it is also borrowed as mutable",
+                "&n",
+                "mutable borrow occurs here: &mut n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_must_not_outlive_their_referent() {
+        assert_eq!(
+            error("fn f() -> &int { let n = 1; return &n; }"),
+            [
+                "Cannot return a reference to local variable `n`",
+                "&n",
+                "the reference is returned here: return &n;",
+            ]
+        );
+        assert_eq!(
+            error(
+                "fn f(c: bool) { let a = 1; let r = &a; if c { let b = 2; r = &b; } println(*r); }"
+            ),
+            [
+                "`b` does not live long enough",
+                "&b",
+                "`b` goes out of scope here while still borrowed: }",
+                "borrow later used here: r",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_closures_borrow_or_move_their_captures() {
+        check(
+            "fn f() -> int {\n\
+             let count = 0; let bump = || { count += 1; }; bump(); bump();\n\
+             let s = \"text\"; let show = move || { println(s); }; show();\n\
+             return count; }",
+        )
+        .unwrap();
+        assert_eq!(
+            error("fn f() { let n = 1; let bump = || { n += 1; }; println(n); bump(); }")[..3],
+            [
+                "Cannot use `n` because it is mutably borrowed",
+                "n",
+                "mutable borrow occurs here: || { n += 1; }",
+            ]
+        );
+        assert_eq!(
+            error("fn f(s: string) { let g = move || { println(s); }; println(s); }"),
+            [
+                "Use of moved value `s`",
+                "s",
+                "value moved here: move || { println(s); }"
+            ]
+        );
+        assert_eq!(
+            error("fn f() -> fn() -> int { let n = 1; return || n; }")[..2],
+            ["Cannot return a reference to local variable `n`", "|| n"]
+        );
+    }
+}
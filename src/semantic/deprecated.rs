@@ -0,0 +1,176 @@
+//! Warnings for uses of functions and structs marked `#[deprecated]`, or
+//! defined in a module marked `#![deprecated]`. `io build` and `io run` report
+//! them as they compile and run a program; the `deprecated` lint reports the
+//! same ones at the level `io.toml` gives it.
+
+use crate::{
+    ast::{ASTNode, Attribute},
+    diagnostics::Diagnostic,
+    semantic::resolve::Resolution,
+    span::Span,
+    visitor::{walk_node, walk_nodes, Visitor},
+    Result,
+};
+
+/// The uses of deprecated items in `program`, whose names `resolution`
+/// resolved.
+pub fn check(program: &ASTNode, resolution: &Resolution) -> Result<Vec<Diagnostic>> {
+    let items = match program {
+        ASTNode::Program(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    uses(items, resolution)
+}
+
+/// Gives the functions, structs and methods among `items` the
+/// `#![deprecated]` and `#![allow(deprecated)]` of the module that defines
+/// them, unless they have a `#[deprecated]` of their own.
+pub fn inherit(module_attributes: &[Attribute], mut items: Vec<ASTNode>) -> Vec<ASTNode> {
+    let inherited: Vec<&Attribute> = module_attributes
+        .iter()
+        .filter(|attribute| attribute.name == "deprecated" || is_allow_deprecated(attribute))
+        .collect();
+    let items_and_methods = items.iter_mut().flat_map(|item| match item {
+        ASTNode::Impl { methods, .. } => methods.iter_mut().collect(),
+        item => vec![item],
+    });
+    for item in items_and_methods {
+        if let ASTNode::Function { attributes, .. } | ASTNode::StructDef { attributes, .. } = item {
+            let own = attributes
+                .iter()
+                .any(|attribute| attribute.name == "deprecated");
+            attributes.extend(
+                inherited
+                    .iter()
+                    .filter(|attribute| !(own && attribute.name == "deprecated"))
+                    .map(|attribute| (*attribute).clone()),
+            );
+        }
+    }
+    items
+}
+
+/// The uses of the deprecated functions and structs among `items` outside
+/// deprecated items and the items that `#[allow(deprecated)]`.
+pub fn uses(items: &[ASTNode], resolution: &Resolution) -> Result<Vec<Diagnostic>> {
+    // Types aren't resolved, so a struct is found used by its literals' names.
+    let mut literals = StructLiterals::default();
+    walk_nodes(&mut literals, items)?;
+    let allowed: Vec<Span> = items
+        .iter()
+        .flat_map(|item| match item {
+            ASTNode::Impl { methods, .. } => methods.iter().collect(),
+            item => vec![item],
+        })
+        .filter(|item| item.attribute("deprecated").is_some() || allows_deprecated(item))
+        .map(ASTNode::span)
+        .collect();
+
+    let mut diagnostics = Vec::new();
+    for item in items {
+        let Some(attribute) = item.attribute("deprecated") else {
+            continue;
+        };
+        let (kind, name, uses) = match item {
+            ASTNode::Function { name, id, .. } => {
+                let uses = resolution
+                    .declared(*id)
+                    .map(|symbol| resolution.uses_of(symbol))
+                    .unwrap_or_default();
+                (
+                    "function",
+                    name,
+                    uses.into_iter().map(|(_, span)| span).collect(),
+                )
+            }
+            ASTNode::StructDef { name, .. } => {
+                let uses: Vec<Span> = literals
+                    .0
+                    .iter()
+                    .filter(|(literal, _)| literal == name)
+                    .map(|(_, span)| *span)
+                    .collect();
+                ("struct", name, uses)
+            }
+            _ => continue,
+        };
+        for span in uses {
+            if allowed.iter().any(|item| item.encloses(span)) {
+                continue;
+            }
+            let mut diagnostic =
+                Diagnostic::warning(format!("Use of deprecated {} `{}`", kind, name))
+                    .with_span(span)
+                    .with_note(attribute.span, format!("`{}` is deprecated here", name));
+            if let Some(note) = attribute.value("note") {
+                diagnostic = diagnostic.with_hint(note);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+    Ok(diagnostics)
+}
+
+fn allows_deprecated(item: &ASTNode) -> bool {
+    item.attributes().iter().any(is_allow_deprecated)
+}
+
+fn is_allow_deprecated(attribute: &Attribute) -> bool {
+    attribute.name == "allow" && attribute.words().any(|word| word == "deprecated")
+}
+
+/// The name and span of each struct literal.
+#[derive(Default)]
+struct StructLiterals(Vec<(String, Span)>);
+
+impl Visitor for StructLiterals {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        if let ASTNode::StructLiteral { name, span, .. } = node {
+            self.0.push((name.clone(), *span));
+        }
+        walk_node(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, semantic::resolve, span::FileId};
+
+    #[test]
+    fn test_uses_outside_allowed_items() {
+        let source = "\
+#[deprecated(note = \"use `distance` instead\")]
+fn dist(a: int, b: int) -> int { return dist(b, a); }
+#[deprecated]
+struct Point { x: int }
+#[allow(deprecated)]
+fn legacy() -> int { return dist(1, 2); }
+fn main() {
+    let p = Point { x: dist(0, 1) };
+    println(p.x + legacy());
+}";
+        let program = parse_source(source, FileId(0)).unwrap();
+        let resolution = resolve::resolve(&program).unwrap();
+        let found: Vec<(String, usize)> = check(&program, &resolution)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.message, diagnostic.span.unwrap().start))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Use of deprecated function `dist`".to_string(),
+                    source.rfind("dist(0").unwrap()
+                ),
+                (
+                    "Use of deprecated struct `Point`".to_string(),
+                    source.rfind("Point {").unwrap()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,749 @@
+use crate::{
+    ast::{ASTNode, NodeId, Type},
+    compiler::control_flow::{is_statement, BasicBlock, ControlFlowGraph, Statement},
+    diagnostics::Diagnostic,
+    semantic::resolve::{self, Resolution, SymbolId},
+    span::Span,
+    visitor::{walk_node, Visitor},
+    Result,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Checks the control flow of each function and method of `program` and of
+/// its top-level statements, over their [`ControlFlowGraph`]s.
+///
+/// Reading a variable declared without a value before it is assigned on
+/// every path, and reaching the end of a function that returns a value, are
+/// errors. Statements no path reaches, loops nothing leaves and values that
+/// are overwritten or dropped before anything reads them are warnings.
+pub fn check(program: &ASTNode) -> Result<Vec<Diagnostic>> {
+    let resolution = resolve::resolve(program)?;
+    let items = match program {
+        ASTNode::Program(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+
+    let mut bodies = Vec::new();
+    let mut statements = Vec::new();
+    for item in items {
+        match item {
+            ASTNode::Impl { methods, .. } => {
+                for method in methods {
+                    Body::collect(method, &mut bodies);
+                }
+            }
+            ASTNode::TraitDef { methods, .. } => {
+                for method in methods {
+                    if let Some(body) = &method.body {
+                        bodies.push(Body::function(
+                            &method.name,
+                            method.return_type.as_ref(),
+                            body.clone(),
+                            method.span,
+                        ));
+                    }
+                }
+            }
+            ASTNode::Function { .. } => Body::collect(item, &mut bodies),
+            ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } => {}
+            statement => statements.push(statement.clone()),
+        }
+    }
+    bodies.push(Body {
+        returns: None,
+        statements,
+        span: Span::dummy(),
+    });
+
+    let mut analyses = Vec::new();
+    for body in &bodies {
+        let mut cfg = ControlFlowGraph::new();
+        cfg.analyze_function(&body.statements)
+            .map_err(|err| err.or_span(body.span))?;
+        analyses.push(Analysis::new(&resolution, cfg)?);
+    }
+
+    // A variable read by another body, or by a closure, may be read whenever
+    // that runs, so no store to it is known to be dead.
+    let mut escaping = HashSet::new();
+    for analysis in &analyses {
+        escaping.extend(&analysis.captured);
+        escaping.extend(
+            analysis
+                .reads()
+                .filter(|symbol| !analysis.declared.contains(symbol)),
+        );
+    }
+
+    let mut diagnostics = Vec::new();
+    for (body, analysis) in bodies.iter().zip(&analyses) {
+        let reachable = analysis.cfg.reachable_from(analysis.cfg.entry());
+        analysis.check_initialization(&resolution, &reachable, &mut diagnostics);
+        if let Some(name) = &body.returns {
+            if analysis.falls_off(analysis.cfg.end(), &reachable, &mut HashSet::new()) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "Function `{}` can reach its end without returning a value",
+                        name
+                    ))
+                    .with_span(closing(body.span)),
+                );
+            }
+        }
+        analysis.check_reachability(&reachable, &mut diagnostics);
+        analysis.check_loops(&reachable, &mut diagnostics);
+        analysis.check_stores(&resolution, &escaping, &reachable, &mut diagnostics);
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.map(|span| (span.file_id.0, span.start)));
+    Ok(diagnostics)
+}
+
+/// A function body, or the program's top-level statements.
+struct Body {
+    /// The function's name, when it returns a value.
+    returns: Option<String>,
+    statements: Vec<ASTNode>,
+    span: Span,
+}
+
+impl Body {
+    fn function(
+        name: &str,
+        return_type: Option<&Type>,
+        statements: Vec<ASTNode>,
+        span: Span,
+    ) -> Self {
+        let returns = return_type
+            .filter(|ty| **ty != Type::Void)
+            .map(|_| name.to_string());
+        Self {
+            returns,
+            statements,
+            span,
+        }
+    }
+
+    /// Adds `function` and the functions nested in it.
+    fn collect(function: &ASTNode, bodies: &mut Vec<Body>) {
+        let ASTNode::Function {
+            name,
+            return_type,
+            body,
+            span,
+            ..
+        } = function
+        else {
+            return;
+        };
+        bodies.push(Self::function(
+            name,
+            return_type.as_ref(),
+            body.clone(),
+            *span,
+        ));
+        let mut nested = NestedFunctions(Vec::new());
+        for node in body {
+            // Collecting never fails.
+            let _ = nested.visit_node(node);
+        }
+        for function in &nested.0 {
+            Self::collect(function, bodies);
+        }
+    }
+}
+
+/// The functions declared inside a body, not counting those inside them.
+struct NestedFunctions(Vec<ASTNode>);
+
+impl Visitor for NestedFunctions {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Function { .. } => {
+                self.0.push(node.clone());
+                Ok(())
+            }
+            node => walk_node(self, node),
+        }
+    }
+}
+
+/// What a statement does to the variables of a body, in order.
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    /// `let x: T;` at `span`.
+    Declare(SymbolId, Span),
+    Read(SymbolId, Span),
+    /// A store of a whole new value. Stores at a `span` are reported when
+    /// nothing reads them; those made by patterns are not.
+    Write(SymbolId, Option<Span>),
+}
+
+/// The events of each block of one body's graph.
+struct Analysis {
+    cfg: ControlFlowGraph,
+    events: HashMap<usize, Vec<Event>>,
+    /// Variables the body declares.
+    declared: HashSet<SymbolId>,
+    /// Variables read by the closures the body creates.
+    captured: HashSet<SymbolId>,
+}
+
+impl Analysis {
+    fn new(resolution: &Resolution, cfg: ControlFlowGraph) -> Result<Self> {
+        let mut events = HashMap::new();
+        let mut declared = HashSet::new();
+        let mut captured = HashSet::new();
+        for block in cfg.blocks() {
+            let mut collector = Events {
+                resolution,
+                events: Vec::new(),
+                declared: &mut declared,
+                captured: &mut captured,
+                closures: 0,
+            };
+            for statement in block.statements() {
+                collector.statement(statement)?;
+            }
+            events.insert(block.id(), collector.events);
+        }
+        Ok(Self {
+            cfg,
+            events,
+            declared,
+            captured,
+        })
+    }
+
+    fn block_events(&self, block: usize) -> &[Event] {
+        self.events.get(&block).map_or(&[], Vec::as_slice)
+    }
+
+    fn reads(&self) -> impl Iterator<Item = SymbolId> + '_ {
+        self.events
+            .values()
+            .flatten()
+            .filter_map(|event| match event {
+                Event::Read(symbol, _) => Some(*symbol),
+                _ => None,
+            })
+    }
+
+    /// Reports reads of variables that some path reaches without assigning
+    /// them, once per variable.
+    fn check_initialization(
+        &self,
+        resolution: &Resolution,
+        reachable: &HashSet<usize>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let declarations: HashMap<SymbolId, Span> = self
+            .events
+            .values()
+            .flatten()
+            .filter_map(|event| match event {
+                Event::Declare(symbol, span) => Some((*symbol, *span)),
+                _ => None,
+            })
+            .collect();
+        if declarations.is_empty() {
+            return;
+        }
+
+        // The variables that may still be unassigned when each block starts.
+        let transfer = |block: usize, mut state: HashSet<SymbolId>| {
+            for event in self.block_events(block) {
+                match event {
+                    Event::Declare(symbol, _) => {
+                        state.insert(*symbol);
+                    }
+                    Event::Write(symbol, _) => {
+                        state.remove(symbol);
+                    }
+                    Event::Read(..) => {}
+                }
+            }
+            state
+        };
+        let mut entry_states: HashMap<usize, HashSet<SymbolId>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.cfg.blocks() {
+                let state: HashSet<SymbolId> = block
+                    .predecessors()
+                    .iter()
+                    .filter(|pred| reachable.contains(pred))
+                    .flat_map(|pred| {
+                        transfer(*pred, entry_states.get(pred).cloned().unwrap_or_default())
+                    })
+                    .collect();
+                if entry_states.get(&block.id()) != Some(&state) {
+                    entry_states.insert(block.id(), state);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut reported = HashSet::new();
+        for block in self
+            .cfg
+            .blocks()
+            .filter(|block| reachable.contains(&block.id()))
+        {
+            let mut state = entry_states.remove(&block.id()).unwrap_or_default();
+            for event in self.block_events(block.id()) {
+                match event {
+                    Event::Declare(symbol, _) => {
+                        state.insert(*symbol);
+                    }
+                    Event::Write(symbol, _) => {
+                        state.remove(symbol);
+                    }
+                    Event::Read(symbol, span) => {
+                        if state.contains(symbol) && reported.insert(*symbol) {
+                            diagnostics.push(
+                                Diagnostic::error(format!(
+                                    "Use of possibly uninitialized variable `{}`",
+                                    resolution.symbol(*symbol).name
+                                ))
+                                .with_span(*span)
+                                .with_note(declarations[symbol], "declared here without a value"),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a reachable path gets to the end of `block` without a
+    /// `return` or a trailing value.
+    fn falls_off(
+        &self,
+        block: usize,
+        reachable: &HashSet<usize>,
+        seen: &mut HashSet<usize>,
+    ) -> bool {
+        if !reachable.contains(&block) || !seen.insert(block) {
+            return false;
+        }
+        let Some(basic_block) = self.cfg.block(block) else {
+            return false;
+        };
+        match last_statement(basic_block) {
+            Some(Statement::Node(node)) => is_statement(node),
+            Some(_) => true,
+            None if block == self.cfg.entry() => true,
+            None => basic_block
+                .predecessors()
+                .iter()
+                .any(|pred| self.falls_off(*pred, reachable, seen)),
+        }
+    }
+
+    /// Reports the first statement of each stretch of code no path reaches.
+    fn check_reachability(&self, reachable: &HashSet<usize>, diagnostics: &mut Vec<Diagnostic>) {
+        let mut covered = HashSet::new();
+        for block in self.cfg.blocks() {
+            if reachable.contains(&block.id()) || covered.contains(&block.id()) {
+                continue;
+            }
+            let Some(span) = block.statements().iter().find_map(statement_span) else {
+                continue;
+            };
+            diagnostics.push(Diagnostic::warning("Unreachable code").with_span(span));
+            covered.extend(self.cfg.reachable_from(block.id()));
+        }
+    }
+
+    /// Reports reachable loops that neither `break` nor `return` leaves.
+    fn check_loops(&self, reachable: &HashSet<usize>, diagnostics: &mut Vec<Diagnostic>) {
+        for lp in self.cfg.loops() {
+            if !reachable.contains(&lp.header) {
+                continue;
+            }
+            let inside = self.cfg.reachable_from(lp.header);
+            if !inside.contains(&lp.after) && !inside.contains(&self.cfg.exit()) {
+                // Only `while true` has no edge out of its header; point at
+                // the keyword.
+                let keyword = Span::new(lp.span.file_id, lp.span.start, lp.span.start + 5);
+                diagnostics.push(Diagnostic::warning("Loop never exits").with_span(keyword));
+            }
+        }
+    }
+
+    /// Reports values stored in a variable of the body that no path reads
+    /// before the next store or the end of the body. Variables that are
+    /// never read at all are left alone.
+    fn check_stores(
+        &self,
+        resolution: &Resolution,
+        escaping: &HashSet<SymbolId>,
+        reachable: &HashSet<usize>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let read: HashSet<SymbolId> = self.reads().collect();
+        let tracked = |symbol: &SymbolId| {
+            self.declared.contains(symbol) && read.contains(symbol) && !escaping.contains(symbol)
+        };
+
+        // The variables each block's successors may read before storing to.
+        let transfer = |block: usize, mut live: HashSet<SymbolId>| {
+            for event in self.block_events(block).iter().rev() {
+                match event {
+                    Event::Read(symbol, _) => {
+                        live.insert(*symbol);
+                    }
+                    Event::Write(symbol, _) | Event::Declare(symbol, _) => {
+                        live.remove(symbol);
+                    }
+                }
+            }
+            live
+        };
+        let mut exit_states: HashMap<usize, HashSet<SymbolId>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.cfg.blocks().collect::<Vec<_>>().into_iter().rev() {
+                let live: HashSet<SymbolId> = block
+                    .successors()
+                    .iter()
+                    .flat_map(|succ| {
+                        transfer(*succ, exit_states.get(succ).cloned().unwrap_or_default())
+                    })
+                    .collect();
+                if exit_states.get(&block.id()) != Some(&live) {
+                    exit_states.insert(block.id(), live);
+                    changed = true;
+                }
+            }
+        }
+
+        for block in self
+            .cfg
+            .blocks()
+            .filter(|block| reachable.contains(&block.id()))
+        {
+            let mut live = exit_states.remove(&block.id()).unwrap_or_default();
+            for event in self.block_events(block.id()).iter().rev() {
+                match event {
+                    Event::Read(symbol, _) => {
+                        live.insert(*symbol);
+                    }
+                    Event::Write(symbol, span) => {
+                        if let (Some(span), false) = (span, live.contains(symbol)) {
+                            if tracked(symbol) {
+                                diagnostics.push(
+                                    Diagnostic::warning(format!(
+                                        "Value assigned to `{}` is never read",
+                                        resolution.symbol(*symbol).name
+                                    ))
+                                    .with_span(*span),
+                                );
+                            }
+                        }
+                        live.remove(symbol);
+                    }
+                    Event::Declare(symbol, _) => {
+                        live.remove(symbol);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The last statement of `block` that does something, which a scope's end
+/// doesn't.
+fn last_statement(block: &BasicBlock) -> Option<&Statement> {
+    block
+        .statements()
+        .iter()
+        .rev()
+        .find(|statement| !matches!(statement, Statement::ScopeEnd { .. }))
+}
+
+fn statement_span(statement: &Statement) -> Option<Span> {
+    match statement {
+        Statement::Node(node)
+        | Statement::Branch(node)
+        | Statement::Yield(node)
+        | Statement::Complete(node) => Some(node.span()),
+        Statement::Bind { span, .. } => Some(*span),
+        Statement::ScopeEnd { .. } => None,
+    }
+}
+
+/// The last character of `span`, the closing brace of a body.
+fn closing(span: Span) -> Span {
+    Span::new(
+        span.file_id,
+        span.end.saturating_sub(1).max(span.start),
+        span.end,
+    )
+}
+
+/// Collects the events of a block's statements.
+struct Events<'a> {
+    resolution: &'a Resolution,
+    events: Vec<Event>,
+    declared: &'a mut HashSet<SymbolId>,
+    captured: &'a mut HashSet<SymbolId>,
+    /// How many closures the visited node is inside.
+    closures: usize,
+}
+
+impl Events<'_> {
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Node(node) | Statement::Branch(node) | Statement::Yield(node) => {
+                self.visit_node(node)
+            }
+            // The value was produced by the `Yield`s before.
+            Statement::Complete(node) => match node {
+                ASTNode::Let { id, span, .. } => {
+                    self.store(*id, Some(*span));
+                    Ok(())
+                }
+                ASTNode::LetPattern { pattern, .. } => {
+                    for (_, id) in pattern.binding_ids() {
+                        self.store(id, None);
+                    }
+                    Ok(())
+                }
+                ASTNode::Assignment { target, span, .. } => self.assign(target, *span),
+                _ => Ok(()),
+            },
+            Statement::Bind { .. } | Statement::ScopeEnd { .. } => Ok(()),
+        }
+    }
+
+    /// A `let` of the variable declared by `node`.
+    fn store(&mut self, node: NodeId, span: Option<Span>) {
+        if let Some(symbol) = self.resolution.declared(node) {
+            self.declared.insert(symbol);
+            self.events.push(Event::Write(symbol, span));
+        }
+    }
+
+    /// Stores to `target`, reading the variable it is part of unless it is
+    /// the whole variable.
+    fn assign(&mut self, target: &ASTNode, span: Span) -> Result<()> {
+        match target {
+            ASTNode::Identifier { id, .. } if self.closures == 0 => {
+                if let Some(symbol) = self.resolution.resolved(*id) {
+                    self.events.push(Event::Write(symbol, Some(span)));
+                }
+                Ok(())
+            }
+            target => self.visit_node(target),
+        }
+    }
+}
+
+impl Visitor for Events<'_> {
+    type Output = ();
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Identifier { id, span, .. } => {
+                if let Some(symbol) = self.resolution.resolved(*id) {
+                    if self.closures > 0 {
+                        self.captured.insert(symbol);
+                    }
+                    self.events.push(Event::Read(symbol, *span));
+                }
+                Ok(())
+            }
+            ASTNode::Let {
+                value, id, span, ..
+            } if self.closures == 0 => {
+                match value {
+                    Some(value) => {
+                        self.visit_node(value)?;
+                        self.store(*id, Some(*span));
+                    }
+                    None => {
+                        if let Some(symbol) = self.resolution.declared(*id) {
+                            self.declared.insert(symbol);
+                            self.events.push(Event::Declare(symbol, *span));
+                        }
+                    }
+                }
+                Ok(())
+            }
+            ASTNode::LetPattern { pattern, value, .. } if self.closures == 0 => {
+                self.visit_node(value)?;
+                for (_, id) in pattern.binding_ids() {
+                    self.store(id, None);
+                }
+                Ok(())
+            }
+            ASTNode::Assignment {
+                target,
+                value,
+                span,
+                ..
+            } => {
+                self.visit_node(value)?;
+                self.assign(target, *span)
+            }
+            ASTNode::CompoundAssignment {
+                target,
+                value,
+                span,
+                ..
+            } => {
+                self.visit_node(value)?;
+                self.visit_node(target)?;
+                match **target {
+                    ASTNode::Identifier { .. } => self.assign(target, *span),
+                    _ => Ok(()),
+                }
+            }
+            ASTNode::Closure { .. } => {
+                self.closures += 1;
+                let result = walk_node(self, node);
+                self.closures -= 1;
+                result
+            }
+            // Nested functions are checked as bodies of their own.
+            ASTNode::Function { .. } => Ok(()),
+            node => walk_node(self, node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+
+    /// Whether each diagnostic for `source` is an error, its message and the
+    /// source text it points at.
+    fn diagnostics(source: &str) -> Vec<(bool, String, &str)> {
+        let program = parse_source(source, FileId(0)).unwrap();
+        check(&program)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| {
+                let span = diagnostic.span.unwrap();
+                (
+                    diagnostic.is_error(),
+                    diagnostic.message,
+                    &source[span.start..span.end],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_possibly_uninitialized_read() {
+        let source = "\
+fn pick(flag: bool) -> int {
+    let x: int;
+    if flag {
+        x = 1;
+    }
+    return x;
+}";
+        assert_eq!(
+            diagnostics(source),
+            vec![(
+                true,
+                "Use of possibly uninitialized variable `x`".to_string(),
+                "x"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_missing_return() {
+        let source = "\
+fn sign(n: int) -> int {
+    if n > 0 {
+        return 1;
+    }
+}";
+        let diagnostics = diagnostics(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].0);
+        assert_eq!(
+            diagnostics[0].1,
+            "Function `sign` can reach its end without returning a value"
+        );
+        assert_eq!(diagnostics[0].2, "}");
+    }
+
+    #[test]
+    fn test_unreachable_code_and_endless_loop() {
+        let source = "\
+fn early() -> int {
+    return 1;
+    println(2);
+}
+fn spin() {
+    while true {
+        println(1);
+    }
+}";
+        assert_eq!(
+            diagnostics(source),
+            vec![
+                (false, "Unreachable code".to_string(), "println(2)"),
+                (false, "Loop never exits".to_string(), "while"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dead_stores() {
+        let source = "\
+fn last() -> int {
+    let x = 1;
+    x = 2;
+    x = 3;
+    return x;
+}";
+        assert_eq!(
+            diagnostics(source),
+            vec![
+                (
+                    false,
+                    "Value assigned to `x` is never read".to_string(),
+                    "let x = 1;"
+                ),
+                (
+                    false,
+                    "Value assigned to `x` is never read".to_string(),
+                    "x = 2"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_well_formed_functions_have_no_diagnostics() {
+        let source = "\
+fn pick(flag: bool) -> int {
+    let x: int;
+    if flag { x = 1; } else { x = 2; }
+    let sum = 0;
+    for i in 0..3 { sum += i; }
+    while true { if flag { break; } }
+    let add = |y: int| y + x;
+    return add(sum);
+}
+fn choose(flag: bool) -> int {
+    if flag { 1 } else { 2 }
+}
+let total = 0;
+fn bump() { total = total + 1; }
+total = 5;
+bump();";
+        assert!(diagnostics(source).is_empty());
+    }
+}
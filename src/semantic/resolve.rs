@@ -0,0 +1,794 @@
+use crate::{
+    ast::{
+        ASTNode, MatchArm, NodeId, Parameter, Pattern, PatternFields, TraitMethod, Type,
+        UnaryOperator,
+    },
+    error::IoError,
+    span::{FileId, Span},
+    stdlib::prelude,
+    visitor::{walk_node, walk_nodes, Visitor},
+    Result,
+};
+use std::collections::{HashMap, HashSet};
+
+/// A binding of the program, numbered in the order it was declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    /// A `const` item.
+    Global,
+    /// An enum variant, named with its enum: `Shape::Circle`.
+    Variant,
+    Parameter,
+    /// Bound by `let`, alone or in a pattern.
+    Variable,
+    /// Bound by the pattern of a `match` arm or a `for` loop.
+    PatternBinding,
+    /// An item of another module brought into scope by an `import`, which
+    /// declares all of its items.
+    Import,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The function, variant, parameter, `let` or pattern binding declaring it.
+    pub node: NodeId,
+    pub span: Span,
+}
+
+impl Symbol {
+    /// Only variables bound by `let` may be assigned.
+    pub fn is_mutable(&self) -> bool {
+        self.kind == SymbolKind::Variable
+    }
+
+    /// Parameters and the variables of a body, as opposed to items.
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self.kind,
+            SymbolKind::Parameter | SymbolKind::Variable | SymbolKind::PatternBinding
+        )
+    }
+}
+
+/// What the resolver found: every binding's symbol, and the symbol each
+/// identifier refers to.
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    symbols: Vec<Symbol>,
+    /// The symbol each declaring node binds. All alternatives of an or-pattern
+    /// bind the same symbols.
+    declarations: HashMap<NodeId, SymbolId>,
+    /// The symbol each identifier refers to, with the identifier's span.
+    /// Builtins like `print` have none.
+    uses: HashMap<NodeId, (SymbolId, Span)>,
+    /// The local binding each local binding hides.
+    shadowed: HashMap<SymbolId, SymbolId>,
+}
+
+impl Resolution {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0 as usize]
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (SymbolId(i as u32), symbol))
+    }
+
+    /// The symbol the function, parameter, `let` or pattern binding `node`
+    /// declares.
+    pub fn declared(&self, node: NodeId) -> Option<SymbolId> {
+        self.declarations.get(&node).copied()
+    }
+
+    /// The symbol the identifier `node` refers to.
+    pub fn resolved(&self, node: NodeId) -> Option<SymbolId> {
+        self.uses.get(&node).map(|(symbol, _)| *symbol)
+    }
+
+    /// The local binding of the same name that was in scope where the local
+    /// binding `symbol` was declared, and that it hides.
+    pub fn shadowed(&self, symbol: SymbolId) -> Option<SymbolId> {
+        self.shadowed.get(&symbol).copied()
+    }
+
+    /// The identifiers referring to `symbol`, in source order.
+    pub fn uses_of(&self, symbol: SymbolId) -> Vec<(NodeId, Span)> {
+        let mut uses: Vec<(NodeId, Span)> = self
+            .uses
+            .iter()
+            .filter(|(_, (used, _))| *used == symbol)
+            .map(|(node, (_, span))| (*node, *span))
+            .collect();
+        uses.sort_by_key(|(_, span)| (span.file_id.0, span.start));
+        uses
+    }
+
+    /// The symbol declared or used at byte `offset` of `file_id`, the innermost
+    /// if several spans contain it.
+    pub fn symbol_at(&self, offset: usize, file_id: FileId) -> Option<SymbolId> {
+        let declarations = self.symbols().map(|(id, symbol)| (id, symbol.span));
+        let uses = self.uses.values().copied();
+        declarations
+            .chain(uses)
+            .filter(|(_, span)| {
+                span.file_id == file_id && span.start <= offset && offset < span.end
+            })
+            .min_by_key(|(_, span)| span.end - span.start)
+            .map(|(id, _)| id)
+    }
+}
+
+/// Where a scope's names were bound, which decides what `break` and
+/// `continue` can reach and where `await` is allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScopeKind {
+    Block,
+    Loop,
+    /// A function, method or closure body; loops around it are out of reach.
+    Body,
+    /// The body of an `async` function or method, which may `await`.
+    Async,
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    names: HashMap<String, SymbolId>,
+    kind: ScopeKind,
+}
+
+/// Name resolution: gives each binding of a program a [`SymbolId`] and links
+/// each identifier to the binding it refers to, in a [`Resolution`] the later
+/// passes share. A `let` shadows earlier bindings of its name from where it
+/// is declared on; functions, consts and enum variants are visible in the
+/// whole of the program declaring them.
+///
+/// A resolver keeps its scopes between calls, so the prelude can be resolved
+/// first and a REPL can resolve one entry at a time.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    resolution: Resolution,
+    scopes: Vec<Scope>,
+}
+
+impl Resolver {
+    /// A resolver whose outermost scope is empty.
+    pub fn new() -> Self {
+        Self {
+            resolution: Resolution::default(),
+            scopes: vec![Scope {
+                names: HashMap::new(),
+                kind: ScopeKind::Block,
+            }],
+        }
+    }
+
+    /// A resolver that has declared the prelude, with a scope of its own for
+    /// the program so it may reuse prelude names.
+    pub fn with_prelude() -> Self {
+        let mut resolver = Self::new();
+        resolver
+            .resolve_items(prelude::items())
+            .expect("the prelude resolves");
+        resolver.push_scope(ScopeKind::Block);
+        resolver
+    }
+
+    /// Starts a scope for what is resolved next, as for a program after the
+    /// prelude.
+    pub fn enter_scope(&mut self) {
+        self.push_scope(ScopeKind::Block);
+    }
+
+    pub fn resolution(&self) -> &Resolution {
+        &self.resolution
+    }
+
+    pub fn into_resolution(self) -> Resolution {
+        self.resolution
+    }
+
+    /// Resolves a program, or a single item or statement, in the current
+    /// scope.
+    pub fn resolve(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Program(items) => self.resolve_items(items),
+            item => self.resolve_items(std::slice::from_ref(item)),
+        }
+    }
+
+    /// Declares the functions, consts and variants among `items` before
+    /// resolving any of them, so uses may precede definitions.
+    pub fn resolve_items(&mut self, items: &[ASTNode]) -> Result<()> {
+        let mut declared = HashSet::new();
+        for item in items {
+            let (name, kind, id, span) = match item {
+                ASTNode::Function { name, id, span, .. } => (name, SymbolKind::Function, id, span),
+                ASTNode::Global { name, id, span, .. } => (name, SymbolKind::Global, id, span),
+                ASTNode::Import {
+                    items: imported,
+                    id,
+                    span,
+                    ..
+                } => {
+                    for name in imported {
+                        if !declared.insert(name.clone()) {
+                            return Err(already_defined(name).with_span(*span));
+                        }
+                        self.declare(name, SymbolKind::Import, *id, *span);
+                    }
+                    continue;
+                }
+                ASTNode::EnumDef { name, variants, .. } => {
+                    for variant in variants {
+                        let path = format!("{}::{}", name, variant.name);
+                        if !declared.insert(path.clone()) {
+                            return Err(already_defined(&path).with_span(variant.span));
+                        }
+                        self.declare(&path, SymbolKind::Variant, variant.id, variant.span);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if !declared.insert(name.clone()) {
+                return Err(already_defined(name).with_span(*span));
+            }
+            self.declare(name, kind, *id, *span);
+        }
+
+        for item in items {
+            match item {
+                ASTNode::Function {
+                    params,
+                    body,
+                    is_async,
+                    span,
+                    ..
+                } => self
+                    .resolve_body(params, body, *is_async)
+                    .map_err(|err| err.or_span(*span))?,
+                ASTNode::Global { value, .. } => {
+                    self.visit_node(value)?;
+                }
+                item => {
+                    self.visit_node(item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push_scope(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope {
+            names: HashMap::new(),
+            kind,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Runs `resolve` in a new scope of `kind`.
+    fn scoped<T>(
+        &mut self,
+        kind: ScopeKind,
+        resolve: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.push_scope(kind);
+        let result = resolve(self);
+        self.pop_scope();
+        result
+    }
+
+    fn declare(&mut self, name: &str, kind: SymbolKind, node: NodeId, span: Span) -> SymbolId {
+        let id = SymbolId(self.resolution.symbols.len() as u32);
+        let symbol = Symbol {
+            name: name.to_string(),
+            kind,
+            node,
+            span,
+        };
+        let hidden = self
+            .lookup(name)
+            .filter(|hidden| symbol.is_local() && self.resolution.symbol(*hidden).is_local());
+        if let Some(hidden) = hidden {
+            self.resolution.shadowed.insert(id, hidden);
+        }
+        self.resolution.symbols.push(symbol);
+        self.resolution.declarations.insert(node, id);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.names.insert(name.to_string(), id);
+        }
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(name).copied())
+    }
+
+    /// The imported item the path `name` starts with: `Shape::Circle` uses
+    /// an imported `Shape`.
+    fn imported(&self, name: &str) -> Option<SymbolId> {
+        let (head, _) = name.split_once("::")?;
+        self.lookup(head)
+            .filter(|symbol| self.resolution.symbol(*symbol).kind == SymbolKind::Import)
+    }
+
+    /// Whether a `break` or `continue` here has a loop to leave.
+    fn in_loop(&self) -> bool {
+        self.scopes
+            .iter()
+            .rev()
+            .map(|scope| scope.kind)
+            .find(|kind| *kind != ScopeKind::Block)
+            == Some(ScopeKind::Loop)
+    }
+
+    /// Whether an `await` here is inside an `async` function.
+    fn in_async(&self) -> bool {
+        self.scopes
+            .iter()
+            .rev()
+            .map(|scope| scope.kind)
+            .find(|kind| !matches!(kind, ScopeKind::Block | ScopeKind::Loop))
+            == Some(ScopeKind::Async)
+    }
+
+    fn resolve_identifier(&mut self, name: &str, id: NodeId, span: Span) -> Result<()> {
+        match self.lookup(name).or_else(|| self.imported(name)) {
+            Some(symbol) => {
+                self.resolution.uses.insert(id, (symbol, span));
+                Ok(())
+            }
+            None if prelude::is_builtin(name) => {
+                self.resolution.uses.remove(&id);
+                Ok(())
+            }
+            None => {
+                Err(IoError::type_error(format!("Undefined variable {}", name)).with_span(span))
+            }
+        }
+    }
+
+    /// Resolves a function, method or closure body with its parameters in
+    /// scope.
+    fn resolve_body(
+        &mut self,
+        params: &[Parameter],
+        body: &[ASTNode],
+        is_async: bool,
+    ) -> Result<()> {
+        let kind = if is_async {
+            ScopeKind::Async
+        } else {
+            ScopeKind::Body
+        };
+        self.scoped(kind, |this| {
+            this.declare_params(params)?;
+            walk_nodes(this, body)
+        })
+    }
+
+    fn declare_params(&mut self, params: &[Parameter]) -> Result<()> {
+        let mut names = HashSet::new();
+        for param in params {
+            if !names.insert(param.name.as_str()) {
+                return Err(already_defined(&param.name).with_span(param.span));
+            }
+            self.declare(&param.name, SymbolKind::Parameter, param.id, param.span);
+        }
+        Ok(())
+    }
+
+    /// Declares the names `pattern` binds, in the current scope.
+    fn bind_pattern(&mut self, pattern: &Pattern, kind: SymbolKind) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Literal { .. } => Ok(()),
+            Pattern::Binding { name, id, span } => {
+                self.declare(name, kind, *id, *span);
+                Ok(())
+            }
+            Pattern::Array { elements, .. } | Pattern::Tuple { elements, .. } => elements
+                .iter()
+                .try_for_each(|element| self.bind_pattern(element, kind)),
+            Pattern::Struct { fields, .. } => match fields {
+                PatternFields::Unit => Ok(()),
+                PatternFields::Tuple(patterns) => patterns
+                    .iter()
+                    .try_for_each(|pattern| self.bind_pattern(pattern, kind)),
+                PatternFields::Named { fields, .. } => fields
+                    .iter()
+                    .try_for_each(|(_, pattern)| self.bind_pattern(pattern, kind)),
+            },
+            // The other alternatives bind the first one's symbols; a name only
+            // they bind gets one of its own, for the type checker to reject.
+            Pattern::Or { alternatives, .. } => {
+                let Some((first, rest)) = alternatives.split_first() else {
+                    return Ok(());
+                };
+                self.bind_pattern(first, kind)?;
+                for alternative in rest {
+                    for (name, id) in alternative.binding_ids() {
+                        let scope = self.scopes.last().map(|scope| &scope.names);
+                        match scope.and_then(|names| names.get(name)).copied() {
+                            Some(symbol) => {
+                                self.resolution.declarations.insert(id, symbol);
+                            }
+                            None => {
+                                self.declare(name, kind, id, alternative.span());
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves `program` after the prelude.
+pub fn resolve(program: &ASTNode) -> Result<Resolution> {
+    let mut resolver = Resolver::with_prelude();
+    resolver.resolve(program)?;
+    Ok(resolver.into_resolution())
+}
+
+fn already_defined(name: &str) -> IoError {
+    IoError::type_error(format!(
+        "Symbol '{}' already defined in current scope",
+        name
+    ))
+}
+
+impl Visitor for Resolver {
+    type Output = ();
+
+    /// Identifiers and declarations are resolved here, where their node ids
+    /// are known.
+    fn visit_node(&mut self, node: &ASTNode) -> Result<()> {
+        match node {
+            ASTNode::Identifier { name, id, span } => self.resolve_identifier(name, *id, *span),
+            // A `let` is in scope after its initializer, which may still use
+            // the name it shadows.
+            ASTNode::Let {
+                name,
+                value,
+                id,
+                span,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.visit_node(value)?;
+                }
+                self.declare(name, SymbolKind::Variable, *id, *span);
+                Ok(())
+            }
+            // A nested function may call itself.
+            ASTNode::Function {
+                name,
+                params,
+                body,
+                is_async,
+                id,
+                span,
+                ..
+            } => {
+                self.declare(name, SymbolKind::Function, *id, *span);
+                self.resolve_body(params, body, *is_async)
+                    .map_err(|err| err.or_span(*span))
+            }
+            ASTNode::Global {
+                name,
+                value,
+                id,
+                span,
+                ..
+            } => {
+                self.visit_node(value)?;
+                self.declare(name, SymbolKind::Global, *id, *span);
+                Ok(())
+            }
+            ASTNode::Break { .. } if !self.in_loop() => {
+                Err(IoError::type_error("Break statement outside loop").with_span(node.span()))
+            }
+            ASTNode::Continue { .. } if !self.in_loop() => {
+                Err(IoError::type_error("Continue statement outside loop").with_span(node.span()))
+            }
+            ASTNode::UnaryOp {
+                op: UnaryOperator::Await,
+                ..
+            } if !self.in_async() => {
+                Err(IoError::type_error("Await outside async function").with_span(node.span()))
+            }
+            node => walk_node(self, node),
+        }
+    }
+
+    fn visit_trait_def(&mut self, _name: &str, methods: &[TraitMethod]) -> Result<()> {
+        for method in methods {
+            if let Some(body) = &method.body {
+                self.resolve_body(&method.params, body, false)
+                    .map_err(|err| err.or_span(method.span))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Methods are called through their receiver, so only their bodies are
+    /// resolved.
+    fn visit_impl(
+        &mut self,
+        _trait_name: Option<&str>,
+        _self_type: &Type,
+        methods: &[ASTNode],
+    ) -> Result<()> {
+        for method in methods {
+            if let ASTNode::Function {
+                params,
+                body,
+                is_async,
+                span,
+                ..
+            } = method
+            {
+                self.resolve_body(params, body, *is_async)
+                    .map_err(|err| err.or_span(*span))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_block(&mut self, statements: &[ASTNode]) -> Result<()> {
+        self.scoped(ScopeKind::Block, |this| walk_nodes(this, statements))
+    }
+
+    fn visit_let_pattern(
+        &mut self,
+        pattern: &Pattern,
+        _type_annotation: Option<&Type>,
+        value: &ASTNode,
+    ) -> Result<()> {
+        self.visit_node(value)?;
+        self.bind_pattern(pattern, SymbolKind::Variable)
+    }
+
+    /// The variable written to is a use of it too.
+    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<()> {
+        self.visit_node(value)?;
+        self.visit_node(target)
+    }
+
+    fn visit_compound_assignment(
+        &mut self,
+        target: &ASTNode,
+        _op: &crate::ast::BinaryOperator,
+        value: &ASTNode,
+    ) -> Result<()> {
+        self.visit_node(value)?;
+        self.visit_node(target)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &ASTNode,
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+    ) -> Result<()> {
+        self.visit_node(condition)?;
+        self.visit_block(then_branch)?;
+        match else_branch {
+            Some(else_branch) => self.visit_block(else_branch),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_match(&mut self, scrutinee: &ASTNode, arms: &[MatchArm]) -> Result<()> {
+        self.visit_node(scrutinee)?;
+        for arm in arms {
+            self.scoped(ScopeKind::Block, |this| {
+                this.bind_pattern(&arm.pattern, SymbolKind::PatternBinding)?;
+                if let Some(guard) = &arm.guard {
+                    this.visit_node(guard)?;
+                }
+                this.visit_node(&arm.body)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn visit_while(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<()> {
+        self.visit_node(condition)?;
+        self.scoped(ScopeKind::Loop, |this| walk_nodes(this, body))
+    }
+
+    fn visit_for(&mut self, pattern: &Pattern, iterable: &ASTNode, body: &[ASTNode]) -> Result<()> {
+        self.visit_node(iterable)?;
+        self.scoped(ScopeKind::Loop, |this| {
+            this.bind_pattern(pattern, SymbolKind::PatternBinding)?;
+            walk_nodes(this, body)
+        })
+    }
+
+    fn visit_closure(
+        &mut self,
+        params: &[Parameter],
+        _return_type: Option<&Type>,
+        body: &ASTNode,
+        _is_move: bool,
+    ) -> Result<()> {
+        self.resolve_body(params, std::slice::from_ref(body), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    fn resolve_source(source: &str) -> Result<Resolution> {
+        resolve(&parse_source(source, FileId(0)).unwrap())
+    }
+
+    /// The symbol the `n`th use of an identifier spelled `text` refers to.
+    fn use_of(resolution: &Resolution, source: &str, text: &str, n: usize) -> Option<SymbolId> {
+        let mut uses: Vec<&(SymbolId, Span)> = resolution
+            .uses
+            .values()
+            .filter(|(_, span)| span.file_id == FileId(0) && &source[span.start..span.end] == text)
+            .collect();
+        uses.sort_by_key(|(_, span)| span.start);
+        uses.get(n).map(|(symbol, _)| *symbol)
+    }
+
+    #[test]
+    fn test_shadowing_and_forward_references() {
+        let source = "\
+fn main() -> int {
+    let x = later(1);
+    let x = x + 1;
+    if x > 0 { let x = true; println(x); }
+    return x;
+}
+fn later(n: int) -> int { return n; }";
+        let resolution = resolve_source(source).unwrap();
+
+        let later = use_of(&resolution, source, "later", 0).unwrap();
+        assert_eq!(resolution.symbol(later).kind, SymbolKind::Function);
+        let first = use_of(&resolution, source, "x", 0).unwrap();
+        let inner = use_of(&resolution, source, "x", 2).unwrap();
+        let returned = use_of(&resolution, source, "x", 3).unwrap();
+        assert_ne!(first, returned);
+        assert_ne!(inner, returned);
+        assert_eq!(use_of(&resolution, source, "x", 1), Some(returned));
+        assert!(resolution.symbol(returned).is_mutable());
+        assert_eq!(resolution.uses_of(returned).len(), 2);
+
+        let n = use_of(&resolution, source, "n", 0).unwrap();
+        assert_eq!(resolution.symbol(n).kind, SymbolKind::Parameter);
+        assert!(!resolution.symbol(n).is_mutable());
+        let param = source.find("n: int").unwrap();
+        assert_eq!(resolution.symbol_at(param, FileId(0)), Some(n));
+        assert_eq!(resolution.declared(resolution.symbol(n).node), Some(n));
+    }
+
+    #[test]
+    fn test_patterns_closures_and_variants() {
+        let source = "\
+enum Shape { Circle(float), Square(float) }
+fn area(s: Shape) -> float {
+    let scale = 2.0;
+    let f = |r: float| r * scale;
+    return match s { Shape::Circle(r) | Shape::Square(r) => f(r) };
+}";
+        let resolution = resolve_source(source).unwrap();
+        let scale = use_of(&resolution, source, "scale", 0).unwrap();
+        assert_eq!(resolution.symbol(scale).kind, SymbolKind::Variable);
+        let r = use_of(&resolution, source, "r", 1).unwrap();
+        assert_eq!(resolution.symbol(r).kind, SymbolKind::PatternBinding);
+        // Both alternatives bind the same `r`.
+        let rs = resolution
+            .symbols()
+            .filter(|(_, symbol)| symbol.name == "r");
+        assert_eq!(rs.count(), 2);
+        let circle = use_of(&resolution, source, "Shape::Circle", 0);
+        assert!(circle.is_none(), "patterns don't use variants as values");
+        let f = use_of(&resolution, source, "f", 0).unwrap();
+        assert_eq!(resolution.symbol(f).kind, SymbolKind::Variable);
+    }
+
+    #[test]
+    fn test_undefined_names_and_misplaced_jumps() {
+        let source = "fn f() { let y = 1; { let z = y; } return z; }";
+        let err = resolve_source(source).unwrap_err();
+        assert_eq!(err.message(), "Undefined variable z");
+        let span = err.span().unwrap();
+        assert_eq!(span.start, source.rfind('z').unwrap());
+
+        let err = resolve_source("fn f() { while true { let g = || { break; }; } }").unwrap_err();
+        assert_eq!(err.message(), "Break statement outside loop");
+        assert!(resolve_source("fn f() { for i in 0..3 { if i > 1 { continue; } } }").is_ok());
+        let err = resolve_source("async fn g() {}\nfn f() { await g(); }").unwrap_err();
+        assert_eq!(err.message(), "Await outside async function");
+        let err = resolve_source("async fn f() { let h = || await f(); }").unwrap_err();
+        assert_eq!(err.message(), "Await outside async function");
+        assert!(resolve_source("async fn f() { while true { if true { await f(); } } }").is_ok());
+        let err = resolve_source("fn f() {}\nfn f() {}").unwrap_err();
+        assert_eq!(err.message(), "Symbol 'f' already defined in current scope");
+        let err = resolve_source("fn f(a: int, a: int) {}").unwrap_err();
+        assert_eq!(err.message(), "Symbol 'a' already defined in current scope");
+    }
+
+    #[test]
+    fn test_runtime_builtins() {
+        let source = "\
+fn main() {
+    let xs = [1, 2];
+    let line = read_line();
+    println(to_string(len(xs)) + line);
+    print(parse_int(\"4\"), parse_float(\"0.5\"));
+    let len = 3;
+    println(len);
+}";
+        let resolution = resolve_source(source).unwrap();
+        for builtin in [
+            "read_line",
+            "to_string",
+            "parse_int",
+            "parse_float",
+            "print",
+        ] {
+            assert_eq!(use_of(&resolution, source, builtin, 0), None, "{}", builtin);
+        }
+        // Only the variable shadowing `len` is a use of a symbol.
+        let len = use_of(&resolution, source, "len", 0).unwrap();
+        assert_eq!(resolution.symbol(len).kind, SymbolKind::Variable);
+        assert_eq!(resolution.uses_of(len).len(), 1);
+    }
+
+    #[test]
+    fn test_imports_and_shadowed_locals() {
+        let source = "\
+import shapes::{Shape, area};
+fn f(x: int) -> float {
+    let x = x + 1;
+    let s = Shape::Circle(2.0);
+    return area(s);
+}";
+        let resolution = resolve_source(source).unwrap();
+        let area = use_of(&resolution, source, "area", 0).unwrap();
+        assert_eq!(resolution.symbol(area).kind, SymbolKind::Import);
+        let shape = use_of(&resolution, source, "Shape::Circle", 0).unwrap();
+        assert_eq!(resolution.symbol(shape).name, "Shape");
+
+        let param = use_of(&resolution, source, "x", 0).unwrap();
+        let (local, _) = resolution
+            .symbols()
+            .filter(|(_, symbol)| symbol.name == "x")
+            .last()
+            .unwrap();
+        assert_eq!(resolution.shadowed(local), Some(param));
+        assert_eq!(resolution.shadowed(param), None);
+
+        let err = resolve_source("import a::{b};\nimport c::{b};").unwrap_err();
+        assert_eq!(err.message(), "Symbol 'b' already defined in current scope");
+    }
+}
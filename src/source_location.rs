@@ -1,3 +1,5 @@
+use crate::span::Span;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceLocation {
     pub file: String,
@@ -43,14 +45,30 @@ impl SourceLocation {
         }
     }
 
+    /// Resolves the start of `span` within `content`, the text of the file it points into.
+    pub fn from_span(file: &str, content: &str, span: Span) -> Self {
+        let line_info = Self::compute_line_info(content);
+        let (line, column) = Self::calculate_position(span.start, &line_info);
+
+        Self {
+            file: file.to_string(),
+            directory: std::path::Path::new(file)
+                .parent()
+                .and_then(|p| p.to_str())
+                .filter(|p| !p.is_empty())
+                .unwrap_or(".")
+                .to_string(),
+            line,
+            column,
+        }
+    }
+
     fn compute_line_info(content: &str) -> LineInfo {
         let mut line_starts = vec![0];
-        let mut pos = 0;
 
-        for c in content.chars() {
-            pos += 1;
+        for (pos, c) in content.char_indices() {
             if c == '\n' {
-                line_starts.push(pos);
+                line_starts.push(pos + 1);
             }
         }
 
@@ -115,6 +133,15 @@ mod tests {
         assert_eq!(loc.column, 1);
     }
 
+    #[test]
+    fn test_from_span() {
+        let content = "fn main() {\n    let x = y;\n}";
+        let span = Span::new(crate::span::FileId(0), 24, 25);
+        let loc = SourceLocation::from_span("src/main.io", content, span);
+        assert_eq!((loc.line, loc.column), (2, 13));
+        assert_eq!(loc.directory, "src");
+    }
+
     #[test]
     fn test_position_at_end() {
         let content = "line1\nline2";
@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// Identifies a source file registered with a `diagnostics::SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub u32);
+
+/// Identifies one macro expansion registered with a `diagnostics::SourceMap`;
+/// `ExpansionId::ROOT` stands for source written by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ExpansionId(pub u32);
+
+impl ExpansionId {
+    pub const ROOT: ExpansionId = ExpansionId(0);
+}
+
+/// A half-open byte range `[start, end)` into a single source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub file_id: FileId,
+    pub start: usize,
+    pub end: usize,
+    /// The macro expansion that produced the text, whose template the range
+    /// points into.
+    pub expansion: ExpansionId,
+}
+
+impl Span {
+    pub fn new(file_id: FileId, start: usize, end: usize) -> Self {
+        Self {
+            file_id,
+            start,
+            end,
+            expansion: ExpansionId::ROOT,
+        }
+    }
+
+    /// The same range, as produced by `expansion`.
+    pub fn in_expansion(self, expansion: ExpansionId) -> Self {
+        Self { expansion, ..self }
+    }
+
+    /// Placeholder span for synthesized nodes that have no source text.
+    pub fn dummy() -> Self {
+        Self::default()
+    }
+
+    pub fn is_dummy(&self) -> bool {
+        self.start == 0 && self.end == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Smallest span covering both `self` and `other`. Ranges from different
+    /// expansions are not comparable, so then it is just `self`.
+    pub fn to(&self, other: Span) -> Span {
+        if self.is_dummy() {
+            return other;
+        }
+        if other.is_dummy() || self.expansion != other.expansion {
+            return *self;
+        }
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            ..*self
+        }
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    /// Whether `other` lies within `self`, in the same file.
+    pub fn encloses(&self, other: Span) -> bool {
+        self.file_id == other.file_id && self.start <= other.start && other.end <= self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_merge() {
+        let a = Span::new(FileId(0), 4, 7);
+        let b = Span::new(FileId(0), 10, 12);
+        assert_eq!(a.to(b), Span::new(FileId(0), 4, 12));
+        assert_eq!(b.to(a), Span::new(FileId(0), 4, 12));
+        assert_eq!(Span::dummy().to(a), a);
+    }
+}
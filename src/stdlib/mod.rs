@@ -5,6 +5,7 @@ pub mod collections;
 pub mod concurrent;
 pub mod io;
 pub mod network;
+pub mod prelude;
 
 use std::collections::HashMap;
 
@@ -0,0 +1,115 @@
+// Types and functions every program can use without declaring them. A program
+// defining an item with the same name uses its own instead.
+
+/// A value that may be absent.
+enum Option<T> {
+    Some(T),
+    None,
+}
+
+/// The outcome of something that can fail: a value, or an error saying why.
+enum Result<T, E> {
+    Ok(T),
+    Err(E),
+}
+
+/// Two values side by side, as `zip` and `enumerate` give them.
+struct Pair<A, B> {
+    first: A,
+    second: B,
+}
+
+/// A sequence a `for` loop can step through. `next` takes `self` by value, so
+/// an iterator stands for the rest of its sequence: it gives the first element
+/// paired with the iterator over the elements after it, or `None` once there
+/// are none left.
+trait Iterator<T> {
+    fn next(self) -> Option<Pair<T, Self>>;
+}
+
+const fn is_some<T>(option: Option<T>) -> bool {
+    return match option {
+        Option::Some(_) => true,
+        Option::None => false,
+    };
+}
+
+const fn is_none<T>(option: Option<T>) -> bool {
+    return !is_some(option);
+}
+
+/// The value, or `default` if there is none.
+const fn unwrap_or<T>(option: Option<T>, default: T) -> T {
+    return match option {
+        Option::Some(value) => value,
+        Option::None => default,
+    };
+}
+
+/// Applies `f` to the value, if there is one.
+fn map_some<T, U>(option: Option<T>, f: fn(T) -> U) -> Option<U> {
+    return match option {
+        Option::Some(value) => Option::Some(f(value)),
+        Option::None => Option::None,
+    };
+}
+
+/// Applies `f`, which may itself find nothing, to the value, if there is one.
+fn and_then<T, U>(option: Option<T>, f: fn(T) -> Option<U>) -> Option<U> {
+    return match option {
+        Option::Some(value) => f(value),
+        Option::None => Option::None,
+    };
+}
+
+/// The value as a `Result`, failing with `error` if there is none.
+const fn ok_or<T, E>(option: Option<T>, error: E) -> Result<T, E> {
+    return match option {
+        Option::Some(value) => Result::Ok(value),
+        Option::None => Result::Err(error),
+    };
+}
+
+const fn is_ok<T, E>(result: Result<T, E>) -> bool {
+    return match result {
+        Result::Ok(_) => true,
+        Result::Err(_) => false,
+    };
+}
+
+const fn is_err<T, E>(result: Result<T, E>) -> bool {
+    return !is_ok(result);
+}
+
+/// The value, dropping the error; combine with the `Option` functions, as in
+/// `unwrap_or(ok(result), 0)`.
+const fn ok<T, E>(result: Result<T, E>) -> Option<T> {
+    return match result {
+        Result::Ok(value) => Option::Some(value),
+        Result::Err(_) => Option::None,
+    };
+}
+
+/// The error, if there is one.
+const fn err<T, E>(result: Result<T, E>) -> Option<E> {
+    return match result {
+        Result::Ok(_) => Option::None,
+        Result::Err(error) => Option::Some(error),
+    };
+}
+
+/// Applies `f` to the value, passing an error through unchanged.
+fn map_ok<T, U, E>(result: Result<T, E>, f: fn(T) -> U) -> Result<U, E> {
+    return match result {
+        Result::Ok(value) => Result::Ok(f(value)),
+        Result::Err(error) => Result::Err(error),
+    };
+}
+
+/// Applies `f` to the error, e.g. to add context before passing it on with `?`.
+fn map_err<T, E, F>(result: Result<T, E>, f: fn(E) -> F) -> Result<T, F> {
+    return match result {
+        Result::Ok(value) => Result::Ok(value),
+        Result::Err(error) => Result::Err(f(error)),
+    };
+}
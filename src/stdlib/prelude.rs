@@ -0,0 +1,113 @@
+//! The prelude: `Option<T>`, `Result<T, E>`, the `Iterator` trait and
+//! functions working on them, written in Io in `prelude.io`. The type checkers
+//! and the interpreter declare it before anything else, and monomorphization
+//! instantiates the functions a program uses. The runtime builtins, the integer
+//! intrinsics, the iterator functions and `Iter` are part of the prelude too,
+//! but built into each backend.
+
+use crate::{
+    ast::{ASTNode, NodeId},
+    lexer::Lexer,
+    parser::Parser,
+    span::FileId,
+};
+use std::sync::OnceLock;
+
+const SOURCE: &str = include_str!("prelude.io");
+
+/// The file spans in the prelude point into.
+pub const FILE_ID: FileId = FileId(u32::MAX);
+
+/// The prelude's node ids start here, far above those of any program, so side
+/// tables keyed by node id can hold both.
+const FIRST_ID: u32 = 1 << 31;
+
+/// Functions provided by the runtime that accept any arguments.
+pub const VARIADIC_BUILTINS: &[&str] = &["print", "println"];
+
+/// Functions provided by the runtime with a fixed signature: `read_line()`
+/// reads a line of standard input, `len(items)` counts the elements of an
+/// array or the characters of a string, `to_string(value)` formats any value
+/// and `parse_int(text)` and `parse_float(text)` read a number.
+pub const RUNTIME_BUILTINS: &[&str] =
+    &["read_line", "len", "to_string", "parse_int", "parse_float"];
+
+/// Integer arithmetic with explicit overflow behavior: `wrapping_*` wraps
+/// around at the bounds of the operands' type, `checked_*` returns an `Option`
+/// that is `None` on overflow.
+pub const INTEGER_INTRINSICS: &[&str] = &[
+    "wrapping_add",
+    "wrapping_sub",
+    "wrapping_mul",
+    "checked_add",
+    "checked_sub",
+    "checked_mul",
+];
+
+/// Functions over anything a `for` loop can iterate: an array, a string, a
+/// range, an `Iter` or a value implementing `Iterator`. `map(items, f)`,
+/// `filter(items, keep)`, `zip(a, b)`, `enumerate(items)` and `take(items, n)`
+/// are lazy: they give an `Iter` that only steps through `items` as it is
+/// iterated itself. `fold(items, initial, f)` combines the elements into
+/// `initial` one at a time and `collect(items)` gathers them into an array.
+pub const ITERATOR_FUNCTIONS: &[&str] = &[
+    "map",
+    "filter",
+    "zip",
+    "enumerate",
+    "take",
+    "fold",
+    "collect",
+];
+
+/// The type of lazy sequences, built into each backend: `Iter<T>` gives
+/// elements of type `T`. Ranges and the lazy iterator functions have it.
+pub const ITER_TYPE: &str = "Iter";
+
+/// The prelude struct `zip` and `enumerate` give their elements in.
+pub const PAIR_TYPE: &str = "Pair";
+
+/// The prelude trait `for` loops use to step through values of other types.
+pub const ITERATOR_TRAIT: &str = "Iterator";
+
+/// The names of the functions built into each backend.
+pub fn builtins() -> impl Iterator<Item = &'static str> {
+    [
+        VARIADIC_BUILTINS,
+        RUNTIME_BUILTINS,
+        INTEGER_INTRINSICS,
+        ITERATOR_FUNCTIONS,
+    ]
+    .into_iter()
+    .flatten()
+    .copied()
+}
+
+/// Whether `name` is a function built into each backend, which a program's
+/// own binding of the name hides.
+pub fn is_builtin(name: &str) -> bool {
+    builtins().any(|builtin| builtin == name)
+}
+
+/// The prelude's items, parsed on first use.
+pub fn items() -> &'static [ASTNode] {
+    static ITEMS: OnceLock<Vec<ASTNode>> = OnceLock::new();
+    ITEMS.get_or_init(|| {
+        let tokens = Lexer::with_file_id(SOURCE, FILE_ID)
+            .tokenize()
+            .expect("the prelude lexes");
+        match Parser::new(tokens.into_iter())
+            .with_first_id(FIRST_ID)
+            .parse_program()
+            .expect("the prelude parses")
+        {
+            ASTNode::Program(items) => items,
+            item => vec![item],
+        }
+    })
+}
+
+/// Whether `id` belongs to a node of the prelude.
+pub fn contains(id: NodeId) -> bool {
+    id.0 >= FIRST_ID
+}
@@ -1,18 +1,25 @@
+use crate::span::Span;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub lexeme: String,
-    pub position: usize,
-} // Added missing closing brace
+    pub span: Span,
+}
 
 impl Token {
-    pub fn new(kind: TokenKind, lexeme: String, position: usize) -> Self {
+    pub fn new(kind: TokenKind, lexeme: impl Into<String>, span: Span) -> Self {
         Self {
             kind,
-            lexeme,
-            position,
+            lexeme: lexeme.into(),
+            span,
         }
     }
+
+    /// Byte offset of the first character of the token.
+    pub fn position(&self) -> usize {
+        self.span.start
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -22,6 +29,8 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -29,40 +38,81 @@ pub enum TokenKind {
     Semicolon,
     Slash,
     Star,
+    Percent,
     Colon,
-    Arrow,
+    Ampersand,
+    Pipe,
+    Caret,
+    Question,
+    Dollar,
+    Hash,
 
-    // One or two character tokens
+    // One, two or three character tokens
+    Arrow,
+    FatArrow,
+    ColonColon,
+    DotDot,
+    DotDotEqual,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
     Less,
     LessEqual,
+    LessLess,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
 
-    // Keywords
+    // `&&` and `||`
     And,
     Or,
+
+    // Keywords
     If,
     Else,
     True,
     False,
     Function,
+    Struct,
+    Enum,
+    Match,
+    Trait,
+    Impl,
+    Dyn,
+    Mut,
     Let,
     Return,
     While,
     For,
+    In,
     Break,
     Continue,
     Async,
     Await,
+    As,
+    Import,
+    Pub,
+    Move,
+    Macro,
+    Const,
 
     // Literals
     Identifier,
     String,
     Number,
+    Char,
+
+    // `///` comments, kept for the documentation generator
+    DocComment,
+
+    /// Malformed input the lexer already reported; lets the parser continue quietly.
+    Error,
 
     EOF,
 }
@@ -1,35 +1,178 @@
 use crate::{
-    ast::{ASTNode, BinaryOperator, Parameter},
+    ast::{
+        ASTNode, BinaryOperator, Field, Literal, MatchArm, NodeId, Parameter, Pattern,
+        PatternFields, TraitMethod, UnaryOperator, Variant, VariantFields,
+    },
+    diagnostics::Diagnostic,
     error::{self, IoError},
-    types::Type,
+    pattern::{self, Constructor, FieldStyle, TypeDefinitions},
+    semantic::resolve::{Resolution, Resolver, SymbolId},
+    span::Span,
+    stdlib::prelude,
+    types::{
+        infer::{InferenceTable, Kind},
+        Type,
+    },
     Result,
 };
 use std::collections::HashMap;
 
+/// A struct definition; field types may mention its type parameters.
+#[derive(Debug, Clone)]
+struct StructInfo {
+    type_params: Vec<String>,
+    fields: Vec<(String, Type)>,
+}
+
+/// An enum definition; variant field types may mention its type parameters.
+#[derive(Debug, Clone)]
+struct EnumInfo {
+    type_params: Vec<String>,
+    variants: Vec<Constructor>,
+}
+
+/// A trait's type parameters and methods in declaration order. The method
+/// types leave out `self` and mention the implementing type as
+/// `Type::Param("Self")`.
+#[derive(Debug, Clone)]
+struct TraitInfo {
+    type_params: Vec<String>,
+    methods: Vec<MethodInfo>,
+    /// Whether this holds the methods of an inherent impl, under the name of
+    /// the only type implementing it; such a trait cannot be named.
+    inherent: bool,
+}
+
+#[derive(Debug, Clone)]
+struct MethodInfo {
+    name: String,
+    ty: Type,
+    has_default: bool,
+}
+
+/// The implementation of `trait_name` for `self_type` that a method call or a
+/// conversion to `dyn Trait` uses. A `self_type` that is a type parameter or a
+/// `dyn` type is only pinned down by monomorphization or at run time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Implementation {
+    pub self_type: Type,
+    pub trait_name: String,
+}
+
 pub struct TypeChecker {
+    /// Builtin types and functions, by name.
     type_env: HashMap<String, Type>,
+    /// What each identifier refers to, as resolved for the node last checked.
+    resolution: Resolution,
+    /// The type of each variable, parameter and global, by symbol.
+    symbol_types: HashMap<SymbolId, Type>,
     current_function_return_type: Option<Type>,
-    in_loop: bool,
+    structs: HashMap<String, StructInfo>,
+    enums: HashMap<String, EnumInfo>,
+    traits: HashMap<String, TraitInfo>,
+    /// Traits implemented by each type, keyed by the type's name.
+    impls: HashMap<String, Vec<String>>,
+    /// The type arguments of each implementation of a generic trait, keyed by
+    /// the type's name and the trait.
+    impl_args: HashMap<(String, String), Vec<Type>>,
+    /// Type parameters of each generic function, in declaration order.
+    generic_functions: HashMap<String, Vec<String>>,
+    /// Trait bounds on the type parameters of each generic function.
+    generic_bounds: HashMap<String, Vec<(String, String)>>,
+    /// Bounds on the type parameters in scope; `Self` is bounded by the trait
+    /// whose default methods are being checked.
+    bounds: Vec<(String, String)>,
+    /// What `Self` stands for inside an impl.
+    self_type: Option<Type>,
+    /// The implementation each method call dispatches to, by call node id.
+    method_calls: HashMap<NodeId, Implementation>,
+    /// Values converted to `dyn Trait`, by node id.
+    coercions: HashMap<NodeId, Implementation>,
+    /// Type arguments inferred for each call of a generic function, in the
+    /// order of the callee's type parameters.
+    instantiations: HashMap<NodeId, Vec<Type>>,
+    /// The type of each variable, keyed by the node declaring it: a `let`, a
+    /// parameter, a `for` loop or a binding in a `let` pattern. A `let`
+    /// pattern's own id has the type of the value it takes apart.
+    binding_types: HashMap<NodeId, Type>,
+    /// The function type of each closure, by node id.
+    closure_types: HashMap<NodeId, Type>,
+    /// The position of the field each member access names in its struct or
+    /// tuple, by node id.
+    field_indices: HashMap<NodeId, usize>,
+    /// The struct or enum each struct literal builds, by node id.
+    struct_literals: HashMap<NodeId, Type>,
+    /// The enum each `Enum::Variant` path builds and each `?` takes apart, by
+    /// node id.
+    enum_values: HashMap<NodeId, Type>,
+    /// The type of each number literal, the operand type of each arithmetic,
+    /// bitwise or comparison operator, compound assignment and integer
+    /// intrinsic, and the source type of each `as`, by node id.
+    numeric_types: HashMap<NodeId, Type>,
+    /// Integer literals still to be checked against the range of their type,
+    /// once it is known.
+    int_literals: Vec<(NodeId, i128, Type, Span)>,
+    /// Solutions for the type variables of unannotated bindings.
+    table: InferenceTable,
+    /// Problems that don't stop the program from compiling, like unreachable
+    /// match arms.
+    warnings: Vec<Diagnostic>,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         let mut checker = Self {
             type_env: HashMap::new(),
+            resolution: Resolver::with_prelude().into_resolution(),
+            symbol_types: HashMap::new(),
             current_function_return_type: None,
-            in_loop: false,
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            impls: HashMap::new(),
+            impl_args: HashMap::new(),
+            generic_functions: HashMap::new(),
+            generic_bounds: HashMap::new(),
+            bounds: Vec::new(),
+            self_type: None,
+            method_calls: HashMap::new(),
+            coercions: HashMap::new(),
+            instantiations: HashMap::new(),
+            binding_types: HashMap::new(),
+            closure_types: HashMap::new(),
+            field_indices: HashMap::new(),
+            struct_literals: HashMap::new(),
+            enum_values: HashMap::new(),
+            numeric_types: HashMap::new(),
+            int_literals: Vec::new(),
+            table: InferenceTable::new(),
+            warnings: Vec::new(),
         };
         checker.init_builtin_types();
+        for item in prelude::items() {
+            checker.check_node(item).expect("the prelude type-checks");
+        }
         checker
     }
 
     fn init_builtin_types(&mut self) {
         let builtins = vec![
+            ("i8", Type::I8),
+            ("i16", Type::I16),
             ("i32", Type::I32),
             ("i64", Type::I64),
+            ("i128", Type::I128),
+            ("isize", Type::Isize),
+            ("u8", Type::U8),
+            ("u16", Type::U16),
+            ("u32", Type::U32),
+            ("u64", Type::U64),
+            ("u128", Type::U128),
+            ("usize", Type::Usize),
             ("f32", Type::F32),
             ("f64", Type::F64),
             ("bool", Type::Bool),
+            ("char", Type::Char),
             ("str", Type::String),
             ("void", Type::Void),
         ];
@@ -39,27 +182,371 @@ impl TypeChecker {
         }
     }
 
+    /// Type-checks `node`, returning its type as far as it can be inferred. Errors
+    /// point at the offending node. Number literals whose type nothing decided
+    /// are `i32` or `f64` afterwards. `resolution` is what the names of `node`
+    /// and of the nodes checked before refer to, resolved after the prelude.
+    pub fn check(&mut self, node: &ASTNode, resolution: &Resolution) -> Result<Type> {
+        self.resolution = resolution.clone();
+        let ty = match node {
+            ASTNode::Program(items) => {
+                for item in items {
+                    self.check_node(item)?;
+                }
+                Type::Void
+            }
+            _ => self.check_node(node)?,
+        };
+        self.table.settle_all();
+        self.check_literal_ranges()?;
+        Ok(self.table.resolve(&ty))
+    }
+
+    /// Fails on the first integer literal that doesn't fit in its type.
+    fn check_literal_ranges(&mut self) -> Result<()> {
+        for (_, value, ty, span) in std::mem::take(&mut self.int_literals) {
+            let ty = self.table.resolve(&ty);
+            if ty.is_integer() && !ty.fits_integer(value) {
+                return Err(IoError::type_error(format!(
+                    "Integer literal {} is out of range for {}",
+                    value, ty
+                ))
+                .with_span(span));
+            }
+        }
+        Ok(())
+    }
+
+    /// The type arguments inferred for each generic call checked so far, keyed by
+    /// the call's node id.
+    pub fn instantiations(&self) -> HashMap<NodeId, Vec<Type>> {
+        self.instantiations
+            .iter()
+            .map(|(id, args)| {
+                (
+                    *id,
+                    args.iter().map(|arg| self.table.resolve(arg)).collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// The implementation each method call checked so far dispatches to, keyed
+    /// by the call's node id.
+    pub fn method_calls(&self) -> HashMap<NodeId, Implementation> {
+        self.method_calls
+            .iter()
+            .map(|(id, implementation)| {
+                (
+                    *id,
+                    Implementation {
+                        self_type: self.table.resolve(&implementation.self_type),
+                        trait_name: implementation.trait_name.clone(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// The symbols of the prelude and the programs checked so far.
+    pub fn resolution(&self) -> &Resolution {
+        &self.resolution
+    }
+
+    /// Values converted to `dyn Trait` so far, keyed by node id, with the type
+    /// they were converted from.
+    pub fn coercions(&self) -> &HashMap<NodeId, Implementation> {
+        &self.coercions
+    }
+
+    /// The methods of `trait_name` in declaration order, which is the order of
+    /// its vtable slots, with their types without `self`.
+    pub fn trait_methods(&self, trait_name: &str) -> Option<Vec<(String, Type)>> {
+        let info = self.traits.get(trait_name)?;
+        Some(
+            info.methods
+                .iter()
+                .map(|method| (method.name.clone(), method.ty.clone()))
+                .collect(),
+        )
+    }
+
+    /// The variants of each enum in declaration order, with their field counts.
+    pub fn enum_variants(&self) -> HashMap<String, Vec<(String, usize)>> {
+        self.enums
+            .iter()
+            .map(|(name, info)| {
+                let variants = info
+                    .variants
+                    .iter()
+                    .map(|variant| (variant.name.clone(), variant.fields.len()))
+                    .collect();
+                (name.clone(), variants)
+            })
+            .collect()
+    }
+
+    /// The types of the variables declared so far, keyed by the `let`,
+    /// parameter, `for` loop or pattern binding declaring them, and of the
+    /// values `let` patterns took apart.
+    pub fn binding_types(&self) -> HashMap<NodeId, Type> {
+        self.binding_types
+            .iter()
+            .map(|(id, ty)| (*id, self.table.resolve(ty)))
+            .collect()
+    }
+
+    /// The function type of each closure checked so far, keyed by node id.
+    pub fn closure_types(&self) -> HashMap<NodeId, Type> {
+        self.closure_types
+            .iter()
+            .map(|(id, ty)| (*id, self.table.resolve(ty)))
+            .collect()
+    }
+
+    /// The position of the field each member access checked so far reads or
+    /// assigns, keyed by node id. It is the same for every instance of a
+    /// generic function.
+    pub fn field_indices(&self) -> &HashMap<NodeId, usize> {
+        &self.field_indices
+    }
+
+    /// The struct or enum type each struct literal checked so far builds,
+    /// keyed by node id.
+    pub fn struct_literals(&self) -> HashMap<NodeId, Type> {
+        self.struct_literals
+            .iter()
+            .map(|(id, ty)| (*id, self.table.resolve(ty)))
+            .collect()
+    }
+
+    /// The enum type of each `Enum::Variant` path and each operand of `?`
+    /// checked so far, keyed by node id.
+    pub fn enum_values(&self) -> HashMap<NodeId, Type> {
+        self.enum_values
+            .iter()
+            .map(|(id, ty)| (*id, self.table.resolve(ty)))
+            .collect()
+    }
+
+    /// The type of each number literal, the operand type of each operator,
+    /// compound assignment and integer intrinsic, and the source type of each
+    /// `as` checked so far, keyed by node id.
+    pub fn numeric_types(&self) -> HashMap<NodeId, Type> {
+        self.numeric_types
+            .iter()
+            .map(|(id, ty)| (*id, self.table.resolve(ty)))
+            .collect()
+    }
+
+    /// Warnings found by the checks so far.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Unifies `found`, the type of the code at `span`, with `expected`. A mismatch
+    /// reads `context: expected X, found Y`, with notes pointing at where any
+    /// inferred part of either type was learned.
+    fn expect_type(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        span: Span,
+        context: &str,
+    ) -> Result<()> {
+        self.table.unify(expected, found, span).map_err(|mismatch| {
+            let mut err = IoError::type_error(format!(
+                "{}: expected {}, found {}",
+                context, mismatch.expected, mismatch.found
+            ))
+            .with_span(span);
+            let mut origins = self.table.origins(expected);
+            origins.extend(self.table.origins(found));
+            for (origin, ty) in origins {
+                if origin != span && !origin.is_dummy() {
+                    err = err.with_note(origin, format!("inferred as {} here", ty));
+                }
+            }
+            err
+        })
+    }
+
     fn check_node(&mut self, node: &ASTNode) -> Result<Type> {
+        self.check_node_kind(node)
+            .map_err(|err| err.or_span(node.span()))
+    }
+
+    fn check_node_kind(&mut self, node: &ASTNode) -> Result<Type> {
         match node {
-            ASTNode::IntLiteral { .. } => Ok(Type::Int),
-            ASTNode::FloatLiteral { .. } => Ok(Type::Float),
-            ASTNode::StringLiteral { .. } => Ok(Type::String),
-            ASTNode::BoolLiteral { .. } => Ok(Type::Bool),
-            ASTNode::Identifier { name, .. } => self.check_identifier(name),
+            ASTNode::Literal {
+                value, id, span, ..
+            } => Ok(self.check_literal(value, *id, *span)),
+            ASTNode::Identifier { name, id, .. } => match self.check_identifier(name, *id) {
+                Err(_) if name.contains("::") => self.check_variant_path(name, *id),
+                result => result,
+            },
             ASTNode::BinaryOp {
-                op, left, right, ..
-            } => self.check_binary_op(op, left, right),
-            ASTNode::Call { name, args, .. } => self.check_call(name, args),
+                op,
+                left,
+                right,
+                id,
+                ..
+            } => self.check_binary_op(op, left, right, *id),
+            ASTNode::Call {
+                callee, args, id, ..
+            } => self.check_call(callee, args, *id),
             ASTNode::Function {
                 name,
+                type_params,
+                bounds,
                 params,
                 return_type,
                 body,
                 is_async,
                 ..
-            } => self.check_function(name, params, return_type, body, *is_async),
-            ASTNode::Return { value, .. } => self.check_return(value),
+            } => {
+                if type_params.is_empty() {
+                    self.generic_functions.remove(name);
+                } else {
+                    self.generic_functions
+                        .insert(name.clone(), type_params.clone());
+                }
+                for (_, bound) in bounds {
+                    match self.traits.get(bound) {
+                        None | Some(TraitInfo { inherent: true, .. }) => {
+                            return Err(IoError::type_error(format!("Unknown trait {}", bound)))
+                        }
+                        Some(info) if !info.type_params.is_empty() => {
+                            return Err(IoError::type_error(format!(
+                                "Generic trait {} cannot be used as a bound",
+                                bound
+                            )))
+                        }
+                        Some(_) => {}
+                    }
+                }
+                self.generic_bounds.insert(name.clone(), bounds.clone());
+
+                let outer = std::mem::replace(&mut self.bounds, bounds.clone());
+                let result =
+                    self.check_function(name, params, return_type.as_ref(), body, *is_async);
+                self.bounds = outer;
+                result
+            }
+            ASTNode::StructDef {
+                name,
+                type_params,
+                fields,
+                ..
+            } => self.check_struct_def(name, type_params, fields),
+            ASTNode::EnumDef {
+                name,
+                type_params,
+                variants,
+                ..
+            } => self.check_enum_def(name, type_params, variants),
+            ASTNode::TraitDef {
+                name,
+                type_params,
+                methods,
+                ..
+            } => self.check_trait_def(name, type_params, methods),
+            ASTNode::Impl {
+                trait_name,
+                trait_args,
+                self_type,
+                methods,
+                ..
+            } => self.check_impl(trait_name.as_deref(), trait_args, self_type, methods),
+            ASTNode::Import { path, .. } => Err(IoError::type_error(format!(
+                "Unresolved import of module {}",
+                path.join("::")
+            ))),
+            ASTNode::Return { value, .. } => self.check_return(value.as_deref()),
             ASTNode::Block { statements, .. } => self.check_block(statements),
+            ASTNode::While {
+                condition, body, ..
+            } => {
+                self.check_node(condition)?;
+                self.check_loop(body)
+            }
+            ASTNode::For {
+                pattern,
+                iterable,
+                body,
+                id,
+                ..
+            } => self.check_for(pattern, iterable, body, *id),
+            ASTNode::Let {
+                type_annotation,
+                value,
+                id,
+                ..
+            } => self.check_let(type_annotation.as_ref(), value.as_deref(), *id),
+            ASTNode::Global {
+                type_annotation,
+                value,
+                id,
+                ..
+            } => self.check_let(type_annotation.as_ref(), Some(value), *id),
+            ASTNode::LetPattern {
+                pattern,
+                type_annotation,
+                value,
+                id,
+                ..
+            } => self.check_let_pattern(pattern, type_annotation.as_ref(), value, *id),
+            ASTNode::Assignment { target, value, .. } => self.check_assignment(target, value),
+            ASTNode::CompoundAssignment {
+                target,
+                op,
+                value,
+                id,
+                ..
+            } => self.check_compound_assignment(target, op, value, *id),
+            ASTNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => self.check_if(condition, then_branch, else_branch.as_deref()),
+            ASTNode::UnaryOp {
+                op,
+                operand,
+                id,
+                span,
+            } => self.check_unary_op(op, operand, *id, *span),
+            ASTNode::Cast {
+                value, target, id, ..
+            } => self.check_cast(value, target, *id),
+            ASTNode::Closure {
+                params,
+                return_type,
+                body,
+                id,
+                ..
+            } => self.check_closure(params, return_type.as_ref(), body, *id, None),
+            ASTNode::ArrayLiteral { elements, .. } => self.check_array(elements),
+            ASTNode::TupleLiteral { elements, .. } => Ok(Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.check_node(element))
+                    .collect::<Result<_>>()?,
+            )),
+            ASTNode::StructLiteral {
+                name, fields, id, ..
+            } => self.check_struct_literal(name, fields, *id),
+            ASTNode::Match {
+                scrutinee, arms, ..
+            } => self.check_match(scrutinee, arms),
+            ASTNode::Index { array, index, .. } => self.check_index(array, index),
+            ASTNode::MemberAccess {
+                object, member, id, ..
+            } => self.check_member_access(object, member, *id),
+            // The resolver rejects `break` and `continue` outside loops.
+            ASTNode::Break { .. } | ASTNode::Continue { .. } => Ok(Type::Void),
+            // `module::ModuleManager` applies them as it loads the file.
+            ASTNode::ModuleAttributes { .. } => Ok(Type::Void),
             _ => Err(IoError::type_error("Unsupported node type")),
         }
     }
@@ -68,22 +555,11 @@ impl TypeChecker {
         &mut self,
         name: &str,
         params: &[Parameter],
-        return_type: &Option<String>,
+        return_type: Option<&Type>,
         body: &[ASTNode],
         is_async: bool,
     ) -> Result<Type> {
-        let param_types = params
-            .iter()
-            .map(|p| self.resolve_type(&p.type_annotation))
-            .collect::<Result<Vec<_>>>()?;
-
-        let ret_type = match return_type {
-            Some(t) => self.resolve_type(t)?,
-            None => Type::Unit,
-        };
-
-        // Store return type for checking returns in function body
-        self.current_function_return_type = Some(ret_type.clone());
+        let (param_types, ret_type) = self.signature(params, return_type)?;
 
         let fn_type = Type::Function {
             params: param_types.clone(),
@@ -94,31 +570,151 @@ impl TypeChecker {
         // Add function to environment
         self.type_env.insert(name.to_string(), fn_type.clone());
 
-        // Add parameters to environment
-        let prev_env = self.type_env.clone();
+        self.check_body(params, &param_types, &ret_type, body)?;
+        Ok(fn_type)
+    }
+
+    /// Checks a function or method body with `params` bound to `param_types`.
+    fn check_body(
+        &mut self,
+        params: &[Parameter],
+        param_types: &[Type],
+        ret_type: &Type,
+        body: &[ASTNode],
+    ) -> Result<()> {
+        // Store return type for checking returns in function body
+        self.current_function_return_type = Some(ret_type.clone());
+
         for (param, param_type) in params.iter().zip(param_types.iter()) {
-            self.type_env.insert(param.name.clone(), param_type.clone());
+            self.bind(param.id, param_type.clone());
+            self.binding_types.insert(param.id, param_type.clone());
         }
 
         // Check body
-        let mut block_type = Type::Unit;
+        let mut block_type = Type::Void;
         for node in body {
             block_type = self.check_node(node)?;
         }
 
-        // Verify return type
-        if !self.types_match(&block_type, &ret_type) {
-            return Err(IoError::type_error(format!(
-                "Function return type mismatch. Expected {:?}, found {:?}",
-                ret_type, block_type
-            )));
+        // A trailing expression is the function's value; `return` statements were
+        // checked against the declared type as they were reached.
+        if let Some(last) = body.last() {
+            if self.table.resolve(&block_type) != Type::Void {
+                self.expect_type(ret_type, &block_type, last.span(), "Return type mismatch")?;
+            }
         }
 
-        // Restore environment
-        self.type_env = prev_env;
         self.current_function_return_type = None;
 
-        Ok(fn_type)
+        Ok(())
+    }
+
+    fn check_struct_def(
+        &mut self,
+        name: &str,
+        type_params: &[String],
+        fields: &[Field],
+    ) -> Result<Type> {
+        let fields = self.resolve_fields(fields, &format!("struct {}", name))?;
+        self.structs.insert(
+            name.to_string(),
+            StructInfo {
+                type_params: type_params.to_vec(),
+                fields,
+            },
+        );
+        Ok(Type::Void)
+    }
+
+    fn check_enum_def(
+        &mut self,
+        name: &str,
+        type_params: &[String],
+        variants: &[Variant],
+    ) -> Result<Type> {
+        // Registered before its variants are resolved, so they can refer to it.
+        self.enums.insert(
+            name.to_string(),
+            EnumInfo {
+                type_params: type_params.to_vec(),
+                variants: Vec::new(),
+            },
+        );
+
+        let mut constructors: Vec<Constructor> = Vec::with_capacity(variants.len());
+        for variant in variants {
+            if constructors.iter().any(|ctor| ctor.name == variant.name) {
+                return Err(IoError::type_error(format!(
+                    "Duplicate variant {} in enum {}",
+                    variant.name, name
+                ))
+                .with_span(variant.span));
+            }
+            let (style, fields) = match &variant.fields {
+                VariantFields::Unit => (FieldStyle::Unit, Vec::new()),
+                VariantFields::Tuple(types) => (
+                    FieldStyle::Tuple,
+                    types
+                        .iter()
+                        .enumerate()
+                        .map(|(i, ty)| Ok((i.to_string(), self.resolve_annotation(ty)?)))
+                        .collect::<Result<_>>()
+                        .map_err(|err| err.or_span(variant.span))?,
+                ),
+                VariantFields::Named(fields) => (
+                    FieldStyle::Named,
+                    self.resolve_fields(fields, &format!("variant {}::{}", name, variant.name))?,
+                ),
+            };
+            constructors.push(Constructor {
+                name: variant.name.clone(),
+                style,
+                fields,
+            });
+        }
+
+        if let Some(info) = self.enums.get_mut(name) {
+            info.variants = constructors;
+        }
+        Ok(Type::Void)
+    }
+
+    /// Resolves the field types of a struct or variant, described by `owner` in
+    /// errors.
+    fn resolve_fields(&self, fields: &[Field], owner: &str) -> Result<Vec<(String, Type)>> {
+        let mut resolved: Vec<(String, Type)> = Vec::with_capacity(fields.len());
+        for field in fields {
+            if resolved.iter().any(|(existing, _)| *existing == field.name) {
+                return Err(IoError::type_error(format!(
+                    "Duplicate field {} in {}",
+                    field.name, owner
+                ))
+                .with_span(field.span));
+            }
+            let ty = self
+                .resolve_annotation(&field.type_annotation)
+                .map_err(|err| err.with_span(field.span))?;
+            resolved.push((field.name.clone(), ty));
+        }
+        Ok(resolved)
+    }
+
+    /// An unsuffixed number literal is an integer or float of a type left for
+    /// its uses to decide.
+    fn check_literal(&mut self, value: &Literal, id: NodeId, span: Span) -> Type {
+        let ty = match value {
+            Literal::Integer(_) => self.table.fresh_of(Kind::Integer),
+            Literal::Float(_) => self.table.fresh_of(Kind::Float),
+            Literal::TypedInteger(..) | Literal::TypedFloat(..) => value.default_type(),
+            other => return other.default_type(),
+        };
+        match value {
+            Literal::Integer(n) => self.int_literals.push((id, *n as i128, ty.clone(), span)),
+            Literal::TypedInteger(n, _) => self.int_literals.push((id, *n, ty.clone(), span)),
+            _ => {}
+        }
+        self.numeric_types.insert(id, ty.clone());
+        ty
     }
 
     fn check_binary_op(
@@ -126,182 +722,1925 @@ impl TypeChecker {
         op: &BinaryOperator,
         left: &ASTNode,
         right: &ASTNode,
+        id: NodeId,
     ) -> Result<Type> {
         let left_type = self.check_node(left)?;
         let right_type = self.check_node(right)?;
+        let left_type = self.table.resolve(&left_type);
+
+        if left_type == Type::String {
+            let right_type = self.table.resolve(&right_type);
+            return self.check_string_operation(op, left_type, right_type);
+        }
+
+        // Apart from `&&` and `||`, both operands have the same type, and it may
+        // only become known here.
+        let operand_type = if matches!(op, BinaryOperator::And | BinaryOperator::Or) {
+            self.expect_type(&Type::Bool, &left_type, left.span(), "Invalid operand")?;
+            self.expect_type(&Type::Bool, &right_type, right.span(), "Invalid operand")?;
+            Type::Bool
+        } else {
+            let right_resolved = self.table.resolve(&right_type);
+            if left_type.is_numeric() && right_resolved.is_numeric() && left_type != right_resolved
+            {
+                return Err(IoError::type_error(format!(
+                    "Mismatched operands: {} and {}; convert one of them with `as`",
+                    left_type, right_resolved
+                ))
+                .with_span(right.span()));
+            }
+            self.expect_type(&left_type, &right_type, right.span(), "Mismatched operands")?;
+            self.table.resolve(&left_type)
+        };
+        let unknown = matches!(operand_type, Type::Var(_));
+        if !matches!(op, BinaryOperator::And | BinaryOperator::Or) {
+            self.numeric_types.insert(id, operand_type.clone());
+        }
 
         match op {
             BinaryOperator::Add
             | BinaryOperator::Subtract
             | BinaryOperator::Multiply
-            | BinaryOperator::Divide => match (&left_type, &right_type) {
-                (Type::Int, Type::Int) => Ok(Type::Int),
-                (Type::Float, Type::Float) => Ok(Type::Float),
-                _ => Err(error::IoError::type_error("Invalid operand types")),
-            },
-            BinaryOperator::Equal | BinaryOperator::NotEqual => {
-                if left_type != right_type {
-                    return Err(IoError::type_error("Type mismatch in comparison"));
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo => {
+                if operand_type.is_numeric() || unknown {
+                    Ok(operand_type)
+                } else {
+                    Err(error::IoError::type_error("Invalid operand types"))
                 }
-                Ok(Type::Bool)
             }
+            BinaryOperator::Equal | BinaryOperator::NotEqual => Ok(Type::Bool),
             BinaryOperator::LessThan
             | BinaryOperator::LessThanEqual
             | BinaryOperator::GreaterThan
-            | BinaryOperator::GreaterThanEqual => match (&left_type, &right_type) {
-                (Type::Int | Type::Float, Type::Int | Type::Float) => Ok(Type::Bool),
-                _ => Err(error::IoError::type_error("Invalid comparison types")),
-            },
-            BinaryOperator::And | BinaryOperator::Or => match (&left_type, &right_type) {
-                (Type::Bool, Type::Bool) => Ok(Type::Bool),
-                _ => Err(IoError::type_error(
-                    "Logical operators require boolean operands",
-                )),
+            | BinaryOperator::GreaterThanEqual => {
+                if operand_type.is_numeric() || unknown {
+                    Ok(Type::Bool)
+                } else {
+                    Err(error::IoError::type_error("Invalid comparison types"))
+                }
+            }
+            BinaryOperator::And | BinaryOperator::Or => Ok(Type::Bool),
+            BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseXor
+            | BinaryOperator::LeftShift
+            | BinaryOperator::RightShift => {
+                if operand_type.is_integer() || unknown {
+                    Ok(operand_type)
+                } else {
+                    Err(IoError::type_error(
+                        "Bitwise operators require integer operands",
+                    ))
+                }
+            }
+            // Ranges are lazy sequences of their bounds' type.
+            BinaryOperator::Range | BinaryOperator::RangeInclusive => {
+                if operand_type.is_integer() || unknown {
+                    Ok(iter_type(operand_type))
+                } else {
+                    Err(IoError::type_error("Range bounds must be integers"))
+                }
+            }
+        }
+    }
+
+    fn check_trait_def(
+        &mut self,
+        name: &str,
+        type_params: &[String],
+        methods: &[TraitMethod],
+    ) -> Result<Type> {
+        let mut infos: Vec<MethodInfo> = Vec::with_capacity(methods.len());
+        for method in methods {
+            if infos.iter().any(|info| info.name == method.name) {
+                return Err(IoError::type_error(format!(
+                    "Duplicate method {} in trait {}",
+                    method.name, name
+                ))
+                .with_span(method.span));
+            }
+            let ty = self
+                .method_type(&method.name, &method.params, method.return_type.as_ref())
+                .map_err(|err| err.or_span(method.span))?;
+            infos.push(MethodInfo {
+                name: method.name.clone(),
+                ty,
+                has_default: method.body.is_some(),
+            });
+        }
+        // Registered before the default methods are checked, so they can call
+        // the trait's other methods on `self`.
+        self.traits.insert(
+            name.to_string(),
+            TraitInfo {
+                type_params: type_params.to_vec(),
+                methods: infos,
+                inherent: false,
             },
+        );
+
+        let outer = std::mem::replace(
+            &mut self.bounds,
+            vec![("Self".to_string(), name.to_string())],
+        );
+        let result = methods.iter().try_for_each(|method| {
+            let Some(body) = &method.body else {
+                return Ok(());
+            };
+            let (param_types, ret_type) =
+                self.signature(&method.params, method.return_type.as_ref())?;
+            self.check_body(&method.params, &param_types, &ret_type, body)
+                .map_err(|err| err.or_span(method.span))
+        });
+        self.bounds = outer;
+        result.map(|()| Type::Void)
+    }
+
+    fn check_impl(
+        &mut self,
+        trait_name: Option<&str>,
+        trait_args: &[Type],
+        self_type: &Type,
+        methods: &[ASTNode],
+    ) -> Result<Type> {
+        let (trait_name, inherent) = match trait_name {
+            Some(trait_name) => (trait_name.to_string(), false),
+            None => (self.declare_inherent_methods(self_type, methods)?, true),
+        };
+        let trait_name = trait_name.as_str();
+        let info = self
+            .traits
+            .get(trait_name)
+            .filter(|info| info.inherent == inherent)
+            .cloned()
+            .ok_or_else(|| IoError::type_error(format!("Unknown trait {}", trait_name)))?;
+        if trait_args.len() != info.type_params.len() {
+            return Err(IoError::type_error(format!(
+                "Trait {} expects {} type arguments but got {}",
+                trait_name,
+                info.type_params.len(),
+                trait_args.len()
+            )));
+        }
+        let trait_args = trait_args
+            .iter()
+            .map(|arg| self.resolve_annotation(arg))
+            .collect::<Result<Vec<_>>>()?;
+        let self_type = self.resolve_annotation(self_type)?;
+        let implemented = self.impls.entry(self_type.to_string()).or_default();
+        if implemented.iter().any(|name| name == trait_name) {
+            return Err(IoError::type_error(format!(
+                "Trait {} is already implemented for {}",
+                trait_name, self_type
+            )));
         }
+        // Registered before the methods are checked, so they can call each other.
+        implemented.push(trait_name.to_string());
+        let mut bindings: HashMap<String, Type> = info
+            .type_params
+            .iter()
+            .cloned()
+            .zip(trait_args.clone())
+            .collect();
+        if !trait_args.is_empty() {
+            self.impl_args
+                .insert((self_type.to_string(), trait_name.to_string()), trait_args);
+        }
+        bindings.insert("Self".to_string(), self_type.clone());
+
+        let outer = self.self_type.replace(self_type.clone());
+        let result = self.check_impl_methods(trait_name, &info, &bindings, methods);
+        self.self_type = outer;
+        result.map(|()| Type::Void)
     }
 
-    fn check_call(&mut self, name: &str, args: &[ASTNode]) -> Result<Type> {
-        let fn_type = self.resolve_type(name)?;
+    /// Declares the methods of `impl Type { ... }` as a trait named after the
+    /// type, and returns that name. Method calls then find them like those of
+    /// any trait the type implements.
+    fn declare_inherent_methods(
+        &mut self,
+        self_type: &Type,
+        methods: &[ASTNode],
+    ) -> Result<String> {
+        let self_type = self.resolve_annotation(self_type)?;
+        let is_defined = match &self_type {
+            Type::Named(name) | Type::Generic { name, .. } => {
+                self.structs.contains_key(name) || self.enums.contains_key(name)
+            }
+            _ => false,
+        };
+        if !is_defined {
+            return Err(IoError::type_error(format!(
+                "Methods can only be defined for structs and enums, not {}",
+                self_type
+            )));
+        }
+        let name = self_type.to_string();
+        if self.traits.contains_key(&name) {
+            return Err(IoError::type_error(format!(
+                "{} already has an impl without a trait",
+                name
+            )));
+        }
 
-        match fn_type {
-            Type::Function {
+        let mut infos = Vec::with_capacity(methods.len());
+        for method in methods {
+            let ASTNode::Function {
+                name,
                 params,
                 return_type,
+                span,
                 ..
-            } => {
-                if args.len() != params.len() {
-                    return Err(IoError::type_error(format!(
-                        "Function {} expects {} arguments but got {}",
-                        name,
-                        params.len(),
-                        args.len()
-                    )));
-                }
-
-                for (arg, param_type) in args.iter().zip(params.iter()) {
-                    let arg_type = self.check_node(arg)?;
-                    if !self.types_match(&arg_type, param_type) {
-                        return Err(IoError::type_error(format!(
-                            "Argument type mismatch: expected {:?}, got {:?}",
-                            param_type, arg_type
-                        )));
-                    }
-                }
-
-                Ok(*return_type)
+            } = method
+            else {
+                continue;
+            };
+            if infos.iter().any(|info: &MethodInfo| info.name == *name) {
+                return Err(IoError::type_error(format!(
+                    "Duplicate method {} in impl {}",
+                    name, self_type
+                ))
+                .with_span(*span));
             }
-            _ => Err(IoError::type_error(format!("{} is not a function", name))),
+            let ty = self
+                .method_type(name, params, return_type.as_ref())
+                .map_err(|err| err.or_span(*span))?;
+            infos.push(MethodInfo {
+                name: name.clone(),
+                ty,
+                has_default: false,
+            });
         }
+        self.traits.insert(
+            name.clone(),
+            TraitInfo {
+                type_params: Vec::new(),
+                methods: infos,
+                inherent: true,
+            },
+        );
+        Ok(name)
     }
 
-    fn check_return(&self, value: Option<&ASTNode>) -> Result<Type> {
-        let return_type = self
-            .current_function_return_type
-            .as_ref()
-            .ok_or_else(|| IoError::type_error("Return statement outside of function"))?;
-
-        match value {
-            Some(expr) => {
-                let expr_type = self.check_node(expr)?;
-                if !self.types_match(&expr_type, return_type) {
-                    return Err(IoError::type_error(format!(
-                        "Return type mismatch: expected {:?}, got {:?}",
-                        return_type, expr_type
-                    )));
-                }
+    /// Checks an impl's methods against the trait's, with `bindings` giving
+    /// what `Self` and the trait's type parameters stand for.
+    fn check_impl_methods(
+        &mut self,
+        trait_name: &str,
+        info: &TraitInfo,
+        bindings: &HashMap<String, Type>,
+        methods: &[ASTNode],
+    ) -> Result<()> {
+        let self_type = &bindings["Self"];
+        let mut defined: Vec<&str> = Vec::with_capacity(methods.len());
+        for method in methods {
+            let ASTNode::Function {
+                name,
+                type_params,
+                params,
+                return_type,
+                body,
+                span,
+                ..
+            } = method
+            else {
+                continue;
+            };
+            if defined.contains(&name.as_str()) {
+                return Err(IoError::type_error(format!(
+                    "Duplicate method {} in impl {} for {}",
+                    name, trait_name, self_type
+                ))
+                .with_span(*span));
             }
-            None => {
-                if !matches!(return_type, Type::Unit) {
-                    return Err(IoError::type_error(format!(
-                        "Function must return {:?}",
-                        return_type
-                    )));
-                }
+            let declared = info
+                .methods
+                .iter()
+                .find(|declared| &declared.name == name)
+                .ok_or_else(|| {
+                    IoError::type_error(format!(
+                        "Method {} is not a member of trait {}",
+                        name, trait_name
+                    ))
+                    .with_span(*span)
+                })?;
+            if !type_params.is_empty() {
+                return Err(IoError::type_error(format!(
+                    "Method {} cannot have type parameters",
+                    name
+                ))
+                .with_span(*span));
             }
-        }
 
-        Ok(Type::Unit)
-    }
+            let expected = declared.ty.substitute(bindings);
+            let found = self
+                .method_type(name, params, return_type.as_ref())
+                .map_err(|err| err.or_span(*span))?;
+            if found != expected {
+                return Err(IoError::type_error(format!(
+                    "Method {} does not match trait {}: expected {}, found {}",
+                    name, trait_name, expected, found
+                ))
+                .with_span(*span));
+            }
 
-    fn check_loop(&mut self, body: &[ASTNode]) -> Result<Type> {
-        let was_in_loop = self.in_loop;
-        self.in_loop = true;
+            let (param_types, ret_type) = self.signature(params, return_type.as_ref())?;
+            self.check_body(params, &param_types, &ret_type, body)
+                .map_err(|err| err.or_span(*span))?;
+            defined.push(name);
+        }
 
-        for node in body {
-            self.check_node(node)?;
+        match info
+            .methods
+            .iter()
+            .find(|method| !method.has_default && !defined.contains(&method.name.as_str()))
+        {
+            Some(missing) => Err(IoError::type_error(format!(
+                "Missing method {} in impl {} for {}",
+                missing.name, trait_name, self_type
+            ))),
+            None => Ok(()),
         }
+    }
 
-        self.in_loop = was_in_loop;
-        Ok(Type::Unit)
+    /// Resolved parameter and return types of a function signature.
+    fn signature(
+        &self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+    ) -> Result<(Vec<Type>, Type)> {
+        let param_types = params
+            .iter()
+            .map(|p| {
+                self.resolve_annotation(&p.type_annotation)
+                    .map_err(|err| err.or_span(p.span))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let ret_type = match return_type {
+            Some(t) => self.resolve_annotation(t)?,
+            None => Type::Void,
+        };
+        Ok((param_types, ret_type))
     }
 
-    fn check_break(&self) -> Result<Type> {
-        if !self.in_loop {
-            return Err(IoError::type_error("Break statement outside of loop"));
+    /// A method's type as seen by its callers, without the `self` parameter it
+    /// must start with.
+    fn method_type(
+        &self,
+        name: &str,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+    ) -> Result<Type> {
+        if params.first().is_none_or(|param| param.name != "self") {
+            return Err(IoError::type_error(format!(
+                "Method {} must take self as its first parameter",
+                name
+            )));
         }
-        Ok(Type::Unit)
+        let (mut param_types, ret_type) = self.signature(params, return_type)?;
+        param_types.remove(0);
+        Ok(Type::Function {
+            params: param_types,
+            return_type: Box::new(ret_type),
+            is_async: false,
+        })
     }
 
-    fn check_continue(&self) -> Result<Type> {
-        if !self.in_loop {
-            return Err(IoError::type_error("Continue statement outside of loop"));
+    /// Whether `ty` implements `trait_name`: through an impl, as a type
+    /// parameter bounded by it, or as `dyn` of it.
+    fn implements(&self, ty: &Type, trait_name: &str) -> bool {
+        match ty {
+            Type::Param(name) => self
+                .bounds
+                .iter()
+                .any(|(param, bound)| param == name && bound == trait_name),
+            Type::Dyn(name) => name == trait_name,
+            ty => self
+                .impls
+                .get(&ty.to_string())
+                .is_some_and(|traits| traits.iter().any(|name| name == trait_name)),
         }
-        Ok(Type::Unit)
     }
 
-    fn check_identifier(&self, name: &str) -> Result<Type> {
-        self.resolve_type(name)
-    }
+    /// The trait providing `method` for `receiver` and the method's type, with
+    /// `Self` replaced by the receiver; `None` if no implemented trait has it.
+    fn find_method(&self, receiver: &Type, method: &str) -> Result<Option<(String, Type)>> {
+        let candidates: Vec<&String> = match receiver {
+            Type::Param(name) => self
+                .bounds
+                .iter()
+                .filter(|(param, _)| param == name)
+                .map(|(_, bound)| bound)
+                .collect(),
+            Type::Dyn(name) => vec![name],
+            ty => self
+                .impls
+                .get(&ty.to_string())
+                .map(|traits| traits.iter().collect())
+                .unwrap_or_default(),
+        };
 
-    fn resolve_type(&self, name: &str) -> Result<Type> {
-        self.type_env
-            .get(name)
-            .cloned()
+        let mut found: Vec<(&String, &MethodInfo)> = Vec::new();
+        for trait_name in candidates {
+            let info = self.traits.get(trait_name);
+            if let Some(info) = info.and_then(|info| info.methods.iter().find(|m| m.name == method))
+            {
+                if !found.iter().any(|(name, _)| *name == trait_name) {
+                    found.push((trait_name, info));
+                }
+            }
+        }
+        let (trait_name, info) = match found.as_slice() {
+            [] => return Ok(None),
+            [only] => *only,
+            [(first, _), (second, _), ..] => {
+                return Err(IoError::type_error(format!(
+                    "Method {} of {} is ambiguous between traits {} and {}",
+                    method, receiver, first, second
+                )))
+            }
+        };
+        if matches!(receiver, Type::Dyn(_)) && info.ty.has_params() {
+            return Err(IoError::type_error(format!(
+                "Method {} of trait {} mentions Self and cannot be called on {}",
+                method, trait_name, receiver
+            )));
+        }
+        let mut bindings = HashMap::from([("Self".to_string(), receiver.clone())]);
+        if let (Some(args), Some(declared)) = (
+            self.impl_args
+                .get(&(receiver.to_string(), trait_name.to_string())),
+            self.traits.get(trait_name),
+        ) {
+            bindings.extend(
+                declared
+                    .type_params
+                    .iter()
+                    .cloned()
+                    .zip(args.iter().cloned()),
+            );
+        }
+        Ok(Some((trait_name.clone(), info.ty.substitute(&bindings))))
+    }
+
+    fn check_call(&mut self, callee: &ASTNode, args: &[ASTNode], call_id: NodeId) -> Result<Type> {
+        let (name, unresolved) = match callee {
+            ASTNode::Identifier { name, id, .. } => {
+                (name.as_str(), self.resolution.resolved(*id).is_none())
+            }
+            ASTNode::MemberAccess { object, member, .. } => {
+                return self.check_method_call(object, member, args, call_id)
+            }
+            _ => ("expression", false),
+        };
+        if prelude::VARIADIC_BUILTINS.contains(&name) && unresolved {
+            for arg in args {
+                self.check_node(arg)?;
+            }
+            return Ok(Type::Void);
+        }
+        if prelude::RUNTIME_BUILTINS.contains(&name) && unresolved {
+            return self.check_runtime_builtin(name, args);
+        }
+        if prelude::INTEGER_INTRINSICS.contains(&name) && unresolved {
+            return self.check_intrinsic(name, args, call_id);
+        }
+        if prelude::ITERATOR_FUNCTIONS.contains(&name) && unresolved {
+            return self.check_iterator_function(name, args, call_id);
+        }
+        let fn_type = self.check_node(callee)?;
+        let type_params = self
+            .generic_functions
+            .get(name)
+            .cloned()
+            .unwrap_or_default();
+        let bounds = self.generic_bounds.get(name).cloned().unwrap_or_default();
+        self.check_arguments(name, &fn_type, &type_params, &bounds, args, call_id)
+    }
+
+    /// `wrapping_add(a, b)` and the like: two integers of the same type, giving
+    /// that type, or an `Option` of it for the `checked_` ones.
+    fn check_intrinsic(&mut self, name: &str, args: &[ASTNode], call_id: NodeId) -> Result<Type> {
+        if args.len() != 2 {
+            return Err(IoError::type_error(format!(
+                "Function {} expects 2 arguments but got {}",
+                name,
+                args.len()
+            )));
+        }
+        let left = self.check_node(&args[0])?;
+        let right = self.check_node(&args[1])?;
+        self.expect_type(&left, &right, args[1].span(), "Argument type mismatch")?;
+        let operand_type = self.table.settle(&left);
+        if !operand_type.is_integer() {
+            return Err(IoError::type_error(format!(
+                "Function {} expects integer arguments, found {}",
+                name, operand_type
+            )));
+        }
+        self.numeric_types.insert(call_id, operand_type.clone());
+        Ok(if name.starts_with("checked_") {
+            Type::Generic {
+                name: "Option".to_string(),
+                args: vec![operand_type],
+            }
+        } else {
+            operand_type
+        })
+    }
+
+    /// `map`, `zip` and the other iterator functions. The first argument may
+    /// be anything a `for` loop can iterate; its element type fixes the types
+    /// the other arguments are checked against.
+    fn check_iterator_function(
+        &mut self,
+        name: &str,
+        args: &[ASTNode],
+        call_id: NodeId,
+    ) -> Result<Type> {
+        let arity = match name {
+            "enumerate" | "collect" => 1,
+            "fold" => 3,
+            _ => 2,
+        };
+        if args.len() != arity {
+            return Err(IoError::type_error(format!(
+                "Function {} expects {} arguments but got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        let elem_type = self.check_iterable(&args[0])?;
+        let result_type = self.table.fresh();
+        let function = |params: Vec<Type>, return_type: &Type| Type::Function {
+            params,
+            return_type: Box::new(return_type.clone()),
+            is_async: false,
+        };
+        let (params, return_type) = match name {
+            "map" => (
+                vec![function(vec![elem_type], &result_type)],
+                iter_type(result_type),
+            ),
+            "filter" => (
+                vec![function(vec![elem_type.clone()], &Type::Bool)],
+                iter_type(elem_type),
+            ),
+            "zip" => {
+                let other = self.check_iterable(&args[1])?;
+                return Ok(iter_type(pair_type(elem_type, other)));
+            }
+            "enumerate" => return Ok(iter_type(pair_type(Type::I32, elem_type))),
+            "take" => (vec![Type::I32], iter_type(elem_type)),
+            "collect" => {
+                return Ok(Type::Array {
+                    elem_type: Box::new(elem_type),
+                    size: 0,
+                })
+            }
+            _ => (
+                vec![
+                    result_type.clone(),
+                    function(vec![result_type.clone(), elem_type], &result_type),
+                ],
+                result_type,
+            ),
+        };
+        let fn_type = function(params, &return_type);
+        self.check_arguments(name, &fn_type, &[], &[], &args[1..], call_id)
+    }
+
+    /// `read_line() -> str`, `len(items) -> int` of an array or string,
+    /// `to_string(value) -> str` of any value, and `parse_int(text) -> int`
+    /// and `parse_float(text) -> float` of a string.
+    fn check_runtime_builtin(&mut self, name: &str, args: &[ASTNode]) -> Result<Type> {
+        let arity = if name == "read_line" { 0 } else { 1 };
+        if args.len() != arity {
+            return Err(IoError::type_error(format!(
+                "Function {} expects {} arguments but got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+        let Some(arg) = args.first() else {
+            return Ok(Type::String);
+        };
+        let arg_type = self.check_node(arg)?;
+        match name {
+            "len" => match self.table.settle(&arg_type) {
+                Type::Array { .. } | Type::String | Type::Var(_) | Type::Unknown => Ok(Type::I32),
+                other => Err(IoError::type_error(format!(
+                    "Function len expects an array or string, found {}",
+                    other
+                ))
+                .with_span(arg.span())),
+            },
+            "to_string" => Ok(Type::String),
+            _ => {
+                self.expect_type(
+                    &Type::String,
+                    &arg_type,
+                    arg.span(),
+                    "Argument type mismatch",
+                )?;
+                Ok(if name == "parse_int" {
+                    Type::I32
+                } else {
+                    Type::F64
+                })
+            }
+        }
+    }
+
+    /// `object.method(args)`: a method of a trait the object's type implements,
+    /// or else a struct field holding a function.
+    fn check_method_call(
+        &mut self,
+        object: &ASTNode,
+        method: &str,
+        args: &[ASTNode],
+        call_id: NodeId,
+    ) -> Result<Type> {
+        let object_type = self.check_node(object)?;
+        let receiver = self.table.settle(&object_type);
+        let fn_type = match self.find_method(&receiver, method)? {
+            Some((trait_name, fn_type)) => {
+                self.method_calls.insert(
+                    call_id,
+                    Implementation {
+                        self_type: receiver,
+                        trait_name,
+                    },
+                );
+                fn_type
+            }
+            None => self
+                .field_type(&receiver, method)
+                .map(|(_, ty)| ty)
+                .map_err(|_| {
+                    IoError::type_error(format!("No method {} found for {}", method, receiver))
+                })?,
+        };
+        self.check_arguments(method, &fn_type, &[], &[], args, call_id)
+    }
+
+    /// Checks a call's arguments against `fn_type`, the callee's type, and
+    /// returns the call's type.
+    fn check_arguments(
+        &mut self,
+        name: &str,
+        fn_type: &Type,
+        type_params: &[String],
+        bounds: &[(String, String)],
+        args: &[ASTNode],
+        call_id: NodeId,
+    ) -> Result<Type> {
+        match self.table.resolve(fn_type) {
+            Type::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                if args.len() != params.len() {
+                    return Err(IoError::type_error(format!(
+                        "Function {} expects {} arguments but got {}",
+                        name,
+                        params.len(),
+                        args.len()
+                    )));
+                }
+
+                // A generic callee's type parameters become fresh variables, which
+                // the arguments then pin down.
+                let type_args: Vec<Type> = type_params.iter().map(|_| self.table.fresh()).collect();
+                let bindings: HashMap<String, Type> = type_params
+                    .iter()
+                    .cloned()
+                    .zip(type_args.iter().cloned())
+                    .collect();
+
+                for (arg, param_type) in args.iter().zip(params.iter()) {
+                    let param_type = param_type.substitute(&bindings);
+                    let arg_type = self.check_expecting(arg, &param_type)?;
+                    self.expect_value(&param_type, &arg_type, arg, "Argument type mismatch")?;
+                }
+
+                if !type_params.is_empty() {
+                    for (param, arg) in type_params.iter().zip(&type_args) {
+                        let unknown = matches!(self.table.resolve(arg), Type::Var(_))
+                            && self.table.kind(arg) == Kind::Any;
+                        if unknown {
+                            return Err(IoError::type_error(format!(
+                                "Cannot infer type parameter {} of {} from its arguments",
+                                param, name
+                            )));
+                        }
+                    }
+                    for (param, bound) in bounds {
+                        let arg = self.table.settle(&bindings[param]);
+                        if !self.implements(&arg, bound) {
+                            return Err(IoError::type_error(format!(
+                                "Type {} does not implement trait {}, required by {} of {}",
+                                arg, bound, param, name
+                            )));
+                        }
+                    }
+                    self.instantiations.insert(call_id, type_args);
+                }
+
+                Ok(return_type.substitute(&bindings))
+            }
+            _ => Err(IoError::type_error(format!("{} is not a function", name))),
+        }
+    }
+
+    /// Like `expect_type`, except that where `dyn Trait` is expected a value of
+    /// any type implementing the trait is accepted. The conversion is recorded
+    /// for code generation.
+    fn expect_value(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        value: &ASTNode,
+        context: &str,
+    ) -> Result<()> {
+        let Type::Dyn(trait_name) = self.table.resolve(expected) else {
+            return self.expect_type(expected, found, value.span(), context);
+        };
+        let found = self.table.settle(found);
+        if matches!(found, Type::Dyn(_) | Type::Var(_)) {
+            return self.expect_type(expected, &found, value.span(), context);
+        }
+        if found.has_params() {
+            return Err(IoError::type_error(format!(
+                "{}: cannot convert {} to dyn {}, its type differs between instantiations",
+                context, found, trait_name
+            ))
+            .with_span(value.span()));
+        }
+        if !self.implements(&found, &trait_name) {
+            return Err(IoError::type_error(format!(
+                "{}: {} does not implement trait {}",
+                context, found, trait_name
+            ))
+            .with_span(value.span()));
+        }
+        self.coercions.insert(
+            value.id(),
+            Implementation {
+                self_type: found,
+                trait_name,
+            },
+        );
+        Ok(())
+    }
+
+    /// Checks `node` where a value of type `expected` is wanted. Closures take
+    /// the types of parameters they leave unannotated from it.
+    fn check_expecting(&mut self, node: &ASTNode, expected: &Type) -> Result<Type> {
+        match node {
+            ASTNode::Closure {
+                params,
+                return_type,
+                body,
+                id,
+                ..
+            } => self
+                .check_closure(params, return_type.as_ref(), body, *id, Some(expected))
+                .map_err(|err| err.or_span(node.span())),
+            _ => self.check_node(node),
+        }
+    }
+
+    /// A closure's function type. Unannotated parameters get the types of
+    /// `expected`, if it is a function type taking as many, or are inferred
+    /// from the body. Captured variables are in scope like in any block.
+    fn check_closure(
+        &mut self,
+        params: &[Parameter],
+        return_type: Option<&Type>,
+        body: &ASTNode,
+        id: NodeId,
+        expected: Option<&Type>,
+    ) -> Result<Type> {
+        let (expected_params, expected_return) = match expected.map(|ty| self.table.resolve(ty)) {
+            Some(Type::Function {
+                params: expected_params,
+                return_type,
+                ..
+            }) if expected_params.len() == params.len() => {
+                (Some(expected_params), Some(*return_type))
+            }
+            _ => (None, None),
+        };
+        let mut param_types = Vec::with_capacity(params.len());
+        for (i, param) in params.iter().enumerate() {
+            param_types.push(match (&param.type_annotation, &expected_params) {
+                (Type::Unknown, Some(expected)) => expected[i].clone(),
+                (Type::Unknown, None) => self.table.fresh(),
+                (annotation, _) => self.resolve_annotation(annotation)?,
+            });
+        }
+        let ret_type = match (return_type, expected_return) {
+            (Some(annotation), _) => self.resolve_annotation(annotation)?,
+            (None, Some(expected)) => expected,
+            (None, None) => self.table.fresh(),
+        };
+
+        // `return` leaves the closure rather than the enclosing function.
+        let outer_return = self.current_function_return_type.replace(ret_type.clone());
+        for (param, param_type) in params.iter().zip(&param_types) {
+            self.bind(param.id, param_type.clone());
+            self.binding_types.insert(param.id, param_type.clone());
+        }
+        let body_type = self.check_node(body);
+        self.current_function_return_type = outer_return;
+
+        // As in a function, a body without a value was checked by its `return`
+        // statements, though with none a closure returns nothing.
+        let body_type = body_type?;
+        let ends_in_return = match body {
+            ASTNode::Block { statements, .. } => {
+                matches!(statements.last(), Some(ASTNode::Return { .. }))
+            }
+            _ => false,
+        };
+        if self.table.resolve(&body_type) != Type::Void
+            || (!ends_in_return && matches!(self.table.resolve(&ret_type), Type::Var(_)))
+        {
+            self.expect_type(&ret_type, &body_type, body.span(), "Return type mismatch")?;
+        }
+
+        let fn_type = Type::Function {
+            params: param_types,
+            return_type: Box::new(ret_type),
+            is_async: false,
+        };
+        self.closure_types.insert(id, fn_type.clone());
+        Ok(fn_type)
+    }
+
+    fn check_return(&mut self, value: Option<&ASTNode>) -> Result<Type> {
+        let return_type = self
+            .current_function_return_type
+            .clone()
+            .ok_or_else(|| IoError::type_error("Return statement outside of function"))?;
+
+        match value {
+            Some(expr) => {
+                let expr_type = self.check_expecting(expr, &return_type)?;
+                self.expect_value(&return_type, &expr_type, expr, "Return type mismatch")?;
+            }
+            None => {
+                if !matches!(return_type, Type::Void) {
+                    return Err(IoError::type_error(format!(
+                        "Function must return {}",
+                        return_type
+                    )));
+                }
+            }
+        }
+
+        Ok(Type::Void)
+    }
+
+    fn check_let(
+        &mut self,
+        type_annotation: Option<&Type>,
+        value: Option<&ASTNode>,
+        id: NodeId,
+    ) -> Result<Type> {
+        // Without an annotation the binding gets a variable, solved by its
+        // initializer or else by what is assigned to it, so later mismatches can
+        // point back at where its type came from.
+        let declared = match type_annotation {
+            Some(annotation) => self.resolve_annotation(annotation)?,
+            None => self.table.fresh(),
+        };
+        if let Some(value) = value {
+            let value_type = self.check_expecting(value, &declared)?;
+            self.expect_value(&declared, &value_type, value, "Type mismatch")?;
+        }
+        self.binding_types.insert(id, declared.clone());
+        self.bind(id, declared);
+        Ok(Type::Void)
+    }
+
+    /// `let pattern = value`: the pattern binds parts of the value, so it must
+    /// match every value of its type.
+    fn check_let_pattern(
+        &mut self,
+        pattern: &Pattern,
+        type_annotation: Option<&Type>,
+        value: &ASTNode,
+        id: NodeId,
+    ) -> Result<Type> {
+        let declared = match type_annotation {
+            Some(annotation) => self.resolve_annotation(annotation)?,
+            None => self.table.fresh(),
+        };
+        let value_type = self.check_expecting(value, &declared)?;
+        self.expect_value(&declared, &value_type, value, "Type mismatch")?;
+        self.binding_types.insert(id, declared.clone());
+        self.check_pattern(pattern, &declared)?;
+        for (_, id) in pattern.binding_ids() {
+            if let Some(ty) = self.binding_type(id) {
+                self.binding_types.insert(id, ty);
+            }
+        }
+
+        let declared = self.table.resolve(&declared);
+        let report = pattern::check_irrefutable(pattern, &declared, self);
+        if !report.missing.is_empty() {
+            return Err(IoError::type_error(format!(
+                "Refutable pattern in let: {}",
+                report.missing_message()
+            ))
+            .with_span(pattern.span()));
+        }
+        Ok(Type::Void)
+    }
+
+    fn check_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<Type> {
+        let target_type = self.check_place(target)?;
+        let value_type = self.check_node(value)?;
+        self.expect_value(
+            &target_type,
+            &value_type,
+            value,
+            &format!("Cannot assign to {}", place_name(target)),
+        )?;
+        Ok(Type::Void)
+    }
+
+    /// The type of the variable, field or element an assignment writes.
+    fn check_place(&mut self, target: &ASTNode) -> Result<Type> {
+        match target {
+            ASTNode::Identifier { name, id, .. } => self.check_identifier(name, *id),
+            target => self.check_node(target),
+        }
+        .map_err(|err| err.or_span(target.span()))
+    }
+
+    /// `target op= value`: both sides have the target's type, on which `op`
+    /// must be defined.
+    fn check_compound_assignment(
+        &mut self,
+        target: &ASTNode,
+        op: &BinaryOperator,
+        value: &ASTNode,
+        id: NodeId,
+    ) -> Result<Type> {
+        let target_type = self.check_place(target)?;
+        let value_type = self.check_node(value)?;
+        self.expect_value(
+            &target_type,
+            &value_type,
+            value,
+            &format!("Cannot assign to {}", place_name(target)),
+        )?;
+        match self.table.resolve(&target_type) {
+            Type::String => self.check_string_operation(op, Type::String, Type::String)?,
+            ty if ty.is_numeric() || matches!(ty, Type::Var(_)) => {
+                self.numeric_types.insert(id, target_type);
+                ty
+            }
+            ty => {
+                return Err(
+                    IoError::type_error(format!("Cannot apply {}= to {}", op, ty))
+                        .with_span(value.span()),
+                )
+            }
+        };
+        Ok(Type::Void)
+    }
+
+    fn check_if(
+        &mut self,
+        condition: &ASTNode,
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+    ) -> Result<Type> {
+        let cond_type = self.check_node(condition)?;
+        self.expect_type(
+            &Type::Bool,
+            &cond_type,
+            condition.span(),
+            "Invalid if condition",
+        )?;
+
+        let then_type = self.check_block(then_branch);
+        let else_type = else_branch
+            .map(|branch| self.check_block(branch))
+            .transpose();
+
+        match (then_type?, else_type?) {
+            (then_type, Some(else_type)) if self.types_match(&else_type, &then_type) => {
+                self.expect_type(&then_type, &else_type, Span::dummy(), "Mismatched branches")?;
+                Ok(then_type)
+            }
+            _ => Ok(Type::Void),
+        }
+    }
+
+    fn check_unary_op(
+        &mut self,
+        op: &UnaryOperator,
+        operand: &ASTNode,
+        id: NodeId,
+        span: Span,
+    ) -> Result<Type> {
+        let operand_type = self.check_node(operand)?;
+        let operand_type = self.table.resolve(&operand_type);
+        match op {
+            UnaryOperator::Negate if operand_type.is_integer() && !operand_type.is_signed() => {
+                Err(IoError::type_error(format!(
+                    "Cannot negate a value of unsigned type {}",
+                    operand_type
+                )))
+            }
+            UnaryOperator::Negate
+                if operand_type.is_numeric() || matches!(operand_type, Type::Var(_)) =>
+            {
+                // `-128` is checked against the range of its type as a whole.
+                if let ASTNode::Literal { id: literal, .. } = operand {
+                    if let Some(entry) = self
+                        .int_literals
+                        .iter_mut()
+                        .rev()
+                        .find(|entry| entry.0 == *literal)
+                    {
+                        entry.1 = -entry.1;
+                        entry.3 = span;
+                    }
+                }
+                Ok(operand_type)
+            }
+            UnaryOperator::Not => {
+                self.expect_type(
+                    &Type::Bool,
+                    &operand_type,
+                    operand.span(),
+                    "Invalid operand",
+                )?;
+                Ok(Type::Bool)
+            }
+            UnaryOperator::Await => Ok(operand_type),
+            UnaryOperator::Ref | UnaryOperator::RefMut => Ok(Type::Ref {
+                mutable: *op == UnaryOperator::RefMut,
+                inner: Box::new(operand_type),
+            }),
+            UnaryOperator::Deref => match operand_type {
+                Type::Ref { inner, .. } => Ok(*inner),
+                other => Err(IoError::type_error(format!(
+                    "Cannot dereference {}",
+                    self.table.settle(&other)
+                ))),
+            },
+            UnaryOperator::Try => self.check_try(operand_type, operand.span(), id),
+            _ => Err(IoError::type_error(format!(
+                "Cannot apply {}= to {}",
+                op,
+                self.table.settle(&operand_type)
+            ))),
+        }
+    }
+
+    /// `value as target`, a conversion between numeric types, or from `bool`
+    /// or `char` to an integer, or from `u8` to `char`.
+    fn check_cast(&mut self, value: &ASTNode, target: &Type, id: NodeId) -> Result<Type> {
+        let value_type = self.check_node(value)?;
+        let target = self.resolve_annotation(target)?;
+        // An unsuffixed literal converted with `as` has its default type.
+        let source = self.table.settle(&value_type);
+        if !matches!(source, Type::Var(_) | Type::Unknown) && !source.can_cast_to(&target) {
+            return Err(IoError::type_error(format!(
+                "Cannot convert {} to {} with as",
+                source, target
+            )));
+        }
+        self.numeric_types.insert(id, source);
+        Ok(target)
+    }
+
+    /// `value?`: the value inside an `Option` or `Result`. A `None` or an error
+    /// is returned from the enclosing function instead, which must return the
+    /// same kind of enum, with the same error type.
+    fn check_try(&mut self, operand_type: Type, span: Span, id: NodeId) -> Result<Type> {
+        let (name, args) = match &operand_type {
+            Type::Generic { name, args } if name == "Option" || name == "Result" => (name, args),
+            other => {
+                return Err(IoError::type_error(format!(
+                    "The ? operator applies to an Option or Result, not {}",
+                    self.table.settle(other)
+                ))
+                .with_span(span))
+            }
+        };
+        let return_type = self
+            .current_function_return_type
+            .clone()
+            .ok_or_else(|| IoError::type_error("The ? operator can only be used in a function"))?;
+        match self.table.resolve(&return_type) {
+            Type::Generic {
+                name: returned,
+                args: returned_args,
+            } if returned == *name => {
+                if let (Some(expected), Some(found)) = (returned_args.get(1), args.get(1)) {
+                    self.expect_type(expected, found, span, "Mismatched error type for ?")?;
+                }
+            }
+            other => {
+                return Err(IoError::type_error(format!(
+                    "The ? operator on {} can only be used in a function returning {}, \
+                     but this function returns {}",
+                    operand_type, name, other
+                )))
+            }
+        }
+        let value_type = args[0].clone();
+        self.enum_values.insert(id, operand_type);
+        Ok(value_type)
+    }
+
+    fn check_array(&mut self, elements: &[ASTNode]) -> Result<Type> {
+        // The element type of `[]` is left for later uses to decide.
+        let elem_type = self.table.fresh();
+        for element in elements {
+            let ty = self.check_node(element)?;
+            self.expect_type(
+                &elem_type,
+                &ty,
+                element.span(),
+                "Array elements must have the same type",
+            )?;
+        }
+        Ok(Type::Array {
+            elem_type: Box::new(elem_type),
+            size: elements.len(),
+        })
+    }
+
+    /// The enum and variant named by `Enum::Variant`, with the enum's type
+    /// parameters.
+    fn find_variant(&self, path: &str) -> Result<(String, Vec<String>, Constructor)> {
+        let unknown = || IoError::type_error(format!("Unknown type or identifier: {}", path));
+        let (enum_name, variant) = path.rsplit_once("::").ok_or_else(unknown)?;
+        let info = self.enums.get(enum_name).ok_or_else(unknown)?;
+        let ctor = info
+            .variants
+            .iter()
+            .find(|ctor| ctor.name == variant)
+            .ok_or_else(|| {
+                IoError::type_error(format!("Enum {} has no variant {}", enum_name, variant))
+            })?;
+        Ok((
+            enum_name.to_string(),
+            info.type_params.clone(),
+            ctor.clone(),
+        ))
+    }
+
+    /// `name` applied to fresh variables for its type parameters, with the
+    /// bindings from each parameter to its variable.
+    fn instantiate(&mut self, name: &str, type_params: &[String]) -> (Type, HashMap<String, Type>) {
+        if type_params.is_empty() {
+            return (Type::Named(name.to_string()), HashMap::new());
+        }
+        let args: Vec<Type> = type_params.iter().map(|_| self.table.fresh()).collect();
+        let bindings = type_params
+            .iter()
+            .cloned()
+            .zip(args.iter().cloned())
+            .collect();
+        (
+            Type::Generic {
+                name: name.to_string(),
+                args,
+            },
+            bindings,
+        )
+    }
+
+    /// An `Enum::Variant` used as a value: a unit variant is a value of the enum,
+    /// a tuple variant a function building one.
+    fn check_variant_path(&mut self, path: &str, id: NodeId) -> Result<Type> {
+        let (enum_name, type_params, variant) = self.find_variant(path)?;
+        let (enum_type, bindings) = self.instantiate(&enum_name, &type_params);
+        self.enum_values.insert(id, enum_type.clone());
+        match variant.style {
+            FieldStyle::Unit => Ok(enum_type),
+            FieldStyle::Tuple => Ok(Type::Function {
+                params: variant
+                    .fields
+                    .iter()
+                    .map(|(_, ty)| ty.substitute(&bindings))
+                    .collect(),
+                return_type: Box::new(enum_type),
+                is_async: false,
+            }),
+            FieldStyle::Named => Err(IoError::type_error(format!(
+                "Variant {} has named fields and is built with `{} {{ .. }}`",
+                path, path
+            ))),
+        }
+    }
+
+    fn check_struct_literal(
+        &mut self,
+        path: &str,
+        fields: &[(String, ASTNode)],
+        id: NodeId,
+    ) -> Result<Type> {
+        let (kind, owner, type_params, variant) = match self.structs.get(path) {
+            Some(info) => (
+                "Struct",
+                path.to_string(),
+                info.type_params.clone(),
+                Constructor {
+                    name: path.to_string(),
+                    style: FieldStyle::Named,
+                    fields: info.fields.clone(),
+                },
+            ),
+            None => {
+                let (enum_name, type_params, variant) = self.find_variant(path)?;
+                if variant.style != FieldStyle::Named {
+                    return Err(IoError::type_error(format!(
+                        "Variant {} has no named fields",
+                        path
+                    )));
+                }
+                ("Variant", enum_name, type_params, variant)
+            }
+        };
+        let (literal_type, bindings) = self.instantiate(&owner, &type_params);
+
+        let mut seen: Vec<&str> = Vec::with_capacity(fields.len());
+        for (field, value) in fields {
+            if seen.contains(&field.as_str()) {
+                return Err(IoError::type_error(format!(
+                    "Field {} is given more than once",
+                    field
+                ))
+                .with_span(value.span()));
+            }
+            seen.push(field);
+            let (_, field_type) = variant
+                .fields
+                .iter()
+                .find(|(name, _)| name == field)
+                .ok_or_else(|| {
+                    IoError::type_error(format!("{} {} has no field {}", kind, path, field))
+                        .with_span(value.span())
+                })?;
+            let value_type = self.check_node(value)?;
+            self.expect_type(
+                &field_type.substitute(&bindings),
+                &value_type,
+                value.span(),
+                &format!("Invalid value for field {}", field),
+            )?;
+        }
+
+        let missing: Vec<&str> = variant
+            .fields
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !seen.contains(name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(IoError::type_error(format!(
+                "Missing fields {} in {}",
+                missing.join(", "),
+                path
+            )));
+        }
+        self.struct_literals.insert(id, literal_type.clone());
+        Ok(literal_type)
+    }
+
+    fn check_match(&mut self, scrutinee: &ASTNode, arms: &[MatchArm]) -> Result<Type> {
+        let scrutinee_type = self.check_node(scrutinee)?;
+
+        let mut arm_types = Vec::with_capacity(arms.len());
+        for arm in arms {
+            arm_types.push(self.check_arm(arm, &scrutinee_type)?);
+        }
+
+        let scrutinee_type = self.table.resolve(&scrutinee_type);
+        let report = pattern::check_match(arms, &scrutinee_type, self);
+        if !report.missing.is_empty() {
+            return Err(IoError::type_error(format!(
+                "Non-exhaustive match: {}",
+                report.missing_message()
+            ))
+            .with_span(scrutinee.span()));
+        }
+        for index in report.unreachable {
+            self.warnings.push(
+                Diagnostic::warning("Unreachable pattern").with_span(arms[index].pattern.span()),
+            );
+        }
+
+        // Like `if`, a match has a value when all its arms agree on its type.
+        let Some(first) = arm_types.first().cloned() else {
+            return Ok(Type::Void);
+        };
+        if !arm_types.iter().all(|ty| self.types_match(ty, &first)) {
+            return Ok(Type::Void);
+        }
+        for (arm, ty) in arms.iter().zip(&arm_types).skip(1) {
+            self.expect_type(&first, ty, arm.body.span(), "Mismatched match arms")?;
+        }
+        Ok(first)
+    }
+
+    fn check_arm(&mut self, arm: &MatchArm, scrutinee_type: &Type) -> Result<Type> {
+        self.check_pattern(&arm.pattern, scrutinee_type)?;
+        if let Some(guard) = &arm.guard {
+            let guard_type = self.check_node(guard)?;
+            self.expect_type(
+                &Type::Bool,
+                &guard_type,
+                guard.span(),
+                "Invalid match guard",
+            )?;
+        }
+        self.check_node(&arm.body)
+    }
+
+    /// Checks that `pattern` can match a value of type `expected`, binding the
+    /// names it introduces.
+    fn check_pattern(&mut self, pattern: &Pattern, expected: &Type) -> Result<()> {
+        self.check_pattern_kind(pattern, expected)
+            .map_err(|err| err.or_span(pattern.span()))
+    }
+
+    fn check_pattern_kind(&mut self, pattern: &Pattern, expected: &Type) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard { .. } => Ok(()),
+            Pattern::Binding { id, .. } => {
+                self.bind(*id, expected.clone());
+                Ok(())
+            }
+            Pattern::Literal { value, span } => {
+                let ty = match value {
+                    Literal::Integer(_) => self.table.fresh_of(Kind::Integer),
+                    Literal::Float(_) => self.table.fresh_of(Kind::Float),
+                    value => value.default_type(),
+                };
+                self.expect_type(expected, &ty, *span, "Mismatched pattern")
+            }
+            Pattern::Array { elements, span, .. } => {
+                let elem_type = self.table.fresh();
+                let array_type = Type::Array {
+                    elem_type: Box::new(elem_type.clone()),
+                    size: 0,
+                };
+                self.expect_type(expected, &array_type, *span, "Mismatched pattern")?;
+                for element in elements {
+                    self.check_pattern(element, &elem_type)?;
+                }
+                Ok(())
+            }
+            Pattern::Tuple { elements, span } => {
+                let elem_types: Vec<Type> = elements.iter().map(|_| self.table.fresh()).collect();
+                let tuple_type = Type::Tuple(elem_types.clone());
+                self.expect_type(expected, &tuple_type, *span, "Mismatched pattern")?;
+                for (element, elem_type) in elements.iter().zip(&elem_types) {
+                    self.check_pattern(element, elem_type)?;
+                }
+                Ok(())
+            }
+            Pattern::Or { alternatives, .. } => self.check_or_pattern(alternatives, expected),
+            Pattern::Struct { path, fields, span } => {
+                let ty = self.pattern_type(path, expected)?;
+                self.expect_type(expected, &ty, *span, "Mismatched pattern")?;
+                let name = path.rsplit("::").next().unwrap_or(path);
+                let ctor = self
+                    .constructors(&ty)
+                    .and_then(|ctors| ctors.into_iter().find(|ctor| ctor.name == name))
+                    .ok_or_else(|| {
+                        IoError::type_error(format!("{} has no variant {}", ty, name))
+                    })?;
+                self.check_pattern_fields(path, &ctor, fields)
+            }
+        }
+    }
+
+    /// The type matched by a struct or variant pattern. A bare variant name is
+    /// looked up in the enum being matched.
+    fn pattern_type(&mut self, path: &str, expected: &Type) -> Result<Type> {
+        let owner = match path.rsplit_once("::") {
+            Some((owner, _)) => owner,
+            None if self.structs.contains_key(path) => path,
+            None => {
+                let resolved = self.table.resolve(expected);
+                return match &resolved {
+                    Type::Named(name) | Type::Generic { name, .. }
+                        if self.enums.contains_key(name) =>
+                    {
+                        Ok(resolved)
+                    }
+                    _ => Err(IoError::type_error(format!(
+                        "Unknown variant or struct {}",
+                        path
+                    ))),
+                };
+            }
+        };
+        let type_params = self
+            .type_params_of(owner)
+            .ok_or_else(|| IoError::type_error(format!("Unknown type {}", owner)))?
+            .to_vec();
+        Ok(self.instantiate(owner, &type_params).0)
+    }
+
+    fn check_pattern_fields(
+        &mut self,
+        path: &str,
+        ctor: &Constructor,
+        fields: &PatternFields,
+    ) -> Result<()> {
+        match (ctor.style, fields) {
+            (FieldStyle::Unit, PatternFields::Unit) => Ok(()),
+            (FieldStyle::Tuple, PatternFields::Tuple(patterns)) => {
+                if patterns.len() != ctor.fields.len() {
+                    return Err(IoError::type_error(format!(
+                        "{} has {} fields but the pattern has {}",
+                        path,
+                        ctor.fields.len(),
+                        patterns.len()
+                    )));
+                }
+                for (pattern, (_, ty)) in patterns.iter().zip(&ctor.fields) {
+                    self.check_pattern(pattern, ty)?;
+                }
+                Ok(())
+            }
+            (FieldStyle::Named, PatternFields::Named { fields, rest }) => {
+                let mut seen: Vec<&str> = Vec::with_capacity(fields.len());
+                for (field, pattern) in fields {
+                    if seen.contains(&field.as_str()) {
+                        return Err(IoError::type_error(format!(
+                            "Field {} is matched more than once",
+                            field
+                        ))
+                        .with_span(pattern.span()));
+                    }
+                    seen.push(field);
+                    let (_, ty) = ctor
+                        .fields
+                        .iter()
+                        .find(|(name, _)| name == field)
+                        .ok_or_else(|| {
+                            IoError::type_error(format!("{} has no field {}", path, field))
+                                .with_span(pattern.span())
+                        })?;
+                    self.check_pattern(pattern, ty)?;
+                }
+                let missing: Vec<&str> = ctor
+                    .fields
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .filter(|name| !seen.contains(name))
+                    .collect();
+                if !rest && !missing.is_empty() {
+                    return Err(IoError::type_error(format!(
+                        "Pattern does not mention fields {} of {}; use `..` to ignore them",
+                        missing.join(", "),
+                        path
+                    )));
+                }
+                Ok(())
+            }
+            (style, _) => {
+                let shape = match style {
+                    FieldStyle::Unit => "",
+                    FieldStyle::Tuple => "(..)",
+                    FieldStyle::Named => " { .. }",
+                };
+                Err(IoError::type_error(format!(
+                    "{} must be matched as `{}{}`",
+                    path, path, shape
+                )))
+            }
+        }
+    }
+
+    /// Every alternative of an or-pattern must bind the same names, at the
+    /// same types.
+    fn check_or_pattern(&mut self, alternatives: &[Pattern], expected: &Type) -> Result<()> {
+        // The alternatives share their symbols, so each one's types are taken
+        // before the next rebinds them.
+        let mut bound: Option<Vec<(String, Type)>> = None;
+        for alternative in alternatives {
+            self.check_pattern(alternative, expected)?;
+            let mut names: Vec<(String, Type)> = alternative
+                .binding_ids()
+                .into_iter()
+                .filter_map(|(name, id)| Some((name.to_string(), self.binding_type(id)?)))
+                .collect();
+            names.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let Some(first) = &bound else {
+                bound = Some(names);
+                continue;
+            };
+            let same_names = first.len() == names.len()
+                && first.iter().zip(&names).all(|((a, _), (b, _))| a == b);
+            if !same_names {
+                return Err(IoError::type_error(
+                    "Every alternative of an or-pattern must bind the same names",
+                )
+                .with_span(alternative.span()));
+            }
+            for ((name, ty), (_, other)) in first.clone().iter().zip(&names) {
+                self.expect_type(
+                    ty,
+                    other,
+                    alternative.span(),
+                    &format!("Mismatched types for {} in or-pattern", name),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_index(&mut self, array: &ASTNode, index: &ASTNode) -> Result<Type> {
+        let container = self.check_node(array)?;
+        let index_type = self.check_node(index)?;
+        let index_type = self.table.settle(&index_type);
+        if let Type::Var(_) = index_type {
+            self.expect_type(&Type::I32, &index_type, index.span(), "Invalid index")?;
+        } else if !index_type.is_integer() {
+            return Err(IoError::type_error(format!(
+                "Index must be an integer, found {}",
+                index_type
+            ))
+            .with_span(index.span()));
+        }
+        match self.table.resolve(&container).referent() {
+            Type::Array { elem_type, .. } => Ok(*elem_type),
+            Type::String => Ok(Type::Char),
+            other => Err(IoError::type_error(format!("Cannot index into {}", other))),
+        }
+    }
+
+    fn check_member_access(&mut self, object: &ASTNode, member: &str, id: NodeId) -> Result<Type> {
+        let object_type = self.check_node(object)?;
+        let object_type = self.table.settle(&object_type).referent();
+        let (index, field_type) = self.field_type(&object_type, member)?;
+        self.field_indices.insert(id, index);
+        Ok(field_type)
+    }
+
+    /// The position and type of the field `member` of a struct or tuple type.
+    fn field_type(&self, object_type: &Type, member: &str) -> Result<(usize, Type)> {
+        let (name, args) = match object_type {
+            Type::Named(name) => (name, &[][..]),
+            Type::Generic { name, args } => (name, args.as_slice()),
+            Type::Tuple(elements) => {
+                return member
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| Some((index, elements.get(index)?.clone())))
+                    .ok_or_else(|| {
+                        IoError::type_error(format!("{} has no field {}", object_type, member))
+                    })
+            }
+            other => {
+                return Err(IoError::type_error(format!(
+                    "{} has no field {}",
+                    other, member
+                )))
+            }
+        };
+        let info = self
+            .structs
+            .get(name)
+            .ok_or_else(|| IoError::type_error(format!("{} has no field {}", name, member)))?;
+        let (index, (_, field_type)) = info
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(_, (field, _))| field == member)
+            .ok_or_else(|| {
+                IoError::type_error(format!("Struct {} has no field {}", name, member))
+            })?;
+        let bindings = info
+            .type_params
+            .iter()
+            .cloned()
+            .zip(args.iter().cloned())
+            .collect();
+        Ok((index, field_type.substitute(&bindings)))
+    }
+
+    fn check_loop(&mut self, body: &[ASTNode]) -> Result<Type> {
+        for node in body {
+            self.check_node(node)?;
+        }
+        Ok(Type::Void)
+    }
+
+    fn check_for(
+        &mut self,
+        pattern: &Pattern,
+        iterable: &ASTNode,
+        body: &[ASTNode],
+        id: NodeId,
+    ) -> Result<Type> {
+        let elem_type = self.check_iterable(iterable)?;
+        self.check_loop_pattern(pattern, &elem_type, id)?;
+        self.check_loop(body)
+    }
+
+    /// Binds the names of a `for` loop's pattern, which has to match every
+    /// element.
+    fn check_loop_pattern(
+        &mut self,
+        pattern: &Pattern,
+        elem_type: &Type,
+        id: NodeId,
+    ) -> Result<()> {
+        if let Pattern::Binding { .. } = pattern {
+            self.binding_types.insert(id, elem_type.clone());
+        }
+        self.check_pattern(pattern, elem_type)?;
+
+        let arm = MatchArm {
+            pattern: pattern.clone(),
+            guard: None,
+            body: ASTNode::Error {
+                id: NodeId::DUMMY,
+                span: pattern.span(),
+            },
+            id: NodeId::DUMMY,
+            span: pattern.span(),
+        };
+        let elem_type = self.table.resolve(elem_type);
+        let report = pattern::check_match(std::slice::from_ref(&arm), &elem_type, self);
+        if !report.missing.is_empty() {
+            return Err(IoError::type_error(format!(
+                "Refutable pattern in for loop: {}",
+                report.missing_message()
+            ))
+            .with_span(pattern.span()));
+        }
+        Ok(())
+    }
+
+    /// Checks an expression a `for` loop or iterator function steps through,
+    /// giving the type of its elements.
+    fn check_iterable(&mut self, iterable: &ASTNode) -> Result<Type> {
+        let iterable_type = self.check_node(iterable)?;
+        match self.element_type(&self.table.resolve(&iterable_type)) {
+            Some(elem_type) => Ok(elem_type),
+            None => Err(IoError::type_error(format!(
+                "Cannot iterate over {}",
+                self.table.settle(&iterable_type)
+            ))
+            .with_span(iterable.span())),
+        }
+    }
+
+    /// The type of the elements of an iterable type: an array's elements, a
+    /// string's chars, what an `Iter` gives, or the type argument of an
+    /// `Iterator` implementation.
+    fn element_type(&self, ty: &Type) -> Option<Type> {
+        match ty {
+            Type::Array { elem_type, .. } => Some(elem_type.as_ref().clone()),
+            Type::String => Some(Type::Char),
+            Type::Generic { name, args } if self.is_iter_type(name) => args.first().cloned(),
+            ty => self
+                .impl_args
+                .get(&(ty.to_string(), prelude::ITERATOR_TRAIT.to_string()))
+                .and_then(|args| args.first().cloned()),
+        }
+    }
+
+    /// Whether `name` is the built-in `Iter`, rather than a type of the
+    /// program's own.
+    fn is_iter_type(&self, name: &str) -> bool {
+        name == prelude::ITER_TYPE && self.type_params_of(name).is_none()
+    }
+
+    /// Variables have the type of the symbol they resolve to; anything else,
+    /// like a function or a builtin, is looked up by name.
+    fn check_identifier(&self, name: &str, id: NodeId) -> Result<Type> {
+        let symbol = self.resolution.resolved(id);
+        match symbol.and_then(|symbol| self.symbol_types.get(&symbol)) {
+            Some(ty) => Ok(ty.clone()),
+            None => self.resolve_type(name),
+        }
+    }
+
+    /// Gives the symbol declared by `node` the type `ty`.
+    fn bind(&mut self, node: NodeId, ty: Type) {
+        if let Some(symbol) = self.resolution.declared(node) {
+            self.symbol_types.insert(symbol, ty);
+        }
+    }
+
+    /// The type of the symbol declared by `node`, once bound.
+    fn binding_type(&self, node: NodeId) -> Option<Type> {
+        let symbol = self.resolution.declared(node)?;
+        self.symbol_types.get(&symbol).cloned()
+    }
+
+    fn resolve_type(&self, name: &str) -> Result<Type> {
+        self.type_env
+            .get(name)
+            .cloned()
             .ok_or_else(|| IoError::type_error(format!("Unknown type or identifier: {}", name)))
     }
 
-    fn types_match(&self, actual: &Type, expected: &Type) -> bool {
-        match (actual, expected) {
-            (Type::Int, Type::Int)
-            | (Type::Float, Type::Float)
-            | (Type::Bool, Type::Bool)
-            | (Type::String, Type::String)
-            | (Type::Unit, Type::Unit) => true,
-
-            (
-                Type::Function {
-                    params: p1,
-                    return_type: r1,
-                    ..
-                },
-                Type::Function {
-                    params: p2,
-                    return_type: r2,
-                    ..
-                },
-            ) => {
-                p1.len() == p2.len()
-                    && p1
+    /// Resolves user type names in an annotation; builtin types are returned as is.
+    /// Structs are referred to by name, with their type arguments if generic.
+    fn resolve_annotation(&self, ty: &Type) -> Result<Type> {
+        match ty {
+            Type::Param(name) if name == "Self" => {
+                Ok(self.self_type.clone().unwrap_or_else(|| ty.clone()))
+            }
+            Type::Dyn(trait_name) => match self.traits.get(trait_name) {
+                None | Some(TraitInfo { inherent: true, .. }) => {
+                    Err(IoError::type_error(format!("Unknown trait {}", trait_name)))
+                }
+                Some(info) if !info.type_params.is_empty() => Err(IoError::type_error(format!(
+                    "Generic trait {} cannot be used with dyn",
+                    trait_name
+                ))),
+                Some(_) => Ok(ty.clone()),
+            },
+            Type::Named(name) => {
+                match self.type_params_of(name) {
+                    Some(type_params) if !type_params.is_empty() => Err(IoError::type_error(
+                        format!("Type {} expects {} type arguments", name, type_params.len()),
+                    )),
+                    Some(_) => Ok(ty.clone()),
+                    None => self.resolve_type(name),
+                }
+            }
+            Type::Generic { name, args } if self.is_iter_type(name) && args.len() == 1 => {
+                Ok(iter_type(self.resolve_annotation(&args[0])?))
+            }
+            Type::Generic { name, args } => {
+                let type_params = self.type_params_of(name).ok_or_else(|| {
+                    IoError::type_error(format!("Unknown generic type: {}", name))
+                })?;
+                if type_params.len() != args.len() {
+                    return Err(IoError::type_error(format!(
+                        "Type {} expects {} type arguments but got {}",
+                        name,
+                        type_params.len(),
+                        args.len()
+                    )));
+                }
+                Ok(Type::Generic {
+                    name: name.clone(),
+                    args: args
                         .iter()
-                        .zip(p2.iter())
-                        .all(|(a, b)| self.types_match(a, b))
-                    && self.types_match(r1, r2)
+                        .map(|arg| self.resolve_annotation(arg))
+                        .collect::<Result<_>>()?,
+                })
             }
+            Type::Array { elem_type, size } => Ok(Type::Array {
+                elem_type: Box::new(self.resolve_annotation(elem_type)?),
+                size: *size,
+            }),
+            Type::Ref { mutable, inner } => Ok(Type::Ref {
+                mutable: *mutable,
+                inner: Box::new(self.resolve_annotation(inner)?),
+            }),
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => Ok(Type::Function {
+                params: params
+                    .iter()
+                    .map(|param| self.resolve_annotation(param))
+                    .collect::<Result<_>>()?,
+                return_type: Box::new(self.resolve_annotation(return_type)?),
+                is_async: *is_async,
+            }),
+            Type::Tuple(elements) => Ok(Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.resolve_annotation(element))
+                    .collect::<Result<_>>()?,
+            )),
+            other => Ok(other.clone()),
+        }
+    }
 
-            _ => false,
+    /// Type parameters of the struct or enum called `name`.
+    fn type_params_of(&self, name: &str) -> Option<&[String]> {
+        match self.structs.get(name) {
+            Some(info) => Some(&info.type_params),
+            None => self.enums.get(name).map(|info| info.type_params.as_slice()),
+        }
+    }
+
+    /// Resolves an annotation for code generation: struct references become
+    /// `Type::Struct` with their fields laid out, named after their type
+    /// arguments (`Pair<i32, bool>`) when generic.
+    pub fn layout(&self, ty: &Type) -> Result<Type> {
+        let resolved = self.resolve_annotation(ty)?;
+        let (name, args) = match &resolved {
+            Type::Named(name) => (name, Vec::new()),
+            Type::Generic { name, args } => (name, args.clone()),
+            Type::Array { elem_type, size } => {
+                return Ok(Type::Array {
+                    elem_type: Box::new(self.layout(elem_type)?),
+                    size: *size,
+                })
+            }
+            Type::Ref { mutable, inner } => {
+                return Ok(Type::Ref {
+                    mutable: *mutable,
+                    inner: Box::new(self.layout(inner)?),
+                })
+            }
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => {
+                return Ok(Type::Function {
+                    params: params
+                        .iter()
+                        .map(|param| self.layout(param))
+                        .collect::<Result<_>>()?,
+                    return_type: Box::new(self.layout(return_type)?),
+                    is_async: *is_async,
+                })
+            }
+            Type::Tuple(elements) => {
+                return Ok(Type::Tuple(
+                    elements
+                        .iter()
+                        .map(|element| self.layout(element))
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            _ => return Ok(resolved),
+        };
+        let Some(info) = self.structs.get(name) else {
+            return Ok(self.enum_layout(&resolved, name, args).unwrap_or(resolved));
+        };
+        let bindings = info.type_params.iter().cloned().zip(args).collect();
+        Ok(Type::Struct {
+            name: resolved.to_string(),
+            fields: info
+                .fields
+                .iter()
+                .map(|(field, ty)| Ok((field.clone(), self.layout(&ty.substitute(&bindings))?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// An enum laid out as a struct: an `i32` tag holding the variant's
+    /// position, then the fields of every variant in turn, named
+    /// `Variant.field`. Recursive enums have no such layout.
+    fn enum_layout(&self, resolved: &Type, name: &str, args: Vec<Type>) -> Option<Type> {
+        let info = self.enums.get(name)?;
+        let bindings = info.type_params.iter().cloned().zip(args).collect();
+        let mut fields = vec![("tag".to_string(), Type::I32)];
+        for variant in &info.variants {
+            for (field, ty) in &variant.fields {
+                if mentions(ty, name) {
+                    return None;
+                }
+                let ty = self.layout(&ty.substitute(&bindings)).ok()?;
+                fields.push((format!("{}.{}", variant.name, field), ty));
+            }
         }
+        Some(Type::Struct {
+            name: resolved.to_string(),
+            fields,
+        })
+    }
+
+    /// Whether the types unify, without keeping anything learned from trying.
+    fn types_match(&self, actual: &Type, expected: &Type) -> bool {
+        self.table
+            .clone()
+            .unify(expected, actual, Span::dummy())
+            .is_ok()
     }
 
     fn check_string_operation(
-        &mut self,
+        &self,
         op: &BinaryOperator,
-        left: &ASTNode,
-        right: &ASTNode,
+        left_type: Type,
+        right_type: Type,
     ) -> Result<Type> {
-        let left_type = self.check_node(left)?;
-        let right_type = self.check_node(right)?;
-
         match (left_type, right_type) {
             (Type::String, Type::String) => {
                 match op {
@@ -323,24 +2662,118 @@ impl TypeChecker {
     }
 }
 
+impl TypeDefinitions for TypeChecker {
+    fn constructors(&self, ty: &Type) -> Option<Vec<Constructor>> {
+        let (name, args) = match self.table.resolve(ty) {
+            Type::Named(name) => (name, Vec::new()),
+            Type::Generic { name, args } => (name, args),
+            _ => return None,
+        };
+        let (type_params, ctors) = match (self.enums.get(&name), self.structs.get(&name)) {
+            (Some(info), _) => (&info.type_params, info.variants.clone()),
+            (None, Some(info)) => (
+                &info.type_params,
+                vec![Constructor {
+                    name: name.clone(),
+                    style: FieldStyle::Named,
+                    fields: info.fields.clone(),
+                }],
+            ),
+            (None, None) => return None,
+        };
+        let bindings: HashMap<String, Type> = type_params.iter().cloned().zip(args).collect();
+        Some(
+            ctors
+                .into_iter()
+                .map(|ctor| Constructor {
+                    fields: ctor
+                        .fields
+                        .into_iter()
+                        .map(|(field, ty)| (field, self.table.resolve(&ty.substitute(&bindings))))
+                        .collect(),
+                    ..ctor
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `Iter<T>`, a lazy sequence of `elem_type`.
+fn iter_type(elem_type: Type) -> Type {
+    Type::Generic {
+        name: prelude::ITER_TYPE.to_string(),
+        args: vec![elem_type],
+    }
+}
+
+/// `Pair<A, B>`, the elements `zip` and `enumerate` give.
+fn pair_type(first: Type, second: Type) -> Type {
+    Type::Generic {
+        name: prelude::PAIR_TYPE.to_string(),
+        args: vec![first, second],
+    }
+}
+
+/// How an assignment target reads in messages: `p.pos.x` or `grid[_][_]`.
+fn place_name(target: &ASTNode) -> String {
+    match target {
+        ASTNode::Identifier { name, .. } => name.clone(),
+        ASTNode::MemberAccess { object, member, .. } => {
+            format!("{}.{}", place_name(object), member)
+        }
+        ASTNode::Index { array, .. } => format!("{}[_]", place_name(array)),
+        _ => "_".to_string(),
+    }
+}
+
+/// Whether `ty` refers to the type named `name`.
+fn mentions(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Named(named) => named == name,
+        Type::Generic { name: named, args } => {
+            named == name || args.iter().any(|arg| mentions(arg, name))
+        }
+        Type::Array {
+            elem_type: inner, ..
+        }
+        | Type::Pointer(inner)
+        | Type::Ref { inner, .. } => mentions(inner, name),
+        Type::Struct { fields, .. } => fields.iter().any(|(_, ty)| mentions(ty, name)),
+        Type::Tuple(elements) => elements.iter().any(|element| mentions(element, name)),
+        Type::Function {
+            params,
+            return_type,
+            ..
+        } => params.iter().any(|param| mentions(param, name)) || mentions(return_type, name),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::semantic::resolve;
 
     #[test]
     fn test_basic_types() {
         let checker = TypeChecker::new();
-        assert!(checker.types_match(&Type::Int, &Type::Int));
-        assert!(checker.types_match(&Type::Float, &Type::Float));
-        assert!(!checker.types_match(&Type::Int, &Type::Float));
+        assert!(checker.types_match(&Type::I32, &Type::I32));
+        assert!(checker.types_match(&Type::F64, &Type::F64));
+        assert!(!checker.types_match(&Type::I32, &Type::F64));
     }
 
     #[test]
     fn test_function_types() {
         let mut checker = TypeChecker::new();
         let fn_type = Type::Function {
-            params: vec![Type::Int, Type::Bool],
-            return_type: Box::new(Type::Float),
+            params: vec![Type::I32, Type::Bool],
+            return_type: Box::new(Type::F64),
             is_async: false,
         };
 
@@ -351,4 +2784,659 @@ mod tests {
         let resolved = checker.resolve_type("test_fn").unwrap();
         assert!(checker.types_match(&resolved, &fn_type));
     }
+
+    #[test]
+    fn test_errors_carry_span() {
+        let source = "fn f() -> bool {\n    return missing;\n}";
+        let program = crate::parser::parse_source(source, crate::span::FileId(0)).unwrap();
+        let err = resolve::resolve(&program)
+            .and_then(|resolution| TypeChecker::new().check(&program, &resolution))
+            .unwrap_err();
+        let span = err.span().expect("type error should have a span");
+        assert_eq!(&source[span.start..span.end], "missing");
+    }
+
+    fn check_source(source: &str) -> (TypeChecker, Result<Type>) {
+        let program = crate::parser::parse_source(source, crate::span::FileId(0)).unwrap();
+        let mut checker = TypeChecker::new();
+        let result =
+            resolve::resolve(&program).and_then(|resolution| checker.check(&program, &resolution));
+        (checker, result)
+    }
+
+    /// The type of the last variable the checked program declared as `name`.
+    fn variable_type(checker: &TypeChecker, name: &str) -> Type {
+        let (symbol, _) = checker
+            .resolution
+            .symbols()
+            .filter(|(_, symbol)| symbol.name == name)
+            .last()
+            .unwrap();
+        checker.symbol_types[&symbol].clone()
+    }
+
+    #[test]
+    fn test_generic_calls_are_instantiated() {
+        let source = "\
+fn map<T, U>(xs: [T], f: fn(T) -> U) -> [U] { return []; }
+fn half(x: int) -> float { return 1.0; }
+let ys = map([1, 2, 3], half);";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+
+        let instances: Vec<Vec<Type>> = checker
+            .instantiations()
+            .into_iter()
+            .filter(|(id, _)| !prelude::contains(*id))
+            .map(|(_, args)| args)
+            .collect();
+        assert_eq!(instances, [vec![Type::I32, Type::F64]]);
+        let ys = variable_type(&checker, "ys");
+        assert_eq!(checker.table.resolve(&ys).to_string(), "[f64]");
+    }
+
+    #[test]
+    fn test_generic_errors() {
+        let (_, result) = check_source("fn make<T>() -> T { return make(); }");
+        let err = result.unwrap_err();
+        assert!(
+            err.message().contains("Cannot infer type parameter T"),
+            "{}",
+            err.message()
+        );
+
+        let (_, result) = check_source("fn same<T>(a: T, b: T) {} same(1, true);");
+        assert!(result
+            .unwrap_err()
+            .message()
+            .contains("expected i32, found bool"));
+
+        let (_, result) = check_source("fn add<T>(a: T, b: T) -> T { return a + b; }");
+        assert!(
+            result.is_err(),
+            "operators are not defined for type parameters"
+        );
+
+        let (_, result) = check_source("struct Pair<A, B> { a: A, b: B } fn f(p: Pair<int>) {}");
+        assert!(result
+            .unwrap_err()
+            .message()
+            .contains("expects 2 type arguments"));
+    }
+
+    #[test]
+    fn test_struct_fields_are_substituted() {
+        let source = "\
+struct Pair<A, B> { first: A, second: B }
+fn second(p: Pair<int, [bool]>) -> [bool] { return p.second; }";
+        let (_, result) = check_source(source);
+        result.unwrap();
+    }
+
+    #[test]
+    fn test_let_bindings_and_empty_arrays_are_inferred() {
+        let source = "\
+let xs = [];
+let n = 1;
+xs = [n, 2];
+let first: int = xs[0];";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        let xs = variable_type(&checker, "xs");
+        assert_eq!(checker.table.resolve(&xs).to_string(), "[i32]");
+    }
+
+    #[test]
+    fn test_mismatch_points_at_both_origins() {
+        let source = "\
+let xs = [];
+xs = [true];
+let ys: [int] = xs;";
+        let (_, result) = check_source(source);
+        let err = result.unwrap_err();
+        assert_eq!(err.message(), "Type mismatch: expected [i32], found [bool]");
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "xs");
+
+        let notes: Vec<(&str, &str)> = err
+            .notes()
+            .iter()
+            .map(|(span, note)| (&source[span.start..span.end], note.as_str()))
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("[]", "inferred as [bool] here"),
+                ("[true]", "inferred as bool here")
+            ]
+        );
+    }
+
+    const SHAPE: &str = "enum Shape { Circle(float), Rect { w: float, h: float } }\n";
+
+    #[test]
+    fn test_match_arms_bind_variant_fields() {
+        let source = format!(
+            "{}{}",
+            SHAPE,
+            "\
+fn area(s: Shape) -> float {
+    match s {
+        Shape::Circle(r) => r * r * 3.14,
+        Shape::Rect { w, h } => w * h,
+    }
+}
+let unit = area(Shape::Rect { w: 1.0, h: 2.0 });"
+        );
+        let (checker, result) = check_source(&source);
+        result.unwrap();
+        assert!(checker.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_non_exhaustive_match_names_missing_pattern() {
+        let source = format!(
+            "{}{}",
+            SHAPE, "fn area(s: Shape) -> float { return match s { Shape::Circle(r) => r }; }"
+        );
+        let (_, result) = check_source(&source);
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Non-exhaustive match: missing pattern `Rect { .. }`"
+        );
+        let span = err.span().unwrap();
+        assert_eq!(&source[span.start..span.end], "s");
+
+        // A guard may fail, so its arm covers nothing.
+        let source = "\
+enum Maybe<T> { Some(T), None }
+let m = Maybe::Some(1);
+let n = match m { Maybe::Some(x) if x > 0 => x, Maybe::None => 0 };";
+        let (_, result) = check_source(source);
+        assert_eq!(
+            result.unwrap_err().message(),
+            "Non-exhaustive match: missing pattern `Some(_)`"
+        );
+    }
+
+    #[test]
+    fn test_unreachable_arms_are_warnings() {
+        let source = format!(
+            "{}{}",
+            SHAPE,
+            "fn f(s: Shape) -> int { match s { Circle(_) | Rect { w: 0.0, .. } => 1, _ => 2, Rect { .. } => 3 } }"
+        );
+        let (checker, result) = check_source(&source);
+        result.unwrap();
+        let warnings: Vec<&str> = checker
+            .warnings()
+            .iter()
+            .map(|warning| {
+                let span = warning.span.unwrap();
+                &source[span.start..span.end]
+            })
+            .collect();
+        assert_eq!(warnings, ["Rect { .. }"]);
+    }
+
+    #[test]
+    fn test_pattern_errors() {
+        let cases = [
+            (
+                "match 1 { true => 1, _ => 2 }",
+                "Mismatched pattern: expected i32, found bool",
+            ),
+            (
+                "fn f(s: Shape) { match s { Shape::Rect { w } => 1, _ => 2 } }",
+                "Pattern does not mention fields h of Shape::Rect; use `..` to ignore them",
+            ),
+            (
+                "fn f(s: Shape) { match s { Shape::Circle(a, b) => 1, _ => 2 } }",
+                "Shape::Circle has 1 fields but the pattern has 2",
+            ),
+            (
+                "fn f(s: Shape) { match s { Circle(r) | Rect { .. } => 1 } }",
+                "Every alternative of an or-pattern must bind the same names",
+            ),
+            (
+                "let s = Shape::Rect { w: 1.0 };",
+                "Missing fields h in Shape::Rect",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(&format!("{}{}", SHAPE, program));
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_references() {
+        let source = "\
+struct Point { x: int, y: int }
+fn sum(p: &Point, xs: &[int]) -> int { return p.x + xs[0]; }
+fn get(p: Point) -> int { let r = &mut p; let q = *r; return sum(&q, &[1, 2]); }";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        let mut types: Vec<String> = checker
+            .binding_types()
+            .into_iter()
+            .filter(|(id, _)| !prelude::contains(*id))
+            .map(|(_, ty)| ty.to_string())
+            .collect();
+        types.sort();
+        assert_eq!(types, ["&Point", "&[i32]", "&mut Point", "Point", "Point"]);
+
+        let cases = [
+            ("let x = *1;", "Cannot dereference i32"),
+            (
+                "fn f(p: &mut int) {} let x = 1; f(&x);",
+                "Argument type mismatch: expected &mut i32, found &i32",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    const AREA: &str = "\
+trait Area {
+    fn area(self) -> float;
+    fn double(self) -> float { return self.area() * 2.0; }
+}
+impl Area for float { fn area(self) -> float { return self * self; } }
+";
+
+    #[test]
+    fn test_trait_methods_and_dyn_conversions() {
+        let source = format!(
+            "{}{}",
+            AREA,
+            "\
+enum Square { Side(float) }
+impl Area for Square {
+    fn area(self) -> float { return match self { Square::Side(s) => s * s }; }
+}
+fn total<T: Area>(shape: T) -> float { return shape.area() + shape.double(); }
+let x = 2.0;
+let a = x.area();
+let b = total(Square::Side(1.0));
+let d: dyn Area = x;
+let c = d.double();"
+        );
+        let (checker, result) = check_source(&source);
+        result.unwrap();
+
+        let mut receivers: Vec<String> = checker
+            .method_calls()
+            .values()
+            .map(|implementation| implementation.self_type.to_string())
+            .collect();
+        receivers.sort();
+        assert_eq!(receivers, ["Self", "T", "T", "dyn Area", "f64"]);
+
+        let coercions: Vec<&Implementation> = checker.coercions().values().collect();
+        assert_eq!(
+            coercions,
+            [&Implementation {
+                self_type: Type::F64,
+                trait_name: "Area".to_string()
+            }]
+        );
+        assert_eq!(
+            checker
+                .trait_methods("Area")
+                .unwrap()
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["area", "double"]
+        );
+    }
+
+    #[test]
+    fn test_trait_errors() {
+        let cases = [
+            (
+                "impl Area for int {}",
+                "Missing method area in impl Area for i32",
+            ),
+            (
+                "impl Area for int { fn area(self) -> int { return 1; } }",
+                "Method area does not match trait Area: expected fn() -> f64, found fn() -> i32",
+            ),
+            (
+                "impl Area for int { fn area(self) -> float { return 1.0; } fn size(self) {} }",
+                "Method size is not a member of trait Area",
+            ),
+            (
+                "impl Area for float { fn area(self) -> float { return 1.0; } }",
+                "Trait Area is already implemented for f64",
+            ),
+            (
+                "fn f<T: Area>(x: T) -> float { return x.area(); } let y = f(1);",
+                "Type i32 does not implement trait Area, required by T of f",
+            ),
+            (
+                "let s: dyn Area = true;",
+                "Type mismatch: bool does not implement trait Area",
+            ),
+            (
+                "trait Same { fn same(self, other: Self) -> bool; }\n\
+                 fn f(x: dyn Same) -> bool { return x.same(x); }",
+                "Method same of trait Same mentions Self and cannot be called on dyn Same",
+            ),
+            (
+                "let n = 1; let m = n.area();",
+                "No method area found for i32",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(&format!("{}{}", AREA, program));
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_for_loops_over_iterables() {
+        let source = "\
+enum Countdown { From(int) }
+impl Iterator<int> for Countdown {
+    fn next(self) -> Option<Pair<int, Self>> {
+        return match self {
+            Countdown::From(0) => Option::None,
+            Countdown::From(n) => Option::Some(Pair { first: n, second: Countdown::From(n - 1) }),
+        };
+    }
+}
+let total = 0;
+for n in Countdown::From(3) { total += n; }
+for c in \"abc\" { let d: char = c; }
+for Pair { first, second } in enumerate(1u8..10u8) { let i: int = first; let s: u8 = second; }
+let evens = collect(take(filter(0..100, |n| n % 2 == 0), 3));
+let pairs = collect(zip(map(Countdown::From(2), |n| n > 1), \"xy\"));";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        for (name, expected) in [("evens", "[i32]"), ("pairs", "[Pair<bool, char>]")] {
+            let ty = variable_type(&checker, name);
+            assert_eq!(checker.table.resolve(&ty).to_string(), expected, "{}", name);
+        }
+
+        let cases = [
+            ("for x in 5 {}", "Cannot iterate over i32"),
+            (
+                "for Option::Some(x) in [Option::Some(1)] {}",
+                "Refutable pattern in for loop: missing pattern `None`",
+            ),
+            (
+                "impl Iterator for int { fn next(self) -> Option<int> { return Option::None; } }",
+                "Trait Iterator expects 1 type arguments but got 0",
+            ),
+            (
+                "let it: dyn Iterator = 1;",
+                "Generic trait Iterator cannot be used with dyn",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_runtime_builtins() {
+        let source = "\
+let line = read_line();
+let count = len([1, 2]) + len(line);
+let text = to_string(count) + to_string([true]);
+let n = parse_int(line);
+let x = parse_float(text);";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        for (name, expected) in [
+            ("count", "i32"),
+            ("text", "string"),
+            ("n", "i32"),
+            ("x", "f64"),
+        ] {
+            let ty = variable_type(&checker, name);
+            assert_eq!(checker.table.resolve(&ty).to_string(), expected, "{}", name);
+        }
+
+        let cases = [
+            (
+                "let n = len(5);",
+                "Function len expects an array or string, found i32",
+            ),
+            (
+                "let n = parse_int(5);",
+                "Argument type mismatch: expected string, found i32",
+            ),
+            (
+                "let s = read_line(1);",
+                "Function read_line expects 0 arguments but got 1",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_question_mark() {
+        let source = "\
+fn parse(s: string) -> Result<int, string> { return Result::Ok(1); }
+fn sum(a: string, b: string) -> Result<int, string> {
+    return Result::Ok(parse(a)? + parse(b)?);
+}
+fn first(xs: [int]) -> Option<int> { return Option::None; }
+fn double_first(xs: [int]) -> Option<int> { return Option::Some(first(xs)? * 2); }
+async fn fetch() -> Result<bool, string> { let n = parse(\"1\")?; return Result::Ok(n > 0); }";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        let tries: Vec<String> = checker
+            .enum_values()
+            .iter()
+            .filter(|(id, ty)| !prelude::contains(**id) && matches!(ty, Type::Generic { .. }))
+            .map(|(_, ty)| ty.to_string())
+            .collect();
+        assert!(tries.contains(&"Result<i32, string>".to_string()));
+        assert!(tries.contains(&"Option<i32>".to_string()));
+
+        let cases = [
+            (
+                "fn f(n: int) -> Option<int> { return Option::Some(n?); }",
+                "The ? operator applies to an Option or Result, not i32",
+            ),
+            (
+                "let o = Option::Some(1); let n = o?;",
+                "The ? operator can only be used in a function",
+            ),
+            (
+                "fn f(o: Option<int>) -> Result<int, string> { return Result::Ok(o?); }",
+                "The ? operator on Option<i32> can only be used in a function returning Option, \
+                 but this function returns Result<i32, string>",
+            ),
+            (
+                "fn f(r: Result<int, bool>) -> Result<int, string> { return Result::Ok(r?); }",
+                "Mismatched error type for ?: expected string, found bool",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_numeric_types() {
+        let source = "\
+let n = 1;
+let a: u8 = 200;
+let b = a + 50;
+let c = b as f64 * 2.5;
+let d = wrapping_add(a, 100);
+let e = checked_mul(3u16, 4);
+let f = -128i8;";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        for (name, expected) in [
+            ("n", "i32"),
+            ("b", "u8"),
+            ("c", "f64"),
+            ("d", "u8"),
+            ("e", "Option<u16>"),
+            ("f", "i8"),
+        ] {
+            let ty = variable_type(&checker, name);
+            assert_eq!(checker.table.resolve(&ty).to_string(), expected, "{}", name);
+        }
+
+        let cases = [
+            (
+                "let x: u8 = 300;",
+                "Integer literal 300 is out of range for u8",
+            ),
+            (
+                "let x = -129i8;",
+                "Integer literal -129 is out of range for i8",
+            ),
+            (
+                "let a: u8 = 1; let b: i32 = 2; let c = a + b;",
+                "Mismatched operands: u8 and i32; convert one of them with `as`",
+            ),
+            (
+                "let a = 1u32; let b = -a;",
+                "Cannot negate a value of unsigned type u32",
+            ),
+            (
+                "let s = \"x\" as i32;",
+                "Cannot convert string to i32 with as",
+            ),
+            (
+                "let x = checked_add(1, true);",
+                "Argument type mismatch: expected i32, found bool",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_closures_are_inferred_from_their_use() {
+        let source = "\
+fn apply(f: fn(int) -> bool, x: int) -> bool { return f(x); }
+let offset = 2u8;
+let shift = |x: u8| x + offset;
+let big = apply(|x| x > 10, 3);
+let flags = map([\"a\", \"bc\"], |s| s == \"a\");
+let evens = filter([1, 2, 3], |x| x % 2 == 0);
+let total = fold([1.5, 2.5], 0.0, |sum, x| { return sum + x; });";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        for (name, expected) in [
+            ("shift", "fn(u8) -> u8"),
+            ("big", "bool"),
+            ("flags", "Iter<bool>"),
+            ("evens", "Iter<i32>"),
+            ("total", "f64"),
+        ] {
+            let ty = variable_type(&checker, name);
+            assert_eq!(checker.table.resolve(&ty).to_string(), expected, "{}", name);
+        }
+
+        let cases = [
+            (
+                "let f = |x: int| x; let y: bool = f(1);",
+                "Type mismatch: expected bool, found i32",
+            ),
+            (
+                "let xs = filter([1, 2], |x| x + 1);",
+                "Return type mismatch: expected bool, found i32",
+            ),
+            (
+                "let f = || -> int { return true; };",
+                "Return type mismatch: expected i32, found bool",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_struct_literals_tuples_places_and_methods() {
+        let source = "\
+struct Point { x: int, y: int }
+struct Line { from: Point, to: Point }
+impl Line {
+    fn length(self) -> int { return self.to.x - self.from.x; }
+}
+let line = Line { from: Point { x: 0, y: 0 }, to: Point { x: 1, y: 2 } };
+line.to.x += 3;
+let pair = (line.from, true);
+let (Point { x, y: _ }, ok) = pair;
+let flag = pair.1;
+let n = line.length();";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        for (name, expected) in [
+            ("pair", "(Point, bool)"),
+            ("x", "i32"),
+            ("flag", "bool"),
+            ("n", "i32"),
+        ] {
+            let ty = variable_type(&checker, name);
+            assert_eq!(checker.table.resolve(&ty).to_string(), expected, "{}", name);
+        }
+
+        let cases = [
+            (
+                "struct P { x: int } let p = P { x: 1, z: 2 };",
+                "Struct P has no field z",
+            ),
+            (
+                "struct P { x: int, y: int } let p = P { x: 1 };",
+                "Missing fields y in P",
+            ),
+            (
+                "struct P { x: int } let p = P { x: 1 }; p.x = true;",
+                "Cannot assign to p.x: expected i32, found bool",
+            ),
+            ("let t = (1, 2); let n = t.2;", "(i32, i32) has no field 2"),
+            (
+                "let (a, 0) = (1, 2);",
+                "Refutable pattern in let: missing pattern `(_, _)`",
+            ),
+            (
+                "impl int { fn f(self) {} }",
+                "Methods can only be defined for structs and enums, not i32",
+            ),
+            (
+                "struct P { x: int } impl P { fn f(self) {} } fn g(p: dyn P) {}",
+                "Unknown trait P",
+            ),
+        ];
+        for (program, expected) in cases {
+            let (_, result) = check_source(program);
+            assert_eq!(result.unwrap_err().message(), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_block_bindings_do_not_leak() {
+        let source =
+            "let x = 1; { let x = true; } let y = x + 1; let z = 0; while z < 1 { let z = \"a\"; }";
+        let (checker, result) = check_source(source);
+        result.unwrap();
+        assert_eq!(
+            checker.table.resolve(&variable_type(&checker, "y")),
+            Type::I32
+        );
+    }
 }
@@ -0,0 +1,404 @@
+//! Type variables and unification for local type inference. The checker gives
+//! unannotated `let` bindings and the elements of empty array literals a fresh
+//! variable, unifies it with every type it is used as, and reads back what it
+//! learned with `resolve`. Unsuffixed number literals get a variable that can
+//! only become an integer or only a float type, defaulting to `i32` or `f64`
+//! when nothing else decides.
+
+use crate::{ast::Type, span::Span};
+
+#[derive(Debug, Clone, Default)]
+struct Variable {
+    value: Option<Type>,
+    /// Where the value was learned, for error notes.
+    bound_at: Option<Span>,
+    kind: Kind,
+}
+
+/// What a variable may be solved to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Kind {
+    #[default]
+    Any,
+    /// The type of an unsuffixed integer literal.
+    Integer,
+    /// The type of an unsuffixed float literal.
+    Float,
+}
+
+impl Kind {
+    fn admits(self, ty: &Type) -> bool {
+        match self {
+            Kind::Any => true,
+            Kind::Integer => ty.is_integer() || *ty == Type::Unknown,
+            Kind::Float => ty.is_float() || *ty == Type::Unknown,
+        }
+    }
+
+    fn default_type(self) -> Option<Type> {
+        match self {
+            Kind::Any => None,
+            Kind::Integer => Some(Type::I32),
+            Kind::Float => Some(Type::F64),
+        }
+    }
+}
+
+/// Two types that could not be unified, resolved as far as currently known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub expected: Type,
+    pub found: Type,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InferenceTable {
+    variables: Vec<Variable>,
+}
+
+impl InferenceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new variable, not yet known to be any particular type.
+    pub fn fresh(&mut self) -> Type {
+        self.fresh_of(Kind::Any)
+    }
+
+    /// A new variable that can only be solved to a type of `kind`.
+    pub fn fresh_of(&mut self, kind: Kind) -> Type {
+        self.variables.push(Variable {
+            kind,
+            ..Variable::default()
+        });
+        Type::Var(self.variables.len() as u32 - 1)
+    }
+
+    /// What an unsolved variable may become; `Any` for every other type.
+    pub fn kind(&self, ty: &Type) -> Kind {
+        match self.shallow_resolve(ty) {
+            Type::Var(var) => self.variables[var as usize].kind,
+            _ => Kind::Any,
+        }
+    }
+
+    /// `ty` resolved, with unsolved literal variables solved to their default
+    /// type, for when the type has to be known now: a method's receiver, or a
+    /// type shown in an error.
+    pub fn settle(&mut self, ty: &Type) -> Type {
+        let mut unsolved = Vec::new();
+        visit_vars(&self.resolve(ty), &mut |var| unsolved.push(var));
+        for var in unsolved {
+            self.default_variable(var);
+        }
+        self.resolve(ty)
+    }
+
+    /// Solves every unsolved literal variable to its default type, once nothing
+    /// more can be learned about them.
+    pub fn settle_all(&mut self) {
+        for var in 0..self.variables.len() as u32 {
+            self.default_variable(var);
+        }
+    }
+
+    fn default_variable(&mut self, var: u32) {
+        let variable = &mut self.variables[var as usize];
+        if variable.value.is_none() {
+            variable.value = variable.kind.default_type();
+        }
+    }
+
+    /// `ty` with every solved variable replaced by its value.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.variables[*var as usize].value {
+                Some(value) => self.resolve(value),
+                None => ty.clone(),
+            },
+            Type::Array { elem_type, size } => Type::Array {
+                elem_type: Box::new(self.resolve(elem_type)),
+                size: *size,
+            },
+            Type::Function {
+                params,
+                return_type,
+                is_async,
+            } => Type::Function {
+                params: params.iter().map(|param| self.resolve(param)).collect(),
+                return_type: Box::new(self.resolve(return_type)),
+                is_async: *is_async,
+            },
+            Type::Pointer(inner) => Type::Pointer(Box::new(self.resolve(inner))),
+            Type::Ref { mutable, inner } => Type::Ref {
+                mutable: *mutable,
+                inner: Box::new(self.resolve(inner)),
+            },
+            Type::Generic { name, args } => Type::Generic {
+                name: name.clone(),
+                args: args.iter().map(|arg| self.resolve(arg)).collect(),
+            },
+            Type::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.resolve(element))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Makes `expected` and `found` the same type by solving variables on either
+    /// side; `at` is recorded as where those solutions were learned. An unsized
+    /// array (`size` 0) unifies with arrays of any size.
+    pub fn unify(&mut self, expected: &Type, found: &Type, at: Span) -> Result<(), Mismatch> {
+        if self.unify_parts(expected, found, at) {
+            Ok(())
+        } else {
+            Err(Mismatch {
+                expected: self.display(expected),
+                found: self.display(found),
+            })
+        }
+    }
+
+    fn unify_parts(&mut self, expected: &Type, found: &Type, at: Span) -> bool {
+        let expected = self.shallow_resolve(expected);
+        let found = self.shallow_resolve(found);
+        match (&expected, &found) {
+            (Type::Var(a), Type::Var(b)) if a == b => true,
+            (Type::Var(a), Type::Var(b)) => {
+                // The variable left unsolved keeps the stricter kind of the two.
+                let kind = match (
+                    self.variables[*a as usize].kind,
+                    self.variables[*b as usize].kind,
+                ) {
+                    (Kind::Any, kind) | (kind, Kind::Any) => kind,
+                    (a, b) if a == b => a,
+                    _ => return false,
+                };
+                self.variables[*b as usize].kind = kind;
+                self.bind(*a, found.clone(), at);
+                true
+            }
+            (Type::Var(var), other) | (other, Type::Var(var)) => {
+                if !self.variables[*var as usize].kind.admits(other) || self.occurs(*var, other) {
+                    return false;
+                }
+                self.bind(*var, other.clone(), at);
+                true
+            }
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (
+                Type::Array { elem_type, size },
+                Type::Array {
+                    elem_type: found_elem,
+                    size: found_size,
+                },
+            ) => {
+                (size == found_size || *size == 0 || *found_size == 0)
+                    && self.unify_parts(elem_type, found_elem, at)
+            }
+            (
+                Type::Function {
+                    params,
+                    return_type,
+                    ..
+                },
+                Type::Function {
+                    params: found_params,
+                    return_type: found_return,
+                    ..
+                },
+            ) => {
+                params.len() == found_params.len()
+                    && params
+                        .iter()
+                        .zip(found_params)
+                        .all(|(param, found)| self.unify_parts(param, found, at))
+                    && self.unify_parts(return_type, found_return, at)
+            }
+            (Type::Pointer(inner), Type::Pointer(found_inner)) => {
+                self.unify_parts(inner, found_inner, at)
+            }
+            (
+                Type::Ref { mutable, inner },
+                Type::Ref {
+                    mutable: found_mutable,
+                    inner: found_inner,
+                },
+            ) => mutable == found_mutable && self.unify_parts(inner, found_inner, at),
+            (
+                Type::Generic { name, args },
+                Type::Generic {
+                    name: found_name,
+                    args: found_args,
+                },
+            ) => {
+                name == found_name
+                    && args.len() == found_args.len()
+                    && args
+                        .iter()
+                        .zip(found_args)
+                        .all(|(arg, found)| self.unify_parts(arg, found, at))
+            }
+            (Type::Tuple(elements), Type::Tuple(found_elements)) => {
+                elements.len() == found_elements.len()
+                    && elements
+                        .iter()
+                        .zip(found_elements)
+                        .all(|(element, found)| self.unify_parts(element, found, at))
+            }
+            _ => expected == found,
+        }
+    }
+
+    fn bind(&mut self, var: u32, value: Type, at: Span) {
+        let variable = &mut self.variables[var as usize];
+        variable.value = Some(value);
+        variable.bound_at = Some(at);
+    }
+
+    /// `ty` resolved for an error message: literal variables read as the type
+    /// they default to.
+    fn display(&self, ty: &Type) -> Type {
+        let mut bindings = Vec::new();
+        visit_vars(&self.resolve(ty), &mut |var| {
+            if let Some(default) = self.variables[var as usize].kind.default_type() {
+                bindings.push((var, default));
+            }
+        });
+        let mut shown = self.clone();
+        for (var, default) in bindings {
+            shown.variables[var as usize].value = Some(default);
+        }
+        shown.resolve(ty)
+    }
+
+    /// Follows solved variables until reaching a type that is not one.
+    fn shallow_resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.variables[var as usize].value {
+                Some(value) => ty = value.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Whether `var` appears in `ty`; binding it there would make an infinite type.
+    fn occurs(&self, var: u32, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => other == var,
+            resolved => {
+                let mut found = false;
+                visit_vars(&resolved, &mut |other| found |= other == var);
+                found
+            }
+        }
+    }
+
+    /// Where the variables in `ty` were solved, with what they were solved to.
+    pub fn origins(&self, ty: &Type) -> Vec<(Span, Type)> {
+        let mut origins = Vec::new();
+        let mut pending = Vec::new();
+        visit_vars(ty, &mut |var| pending.push(var));
+        while let Some(var) = pending.pop() {
+            let variable = &self.variables[var as usize];
+            if let (Some(value), Some(at)) = (&variable.value, variable.bound_at) {
+                let entry = (at, self.resolve(value));
+                if !origins.contains(&entry) {
+                    origins.push(entry);
+                }
+                visit_vars(value, &mut |var| pending.push(var));
+            }
+        }
+        origins
+    }
+}
+
+fn visit_vars(ty: &Type, f: &mut impl FnMut(u32)) {
+    match ty {
+        Type::Var(var) => f(*var),
+        Type::Array { elem_type, .. } => visit_vars(elem_type, f),
+        Type::Function {
+            params,
+            return_type,
+            ..
+        } => {
+            for param in params {
+                visit_vars(param, f);
+            }
+            visit_vars(return_type, f);
+        }
+        Type::Pointer(inner) | Type::Ref { inner, .. } => visit_vars(inner, f),
+        Type::Generic { args, .. } | Type::Tuple(args) => {
+            for arg in args {
+                visit_vars(arg, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(elem_type: Type) -> Type {
+        Type::Array {
+            elem_type: Box::new(elem_type),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn test_unify_solves_variables_on_either_side() {
+        let mut table = InferenceTable::new();
+        let (a, b) = (table.fresh(), table.fresh());
+        table.unify(&array(a.clone()), &b, Span::dummy()).unwrap();
+        table.unify(&b, &array(Type::Bool), Span::dummy()).unwrap();
+        assert_eq!(table.resolve(&a), Type::Bool);
+        assert_eq!(table.resolve(&b), array(Type::Bool));
+    }
+
+    #[test]
+    fn test_mismatch_reports_resolved_types_and_origins() {
+        let mut table = InferenceTable::new();
+        let var = table.fresh();
+        let origin = Span::new(Default::default(), 4, 5);
+        table.unify(&var, &Type::I32, origin).unwrap();
+
+        let mismatch = table
+            .unify(&array(Type::Bool), &array(var.clone()), Span::dummy())
+            .unwrap_err();
+        assert_eq!(mismatch.found, array(Type::I32));
+        assert_eq!(table.origins(&array(var)), vec![(origin, Type::I32)]);
+    }
+
+    #[test]
+    fn test_occurs_check_rejects_infinite_types() {
+        let mut table = InferenceTable::new();
+        let var = table.fresh();
+        assert!(table
+            .unify(&var, &array(var.clone()), Span::dummy())
+            .is_err());
+    }
+
+    #[test]
+    fn test_number_variables_default_unless_solved() {
+        let mut table = InferenceTable::new();
+        let (int, float) = (table.fresh_of(Kind::Integer), table.fresh_of(Kind::Float));
+        assert!(table.unify(&int, &Type::Bool, Span::dummy()).is_err());
+        table.unify(&float, &Type::F32, Span::dummy()).unwrap();
+
+        let other = table.fresh_of(Kind::Integer);
+        table.unify(&int, &other, Span::dummy()).unwrap();
+        assert_eq!(table.kind(&other), Kind::Integer);
+        assert_eq!(table.settle(&other), Type::I32);
+        assert_eq!(table.resolve(&int), Type::I32);
+        assert_eq!(table.settle(&float), Type::F32);
+    }
+}
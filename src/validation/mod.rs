@@ -1,6 +1,6 @@
 use crate::{
-    ast::{ASTNode, Expression, Function, Statement},
-    visitor::{Visitable, Visitor},
+    ast::{ASTNode, Parameter, Pattern, Type},
+    visitor::{walk_nodes, Visitable, Visitor},
     IoError, Result,
 };
 
@@ -22,45 +22,85 @@ impl Validator {
     }
 }
 
-impl Visitor<()> for Validator {
-    fn visit_program(&mut self, statements: &[ASTNode]) -> Result<()> {
-        for stmt in statements {
-            stmt.accept(self)?;
-        }
-        Ok(())
+impl Default for Validator {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn visit_function(&mut self, function: &Function) -> Result<()> {
-        if !is_valid_identifier(&function.name) {
+impl Visitor for Validator {
+    type Output = ();
+
+    fn visit_function(
+        &mut self,
+        name: &str,
+        _params: &[Parameter],
+        _return_type: Option<&Type>,
+        body: &[ASTNode],
+        _is_async: bool,
+    ) -> Result<()> {
+        if !is_valid_identifier(name) {
             return Err(IoError::validation_error(format!(
                 "Invalid function name: {}",
-                function.name
+                name
             )));
         }
 
         let was_in_function = self.in_function;
         self.in_function = true;
+        let result = walk_nodes(self, body);
+        self.in_function = was_in_function;
+        result
+    }
 
-        for stmt in &function.body {
-            stmt.accept(self)?;
-        }
-
+    fn visit_closure(
+        &mut self,
+        _params: &[Parameter],
+        _return_type: Option<&Type>,
+        body: &ASTNode,
+        _is_move: bool,
+    ) -> Result<()> {
+        // A closure's body is a function body of its own.
+        let (was_in_function, was_in_loop) = (self.in_function, self.in_loop);
+        self.in_function = true;
+        self.in_loop = false;
+        let result = body.accept(self);
         self.in_function = was_in_function;
-        Ok(())
+        self.in_loop = was_in_loop;
+        result
     }
 
-    fn visit_loop(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<()> {
+    fn visit_while(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<()> {
         let was_in_loop = self.in_loop;
         self.in_loop = true;
 
         condition.accept(self)?;
+        let result = walk_nodes(self, body);
+
+        self.in_loop = was_in_loop;
+        result
+    }
 
-        for stmt in body {
-            stmt.accept(self)?;
+    fn visit_for(&mut self, pattern: &Pattern, iterable: &ASTNode, body: &[ASTNode]) -> Result<()> {
+        if let Some((variable, span)) = pattern
+            .bindings()
+            .into_iter()
+            .find(|(name, _)| !is_valid_identifier(name))
+        {
+            return Err(IoError::validation_error(format!(
+                "Invalid loop variable name: {}",
+                variable
+            ))
+            .with_span(span));
         }
 
+        iterable.accept(self)?;
+
+        let was_in_loop = self.in_loop;
+        self.in_loop = true;
+        let result = walk_nodes(self, body);
         self.in_loop = was_in_loop;
-        Ok(())
+        result
     }
 
     fn visit_break(&mut self) -> Result<()> {
@@ -79,7 +119,7 @@ impl Visitor<()> for Validator {
         Ok(())
     }
 
-    fn visit_return(&mut self, value: &Option<ASTNode>) -> Result<()> {
+    fn visit_return(&mut self, value: Option<&ASTNode>) -> Result<()> {
         if !self.in_function {
             return Err(IoError::validation_error(
                 "Return statement outside of function",
@@ -92,11 +132,11 @@ impl Visitor<()> for Validator {
         Ok(())
     }
 
-    fn visit_variable_decl(
+    fn visit_let(
         &mut self,
         name: &str,
-        type_name: &Option<String>,
-        initializer: &Option<ASTNode>,
+        _type_annotation: Option<&Type>,
+        value: Option<&ASTNode>,
     ) -> Result<()> {
         if !is_valid_identifier(name) {
             return Err(IoError::validation_error(format!(
@@ -105,78 +145,10 @@ impl Visitor<()> for Validator {
             )));
         }
 
-        if let Some(init) = initializer {
-            init.accept(self)?;
-        }
-        Ok(())
-    }
-
-    fn visit_call_expr(&mut self, callee: &ASTNode, arguments: &[ASTNode]) -> Result<()> {
-        callee.accept(self)?;
-
-        for arg in arguments {
-            arg.accept(self)?;
+        match value {
+            Some(value) => value.accept(self),
+            None => Ok(()),
         }
-        Ok(())
-    }
-
-    fn visit_if(
-        &mut self,
-        condition: &ASTNode,
-        then_branch: &[ASTNode],
-        else_branch: &Option<Vec<ASTNode>>,
-    ) -> Result<()> {
-        condition.accept(self)?;
-
-        for stmt in then_branch {
-            stmt.accept(self)?;
-        }
-
-        if let Some(else_stmts) = else_branch {
-            for stmt in else_stmts {
-                stmt.accept(self)?;
-            }
-        }
-        Ok(())
-    }
-
-    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<()> {
-        match target {
-            ASTNode::Identifier(_) | ASTNode::MemberAccess { .. } => {
-                target.accept(self)?;
-                value.accept(self)
-            }
-            _ => Err(IoError::validation_error("Invalid assignment target")),
-        }
-    }
-
-    fn visit_statement(&mut self, _statement: &Statement) -> Result<()> {
-        Ok(())
-    }
-
-    fn visit_expression(&mut self, _expression: &Expression) -> Result<()> {
-        Ok(())
-    }
-
-    fn visit_binary_expr(
-        &mut self,
-        _left: &Expression,
-        _operator: &str,
-        _right: &Expression,
-    ) -> Result<()> {
-        Ok(())
-    }
-
-    fn visit_unary_expr(&mut self, _operator: &str, _operand: &Expression) -> Result<()> {
-        Ok(())
-    }
-
-    fn visit_identifier(&mut self, _name: &str) -> Result<()> {
-        Ok(())
-    }
-
-    fn visit_literal(&mut self, _value: &Expression) -> Result<()> {
-        Ok(())
     }
 }
 
@@ -189,10 +161,16 @@ fn is_valid_identifier(name: &str) -> bool {
     first_char && name.chars().all(|c| c.is_alphanumeric() || c == '_')
 }
 
-fn validate_node(&self, node: &ASTNode) -> Result<()> {
-    match node {
-        ASTNode::Identifier(_) | ASTNode::MemberAccess { .. } => {
-            // ...existing code...
-        } // ...existing match arms...
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, span::FileId};
+
+    #[test]
+    fn test_break_outside_loop_is_rejected() {
+        let program = parse_source("fn f() { while true { break; } break; }", FileId(0)).unwrap();
+        let err = Validator::new().validate(&program).unwrap_err();
+        assert_eq!(err.message(), "Break statement outside of loop");
+        assert_eq!(err.span().map(|span| span.start), Some(31));
     }
 }
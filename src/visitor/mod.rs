@@ -1,100 +1,999 @@
-use crate::ast::{ASTNode, Expression, Function, Statement};
-use crate::prelude::*;
+use crate::ast::{
+    ASTNode, Attribute, BinaryOperator, Field, Literal, MatchArm, NodeId, Parameter, Pattern,
+    TraitMethod, Type, UnaryOperator, Variant, VariantFields,
+};
+use crate::span::Span;
 use crate::Result;
 
-pub trait Visitor<T> {
-    fn visit_program(&mut self, statements: &[ASTNode]) -> Result<T>;
-    fn visit_function(&mut self, function: &Function) -> Result<T>;
-    fn visit_statement(&mut self, statement: &Statement) -> Result<T>;
-    fn visit_expression(&mut self, expression: &Expression) -> Result<T>;
-
-    // Control flow
-    fn visit_loop(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<T>;
-    fn visit_break(&mut self) -> Result<T>;
-    fn visit_continue(&mut self) -> Result<T>;
-    fn visit_return(&mut self, value: &Option<ASTNode>) -> Result<T>;
+/// Read-only traversal of the AST.
+///
+/// Every hook defaults to walking the node's children and returning
+/// `Output::default()`, so a pass only overrides the nodes it cares about.
+/// Errors raised while visiting a node point at that node unless a nested
+/// node already claimed them.
+pub trait Visitor {
+    type Output: Default;
+
+    fn visit_node(&mut self, node: &ASTNode) -> Result<Self::Output> {
+        walk_node(self, node)
+    }
+
+    fn visit_program(&mut self, items: &[ASTNode]) -> Result<Self::Output> {
+        walk_nodes(self, items)
+    }
+
+    fn visit_function(
+        &mut self,
+        _name: &str,
+        _params: &[Parameter],
+        _return_type: Option<&Type>,
+        body: &[ASTNode],
+        _is_async: bool,
+    ) -> Result<Self::Output> {
+        walk_nodes(self, body)
+    }
+
+    fn visit_global(
+        &mut self,
+        _name: &str,
+        _type_annotation: Option<&Type>,
+        value: &ASTNode,
+    ) -> Result<Self::Output> {
+        self.visit_node(value)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_struct_def(
+        &mut self,
+        _name: &str,
+        _type_params: &[String],
+        _fields: &[Field],
+    ) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_enum_def(
+        &mut self,
+        _name: &str,
+        _type_params: &[String],
+        _variants: &[Variant],
+    ) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    /// Walks the bodies of default methods.
+    fn visit_trait_def(&mut self, _name: &str, methods: &[TraitMethod]) -> Result<Self::Output> {
+        for method in methods {
+            if let Some(body) = &method.body {
+                walk_nodes(self, body)?;
+            }
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn visit_impl(
+        &mut self,
+        _trait_name: Option<&str>,
+        _self_type: &Type,
+        methods: &[ASTNode],
+    ) -> Result<Self::Output> {
+        walk_nodes(self, methods)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_import(&mut self, _path: &[String], _items: &[String]) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_module_attributes(&mut self, _attributes: &[Attribute]) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_block(&mut self, statements: &[ASTNode]) -> Result<Self::Output> {
+        walk_nodes(self, statements)
+    }
+
+    fn visit_let(
+        &mut self,
+        _name: &str,
+        _type_annotation: Option<&Type>,
+        value: Option<&ASTNode>,
+    ) -> Result<Self::Output> {
+        if let Some(value) = value {
+            self.visit_node(value)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn visit_let_pattern(
+        &mut self,
+        _pattern: &Pattern,
+        _type_annotation: Option<&Type>,
+        value: &ASTNode,
+    ) -> Result<Self::Output> {
+        self.visit_node(value)?;
+        Ok(Self::Output::default())
+    }
+
+    /// Visits the value, then what the target reads; see [`walk_place`].
+    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<Self::Output> {
+        self.visit_node(value)?;
+        walk_place(self, target)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_compound_assignment(
+        &mut self,
+        target: &ASTNode,
+        _op: &BinaryOperator,
+        value: &ASTNode,
+    ) -> Result<Self::Output> {
+        self.visit_node(value)?;
+        walk_place(self, target)?;
+        Ok(Self::Output::default())
+    }
+
     fn visit_if(
         &mut self,
         condition: &ASTNode,
-        then_branch: &[Statement],
-        else_branch: &Option<Vec<Statement>>,
-    ) -> Result<T>;
-
-    // Expressions
-    fn visit_binary_expr(&mut self, left: &ASTNode, operator: &str, right: &ASTNode) -> Result<T>;
-    fn visit_unary_expr(&mut self, operator: &str, operand: &ASTNode) -> Result<T>;
-    fn visit_call_expr(&mut self, callee: &ASTNode, arguments: &[ASTNode]) -> Result<T>;
-    fn visit_assignment(&mut self, target: &ASTNode, value: &ASTNode) -> Result<T>;
-    fn visit_identifier(&mut self, name: &str) -> Result<T>;
-    fn visit_literal(&mut self, value: &Expression) -> Result<T>;
-
-    // Declarations
-    fn visit_variable_decl(
+        then_branch: &[ASTNode],
+        else_branch: Option<&[ASTNode]>,
+    ) -> Result<Self::Output> {
+        self.visit_node(condition)?;
+        walk_nodes(self, then_branch)?;
+        if let Some(else_branch) = else_branch {
+            walk_nodes(self, else_branch)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn visit_match(&mut self, scrutinee: &ASTNode, arms: &[MatchArm]) -> Result<Self::Output> {
+        self.visit_node(scrutinee)?;
+        for arm in arms {
+            if let Some(guard) = &arm.guard {
+                self.visit_node(guard)?;
+            }
+            self.visit_node(&arm.body)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn visit_while(&mut self, condition: &ASTNode, body: &[ASTNode]) -> Result<Self::Output> {
+        self.visit_node(condition)?;
+        walk_nodes(self, body)
+    }
+
+    fn visit_for(
+        &mut self,
+        _pattern: &Pattern,
+        iterable: &ASTNode,
+        body: &[ASTNode],
+    ) -> Result<Self::Output> {
+        self.visit_node(iterable)?;
+        walk_nodes(self, body)
+    }
+
+    fn visit_return(&mut self, value: Option<&ASTNode>) -> Result<Self::Output> {
+        if let Some(value) = value {
+            self.visit_node(value)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn visit_break(&mut self) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_continue(&mut self) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_call(&mut self, callee: &ASTNode, args: &[ASTNode]) -> Result<Self::Output> {
+        self.visit_node(callee)?;
+        walk_nodes(self, args)
+    }
+
+    fn visit_binary(
+        &mut self,
+        _op: &BinaryOperator,
+        left: &ASTNode,
+        right: &ASTNode,
+    ) -> Result<Self::Output> {
+        self.visit_node(left)?;
+        self.visit_node(right)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_unary(&mut self, _op: &UnaryOperator, operand: &ASTNode) -> Result<Self::Output> {
+        self.visit_node(operand)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_cast(&mut self, value: &ASTNode, _target: &Type) -> Result<Self::Output> {
+        self.visit_node(value)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_member_access(&mut self, object: &ASTNode, _member: &str) -> Result<Self::Output> {
+        self.visit_node(object)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_index(&mut self, array: &ASTNode, index: &ASTNode) -> Result<Self::Output> {
+        self.visit_node(array)?;
+        self.visit_node(index)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_closure(
         &mut self,
-        name: &str,
-        type_name: &Option<String>,
-        initializer: &Option<ASTNode>,
-    ) -> Result<T>;
+        _params: &[Parameter],
+        _return_type: Option<&Type>,
+        body: &ASTNode,
+        _is_move: bool,
+    ) -> Result<Self::Output> {
+        self.visit_node(body)?;
+        Ok(Self::Output::default())
+    }
+
+    fn visit_array(&mut self, elements: &[ASTNode]) -> Result<Self::Output> {
+        walk_nodes(self, elements)
+    }
+
+    fn visit_tuple(&mut self, elements: &[ASTNode]) -> Result<Self::Output> {
+        walk_nodes(self, elements)
+    }
+
+    fn visit_struct_literal(
+        &mut self,
+        _name: &str,
+        fields: &[(String, ASTNode)],
+    ) -> Result<Self::Output> {
+        for (_, value) in fields {
+            self.visit_node(value)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn visit_identifier(&mut self, _name: &str) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_literal(&mut self, _value: &Literal) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+
+    fn visit_error(&mut self) -> Result<Self::Output> {
+        Ok(Self::Output::default())
+    }
+}
+
+/// Dispatches `node` to the matching `visit_*` hook.
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &ASTNode) -> Result<V::Output> {
+    let result = match node {
+        ASTNode::Program(items) => visitor.visit_program(items),
+        ASTNode::Function {
+            name,
+            params,
+            return_type,
+            body,
+            is_async,
+            ..
+        } => visitor.visit_function(name, params, return_type.as_ref(), body, *is_async),
+        ASTNode::Global {
+            name,
+            type_annotation,
+            value,
+            ..
+        } => visitor.visit_global(name, type_annotation.as_ref(), value),
+        ASTNode::StructDef {
+            name,
+            type_params,
+            fields,
+            ..
+        } => visitor.visit_struct_def(name, type_params, fields),
+        ASTNode::EnumDef {
+            name,
+            type_params,
+            variants,
+            ..
+        } => visitor.visit_enum_def(name, type_params, variants),
+        ASTNode::TraitDef { name, methods, .. } => visitor.visit_trait_def(name, methods),
+        ASTNode::Impl {
+            trait_name,
+            self_type,
+            methods,
+            ..
+        } => visitor.visit_impl(trait_name.as_deref(), self_type, methods),
+        ASTNode::Import { path, items, .. } => visitor.visit_import(path, items),
+        ASTNode::ModuleAttributes { attributes, .. } => visitor.visit_module_attributes(attributes),
+        ASTNode::Block { statements, .. } => visitor.visit_block(statements),
+        ASTNode::Let {
+            name,
+            type_annotation,
+            value,
+            ..
+        } => visitor.visit_let(name, type_annotation.as_ref(), value.as_deref()),
+        ASTNode::LetPattern {
+            pattern,
+            type_annotation,
+            value,
+            ..
+        } => visitor.visit_let_pattern(pattern, type_annotation.as_ref(), value),
+        ASTNode::Assignment { target, value, .. } => visitor.visit_assignment(target, value),
+        ASTNode::CompoundAssignment {
+            target, op, value, ..
+        } => visitor.visit_compound_assignment(target, op, value),
+        ASTNode::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+        ASTNode::Match {
+            scrutinee, arms, ..
+        } => visitor.visit_match(scrutinee, arms),
+        ASTNode::While {
+            condition, body, ..
+        } => visitor.visit_while(condition, body),
+        ASTNode::For {
+            pattern,
+            iterable,
+            body,
+            ..
+        } => visitor.visit_for(pattern, iterable, body),
+        ASTNode::Return { value, .. } => visitor.visit_return(value.as_deref()),
+        ASTNode::Break { .. } => visitor.visit_break(),
+        ASTNode::Continue { .. } => visitor.visit_continue(),
+        ASTNode::Call { callee, args, .. } => visitor.visit_call(callee, args),
+        ASTNode::BinaryOp {
+            op, left, right, ..
+        } => visitor.visit_binary(op, left, right),
+        ASTNode::UnaryOp { op, operand, .. } => visitor.visit_unary(op, operand),
+        ASTNode::Cast { value, target, .. } => visitor.visit_cast(value, target),
+        ASTNode::MemberAccess { object, member, .. } => visitor.visit_member_access(object, member),
+        ASTNode::Index { array, index, .. } => visitor.visit_index(array, index),
+        ASTNode::Closure {
+            params,
+            return_type,
+            body,
+            is_move,
+            ..
+        } => visitor.visit_closure(params, return_type.as_ref(), body, *is_move),
+        ASTNode::ArrayLiteral { elements, .. } => visitor.visit_array(elements),
+        ASTNode::TupleLiteral { elements, .. } => visitor.visit_tuple(elements),
+        ASTNode::StructLiteral { name, fields, .. } => visitor.visit_struct_literal(name, fields),
+        ASTNode::Identifier { name, .. } => visitor.visit_identifier(name),
+        ASTNode::Literal { value, .. } => visitor.visit_literal(value),
+        ASTNode::Error { .. } => visitor.visit_error(),
+    };
+    result.map_err(|err| err.or_span(node.span()))
+}
+
+/// Visits `nodes` in order, returning the output of the last one.
+pub fn walk_nodes<V: Visitor + ?Sized>(visitor: &mut V, nodes: &[ASTNode]) -> Result<V::Output> {
+    let mut output = V::Output::default();
+    for node in nodes {
+        output = visitor.visit_node(node)?;
+    }
+    Ok(output)
+}
+
+/// Visits the expressions an assignment target evaluates to find its place,
+/// the indices in `grid[i][j]`, but not the variable it writes into, which
+/// the assignment doesn't read.
+pub fn walk_place<V: Visitor + ?Sized>(visitor: &mut V, target: &ASTNode) -> Result<()> {
+    match target {
+        ASTNode::Identifier { .. } => Ok(()),
+        ASTNode::MemberAccess { object, .. } => walk_place(visitor, object),
+        ASTNode::Index { array, index, .. } => {
+            walk_place(visitor, array)?;
+            visitor.visit_node(index).map(|_| ())
+        }
+        other => visitor.visit_node(other).map(|_| ()),
+    }
+}
+
+/// Owning, rewriting traversal of the AST.
+///
+/// `fold_node` defaults to rebuilding the node from its folded children, so a
+/// pass overrides it, handles the nodes it rewrites and defers the rest to
+/// [`fold_children`]. Rebuilt nodes keep their id and span.
+pub trait Folder {
+    fn fold_node(&mut self, node: ASTNode) -> Result<ASTNode> {
+        fold_children(self, node)
+    }
+
+    /// Rewrites a type annotation. [`fold_children`] passes every annotation
+    /// of the node it rebuilds through here; nested types are left to the
+    /// override.
+    fn fold_type(&mut self, ty: Type) -> Result<Type> {
+        Ok(ty)
+    }
+
+    fn fold_nodes(&mut self, nodes: Vec<ASTNode>) -> Result<Vec<ASTNode>> {
+        nodes.into_iter().map(|node| self.fold_node(node)).collect()
+    }
+}
+
+/// Folds every child of `node` and reassembles it.
+pub fn fold_children<F: Folder + ?Sized>(folder: &mut F, node: ASTNode) -> Result<ASTNode> {
+    let span = node.span();
+    rebuild(folder, node).map_err(|err| err.or_span(span))
+}
+
+fn rebuild<F: Folder + ?Sized>(folder: &mut F, node: ASTNode) -> Result<ASTNode> {
+    match node {
+        ASTNode::Program(items) => Ok(ASTNode::Program(folder.fold_nodes(items)?)),
+        ASTNode::Function {
+            name,
+            type_params,
+            bounds,
+            params,
+            return_type,
+            body,
+            is_async,
+            is_const,
+            is_pub,
+            attributes,
+            id,
+            span,
+        } => Ok(ASTNode::Function {
+            name,
+            type_params,
+            bounds,
+            params: fold_params(folder, params)?,
+            return_type: return_type.map(|ty| folder.fold_type(ty)).transpose()?,
+            body: folder.fold_nodes(body)?,
+            is_async,
+            is_const,
+            is_pub,
+            attributes,
+            id,
+            span,
+        }),
+        ASTNode::Global {
+            name,
+            type_annotation,
+            value,
+            is_pub,
+            id,
+            span,
+        } => Ok(ASTNode::Global {
+            name,
+            type_annotation: type_annotation.map(|ty| folder.fold_type(ty)).transpose()?,
+            value: fold_boxed(folder, value)?,
+            is_pub,
+            id,
+            span,
+        }),
+        ASTNode::Block {
+            statements,
+            id,
+            span,
+        } => Ok(ASTNode::Block {
+            statements: folder.fold_nodes(statements)?,
+            id,
+            span,
+        }),
+        ASTNode::Let {
+            name,
+            type_annotation,
+            value,
+            id,
+            span,
+        } => Ok(ASTNode::Let {
+            name,
+            type_annotation: type_annotation.map(|ty| folder.fold_type(ty)).transpose()?,
+            value: value.map(|value| fold_boxed(folder, value)).transpose()?,
+            id,
+            span,
+        }),
+        ASTNode::LetPattern {
+            pattern,
+            type_annotation,
+            value,
+            id,
+            span,
+        } => Ok(ASTNode::LetPattern {
+            pattern,
+            type_annotation: type_annotation.map(|ty| folder.fold_type(ty)).transpose()?,
+            value: fold_boxed(folder, value)?,
+            id,
+            span,
+        }),
+        ASTNode::Assignment {
+            target,
+            value,
+            id,
+            span,
+        } => {
+            let value = fold_boxed(folder, value)?;
+            Ok(ASTNode::Assignment {
+                target: Box::new(fold_place(folder, *target)?),
+                value,
+                id,
+                span,
+            })
+        }
+        ASTNode::CompoundAssignment {
+            target,
+            op,
+            value,
+            id,
+            span,
+        } => {
+            let value = fold_boxed(folder, value)?;
+            Ok(ASTNode::CompoundAssignment {
+                target: Box::new(fold_place(folder, *target)?),
+                op,
+                value,
+                id,
+                span,
+            })
+        }
+        ASTNode::If {
+            condition,
+            then_branch,
+            else_branch,
+            id,
+            span,
+        } => {
+            let condition = fold_boxed(folder, condition)?;
+            Ok(ASTNode::If {
+                condition,
+                then_branch: folder.fold_nodes(then_branch)?,
+                else_branch: else_branch
+                    .map(|branch| folder.fold_nodes(branch))
+                    .transpose()?,
+                id,
+                span,
+            })
+        }
+        ASTNode::Match {
+            scrutinee,
+            arms,
+            id,
+            span,
+        } => {
+            let scrutinee = fold_boxed(folder, scrutinee)?;
+            let arms = arms
+                .into_iter()
+                .map(|arm| {
+                    Ok(MatchArm {
+                        guard: arm.guard.map(|guard| folder.fold_node(guard)).transpose()?,
+                        body: folder.fold_node(arm.body)?,
+                        ..arm
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Ok(ASTNode::Match {
+                scrutinee,
+                arms,
+                id,
+                span,
+            })
+        }
+        ASTNode::While {
+            condition,
+            body,
+            id,
+            span,
+        } => {
+            let condition = fold_boxed(folder, condition)?;
+            Ok(ASTNode::While {
+                condition,
+                body: folder.fold_nodes(body)?,
+                id,
+                span,
+            })
+        }
+        ASTNode::For {
+            pattern,
+            iterable,
+            body,
+            id,
+            span,
+        } => {
+            let iterable = fold_boxed(folder, iterable)?;
+            Ok(ASTNode::For {
+                pattern,
+                iterable,
+                body: folder.fold_nodes(body)?,
+                id,
+                span,
+            })
+        }
+        ASTNode::Return { value, id, span } => Ok(ASTNode::Return {
+            value: value.map(|value| fold_boxed(folder, value)).transpose()?,
+            id,
+            span,
+        }),
+        ASTNode::Call {
+            callee,
+            args,
+            id,
+            span,
+        } => {
+            let callee = fold_boxed(folder, callee)?;
+            Ok(ASTNode::Call {
+                callee,
+                args: folder.fold_nodes(args)?,
+                id,
+                span,
+            })
+        }
+        ASTNode::BinaryOp {
+            op,
+            left,
+            right,
+            id,
+            span,
+        } => Ok(ASTNode::BinaryOp {
+            op,
+            left: fold_boxed(folder, left)?,
+            right: fold_boxed(folder, right)?,
+            id,
+            span,
+        }),
+        ASTNode::UnaryOp {
+            op,
+            operand,
+            id,
+            span,
+        } => Ok(ASTNode::UnaryOp {
+            op,
+            operand: fold_boxed(folder, operand)?,
+            id,
+            span,
+        }),
+        ASTNode::Cast {
+            value,
+            target,
+            id,
+            span,
+        } => Ok(ASTNode::Cast {
+            value: fold_boxed(folder, value)?,
+            target: folder.fold_type(target)?,
+            id,
+            span,
+        }),
+        ASTNode::MemberAccess {
+            object,
+            member,
+            id,
+            span,
+        } => Ok(ASTNode::MemberAccess {
+            object: fold_boxed(folder, object)?,
+            member,
+            id,
+            span,
+        }),
+        ASTNode::Index {
+            array,
+            index,
+            id,
+            span,
+        } => Ok(ASTNode::Index {
+            array: fold_boxed(folder, array)?,
+            index: fold_boxed(folder, index)?,
+            id,
+            span,
+        }),
+        ASTNode::Closure {
+            params,
+            return_type,
+            body,
+            is_move,
+            id,
+            span,
+        } => Ok(ASTNode::Closure {
+            params: fold_params(folder, params)?,
+            return_type: return_type.map(|ty| folder.fold_type(ty)).transpose()?,
+            body: fold_boxed(folder, body)?,
+            is_move,
+            id,
+            span,
+        }),
+        ASTNode::ArrayLiteral { elements, id, span } => Ok(ASTNode::ArrayLiteral {
+            elements: folder.fold_nodes(elements)?,
+            id,
+            span,
+        }),
+        ASTNode::TupleLiteral { elements, id, span } => Ok(ASTNode::TupleLiteral {
+            elements: folder.fold_nodes(elements)?,
+            id,
+            span,
+        }),
+        ASTNode::StructLiteral {
+            name,
+            fields,
+            id,
+            span,
+        } => Ok(ASTNode::StructLiteral {
+            name,
+            fields: fields
+                .into_iter()
+                .map(|(field, value)| Ok((field, folder.fold_node(value)?)))
+                .collect::<Result<_>>()?,
+            id,
+            span,
+        }),
+        ASTNode::TraitDef {
+            name,
+            type_params,
+            methods,
+            is_pub,
+            id,
+            span,
+        } => Ok(ASTNode::TraitDef {
+            name,
+            type_params,
+            is_pub,
+            methods: methods
+                .into_iter()
+                .map(|method| {
+                    Ok(TraitMethod {
+                        params: fold_params(folder, method.params)?,
+                        return_type: method
+                            .return_type
+                            .map(|ty| folder.fold_type(ty))
+                            .transpose()?,
+                        body: method
+                            .body
+                            .map(|body| folder.fold_nodes(body))
+                            .transpose()?,
+                        ..method
+                    })
+                })
+                .collect::<Result<_>>()?,
+            id,
+            span,
+        }),
+        ASTNode::Impl {
+            trait_name,
+            trait_args,
+            self_type,
+            methods,
+            id,
+            span,
+        } => Ok(ASTNode::Impl {
+            trait_name,
+            trait_args: trait_args
+                .into_iter()
+                .map(|arg| folder.fold_type(arg))
+                .collect::<Result<_>>()?,
+            self_type: folder.fold_type(self_type)?,
+            methods: folder.fold_nodes(methods)?,
+            id,
+            span,
+        }),
+        ASTNode::StructDef {
+            name,
+            type_params,
+            fields,
+            is_pub,
+            attributes,
+            id,
+            span,
+        } => Ok(ASTNode::StructDef {
+            name,
+            type_params,
+            fields: fold_fields(folder, fields)?,
+            is_pub,
+            attributes,
+            id,
+            span,
+        }),
+        ASTNode::EnumDef {
+            name,
+            type_params,
+            variants,
+            is_pub,
+            id,
+            span,
+        } => Ok(ASTNode::EnumDef {
+            name,
+            type_params,
+            variants: variants
+                .into_iter()
+                .map(|variant| {
+                    let fields = match variant.fields {
+                        VariantFields::Unit => VariantFields::Unit,
+                        VariantFields::Tuple(types) => VariantFields::Tuple(
+                            types
+                                .into_iter()
+                                .map(|ty| folder.fold_type(ty))
+                                .collect::<Result<_>>()?,
+                        ),
+                        VariantFields::Named(fields) => {
+                            VariantFields::Named(fold_fields(folder, fields)?)
+                        }
+                    };
+                    Ok(Variant { fields, ..variant })
+                })
+                .collect::<Result<_>>()?,
+            is_pub,
+            id,
+            span,
+        }),
+        leaf @ (ASTNode::Import { .. }
+        | ASTNode::ModuleAttributes { .. }
+        | ASTNode::Identifier { .. }
+        | ASTNode::Literal { .. }
+        | ASTNode::Break { .. }
+        | ASTNode::Continue { .. }
+        | ASTNode::Error { .. }) => Ok(leaf),
+    }
+}
+
+fn fold_params<F: Folder + ?Sized>(
+    folder: &mut F,
+    params: Vec<Parameter>,
+) -> Result<Vec<Parameter>> {
+    params
+        .into_iter()
+        .map(|param| {
+            Ok(Parameter {
+                type_annotation: folder.fold_type(param.type_annotation)?,
+                ..param
+            })
+        })
+        .collect()
+}
+
+fn fold_fields<F: Folder + ?Sized>(folder: &mut F, fields: Vec<Field>) -> Result<Vec<Field>> {
+    fields
+        .into_iter()
+        .map(|field| {
+            Ok(Field {
+                type_annotation: folder.fold_type(field.type_annotation)?,
+                ..field
+            })
+        })
+        .collect()
+}
+
+/// Folds a boxed child, reusing its allocation for the result.
+fn fold_boxed<F: Folder + ?Sized>(folder: &mut F, mut node: Box<ASTNode>) -> Result<Box<ASTNode>> {
+    let placeholder = ASTNode::Error {
+        id: NodeId::DUMMY,
+        span: Span::dummy(),
+    };
+    let child = std::mem::replace(&mut *node, placeholder);
+    *node = folder.fold_node(child)?;
+    Ok(node)
+}
+
+/// Folds the expressions of an assignment target, like [`walk_place`]
+/// visits them; the variable the target writes into is kept as it is.
+pub fn fold_place<F: Folder + ?Sized>(folder: &mut F, target: ASTNode) -> Result<ASTNode> {
+    match target {
+        ASTNode::Identifier { .. } => Ok(target),
+        ASTNode::MemberAccess {
+            object,
+            member,
+            id,
+            span,
+        } => Ok(ASTNode::MemberAccess {
+            object: Box::new(fold_place(folder, *object)?),
+            member,
+            id,
+            span,
+        }),
+        ASTNode::Index {
+            array,
+            index,
+            id,
+            span,
+        } => Ok(ASTNode::Index {
+            array: Box::new(fold_place(folder, *array)?),
+            index: fold_boxed(folder, index)?,
+            id,
+            span,
+        }),
+        other => folder.fold_node(other),
+    }
 }
 
 pub trait Visitable {
-    fn accept<T>(&self, visitor: &mut dyn Visitor<T>) -> Result<T>;
+    fn accept<V: Visitor + ?Sized>(&self, visitor: &mut V) -> Result<V::Output>;
 }
 
 impl Visitable for ASTNode {
-    fn accept<T>(&self, visitor: &mut dyn Visitor<T>) -> Result<T> {
-        match self {
-            ASTNode::Program(statements) => visitor.visit_program(statements),
-            ASTNode::Function {
-                name,
-                params,
-                return_type,
-                body,
-                is_async,
-            } => {
-                let func = Function {
-                    name: name.clone(),
-                    parameters: params.clone(),
-                    return_type: return_type.clone(),
-                    body: body.clone(),
-                    is_async: *is_async,
-                };
-                visitor.visit_function(&func)
-            }
-            ASTNode::Statement(stmt) => visitor.visit_statement(stmt),
-            ASTNode::Expression(expr) => visitor.visit_expression(expr),
-            ASTNode::BinaryOp { left, op, right } => {
-                visitor.visit_binary_expr(left, &op.to_string(), right)
+    fn accept<V: Visitor + ?Sized>(&self, visitor: &mut V) -> Result<V::Output> {
+        visitor.visit_node(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+    use crate::span::FileId;
+
+    #[derive(Default)]
+    struct CallCounter {
+        calls: Vec<String>,
+    }
+
+    impl Visitor for CallCounter {
+        type Output = ();
+
+        fn visit_call(&mut self, callee: &ASTNode, args: &[ASTNode]) -> Result<()> {
+            if let ASTNode::Identifier { name, .. } = callee {
+                self.calls.push(name.clone());
             }
-            ASTNode::Call { callee, args } => visitor.visit_call_expr(callee, args),
-            ASTNode::If {
-                condition,
-                then_branch,
-                else_branch,
-            } => visitor.visit_if(condition, then_branch, else_branch),
-            ASTNode::Assignment { target, value } => visitor.visit_assignment(target, value),
-            ASTNode::Identifier(name) => visitor.visit_identifier(name),
-            ASTNode::Literal(value) => visitor.visit_literal(&Expression::Literal(value.clone())),
-            _ => Err(crate::error::IoError::runtime_error(
-                "Unsupported node type",
-            )),
+            walk_nodes(self, args)?;
+            Ok(())
         }
     }
-}
 
-impl Visitor for SomeVisitor {
-    fn visit_if(
-        &mut self,
-        condition: &ASTNode,
-        then_branch: &[ASTNode],
-        else_branch: &Option<Vec<ASTNode>>,
-    ) -> Result<()> {
-        // ...existing code...
+    struct Doubler;
+
+    impl Folder for Doubler {
+        fn fold_node(&mut self, node: ASTNode) -> Result<ASTNode> {
+            match node {
+                ASTNode::Literal {
+                    value: Literal::Integer(n),
+                    id,
+                    span,
+                } => Ok(ASTNode::Literal {
+                    value: Literal::Integer(n * 2),
+                    id,
+                    span,
+                }),
+                other => fold_children(self, other),
+            }
+        }
     }
 
-    fn visit_call_expr(&mut self, name: &str, args: &[ASTNode]) -> Result<()> {
-        // ...existing code...
+    #[test]
+    fn test_visitor_reaches_nested_calls() {
+        let program =
+            parse_source("fn main() { if ready() { log(add(1, 2)); } }", FileId(0)).unwrap();
+        let mut counter = CallCounter::default();
+        program.accept(&mut counter).unwrap();
+        assert_eq!(counter.calls, vec!["ready", "log", "add"]);
     }
 
-    // ...existing methods...
+    #[test]
+    fn test_folder_rewrites_and_keeps_ids() {
+        let program = parse_source("let x = 1 + 2;", FileId(0)).unwrap();
+        let before = program.clone();
+        let folded = Doubler.fold_node(program).unwrap();
+
+        let ASTNode::Program(items) = &folded else {
+            panic!("expected program");
+        };
+        let ASTNode::Let {
+            value: Some(value),
+            id,
+            ..
+        } = &items[0]
+        else {
+            panic!("expected let");
+        };
+        let ASTNode::BinaryOp { left, right, .. } = value.as_ref() else {
+            panic!("expected binary op");
+        };
+        assert!(matches!(
+            left.as_ref(),
+            ASTNode::Literal {
+                value: Literal::Integer(2),
+                ..
+            }
+        ));
+        assert!(matches!(
+            right.as_ref(),
+            ASTNode::Literal {
+                value: Literal::Integer(4),
+                ..
+            }
+        ));
+        let ASTNode::Program(original) = &before else {
+            unreachable!()
+        };
+        assert_eq!(*id, original[0].id());
+    }
 }